@@ -0,0 +1,93 @@
+mod common;
+
+use applib::drawing::primitives::draw_rect;
+use applib::{Color, FbView, FbViewMut, Framebuffer, OwnedPixels};
+use common::{bench, noise};
+use std::hint::black_box;
+
+const SIZE: u32 = 512;
+
+// Alpha of the source pixel at (x, y)
+type AlphaFn = fn(i64, i64) -> u8;
+
+// The per-pixel blending the row functions replaced, as a baseline
+fn naive_blend(src: Color, dst: Color) -> Color {
+    let (r1, g1, b1, a1) = src.as_rgba();
+    let (r2, g2, b2, a2) = dst.as_rgba();
+    let channel =
+        |d: u8, s: u8| ((d as u16 * (256 - a1 as u16) + s as u16 * (1 + a1 as u16)) >> 8) as u8;
+    Color::rgba(channel(r2, r1), channel(g2, g1), channel(b2, b1), a2)
+}
+
+fn with_alpha(alpha: AlphaFn) -> Framebuffer<OwnedPixels> {
+    let mut fb = noise(SIZE, SIZE, 646);
+    for y in 0..SIZE as i64 {
+        for x in 0..SIZE as i64 {
+            let color = fb.get_pixel(x, y).unwrap();
+            fb.set_pixel(x, y, color.with_alpha(alpha(x, y)));
+        }
+    }
+    fb
+}
+
+fn to_vec(fb: &Framebuffer<OwnedPixels>) -> Vec<Color> {
+    (0..SIZE as i64)
+        .flat_map(|y| (0..SIZE as i64).map(move |x| (x, y)))
+        .map(|(x, y)| fb.get_pixel(x, y).unwrap())
+        .collect()
+}
+
+// Round opaque icons with antialiased edges on a transparent background
+fn sprite_alpha(x: i64, y: i64) -> u8 {
+    let (dx, dy) = ((x % 32 - 16) as f32, (y % 32 - 16) as f32);
+    let d = (dx * dx + dy * dy).sqrt();
+    (255.0 * (13.0 - d).clamp(0.0, 1.0)) as u8
+}
+
+fn main() {
+    let cases: [(&str, AlphaFn); 6] = [
+        ("alpha 0", |_, _| 0),
+        ("alpha 64", |_, _| 64),
+        ("alpha 128", |_, _| 128),
+        ("alpha 255", |_, _| 255),
+        ("alpha gradient", |x, _| x as u8),
+        ("icon-like sprites", sprite_alpha),
+    ];
+
+    let background = noise(SIZE, SIZE, 1);
+    let background_data = to_vec(&background);
+
+    for (label, alpha) in cases {
+        let src = with_alpha(alpha);
+        let src_data = to_vec(&src);
+
+        let mut dst_data = background_data.clone();
+        bench(&format!("copy {} (per pixel)", label), || {
+            dst_data
+                .iter_mut()
+                .zip(black_box(&src_data))
+                .for_each(|(dst, src)| *dst = naive_blend(*src, *dst));
+        });
+
+        let mut dst = noise(SIZE, SIZE, 1);
+        bench(&format!("copy {} (rows)", label), || {
+            dst.copy_from_fb(black_box(&src), (0, 0), true);
+        });
+    }
+
+    let color = Color::rgba(30, 140, 200, 100);
+
+    let mut dst_data = background_data.clone();
+    bench("fill alpha 100 (per pixel)", || {
+        let color = black_box(color);
+        dst_data
+            .iter_mut()
+            .for_each(|dst| *dst = naive_blend(color, *dst));
+    });
+
+    let mut dst = background;
+    let rect = dst.shape_as_rect();
+    bench("fill alpha 100 (rows)", || {
+        draw_rect(&mut dst, &rect, black_box(color), true);
+    });
+}
@@ -0,0 +1,45 @@
+// Shared by the benchmarks, which have no harness: `cargo bench -p applib` prints the
+// timings of each case
+use applib::{Color, FbViewMut, Framebuffer, OwnedPixels};
+use std::time::{Duration, Instant};
+
+// Random pixels, the same for every run
+pub fn noise(w: u32, h: u32, seed: u64) -> Framebuffer<OwnedPixels> {
+    let mut state = seed | 1;
+    let mut fb = Framebuffer::new_owned(w, h);
+    for y in 0..h as i64 {
+        for x in 0..w as i64 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let [r, g, b, a, ..] = state.to_le_bytes();
+            fb.set_pixel(x, y, Color::rgba(r, g, b, a));
+        }
+    }
+    fb
+}
+
+// Runs f for about a second after a warm-up, and prints the fastest and mean times
+pub fn bench<F: FnMut()>(name: &str, mut f: F) {
+    for _ in 0..3 {
+        f();
+    }
+
+    let mut times = Vec::new();
+    let start = Instant::now();
+    while times.len() < 5 || start.elapsed() < Duration::from_secs(1) {
+        let t0 = Instant::now();
+        f();
+        times.push(t0.elapsed());
+    }
+
+    let min = times.iter().min().unwrap();
+    let mean = times.iter().sum::<Duration>() / times.len() as u32;
+    println!(
+        "{:<40} min {:>8.3} ms   mean {:>8.3} ms   ({} runs)",
+        name,
+        min.as_secs_f64() * 1000.0,
+        mean.as_secs_f64() * 1000.0,
+        times.len()
+    );
+}
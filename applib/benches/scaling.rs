@@ -0,0 +1,29 @@
+mod common;
+
+use applib::{FbView, FbViewMut, Framebuffer, Rect, ScaleFilter};
+use common::{bench, noise};
+use std::hint::black_box;
+
+const SRC_W: u32 = 1024;
+const SRC_H: u32 = 768;
+
+fn main() {
+    let src = noise(SRC_W, SRC_H, 639);
+    let src_rect = src.shape_as_rect();
+
+    for (label, scale) in [("0.6x", 0.6), ("1.5x", 1.5)] {
+        let (w, h) = ((SRC_W as f64 * scale) as u32, (SRC_H as f64 * scale) as u32);
+        let mut dst = Framebuffer::new_owned(w, h);
+        let dst_rect = Rect { x0: 0, y0: 0, w, h };
+
+        for filter in [ScaleFilter::Nearest, ScaleFilter::Bilinear] {
+            for blend in [false, true] {
+                let name = format!("{:?} {} blend={}", filter, label, blend);
+                bench(&name, || {
+                    dst.copy_from_fb_scaled(black_box(&src), &src_rect, &dst_rect, filter, blend);
+                    black_box(&mut dst);
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::Color;
+
+// Row versions of blend_colors(), with the same results. A pixel is handled as a u32 and
+// its channels two at a time (red and blue, then green and alpha), each in a 16-bit
+// lane: dst * (256 - a) + src * (1 + a) is at most 255 * 257, so lanes cannot overflow.
+
+const EVEN_CHANNELS: u32 = 0x00ff_00ff;
+const ALPHA_MASK: u32 = 0xff00_0000;
+
+// Pixels are checked by chunks, small enough that fully transparent or opaque areas are
+// caught, large enough that the checks stay cheap
+const CHUNK_LEN: usize = 64;
+
+pub(crate) fn blend_row(dst: &mut [Color], src: &[Color]) {
+    for (dst, src) in dst.chunks_mut(CHUNK_LEN).zip(src.chunks(CHUNK_LEN)) {
+        let (all, any) = src.iter().fold((u32::MAX, 0), |(all, any), c| {
+            let c = u32::from_le_bytes(c.0);
+            (all & c, any | c)
+        });
+
+        // Blending leaves the destination as it is at zero alpha, and replaces its color
+        // at full alpha
+        if any & ALPHA_MASK == 0 {
+            continue;
+        }
+        if all & ALPHA_MASK == ALPHA_MASK {
+            dst.iter_mut().zip(src).for_each(|(dst, src)| {
+                let (s, d) = (u32::from_le_bytes(src.0), u32::from_le_bytes(dst.0));
+                dst.0 = ((s & !ALPHA_MASK) | (d & ALPHA_MASK)).to_le_bytes();
+            });
+            continue;
+        }
+
+        dst.iter_mut().zip(src).for_each(|(dst, src)| {
+            let (s, d) = (u32::from_le_bytes(src.0), u32::from_le_bytes(dst.0));
+            let a = s >> 24;
+            let (d_even, d_odd) = (d & EVEN_CHANNELS, (d >> 8) & EVEN_CHANNELS);
+            let (s_even, s_odd) = (s & EVEN_CHANNELS, (s >> 8) & EVEN_CHANNELS);
+            let blended = blend_lanes(d_even, d_odd, s_even * (1 + a), s_odd * (1 + a), 256 - a, d);
+            dst.0 = blended.to_le_bytes();
+        });
+    }
+}
+
+pub(crate) fn blend_row_color(dst: &mut [Color], color: Color) {
+    let s = u32::from_le_bytes(color.0);
+    let a = s >> 24;
+
+    match a {
+        0 => (),
+        255 => dst.iter_mut().for_each(|dst| {
+            let d = u32::from_le_bytes(dst.0);
+            dst.0 = ((s & !ALPHA_MASK) | (d & ALPHA_MASK)).to_le_bytes();
+        }),
+        _ => {
+            // The color terms are the same for the whole row
+            let s_even = (s & EVEN_CHANNELS) * (1 + a);
+            let s_odd = ((s >> 8) & EVEN_CHANNELS) * (1 + a);
+            let inv_a = 256 - a;
+            dst.iter_mut().for_each(|dst| {
+                let d = u32::from_le_bytes(dst.0);
+                let (d_even, d_odd) = (d & EVEN_CHANNELS, (d >> 8) & EVEN_CHANNELS);
+                dst.0 = blend_lanes(d_even, d_odd, s_even, s_odd, inv_a, d).to_le_bytes();
+            });
+        }
+    }
+}
+
+// The source terms are already multiplied by 1 + alpha, the destination alpha is kept
+#[inline(always)]
+fn blend_lanes(d_even: u32, d_odd: u32, s_even: u32, s_odd: u32, inv_a: u32, d: u32) -> u32 {
+    let even = ((d_even * inv_a + s_even) >> 8) & EVEN_CHANNELS;
+    let odd = (d_odd * inv_a + s_odd) & !EVEN_CHANNELS;
+    (even | odd) & !ALPHA_MASK | (d & ALPHA_MASK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blend_colors;
+    use crate::test_utils::Rng;
+    use alloc::vec::Vec;
+
+    // Straight alpha compositing in floating point, keeping the destination alpha
+    fn reference_blend(src: Color, dst: Color) -> Color {
+        let (rs, gs, bs, a) = src.as_rgba();
+        let (rd, gd, bd, ad) = dst.as_rgba();
+        let a = a as f32 / 255.0;
+        let channel = |s: u8, d: u8| (s as f32 * a + d as f32 * (1.0 - a)).round() as u8;
+        Color::rgba(channel(rs, rd), channel(gs, gd), channel(bs, bd), ad)
+    }
+
+    fn assert_close(actual: Color, expected: Color, src: Color, dst: Color) {
+        let (a, e) = (actual.as_rgba(), expected.as_rgba());
+        let diffs = [a.0.abs_diff(e.0), a.1.abs_diff(e.1), a.2.abs_diff(e.2)];
+        assert!(
+            diffs.iter().all(|&d| d <= 1) && a.3 == e.3,
+            "{:?} over {:?}: {:?}, expected {:?}",
+            src,
+            dst,
+            actual,
+            expected
+        );
+    }
+
+    // Every source alpha and source channel value over a range of destination values, in
+    // rows long enough to span several chunks
+    #[test]
+    fn rows_match_reference() {
+        let mut rng = Rng::new(646);
+        for a in 0..=255u8 {
+            for d in (0..=255u8).step_by(5) {
+                let src: Vec<Color> = (0..=255u8)
+                    .map(|s| Color::rgba(s, 255 - s, s.wrapping_mul(7), a))
+                    .collect();
+                let dst: Vec<Color> = (0..=255u8)
+                    .map(|i| Color::rgba(d, d ^ 0x5a, 255 - d, rng.next_u32() as u8 ^ i))
+                    .collect();
+
+                let mut out = dst.clone();
+                blend_row(&mut out, &src);
+                for ((&o, &s), &d) in out.iter().zip(&src).zip(&dst) {
+                    assert_eq!(o, blend_colors(s, d));
+                    assert_close(o, reference_blend(s, d), s, d);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fills_match_reference() {
+        let dst: Vec<Color> = (0..=255u8)
+            .map(|v| Color::rgba(v, 255 - v, v / 3, v ^ 0x33))
+            .collect();
+        for a in 0..=255u8 {
+            for v in (0..=255u8).step_by(3) {
+                let color = Color::rgba(v, 255 - v, v.wrapping_mul(5), a);
+                let mut out = dst.clone();
+                blend_row_color(&mut out, color);
+                for (&o, &d) in out.iter().zip(&dst) {
+                    assert_eq!(o, blend_colors(color, d));
+                    assert_close(o, reference_blend(color, d), color, d);
+                }
+            }
+        }
+    }
+
+    // Chunks that are fully transparent, fully opaque or mixed, and a partial last chunk
+    #[test]
+    fn mixed_chunks() {
+        let mut rng = Rng::new(1);
+        let len = 5 * CHUNK_LEN + 17;
+        let src: Vec<Color> = (0..len)
+            .map(|i| {
+                let [r, g, b, a] = rng.next_u32().to_le_bytes();
+                let a = match i / CHUNK_LEN {
+                    0 => 0,
+                    1 => 255,
+                    2 if i % CHUNK_LEN == 10 => 1,
+                    2 => 0,
+                    3 if i % CHUNK_LEN == 10 => 254,
+                    3 => 255,
+                    _ => a,
+                };
+                Color::rgba(r, g, b, a)
+            })
+            .collect();
+        let dst: Vec<Color> = (0..len)
+            .map(|_| Color(rng.next_u32().to_le_bytes()))
+            .collect();
+
+        let mut out = dst.clone();
+        blend_row(&mut out, &src);
+        for (i, ((&o, &s), &d)) in out.iter().zip(&src).zip(&dst).enumerate() {
+            assert_eq!(o, blend_colors(s, d), "pixel {}", i);
+        }
+        assert_eq!(out[..CHUNK_LEN], dst[..CHUNK_LEN]);
+    }
+}
@@ -0,0 +1,537 @@
+use crate::{Color, WidgetColors};
+use lazy_static::lazy_static;
+use num::Float;
+
+// Linear intensities are stored on 12 bits, enough to tell apart the darkest sRGB values
+pub(crate) const LINEAR_MAX: u16 = 4095;
+
+lazy_static! {
+    static ref TO_LINEAR: [u16; 256] =
+        core::array::from_fn(|c| (to_linear(c as u8) * LINEAR_MAX as f32).round() as u16);
+    static ref FROM_LINEAR: [u8; LINEAR_MAX as usize + 1] =
+        core::array::from_fn(|l| from_linear(l as f32 / LINEAR_MAX as f32));
+}
+
+impl Color {
+    // Hue in degrees, saturation and value between 0 and 1
+    pub fn from_hsv(h: f32, s: f32, v: f32, a: u8) -> Self {
+        let h = (h % 360.0 + 360.0) % 360.0 / 60.0;
+        let s = s.clamp(0.0, 1.0);
+        let v = v.clamp(0.0, 1.0);
+
+        let c = v * s;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+
+        let m = v - c;
+        let to_u8 = |val: f32| ((val + m) * 255.0).round() as u8;
+        Color::rgba(to_u8(r), to_u8(g), to_u8(b), a)
+    }
+
+    // The hue is 0 for greys, which have none
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let (r, g, b, _) = self.as_rgba();
+        let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+
+        let max = f32::max(r, f32::max(g, b));
+        let min = f32::min(r, f32::min(g, b));
+        let delta = max - min;
+
+        let h = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let h = (h + 360.0) % 360.0;
+
+        let s = match max {
+            0.0 => 0.0,
+            _ => delta / max,
+        };
+
+        (h, s, max)
+    }
+
+    // Hue in degrees, saturation and lightness between 0 and 1
+    pub fn from_hsl(h: f32, s: f32, l: f32, a: u8) -> Self {
+        let s = s.clamp(0.0, 1.0);
+        let l = l.clamp(0.0, 1.0);
+
+        let v = l + s * f32::min(l, 1.0 - l);
+        let s_v = match v {
+            0.0 => 0.0,
+            _ => 2.0 * (1.0 - l / v),
+        };
+
+        Color::from_hsv(h, s_v, v, a)
+    }
+
+    // The hue is 0 for greys, which have none
+    pub fn to_hsl(&self) -> (f32, f32, f32) {
+        let (h, s_v, v) = self.to_hsv();
+
+        let l = v * (1.0 - s_v / 2.0);
+        let s = match l {
+            0.0 | 1.0 => 0.0,
+            _ => (v - l) / f32::min(l, 1.0 - l),
+        };
+
+        (h, s, l)
+    }
+
+    // Adds amount to the lightness, which goes from 0 to 1
+    pub fn lighten(&self, amount: f32) -> Self {
+        let (h, s, l) = self.to_hsl();
+        Color::from_hsl(h, s, l + amount, self.0[3])
+    }
+
+    pub fn darken(&self, amount: f32) -> Self {
+        self.lighten(-amount)
+    }
+
+    pub fn with_alpha(&self, a: u8) -> Self {
+        let (r, g, b, _) = self.as_rgba();
+        Color::rgba(r, g, b, a)
+    }
+
+    // WCAG relative luminance, from 0 (black) to 1 (white). The alpha is ignored.
+    pub fn relative_luminance(&self) -> f32 {
+        let (r, g, b, _) = self.as_rgba();
+        0.2126 * to_linear(r) + 0.7152 * to_linear(g) + 0.0722 * to_linear(b)
+    }
+}
+
+// Like Color::lerp(), with the RGB channels interpolated in linear light so that
+// mixes do not look too dark. t goes from 0 (a) to 1 (b).
+pub fn mix(a: Color, b: Color, t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    let (r1, g1, b1, a1) = a.as_rgba();
+    let (r2, g2, b2, a2) = b.as_rgba();
+
+    let mix_channel = |c1: u8, c2: u8| {
+        let (c1, c2) = (to_linear(c1), to_linear(c2));
+        from_linear(c1 + (c2 - c1) * t)
+    };
+    let alpha = (a1 as f32 + (a2 as f32 - a1 as f32) * t).round() as u8;
+
+    Color::rgba(
+        mix_channel(r1, r2),
+        mix_channel(g1, g2),
+        mix_channel(b1, b2),
+        alpha,
+    )
+}
+
+// WCAG contrast ratio, from 1 (same luminance) to 21 (black on white). Text should
+// have at least 4.5 against its background.
+pub fn contrast_ratio(a: Color, b: Color) -> f32 {
+    let la = a.relative_luminance();
+    let lb = b.relative_luminance();
+    (f32::max(la, lb) + 0.05) / (f32::min(la, lb) + 0.05)
+}
+
+// Whichever of the candidates contrasts the most with the background
+pub fn most_readable(bg: Color, candidates: &[Color]) -> Color {
+    candidates
+        .iter()
+        .copied()
+        .max_by(|a, b| contrast_ratio(*a, bg).total_cmp(&contrast_ratio(*b, bg)))
+        .expect("No candidate colors")
+}
+
+// Like blend_colors(), with the RGB channels blended in linear light. Used for the
+// coverage of glyphs, which otherwise makes dark text on light backgrounds look too bold.
+pub fn blend_colors_linear(src: Color, dst: Color) -> Color {
+    blend_linear_with(&TO_LINEAR, &FROM_LINEAR, src, dst)
+}
+
+// Same over a row, with the lookup tables fetched once
+pub(crate) fn blend_row_linear(dst: &mut [Color], src: &[Color]) {
+    let (to_linear, from_linear) = (&*TO_LINEAR, &*FROM_LINEAR);
+    for (dst, src) in dst.iter_mut().zip(src) {
+        *dst = match src.0[3] {
+            0 => *dst,
+            255 => Color::rgba(src.0[0], src.0[1], src.0[2], dst.0[3]),
+            _ => blend_linear_with(to_linear, from_linear, *src, *dst),
+        };
+    }
+}
+
+#[inline]
+fn blend_linear_with(
+    to_linear: &[u16; 256],
+    from_linear: &[u8; LINEAR_MAX as usize + 1],
+    src: Color,
+    dst: Color,
+) -> Color {
+    let (r1, g1, b1, a1) = src.as_rgba();
+    let (r2, g2, b2, a2) = dst.as_rgba();
+    let a = a1 as u32;
+
+    let blend_channel = |c1: u8, c2: u8| {
+        let (l1, l2) = (to_linear[c1 as usize] as u32, to_linear[c2 as usize] as u32);
+        let l = (l1 * a + l2 * (255 - a) + 127) / 255;
+        from_linear[l as usize]
+    };
+
+    Color::rgba(
+        blend_channel(r1, r2),
+        blend_channel(g1, g2),
+        blend_channel(b1, b2),
+        a2,
+    )
+}
+
+// The same transfer functions through lookup tables, on LINEAR_MAX
+pub(crate) fn to_linear_fast(c: u8) -> u16 {
+    TO_LINEAR[c as usize]
+}
+
+pub(crate) fn from_linear_fast(l: u16) -> u8 {
+    FROM_LINEAR[usize::min(l as usize, LINEAR_MAX as usize)]
+}
+
+// sRGB transfer functions, between a channel and its linear intensity from 0 to 1
+fn to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    match c <= 0.04045 {
+        true => c / 12.92,
+        false => ((c + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+fn from_linear(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = match c <= 0.0031308 {
+        true => c * 12.92,
+        false => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+    };
+    (c * 255.0).round() as u8
+}
+
+// Colors of the widget states, all derived from one accent color and the color of the
+// widgets, so that a theme can change its accent in one place
+#[derive(Clone, Copy)]
+pub struct Palette {
+    pub accent: Color,
+    pub focus_ring: Color,
+    pub normal: WidgetColors,
+    pub hover: WidgetColors,
+    pub pressed: WidgetColors,
+    pub selected: WidgetColors,
+    pub disabled: WidgetColors,
+}
+
+impl Palette {
+    pub fn from_accent(accent: Color, element: Color) -> Self {
+        let (_, _, element_l) = element.to_hsl();
+        let is_dark = element_l < 0.5;
+
+        // Toward the middle lightness, so that it stays visible on both sides
+        let shade = |amount: f32| match is_dark {
+            true => element.lighten(amount),
+            false => element.darken(amount),
+        };
+
+        let text_on = |bg: Color| most_readable(bg, &[Color::WHITE, Color::BLACK]);
+        let colors = |bg: Color, border: Color| WidgetColors {
+            bg,
+            border,
+            text: text_on(bg),
+        };
+
+        let normal_bg = element;
+        let hover_bg = shade(0.06);
+        let pressed_bg = element.darken(0.08);
+        let selected_bg = mix(element, accent, 0.3);
+        // Away from the other states
+        let disabled_bg = shade(-0.06);
+
+        let disabled_text = mix(text_on(disabled_bg), disabled_bg, 0.65);
+
+        Palette {
+            accent,
+            focus_ring: accent.lighten(0.15),
+            normal: colors(normal_bg, shade(0.12)),
+            hover: colors(hover_bg, shade(0.25)),
+            pressed: colors(pressed_bg, accent),
+            selected: colors(selected_bg, accent),
+            disabled: WidgetColors {
+                bg: disabled_bg,
+                border: mix(disabled_bg, disabled_text, 0.3),
+                text: disabled_text,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32, tolerance: f32) {
+        assert!((a - b).abs() <= tolerance, "{} != {}", a, b);
+    }
+
+    fn assert_hsv(color: Color, (h, s, v): (f32, f32, f32)) {
+        let (h2, s2, v2) = color.to_hsv();
+        assert_close(h2, h, 0.5);
+        assert_close(s2, s, 0.005);
+        assert_close(v2, v, 0.005);
+    }
+
+    fn assert_hsl(color: Color, (h, s, l): (f32, f32, f32)) {
+        let (h2, s2, l2) = color.to_hsl();
+        assert_close(h2, h, 0.5);
+        assert_close(s2, s, 0.005);
+        assert_close(l2, l, 0.005);
+    }
+
+    #[test]
+    fn hsv_reference_values() {
+        assert_hsv(Color::RED, (0.0, 1.0, 1.0));
+        assert_hsv(Color::YELLOW, (60.0, 1.0, 1.0));
+        assert_hsv(Color::rgb(0, 255, 0), (120.0, 1.0, 1.0));
+        assert_hsv(Color::rgb(0, 255, 255), (180.0, 1.0, 1.0));
+        assert_hsv(Color::BLUE, (240.0, 1.0, 1.0));
+        assert_hsv(Color::FUCHSIA, (300.0, 1.0, 1.0));
+        assert_hsv(Color::rgb(255, 0, 1), (359.8, 1.0, 1.0));
+        assert_hsv(Color::rgb(0x66, 0x33, 0x99), (270.0, 0.667, 0.6));
+        assert_hsv(Color::rgb(128, 64, 64), (0.0, 0.5, 0.502));
+        assert_hsv(Color::rgb(128, 128, 128), (0.0, 0.0, 0.502));
+        assert_hsv(Color::BLACK, (0.0, 0.0, 0.0));
+
+        assert_eq!(Color::from_hsv(0.0, 1.0, 1.0, 255), Color::RED);
+        assert_eq!(
+            Color::from_hsv(90.0, 1.0, 1.0, 255),
+            Color::rgb(128, 255, 0)
+        );
+        assert_eq!(
+            Color::from_hsv(210.0, 0.5, 0.8, 9),
+            Color::rgba(102, 153, 204, 9)
+        );
+        assert_eq!(
+            Color::from_hsv(123.0, 0.0, 0.5, 255),
+            Color::rgb(128, 128, 128)
+        );
+        assert_eq!(Color::from_hsv(77.0, 1.0, 0.0, 255), Color::BLACK);
+    }
+
+    // Hues wrap around, saturation and value are clamped
+    #[test]
+    fn hsv_out_of_range() {
+        assert_eq!(Color::from_hsv(360.0, 1.0, 1.0, 255), Color::RED);
+        assert_eq!(Color::from_hsv(-120.0, 1.0, 1.0, 255), Color::BLUE);
+        assert_eq!(Color::from_hsv(720.0 + 60.0, 1.0, 1.0, 255), Color::YELLOW);
+        assert_eq!(Color::from_hsv(-0.01, 1.0, 1.0, 255), Color::RED);
+        assert_eq!(Color::from_hsv(240.0, 2.0, 5.0, 255), Color::BLUE);
+        assert_eq!(Color::from_hsv(240.0, -1.0, -1.0, 255), Color::BLACK);
+    }
+
+    #[test]
+    fn hsv_round_trips() {
+        for r in (0..=255).step_by(5) {
+            for g in (0..=255).step_by(5) {
+                for b in (0..=255).step_by(5) {
+                    let color = Color::rgba(r, g, b, 200);
+                    let (h, s, v) = color.to_hsv();
+                    assert!((0.0..360.0).contains(&h), "{} for {:?}", h, color);
+                    assert_eq!(Color::from_hsv(h, s, v, 200), color);
+                }
+            }
+        }
+    }
+
+    // Values from the CSS color specification
+    #[test]
+    fn hsl_reference_values() {
+        assert_hsl(Color::rgb(255, 0, 0), (0.0, 1.0, 0.5));
+        assert_hsl(Color::rgb(0, 0, 128), (240.0, 1.0, 0.251));
+        assert_hsl(Color::rgb(0x66, 0x33, 0x99), (270.0, 0.5, 0.4));
+        assert_hsl(Color::rgb(0xff, 0xa5, 0x00), (38.8, 1.0, 0.5));
+        assert_hsl(Color::rgb(128, 128, 128), (0.0, 0.0, 0.502));
+        assert_hsl(Color::WHITE, (0.0, 0.0, 1.0));
+        assert_hsl(Color::BLACK, (0.0, 0.0, 0.0));
+
+        assert_eq!(
+            Color::from_hsl(120.0, 1.0, 0.25, 255),
+            Color::rgb(0, 128, 0)
+        );
+        assert_eq!(
+            Color::from_hsl(270.0, 0.5, 0.4, 255),
+            Color::rgb(0x66, 0x33, 0x99)
+        );
+        assert_eq!(
+            Color::from_hsl(-90.0, 0.5, 0.4, 7),
+            Color::rgba(0x66, 0x33, 0x99, 7)
+        );
+        assert_eq!(Color::from_hsl(0.0, 0.0, 1.0, 255), Color::WHITE);
+    }
+
+    #[test]
+    fn hsl_round_trips() {
+        for r in (0..=255).step_by(15) {
+            for g in (0..=255).step_by(15) {
+                for b in (0..=255).step_by(15) {
+                    let color = Color::rgba(r, g, b, 42);
+                    let (h, s, l) = color.to_hsl();
+                    assert_eq!(Color::from_hsl(h, s, l, 42), color);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lighten_and_darken_saturate() {
+        assert_eq!(Color::rgb(0x66, 0x33, 0x99).lighten(1.0), Color::WHITE);
+        assert_eq!(Color::rgb(0x66, 0x33, 0x99).darken(1.0), Color::BLACK);
+        assert_eq!(Color::BLACK.lighten(0.5), Color::rgb(128, 128, 128));
+        assert_eq!(
+            Color::RED.with_alpha(3).lighten(0.0),
+            Color::rgba(255, 0, 0, 3)
+        );
+    }
+
+    #[test]
+    fn srgb_transfer_reference_values() {
+        assert_close(to_linear(128), 0.21586, 1e-5);
+        assert_close(to_linear(10), 0.003035, 1e-6);
+        assert_eq!(to_linear(0), 0.0);
+        assert_eq!(to_linear(255), 1.0);
+        assert_eq!(from_linear(0.5), 188);
+        assert_eq!(from_linear(-1.0), 0);
+        assert_eq!(from_linear(2.0), 255);
+        for c in 0..=255 {
+            assert_eq!(from_linear(to_linear(c)), c);
+        }
+    }
+
+    // The 12 bits of the tables are enough for every channel value to come back
+    #[test]
+    fn lookup_tables_match() {
+        for c in 0..=255 {
+            let l = to_linear_fast(c);
+            assert_eq!(l, (to_linear(c) * LINEAR_MAX as f32).round() as u16);
+            assert_eq!(from_linear_fast(l), c);
+        }
+        assert_eq!(from_linear_fast(u16::MAX), 255);
+    }
+
+    // Values from the WCAG definitions
+    #[test]
+    fn luminance_and_contrast_reference_values() {
+        assert_close(Color::RED.relative_luminance(), 0.2126, 1e-4);
+        assert_close(Color::rgb(0, 255, 0).relative_luminance(), 0.7152, 1e-4);
+        assert_close(Color::BLUE.relative_luminance(), 0.0722, 1e-4);
+        assert_close(
+            Color::rgba(119, 119, 119, 0).relative_luminance(),
+            0.18447,
+            1e-4,
+        );
+
+        assert_close(contrast_ratio(Color::WHITE, Color::BLACK), 21.0, 1e-3);
+        assert_close(contrast_ratio(Color::BLACK, Color::WHITE), 21.0, 1e-3);
+        assert_close(contrast_ratio(Color::RED, Color::RED), 1.0, 1e-6);
+        // The usual example of a grey just failing 4.5 on white
+        assert_close(
+            contrast_ratio(Color::rgb(119, 119, 119), Color::WHITE),
+            4.478,
+            1e-3,
+        );
+        assert_close(contrast_ratio(Color::BLUE, Color::WHITE), 8.592, 1e-3);
+    }
+
+    #[test]
+    fn most_readable_picks_highest_contrast() {
+        let bw = [Color::WHITE, Color::BLACK];
+        assert_eq!(most_readable(Color::rgb(0, 0, 128), &bw), Color::WHITE);
+        assert_eq!(most_readable(Color::YELLOW, &bw), Color::BLACK);
+        assert_eq!(
+            most_readable(Color::WHITE, &[Color::YELLOW, Color::BLUE]),
+            Color::BLUE
+        );
+    }
+
+    #[test]
+    fn mix_in_linear_light() {
+        // Not 128, which would look too dark
+        assert_eq!(
+            mix(Color::BLACK, Color::WHITE, 0.5),
+            Color::rgb(188, 188, 188)
+        );
+        assert_eq!(
+            mix(Color::RED, Color::rgb(0, 255, 0), 0.5),
+            Color::rgb(188, 188, 0)
+        );
+        assert_eq!(mix(Color::RED, Color::BLUE, 0.0), Color::RED);
+        assert_eq!(mix(Color::RED, Color::BLUE, 1.0), Color::BLUE);
+        assert_eq!(mix(Color::RED, Color::BLUE, 7.0), Color::BLUE);
+        assert_eq!(mix(Color::ZERO, Color::WHITE, 0.5).0[3], 128);
+    }
+
+    #[test]
+    fn blend_linear_reference_values() {
+        let half_white = Color::rgba(255, 255, 255, 128);
+        assert_eq!(
+            blend_colors_linear(half_white, Color::BLACK),
+            Color::rgb(188, 188, 188)
+        );
+        assert_eq!(blend_colors_linear(Color::RED, Color::BLUE), Color::RED);
+        assert_eq!(
+            blend_colors_linear(Color::RED.with_alpha(0), Color::rgba(1, 2, 3, 4)),
+            Color::rgba(1, 2, 3, 4)
+        );
+
+        let src = [half_white, Color::RED, Color::ZERO];
+        let mut dst = [Color::BLACK, Color::rgba(0, 0, 255, 9), Color::GREEN];
+        blend_row_linear(&mut dst, &src);
+        assert_eq!(
+            dst,
+            [
+                Color::rgb(188, 188, 188),
+                Color::rgba(255, 0, 0, 9),
+                Color::GREEN
+            ]
+        );
+    }
+
+    #[test]
+    fn palette_states_are_readable_and_distinct() {
+        let accents = [Color::rgb(0x3d, 0x7e, 0xff), Color::ORANGE, Color::GREY];
+        let elements = [
+            Color::rgb(40, 40, 48),
+            Color::rgb(230, 230, 235),
+            Color::GREY,
+        ];
+        for accent in accents {
+            for element in elements {
+                let palette = Palette::from_accent(accent, element);
+                let states = [
+                    palette.normal,
+                    palette.hover,
+                    palette.pressed,
+                    palette.selected,
+                ];
+                for colors in states {
+                    assert!(contrast_ratio(colors.text, colors.bg) >= 4.5);
+                }
+                assert_eq!(palette.normal.bg, element);
+                assert_ne!(palette.hover.bg, palette.normal.bg);
+                assert_ne!(palette.pressed.bg, palette.normal.bg);
+                assert_ne!(palette.disabled.bg, palette.normal.bg);
+                assert_eq!(palette.pressed.border, accent);
+            }
+        }
+    }
+}
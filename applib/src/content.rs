@@ -63,7 +63,7 @@ pub struct UuidProvider {
 }
 
 impl UuidProvider {
-    fn make_id(&mut self) -> ContentId {
+    pub(crate) fn make_id(&mut self) -> ContentId {
         let content_id = ContentId(self.next);
         if self.next == u64::MAX {
             log::warn!("Reached max content ID, wrapping around")
@@ -0,0 +1,134 @@
+use crate::{clip_intersection, Rect};
+use alloc::vec::Vec;
+
+// Rects closer than that are merged, a few extra pixels are cheaper to upload than
+// an extra region
+const MERGE_SLACK: u64 = 32 * 32;
+
+// Past that share of the bounds, the whole area is damaged
+const FULL_DAMAGE_NUM: u64 = 3;
+const FULL_DAMAGE_DEN: u64 = 4;
+
+// The parts of a framebuffer which changed, as at most max_rects rects.
+// When too fragmented, the whole bounds are damaged.
+#[derive(Clone, Debug)]
+pub struct DamageList {
+    bounds: Rect,
+    max_rects: usize,
+    rects: Vec<Rect>,
+    full: bool,
+}
+
+impl DamageList {
+    pub fn new(bounds: &Rect, max_rects: usize) -> Self {
+        DamageList {
+            bounds: bounds.clone(),
+            max_rects: usize::max(1, max_rects),
+            rects: Vec::new(),
+            full: false,
+        }
+    }
+
+    pub fn add(&mut self, rect: &Rect) {
+        if self.full {
+            return;
+        }
+
+        let mut rect = clip_intersection(&self.bounds, rect);
+        if area(&rect) == 0 {
+            return;
+        }
+
+        // The last added rect is the most likely to contain the new one, e.g for lines
+        // drawn pixel by pixel
+        if self
+            .rects
+            .last()
+            .is_some_and(|last| last.check_contains_rect(&rect))
+        {
+            return;
+        }
+
+        // Overlapping rects are always merged so that they stay disjoint. Merging can make
+        // the rect reach others, so this is repeated until it does not grow.
+        while let Some(i) = self.rects.iter().position(|other| {
+            let overlap = area(&clip_intersection(other, &rect));
+            let merged_area = area(&other.bounding_box(&rect));
+            overlap > 0 || merged_area <= area(other) + area(&rect) + MERGE_SLACK
+        }) {
+            let other = self.rects.swap_remove(i);
+            rect = other.bounding_box(&rect);
+        }
+        self.rects.push(rect);
+
+        while self.rects.len() > self.max_rects {
+            self.merge_closest();
+        }
+
+        let covered: u64 = self.rects.iter().map(area).sum();
+        if covered * FULL_DAMAGE_DEN >= area(&self.bounds) * FULL_DAMAGE_NUM {
+            self.set_full();
+        }
+    }
+
+    pub fn add_list(&mut self, other: &DamageList) {
+        match other.full {
+            true => self.add(&other.bounds),
+            false => other.rects.iter().for_each(|rect| self.add(rect)),
+        }
+    }
+
+    pub fn set_full(&mut self) {
+        self.full = true;
+        self.rects.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.full = false;
+        self.rects.clear();
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.full && self.rects.is_empty()
+    }
+
+    // Disjoint rects, or the bounds when fully damaged
+    pub fn rects(&self) -> &[Rect] {
+        match self.full {
+            true => core::slice::from_ref(&self.bounds),
+            false => &self.rects,
+        }
+    }
+
+    // In pixels
+    pub fn area(&self) -> u64 {
+        self.rects().iter().map(area).sum()
+    }
+
+    // The pair which wastes the fewest pixels once merged
+    fn merge_closest(&mut self) {
+        let n = self.rects.len();
+        let (i, j) = (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .min_by_key(|&(i, j)| {
+                let (a, b) = (&self.rects[i], &self.rects[j]);
+                area(&a.bounding_box(b)) - (area(a) + area(b))
+            })
+            .expect("Not enough rects to merge");
+
+        let b = self.rects.swap_remove(j);
+        let a = self.rects.swap_remove(i);
+        let merged = a.bounding_box(&b);
+
+        // Not pushed as is, the merged rect may now overlap others
+        self.add(&merged);
+    }
+}
+
+fn area(rect: &Rect) -> u64 {
+    rect.w as u64 * rect.h as u64
+}
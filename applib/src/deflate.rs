@@ -0,0 +1,561 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+// zlib stream (RFC 1950) of a single deflate block with the fixed Huffman codes (RFC 1951).
+// The input is fed in pieces and the compressed bytes can be taken out as they come, so
+// neither the whole input nor the whole output needs to be kept in memory.
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// How many earlier positions with the same hash are tried, more compresses better but slower
+const MAX_CHAIN: usize = 24;
+// Good enough matches end the search early
+const NICE_MATCH: usize = 128;
+// Inside longer matches only the first positions go in the hash table, flat areas would
+// spend most of the time there otherwise
+const MAX_INSERT_LEN: usize = 32;
+
+const HASH_BITS: u32 = 15;
+const NO_POS: u32 = u32::MAX;
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+pub(crate) struct ZlibEncoder {
+    // Input not compressed yet, after up to WINDOW_SIZE bytes of history
+    buf: Vec<u8>,
+    // Position of buf[0] in the whole input
+    buf_start: usize,
+    // Next position to compress, in the whole input
+    pos: usize,
+    // Last position with each hash, and the previous one with the same hash of each
+    // position of the window
+    head: Vec<u32>,
+    prev: Vec<u32>,
+    adler: (u32, u32),
+    bits: BitWriter,
+}
+
+impl ZlibEncoder {
+    pub(crate) fn new() -> Self {
+        let mut bits = BitWriter::new();
+        // Deflate with a 32K window, default level. The check bits make the header a
+        // multiple of 31.
+        bits.out.extend_from_slice(&[0x78, 0x9c]);
+        // Final block, fixed codes
+        bits.write(0b011, 3);
+
+        ZlibEncoder {
+            buf: Vec::new(),
+            buf_start: 0,
+            pos: 0,
+            head: vec![NO_POS; 1 << HASH_BITS],
+            prev: vec![NO_POS; WINDOW_SIZE],
+            adler: (1, 0),
+            bits,
+        }
+    }
+
+    pub(crate) fn write(&mut self, data: &[u8]) {
+        self.update_adler(data);
+        self.buf.extend_from_slice(data);
+
+        // Matches can run until the end of the input, so the last bytes wait for more
+        let end = self.buf_start + self.buf.len();
+        if end >= MAX_MATCH {
+            self.compress_until(end - MAX_MATCH);
+        }
+
+        // Drops the history older than the window
+        let keep_from = self.pos.saturating_sub(WINDOW_SIZE);
+        if keep_from - self.buf_start >= WINDOW_SIZE {
+            self.buf.drain(..keep_from - self.buf_start);
+            self.buf_start = keep_from;
+        }
+    }
+
+    // Compressed bytes so far, whole bytes only
+    pub(crate) fn take_output(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.bits.out)
+    }
+
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        self.compress_until(self.buf_start + self.buf.len());
+        self.write_symbol(256);
+        self.bits.flush();
+
+        let (a, b) = self.adler;
+        self.bits
+            .out
+            .extend_from_slice(&((b << 16) | a).to_be_bytes());
+        self.bits.out
+    }
+
+    fn update_adler(&mut self, data: &[u8]) {
+        const MOD: u32 = 65521;
+        // The sums cannot overflow over this many bytes
+        const MAX_RUN: usize = 5552;
+        let (mut a, mut b) = self.adler;
+        for run in data.chunks(MAX_RUN) {
+            for &byte in run {
+                a += byte as u32;
+                b += a;
+            }
+            a %= MOD;
+            b %= MOD;
+        }
+        self.adler = (a, b);
+    }
+
+    fn compress_until(&mut self, end: usize) {
+        let data_end = self.buf_start + self.buf.len();
+        while self.pos < end {
+            let pos = self.pos;
+            let (len, dist) = self.find_match(pos, data_end);
+            if len >= MIN_MATCH {
+                self.write_match(len, dist);
+                for p in pos..pos + usize::min(len, MAX_INSERT_LEN) {
+                    self.insert_hash(p, data_end);
+                }
+                self.pos += len;
+            } else {
+                let literal = self.buf[pos - self.buf_start];
+                self.write_symbol(literal as u16);
+                self.insert_hash(pos, data_end);
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn hash_at(&self, pos: usize) -> usize {
+        let i = pos - self.buf_start;
+        let v = u32::from_le_bytes([self.buf[i], self.buf[i + 1], self.buf[i + 2], 0]);
+        (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert_hash(&mut self, pos: usize, data_end: usize) {
+        if pos + MIN_MATCH > data_end {
+            return;
+        }
+        let h = self.hash_at(pos);
+        self.prev[pos % WINDOW_SIZE] = self.head[h];
+        self.head[h] = pos as u32;
+    }
+
+    fn find_match(&self, pos: usize, data_end: usize) -> (usize, usize) {
+        let max_len = usize::min(MAX_MATCH, data_end - pos);
+        if max_len < MIN_MATCH {
+            return (0, 0);
+        }
+
+        let data = &self.buf[pos - self.buf_start..pos - self.buf_start + max_len];
+        let min_pos = usize::max(pos.saturating_sub(WINDOW_SIZE), self.buf_start);
+
+        let (mut best_len, mut best_dist) = (0, 0);
+        let mut candidate = self.head[self.hash_at(pos)];
+        for _ in 0..MAX_CHAIN {
+            if candidate == NO_POS || (candidate as usize) < min_pos {
+                break;
+            }
+            let cand = candidate as usize;
+            let cand_data = &self.buf[cand - self.buf_start..];
+            let len = match_len(data, cand_data);
+            if len > best_len {
+                (best_len, best_dist) = (len, pos - cand);
+                if len >= usize::min(NICE_MATCH, max_len) {
+                    break;
+                }
+            }
+            let next = self.prev[cand % WINDOW_SIZE];
+            // Older entries of the ring were overwritten
+            if next == NO_POS || next as usize >= cand {
+                break;
+            }
+            candidate = next;
+        }
+
+        (best_len, best_dist)
+    }
+
+    // Literal/length symbol with the fixed code
+    fn write_symbol(&mut self, symbol: u16) {
+        let (code, len) = match symbol {
+            0..=143 => (0x30 + symbol, 8),
+            144..=255 => (0x190 + symbol - 144, 9),
+            256..=279 => (symbol - 256, 7),
+            _ => (0xc0 + symbol - 280, 8),
+        };
+        self.bits.write_huffman(code as u32, len);
+    }
+
+    fn write_match(&mut self, len: usize, dist: usize) {
+        let i = LENGTH_BASES.partition_point(|&base| base as usize <= len) - 1;
+        self.write_symbol(257 + i as u16);
+        self.bits.write(
+            (len - LENGTH_BASES[i] as usize) as u32,
+            LENGTH_EXTRA[i] as u32,
+        );
+
+        let i = DIST_BASES.partition_point(|&base| base as usize <= dist) - 1;
+        self.bits.write_huffman(i as u32, 5);
+        self.bits
+            .write((dist - DIST_BASES[i] as usize) as u32, DIST_EXTRA[i] as u32);
+    }
+}
+
+// Compares 8 bytes at a time
+fn match_len(a: &[u8], b: &[u8]) -> usize {
+    let n = usize::min(a.len(), b.len());
+    let mut len = 0;
+    while len + 8 <= n {
+        let x = u64::from_le_bytes(a[len..len + 8].try_into().unwrap());
+        let y = u64::from_le_bytes(b[len..len + 8].try_into().unwrap());
+        if x != y {
+            return len + ((x ^ y).trailing_zeros() / 8) as usize;
+        }
+        len += 8;
+    }
+    len + a[len..n]
+        .iter()
+        .zip(b[len..n].iter())
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    n: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            out: Vec::new(),
+            acc: 0,
+            n: 0,
+        }
+    }
+
+    // Least significant bit first
+    fn write(&mut self, value: u32, n: u32) {
+        self.acc |= (value as u64) << self.n;
+        self.n += n;
+        while self.n >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.n -= 8;
+        }
+    }
+
+    // Huffman codes go most significant bit first
+    fn write_huffman(&mut self, code: u32, n: u32) {
+        self.write(code.reverse_bits() >> (32 - n), n);
+    }
+
+    fn flush(&mut self) {
+        if self.n > 0 {
+            self.out.push(self.acc as u8);
+            (self.acc, self.n) = (0, 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::Rng;
+
+    // Reference decoder for zlib streams, all block types, written from RFC 1950/1951
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bit(&mut self) -> u32 {
+            let byte = *self.data.get(self.pos / 8).expect("Truncated stream");
+            self.pos += 1;
+            ((byte >> ((self.pos - 1) % 8)) & 1) as u32
+        }
+
+        fn bits(&mut self, n: u32) -> u32 {
+            (0..n).fold(0, |v, i| v | (self.bit() << i))
+        }
+
+        fn align(&mut self) {
+            self.pos = self.pos.next_multiple_of(8);
+        }
+    }
+
+    // Canonical code from the lengths: (code, len) of each symbol
+    struct Huffman(Vec<(u32, u32)>);
+
+    impl Huffman {
+        fn new(lengths: &[u32]) -> Self {
+            let mut next = 0;
+            let mut codes = vec![(0, 0); lengths.len()];
+            for len in 1..=15 {
+                for (symbol, _) in lengths.iter().enumerate().filter(|(_, &l)| l == len) {
+                    codes[symbol] = (next, len);
+                    next += 1;
+                }
+                next <<= 1;
+            }
+            Huffman(codes)
+        }
+
+        fn decode(&self, reader: &mut BitReader) -> usize {
+            let (mut code, mut len) = (0, 0);
+            loop {
+                code = (code << 1) | reader.bit();
+                len += 1;
+                assert!(len <= 15, "Invalid code");
+                if let Some(symbol) = self.0.iter().position(|&c| c == (code, len)) {
+                    return symbol;
+                }
+            }
+        }
+    }
+
+    fn inflate(zlib: &[u8]) -> Vec<u8> {
+        assert_eq!(zlib[0] & 0xf, 8, "Not deflate");
+        assert_eq!(
+            u16::from_be_bytes([zlib[0], zlib[1]]) % 31,
+            0,
+            "Bad header check"
+        );
+
+        let mut reader = BitReader {
+            data: &zlib[2..],
+            pos: 0,
+        };
+        let mut out = Vec::new();
+
+        loop {
+            let last = reader.bit() == 1;
+            match reader.bits(2) {
+                0 => {
+                    reader.align();
+                    let len = reader.bits(16);
+                    assert_eq!(len ^ 0xffff, reader.bits(16), "Bad stored length");
+                    for _ in 0..len {
+                        out.push(reader.bits(8) as u8);
+                    }
+                }
+                1 => {
+                    let mut lengths = vec![8; 144];
+                    lengths.extend([9; 112]);
+                    lengths.extend([7; 24]);
+                    lengths.extend([8; 8]);
+                    let lit = Huffman::new(&lengths);
+                    let dist = Huffman::new(&[5; 30]);
+                    inflate_block(&mut reader, &lit, &dist, &mut out);
+                }
+                2 => {
+                    let (lit, dist) = read_dynamic_codes(&mut reader);
+                    inflate_block(&mut reader, &lit, &dist, &mut out);
+                }
+                _ => panic!("Invalid block type"),
+            }
+            if last {
+                break;
+            }
+        }
+
+        reader.align();
+        let adler = &zlib[2 + reader.pos / 8..];
+        assert_eq!(adler.len(), 4, "Data after the stream");
+        let mut encoder = ZlibEncoder::new();
+        encoder.update_adler(&out);
+        let (a, b) = encoder.adler;
+        assert_eq!(adler, ((b << 16) | a).to_be_bytes(), "Bad Adler-32");
+
+        out
+    }
+
+    fn read_dynamic_codes(reader: &mut BitReader) -> (Huffman, Huffman) {
+        const ORDER: [usize; 19] = [
+            16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+        ];
+        let nb_lit = reader.bits(5) as usize + 257;
+        let nb_dist = reader.bits(5) as usize + 1;
+        let nb_code_len = reader.bits(4) as usize + 4;
+
+        let mut code_lengths = [0; 19];
+        for &i in &ORDER[..nb_code_len] {
+            code_lengths[i] = reader.bits(3);
+        }
+        let code_len_code = Huffman::new(&code_lengths);
+
+        let mut lengths = Vec::new();
+        while lengths.len() < nb_lit + nb_dist {
+            match code_len_code.decode(reader) {
+                len @ 0..=15 => lengths.push(len as u32),
+                16 => {
+                    let prev = *lengths.last().expect("Nothing to repeat");
+                    let n = 3 + reader.bits(2);
+                    lengths.extend((0..n).map(|_| prev));
+                }
+                17 => lengths.extend((0..3 + reader.bits(3)).map(|_| 0)),
+                _ => lengths.extend((0..11 + reader.bits(7)).map(|_| 0)),
+            }
+        }
+        assert_eq!(lengths.len(), nb_lit + nb_dist, "Lengths overflow");
+
+        (
+            Huffman::new(&lengths[..nb_lit]),
+            Huffman::new(&lengths[nb_lit..]),
+        )
+    }
+
+    fn inflate_block(reader: &mut BitReader, lit: &Huffman, dist: &Huffman, out: &mut Vec<u8>) {
+        loop {
+            let symbol = lit.decode(reader);
+            match symbol {
+                0..=255 => out.push(symbol as u8),
+                256 => return,
+                _ => {
+                    let i = symbol - 257;
+                    let len =
+                        LENGTH_BASES[i] as usize + reader.bits(LENGTH_EXTRA[i] as u32) as usize;
+                    let i = dist.decode(reader);
+                    let d = DIST_BASES[i] as usize + reader.bits(DIST_EXTRA[i] as u32) as usize;
+                    assert!(d <= out.len(), "Distance too far back");
+                    for _ in 0..len {
+                        out.push(out[out.len() - d]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn compress(data: &[u8], piece_len: usize) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new();
+        let mut zlib = Vec::new();
+        for piece in data.chunks(piece_len) {
+            encoder.write(piece);
+            zlib.extend(encoder.take_output());
+        }
+        zlib.extend(encoder.finish());
+        zlib
+    }
+
+    // The vectors below are from zlib
+    #[test]
+    fn inflate_stored_block() {
+        let zlib = [
+            120, 1, 1, 20, 0, 235, 255, 72, 101, 108, 108, 111, 44, 32, 115, 116, 111, 114, 101,
+            100, 32, 98, 108, 111, 99, 107, 33, 75, 140, 7, 30,
+        ];
+        assert_eq!(inflate(&zlib), b"Hello, stored block!");
+    }
+
+    #[test]
+    fn inflate_fixed_block() {
+        let zlib = [
+            120, 218, 75, 76, 74, 78, 132, 33, 29, 133, 140, 212, 156, 156, 124, 8, 9, 0, 118, 231,
+            9, 45,
+        ];
+        assert_eq!(inflate(&zlib), b"abcabcabcabc, hello hello");
+    }
+
+    #[test]
+    fn inflate_dynamic_block() {
+        let zlib = [
+            120, 218, 173, 140, 219, 17, 195, 32, 12, 4, 91, 185, 2, 50, 238, 73, 65, 146, 33, 54,
+            40, 6, 225, 71, 170, 183, 135, 26, 242, 119, 59, 59, 123, 30, 5, 91, 79, 97, 193, 187,
+            218, 81, 160, 118, 226, 211, 243, 183, 193, 118, 169, 240, 71, 175, 244, 187, 192, 54,
+            79, 32, 176, 232, 74, 46, 104, 94, 133, 50, 142, 228, 17, 124, 21, 202, 41, 32, 118,
+            213, 76, 5, 193, 88, 218, 107, 164, 99, 66, 147, 15, 98, 114, 250, 211, 201, 13, 210,
+            239, 65, 130,
+        ];
+        let sentence = "a deflate stream with dynamic huffman codes, the codes fit the data. ";
+        let expected =
+            "the quick brown fox jumps over the lazy dog. ".to_owned() + &sentence.repeat(2);
+        assert_eq!(inflate(&zlib), expected.as_bytes());
+    }
+
+    // A fixed block, an empty stored one from a full flush, then the final fixed block
+    #[test]
+    fn inflate_several_blocks() {
+        let zlib = [
+            120, 218, 74, 203, 44, 42, 46, 81, 72, 202, 201, 79, 206, 214, 81, 0, 0, 0, 0, 255,
+            255, 43, 78, 77, 206, 207, 75, 81, 72, 202, 201, 79, 206, 6, 0, 121, 73, 9, 71,
+        ];
+        assert_eq!(inflate(&zlib), b"first block, second block");
+    }
+
+    #[test]
+    fn adler32_reference_value() {
+        let mut encoder = ZlibEncoder::new();
+        encoder.update_adler(b"Wikipedia");
+        let (a, b) = encoder.adler;
+        assert_eq!((b << 16) | a, 0x11e6_0398);
+
+        // Long enough for the sums to be reduced several times
+        let mut encoder = ZlibEncoder::new();
+        encoder.update_adler(&[0xff; 100_000]);
+        let (a, b) = encoder.adler;
+        assert_eq!((b << 16) | a, 0x149a_302c);
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        assert!(inflate(&compress(&[], 1)).is_empty());
+    }
+
+    #[test]
+    fn round_trip_text() {
+        let text = b"to be or not to be, that is the question: whether 'tis nobler";
+        for piece_len in [1, 7, 1000] {
+            assert_eq!(inflate(&compress(text, piece_len)), text);
+        }
+    }
+
+    #[test]
+    fn round_trip_noise() {
+        let mut rng = Rng::new(1);
+        let data: Vec<u8> = (0..5000).map(|_| rng.next_u32() as u8).collect();
+        assert_eq!(inflate(&compress(&data, 333)), data);
+    }
+
+    // Matches at the maximum length and distance, and history dropped as the window moves
+    #[test]
+    fn round_trip_long_matches() {
+        let mut rng = Rng::new(2);
+        let block: Vec<u8> = (0..WINDOW_SIZE - 300)
+            .map(|_| rng.next_u32() as u8)
+            .collect();
+        let mut data = block.clone();
+        data.extend([7; 300]);
+        // Exactly WINDOW_SIZE back
+        data.extend(&block);
+        data.extend(&block[..100]);
+
+        let zlib = compress(&data, 4096);
+        // The repeated part takes a few bytes per match
+        let first_len = compress(&block, 4096).len();
+        assert!(
+            zlib.len() < first_len + 1000,
+            "{} {}",
+            zlib.len(),
+            first_len
+        );
+        assert_eq!(inflate(&zlib), data);
+    }
+}
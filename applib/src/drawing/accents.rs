@@ -0,0 +1,187 @@
+use crate::{blend_colors, Color, FbViewMut, Rect};
+
+// Diacritics of the Latin-1 letters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Accent {
+    Acute,
+    Grave,
+    Circumflex,
+    Diaeresis,
+    Tilde,
+    Ring,
+    Cedilla,
+    Stroke,
+}
+
+impl Accent {
+    // What a dead key types when it does not combine, e.g. when followed by a space
+    pub fn spacing_char(&self) -> char {
+        match self {
+            Accent::Acute => '´',
+            Accent::Grave => '`',
+            Accent::Circumflex => '^',
+            Accent::Diaeresis => '¨',
+            Accent::Tilde => '~',
+            Accent::Ring => '°',
+            Accent::Cedilla => '¸',
+            Accent::Stroke => '/',
+        }
+    }
+
+    // 5x3 patterns, stretched over the room the glyph leaves for them
+    fn pattern(&self) -> [&'static str; 3] {
+        match self {
+            Accent::Acute => ["...#.", "..#..", ".#..."],
+            Accent::Grave => [".#...", "..#..", "...#."],
+            Accent::Circumflex => ["..#..", ".#.#.", "#...#"],
+            Accent::Diaeresis => [".....", "#...#", "....."],
+            Accent::Tilde => [".....", ".##.#", "#.##."],
+            Accent::Ring => [".###.", ".#.#.", ".###."],
+            Accent::Cedilla => ["..#..", "...#.", "..##."],
+            Accent::Stroke => ["....#", "..#..", "#...."],
+        }
+    }
+}
+
+const LATIN1_LETTERS: [(char, char, Accent); 58] = [
+    ('À', 'A', Accent::Grave),
+    ('Á', 'A', Accent::Acute),
+    ('Â', 'A', Accent::Circumflex),
+    ('Ã', 'A', Accent::Tilde),
+    ('Ä', 'A', Accent::Diaeresis),
+    ('Å', 'A', Accent::Ring),
+    ('Ç', 'C', Accent::Cedilla),
+    ('È', 'E', Accent::Grave),
+    ('É', 'E', Accent::Acute),
+    ('Ê', 'E', Accent::Circumflex),
+    ('Ë', 'E', Accent::Diaeresis),
+    ('Ì', 'I', Accent::Grave),
+    ('Í', 'I', Accent::Acute),
+    ('Î', 'I', Accent::Circumflex),
+    ('Ï', 'I', Accent::Diaeresis),
+    ('Ð', 'D', Accent::Stroke),
+    ('Ñ', 'N', Accent::Tilde),
+    ('Ò', 'O', Accent::Grave),
+    ('Ó', 'O', Accent::Acute),
+    ('Ô', 'O', Accent::Circumflex),
+    ('Õ', 'O', Accent::Tilde),
+    ('Ö', 'O', Accent::Diaeresis),
+    ('Ø', 'O', Accent::Stroke),
+    ('Ù', 'U', Accent::Grave),
+    ('Ú', 'U', Accent::Acute),
+    ('Û', 'U', Accent::Circumflex),
+    ('Ü', 'U', Accent::Diaeresis),
+    ('Ý', 'Y', Accent::Acute),
+    ('à', 'a', Accent::Grave),
+    ('á', 'a', Accent::Acute),
+    ('â', 'a', Accent::Circumflex),
+    ('ã', 'a', Accent::Tilde),
+    ('ä', 'a', Accent::Diaeresis),
+    ('å', 'a', Accent::Ring),
+    ('ç', 'c', Accent::Cedilla),
+    ('è', 'e', Accent::Grave),
+    ('é', 'e', Accent::Acute),
+    ('ê', 'e', Accent::Circumflex),
+    ('ë', 'e', Accent::Diaeresis),
+    ('ì', 'i', Accent::Grave),
+    ('í', 'i', Accent::Acute),
+    ('î', 'i', Accent::Circumflex),
+    ('ï', 'i', Accent::Diaeresis),
+    ('ð', 'd', Accent::Stroke),
+    ('ñ', 'n', Accent::Tilde),
+    ('ò', 'o', Accent::Grave),
+    ('ó', 'o', Accent::Acute),
+    ('ô', 'o', Accent::Circumflex),
+    ('õ', 'o', Accent::Tilde),
+    ('ö', 'o', Accent::Diaeresis),
+    ('ø', 'o', Accent::Stroke),
+    ('ù', 'u', Accent::Grave),
+    ('ú', 'u', Accent::Acute),
+    ('û', 'u', Accent::Circumflex),
+    ('ü', 'u', Accent::Diaeresis),
+    ('ý', 'y', Accent::Acute),
+    ('ÿ', 'y', Accent::Diaeresis),
+    ('Ÿ', 'Y', Accent::Diaeresis),
+];
+
+// The accented letter, if there is one in the table
+pub fn compose(base: char, accent: Accent) -> Option<char> {
+    LATIN1_LETTERS
+        .iter()
+        .find(|(_, b, a)| *b == base && *a == accent)
+        .map(|(c, _, _)| *c)
+}
+
+pub fn decompose(c: char) -> Option<(char, Accent)> {
+    LATIN1_LETTERS
+        .iter()
+        .find(|(composed, _, _)| *composed == c)
+        .map(|(_, base, accent)| (*base, *accent))
+}
+
+// ASCII look-alikes for the other Latin-1 characters, when a font lacks them
+pub fn ascii_fallback(c: char) -> Option<char> {
+    let fallback = match c {
+        '\u{a0}' => ' ',
+        '¡' => '!',
+        '¦' => '|',
+        '«' => '<',
+        '\u{ad}' => '-',
+        '´' => '\'',
+        '·' => '.',
+        '¸' => ',',
+        '»' => '>',
+        '¿' => '?',
+        '×' => 'x',
+        'Þ' => 'P',
+        'ß' => 'B',
+        'þ' => 'p',
+        _ => return None,
+    };
+    Some(fallback)
+}
+
+// Draws the mark of an accent over, under or through the glyph drawn in the cell.
+// The vertical bounds of the glyph are in cell coordinates, the mark goes above
+// glyph_top, under base_y, or between them.
+pub(crate) fn draw_accent<F: FbViewMut>(
+    fb: &mut F,
+    accent: Accent,
+    cell: &Rect,
+    glyph_top: i64,
+    base_y: i64,
+    color: Color,
+) {
+    let mark_h = i64::max(2, cell.h as i64 / 6);
+    let inset = cell.w as i64 / 8;
+
+    let (y0, y1) = match accent {
+        Accent::Cedilla => (base_y, i64::min(cell.h as i64, base_y + mark_h)),
+        Accent::Stroke => (glyph_top, base_y),
+        _ => (
+            i64::max(0, glyph_top - 1 - mark_h),
+            i64::max(1, glyph_top - 1),
+        ),
+    };
+    let mark_rect = Rect {
+        x0: cell.x0 + inset,
+        y0: cell.y0 + y0,
+        w: u32::max(1, cell.w.saturating_sub(2 * inset as u32)),
+        h: u32::max(1, (y1 - y0) as u32),
+    };
+
+    let pattern = accent.pattern();
+    let [x0, y0, x1, y1] = mark_rect.as_xyxy();
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            let px = ((x - x0) * 5 / mark_rect.w as i64) as usize;
+            let py = ((y - y0) * 3 / mark_rect.h as i64) as usize;
+            if pattern[py].as_bytes()[px] != b'#' {
+                continue;
+            }
+            if let Some(curr_color) = fb.get_pixel(x, y) {
+                fb.set_pixel(x, y, blend_colors(color, curr_color));
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{Color, FbLine, FbView, FbViewMut, Framebuffer, OwnedPixels};
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Two box blurs in a row look close enough to a gaussian one
+const BLUR_PASSES: u32 = 2;
+
+#[derive(Clone, Debug, PartialEq, Hash)]
+pub struct Shadow {
+    // From the origin of what casts the shadow
+    pub offset: (i64, i64),
+    // How far the shadow spreads around the silhouette
+    pub radius: u32,
+    pub color: Color,
+}
+
+impl Shadow {
+    // Where render_shadow() output goes, for a silhouette drawn at pos
+    pub fn origin(&self, pos: (i64, i64)) -> (i64, i64) {
+        let r = self.radius as i64;
+        (pos.0 + self.offset.0 - r, pos.1 + self.offset.1 - r)
+    }
+}
+
+impl Default for Shadow {
+    fn default() -> Self {
+        Shadow {
+            offset: (4, 4),
+            radius: 8,
+            color: Color::rgba(0, 0, 0, 100),
+        }
+    }
+}
+
+// The blurred alpha of src in the shadow color, larger than src by the radius on each side
+pub fn render_shadow<F: FbView>(src: &F, shadow: &Shadow) -> Framebuffer<OwnedPixels> {
+    let (w, h) = src.shape();
+    let r = shadow.radius;
+    let (shadow_w, shadow_h) = (w + 2 * r, h + 2 * r);
+
+    let mut alpha = vec![0u8; (shadow_w * shadow_h) as usize];
+    for y in 0..h {
+        let i0 = ((y + r) * shadow_w + r) as usize;
+        for x in 0..w {
+            if let Some(color) = src.get_pixel(x as i64, y as i64) {
+                alpha[i0 + x as usize] = color.0[3];
+            }
+        }
+    }
+
+    box_blur(&mut alpha, shadow_w, shadow_h, r);
+
+    let (cr, cg, cb, ca) = shadow.color.as_rgba();
+    let mut shadow_fb = Framebuffer::new_owned(shadow_w, shadow_h);
+    for (pixel, a) in shadow_fb.get_data_mut().iter_mut().zip(alpha) {
+        let a = (a as u16 * ca as u16 / 255) as u8;
+        *pixel = Color::rgba(cr, cg, cb, a);
+    }
+
+    shadow_fb
+}
+
+// Blurs all the channels. Colors are weighted by their alpha, so that transparent pixels
+// do not darken their neighbours.
+pub fn blur(fb: &mut Framebuffer<OwnedPixels>, radius: u32) {
+    if radius == 0 {
+        return;
+    }
+
+    let (w, h) = fb.shape();
+    let mut channels: [Vec<u8>; 4] = Default::default();
+    for color in fb.get_data() {
+        let (r, g, b, a) = color.as_rgba();
+        let premultiply = |v: u8| ((v as u16 * a as u16 + 127) / 255) as u8;
+        for (channel, v) in
+            channels
+                .iter_mut()
+                .zip([premultiply(r), premultiply(g), premultiply(b), a])
+        {
+            channel.push(v);
+        }
+    }
+
+    for channel in channels.iter_mut() {
+        box_blur(channel, w, h, radius);
+    }
+
+    for (i, pixel) in fb.get_data_mut().iter_mut().enumerate() {
+        let a = channels[3][i];
+        let unpremultiply = |v: u8| match a {
+            0 => 0,
+            _ => u16::min(255, (v as u16 * 255 + a as u16 / 2) / a as u16) as u8,
+        };
+        *pixel = Color::rgba(
+            unpremultiply(channels[0][i]),
+            unpremultiply(channels[1][i]),
+            unpremultiply(channels[2][i]),
+            a,
+        );
+    }
+}
+
+// Values outside of the buffer are those of the nearest edge. The radius is shared between
+// the passes, so that it stays the distance the values spread to.
+pub fn box_blur(data: &mut [u8], w: u32, h: u32, radius: u32) {
+    assert_eq!(data.len(), (w * h) as usize);
+
+    if w == 0 || h == 0 {
+        return;
+    }
+
+    let (w, h) = (w as usize, h as usize);
+    let mut line = Vec::with_capacity(usize::max(w, h));
+
+    for pass in 0..BLUR_PASSES {
+        let r = (radius + pass) / BLUR_PASSES;
+        if r == 0 {
+            continue;
+        }
+        for y in 0..h {
+            blur_line(data, y * w, 1, w, r as usize, &mut line);
+        }
+        for x in 0..w {
+            blur_line(data, x, w, h, r as usize, &mut line);
+        }
+    }
+}
+
+// Running sum over a window of 2 * r + 1 values, so the cost does not depend on r
+fn blur_line(
+    data: &mut [u8],
+    start: usize,
+    stride: usize,
+    len: usize,
+    r: usize,
+    line: &mut Vec<u8>,
+) {
+    line.clear();
+    line.extend((0..len).map(|i| data[start + i * stride]));
+
+    let window = 2 * r as u32 + 1;
+    let at = |i: isize| line[i.clamp(0, len as isize - 1) as usize] as u32;
+    let mut sum: u32 = (-(r as isize)..r as isize).map(at).sum();
+
+    for i in 0..len as isize {
+        sum += at(i + r as isize);
+        data[start + i as usize * stride] = ((sum + window / 2) / window) as u8;
+        sum -= at(i - r as isize);
+    }
+}
+
+// Blends src with its alpha scaled by opacity, from 0 to 1
+pub fn blend_with_opacity<F: FbViewMut, F1: FbView>(
+    dst: &mut F,
+    src: &F1,
+    pos: (i64, i64),
+    opacity: f32,
+) {
+    let factor = (opacity.clamp(0.0, 1.0) * 256.0) as u32;
+    match factor {
+        0 => return,
+        256 => return dst.copy_from_fb(src, pos, true),
+        _ => (),
+    }
+
+    let (w, h) = src.shape();
+    let mut row = vec![Color::ZERO; w as usize];
+
+    for y in 0..h as i64 {
+        let src_line = src.get_line(0, w, y);
+        let x_start = src_line.x_data_start as usize;
+
+        row.fill(Color::ZERO);
+        for (dst, src) in row[x_start..].iter_mut().zip(src_line.data) {
+            let mut color = *src;
+            color.0[3] = ((color.0[3] as u32 * factor) >> 8) as u8;
+            *dst = color;
+        }
+
+        let faded_line = FbLine {
+            data: &row,
+            x_data_start: 0,
+            line_w: w,
+        };
+        dst.get_line_mut(pos.0, w, pos.1 + y)
+            .copy_from_line(&faded_line, true);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{Color, FbViewMut, Framebuffer, Rect};
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use num::Float;
+
+// Precision of the linear values converted back to sRGB
+const LINEAR_STEPS: usize = 4096;
+
+lazy_static! {
+    static ref SRGB_TO_LINEAR: [f32; 256] = core::array::from_fn(|i| {
+        let v = i as f32 / 255.0;
+        match v <= 0.04045 {
+            true => v / 12.92,
+            false => ((v + 0.055) / 1.055).powf(2.4),
+        }
+    });
+    static ref LINEAR_TO_SRGB: [u8; LINEAR_STEPS] = core::array::from_fn(|i| {
+        let v = i as f32 / (LINEAR_STEPS - 1) as f32;
+        let v = match v <= 0.0031308 {
+            true => v * 12.92,
+            false => 1.055 * v.powf(1.0 / 2.4) - 0.055,
+        };
+        (v * 255.0).round() as u8
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub enum GradientDirection {
+    // Left to right
+    Horizontal,
+    // Top to bottom
+    Vertical,
+    // In radians, from the left to the right edge at 0, turning clockwise (y goes down)
+    Angle(f32),
+}
+
+// Interpolated in linear light, a plain interpolation of the sRGB values gives muddy
+// midpoints. Alpha is interpolated as is.
+pub fn lerp_linear_light(c0: Color, c1: Color, t: f32) -> Color {
+    let (r0, g0, b0, a0) = c0.as_rgba();
+    let (r1, g1, b1, a1) = c1.as_rgba();
+    let t = t.clamp(0.0, 1.0);
+
+    let channel = |v0: u8, v1: u8| {
+        let (l0, l1) = (SRGB_TO_LINEAR[v0 as usize], SRGB_TO_LINEAR[v1 as usize]);
+        let l = l0 + (l1 - l0) * t;
+        LINEAR_TO_SRGB[(l * (LINEAR_STEPS - 1) as f32).round() as usize]
+    };
+    let a = a0 as f32 + (a1 as f32 - a0 as f32) * t;
+
+    Color::rgba(
+        channel(r0, r1),
+        channel(g0, g1),
+        channel(b0, b1),
+        a.round() as u8,
+    )
+}
+
+// Overwrites the pixels of the rect
+pub fn fill_linear_gradient<F: FbViewMut>(
+    fb: &mut F,
+    rect: &Rect,
+    start_color: Color,
+    end_color: Color,
+    direction: GradientDirection,
+) {
+    fill_ramp(
+        fb,
+        rect,
+        direction,
+        |t| lerp_linear_light(start_color, end_color, t),
+        false,
+    );
+}
+
+// The color is blended over the pixels of the rect with an alpha going from start_alpha
+// to end_alpha, e.g. to fade out the edge of a view
+pub fn fill_alpha_gradient<F: FbViewMut>(
+    fb: &mut F,
+    rect: &Rect,
+    color: Color,
+    start_alpha: u8,
+    end_alpha: u8,
+    direction: GradientDirection,
+) {
+    let (r, g, b, _) = color.as_rgba();
+    let alpha_at = |t: f32| {
+        let a = start_alpha as f32 + (end_alpha as f32 - start_alpha as f32) * t;
+        Color::rgba(r, g, b, a.round() as u8)
+    };
+    fill_ramp(fb, rect, direction, alpha_at, true);
+}
+
+// The colors are computed once along the gradient, then each row is copied from them
+fn fill_ramp<F, C>(fb: &mut F, rect: &Rect, direction: GradientDirection, color_at: C, blend: bool)
+where
+    F: FbViewMut,
+    C: Fn(f32) -> Color,
+{
+    let (fb_w, fb_h) = fb.shape();
+    let fb_rect = Rect {
+        x0: 0,
+        y0: 0,
+        w: fb_w,
+        h: fb_h,
+    };
+    let Some(clipped) = rect.intersection(&fb_rect) else {
+        return;
+    };
+
+    let t_of = |i: i64, n: u32| match n {
+        0 | 1 => 0.0,
+        n => i as f32 / (n - 1) as f32,
+    };
+
+    let Rect { x0, y0, w, h } = *rect;
+    let [cx0, cy0, _, cy1] = clipped.as_xyxy();
+
+    match direction {
+        // Rows have a single color, no need for a row buffer
+        GradientDirection::Vertical => {
+            for y in cy0..=cy1 {
+                let color = color_at(t_of(y - y0, h));
+                fb.fill_line(cx0, clipped.w, y, color, blend);
+            }
+        }
+
+        GradientDirection::Horizontal => {
+            let mut row_fb = Framebuffer::new_owned(clipped.w, 1);
+            for (i, pixel) in row_fb.get_data_mut().iter_mut().enumerate() {
+                *pixel = color_at(t_of(cx0 - x0 + i as i64, w));
+            }
+            for y in cy0..=cy1 {
+                fb.copy_from_fb(&row_fb, (cx0, y), blend);
+            }
+        }
+
+        GradientDirection::Angle(angle) => {
+            let (sin, cos) = (angle.sin(), angle.cos());
+            let project = |x: i64, y: i64| (x - x0) as f32 * cos + (y - y0) as f32 * sin;
+
+            // Along the direction, the gradient spans the projections of the corners
+            let [x1, y1] = [x0 + w as i64 - 1, y0 + h as i64 - 1];
+            let corners = [
+                project(x0, y0),
+                project(x1, y0),
+                project(x0, y1),
+                project(x1, y1),
+            ];
+            let p_min = corners.into_iter().fold(f32::MAX, f32::min);
+            let p_max = corners.into_iter().fold(f32::MIN, f32::max);
+
+            let ramp_len = (p_max - p_min).ceil() as u32 + 1;
+            let ramp: Vec<Color> = (0..ramp_len as i64)
+                .map(|i| color_at(t_of(i, ramp_len)))
+                .collect();
+
+            let mut row_fb = Framebuffer::new_owned(clipped.w, 1);
+            for y in cy0..=cy1 {
+                for (i, pixel) in row_fb.get_data_mut().iter_mut().enumerate() {
+                    let p = project(cx0 + i as i64, y) - p_min;
+                    *pixel = ramp[usize::min(p.round() as usize, ramp.len() - 1)];
+                }
+                fb.copy_from_fb(&row_fb, (cx0, y), blend);
+            }
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_polyline, draw_rect, StrokeStyle};
+use crate::drawing::text::{draw_str, Font};
+use crate::geometry::Point2D;
+use crate::{Color, FbViewMut, Framebuffer, OwnedPixels, Rect};
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+use lazy_static::lazy_static;
+use num::Float;
+use spin::Mutex;
+
+// For debugging and measuring, graphs are then drawn from scratch every time
+const DISABLE_CACHING: bool = false;
+
+// There are only ever a few graphs on screen
+const MAX_CACHED_GRAPHS: usize = 16;
+
+// Space between the tick labels and the plot
+const LABEL_MARGIN: u32 = 4;
+
+lazy_static! {
+    static ref GRAPHS: Mutex<GraphCache> = Mutex::new(GraphCache::new(MAX_CACHED_GRAPHS));
+}
+
+#[derive(Clone, Copy)]
+pub struct GraphStyle {
+    pub line_color: Color,
+    pub thickness: u32,
+    // Area between the line and the bottom of the plot, usually translucent
+    pub fill_color: Option<Color>,
+    pub bg_color: Color,
+    // Bounds of the y axis, taken from the data when None. Values outside of them are
+    // clamped.
+    pub y_min: Option<f32>,
+    pub y_max: Option<f32>,
+    pub grid: Option<GraphGrid>,
+}
+
+// Horizontal lines at round values of the y axis, labeled on the left
+#[derive(Clone, Copy)]
+pub struct GraphGrid {
+    pub font: &'static Font,
+    pub line_color: Color,
+    pub text_color: Color,
+    // Roughly, it is adjusted so that ticks land on round values
+    pub nb_ticks: u32,
+}
+
+impl Default for GraphStyle {
+    fn default() -> Self {
+        GraphStyle {
+            line_color: Color::WHITE,
+            thickness: 1,
+            fill_color: None,
+            bg_color: Color::BLACK,
+            y_min: None,
+            y_max: None,
+            grid: None,
+        }
+    }
+}
+
+impl Hash for GraphStyle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.line_color.hash(state);
+        self.thickness.hash(state);
+        self.fill_color.hash(state);
+        self.bg_color.hash(state);
+        self.y_min.map(f32::to_bits).hash(state);
+        self.y_max.map(f32::to_bits).hash(state);
+        if let Some(grid) = self.grid {
+            grid.font.name.hash(state);
+            grid.line_color.hash(state);
+            grid.text_color.hash(state);
+            grid.nb_ticks.hash(state);
+        }
+    }
+}
+
+// The series fills the rect from left to right, one sample per step. When there are more
+// samples than pixels, each column shows the range of the samples it covers, so that
+// spikes are not lost. NaN and infinite samples leave a gap in the line.
+//
+// Graphs are cached as they are drawn on their background, so that redrawing one that
+// did not change is a single copy. Those with a translucent background are drawn from
+// scratch every time.
+pub fn draw_series<F: FbViewMut>(fb: &mut F, rect: &Rect, series: &[f32], style: &GraphStyle) {
+    let (w, h) = rect.shape();
+    if w == 0 || h == 0 {
+        return;
+    }
+
+    if DISABLE_CACHING || style.bg_color.0[3] < 255 {
+        draw_rect(fb, rect, style.bg_color, true);
+        return render_graph(&mut fb.subregion_mut(rect), series, style);
+    }
+
+    let id = ContentId::from_hash(&(SeriesBits(series), style, w, h));
+
+    let cached = GRAPHS.lock().get(id);
+    let graph = cached.unwrap_or_else(|| {
+        let mut graph = Framebuffer::new_owned_filled(w, h, style.bg_color);
+        render_graph(&mut graph, series, style);
+        let graph = Arc::new(graph);
+        GRAPHS.lock().insert(id, graph.clone());
+        graph
+    });
+
+    fb.copy_from_fb(graph.as_ref(), (rect.x0, rect.y0), false);
+}
+
+// f32 is not Hash
+struct SeriesBits<'a>(&'a [f32]);
+
+impl Hash for SeriesBits<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.len().hash(state);
+        for val in self.0 {
+            val.to_bits().hash(state);
+        }
+    }
+}
+
+// Over the background
+fn render_graph<F: FbViewMut>(fb: &mut F, series: &[f32], style: &GraphStyle) {
+    let rect = fb.shape_as_rect();
+
+    let (y_min, y_max, ticks) = y_axis(series, style);
+    let scale = YScale { y_min, y_max };
+
+    let plot_rect = match style.grid {
+        Some(grid) => draw_grid(fb, &rect, &grid, &ticks, &scale),
+        None => rect,
+    };
+    if plot_rect.w == 0 || plot_rect.h == 0 {
+        return;
+    }
+
+    let to_y = |val: f32| scale.to_y(val, &plot_rect);
+    match series.len() > plot_rect.w as usize {
+        true => draw_compressed(fb, &plot_rect, series, style, to_y),
+        false => draw_lines(fb, &plot_rect, series, style, to_y),
+    }
+}
+
+struct YScale {
+    y_min: f32,
+    y_max: f32,
+}
+
+impl YScale {
+    // Row of the plot where the value goes
+    fn to_y(&self, val: f32, plot_rect: &Rect) -> i64 {
+        let frac = (val.clamp(self.y_min, self.y_max) - self.y_min) / (self.y_max - self.y_min);
+        let bottom = plot_rect.y0 + plot_rect.h as i64 - 1;
+        bottom - (frac * (plot_rect.h - 1) as f32).round() as i64
+    }
+}
+
+// Bounds of the y axis, and where the ticks go if there is a grid. Bounds taken from
+// the data are pushed out to the nearest ticks.
+fn y_axis(series: &[f32], style: &GraphStyle) -> (f32, f32, Vec<f32>) {
+    let finite = || series.iter().copied().filter(|val| val.is_finite());
+    let data_min = finite().reduce(f32::min);
+    let data_max = finite().reduce(f32::max);
+
+    let mut y_min = style.y_min.or(data_min).unwrap_or(0.0);
+    let mut y_max = style.y_max.or(data_max).unwrap_or(1.0);
+    // e.g. a flat series
+    if y_max <= y_min {
+        match (style.y_min, style.y_max) {
+            (_, Some(_)) => y_min = y_max - 1.0,
+            (Some(_), None) => y_max = y_min + 1.0,
+            (None, None) => (y_min, y_max) = (y_min - 0.5, y_max + 0.5),
+        }
+    }
+
+    let Some(grid) = style.grid.filter(|grid| grid.nb_ticks > 0) else {
+        return (y_min, y_max, Vec::new());
+    };
+
+    let step = tick_step((y_max - y_min) / grid.nb_ticks as f32);
+    if style.y_min.is_none() {
+        y_min = (y_min / step).floor() * step;
+    }
+    if style.y_max.is_none() {
+        y_max = (y_max / step).ceil() * step;
+    }
+
+    // With some slack for rounding errors, the bounds are often on a tick
+    let first = (y_min / step - 1e-3).ceil() as i64;
+    let last = (y_max / step + 1e-3).floor() as i64;
+    let ticks = (first..=last).map(|i| i as f32 * step).collect();
+
+    (y_min, y_max, ticks)
+}
+
+// The first of 1, 2 and 5 times a power of ten that is at least the given step
+fn tick_step(min_step: f32) -> f32 {
+    let magnitude = 10f32.powf(min_step.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|k| k * magnitude)
+        .find(|&step| step >= min_step)
+        .unwrap_or(10.0 * magnitude)
+}
+
+// Returns the rect left for the plot, to the right of the labels. Half a line of text is
+// kept above and below so that the top and bottom labels fit.
+fn draw_grid<F: FbViewMut>(
+    fb: &mut F,
+    rect: &Rect,
+    grid: &GraphGrid,
+    ticks: &[f32],
+    scale: &YScale,
+) -> Rect {
+    let font = grid.font;
+    let labels: Vec<String> = ticks.iter().map(|&val| tick_label(val, ticks)).collect();
+    let label_w = labels.iter().map(|label| font.text_width(label)).max();
+
+    let x_offset = label_w.map(|w| w + LABEL_MARGIN).unwrap_or(0);
+    let y_offset = font.char_h as u32 / 2;
+    let plot_rect = Rect {
+        x0: rect.x0 + x_offset as i64,
+        y0: rect.y0 + y_offset as i64,
+        w: rect.w.saturating_sub(x_offset),
+        h: rect.h.saturating_sub(2 * y_offset),
+    };
+    if plot_rect.w == 0 || plot_rect.h == 0 {
+        return plot_rect;
+    }
+
+    for (&val, label) in ticks.iter().zip(labels.iter()) {
+        let y = scale.to_y(val, &plot_rect);
+        let line_rect = Rect {
+            x0: plot_rect.x0,
+            y0: y,
+            w: plot_rect.w,
+            h: 1,
+        };
+        draw_rect(fb, &line_rect, grid.line_color, true);
+
+        // Right-aligned against the plot
+        let x = plot_rect.x0 - (LABEL_MARGIN + font.text_width(label)) as i64;
+        let y = y - font.char_h as i64 / 2;
+        draw_str(fb, label, x, y, font, grid.text_color, None);
+    }
+
+    plot_rect
+}
+
+// With as many decimals as the spacing of the ticks needs
+fn tick_label(val: f32, ticks: &[f32]) -> String {
+    let step = match ticks {
+        [a, b, ..] => b - a,
+        _ => 1.0,
+    };
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    format!("{:.*}", decimals, val)
+}
+
+// Runs of samples without gaps, as the index of their first sample and their values
+fn finite_runs(series: &[f32]) -> impl Iterator<Item = (usize, &[f32])> {
+    let mut start = 0;
+    series
+        .split(|val| !val.is_finite())
+        .map(move |run| {
+            let run_start = start;
+            start += run.len() + 1;
+            (run_start, run)
+        })
+        .filter(|(_, run)| !run.is_empty())
+}
+
+// At most one sample per column, joined by lines
+fn draw_lines<F, T>(fb: &mut F, plot_rect: &Rect, series: &[f32], style: &GraphStyle, to_y: T)
+where
+    F: FbViewMut,
+    T: Fn(f32) -> i64,
+{
+    let n = series.len();
+    let to_x = |i: usize| match n {
+        1 => plot_rect.x0,
+        _ => plot_rect.x0 + (i as i64 * (plot_rect.w as i64 - 1)) / (n as i64 - 1),
+    };
+    let bottom = plot_rect.y0 + plot_rect.h as i64;
+
+    for (start, run) in finite_runs(series) {
+        let points: Vec<Point2D<i64>> = run
+            .iter()
+            .enumerate()
+            .map(|(i, &val)| Point2D {
+                x: to_x(start + i),
+                y: to_y(val),
+            })
+            .collect();
+
+        // Column by column, each one once so that translucent fills stay even
+        if let Some(fill_color) = style.fill_color {
+            let last_x = points.last().unwrap().x;
+            let segments = points.windows(2).map(|pair| (pair[0], pair[1]));
+            let single = (points.len() == 1).then(|| (points[0], points[0]));
+            for (a, b) in segments.chain(single) {
+                let x_end = if b.x == last_x { b.x + 1 } else { b.x };
+                for x in a.x..x_end {
+                    let t = match b.x - a.x {
+                        0 => 0.0,
+                        dx => (x - a.x) as f32 / dx as f32,
+                    };
+                    let y = (a.y as f32 + t * (b.y - a.y) as f32).round() as i64;
+                    let fill_rect = Rect::from_xyxy([x, y, x, bottom - 1]);
+                    draw_rect(fb, &fill_rect, fill_color, true);
+                }
+            }
+        }
+
+        let stroke = StrokeStyle::solid(style.thickness);
+        draw_polyline(fb, &points, false, style.line_color, &stroke, false);
+    }
+}
+
+// More samples than columns, each column covers the range of its samples and reaches
+// towards the previous one so that the line stays connected
+fn draw_compressed<F, T>(fb: &mut F, plot_rect: &Rect, series: &[f32], style: &GraphStyle, to_y: T)
+where
+    F: FbViewMut,
+    T: Fn(f32) -> i64,
+{
+    let (n, w) = (series.len(), plot_rect.w as usize);
+    let bottom = plot_rect.y0 + plot_rect.h as i64;
+    let (above, below) = (
+        (style.thickness.saturating_sub(1) / 2) as i64,
+        (style.thickness / 2) as i64,
+    );
+
+    let mut prev: Option<(i64, i64)> = None;
+    for col in 0..w {
+        let samples = &series[col * n / w..(col + 1) * n / w];
+        let finite = || samples.iter().copied().filter(|val| val.is_finite());
+        let (Some(min), Some(max)) = (finite().reduce(f32::min), finite().reduce(f32::max)) else {
+            prev = None;
+            continue;
+        };
+
+        let (top, low) = (to_y(max), to_y(min));
+        let (y0, y1) = match prev {
+            Some((prev_top, _)) if prev_top > low => (top, prev_top),
+            Some((_, prev_low)) if prev_low < top => (prev_low, low),
+            _ => (top, low),
+        };
+        prev = Some((top, low));
+
+        let x = plot_rect.x0 + col as i64;
+        if let Some(fill_color) = style.fill_color {
+            let fill_rect = Rect::from_xyxy([x, top, x, bottom - 1]);
+            draw_rect(fb, &fill_rect, fill_color, true);
+        }
+        if style.thickness > 0 {
+            let band = Rect::from_xyxy([x, y0 - above, x, y1 + below]);
+            draw_rect(fb, &band, style.line_color, false);
+        }
+    }
+}
+
+// Evicts the least recently used graph once over the maximum
+struct GraphCache {
+    max_graphs: usize,
+    // Most recently used last
+    graphs: Vec<(ContentId, Arc<Framebuffer<OwnedPixels>>)>,
+}
+
+impl GraphCache {
+    fn new(max_graphs: usize) -> Self {
+        GraphCache {
+            max_graphs,
+            graphs: Vec::new(),
+        }
+    }
+
+    fn get(&mut self, id: ContentId) -> Option<Arc<Framebuffer<OwnedPixels>>> {
+        let i = self
+            .graphs
+            .iter()
+            .position(|(graph_id, _)| *graph_id == id)?;
+        let entry = self.graphs.remove(i);
+        let graph = entry.1.clone();
+        self.graphs.push(entry);
+        Some(graph)
+    }
+
+    fn insert(&mut self, id: ContentId, graph: Arc<Framebuffer<OwnedPixels>>) {
+        self.graphs.retain(|(graph_id, _)| *graph_id != id);
+        self.graphs.push((id, graph));
+        if self.graphs.len() > self.max_graphs {
+            self.graphs.remove(0);
+        }
+    }
+}
@@ -1,2 +1,9 @@
+pub mod accents;
+pub mod effects;
+pub mod gradient;
+pub mod graph;
+pub mod nine_patch;
 pub mod primitives;
 pub mod text;
+pub mod text_cache;
+pub mod ttf;
@@ -0,0 +1,458 @@
+use crate::{FbView, FbViewMut, Framebuffer, OwnedPixels, Rect, ScaleFilter};
+
+// Widths of the borders of a nine-patch image, the parts that are not stretched
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+#[repr(C)]
+pub struct Insets {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl Insets {
+    pub const fn uniform(size: u32) -> Self {
+        Insets {
+            left: size,
+            top: size,
+            right: size,
+            bottom: size,
+        }
+    }
+}
+
+// How the edges and the center are made to fit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub enum EdgeMode {
+    Stretch,
+    // Repeated and cut at the end, for patterned borders
+    Tile,
+}
+
+pub struct NinePatch {
+    fb: Framebuffer<OwnedPixels>,
+    insets: Insets,
+    pub edges: EdgeMode,
+}
+
+impl NinePatch {
+    // Insets are shrunk if needed so that the middle keeps at least one pixel
+    pub fn from_fb<F: FbView>(src: &F, insets: Insets) -> Self {
+        let (w, h) = src.shape();
+        let mut fb = Framebuffer::new_owned(w, h);
+        fb.copy_from_fb(src, (0, 0), false);
+
+        let (left, right) = fit_insets(insets.left, insets.right, w.saturating_sub(1));
+        let (top, bottom) = fit_insets(insets.top, insets.bottom, h.saturating_sub(1));
+
+        NinePatch {
+            fb,
+            insets: Insets {
+                left,
+                top,
+                right,
+                bottom,
+            },
+            edges: EdgeMode::Stretch,
+        }
+    }
+
+    pub fn with_edges(self, edges: EdgeMode) -> Self {
+        NinePatch { edges, ..self }
+    }
+
+    pub fn insets(&self) -> &Insets {
+        &self.insets
+    }
+
+    // What is left inside the borders once drawn in rect
+    pub fn content_rect(&self, rect: &Rect) -> Rect {
+        let Insets {
+            left,
+            top,
+            right,
+            bottom,
+        } = self.insets;
+        let (left, right) = fit_insets(left, right, rect.w);
+        let (top, bottom) = fit_insets(top, bottom, rect.h);
+        Rect {
+            x0: rect.x0 + left as i64,
+            y0: rect.y0 + top as i64,
+            w: rect.w - left - right,
+            h: rect.h - top - bottom,
+        }
+    }
+}
+
+// Corners are drawn as they are, unless the rect is too small for them, in which case
+// they are shrunk and share the rect in proportion to their sizes
+pub fn draw_nine_patch<F: FbViewMut>(dst: &mut F, np: &NinePatch, dst_rect: &Rect) {
+    let (src_w, src_h) = np.fb.shape();
+    if [src_w, src_h, dst_rect.w, dst_rect.h].contains(&0) {
+        return;
+    }
+
+    let Insets {
+        left,
+        top,
+        right,
+        bottom,
+    } = np.insets;
+
+    let src_cols = spans(0, src_w, left, right);
+    let src_rows = spans(0, src_h, top, bottom);
+
+    let (dst_left, dst_right) = fit_insets(left, right, dst_rect.w);
+    let (dst_top, dst_bottom) = fit_insets(top, bottom, dst_rect.h);
+    let dst_cols = spans(dst_rect.x0, dst_rect.w, dst_left, dst_right);
+    let dst_rows = spans(dst_rect.y0, dst_rect.h, dst_top, dst_bottom);
+
+    for (row, (src_y, dst_y)) in src_rows.iter().zip(dst_rows.iter()).enumerate() {
+        for (col, (src_x, dst_x)) in src_cols.iter().zip(dst_cols.iter()).enumerate() {
+            let src_rect = Rect {
+                x0: src_x.0,
+                y0: src_y.0,
+                w: src_x.1,
+                h: src_y.1,
+            };
+            let cell_rect = Rect {
+                x0: dst_x.0,
+                y0: dst_y.0,
+                w: dst_x.1,
+                h: dst_y.1,
+            };
+
+            // Edges can only be tiled along their length, shrunk ones are stretched
+            let tile = np.edges == EdgeMode::Tile
+                && match (row, col) {
+                    (1, 1) => true,
+                    (1, _) => src_rect.w == cell_rect.w,
+                    (_, 1) => src_rect.h == cell_rect.h,
+                    _ => false,
+                };
+
+            draw_cell(dst, &np.fb, &src_rect, &cell_rect, tile);
+        }
+    }
+}
+
+fn draw_cell<F: FbViewMut>(
+    dst: &mut F,
+    src: &Framebuffer<OwnedPixels>,
+    src_rect: &Rect,
+    dst_rect: &Rect,
+    tile: bool,
+) {
+    if [src_rect.w, src_rect.h, dst_rect.w, dst_rect.h].contains(&0) {
+        return;
+    }
+
+    let src_view = src.subregion(src_rect);
+
+    if src_rect.shape() == dst_rect.shape() {
+        dst.copy_from_fb(&src_view, (dst_rect.x0, dst_rect.y0), true);
+    } else if tile {
+        dst.push_clip(dst_rect);
+        for y in (0..dst_rect.h).step_by(src_rect.h as usize) {
+            for x in (0..dst_rect.w).step_by(src_rect.w as usize) {
+                let pos = (dst_rect.x0 + x as i64, dst_rect.y0 + y as i64);
+                dst.copy_from_fb(&src_view, pos, true);
+            }
+        }
+        dst.pop_clip();
+    } else {
+        // Nearest, so that neighbouring cells do not bleed into each other
+        let src_rect = src_view.shape_as_rect();
+        dst.copy_from_fb_scaled(&src_view, &src_rect, dst_rect, ScaleFilter::Nearest, true);
+    }
+}
+
+// Sizes of the two borders along an axis of the given length, shrunk proportionally when
+// they do not fit
+fn fit_insets(a: u32, b: u32, len: u32) -> (u32, u32) {
+    let total = a as u64 + b as u64;
+    if total <= len as u64 {
+        return (a, b);
+    }
+    let a = ((a as u64 * len as u64 + total / 2) / total) as u32;
+    (a, len - a)
+}
+
+// Start and length of the three parts of an axis
+fn spans(start: i64, len: u32, a: u32, b: u32) -> [(i64, u32); 3] {
+    let middle = len - a - b;
+    [
+        (start, a),
+        (start + a as i64, middle),
+        (start + (a + middle) as i64, b),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::noise;
+    use crate::Color;
+
+    const BG: Color = Color::rgb(1, 2, 3);
+
+    // Each of the 9 parts of a 6x6 image with 2-pixel insets in its own flat color
+    fn part_color(col: u32, row: u32) -> Color {
+        Color::rgb(col as u8 * 100, row as u8 * 100, 50)
+    }
+
+    fn flat_parts() -> NinePatch {
+        let mut fb = Framebuffer::new_owned(6, 6);
+        for y in 0..6 {
+            for x in 0..6 {
+                fb.set_pixel(x as i64, y as i64, part_color(x / 2, y / 2));
+            }
+        }
+        NinePatch::from_fb(&fb, Insets::uniform(2))
+    }
+
+    fn part_of(pos: u32, len: u32, (a, b): (u32, u32)) -> u32 {
+        match pos {
+            p if p < a => 0,
+            p if p < len - b => 1,
+            _ => 2,
+        }
+    }
+
+    // Every pixel of the rect in the color of the part covering it, nothing outside
+    fn check_layout(np: &NinePatch, rect: &Rect) {
+        let mut fb = Framebuffer::new_owned_filled(20, 20, BG);
+        draw_nine_patch(&mut fb, np, rect);
+
+        let insets = np.insets();
+        let cols = fit_insets(insets.left, insets.right, rect.w);
+        let rows = fit_insets(insets.top, insets.bottom, rect.h);
+        for y in 0..20 {
+            for x in 0..20 {
+                let (dx, dy) = (x - rect.x0, y - rect.y0);
+                let inside = (0..rect.w as i64).contains(&dx) && (0..rect.h as i64).contains(&dy);
+                let expected = match inside {
+                    true => part_color(
+                        part_of(dx as u32, rect.w, cols),
+                        part_of(dy as u32, rect.h, rows),
+                    ),
+                    false => BG,
+                };
+                let actual = fb.get_pixel(x, y).unwrap();
+                assert_eq!(actual, expected, "At ({}, {}) in {:?}", x, y, rect);
+            }
+        }
+    }
+
+    #[test]
+    fn every_small_size() {
+        let np = flat_parts();
+        for w in 0..=14 {
+            for h in 0..=14 {
+                check_layout(&np, &Rect { x0: 3, y0: 2, w, h });
+            }
+        }
+    }
+
+    #[test]
+    fn one_pixel_destination() {
+        let np = flat_parts();
+        check_layout(
+            &np,
+            &Rect {
+                x0: 5,
+                y0: 5,
+                w: 1,
+                h: 1,
+            },
+        );
+
+        // The top-left corner wins the single pixel
+        let mut fb = Framebuffer::new_owned_filled(1, 1, BG);
+        let rect = fb.shape_as_rect();
+        draw_nine_patch(&mut fb, &np, &rect);
+        assert_eq!(fb.get_pixel(0, 0), Some(part_color(0, 0)));
+    }
+
+    // The corners share the rect in proportion to their sizes, the middle is gone
+    #[test]
+    fn destination_smaller_than_insets() {
+        let mut fb = Framebuffer::new_owned(8, 8);
+        fb.fill(Color::WHITE);
+        let np = NinePatch::from_fb(
+            &fb,
+            Insets {
+                left: 3,
+                top: 1,
+                right: 1,
+                bottom: 3,
+            },
+        );
+        let rect = Rect {
+            x0: 0,
+            y0: 0,
+            w: 2,
+            h: 3,
+        };
+        let content = np.content_rect(&rect);
+        assert_eq!(
+            content,
+            Rect {
+                x0: 2,
+                y0: 1,
+                w: 0,
+                h: 0
+            }
+        );
+
+        check_layout(
+            &flat_parts(),
+            &Rect {
+                x0: 4,
+                y0: 4,
+                w: 3,
+                h: 2,
+            },
+        );
+    }
+
+    #[test]
+    fn clipped_and_off_screen() {
+        let np = flat_parts();
+        check_layout(
+            &np,
+            &Rect {
+                x0: -3,
+                y0: -4,
+                w: 9,
+                h: 9,
+            },
+        );
+        check_layout(
+            &np,
+            &Rect {
+                x0: 15,
+                y0: 12,
+                w: 30,
+                h: 30,
+            },
+        );
+        check_layout(
+            &np,
+            &Rect {
+                x0: -100,
+                y0: 5,
+                w: 90,
+                h: 3,
+            },
+        );
+        check_layout(
+            &np,
+            &Rect {
+                x0: 0,
+                y0: 0,
+                w: 100_000,
+                h: 100_000,
+            },
+        );
+    }
+
+    #[test]
+    fn pathological_sources() {
+        // Insets larger than the image leave it one pixel of middle
+        let np = NinePatch::from_fb(&noise(4, 3, 1), Insets::uniform(u32::MAX));
+        assert_eq!(
+            *np.insets(),
+            Insets {
+                left: 2,
+                top: 1,
+                right: 1,
+                bottom: 1,
+            }
+        );
+
+        // A single pixel is all middle
+        let np = NinePatch::from_fb(
+            &Framebuffer::new_owned_filled(1, 1, Color::RED),
+            Insets::uniform(3),
+        );
+        assert_eq!(*np.insets(), Insets::default());
+        let mut fb = Framebuffer::new_owned_filled(5, 5, BG);
+        draw_nine_patch(
+            &mut fb,
+            &np,
+            &Rect {
+                x0: 1,
+                y0: 1,
+                w: 3,
+                h: 3,
+            },
+        );
+        assert_eq!(fb.get_pixel(2, 2), Some(Color::RED));
+        assert_eq!(fb.get_pixel(0, 0), Some(BG));
+
+        // Nothing to draw
+        let np = NinePatch::from_fb(&Framebuffer::new_owned(0, 0), Insets::uniform(3));
+        draw_nine_patch(
+            &mut fb,
+            &np,
+            &Rect {
+                x0: 0,
+                y0: 0,
+                w: 5,
+                h: 5,
+            },
+        );
+        assert_eq!(fb.get_pixel(2, 2), Some(Color::RED));
+    }
+
+    #[test]
+    fn corners_are_copied_as_they_are() {
+        let mut src = noise(7, 7, 2);
+        for color in src.get_data_mut() {
+            color.0[3] = 255;
+        }
+        let np = NinePatch::from_fb(&src, Insets::uniform(3));
+        let mut fb = Framebuffer::new_owned_filled(15, 11, BG);
+        let rect = fb.shape_as_rect();
+        draw_nine_patch(&mut fb, &np, &rect);
+
+        for (sx, dx) in [(0, 0), (2, 2), (4, 12), (6, 14)] {
+            for (sy, dy) in [(0, 0), (2, 2), (4, 8), (6, 10)] {
+                assert_eq!(fb.get_pixel(dx, dy), src.get_pixel(sx, sy));
+            }
+        }
+    }
+
+    #[test]
+    fn tiled_edges_repeat_and_cut() {
+        // A middle column of 3 distinct pixels between 1-pixel insets
+        let mut src = Framebuffer::new_owned_filled(5, 3, Color::BLACK);
+        let middle = [Color::RED, Color::GREEN, Color::BLUE];
+        for (x, color) in middle.iter().enumerate() {
+            src.set_pixel(x as i64 + 1, 0, *color);
+        }
+        let np = NinePatch::from_fb(&src, Insets::uniform(1)).with_edges(EdgeMode::Tile);
+
+        let mut fb = Framebuffer::new_owned_filled(10, 3, BG);
+        let rect = fb.shape_as_rect();
+        draw_nine_patch(&mut fb, &np, &rect);
+        let top: Vec<Color> = (0..10).map(|x| fb.get_pixel(x, 0).unwrap()).collect();
+        assert_eq!(
+            top,
+            [
+                Color::BLACK,
+                Color::RED,
+                Color::GREEN,
+                Color::BLUE,
+                Color::RED,
+                Color::GREEN,
+                Color::BLUE,
+                Color::RED,
+                Color::GREEN,
+                Color::BLACK,
+            ]
+        );
+    }
+}
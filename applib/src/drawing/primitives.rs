@@ -1,8 +1,95 @@
 use crate::geometry::{Point2D, Quad2D, Triangle2D, Vec2D};
 use crate::{Color, FbViewMut, Rect};
+use alloc::vec;
+use alloc::vec::Vec;
 use core::f32::consts::PI;
 use num::Float;
 
+// How lines and outlines are drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrokeStyle<'a> {
+    pub thickness: u32,
+    // Lengths in pixels of the dashes and of the gaps between them, alternating and
+    // starting with a dash. Odd-length patterns are repeated once to make them even.
+    // Solid if None.
+    pub pattern: Option<&'a [u32]>,
+    // Where in the pattern the path starts. Increasing it every frame makes the dashes
+    // march backwards along the path.
+    pub phase: u32,
+}
+
+impl StrokeStyle<'static> {
+    pub const fn solid(thickness: u32) -> Self {
+        StrokeStyle {
+            thickness,
+            pattern: None,
+            phase: 0,
+        }
+    }
+}
+
+impl<'a> StrokeStyle<'a> {
+    pub const fn dashed(thickness: u32, pattern: &'a [u32], phase: u32) -> Self {
+        StrokeStyle {
+            thickness,
+            pattern: Some(pattern),
+            phase,
+        }
+    }
+
+    // The drawn parts between two distances along the path. They only depend on the
+    // distance from the start, so clipping does not move the dashes.
+    fn dashes(&self, start: f32, end: f32) -> Vec<(f32, f32)> {
+        let pattern = match self.pattern {
+            Some(pattern) if pattern.iter().any(|&len| len > 0) => pattern,
+            _ => {
+                return match start < end {
+                    true => vec![(start, end)],
+                    false => Vec::new(),
+                }
+            }
+        };
+
+        let n = match pattern.len() % 2 {
+            0 => pattern.len(),
+            _ => 2 * pattern.len(),
+        };
+        let period = pattern.iter().sum::<u32>() as f32 * (n / pattern.len()) as f32;
+
+        // Position in the pattern at the start
+        let pos_in_pattern = start + self.phase as f32;
+        let mut offset = pos_in_pattern - (pos_in_pattern / period).floor() * period;
+        let mut i = 0;
+        while offset >= pattern[i % pattern.len()] as f32 {
+            offset -= pattern[i % pattern.len()] as f32;
+            i = (i + 1) % n;
+        }
+
+        let mut dashes = Vec::new();
+        let mut pos = start;
+        while pos < end {
+            let part_end = f32::min(end, pos + pattern[i % pattern.len()] as f32 - offset);
+            if i % 2 == 0 && part_end > pos {
+                dashes.push((pos, part_end));
+            }
+            pos = part_end;
+            offset = 0.0;
+            i = (i + 1) % n;
+        }
+        dashes
+    }
+
+    fn is_drawn_at(&self, dist: f32) -> bool {
+        !self.dashes(dist, dist + 0.5).is_empty()
+    }
+}
+
+impl Default for StrokeStyle<'static> {
+    fn default() -> Self {
+        StrokeStyle::solid(1)
+    }
+}
+
 pub fn draw_triangle<F: FbViewMut>(fb: &mut F, tri: &Triangle2D<i64>, color: Color, blend: bool) {
     const WIREFRAME: bool = false;
     match WIREFRAME {
@@ -235,3 +322,1047 @@ pub fn draw_rect_outline<F: FbViewMut>(
     };
     draw_rect(fb, &right_rect, color, blend);
 }
+
+// The radius is clamped to half the smaller side, and the corners are antialiased.
+// Fully covered pixels are overwritten, those on the edge of the corners are blended.
+pub fn draw_rounded_rect<F: FbViewMut>(
+    fb: &mut F,
+    rect: &Rect,
+    radius: u32,
+    color: Color,
+    border: Option<(u32, Color)>,
+) {
+    if rect.w == 0 || rect.h == 0 {
+        return;
+    }
+
+    let r = u32::min(radius, u32::min(rect.w, rect.h) / 2);
+    let (t, border_color) = border.unwrap_or((0, color));
+
+    // In pixel center coordinates, the edges of the rect are half a pixel out
+    let [x0, y0, x1, y1] = rect.as_xyxy();
+    let center = ((x0 + x1) as f32 / 2.0, (y0 + y1) as f32 / 2.0);
+    let half = (rect.w as f32 / 2.0, rect.h as f32 / 2.0);
+    let inner_half = (half.0 - t as f32, half.1 - t as f32);
+    let inner_r = r.saturating_sub(t) as f32;
+    let has_inner = inner_half.0 > 0.0 && inner_half.1 > 0.0;
+
+    let coverage = |x: i64, y: i64, half: (f32, f32), r: f32| {
+        let dist = rounded_rect_dist((x as f32, y as f32), center, half, r);
+        (0.5 - dist).clamp(0.0, 1.0)
+    };
+
+    // Away from the edges, rows are solid
+    let edge_w = u32::max(r, t) as i64 + 1;
+
+    let (fb_w, fb_h) = fb.shape();
+    let (fb_x1, fb_y1) = (fb_w as i64 - 1, fb_h as i64 - 1);
+
+    for y in i64::max(0, y0)..=i64::min(fb_y1, y1) {
+        let (solid_x0, solid_x1) = match y - y0 < edge_w || y1 - y < edge_w {
+            true => (x1 + 1, x1),
+            false => (x0 + edge_w, x1 - edge_w),
+        };
+        fill_span(fb, solid_x0, solid_x1, y, color);
+
+        let edges = match solid_x0 <= solid_x1 {
+            true => [(x0, solid_x0 - 1), (solid_x1 + 1, x1)],
+            false => [(x0, x1), (0, -1)],
+        };
+        for (ex0, ex1) in edges {
+            for x in i64::max(0, ex0)..=i64::min(fb_x1, ex1) {
+                let outer = coverage(x, y, half, r as f32);
+                let inner = match has_inner {
+                    true => coverage(x, y, inner_half, inner_r),
+                    false => 0.0,
+                };
+                match (outer, inner) {
+                    (_, 1.0) => fb.set_pixel(x, y, color),
+                    (1.0, 0.0) => fb.set_pixel(x, y, border_color),
+                    _ => {
+                        blend_pixel(fb, x, y, border_color, outer - inner);
+                        blend_pixel(fb, x, y, color, inner);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Signed distance from p to the outline of a rounded rect, negative inside
+fn rounded_rect_dist(p: (f32, f32), center: (f32, f32), half: (f32, f32), r: f32) -> f32 {
+    let qx = (p.0 - center.0).abs() - (half.0 - r);
+    let qy = (p.1 - center.1).abs() - (half.1 - r);
+    let (ox, oy) = (f32::max(qx, 0.0), f32::max(qy, 0.0));
+    (ox * ox + oy * oy).sqrt() + f32::min(f32::max(qx, qy), 0.0) - r
+}
+
+// Scanline fill with the even-odd rule, so concave and self-intersecting polygons work.
+// Like triangles, the right and bottom edges are left out so that adjacent polygons
+// do not overlap.
+pub fn draw_polygon<F: FbViewMut>(fb: &mut F, points: &[Point2D<i64>], color: Color) {
+    if points.len() < 3 {
+        return;
+    }
+
+    let (_, fb_h) = fb.shape();
+    let y_min = points.iter().map(|p| p.y).min().unwrap_or(0);
+    let y_max = points.iter().map(|p| p.y).max().unwrap_or(0);
+
+    let mut crossings: Vec<f64> = Vec::with_capacity(points.len());
+
+    for y in i64::max(0, y_min)..i64::min(fb_h as i64, y_max) {
+        crossings.clear();
+        for (i, a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            // Each edge covers the rows from its top included to its bottom excluded
+            if (a.y <= y) != (b.y <= y) {
+                let x = a.x as f64 + (y - a.y) as f64 * (b.x - a.x) as f64 / (b.y - a.y) as f64;
+                crossings.push(x);
+            }
+        }
+        crossings.sort_unstable_by(f64::total_cmp);
+
+        for pair in crossings.chunks_exact(2) {
+            let (x0, x1) = (pair[0].ceil() as i64, pair[1].ceil() as i64 - 1);
+            fill_span(fb, x0, x1, y, color);
+        }
+    }
+}
+
+//
+// Lines, circles and ellipses. The aliased versions overwrite the pixels they cover,
+// the antialiased ones blend the color into them according to coverage.
+
+// Dashes have the round caps of thick lines
+pub fn draw_line<F: FbViewMut>(
+    fb: &mut F,
+    p0: Point2D<i64>,
+    p1: Point2D<i64>,
+    color: Color,
+    style: &StrokeStyle,
+    antialiased: bool,
+) {
+    if style.pattern.is_none() {
+        return draw_segment(fb, p0, p1, color, style.thickness, antialiased);
+    }
+
+    let (a, b) = ((p0.x as f32, p0.y as f32), (p1.x as f32, p1.y as f32));
+    let len = distance(a, b);
+    for (d0, d1) in style.dashes(0.0, len) {
+        let (q0, q1) = dash_ends(a, b, len, d0, d1);
+        draw_segment(
+            fb,
+            round_point(q0),
+            round_point(q1),
+            color,
+            style.thickness,
+            antialiased,
+        );
+    }
+}
+
+fn draw_segment<F: FbViewMut>(
+    fb: &mut F,
+    p0: Point2D<i64>,
+    p1: Point2D<i64>,
+    color: Color,
+    thickness: u32,
+    antialiased: bool,
+) {
+    match (thickness, antialiased) {
+        (0, _) => (),
+        (1, false) => draw_thin_line(fb, p0, p1, color),
+        _ => draw_capsule(fb, p0, p1, color, thickness, antialiased),
+    }
+}
+
+// Joins between segments are mitered, or beveled where the angle is too sharp for the
+// miter to stay short. Thick aliased lines have flat ends, antialiased ones keep the round
+// caps of draw_line(), and so round joins. The dash pattern carries over from one segment
+// to the next.
+pub fn draw_polyline<F: FbViewMut>(
+    fb: &mut F,
+    points: &[Point2D<i64>],
+    closed: bool,
+    color: Color,
+    style: &StrokeStyle,
+    antialiased: bool,
+) {
+    if style.thickness == 0 || points.is_empty() {
+        return;
+    }
+
+    let to_f32 = |p: &Point2D<i64>| (p.x as f32, p.y as f32);
+    let nb_segments = match closed && points.len() > 2 {
+        true => points.len(),
+        false => points.len() - 1,
+    };
+    let segment = |i: usize| (to_f32(&points[i]), to_f32(&points[(i + 1) % points.len()]));
+    let flat = style.thickness > 1 && !antialiased;
+
+    if nb_segments == 0 {
+        let p = points[0];
+        return draw_segment(fb, p, p, color, style.thickness, antialiased);
+    }
+
+    let mut dist = 0.0;
+    for i in 0..nb_segments {
+        let (a, b) = segment(i);
+        let len = distance(a, b);
+
+        for (d0, d1) in style.dashes(dist, dist + len) {
+            let (q0, q1) = dash_ends(a, b, len, d0 - dist, d1 - dist);
+            match flat {
+                true => fill_band(fb, q0, q1, style.thickness, color),
+                false => {
+                    let (q0, q1) = (round_point(q0), round_point(q1));
+                    draw_segment(fb, q0, q1, color, style.thickness, antialiased);
+                }
+            }
+        }
+        dist += len;
+
+        // The join at the end of the segment, where the pattern is drawn
+        let has_next = i + 1 < nb_segments || closed;
+        if flat && has_next && style.is_drawn_at(dist) {
+            let (_, c) = segment((i + 1) % nb_segments);
+            fill_join(fb, a, b, c, style.thickness, color);
+        }
+    }
+}
+
+pub fn draw_polygon_outline<F: FbViewMut>(
+    fb: &mut F,
+    points: &[Point2D<i64>],
+    color: Color,
+    style: &StrokeStyle,
+    antialiased: bool,
+) {
+    draw_polyline(fb, points, true, color, style, antialiased);
+}
+
+// Inside the rect like draw_rect_outline(), the pattern goes clockwise from the top-left
+// corner
+pub fn draw_rect_outline_styled<F: FbViewMut>(
+    fb: &mut F,
+    rect: &Rect,
+    color: Color,
+    blend: bool,
+    style: &StrokeStyle,
+) {
+    if style.pattern.is_none() {
+        return draw_rect_outline(fb, rect, color, blend, style.thickness);
+    }
+
+    let Rect { x0, y0, w, h } = *rect;
+    let t = u32::min(style.thickness, u32::min(w, h)) as i64;
+    let [_, _, x1, y1] = rect.as_xyxy();
+
+    // Top, right, bottom and left, each starting where the previous one ends
+    let mut start = 0;
+    for side in 0..4 {
+        let len = match side % 2 {
+            0 => w as i64,
+            _ => h as i64,
+        };
+        for (d0, d1) in style.dashes(start as f32, (start + len) as f32) {
+            let (d0, d1) = (d0.round() as i64 - start, d1.round() as i64 - start);
+            if d1 <= d0 {
+                continue;
+            }
+            let dash_rect = match side {
+                0 => [x0 + d0, y0, x0 + d1 - 1, y0 + t - 1],
+                1 => [x1 - t + 1, y0 + d0, x1, y0 + d1 - 1],
+                2 => [x1 - d1 + 1, y1 - t + 1, x1 - d0, y1],
+                _ => [x0, y1 - d1 + 1, x0 + t - 1, y1 - d0],
+            };
+            draw_rect(fb, &Rect::from_xyxy(dash_rect), color, blend);
+        }
+        start += len;
+    }
+}
+
+// Beyond that ratio of the miter length to the half thickness, joins are beveled.
+// 2 is about 60 degrees.
+const MITER_LIMIT: f32 = 2.0;
+
+// Where a dash from d0 to d1 along the segment ab starts and ends, the end included
+fn dash_ends(a: (f32, f32), b: (f32, f32), len: f32, d0: f32, d1: f32) -> ((f32, f32), (f32, f32)) {
+    if len < f32::EPSILON {
+        return (a, a);
+    }
+    let at = |d: f32| {
+        let t = (d / len).clamp(0.0, 1.0);
+        (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1))
+    };
+    (at(d0), at(f32::max(d0, d1 - 1.0)))
+}
+
+// A segment with flat ends, the same thickness as a capsule
+fn fill_band<F: FbViewMut>(fb: &mut F, a: (f32, f32), b: (f32, f32), thickness: u32, color: Color) {
+    let r = thickness as f32 / 2.0;
+    let len = distance(a, b);
+    // Dashes as short as a point are squares
+    let (dx, dy) = match len < f32::EPSILON {
+        true => (1.0, 0.0),
+        false => ((b.0 - a.0) / len, (b.1 - a.1) / len),
+    };
+    let (nx, ny) = (-dy * r, dx * r);
+    // The ends are extended by half a pixel, so that the pixels of the points are covered
+    let (ex, ey) = (dx * 0.5, dy * 0.5);
+    let (a, b) = ((a.0 - ex, a.1 - ey), (b.0 + ex, b.1 + ey));
+    fill_convex(
+        fb,
+        &[
+            (a.0 + nx, a.1 + ny),
+            (b.0 + nx, b.1 + ny),
+            (b.0 - nx, b.1 - ny),
+            (a.0 - nx, a.1 - ny),
+        ],
+        color,
+    );
+}
+
+// The wedge on the outer side of the corner at b, between the segments ab and bc
+fn fill_join<F: FbViewMut>(
+    fb: &mut F,
+    a: (f32, f32),
+    b: (f32, f32),
+    c: (f32, f32),
+    thickness: u32,
+    color: Color,
+) {
+    let (len_1, len_2) = (distance(a, b), distance(b, c));
+    if len_1 < f32::EPSILON || len_2 < f32::EPSILON {
+        return;
+    }
+    let d1 = ((b.0 - a.0) / len_1, (b.1 - a.1) / len_1);
+    let d2 = ((c.0 - b.0) / len_2, (c.1 - b.1) / len_2);
+    let cross = d1.0 * d2.1 - d1.1 * d2.0;
+    if cross.abs() < 1e-3 {
+        return;
+    }
+
+    // Normals on the outer side of the turn
+    let side = -cross.signum();
+    let r = thickness as f32 / 2.0;
+    let n1 = (-d1.1 * side, d1.0 * side);
+    let n2 = (-d2.1 * side, d2.0 * side);
+    let p1 = (b.0 + n1.0 * r, b.1 + n1.1 * r);
+    let p2 = (b.0 + n2.0 * r, b.1 + n2.1 * r);
+
+    // The miter tip is along the bisector of the normals, 1 / cos(angle / 2) further
+    let (mx, my) = (n1.0 + n2.0, n1.1 + n2.1);
+    let m_len = (mx * mx + my * my).sqrt();
+    let cos_half = m_len / 2.0;
+    match cos_half > f32::EPSILON && 1.0 / cos_half <= MITER_LIMIT {
+        true => {
+            let k = r / cos_half / m_len;
+            let tip = (b.0 + mx * k, b.1 + my * k);
+            fill_convex(fb, &[b, p1, tip, p2], color);
+        }
+        false => fill_convex(fb, &[b, p1, p2], color),
+    }
+}
+
+// Same rule as draw_polygon(): pixels are covered if their center is inside, the right
+// and bottom edges are left out
+fn fill_convex<F: FbViewMut>(fb: &mut F, points: &[(f32, f32)], color: Color) {
+    let (_, fb_h) = fb.shape();
+    let y_min = points.iter().map(|p| p.1).fold(f32::INFINITY, f32::min);
+    let y_max = points.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+
+    for y in i64::max(0, y_min.ceil() as i64)..i64::min(fb_h as i64, y_max.ceil() as i64) {
+        let yf = y as f32;
+        let mut x_range: Option<(f32, f32)> = None;
+        for (i, a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            if (a.1 <= yf) != (b.1 <= yf) {
+                let x = a.0 + (yf - a.1) * (b.0 - a.0) / (b.1 - a.1);
+                x_range = Some(match x_range {
+                    None => (x, x),
+                    Some((x0, x1)) => (f32::min(x0, x), f32::max(x1, x)),
+                });
+            }
+        }
+        if let Some((x0, x1)) = x_range {
+            fill_span(fb, x0.ceil() as i64, x1.ceil() as i64 - 1, y, color);
+        }
+    }
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    (dx * dx + dy * dy).sqrt()
+}
+
+fn round_point(p: (f32, f32)) -> Point2D<i64> {
+    Point2D {
+        x: p.0.round() as i64,
+        y: p.1.round() as i64,
+    }
+}
+
+// Bresenham, computed per column (or row) of the major axis so that only the part
+// of the line inside the framebuffer is walked
+fn draw_thin_line<F: FbViewMut>(fb: &mut F, p0: Point2D<i64>, p1: Point2D<i64>, color: Color) {
+    let (fb_w, fb_h) = fb.shape();
+    let x_major = (p1.x - p0.x).abs() >= (p1.y - p0.y).abs();
+
+    // (major, minor) coordinates, ordered along the major axis
+    let (a, b, major_len, minor_len) = match x_major {
+        true => ((p0.x, p0.y), (p1.x, p1.y), fb_w as i64, fb_h as i64),
+        false => ((p0.y, p0.x), (p1.y, p1.x), fb_h as i64, fb_w as i64),
+    };
+    let (a, b) = match a.0 <= b.0 {
+        true => (a, b),
+        false => (b, a),
+    };
+    let (d_major, d_minor) = (b.0 - a.0, b.1 - a.1);
+
+    for major in i64::max(0, a.0)..=i64::min(major_len - 1, b.0) {
+        let minor = match d_major {
+            0 => a.1,
+            _ => a.1 + div_round(d_minor as i128 * (major - a.0) as i128, d_major as i128),
+        };
+        if minor < 0 || minor >= minor_len {
+            continue;
+        }
+        match x_major {
+            true => fb.set_pixel(major, minor, color),
+            false => fb.set_pixel(minor, major, color),
+        }
+    }
+}
+
+// Rounds half up, like the error term of Bresenham
+fn div_round(n: i128, d: i128) -> i64 {
+    (2 * n + d).div_euclid(2 * d) as i64
+}
+
+// Thick lines have round caps, they cover the pixels whose centers are within
+// thickness / 2 of the segment
+fn draw_capsule<F: FbViewMut>(
+    fb: &mut F,
+    p0: Point2D<i64>,
+    p1: Point2D<i64>,
+    color: Color,
+    thickness: u32,
+    antialiased: bool,
+) {
+    // Even thicknesses have no middle row of pixels, the segment is moved by half a
+    // pixel so that they are not drawn one pixel thicker
+    let shift = match thickness % 2 {
+        0 => 0.5,
+        _ => 0.0,
+    };
+    let a = (p0.x as f32 + shift, p0.y as f32 + shift);
+    let b = (p1.x as f32 + shift, p1.y as f32 + shift);
+
+    let r = thickness as f32 / 2.0;
+    // Antialiased pixels are partly covered up to half a pixel further
+    let reach = match antialiased {
+        true => r + 0.5,
+        false => r,
+    };
+
+    let (fb_w, fb_h) = fb.shape();
+    let y_min = i64::max(0, (f32::min(a.1, b.1) - reach).floor() as i64);
+    let y_max = i64::min(fb_h as i64 - 1, (f32::max(a.1, b.1) + reach).ceil() as i64);
+
+    for y in y_min..=y_max {
+        let Some((x_min, x_max)) = capsule_row(a, b, reach, y as f32) else {
+            continue;
+        };
+        let x_min = i64::max(0, x_min.ceil() as i64);
+        let x_max = i64::min(fb_w as i64 - 1, x_max.floor() as i64);
+
+        match antialiased {
+            false => fill_span(fb, x_min, x_max, y, color),
+            true => {
+                for x in x_min..=x_max {
+                    let dist = dist_to_segment((x as f32, y as f32), a, b);
+                    blend_pixel(fb, x, y, color, r + 0.5 - dist);
+                }
+            }
+        }
+    }
+}
+
+// Horizontal extent at row y of the points within `reach` of the segment. The shape is
+// convex, so it is the union of the extents of the end discs and of the band between them.
+fn capsule_row(a: (f32, f32), b: (f32, f32), reach: f32, y: f32) -> Option<(f32, f32)> {
+    let disc = |(xc, yc): (f32, f32)| {
+        let h2 = reach * reach - (y - yc) * (y - yc);
+        (h2 >= 0.0).then(|| (xc - h2.sqrt(), xc + h2.sqrt()))
+    };
+
+    let band = || {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let len = (dx * dx + dy * dy).sqrt();
+        if len < f32::EPSILON {
+            return None;
+        }
+        // Along the segment, 0 <= (p - a).d <= len², and across it, |(p - a).n| <= reach * len
+        let (along_0, along_1) = linear_range(dx, (y - a.1) * dy - a.0 * dx, 0.0, len * len)?;
+        let (across_0, across_1) =
+            linear_range(-dy, (y - a.1) * dx + a.0 * dy, -reach * len, reach * len)?;
+        let (x0, x1) = (f32::max(along_0, across_0), f32::min(along_1, across_1));
+        (x0 <= x1).then_some((x0, x1))
+    };
+
+    [disc(a), disc(b), band()]
+        .into_iter()
+        .flatten()
+        .reduce(|(x0, x1), (x0b, x1b)| (f32::min(x0, x0b), f32::max(x1, x1b)))
+}
+
+// Values of x where lo <= k * x + c <= hi
+fn linear_range(k: f32, c: f32, lo: f32, hi: f32) -> Option<(f32, f32)> {
+    if k.abs() < f32::EPSILON {
+        return (lo <= c && c <= hi).then_some((f32::NEG_INFINITY, f32::INFINITY));
+    }
+    let (x0, x1) = ((lo - c) / k, (hi - c) / k);
+    Some((f32::min(x0, x1), f32::max(x0, x1)))
+}
+
+fn dist_to_segment(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    let t = match len2 > 0.0 {
+        true => (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2).clamp(0.0, 1.0),
+        false => 0.0,
+    };
+    let (vx, vy) = (a.0 + t * dx - p.0, a.1 + t * dy - p.1);
+    (vx * vx + vy * vy).sqrt()
+}
+
+pub fn draw_circle<F: FbViewMut>(
+    fb: &mut F,
+    center: Point2D<i64>,
+    radius: u32,
+    color: Color,
+    filled: bool,
+    antialiased: bool,
+) {
+    draw_ellipse(fb, center, radius, radius, color, filled, antialiased);
+}
+
+// Outlines are 1 pixel wide
+pub fn draw_ellipse<F: FbViewMut>(
+    fb: &mut F,
+    center: Point2D<i64>,
+    rx: u32,
+    ry: u32,
+    color: Color,
+    filled: bool,
+    antialiased: bool,
+) {
+    // Flat ellipses are lines
+    if rx == 0 || ry == 0 {
+        let (rx, ry) = (rx as i64, ry as i64);
+        let p0 = Point2D {
+            x: center.x - rx,
+            y: center.y - ry,
+        };
+        let p1 = Point2D {
+            x: center.x + rx,
+            y: center.y + ry,
+        };
+        return draw_line(fb, p0, p1, color, &StrokeStyle::solid(1), antialiased);
+    }
+
+    match antialiased {
+        false => draw_aliased_ellipse(fb, center, rx, ry, color, filled),
+        true => draw_antialiased_ellipse(fb, center, rx, ry, color, filled),
+    }
+}
+
+// Pixels whose centers are inside the ellipse of radii rx + 0.5 and ry + 0.5, drawn row
+// by row. Each row of the outline reaches in to the span of its neighbours, so that
+// it has no gaps.
+fn draw_aliased_ellipse<F: FbViewMut>(
+    fb: &mut F,
+    center: Point2D<i64>,
+    rx: u32,
+    ry: u32,
+    color: Color,
+    filled: bool,
+) {
+    let (rx_f, ry_f) = (rx as f32 + 0.5, ry as f32 + 0.5);
+    let half_w = |dy: i64| -> i64 {
+        let t = dy as f32 / ry_f;
+        match t.abs() < 1.0 {
+            true => (rx_f * (1.0 - t * t).sqrt()).floor() as i64,
+            false => -1,
+        }
+    };
+
+    let (_, fb_h) = fb.shape();
+    let Point2D { x: xc, y: yc } = center;
+    let ry = ry as i64;
+
+    for y in i64::max(0, yc - ry)..=i64::min(fb_h as i64 - 1, yc + ry) {
+        let dy = y - yc;
+        let hw = half_w(dy);
+
+        let inner = match filled {
+            true => 0,
+            false => i64::min(hw, i64::min(half_w(dy - 1), half_w(dy + 1)) + 1),
+        };
+        match inner {
+            0 => fill_span(fb, xc - hw, xc + hw, y, color),
+            _ => {
+                fill_span(fb, xc - hw, xc - inner, y, color);
+                fill_span(fb, xc + inner, xc + hw, y, color);
+            }
+        }
+    }
+}
+
+// Coverage from a first-order approximation of the distance to the outline, exact on
+// the axes of circles and close enough within a pixel of the outline of any ellipse
+fn draw_antialiased_ellipse<F: FbViewMut>(
+    fb: &mut F,
+    center: Point2D<i64>,
+    rx: u32,
+    ry: u32,
+    color: Color,
+    filled: bool,
+) {
+    let (rx, ry) = (rx as f32, ry as f32);
+
+    let signed_dist = |x: f32, y: f32| {
+        let f = (x / rx) * (x / rx) + (y / ry) * (y / ry) - 1.0;
+        let grad = 2.0 * (x * x / (rx * rx * rx * rx) + y * y / (ry * ry * ry * ry)).sqrt();
+        match grad > f32::EPSILON {
+            true => f / grad,
+            false => -f32::min(rx, ry),
+        }
+    };
+    let coverage = |dist: f32| match filled {
+        true => 0.5 - dist,
+        false => 1.0 - dist.abs(),
+    };
+
+    // Half width of the row in an ellipse of the given radii, if the row crosses it
+    let half_w = |rx: f32, ry: f32, dy: f32| {
+        let t = dy / ry;
+        (rx > 0.0 && ry > 0.0 && t.abs() < 1.0).then(|| rx * (1.0 - t * t).sqrt())
+    };
+
+    let (fb_w, fb_h) = fb.shape();
+    let Point2D { x: xc, y: yc } = center;
+    let reach = ry as i64 + 1;
+
+    for y in i64::max(0, yc - reach)..=i64::min(fb_h as i64 - 1, yc + reach) {
+        let dy = (y - yc) as f32;
+        let Some(hw_out) = half_w(rx + 1.0, ry + 1.0, dy) else {
+            continue;
+        };
+        let hw_out = hw_out.ceil() as i64;
+
+        // Well inside the outline, pixels are either fully covered or not at all
+        let hw_in = half_w(rx - 2.0, ry - 2.0, dy).map_or(-1, |hw| hw.floor() as i64);
+        if filled && hw_in >= 0 {
+            for x in i64::max(0, xc - hw_in)..=i64::min(fb_w as i64 - 1, xc + hw_in) {
+                blend_pixel(fb, x, y, color, 1.0);
+            }
+        }
+
+        let edges = match hw_in {
+            -1 => [(xc - hw_out, xc + hw_out), (0, -1)],
+            _ => [(xc - hw_out, xc - hw_in - 1), (xc + hw_in + 1, xc + hw_out)],
+        };
+        for (x0, x1) in edges {
+            for x in i64::max(0, x0)..=i64::min(fb_w as i64 - 1, x1) {
+                let dist = signed_dist((x - xc) as f32, dy);
+                blend_pixel(fb, x, y, color, coverage(dist));
+            }
+        }
+    }
+}
+
+// From x0 to x1 included, clipped to the framebuffer
+fn fill_span<F: FbViewMut>(fb: &mut F, x0: i64, x1: i64, y: i64, color: Color) {
+    let (fb_w, fb_h) = fb.shape();
+    let (x0, x1) = (i64::max(0, x0), i64::min(fb_w as i64 - 1, x1));
+    if y >= 0 && y < fb_h as i64 && x0 <= x1 {
+        fb.fill_line(x0, (x1 - x0 + 1) as u32, y, color, false);
+    }
+}
+
+// Coverage is clamped to 0..1
+fn blend_pixel<F: FbViewMut>(fb: &mut F, x: i64, y: i64, color: Color, coverage: f32) {
+    let coverage = coverage.clamp(0.0, 1.0);
+    if coverage == 0.0 {
+        return;
+    }
+    if let Some(curr_color) = fb.get_pixel(x, y) {
+        let (r, g, b, a) = color.as_rgba();
+        let a = (a as f32 * coverage).round() as u8;
+        fb.set_pixel(x, y, composite_over(Color::rgba(r, g, b, a), curr_color));
+    }
+}
+
+// Unlike blend_colors(), also right over transparent pixels, like those of the
+// off-screen framebuffers widgets are rendered in
+fn composite_over(src: Color, dst: Color) -> Color {
+    let (rs, gs, bs, a_src) = src.as_rgba();
+    let (rd, gd, bd, a_dst) = dst.as_rgba();
+    let (a_src, a_dst) = (a_src as u32, a_dst as u32);
+
+    // Scaled by 255
+    let a_out = a_src * 255 + a_dst * (255 - a_src);
+    if a_out == 0 {
+        return dst;
+    }
+    let channel =
+        |s: u8, d: u8| ((s as u32 * a_src * 255 + d as u32 * a_dst * (255 - a_src)) / a_out) as u8;
+
+    Color::rgba(
+        channel(rs, rd),
+        channel(gs, gd),
+        channel(bs, bd),
+        (a_out / 255) as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FbView, Framebuffer, OwnedPixels};
+    use alloc::string::String;
+
+    fn pt(x: i64, y: i64) -> Point2D<i64> {
+        Point2D { x, y }
+    }
+
+    // White shapes drawn on a transparent framebuffer, so that alpha is the coverage
+    fn render(
+        w: u32,
+        h: u32,
+        draw: impl FnOnce(&mut Framebuffer<OwnedPixels>),
+    ) -> Framebuffer<OwnedPixels> {
+        let mut fb = Framebuffer::new_owned(w, h);
+        draw(&mut fb);
+        fb
+    }
+
+    // One character per pixel, '#' where covered
+    fn mask(fb: &Framebuffer<OwnedPixels>) -> Vec<String> {
+        let (w, h) = fb.shape();
+        (0..h as i64)
+            .map(|y| {
+                (0..w as i64)
+                    .map(|x| match fb.get_pixel(x, y).unwrap() {
+                        Color::ZERO => '.',
+                        Color::WHITE => '#',
+                        c => panic!("Blended pixel {:?} at ({}, {})", c, x, y),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Alpha of each pixel in hexadecimal
+    fn alphas(fb: &Framebuffer<OwnedPixels>) -> Vec<String> {
+        let (w, h) = fb.shape();
+        (0..h as i64)
+            .map(|y| {
+                let row: Vec<String> = (0..w as i64)
+                    .map(|x| format!("{:02x}", fb.get_pixel(x, y).unwrap().as_rgba().3))
+                    .collect();
+                row.join(" ")
+            })
+            .collect()
+    }
+
+    fn line(
+        p0: Point2D<i64>,
+        p1: Point2D<i64>,
+        thickness: u32,
+        aa: bool,
+    ) -> impl FnOnce(&mut Framebuffer<OwnedPixels>) {
+        move |fb| draw_line(fb, p0, p1, Color::WHITE, &StrokeStyle::solid(thickness), aa)
+    }
+
+    #[test]
+    fn aliased_thin_lines() {
+        // Ties round towards the end point
+        let fb = render(7, 4, line(pt(0, 0), pt(6, 3), 1, false));
+        assert_eq!(mask(&fb), ["#......", ".##....", "...##..", ".....##"]);
+
+        // Same pixels whichever end the line starts from
+        let reversed = render(7, 4, line(pt(6, 3), pt(0, 0), 1, false));
+        assert_eq!(mask(&reversed), mask(&fb));
+
+        let fb = render(4, 6, line(pt(3, 0), pt(0, 5), 1, false));
+        assert_eq!(mask(&fb), ["...#", "..#.", "..#.", ".#..", ".#..", "#..."]);
+
+        let fb = render(4, 3, line(pt(2, 1), pt(2, 1), 1, false));
+        assert_eq!(mask(&fb), ["....", "..#.", "...."]);
+    }
+
+    #[test]
+    fn aliased_thin_lines_are_clipped() {
+        // The visible part is where the unclipped line would be
+        let fb = render(6, 4, line(pt(-3, -1), pt(10, 5), 1, false));
+        assert_eq!(mask(&fb), ["#.....", ".##...", "...##.", ".....#"]);
+
+        let fb = render(5, 3, line(pt(-100, 1), pt(100, 1), 1, false));
+        assert_eq!(mask(&fb), [".....", "#####", "....."]);
+
+        for (p0, p1) in [
+            (pt(-5, -5), pt(-1, 10)),
+            (pt(5, 0), pt(9, 2)),
+            (pt(-3, 4), pt(10, 4)),
+            (pt(-10, 0), pt(0, -10)),
+        ] {
+            let fb = render(5, 3, line(p0, p1, 1, false));
+            assert!(
+                mask(&fb).iter().all(|row| row == "....."),
+                "{:?} {:?}",
+                p0,
+                p1
+            );
+        }
+    }
+
+    #[test]
+    fn aliased_thick_lines_are_capsules() {
+        let fb = render(9, 7, line(pt(2, 3), pt(6, 3), 3, false));
+        #[rustfmt::skip]
+        assert_eq!(mask(&fb), [
+            ".........",
+            ".........",
+            ".#######.",
+            ".#######.",
+            ".#######.",
+            ".........",
+            ".........",
+        ]);
+
+        // Even thicknesses are shifted by half a pixel instead of drawn one pixel thicker
+        let fb = render(8, 6, line(pt(2, 2), pt(5, 2), 2, false));
+        #[rustfmt::skip]
+        assert_eq!(mask(&fb), [
+            "........",
+            "........",
+            "..#####.",
+            "..#####.",
+            "........",
+            "........",
+        ]);
+
+        let fb = render(8, 8, line(pt(2, 2), pt(5, 5), 3, false));
+        #[rustfmt::skip]
+        assert_eq!(mask(&fb), [
+            "........",
+            ".###....",
+            ".####...",
+            ".#####..",
+            "..#####.",
+            "...####.",
+            "....###.",
+            "........",
+        ]);
+    }
+
+    #[test]
+    fn aliased_circles_and_ellipses() {
+        let fb = render(9, 9, |fb| {
+            draw_circle(fb, pt(4, 4), 3, Color::WHITE, false, false)
+        });
+        #[rustfmt::skip]
+        assert_eq!(mask(&fb), [
+            ".........",
+            "...###...",
+            "..#...#..",
+            ".#.....#.",
+            ".#.....#.",
+            ".#.....#.",
+            "..#...#..",
+            "...###...",
+            ".........",
+        ]);
+
+        let fb = render(7, 7, |fb| {
+            draw_circle(fb, pt(3, 3), 2, Color::WHITE, true, false)
+        });
+        #[rustfmt::skip]
+        assert_eq!(mask(&fb), [
+            ".......",
+            "..###..",
+            ".#####.",
+            ".#####.",
+            ".#####.",
+            "..###..",
+            ".......",
+        ]);
+
+        // The outline has no gaps where it is steep
+        let fb = render(11, 7, |fb| {
+            draw_ellipse(fb, pt(5, 3), 4, 2, Color::WHITE, false, false)
+        });
+        #[rustfmt::skip]
+        assert_eq!(mask(&fb), [
+            "...........",
+            "...#####...",
+            ".##.....##.",
+            ".#.......#.",
+            ".##.....##.",
+            "...#####...",
+            "...........",
+        ]);
+
+        // Flat ellipses are lines
+        let fb = render(7, 3, |fb| {
+            draw_ellipse(fb, pt(3, 1), 2, 0, Color::WHITE, true, false)
+        });
+        assert_eq!(mask(&fb), [".......", ".#####.", "......."]);
+    }
+
+    #[test]
+    fn aliased_circles_are_clipped() {
+        let fb = render(5, 4, |fb| {
+            draw_circle(fb, pt(0, 0), 3, Color::WHITE, false, false)
+        });
+        assert_eq!(mask(&fb), ["...#.", "...#.", "..#..", "##..."]);
+
+        for center in [pt(-4, 2), pt(2, -4), pt(8, 2), pt(2, 7), pt(-100, -100)] {
+            let fb = render(5, 4, |fb| {
+                draw_circle(fb, center, 3, Color::WHITE, true, false)
+            });
+            assert!(mask(&fb).iter().all(|row| row == "....."), "{:?}", center);
+        }
+    }
+
+    #[test]
+    fn antialiased_lines() {
+        // A 1-pixel line along the pixel centers covers exactly one row
+        let fb = render(7, 3, line(pt(1, 1), pt(5, 1), 1, true));
+        #[rustfmt::skip]
+        assert_eq!(alphas(&fb), [
+            "00 00 00 00 00 00 00",
+            "00 ff ff ff ff ff 00",
+            "00 00 00 00 00 00 00",
+        ]);
+
+        // 1 - 1/√2 of the pixels next to a diagonal
+        let fb = render(6, 6, line(pt(1, 1), pt(4, 4), 1, true));
+        #[rustfmt::skip]
+        assert_eq!(alphas(&fb), [
+            "00 00 00 00 00 00",
+            "00 ff 4b 00 00 00",
+            "00 4b ff 4b 00 00",
+            "00 00 4b ff 4b 00",
+            "00 00 00 4b ff 00",
+            "00 00 00 00 00 00",
+        ]);
+
+        // The round caps cover 1.5 - √0.5 of their corner pixels
+        let fb = render(8, 6, line(pt(2, 2), pt(5, 2), 2, true));
+        #[rustfmt::skip]
+        assert_eq!(alphas(&fb), [
+            "00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00",
+            "00 00 ca ff ff ff ca 00",
+            "00 00 ca ff ff ff ca 00",
+            "00 00 00 00 00 00 00 00",
+            "00 00 00 00 00 00 00 00",
+        ]);
+    }
+
+    #[test]
+    fn antialiased_circles() {
+        // Half covered where the outline goes through the pixel centers
+        let fb = render(7, 7, |fb| {
+            draw_circle(fb, pt(3, 3), 2, Color::WHITE, true, true)
+        });
+        #[rustfmt::skip]
+        assert_eq!(alphas(&fb), [
+            "00 00 00 00 00 00 00",
+            "00 00 46 80 46 00 00",
+            "00 46 ff ff ff 46 00",
+            "00 80 ff ff ff 80 00",
+            "00 46 ff ff ff 46 00",
+            "00 00 46 80 46 00 00",
+            "00 00 00 00 00 00 00",
+        ]);
+
+        let fb = render(9, 9, |fb| {
+            draw_circle(fb, pt(4, 4), 3, Color::WHITE, false, true)
+        });
+        #[rustfmt::skip]
+        assert_eq!(alphas(&fb), [
+            "00 00 00 00 00 00 00 00 00",
+            "00 00 72 d7 ff d7 72 00 00",
+            "00 72 d2 1b 00 1b d2 72 00",
+            "08 d7 1b 00 00 00 1b d7 08",
+            "20 ff 00 00 00 00 00 ff 20",
+            "08 d7 1b 00 00 00 1b d7 08",
+            "00 72 d2 1b 00 1b d2 72 00",
+            "00 00 72 d7 ff d7 72 00 00",
+            "00 00 00 00 00 00 00 00 00",
+        ]);
+
+        let fb = render(4, 4, |fb| {
+            draw_circle(fb, pt(-1, 0), 3, Color::WHITE, true, true)
+        });
+        assert_eq!(
+            alphas(&fb),
+            ["ff ff 80 00", "ff ff 57 00", "ff ad 00 00", "57 00 00 00"]
+        );
+    }
+
+    // Clipping must not change the pixels that remain, in either mode
+    #[test]
+    fn clipped_shapes_match_unclipped() {
+        type Draw = fn(&mut Framebuffer<OwnedPixels>, Point2D<i64>, bool);
+        let shapes: [Draw; 5] = [
+            |fb, o, aa| {
+                draw_line(
+                    fb,
+                    pt(o.x - 3, o.y + 1),
+                    pt(o.x + 14, o.y + 9),
+                    Color::WHITE,
+                    &StrokeStyle::solid(1),
+                    aa,
+                )
+            },
+            |fb, o, aa| {
+                draw_line(
+                    fb,
+                    pt(o.x + 1, o.y + 12),
+                    pt(o.x + 11, o.y - 2),
+                    Color::WHITE,
+                    &StrokeStyle::solid(4),
+                    aa,
+                )
+            },
+            |fb, o, aa| draw_circle(fb, pt(o.x + 2, o.y + 3), 6, Color::WHITE, false, aa),
+            |fb, o, aa| draw_circle(fb, pt(o.x + 9, o.y + 8), 5, Color::WHITE, true, aa),
+            |fb, o, aa| draw_ellipse(fb, pt(o.x + 5, o.y), 8, 3, Color::WHITE, false, aa),
+        ];
+
+        let margin = 20;
+        let window = Rect {
+            x0: margin,
+            y0: margin,
+            w: 10,
+            h: 10,
+        };
+        for draw in shapes {
+            for aa in [false, true] {
+                let big = render(10 + 2 * margin as u32, 10 + 2 * margin as u32, |fb| {
+                    draw(fb, pt(margin, margin), aa)
+                });
+                let small = render(10, 10, |fb| draw(fb, pt(0, 0), aa));
+                let expected = big.subregion(&window);
+                crate::test_utils::assert_same_pixels(&small, &expected);
+            }
+        }
+    }
+}
@@ -1,13 +1,23 @@
+use crate::color_utils::blend_colors_linear;
 use crate::{blend_colors, decode_png, Color, FbView, FbViewMut, Rect};
 use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
 use alloc::collections::btree_map::BTreeMap;
 use alloc::format;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::lazy_static;
+use num::Float;
 use serde::Deserialize;
+use spin::Mutex;
 
-use super::primitives::draw_rect;
+use super::accents::{ascii_fallback, decompose, draw_accent};
+use super::primitives::{draw_rect, draw_rect_outline};
+use super::text_cache::draw_cached_run;
+use super::ttf::{GlyphBitmap, GlyphCache, TrueTypeFace};
+use crate::content::ContentId;
 use crate::hash::compute_hash;
 
 #[derive(Deserialize)]
@@ -17,6 +27,14 @@ struct FontSpec {
     char_h: usize,
     char_w: usize,
     base_y: usize,
+    // Bounds of the Unicode ranges in the bitmap, end excluded
+    #[serde(default = "ascii_char_ranges")]
+    char_ranges: Vec<(u32, u32)>,
+}
+
+// Older bitmaps only have the printable ASCII characters
+fn ascii_char_ranges() -> Vec<(u32, u32)> {
+    vec![(32, 127)]
 }
 
 struct FontData {
@@ -34,12 +52,18 @@ fn load_font(family_name: &str, data: &FontData) -> Font {
         char_h,
         char_w,
         base_y,
+        char_ranges,
     } = spec;
 
     if bitmap.len() != nb_chars * char_w * char_h {
         panic!("Invalid font bitmap size");
     }
 
+    let ranges_len: u32 = char_ranges.iter().map(|(start, end)| end - start).sum();
+    if ranges_len as usize != nb_chars {
+        panic!("Invalid font char ranges");
+    }
+
     Font {
         name: format!("{}-{}", family_name, size),
         size,
@@ -48,12 +72,50 @@ fn load_font(family_name: &str, data: &FontData) -> Font {
         char_h,
         char_w,
         base_y,
+        char_ranges,
+        outline: None,
+    }
+}
+
+// Sizes offered for outline fonts, though any size can be used
+const OUTLINE_SIZES: [u32; 10] = [10, 12, 14, 16, 18, 20, 22, 24, 28, 32];
+const MAX_CACHED_GLYPHS: usize = 1024;
+
+// Positions per pixel that glyphs of outline fonts are rasterized at, along x
+const SUBPIXEL_STEPS: u32 = 4;
+
+// From StyleSheetText::smooth, set by UiStore::get_context()
+static SMOOTH_TEXT: AtomicBool = AtomicBool::new(true);
+
+pub fn set_smooth_text(smooth: bool) {
+    SMOOTH_TEXT.store(smooth, Ordering::Relaxed);
+}
+
+pub fn is_smooth_text() -> bool {
+    SMOOTH_TEXT.load(Ordering::Relaxed)
+}
+
+// Coverage is blended in linear light for smooth text
+fn glyph_blend_func() -> fn(Color, Color) -> Color {
+    match is_smooth_text() {
+        true => blend_colors_linear,
+        false => blend_colors,
     }
 }
 
+// In bytes, longer strings are drawn glyph by glyph, they rarely show up twice
+const MAX_CACHED_RUN_LEN: usize = 256;
+
 pub struct FontFamily {
     pub name: &'static str,
     by_size: BTreeMap<u32, Font>,
+    // Outline families make their fonts on demand, for any size
+    outline: Option<OutlineFamily>,
+}
+
+struct OutlineFamily {
+    face: &'static TrueTypeFace,
+    by_size: Mutex<BTreeMap<u32, &'static Font>>,
 }
 
 impl FontFamily {
@@ -69,17 +131,40 @@ impl FontFamily {
         FontFamily {
             name: family_name,
             by_size,
+            outline: None,
+        }
+    }
+
+    fn from_truetype(family_name: &'static str, face: &'static TrueTypeFace) -> Self {
+        FontFamily {
+            name: family_name,
+            by_size: BTreeMap::new(),
+            outline: Some(OutlineFamily {
+                face,
+                by_size: Mutex::new(BTreeMap::new()),
+            }),
         }
     }
 
     pub fn get_size(&self, size: u32) -> &Font {
-        self.by_size
-            .get(&size)
-            .expect("No font available for this size")
+        match &self.outline {
+            // Fonts are only metrics, they live as long as the family
+            Some(outline) => outline.by_size.lock().entry(size).or_insert_with(|| {
+                Box::leak(Box::new(Font::from_outline(self.name, outline.face, size)))
+            }),
+            None => self
+                .by_size
+                .get(&size)
+                .expect("No font available for this size"),
+        }
     }
 
     pub fn get_available_sizes(&self) -> impl Iterator<Item = u32> + use<'_> {
-        self.by_size.keys().map(|k| *k)
+        let sizes: Vec<u32> = match self.outline {
+            Some(_) => OUTLINE_SIZES.to_vec(),
+            None => self.by_size.keys().copied().collect(),
+        };
+        sizes.into_iter()
     }
 }
 
@@ -91,6 +176,185 @@ pub struct Font {
     pub char_h: usize,
     pub char_w: usize,
     pub base_y: usize,
+    char_ranges: Vec<(u32, u32)>,
+    // Glyphs are rasterized from it when set, instead of taken from the bitmap
+    outline: Option<Outline>,
+}
+
+struct Outline {
+    family: &'static str,
+    face: &'static TrueTypeFace,
+    // Pixels per font unit
+    scale: f32,
+}
+
+impl Font {
+    // For outline fonts, char_w is the advance of digits and the width of the box drawn
+    // for missing characters
+    fn from_outline(family: &'static str, face: &'static TrueTypeFace, size: u32) -> Self {
+        let scale = size as f32 / face.units_per_em as f32;
+        let ascent = (face.ascent as f32 * scale).ceil() as usize;
+        let descent = (-face.descent as f32 * scale).ceil() as usize;
+        let char_w = face
+            .glyph_id('0')
+            .map(|glyph| (face.advance(glyph) as f32 * scale).round() as usize)
+            .unwrap_or(size as usize / 2);
+
+        Font {
+            name: format!("{}-{}", family, size),
+            size: size as usize,
+            bitmap: Vec::new(),
+            nb_chars: 0,
+            char_h: ascent + descent,
+            char_w,
+            base_y: ascent,
+            char_ranges: Vec::new(),
+            outline: Some(Outline {
+                family,
+                face,
+                scale,
+            }),
+        }
+    }
+
+    // In pixels
+    pub fn char_advance(&self, c: char) -> u32 {
+        let Some(outline) = &self.outline else {
+            return self.char_w as u32;
+        };
+        match outline.face.glyph_id(c) {
+            Some(glyph) => (outline.face.advance(glyph) as f32 * outline.scale).round() as u32,
+            None => self.char_w as u32,
+        }
+    }
+
+    // In pixels, to add to the advance of the left character when followed by the right one
+    pub fn kerning(&self, left: char, right: char) -> i32 {
+        let Some(outline) = &self.outline else {
+            return 0;
+        };
+        let face = outline.face;
+        match (face.glyph_id(left), face.glyph_id(right)) {
+            (Some(l), Some(r)) => (face.kerning(l, r) as f32 * outline.scale).round() as i32,
+            _ => 0,
+        }
+    }
+
+    // Position of each character boundary from the start of the string, so one more
+    // than the number of characters
+    pub fn char_offsets(&self, s: &str) -> Vec<i64> {
+        if let Some(pen_positions) = self.pen_positions(s) {
+            return pen_positions.iter().map(|x| x.round() as i64).collect();
+        }
+
+        let mut offsets = vec![0];
+        let mut x = 0;
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            let kerning = chars.peek().map(|&next| self.kerning(c, next)).unwrap_or(0);
+            x += i64::max(0, self.char_advance(c) as i64 + kerning as i64);
+            offsets.push(x);
+        }
+        offsets
+    }
+
+    // Same as char_offsets() without rounding, where glyphs go with subpixel positioning.
+    // None for bitmap fonts or when text is not smooth.
+    fn pen_positions(&self, s: &str) -> Option<Vec<f32>> {
+        let outline = self.outline.as_ref()?;
+        if !is_smooth_text() {
+            return None;
+        }
+
+        let face = outline.face;
+        let advance = |c: char| match face.glyph_id(c) {
+            Some(glyph) => face.advance(glyph) as f32 * outline.scale,
+            None => self.char_w as f32,
+        };
+        let kerning = |left: char, right: char| match (face.glyph_id(left), face.glyph_id(right)) {
+            (Some(l), Some(r)) => face.kerning(l, r) as f32 * outline.scale,
+            _ => 0.0,
+        };
+
+        let mut positions = vec![0.0];
+        let mut x = 0.0;
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            let kerning = chars.peek().map(|&next| kerning(c, next)).unwrap_or(0.0);
+            x += f32::max(0.0, advance(c) + kerning);
+            positions.push(x);
+        }
+        Some(positions)
+    }
+
+    // Top of the underline from the top of the character cell, and its thickness
+    pub fn underline_metrics(&self) -> (i64, u32) {
+        let thickness = self.default_stroke_thickness();
+        let default = (self.base_y as i64 + thickness as i64, thickness);
+        self.outline_stroke(|face| face.underline)
+            .unwrap_or(default)
+    }
+
+    // Same for the line through the characters, around the middle of lowercase letters
+    pub fn strikethrough_metrics(&self) -> (i64, u32) {
+        let thickness = self.default_stroke_thickness();
+        let default = (self.base_y as i64 - (3 * self.size / 10) as i64, thickness);
+        self.outline_stroke(|face| face.strikeout)
+            .unwrap_or(default)
+    }
+
+    fn default_stroke_thickness(&self) -> u32 {
+        u32::max(1, (self.size as u32 + 7) / 14)
+    }
+
+    fn outline_stroke(
+        &self,
+        get: impl Fn(&TrueTypeFace) -> Option<(i16, i16)>,
+    ) -> Option<(i64, u32)> {
+        let outline = self.outline.as_ref()?;
+        let (position, thickness) = get(outline.face)?;
+        let top = self.base_y as i64 - (position as f32 * outline.scale).round() as i64;
+        let thickness = u32::max(1, (thickness as f32 * outline.scale).round() as u32);
+        Some((top, thickness))
+    }
+
+    pub fn text_width(&self, s: &str) -> u32 {
+        match self.outline {
+            Some(_) => *self.char_offsets(s).last().unwrap() as u32,
+            None => (self.char_w * s.chars().count()) as u32,
+        }
+    }
+
+    // Index of the character in the bitmap
+    fn glyph_index(&self, c: char) -> Option<usize> {
+        let c = c as u32;
+        let mut offset = 0;
+        for &(start, end) in self.char_ranges.iter() {
+            if (start..end).contains(&c) {
+                return Some(offset + (c - start) as usize);
+            }
+            offset += (end - start) as usize;
+        }
+        None
+    }
+
+    // First row with a lit pixel, or the baseline for blank glyphs
+    fn glyph_top(&self, index: usize) -> usize {
+        let row_w = self.char_w * self.nb_chars;
+        (0..self.char_h)
+            .find(|y| {
+                let row_start = y * row_w + index * self.char_w;
+                self.bitmap[row_start..row_start + self.char_w]
+                    .iter()
+                    .any(|val| *val > 0)
+            })
+            .unwrap_or(self.base_y)
+    }
+}
+
+// Whether draw_char() can draw the character, from the font or by approximating it
+pub fn is_supported_char(c: char) -> bool {
+    matches!(c, ' '..='~' | '\u{a0}'..='\u{ff}')
 }
 
 macro_rules! font_data {
@@ -109,11 +373,16 @@ macro_rules! font_data {
 }
 
 lazy_static! {
+    static ref DEJAVU_SANS: TrueTypeFace =
+        TrueTypeFace::parse(include_bytes!("../../fonts/DejaVuSans/DejaVuSans.ttf"))
+            .expect("Invalid TrueType font");
+    static ref GLYPH_CACHE: Mutex<GlyphCache> = Mutex::new(GlyphCache::new(MAX_CACHED_GLYPHS));
     pub static ref FONT_FAMILIES: BTreeMap<&'static str, FontFamily> = [
         font_data!("NotoSansMono", 12 14 16 18 20 22),
         font_data!("XanMono", 12 14 16 18 20 22),
         font_data!("Libertinus", 12 14 16 18 20 22),
         font_data!("MajorMono", 12 14 16 18 20 22),
+        FontFamily::from_truetype("DejaVuSans", &DEJAVU_SANS),
     ]
     .into_iter()
     .map(|family| (family.name, family))
@@ -144,7 +413,7 @@ pub fn draw_line_in_rect<F: FbViewMut>(
     justif: TextJustification,
 ) -> (i64, i64) {
     let text_h = font.char_h as u32;
-    let text_w = (font.char_w * s.len()) as i64;
+    let text_w = font.text_width(s) as i64;
     let (xc, yc) = rect.center();
 
     let pad_y = i64::max(0, rect.h as i64 - text_h as i64) / 2;
@@ -161,7 +430,7 @@ pub fn draw_line_in_rect<F: FbViewMut>(
 
     draw_str(fb, s, text_x0, text_y0, font, color, None);
 
-    let text_x1 = text_x0 + (font.char_w * s.len()) as i64;
+    let text_x1 = text_x0 + text_w;
 
     (text_x0, text_x1)
 }
@@ -176,7 +445,7 @@ pub fn draw_str<F: FbViewMut>(
     bg_color: Option<Color>,
 ) {
     if let Some(bg_color) = bg_color {
-        let text_w = (font.char_w * s.len()) as u32;
+        let text_w = font.text_width(s);
         let rect = Rect {
             x0,
             y0,
@@ -186,10 +455,38 @@ pub fn draw_str<F: FbViewMut>(
         draw_rect(fb, &rect, bg_color, true);
     }
 
-    let mut x = x0;
-    for c in s.chars() {
-        draw_char(fb, c, x, y0, font, color, true);
-        x += font.char_w as i64;
+    if s.is_empty() {
+        return;
+    }
+    if s.len() > MAX_CACHED_RUN_LEN {
+        return draw_str_glyphs(fb, s, x0, y0, font, color);
+    }
+
+    // Glyphs can reach outside of their cell, e.g. italics and accents
+    let m = (font.char_h / 2) as i64;
+    let run_id = ContentId::from_hash(&(s, font.name.as_str(), color));
+    let run_size = || {
+        let w = font.text_width(s) + 2 * m as u32;
+        (w, font.char_h as u32 + 2 * m as u32)
+    };
+    draw_cached_run(fb, run_id, (x0 - m, y0 - m), run_size, |run_fb| {
+        draw_str_glyphs(run_fb, s, m, m, font, color)
+    });
+}
+
+fn draw_str_glyphs<F: FbViewMut>(fb: &mut F, s: &str, x0: i64, y0: i64, font: &Font, color: Color) {
+    let (Some(outline), Some(pen_positions)) = (&font.outline, font.pen_positions(s)) else {
+        for (c, x) in s.chars().zip(font.char_offsets(s)) {
+            draw_char(fb, c, x0 + x, y0, font, color, true);
+        }
+        return;
+    };
+
+    let steps = SUBPIXEL_STEPS as i64;
+    for (c, x) in s.chars().zip(pen_positions) {
+        let x = (x * steps as f32).round() as i64;
+        let (x, subpixel) = (x.div_euclid(steps), x.rem_euclid(steps) as u8);
+        draw_outline_char(fb, c, x0 + x, y0, font, outline, color, true, subpixel);
     }
 }
 
@@ -202,14 +499,48 @@ pub fn draw_char<F: FbViewMut>(
     color: Color,
     blend: bool,
 ) {
-    let mut c = c as u8;
+    if let Some(outline) = &font.outline {
+        draw_outline_char(fb, c, x0, y0, font, outline, color, blend, 0);
+        return;
+    }
 
-    // Replacing unsupported chars with spaces
-    if c < 32 || c > 126 {
-        c = 32
+    // Latin-1 letters missing from the bitmap are drawn as their base letter with
+    // the accent added, and other unsupported chars as look-alikes or replaced
+    if let Some(index) = font.glyph_index(c) {
+        draw_glyph(fb, index, x0, y0, font, color, blend);
+    } else if let Some((base, accent)) = decompose(c) {
+        let Some(index) = font.glyph_index(base) else {
+            return;
+        };
+        draw_glyph(fb, index, x0, y0, font, color, blend);
+        let cell = Rect {
+            x0,
+            y0,
+            w: font.char_w as u32,
+            h: font.char_h as u32,
+        };
+        let glyph_top = font.glyph_top(index) as i64;
+        draw_accent(fb, accent, &cell, glyph_top, font.base_y as i64, color);
+    } else {
+        let replacement = match c {
+            c if c.is_control() => ' ',
+            c => ascii_fallback(c).unwrap_or('?'),
+        };
+        if let Some(index) = font.glyph_index(replacement) {
+            draw_glyph(fb, index, x0, y0, font, color, blend);
+        }
     }
+}
 
-    let c_index = (c - 32) as usize;
+fn draw_glyph<F: FbViewMut>(
+    fb: &mut F,
+    c_index: usize,
+    x0: i64,
+    y0: i64,
+    font: &Font,
+    color: Color,
+    blend: bool,
+) {
     let Font {
         nb_chars,
         char_h,
@@ -217,6 +548,7 @@ pub fn draw_char<F: FbViewMut>(
         ..
     } = *font;
     let (r, g, b, _a) = color.as_rgba();
+    let blend_func = glyph_blend_func();
 
     let char_rect = Rect {
         x0,
@@ -241,7 +573,78 @@ pub fn draw_char<F: FbViewMut>(
             if let Some(curr_color) = fb.get_pixel(x, y) {
                 let txt_color = Color::rgba(r, g, b, val_font);
                 let new_color = match blend {
-                    true => blend_colors(txt_color, curr_color),
+                    true => blend_func(txt_color, curr_color),
+                    false => txt_color,
+                };
+                fb.set_pixel(x, y, new_color);
+            }
+        }
+    }
+}
+
+// Characters missing from outline fonts are drawn as a box. The glyph is moved right
+// by subpixel / SUBPIXEL_STEPS of a pixel.
+#[allow(clippy::too_many_arguments)]
+fn draw_outline_char<F: FbViewMut>(
+    fb: &mut F,
+    c: char,
+    x0: i64,
+    y0: i64,
+    font: &Font,
+    outline: &Outline,
+    color: Color,
+    blend: bool,
+    subpixel: u8,
+) {
+    if c.is_control() {
+        return;
+    }
+
+    let Some(glyph) = outline.face.glyph_id(c) else {
+        let m = (font.char_w / 6) as u32;
+        let box_rect = Rect {
+            x0: x0 + m as i64,
+            y0: y0 + (font.char_h - font.base_y) as i64,
+            w: (font.char_w as u32).saturating_sub(2 * m),
+            h: (2 * font.base_y).saturating_sub(font.char_h) as u32,
+        };
+        if box_rect.w > 2 && box_rect.h > 2 {
+            draw_rect_outline(fb, &box_rect, color, blend, 1);
+        }
+        return;
+    };
+
+    let key = (outline.family, c, font.size as u32, subpixel);
+    let x_shift = subpixel as f32 / SUBPIXEL_STEPS as f32;
+    let bitmap = GLYPH_CACHE.lock().get_or_rasterize(key, || {
+        outline.face.rasterize(glyph, outline.scale, x_shift)
+    });
+    let Some(bitmap) = bitmap else {
+        return;
+    };
+
+    let GlyphBitmap {
+        w,
+        left,
+        top,
+        ref alpha,
+        ..
+    } = *bitmap;
+    let gx0 = x0 + left as i64;
+    let gy0 = y0 + font.base_y as i64 - top as i64;
+    let (r, g, b, _a) = color.as_rgba();
+    let blend_func = glyph_blend_func();
+
+    for (dy, row) in alpha.chunks_exact(w as usize).enumerate() {
+        for (dx, &val) in row.iter().enumerate() {
+            if val == 0 {
+                continue;
+            }
+            let (x, y) = (gx0 + dx as i64, gy0 + dy as i64);
+            if let Some(curr_color) = fb.get_pixel(x, y) {
+                let txt_color = Color::rgba(r, g, b, val);
+                let new_color = match blend {
+                    true => blend_func(txt_color, curr_color),
                     false => txt_color,
                 };
                 fb.set_pixel(x, y, new_color);
@@ -284,6 +687,17 @@ impl RichText {
     }
 
     pub fn add_part(&mut self, s: &str, color: Color, font: &'static Font, link: Option<&str>) {
+        self.add_styled_part(s, color, font, link, TextStyle::default());
+    }
+
+    pub fn add_styled_part(
+        &mut self,
+        s: &str,
+        color: Color,
+        font: &'static Font,
+        link: Option<&str>,
+        style: TextStyle,
+    ) {
         // Adding link to store
         let link_id = link.map(|link| {
             let link_id = LinkId(compute_hash(link));
@@ -298,22 +712,36 @@ impl RichText {
             c,
             color,
             font,
+            style,
             link_id,
         }));
     }
 
+    // The character takes the style of the run it is inserted into, if any
     pub fn insert(&mut self, pos: usize, c: char, color: Color, font: &'static Font) {
+        let style = self.run_style(pos);
         self.chars.insert(
             pos,
             RichChar {
                 c,
                 color,
                 font,
+                style,
                 link_id: None,
             },
         );
     }
 
+    // Style shared by the characters on both sides of the position, the default one
+    // between two differently styled characters
+    pub fn run_style(&self, pos: usize) -> TextStyle {
+        let before = pos.checked_sub(1).and_then(|i| self.chars.get(i));
+        match (before, self.chars.get(pos)) {
+            (Some(before), Some(after)) if before.style == after.style => before.style,
+            _ => TextStyle::default(),
+        }
+    }
+
     pub fn remove(&mut self, pos: usize) {
         // Decrementing counter in link store
         if let Some(link_id) = self.chars[pos].link_id {
@@ -328,6 +756,75 @@ impl RichText {
         self.chars.remove(pos);
     }
 
+    pub fn slice(&self, start: usize, end: usize) -> Vec<RichChar> {
+        self.chars[start..end].to_vec()
+    }
+
+    pub fn splice_ranges(&mut self, ranges: &[(usize, usize)], parts: Vec<Vec<RichChar>>) {
+        let old_chars = core::mem::take(&mut self.chars);
+        let mut new_chars = Vec::with_capacity(old_chars.len());
+        let mut removed_links = Vec::new();
+        let mut old_chars = old_chars.into_iter().enumerate().peekable();
+
+        for (&(start, end), part) in ranges.iter().zip(parts.into_iter()) {
+            while let Some((_, rich_char)) = old_chars.next_if(|(i, _)| *i < start) {
+                new_chars.push(rich_char);
+            }
+
+            while let Some((_, rich_char)) = old_chars.next_if(|(i, _)| *i < end) {
+                if let Some(link_id) = rich_char.link_id {
+                    removed_links.push(link_id);
+                }
+            }
+
+            new_chars.extend(part.into_iter().map(|mut rich_char| {
+                // Links are not restored if they were fully removed from the store
+                if let Some(link_id) = rich_char.link_id {
+                    match self.link_store.get_mut(&link_id) {
+                        Some((counter, _)) => *counter += 1,
+                        None => rich_char.link_id = None,
+                    }
+                }
+                rich_char
+            }));
+        }
+
+        new_chars.extend(old_chars.map(|(_, rich_char)| rich_char));
+
+        self.chars = new_chars;
+
+        for link_id in removed_links {
+            if let Some((counter, _)) = self.link_store.get_mut(&link_id) {
+                *counter -= 1;
+                if *counter == 0 {
+                    self.link_store.remove(&link_id);
+                }
+            }
+        }
+    }
+
+    pub fn replace_ranges(&mut self, ranges: &[(usize, usize)], s: &str) {
+        let parts = ranges
+            .iter()
+            .map(|&(start, _)| {
+                // Replacement text inherits the style of the first replaced character
+                let ref_char = self.chars.get(start).or(self.chars.last());
+                match ref_char {
+                    Some(ref_char) => s
+                        .chars()
+                        .map(|c| {
+                            RichChar::new(c, ref_char.color, ref_char.font)
+                                .with_style(ref_char.style)
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                }
+            })
+            .collect();
+
+        self.splice_ranges(ranges, parts);
+    }
+
     pub fn from_str(s: &str, color: Color, font: &'static Font, link: Option<&str>) -> Self {
         let mut t = Self::new();
         t.add_part(s, color, font, link);
@@ -346,6 +843,22 @@ impl RichText {
         self.chars.len()
     }
 
+    pub fn chars(&self) -> impl Iterator<Item = &RichChar> {
+        self.chars.iter()
+    }
+
+    pub fn get_char(&self, index: usize) -> &RichChar {
+        &self.chars[index]
+    }
+
+    pub fn set_color(&mut self, pos: usize, color: Color) {
+        self.chars[pos].color = color;
+    }
+
+    pub fn set_style(&mut self, pos: usize, style: TextStyle) {
+        self.chars[pos].style = style;
+    }
+
     pub fn concat(&mut self, mut other: Self) {
         for (link_id, (other_count, other_link)) in other.link_store.into_iter() {
             self.link_store
@@ -361,26 +874,58 @@ impl RichText {
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct LinkId(u64);
 
+// Decorations drawn with the characters
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct TextStyle {
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub bg_color: Option<Color>,
+}
+
 #[derive(Clone)]
 pub struct RichChar {
     pub c: char,
     pub color: Color,
     pub font: &'static Font,
+    pub style: TextStyle,
     link_id: Option<LinkId>,
 }
 
 impl RichChar {
+    pub fn new(c: char, color: Color, font: &'static Font) -> Self {
+        RichChar {
+            c,
+            color,
+            font,
+            style: TextStyle::default(),
+            link_id: None,
+        }
+    }
+
+    pub fn with_style(mut self, style: TextStyle) -> Self {
+        self.style = style;
+        self
+    }
+
     fn width(&self) -> u32 {
         if self.c == '\n' {
             0
         } else {
-            self.font.char_w as u32
+            self.font.char_advance(self.c)
         }
     }
+}
 
-    fn height(&self) -> u32 {
-        self.font.char_h as u32
-    }
+// Horizontal space taken by each character, kerning with the next one included when it
+// has the same font
+fn rich_advances(chars: &[RichChar]) -> impl Iterator<Item = i64> + '_ {
+    chars.iter().enumerate().map(|(i, rc)| {
+        let kerning = match chars.get(i + 1) {
+            Some(next) if core::ptr::eq(next.font, rc.font) => rc.font.kerning(rc.c, next.c),
+            _ => 0,
+        };
+        i64::max(0, rc.width() as i64 + kerning as i64)
+    })
 }
 
 pub struct FormattedRichLine {
@@ -423,8 +968,7 @@ impl FormattedRichText {
         let get_line_pos = |line_i: usize, line_char_i: usize| -> (u32, u32, u32) {
             let line = &self.lines[line_i];
             let x_offset = line.x_offset;
-            let left_chars = &line.chars[0..line_char_i];
-            let x = left_chars.iter().map(|c| c.font.char_w as u32).sum::<u32>();
+            let x = rich_advances(&line.chars).take(line_char_i).sum::<i64>() as u32;
             let y = self.lines[..line_i].iter().map(|l| l.h).sum::<u32>();
 
             let ref_char = if line_char_i == 0 {
@@ -486,12 +1030,9 @@ impl FormattedRichText {
             if line_rect.check_contains_point(xp, yp) {
                 let mut x = line_rect.x0;
 
-                let index = line
-                    .chars
-                    .iter()
+                let index = rich_advances(&line.chars)
                     .enumerate()
-                    .find_map(|(i, c)| {
-                        let char_w = c.font.char_w as i64;
+                    .find_map(|(i, char_w)| {
                         if xp <= x + char_w {
                             return Some(index + i);
                         }
@@ -510,6 +1051,93 @@ impl FormattedRichText {
         None
     }
 
+    // Like xy_to_index(), but a point outside of the text maps to the closest position,
+    // e.g. the end of the line for a point on its right
+    pub fn xy_to_nearest_index(&self, xy: (i64, i64)) -> usize {
+        let (xp, yp) = xy;
+
+        let mut index = 0;
+        let mut y = 0;
+        for (line_i, line) in self.lines.iter().enumerate() {
+            let line_end = index + line.chars.len();
+            let is_last = line_i == self.lines.len() - 1;
+
+            if yp >= y + line.h as i64 && is_last {
+                return line_end;
+            }
+
+            if yp < y + line.h as i64 {
+                let mut x = line.x_offset as i64;
+                let advances = rich_advances(&line.chars);
+                for (i, (c, char_w)) in line.chars.iter().zip(advances).enumerate() {
+                    if c.c == '\n' || xp < x + char_w / 2 {
+                        return index + i;
+                    }
+                    x += char_w;
+                }
+                return line_end;
+            }
+
+            y += line.h as i64;
+            index = line_end;
+        }
+
+        index
+    }
+
+    pub fn ranges_to_rects(&self, ranges: &[(usize, usize)], clip_rect: &Rect) -> Vec<Rect> {
+        let [_, clip_y1, _, clip_y2] = clip_rect.as_xyxy();
+
+        let mut rects = Vec::new();
+        let mut ranges = ranges.iter().peekable();
+        let mut index = 0;
+        let mut y = 0;
+
+        for line in self.lines.iter() {
+            let line_end = index + line.chars.len();
+            let line_visible = y <= clip_y2 && y + line.h as i64 > clip_y1;
+
+            while let Some(&&(start, end)) = ranges.peek() {
+                if start >= line_end {
+                    break;
+                }
+
+                if line_visible {
+                    let mut x = line.x_offset as i64;
+                    let mut span: Option<(i64, i64)> = None;
+                    for (i, char_w) in rich_advances(&line.chars).enumerate() {
+                        if (start..end).contains(&(index + i)) {
+                            let x1 = span.map(|(x1, _)| x1).unwrap_or(x);
+                            span = Some((x1, x + char_w));
+                        }
+                        x += char_w;
+                    }
+
+                    if let Some((x1, x2)) = span {
+                        rects.push(Rect {
+                            x0: x1,
+                            y0: y,
+                            w: (x2 - x1) as u32,
+                            h: line.h,
+                        });
+                    }
+                }
+
+                // Range continues on the next line
+                if end > line_end {
+                    break;
+                }
+
+                ranges.next();
+            }
+
+            y += line.h as i64;
+            index = line_end;
+        }
+
+        rects
+    }
+
     pub fn has_link(&self) -> bool {
         !self.link_store.is_empty()
     }
@@ -532,13 +1160,13 @@ impl FormattedRichText {
             let mut underline_opt = None;
 
             let mut x = line.x_offset as i64;
-            for rc in line.chars.iter() {
+            for (rc, char_w) in line.chars.iter().zip(rich_advances(&line.chars)) {
                 if rc.link_id == Some(link_id) {
                     let (x0, x1) = underline_opt.get_or_insert((x, x));
                     *x0 = i64::min(*x0, x);
-                    *x1 = i64::max(*x1, x + rc.font.char_w as i64);
+                    *x1 = i64::max(*x1, x + char_w);
                 }
-                x += rc.font.char_w as i64;
+                x += char_w;
             }
 
             if let Some((x0, x1)) = underline_opt {
@@ -590,71 +1218,128 @@ impl FormattedRichLine {
     }
 }
 
-pub fn format_rich_lines(
-    text: &RichText,
-    max_w: u32,
-    justif: TextJustification,
-) -> FormattedRichText {
-    let RichText {
-        chars, link_store, ..
-    } = text;
-
-    let lines: Vec<FormattedRichLine> = chars
-        .split_inclusive(|rc| rc.c == '\n')
-        .flat_map(|explicit_line| {
-            let mut segments = Vec::new();
+pub struct TextMetrics {
+    pub width: u32,
+    // Above and below the baseline
+    pub ascent: u32,
+    pub descent: u32,
+}
+
+pub fn measure_str(s: &str, font: &Font) -> TextMetrics {
+    TextMetrics {
+        width: font.text_width(s),
+        ascent: font.base_y as u32,
+        descent: (font.char_h - font.base_y) as u32,
+    }
+}
+
+// Characters start..end of the text (end excluded). The whitespace at the end of the
+// line belongs to it, but does not count in its width.
+pub struct LineMetrics {
+    pub start: usize,
+    pub end: usize,
+    pub width: u32,
+    pub ascent: u32,
+    pub descent: u32,
+}
+
+impl LineMetrics {
+    pub fn height(&self) -> u32 {
+        self.ascent + self.descent
+    }
+}
+
+// Lines are broken between words when possible, and inside words longer than a line
+pub fn break_lines(text: &RichText, max_w: u32) -> Vec<LineMetrics> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    for explicit_line in text.chars.split_inclusive(|rc| rc.c == '\n') {
+        let advances: Vec<u32> = rich_advances(explicit_line).map(|w| w as u32).collect();
+        let n = explicit_line.len();
+
+        let mut start = 0;
+        while start < n {
             let mut x = 0;
-            let mut i1 = 0;
-            let mut i2 = 0;
-            loop {
-                let ended = i2 == explicit_line.len();
-
-                let push_line = {
-                    if ended {
-                        true
-                    } else {
-                        let rc = &explicit_line[i2];
-                        let char_w = rc.width();
-                        if x + char_w > max_w {
-                            true
-                        } else {
-                            x += char_w;
-                            i2 += 1;
-                            false
-                        }
+            // Start of the last word after some whitespace
+            let mut word_start = None;
+            let mut end = start;
+            while end < n {
+                let c = explicit_line[end].c;
+                let char_w = advances[end];
+
+                // Whitespace never wraps, it hangs at the end of the line instead
+                if c.is_whitespace() {
+                    x += char_w;
+                    end += 1;
+                    let before_word = explicit_line
+                        .get(end)
+                        .is_some_and(|rc| !rc.c.is_whitespace());
+                    if before_word {
+                        word_start = Some(end);
                     }
-                };
-
-                if push_line {
-                    let s = &explicit_line[i1..i2];
-                    let line_w = s.iter().map(|rc| rc.width()).sum();
-                    let line_h = s.iter().map(|rc| rc.height()).max().unwrap();
-                    let line_base_y = s.iter().map(|rc| rc.font.base_y).max().unwrap();
-
-                    let x_offset = match justif {
-                        TextJustification::Left => 0,
-                        TextJustification::Center => (max_w - line_w) / 2,
-                        TextJustification::Right => max_w - line_w,
-                    };
-
-                    segments.push(FormattedRichLine {
-                        chars: s.to_vec(),
-                        w: line_w,
-                        h: line_h,
-                        base_y: line_base_y as u32,
-                        x_offset,
-                    });
-
-                    i1 = i2;
-                    x = 0;
+                    continue;
                 }
 
-                if ended {
+                // At least one character per line
+                if x + char_w > max_w && end > start {
+                    end = word_start.unwrap_or(end);
                     break;
                 }
+
+                x += char_w;
+                end += 1;
             }
 
-            segments
+            let line_chars = &explicit_line[start..end];
+            let trimmed_len = line_chars
+                .iter()
+                .rposition(|rc| !rc.c.is_whitespace())
+                .map(|i| i + 1)
+                .unwrap_or(0);
+
+            lines.push(LineMetrics {
+                start: offset + start,
+                end: offset + end,
+                width: rich_advances(&line_chars[..trimmed_len]).sum::<i64>() as u32,
+                ascent: line_chars.iter().map(|rc| rc.font.base_y).max().unwrap() as u32,
+                descent: line_chars
+                    .iter()
+                    .map(|rc| rc.font.char_h - rc.font.base_y)
+                    .max()
+                    .unwrap() as u32,
+            });
+
+            start = end;
+        }
+
+        offset += n;
+    }
+
+    lines
+}
+
+pub fn format_rich_lines(
+    text: &RichText,
+    max_w: u32,
+    justif: TextJustification,
+) -> FormattedRichText {
+    let lines: Vec<FormattedRichLine> = break_lines(text, max_w)
+        .into_iter()
+        .map(|metrics| {
+            let x_offset = match justif {
+                TextJustification::Left => 0,
+                TextJustification::Center => max_w.saturating_sub(metrics.width) / 2,
+                TextJustification::Right => max_w.saturating_sub(metrics.width),
+            };
+
+            FormattedRichLine {
+                chars: text.chars[metrics.start..metrics.end].to_vec(),
+                w: metrics.width,
+                h: metrics.height(),
+                base_y: metrics.ascent,
+                x_offset,
+            }
         })
         .collect();
 
@@ -665,7 +1350,7 @@ pub fn format_rich_lines(
         w: max_w,
         h: text_h,
         justif,
-        link_store: link_store.clone(),
+        link_store: text.link_store.clone(),
     }
 }
 
@@ -680,24 +1365,317 @@ pub fn draw_rich_slice<F: FbViewMut>(fb: &mut F, rich_slice: &[RichChar], x0: i6
         .max()
         .unwrap();
 
+    let slice_h = rich_slice
+        .iter()
+        .map(|rich_char| max_base_y - rich_char.font.base_y + rich_char.font.char_h)
+        .max()
+        .unwrap();
+
+    // Backgrounds go under all the glyphs, which can overhang their advance
     let mut x = x0;
-    for rich_char in rich_slice.iter() {
-        let dy = (max_base_y - rich_char.font.base_y) as i64;
-        draw_char(
-            fb,
-            rich_char.c,
-            x,
-            y0 + dy,
-            rich_char.font,
-            rich_char.color,
-            true,
-        );
-        x += rich_char.font.char_w as i64;
+    for (rich_char, char_w) in rich_slice.iter().zip(rich_advances(rich_slice)) {
+        if let Some(bg_color) = rich_char.style.bg_color {
+            let bg_rect = Rect {
+                x0: x,
+                y0,
+                w: char_w as u32,
+                h: slice_h as u32,
+            };
+            draw_rect(fb, &bg_rect, bg_color, true);
+        }
+        x += char_w;
+    }
+
+    let mut x = x0;
+    for (rich_char, char_w) in rich_slice.iter().zip(rich_advances(rich_slice)) {
+        let RichChar {
+            c,
+            color,
+            font,
+            style,
+            ..
+        } = *rich_char;
+        let y = y0 + (max_base_y - font.base_y) as i64;
+        draw_char(fb, c, x, y, font, color, true);
+
+        let strokes = [
+            (style.underline, font.underline_metrics()),
+            (style.strikethrough, font.strikethrough_metrics()),
+        ];
+        for (_, (top, thickness)) in strokes.into_iter().filter(|(enabled, _)| *enabled) {
+            let stroke_rect = Rect {
+                x0: x,
+                y0: y + top,
+                w: char_w as u32,
+                h: thickness,
+            };
+            draw_rect(fb, &stroke_rect, color, true);
+        }
+
+        x += char_w;
     }
 }
 
 pub fn compute_text_bbox(s: &str, font: &Font) -> (u32, u32) {
-    let w = font.char_w * s.len();
-    let h = font.char_h;
-    (w as u32, h as u32)
+    let metrics = measure_str(s, font);
+    (metrics.width, metrics.ascent + metrics.descent)
+}
+
+// Truncated with "..." to fit in max_len pixels
+pub fn ellipsize_text(txt: &str, font: &Font, max_len: u32) -> String {
+    let dots_w = font.text_width("...");
+
+    if font.text_width(txt) <= max_len {
+        txt.to_owned()
+    } else if dots_w > max_len {
+        String::new()
+    } else {
+        let offsets = font.char_offsets(txt);
+        let nb_kept = offsets
+            .iter()
+            .rposition(|&x| x as u32 + dots_w <= max_len)
+            .unwrap_or(0);
+        let s = txt.chars().take(nb_kept).collect::<String>();
+        format!("{}...", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_golden, pixels, text_mode_lock};
+    use crate::{Framebuffer, OwnedPixels};
+
+    const SAMPLE: &str = "Hamburgefonstiv 0.5 WAVE";
+
+    fn render(s: &str, font: &Font, fg: Color, bg: Color) -> Framebuffer<OwnedPixels> {
+        let w = font.text_width(s) + 8;
+        let mut fb = Framebuffer::new_owned_filled(w, font.char_h as u32 + 8, bg);
+        draw_str(&mut fb, s, 4, 4, font, fg, None);
+        fb
+    }
+
+    // Glyph by glyph, without the run cache
+    fn render_uncached(s: &str, font: &Font, fg: Color, bg: Color) -> Framebuffer<OwnedPixels> {
+        let w = font.text_width(s) + 8;
+        let mut fb = Framebuffer::new_owned_filled(w, font.char_h as u32 + 8, bg);
+        draw_str_glyphs(&mut fb, s, 4, 4, font, fg);
+        fb
+    }
+
+    #[test]
+    fn golden_renders() {
+        let _lock = text_mode_lock();
+        let dejavu = get_font("DejaVuSans", 16);
+        let (dark, light) = (Color::rgb(20, 20, 30), Color::rgb(240, 240, 235));
+
+        for (smooth, mode) in [(true, "smooth"), (false, "crisp")] {
+            set_smooth_text(smooth);
+            let name = format!("text_dejavu_{}_dark_on_light", mode);
+            assert_golden(&name, &render(SAMPLE, dejavu, dark, light));
+            let name = format!("text_dejavu_{}_light_on_dark", mode);
+            assert_golden(&name, &render(SAMPLE, dejavu, light, dark));
+        }
+        set_smooth_text(true);
+
+        let small = get_font("DejaVuSans", 10);
+        assert_golden(
+            "text_dejavu_small_colored",
+            &render(
+                SAMPLE,
+                small,
+                Color::rgb(200, 40, 40),
+                Color::rgb(30, 60, 90),
+            ),
+        );
+        let bitmap = get_font("NotoSansMono", 14);
+        assert_golden("text_bitmap", &render(SAMPLE, bitmap, dark, light));
+    }
+
+    // Crisp text has whole-pixel advances, smooth text does not
+    #[test]
+    fn subpixel_positions() {
+        let _lock = text_mode_lock();
+        let font = get_font("DejaVuSans", 16);
+
+        set_smooth_text(false);
+        assert!(font.pen_positions("iiiiiiii").is_none());
+        let crisp = font.char_offsets("iiiiiiii");
+        set_smooth_text(true);
+        let smooth = font.pen_positions("iiiiiiii").unwrap();
+        assert!(smooth.iter().any(|x| x.fract() != 0.0), "{:?}", smooth);
+
+        // The offsets used for layout round the same positions
+        let offsets = font.char_offsets("iiiiiiii");
+        for (offset, x) in offsets.iter().zip(smooth.iter()) {
+            assert_eq!(*offset, x.round() as i64);
+        }
+        // Whole-pixel advances drift away from them
+        assert_ne!(crisp, offsets);
+        assert!(get_font("NotoSansMono", 14).pen_positions("ii").is_none());
+    }
+
+    #[test]
+    fn cached_runs_match_glyphs() {
+        let _lock = text_mode_lock();
+        let font = get_font("DejaVuSans", 16);
+        let pairs = [
+            (Color::BLACK, Color::WHITE),
+            (Color::WHITE, Color::BLACK),
+            (Color::rgb(200, 40, 40), Color::rgb(30, 60, 90)),
+        ];
+
+        for smooth in [true, false] {
+            set_smooth_text(smooth);
+            for (fg, bg) in pairs {
+                let cached = render(SAMPLE, font, fg, bg);
+                let uncached = render_uncached(SAMPLE, font, fg, bg);
+                let max_diff = pixels(&cached)
+                    .iter()
+                    .zip(pixels(&uncached).iter())
+                    .flat_map(|(a, b)| a.0.iter().zip(b.0.iter()).map(|(x, y)| x.abs_diff(*y)))
+                    .max()
+                    .unwrap();
+                assert!(max_diff <= 4, "{} levels off, smooth {}", max_diff, smooth);
+            }
+        }
+        set_smooth_text(true);
+    }
+
+    fn rich(parts: &[(&str, &'static Font)]) -> RichText {
+        let mut text = RichText::new();
+        for &(s, font) in parts {
+            text.add_part(s, Color::BLACK, font, None);
+        }
+        text
+    }
+
+    fn line_strs(text: &RichText, lines: &[LineMetrics]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                text.chars[line.start..line.end]
+                    .iter()
+                    .map(|rc| rc.c)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn measure_empty_and_bitmap_strings() {
+        let _lock = text_mode_lock();
+        for font in [get_font("NotoSansMono", 14), get_font("DejaVuSans", 16)] {
+            let metrics = measure_str("", font);
+            assert_eq!(metrics.width, 0);
+            assert_eq!(metrics.ascent, font.base_y as u32);
+            assert_eq!(metrics.ascent + metrics.descent, font.char_h as u32);
+        }
+
+        let mono = get_font("NotoSansMono", 14);
+        assert_eq!(measure_str("abc de", mono).width, 6 * mono.char_w as u32);
+
+        let font = get_font("DejaVuSans", 16);
+        let (w1, w2) = (
+            measure_str("Hello", font).width,
+            measure_str("Hello world", font).width,
+        );
+        assert!(0 < w1 && w1 < w2);
+    }
+
+    #[test]
+    fn break_empty_text() {
+        let mono = get_font("NotoSansMono", 14);
+        assert!(break_lines(&RichText::new(), 100).is_empty());
+        assert_eq!(
+            format_rich_lines(&RichText::new(), 100, TextJustification::Left).h,
+            0
+        );
+
+        // Empty lines between newlines still take their height
+        let text = rich(&[("a\n\nb", mono)]);
+        let lines = break_lines(&text, 100);
+        assert_eq!(line_strs(&text, &lines), ["a\n", "\n", "b"]);
+        assert_eq!(lines[1].width, 0);
+        assert_eq!(lines[1].height(), mono.char_h as u32);
+    }
+
+    #[test]
+    fn break_between_words() {
+        let mono = get_font("NotoSansMono", 14);
+        let cw = mono.char_w as u32;
+
+        let text = rich(&[("the quick brown fox", mono)]);
+        let lines = break_lines(&text, 10 * cw);
+        assert_eq!(line_strs(&text, &lines), ["the quick ", "brown fox"]);
+        // The trailing space hangs past the line without counting in its width
+        assert_eq!(lines[0].width, 9 * cw);
+        assert_eq!(lines[1].width, 9 * cw);
+
+        // Runs of spaces stay at the end of the line rather than wrap on their own
+        let text = rich(&[("ab     cd", mono)]);
+        let lines = break_lines(&text, 3 * cw);
+        assert_eq!(line_strs(&text, &lines), ["ab     ", "cd"]);
+        assert_eq!(lines[0].width, 2 * cw);
+
+        // Exactly fitting
+        let text = rich(&[("abc def", mono)]);
+        assert_eq!(line_strs(&text, &break_lines(&text, 7 * cw)), ["abc def"]);
+        assert_eq!(
+            line_strs(&text, &break_lines(&text, 7 * cw - 1)),
+            ["abc ", "def"]
+        );
+    }
+
+    #[test]
+    fn break_inside_overlong_words() {
+        let mono = get_font("NotoSansMono", 14);
+        let cw = mono.char_w as u32;
+
+        let text = rich(&[("abcdefghij", mono)]);
+        let lines = break_lines(&text, 4 * cw);
+        assert_eq!(line_strs(&text, &lines), ["abcd", "efgh", "ij"]);
+        assert_eq!(
+            lines.iter().map(|l| l.width).collect::<Vec<_>>(),
+            [4 * cw, 4 * cw, 2 * cw]
+        );
+
+        // The long word starts on its own line
+        let text = rich(&[("ab abcdefghij x", mono)]);
+        let lines = break_lines(&text, 4 * cw);
+        assert_eq!(line_strs(&text, &lines), ["ab ", "abcd", "efgh", "ij x"]);
+
+        // At least one character per line, however narrow
+        let text = rich(&[("abc", mono)]);
+        assert_eq!(line_strs(&text, &break_lines(&text, 0)), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn break_mixed_size_spans() {
+        let small = get_font("NotoSansMono", 12);
+        let big = get_font("NotoSansMono", 22);
+        let (sw, bw) = (small.char_w as u32, big.char_w as u32);
+        assert!(sw < bw);
+
+        let text = rich(&[("aa ", small), ("BB", big), (" cc dd", small)]);
+        let lines = break_lines(&text, 6 * sw + 2 * bw);
+        assert_eq!(line_strs(&text, &lines), ["aa BB cc ", "dd"]);
+        assert_eq!(lines[0].width, 6 * sw + 2 * bw);
+        assert_eq!(lines[1].width, 2 * sw);
+
+        // Lines are as tall as their tallest span, aligned on the baseline
+        assert_eq!(lines[0].ascent, big.base_y as u32);
+        assert_eq!(lines[0].height(), big.char_h as u32);
+        assert_eq!(lines[1].ascent, small.base_y as u32);
+        assert_eq!(lines[1].height(), small.char_h as u32);
+
+        // A big span breaks where its own characters no longer fit
+        let text = rich(&[("a", small), ("BBBB", big)]);
+        let lines = break_lines(&text, sw + 2 * bw);
+        assert_eq!(line_strs(&text, &lines), ["aBB", "BB"]);
+
+        let formatted = format_rich_lines(&text, sw + 2 * bw, TextJustification::Right);
+        assert_eq!(formatted.h, 2 * big.char_h as u32);
+        assert_eq!(formatted.lines[1].x_offset, sw);
+    }
 }
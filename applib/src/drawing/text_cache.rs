@@ -0,0 +1,238 @@
+use crate::color_utils::{blend_row_linear, from_linear_fast, to_linear_fast, LINEAR_MAX};
+use crate::content::ContentId;
+use crate::drawing::text::is_smooth_text;
+use crate::{BorrowedMutPixels, Color, FbView, FbViewMut, Framebuffer, OwnedPixels, Rect};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+// For debugging and measuring, runs are then drawn glyph by glyph every time
+const DISABLE_CACHING: bool = false;
+
+const MAX_CACHED_BYTES: usize = 4_000_000;
+
+lazy_static! {
+    static ref TEXT_RUNS: Mutex<TextRunCache> = Mutex::new(TextRunCache::new(MAX_CACHED_BYTES));
+}
+
+// Draws the run with the given ID from the cache, or rasterizes it first with draw. The
+// run covers at most the given size from pos, and draw gets a view of that area.
+// The ID must cover everything that changes the pixels, e.g. the text, font and colors.
+pub fn draw_cached_run<F, S, D>(fb: &mut F, id: ContentId, pos: (i64, i64), size: S, draw: D)
+where
+    F: FbViewMut,
+    S: FnOnce() -> (u32, u32),
+    D: Fn(&mut Framebuffer<BorrowedMutPixels>),
+{
+    if DISABLE_CACHING {
+        let (w, h) = size();
+        let (x0, y0) = pos;
+        draw(&mut fb.subregion_mut(&Rect { x0, y0, w, h }));
+        return;
+    }
+
+    // Smooth runs are blended in linear light, so their colors and alpha are worked out
+    // in linear light too
+    let linear = is_smooth_text();
+    let id = ContentId::from_hash(&(id, linear));
+
+    let cached = TEXT_RUNS.lock().get(id);
+    let run = cached.unwrap_or_else(|| {
+        let (w, h) = size();
+        let run = Arc::new(rasterize_run(w, h, &draw, linear));
+        TEXT_RUNS.lock().insert(id, run.clone());
+        run
+    });
+
+    let (dx, dy) = run.offset;
+    let pos = (pos.0 + dx, pos.1 + dy);
+    match linear {
+        true => blend_linear(fb, &run.fb, pos),
+        false => fb.copy_from_fb(&run.fb, pos, true),
+    }
+}
+
+// Same as copy_from_fb() with blending, in linear light
+fn blend_linear<F: FbViewMut>(dst: &mut F, src: &Framebuffer<OwnedPixels>, pos: (i64, i64)) {
+    let (w, h) = src.shape();
+    for y in 0..h as i64 {
+        let src_line = src.get_line(0, w, y);
+        let dst_line = dst.get_line_mut(pos.0, w, pos.1 + y);
+
+        let x_start = u32::max(src_line.x_data_start, dst_line.x_data_start);
+        let x_end = u32::min(
+            src_line.x_data_start + src_line.data.len() as u32,
+            dst_line.x_data_start + dst_line.data.len() as u32,
+        );
+        if x_end <= x_start {
+            continue;
+        }
+
+        let src_data = &src_line.data[(x_start - src_line.x_data_start) as usize..];
+        let dst_data = &mut dst_line.data[(x_start - dst_line.x_data_start) as usize..];
+        let len = (x_end - x_start) as usize;
+        blend_row_linear(&mut dst_data[..len], &src_data[..len]);
+    }
+}
+
+// Only the part with visible pixels is kept
+struct CachedRun {
+    offset: (i64, i64),
+    fb: Framebuffer<OwnedPixels>,
+}
+
+// Drawing calls blend into what is below them, which does not work on a transparent
+// buffer, so the run is drawn on black and on white and the color and alpha of each
+// pixel are worked out from the difference
+fn rasterize_run<D>(w: u32, h: u32, draw: &D, linear: bool) -> CachedRun
+where
+    D: Fn(&mut Framebuffer<BorrowedMutPixels>),
+{
+    let rect = Rect { x0: 0, y0: 0, w, h };
+    let mut on_black = Framebuffer::new_owned_filled(w, h, Color::BLACK);
+    let mut on_white = Framebuffer::new_owned_filled(w, h, Color::WHITE);
+    draw(&mut on_black.subregion_mut(&rect));
+    draw(&mut on_white.subregion_mut(&rect));
+
+    let mut run_fb = Framebuffer::new_owned(w, h);
+    let pixels = on_black.get_data().iter().zip(on_white.get_data());
+    for (pixel, (black, white)) in run_fb.get_data_mut().iter_mut().zip(pixels) {
+        *pixel = match linear {
+            true => unblend_linear(*black, *white),
+            false => unblend(*black, *white),
+        };
+    }
+
+    let visible = visible_bbox(&run_fb).unwrap_or(Rect {
+        x0: 0,
+        y0: 0,
+        w: 0,
+        h: 0,
+    });
+    let mut fb = Framebuffer::new_owned(visible.w, visible.h);
+    fb.copy_from_fb(&run_fb.subregion(&visible), (0, 0), false);
+
+    CachedRun {
+        offset: (visible.x0, visible.y0),
+        fb,
+    }
+}
+
+fn visible_bbox<F: FbView>(fb: &F) -> Option<Rect> {
+    let (w, h) = fb.shape();
+    let is_visible = |x: u32, y: u32| fb.get_pixel(x as i64, y as i64).unwrap().0[3] > 0;
+
+    let y0 = (0..h).find(|&y| (0..w).any(|x| is_visible(x, y)))?;
+    let y1 = (0..h).rfind(|&y| (0..w).any(|x| is_visible(x, y)))?;
+    let x0 = (0..w).find(|&x| (y0..=y1).any(|y| is_visible(x, y)))?;
+    let x1 = (0..w).rfind(|&x| (y0..=y1).any(|y| is_visible(x, y)))?;
+
+    Some(Rect::from_xyxy([
+        x0 as i64, y0 as i64, x1 as i64, y1 as i64,
+    ]))
+}
+
+// The inverse of blending over black and over white, see blend_colors()
+fn unblend(black: Color, white: Color) -> Color {
+    let (br, bg, bb, _) = black.as_rgba();
+    let (wr, wg, wb, _) = white.as_rgba();
+
+    // Over white, each channel is raised by 255 * (256 - a) / 256
+    let diff = [
+        wr.saturating_sub(br),
+        wg.saturating_sub(bg),
+        wb.saturating_sub(bb),
+    ]
+    .into_iter()
+    .max()
+    .unwrap() as u32;
+    let a = 256 - u32::min(256, (diff * 256 + 127) / 255);
+    if a == 0 {
+        return Color::ZERO;
+    }
+    let a = u32::min(255, a);
+
+    // Over black, each channel is src * (1 + a) / 256
+    let unscale = |v: u8| u32::min(255, (v as u32 * 256 + a / 2) / (1 + a)) as u8;
+    Color::rgba(unscale(br), unscale(bg), unscale(bb), a as u8)
+}
+
+// Same for blend_colors_linear(), where over white each channel is raised by the
+// linear intensity of white times (255 - a) / 255
+fn unblend_linear(black: Color, white: Color) -> Color {
+    let (br, bg, bb, _) = black.as_rgba();
+    let (wr, wg, wb, _) = white.as_rgba();
+    let (br, bg, bb) = (to_linear_fast(br), to_linear_fast(bg), to_linear_fast(bb));
+    let (wr, wg, wb) = (to_linear_fast(wr), to_linear_fast(wg), to_linear_fast(wb));
+
+    let diff = [
+        wr.saturating_sub(br),
+        wg.saturating_sub(bg),
+        wb.saturating_sub(bb),
+    ]
+    .into_iter()
+    .max()
+    .unwrap() as u32;
+    let max = LINEAR_MAX as u32;
+    let a = 255 - u32::min(255, (diff * 255 + max / 2) / max);
+    if a == 0 {
+        return Color::ZERO;
+    }
+
+    let unscale = |l: u16| from_linear_fast(u32::min(max, (l as u32 * 255 + a / 2) / a) as u16);
+    Color::rgba(unscale(br), unscale(bg), unscale(bb), a as u8)
+}
+
+// Evicts the least recently used runs once over max_bytes
+struct TextRunCache {
+    max_bytes: usize,
+    bytes: usize,
+    clock: u64,
+    runs: BTreeMap<ContentId, (Arc<CachedRun>, u64)>,
+    by_last_use: BTreeMap<u64, ContentId>,
+}
+
+impl TextRunCache {
+    fn new(max_bytes: usize) -> Self {
+        TextRunCache {
+            max_bytes,
+            bytes: 0,
+            clock: 0,
+            runs: BTreeMap::new(),
+            by_last_use: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, id: ContentId) -> Option<Arc<CachedRun>> {
+        self.clock += 1;
+        let now = self.clock;
+
+        let (run, last_use) = self.runs.get_mut(&id)?;
+        self.by_last_use.remove(last_use);
+        self.by_last_use.insert(now, id);
+        *last_use = now;
+        Some(run.clone())
+    }
+
+    fn insert(&mut self, id: ContentId, run: Arc<CachedRun>) {
+        self.clock += 1;
+        let now = self.clock;
+
+        self.bytes += run.fb.size_bytes();
+        if let Some((old_run, last_use)) = self.runs.insert(id, (run, now)) {
+            self.bytes -= old_run.fb.size_bytes();
+            self.by_last_use.remove(&last_use);
+        }
+        self.by_last_use.insert(now, id);
+
+        while self.bytes > self.max_bytes {
+            let Some((_, oldest)) = self.by_last_use.pop_first() else {
+                break;
+            };
+            if let Some((old_run, _)) = self.runs.remove(&oldest) {
+                self.bytes -= old_run.fb.size_bytes();
+            }
+        }
+    }
+}
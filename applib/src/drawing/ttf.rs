@@ -0,0 +1,736 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use num::Float;
+
+// Just enough of TrueType to draw the glyphs of a font: character map, metrics, kerning
+// pairs and quadratic outlines. Hinting instructions are ignored.
+
+// Composite glyphs can nest, but never that deep in practice
+const MAX_COMPOSITE_DEPTH: u32 = 8;
+// Curves are split into at most that many lines
+const MAX_CURVE_STEPS: u32 = 16;
+
+// In pixels, y up
+type Point = (f32, f32);
+type Line = (Point, Point);
+
+pub struct TrueTypeFace {
+    data: &'static [u8],
+    pub units_per_em: u16,
+    pub ascent: i16,
+    pub descent: i16,
+    pub line_gap: i16,
+    pub nb_glyphs: u16,
+    // Top of the stroke from the baseline and thickness, if the font has them
+    pub underline: Option<(i16, i16)>,
+    pub strikeout: Option<(i16, i16)>,
+    nb_h_metrics: u16,
+    long_loca: bool,
+    // Table offsets
+    cmap: Cmap,
+    hmtx: usize,
+    loca: usize,
+    glyf: usize,
+    // Start and count of the sorted kerning pairs
+    kern: Option<(usize, usize)>,
+}
+
+enum Cmap {
+    Segments(usize),
+    Groups(usize),
+}
+
+// In pixels, the alpha of each pixel of the glyph box
+pub struct GlyphBitmap {
+    pub w: u32,
+    pub h: u32,
+    // Position of the top left corner, from the pen position on the baseline (y up)
+    pub left: i32,
+    pub top: i32,
+    pub alpha: Vec<u8>,
+}
+
+impl TrueTypeFace {
+    pub fn parse(data: &'static [u8]) -> Option<Self> {
+        let nb_tables = read_u16(data, 4)? as usize;
+        let table = |tag: &[u8; 4]| {
+            (0..nb_tables)
+                .map(|i| 12 + 16 * i)
+                .find(|&rec| data.get(rec..rec + 4) == Some(tag.as_slice()))
+                .and_then(|rec| read_u32(data, rec + 8))
+                .map(|offset| offset as usize)
+        };
+
+        let head = table(b"head")?;
+        let hhea = table(b"hhea")?;
+        let maxp = table(b"maxp")?;
+        let cmap = table(b"cmap")?;
+
+        Some(TrueTypeFace {
+            data,
+            units_per_em: read_u16(data, head + 18)?,
+            long_loca: read_u16(data, head + 50)? == 1,
+            ascent: read_i16(data, hhea + 4)?,
+            descent: read_i16(data, hhea + 6)?,
+            line_gap: read_i16(data, hhea + 8)?,
+            nb_h_metrics: read_u16(data, hhea + 34)?,
+            nb_glyphs: read_u16(data, maxp + 4)?,
+            underline: table(b"post")
+                .and_then(|post| Some((read_i16(data, post + 8)?, read_i16(data, post + 10)?))),
+            strikeout: table(b"OS/2")
+                .and_then(|os2| Some((read_i16(data, os2 + 28)?, read_i16(data, os2 + 26)?))),
+            cmap: find_cmap(data, cmap)?,
+            hmtx: table(b"hmtx")?,
+            loca: table(b"loca")?,
+            glyf: table(b"glyf")?,
+            kern: table(b"kern").and_then(|kern| find_kern_pairs(data, kern)),
+        })
+    }
+
+    // None for characters missing from the font
+    pub fn glyph_id(&self, c: char) -> Option<u16> {
+        let data = self.data;
+        let c = c as u32;
+        let id = match self.cmap {
+            Cmap::Segments(table) => {
+                if c > 0xffff {
+                    return None;
+                }
+                let nb_segments = read_u16(data, table + 6)? as usize / 2;
+                let ends = table + 14;
+                let starts = ends + 2 * nb_segments + 2;
+                let deltas = starts + 2 * nb_segments;
+                let range_offsets = deltas + 2 * nb_segments;
+
+                // The end codes are sorted
+                let (mut lo, mut hi) = (0, nb_segments);
+                while lo < hi {
+                    let mid = (lo + hi) / 2;
+                    match (read_u16(data, ends + 2 * mid)? as u32) < c {
+                        true => lo = mid + 1,
+                        false => hi = mid,
+                    }
+                }
+                let i = lo;
+                if i == nb_segments || (read_u16(data, starts + 2 * i)? as u32) > c {
+                    return None;
+                }
+
+                let start = read_u16(data, starts + 2 * i)? as u32;
+                let delta = read_u16(data, deltas + 2 * i)?;
+                let range_offset = read_u16(data, range_offsets + 2 * i)? as usize;
+                match range_offset {
+                    0 => (c as u16).wrapping_add(delta),
+                    _ => {
+                        let offset =
+                            range_offsets + 2 * i + range_offset + 2 * (c - start) as usize;
+                        match read_u16(data, offset)? {
+                            0 => 0,
+                            id => id.wrapping_add(delta),
+                        }
+                    }
+                }
+            }
+            Cmap::Groups(table) => {
+                let nb_groups = read_u32(data, table + 12)? as usize;
+                let (mut lo, mut hi) = (0, nb_groups);
+                let mut id = 0;
+                while lo < hi {
+                    let mid = (lo + hi) / 2;
+                    let group = table + 16 + 12 * mid;
+                    let start = read_u32(data, group)?;
+                    let end = read_u32(data, group + 4)?;
+                    if c < start {
+                        hi = mid;
+                    } else if c > end {
+                        lo = mid + 1;
+                    } else {
+                        id = (read_u32(data, group + 8)? + c - start) as u16;
+                        break;
+                    }
+                }
+                id
+            }
+        };
+
+        match id {
+            0 => None,
+            id => Some(id),
+        }
+    }
+
+    // In font units
+    pub fn advance(&self, glyph: u16) -> u16 {
+        let i = u16::min(glyph, self.nb_h_metrics - 1) as usize;
+        read_u16(self.data, self.hmtx + 4 * i).unwrap_or(0)
+    }
+
+    // In font units, to add to the advance of the left glyph
+    pub fn kerning(&self, left: u16, right: u16) -> i16 {
+        let Some((pairs, nb_pairs)) = self.kern else {
+            return 0;
+        };
+        let key = ((left as u32) << 16) | right as u32;
+
+        let (mut lo, mut hi) = (0, nb_pairs);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let pair = pairs + 6 * mid;
+            match read_u32(self.data, pair) {
+                Some(k) if k < key => lo = mid + 1,
+                Some(k) if k > key => hi = mid,
+                Some(_) => return read_i16(self.data, pair + 4).unwrap_or(0),
+                None => return 0,
+            }
+        }
+        0
+    }
+
+    // Antialiased, None for glyphs without an outline (e.g. spaces). x_shift moves the
+    // glyph right by a fraction of a pixel.
+    pub fn rasterize(&self, glyph: u16, scale: f32, x_shift: f32) -> Option<GlyphBitmap> {
+        let mut lines = Vec::new();
+        self.glyph_outline(glyph, &Transform::scale(scale), 0, &mut lines)?;
+        if lines.is_empty() {
+            return None;
+        }
+        for (p0, p1) in lines.iter_mut() {
+            p0.0 += x_shift;
+            p1.0 += x_shift;
+        }
+
+        let (mut x_min, mut y_min) = (f32::MAX, f32::MAX);
+        let (mut x_max, mut y_max) = (f32::MIN, f32::MIN);
+        for &(p0, _) in lines.iter() {
+            x_min = x_min.min(p0.0);
+            x_max = x_max.max(p0.0);
+            y_min = y_min.min(p0.1);
+            y_max = y_max.max(p0.1);
+        }
+
+        let left = x_min.floor() as i32;
+        let top = y_max.ceil() as i32;
+        let w = (x_max.ceil() as i32 - left) as u32 + 1;
+        let h = (top - y_min.floor() as i32) as u32 + 1;
+
+        // Flipped so that y goes down, like in framebuffers
+        let mut raster = Raster::new(w, h);
+        for &(p0, p1) in lines.iter() {
+            let to_px = |(x, y): Point| (x - left as f32, top as f32 - y);
+            raster.draw_line(to_px(p0), to_px(p1));
+        }
+
+        Some(GlyphBitmap {
+            w,
+            h,
+            left,
+            top,
+            alpha: raster.into_alpha(),
+        })
+    }
+
+    fn glyph_range(&self, glyph: u16) -> Option<(usize, usize)> {
+        let i = glyph as usize;
+        let (start, end) = match self.long_loca {
+            true => (
+                read_u32(self.data, self.loca + 4 * i)? as usize,
+                read_u32(self.data, self.loca + 4 * i + 4)? as usize,
+            ),
+            false => (
+                2 * read_u16(self.data, self.loca + 2 * i)? as usize,
+                2 * read_u16(self.data, self.loca + 2 * i + 2)? as usize,
+            ),
+        };
+        Some((self.glyf + start, self.glyf + end))
+    }
+
+    // Appends the outline as lines, in scaled units with y up
+    fn glyph_outline(
+        &self,
+        glyph: u16,
+        transform: &Transform,
+        depth: u32,
+        lines: &mut Vec<Line>,
+    ) -> Option<()> {
+        let (start, end) = self.glyph_range(glyph)?;
+        if start == end {
+            return Some(());
+        }
+
+        let data = self.data;
+        let nb_contours = read_i16(data, start)?;
+        match nb_contours >= 0 {
+            true => self.simple_outline(start, nb_contours as usize, transform, lines),
+            false if depth < MAX_COMPOSITE_DEPTH => {
+                self.composite_outline(start, transform, depth, lines)
+            }
+            false => None,
+        }
+    }
+
+    fn simple_outline(
+        &self,
+        start: usize,
+        nb_contours: usize,
+        transform: &Transform,
+        lines: &mut Vec<Line>,
+    ) -> Option<()> {
+        let data = self.data;
+        let mut ends = Vec::with_capacity(nb_contours);
+        for i in 0..nb_contours {
+            ends.push(read_u16(data, start + 10 + 2 * i)? as usize);
+        }
+        let nb_points = ends.last().map(|end| end + 1).unwrap_or(0);
+
+        let instructions_len = read_u16(data, start + 10 + 2 * nb_contours)? as usize;
+        let mut offset = start + 12 + 2 * nb_contours + instructions_len;
+
+        const ON_CURVE: u8 = 0x01;
+        const X_SHORT: u8 = 0x02;
+        const Y_SHORT: u8 = 0x04;
+        const REPEAT: u8 = 0x08;
+        const X_SAME_OR_POSITIVE: u8 = 0x10;
+        const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+        let mut flags = Vec::with_capacity(nb_points);
+        while flags.len() < nb_points {
+            let flag = *data.get(offset)?;
+            offset += 1;
+            flags.push(flag);
+            if flag & REPEAT != 0 {
+                let count = *data.get(offset)?;
+                offset += 1;
+                for _ in 0..count {
+                    flags.push(flag);
+                }
+            }
+        }
+        flags.truncate(nb_points);
+
+        // Coordinates are deltas from the previous point
+        let mut read_coords = |short: u8, same_or_positive: u8| -> Option<Vec<i32>> {
+            let mut value = 0i32;
+            let mut coords = Vec::with_capacity(nb_points);
+            for &flag in flags.iter() {
+                if flag & short != 0 {
+                    let delta = *data.get(offset)? as i32;
+                    offset += 1;
+                    value += match flag & same_or_positive != 0 {
+                        true => delta,
+                        false => -delta,
+                    };
+                } else if flag & same_or_positive == 0 {
+                    value += read_i16(data, offset)? as i32;
+                    offset += 2;
+                }
+                coords.push(value);
+            }
+            Some(coords)
+        };
+        let xs = read_coords(X_SHORT, X_SAME_OR_POSITIVE)?;
+        let ys = read_coords(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+
+        let mut contour_start = 0;
+        for &contour_end in ends.iter() {
+            if contour_end < contour_start || contour_end >= nb_points {
+                return None;
+            }
+            let points: Vec<(Point, bool)> = (contour_start..=contour_end)
+                .map(|i| {
+                    let p = transform.apply(xs[i] as f32, ys[i] as f32);
+                    (p, flags[i] & ON_CURVE != 0)
+                })
+                .collect();
+            contour_lines(&points, lines);
+            contour_start = contour_end + 1;
+        }
+
+        Some(())
+    }
+
+    fn composite_outline(
+        &self,
+        start: usize,
+        transform: &Transform,
+        depth: u32,
+        lines: &mut Vec<Line>,
+    ) -> Option<()> {
+        const ARGS_ARE_WORDS: u16 = 0x0001;
+        const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+        const HAS_SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+        const HAS_XY_SCALE: u16 = 0x0040;
+        const HAS_2X2: u16 = 0x0080;
+
+        let data = self.data;
+        let mut offset = start + 10;
+        loop {
+            let flags = read_u16(data, offset)?;
+            let component = read_u16(data, offset + 2)?;
+            offset += 4;
+
+            let (dx, dy) = match flags & ARGS_ARE_WORDS != 0 {
+                true => {
+                    offset += 4;
+                    (read_i16(data, offset - 4)?, read_i16(data, offset - 2)?)
+                }
+                false => {
+                    offset += 2;
+                    (
+                        *data.get(offset - 2)? as i8 as i16,
+                        *data.get(offset - 1)? as i8 as i16,
+                    )
+                }
+            };
+            // Components placed by matching points are rare, they are left unmoved
+            let (dx, dy) = match flags & ARGS_ARE_XY_VALUES != 0 {
+                true => (dx as f32, dy as f32),
+                false => (0.0, 0.0),
+            };
+
+            let f2dot14 = |offset: usize| read_i16(data, offset).map(|v| v as f32 / 16384.0);
+            let [a, b, c, d] = if flags & HAS_SCALE != 0 {
+                offset += 2;
+                let s = f2dot14(offset - 2)?;
+                [s, 0.0, 0.0, s]
+            } else if flags & HAS_XY_SCALE != 0 {
+                offset += 4;
+                [f2dot14(offset - 4)?, 0.0, 0.0, f2dot14(offset - 2)?]
+            } else if flags & HAS_2X2 != 0 {
+                offset += 8;
+                [
+                    f2dot14(offset - 8)?,
+                    f2dot14(offset - 6)?,
+                    f2dot14(offset - 4)?,
+                    f2dot14(offset - 2)?,
+                ]
+            } else {
+                [1.0, 0.0, 0.0, 1.0]
+            };
+
+            let local = Transform {
+                m: [a, b, c, d],
+                t: (dx, dy),
+            };
+            self.glyph_outline(component, &transform.then(&local), depth + 1, lines)?;
+
+            if flags & MORE_COMPONENTS == 0 {
+                return Some(());
+            }
+        }
+    }
+}
+
+// The preferred Unicode subtable, full repertoire first
+fn find_cmap(data: &[u8], cmap: usize) -> Option<Cmap> {
+    let nb_subtables = read_u16(data, cmap + 2)? as usize;
+    let mut segments = None;
+    for i in 0..nb_subtables {
+        let record = cmap + 4 + 8 * i;
+        let platform = read_u16(data, record)?;
+        let encoding = read_u16(data, record + 2)?;
+        let table = cmap + read_u32(data, record + 4)? as usize;
+        let unicode = platform == 0 || (platform == 3 && matches!(encoding, 1 | 10));
+        if !unicode {
+            continue;
+        }
+        match read_u16(data, table)? {
+            12 => return Some(Cmap::Groups(table)),
+            4 => segments = Some(Cmap::Segments(table)),
+            _ => (),
+        }
+    }
+    segments
+}
+
+// Only the first horizontal subtable, in format 0 (a sorted list of pairs)
+fn find_kern_pairs(data: &[u8], kern: usize) -> Option<(usize, usize)> {
+    if read_u16(data, kern)? != 0 || read_u16(data, kern + 2)? == 0 {
+        return None;
+    }
+    let subtable = kern + 4;
+    let coverage = read_u16(data, subtable + 4)?;
+    let horizontal_format_0 = coverage & 0x1 != 0 && coverage >> 8 == 0;
+    match horizontal_format_0 {
+        true => Some((subtable + 14, read_u16(data, subtable + 6)? as usize)),
+        false => None,
+    }
+}
+
+// Quadratic contour to lines. Two off-curve points in a row have an implied on-curve
+// point between them.
+fn contour_lines(points: &[(Point, bool)], lines: &mut Vec<Line>) {
+    let n = points.len();
+    if n < 2 {
+        return;
+    }
+    let mid = |a: Point, b: Point| ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+
+    // Starting from an on-curve point, or the implied one before the first point
+    let first_on = points.iter().position(|(_, on)| *on);
+    let (start, skip) = match first_on {
+        Some(i) => (points[i].0, i),
+        None => (mid(points[n - 1].0, points[0].0), 0),
+    };
+
+    let mut current = start;
+    let mut control: Option<Point> = None;
+    for k in 1..=n {
+        let (p, on) = points[(skip + k) % n];
+        let p = match (k == n, first_on) {
+            (true, None) => start,
+            _ => p,
+        };
+        match (on || (k == n && first_on.is_none()), control) {
+            (true, None) => {
+                lines.push((current, p));
+                current = p;
+            }
+            (true, Some(c)) => {
+                push_curve(current, c, p, lines);
+                current = p;
+                control = None;
+            }
+            (false, None) => control = Some(p),
+            (false, Some(c)) => {
+                let implied = mid(c, p);
+                push_curve(current, c, implied, lines);
+                current = implied;
+                control = Some(p);
+            }
+        }
+    }
+    if let Some(c) = control {
+        push_curve(current, c, start, lines);
+    }
+}
+
+fn push_curve(p0: Point, p1: Point, p2: Point, lines: &mut Vec<Line>) {
+    // Enough steps to stay within a fraction of a pixel of the curve
+    let dx = p0.0 - 2.0 * p1.0 + p2.0;
+    let dy = p0.1 - 2.0 * p1.1 + p2.1;
+    let deviation = (dx * dx + dy * dy).sqrt();
+    let steps = ((deviation * 2.0).sqrt().ceil() as u32).clamp(1, MAX_CURVE_STEPS);
+
+    let mut prev = p0;
+    for i in 1..=steps {
+        let t = i as f32 / steps as f32;
+        let u = 1.0 - t;
+        let p = (
+            u * u * p0.0 + 2.0 * u * t * p1.0 + t * t * p2.0,
+            u * u * p0.1 + 2.0 * u * t * p1.1 + t * t * p2.1,
+        );
+        lines.push((prev, p));
+        prev = p;
+    }
+}
+
+struct Transform {
+    m: [f32; 4],
+    t: Point,
+}
+
+impl Transform {
+    fn scale(s: f32) -> Self {
+        Transform {
+            m: [s, 0.0, 0.0, s],
+            t: (0.0, 0.0),
+        }
+    }
+
+    fn apply(&self, x: f32, y: f32) -> Point {
+        let [a, b, c, d] = self.m;
+        (a * x + c * y + self.t.0, b * x + d * y + self.t.1)
+    }
+
+    // Applies other first, then self
+    fn then(&self, other: &Transform) -> Transform {
+        let [a, b, c, d] = self.m;
+        let [oa, ob, oc, od] = other.m;
+        Transform {
+            m: [
+                a * oa + c * ob,
+                b * oa + d * ob,
+                a * oc + c * od,
+                b * oc + d * od,
+            ],
+            t: self.apply(other.t.0, other.t.1),
+        }
+    }
+}
+
+// Signed area coverage: each line adds the area it covers to the right of it in each
+// pixel, and the coverage of a pixel is the running sum along its row
+struct Raster {
+    w: u32,
+    h: u32,
+    acc: Vec<f32>,
+}
+
+impl Raster {
+    fn new(w: u32, h: u32) -> Self {
+        Raster {
+            w,
+            h,
+            // One more column for the lines along the right edge
+            acc: vec![0.0; ((w + 1) * h) as usize],
+        }
+    }
+
+    fn draw_line(&mut self, p0: Point, p1: Point) {
+        if p0.1 == p1.1 {
+            return;
+        }
+        let (dir, p0, p1) = match p0.1 < p1.1 {
+            true => (1.0, p0, p1),
+            false => (-1.0, p1, p0),
+        };
+        let row_w = (self.w + 1) as usize;
+        let dxdy = (p1.0 - p0.0) / (p1.1 - p0.1);
+
+        let y_start = p0.1.max(0.0).floor() as u32;
+        let y_end = u32::min(self.h, p1.1.ceil() as u32);
+        let mut x = p0.0 + (y_start as f32).max(p0.1).sub_positive(p0.1) * dxdy;
+
+        for y in y_start..y_end {
+            let dy = ((y + 1) as f32).min(p1.1) - (y as f32).max(p0.1);
+            let x_next = x + dxdy * dy;
+            let d = dy * dir;
+
+            let (xa, xb) = match x < x_next {
+                true => (x, x_next),
+                false => (x_next, x),
+            };
+            let xa = xa.clamp(0.0, self.w as f32);
+            let xb = xb.clamp(0.0, self.w as f32);
+
+            let row = &mut self.acc[y as usize * row_w..(y as usize + 1) * row_w];
+            let ia = xa.floor() as usize;
+            let ib = xb.ceil() as usize;
+
+            if ib <= ia + 1 {
+                // Within one pixel: split by the mean x
+                let xm = 0.5 * (xa + xb) - ia as f32;
+                row[ia] += d * (1.0 - xm);
+                if ia + 1 < row_w {
+                    row[ia + 1] += d * xm;
+                }
+            } else {
+                // Across several pixels: the covered area grows linearly in between
+                let s = 1.0 / (xb - xa);
+                let fa = xa - ia as f32;
+                let a0 = 0.5 * s * (1.0 - fa) * (1.0 - fa);
+                let fb = xb - ib as f32 + 1.0;
+                let am = 0.5 * s * fb * fb;
+
+                row[ia] += d * a0;
+                if ib == ia + 2 {
+                    row[ia + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - fa);
+                    row[ia + 1] += d * (a1 - a0);
+                    for cell in row[ia + 2..ib - 1].iter_mut() {
+                        *cell += d * s;
+                    }
+                    let a2 = a1 + (ib - ia - 3) as f32 * s;
+                    row[ib - 1] += d * (1.0 - a2 - am);
+                }
+                if ib < row_w {
+                    row[ib] += d * am;
+                }
+            }
+
+            x = x_next;
+        }
+    }
+
+    fn into_alpha(self) -> Vec<u8> {
+        let row_w = (self.w + 1) as usize;
+        let mut alpha = Vec::with_capacity((self.w * self.h) as usize);
+        for row in self.acc.chunks_exact(row_w) {
+            let mut sum = 0.0;
+            for &v in row[..self.w as usize].iter() {
+                sum += v;
+                alpha.push((sum.abs().min(1.0) * 255.0).round() as u8);
+            }
+        }
+        alpha
+    }
+}
+
+trait SubPositive {
+    fn sub_positive(self, other: f32) -> f32;
+}
+
+impl SubPositive for f32 {
+    fn sub_positive(self, other: f32) -> f32 {
+        (self - other).max(0.0)
+    }
+}
+
+// Family name, character, size and subpixel offset
+pub type GlyphKey = (&'static str, char, u32, u8);
+
+// Rasterized glyphs, the least recently used ones are evicted first. Glyphs without an
+// outline are cached too, as None.
+pub struct GlyphCache {
+    max_glyphs: usize,
+    clock: u64,
+    glyphs: BTreeMap<GlyphKey, (Option<Arc<GlyphBitmap>>, u64)>,
+    by_last_use: BTreeMap<u64, GlyphKey>,
+}
+
+impl GlyphCache {
+    pub fn new(max_glyphs: usize) -> Self {
+        GlyphCache {
+            max_glyphs,
+            clock: 0,
+            glyphs: BTreeMap::new(),
+            by_last_use: BTreeMap::new(),
+        }
+    }
+
+    pub fn get_or_rasterize(
+        &mut self,
+        key: GlyphKey,
+        rasterize: impl FnOnce() -> Option<GlyphBitmap>,
+    ) -> Option<Arc<GlyphBitmap>> {
+        self.clock += 1;
+        let now = self.clock;
+
+        if let Some((glyph, last_use)) = self.glyphs.get_mut(&key) {
+            self.by_last_use.remove(last_use);
+            self.by_last_use.insert(now, key);
+            *last_use = now;
+            return glyph.clone();
+        }
+
+        while self.glyphs.len() >= self.max_glyphs {
+            let Some((_, oldest)) = self.by_last_use.pop_first() else {
+                break;
+            };
+            self.glyphs.remove(&oldest);
+        }
+
+        let glyph = rasterize().map(Arc::new);
+        self.glyphs.insert(key, (glyph.clone(), now));
+        self.by_last_use.insert(now, key);
+        glyph
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    read_u16(data, offset).map(|v| v as i16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
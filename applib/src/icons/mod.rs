@@ -0,0 +1,200 @@
+use crate::{Color, FbView, FbViewMut, Framebuffer, OwnedPixels, Rect, ScaleFilter};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+mod stock;
+
+// Height and width of the stock icons, in pixels
+pub const STOCK_ICON_SIZE: u32 = 16;
+
+const ATLAS_W: u32 = 256;
+const MAX_CACHED: usize = 128;
+
+lazy_static! {
+    static ref ATLAS: Mutex<IconAtlas> = Mutex::new(IconAtlas::with_stock_icons());
+}
+
+// Scaled to the given height, keeping the aspect ratio. Shrunk icons are filtered,
+// enlarged ones keep sharp pixels.
+pub fn get(name: &str, size: u32) -> Option<Framebuffer<OwnedPixels>> {
+    ATLAS.lock().get(name, size, None, None)
+}
+
+pub fn get_filtered(
+    name: &str,
+    size: u32,
+    filter: ScaleFilter,
+) -> Option<Framebuffer<OwnedPixels>> {
+    ATLAS.lock().get(name, size, Some(filter), None)
+}
+
+// Tintable icons (all the stock ones) are drawn with that color, the others are
+// returned as they are
+pub fn get_tinted(name: &str, size: u32, color: Color) -> Option<Framebuffer<OwnedPixels>> {
+    ATLAS.lock().get(name, size, None, Some(color))
+}
+
+// Adds an icon to the atlas, or replaces the one with that name. Tintable icons only
+// keep their alpha channel when tinted.
+pub fn register<F: FbView>(name: &str, icon: &F, tintable: bool) {
+    ATLAS.lock().insert(name, icon, tintable);
+}
+
+// At the size the icon was registered with
+pub fn get_unscaled(name: &str, tint: Option<Color>) -> Option<Framebuffer<OwnedPixels>> {
+    let mut atlas = ATLAS.lock();
+    let (_, h) = atlas.entries.get(name)?.rect.shape();
+    atlas.get(name, h, None, tint)
+}
+
+pub fn exists(name: &str) -> bool {
+    ATLAS.lock().entries.contains_key(name)
+}
+
+struct AtlasEntry {
+    rect: Rect,
+    tintable: bool,
+}
+
+// Name, height, filter and tint
+type CacheKey = (String, u32, ScaleFilter, Option<[u8; 4]>);
+
+// All the icons in one framebuffer, packed in rows
+struct IconAtlas {
+    fb: Framebuffer<OwnedPixels>,
+    entries: BTreeMap<String, AtlasEntry>,
+    row_x: u32,
+    row_y: u32,
+    row_h: u32,
+    cache: BTreeMap<CacheKey, Framebuffer<OwnedPixels>>,
+}
+
+impl IconAtlas {
+    fn with_stock_icons() -> Self {
+        let mut atlas = IconAtlas {
+            fb: Framebuffer::new_owned(ATLAS_W, STOCK_ICON_SIZE),
+            entries: BTreeMap::new(),
+            row_x: 0,
+            row_y: 0,
+            row_h: 0,
+            cache: BTreeMap::new(),
+        };
+
+        let mut icon_fb = Framebuffer::new_owned(STOCK_ICON_SIZE, STOCK_ICON_SIZE);
+        for (name, rows) in stock::STOCK_ICONS {
+            for (y, row) in rows.iter().enumerate() {
+                for (x, c) in row.chars().enumerate() {
+                    let color = match c {
+                        '#' => Color::WHITE,
+                        _ => Color::ZERO,
+                    };
+                    icon_fb.set_pixel(x as i64, y as i64, color);
+                }
+            }
+            atlas.insert(name, &icon_fb, true);
+        }
+
+        atlas
+    }
+
+    fn insert<F: FbView>(&mut self, name: &str, icon: &F, tintable: bool) {
+        let (w, h) = icon.shape();
+        let rect = self.allocate(w, h);
+        self.fb.copy_from_fb(icon, (rect.x0, rect.y0), false);
+
+        // The previous icon with that name is left unused in the atlas
+        self.entries
+            .insert(name.to_string(), AtlasEntry { rect, tintable });
+        self.cache
+            .retain(|(cached_name, ..), _| cached_name != name);
+    }
+
+    fn allocate(&mut self, w: u32, h: u32) -> Rect {
+        let (atlas_w, atlas_h) = self.fb.shape();
+
+        if self.row_x + w > atlas_w {
+            self.row_x = 0;
+            self.row_y += self.row_h;
+            self.row_h = 0;
+        }
+
+        // Growing the atlas, which only happens while apps register their icons
+        let needed_w = u32::max(atlas_w, w);
+        let needed_h = self.row_y + h;
+        if needed_w > atlas_w || needed_h > atlas_h {
+            let new_h = u32::max(needed_h, 2 * atlas_h);
+            let mut new_fb = Framebuffer::new_owned(needed_w, new_h);
+            new_fb.copy_from_fb(&self.fb, (0, 0), false);
+            self.fb = new_fb;
+        }
+
+        let rect = Rect {
+            x0: self.row_x as i64,
+            y0: self.row_y as i64,
+            w,
+            h,
+        };
+        self.row_x += w;
+        self.row_h = u32::max(self.row_h, h);
+
+        rect
+    }
+
+    fn get(
+        &mut self,
+        name: &str,
+        size: u32,
+        filter: Option<ScaleFilter>,
+        tint: Option<Color>,
+    ) -> Option<Framebuffer<OwnedPixels>> {
+        let entry = self.entries.get(name)?;
+        let (src_w, src_h) = entry.rect.shape();
+        if size == 0 || src_h == 0 {
+            return None;
+        }
+
+        let filter = filter.unwrap_or(match size < src_h {
+            true => ScaleFilter::Bilinear,
+            false => ScaleFilter::Nearest,
+        });
+        let tint = tint.filter(|_| entry.tintable);
+        let key = (name.to_string(), size, filter, tint.map(|color| color.0));
+
+        if !self.cache.contains_key(&key) {
+            let w = u32::max(1, src_w * size / src_h);
+            let src = self.fb.subregion(&entry.rect);
+            let mut icon_fb = scale(&src, w, size, filter);
+
+            if let Some(color) = tint {
+                let (r, g, b, a) = color.as_rgba();
+                for pixel in icon_fb.get_data_mut().iter_mut() {
+                    let alpha = (pixel.0[3] as u32 * a as u32 / 255) as u8;
+                    *pixel = Color::rgba(r, g, b, alpha);
+                }
+            }
+
+            if self.cache.len() >= MAX_CACHED {
+                self.cache.clear();
+            }
+            self.cache.insert(key.clone(), icon_fb);
+        }
+
+        self.cache.get(&key).map(copy_fb)
+    }
+}
+
+fn scale<F: FbView>(src: &F, w: u32, h: u32, filter: ScaleFilter) -> Framebuffer<OwnedPixels> {
+    let mut dst = Framebuffer::new_owned(w, h);
+    let (src_rect, dst_rect) = (src.shape_as_rect(), dst.shape_as_rect());
+    dst.copy_from_fb_scaled(src, &src_rect, &dst_rect, filter, false);
+    dst
+}
+
+fn copy_fb<F: FbView>(src: &F) -> Framebuffer<OwnedPixels> {
+    let (w, h) = src.shape();
+    let mut dst = Framebuffer::new_owned(w, h);
+    dst.copy_from_fb(src, (0, 0), false);
+    dst
+}
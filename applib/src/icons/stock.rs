@@ -0,0 +1,277 @@
+// Drawn with '#' for opaque white pixels and '.' for transparent ones, so that
+// they can be tinted with any color
+pub(super) const STOCK_ICONS: &[(&str, [&str; 16])] = &[
+    (
+        "close",
+        [
+            "................",
+            "................",
+            "..##........##..",
+            "..###......###..",
+            "...###....###...",
+            "....###..###....",
+            ".....######.....",
+            "......####......",
+            "......####......",
+            ".....######.....",
+            "....###..###....",
+            "...###....###...",
+            "..###......###..",
+            "..##........##..",
+            "................",
+            "................",
+        ],
+    ),
+    (
+        "arrow_left",
+        [
+            "................",
+            "................",
+            ".......#........",
+            "......##........",
+            ".....###........",
+            "....####........",
+            "...###########..",
+            "..############..",
+            "..############..",
+            "...###########..",
+            "....####........",
+            ".....###........",
+            "......##........",
+            ".......#........",
+            "................",
+            "................",
+        ],
+    ),
+    (
+        "arrow_right",
+        [
+            "................",
+            "................",
+            "........#.......",
+            "........##......",
+            "........###.....",
+            "........####....",
+            "..###########...",
+            "..############..",
+            "..############..",
+            "..###########...",
+            "........####....",
+            "........###.....",
+            "........##......",
+            "........#.......",
+            "................",
+            "................",
+        ],
+    ),
+    (
+        "arrow_up",
+        [
+            "................",
+            "................",
+            ".......##.......",
+            "......####......",
+            ".....######.....",
+            "....########....",
+            "...##########...",
+            "..############..",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "................",
+            "................",
+        ],
+    ),
+    (
+        "arrow_down",
+        [
+            "................",
+            "................",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "..############..",
+            "...##########...",
+            "....########....",
+            ".....######.....",
+            "......####......",
+            ".......##.......",
+            "................",
+            "................",
+        ],
+    ),
+    (
+        "folder",
+        [
+            "................",
+            "................",
+            ".#####..........",
+            ".#...##.........",
+            ".#....#########.",
+            ".#............#.",
+            ".#............#.",
+            ".#............#.",
+            ".#............#.",
+            ".#............#.",
+            ".#............#.",
+            ".#............#.",
+            ".##############.",
+            "................",
+            "................",
+            "................",
+        ],
+    ),
+    (
+        "file",
+        [
+            "................",
+            "...#######......",
+            "...#.....##.....",
+            "...#.....#.#....",
+            "...#.....#..#...",
+            "...#.....####...",
+            "...#........#...",
+            "...#........#...",
+            "...#........#...",
+            "...#........#...",
+            "...#........#...",
+            "...#........#...",
+            "...#........#...",
+            "...#........#...",
+            "...##########...",
+            "................",
+        ],
+    ),
+    (
+        "search",
+        [
+            "................",
+            "....#####.......",
+            "...#######......",
+            "..###...###.....",
+            ".###.....###....",
+            ".##.......##....",
+            ".##.......##....",
+            ".##.......##....",
+            ".###.....###....",
+            "..###...###.....",
+            "...#########....",
+            "....#####.###...",
+            "...........###..",
+            "............###.",
+            ".............##.",
+            "................",
+        ],
+    ),
+    (
+        "settings",
+        [
+            "................",
+            "......####......",
+            "..##..####..##..",
+            "..############..",
+            "...##########...",
+            "..####....####..",
+            ".####......####.",
+            ".####......####.",
+            ".####......####.",
+            ".####......####.",
+            "..####....####..",
+            "...##########...",
+            "..############..",
+            "..##..####..##..",
+            "......####......",
+            "................",
+        ],
+    ),
+    (
+        "star",
+        [
+            "................",
+            ".......##.......",
+            ".......##.......",
+            "......####......",
+            "......####......",
+            ".##############.",
+            "..############..",
+            "...##########...",
+            "....########....",
+            "....########....",
+            "...####..####...",
+            "...###....###...",
+            "..###......###..",
+            "..##........##..",
+            "................",
+            "................",
+        ],
+    ),
+    (
+        "align_left",
+        [
+            "................",
+            ".##############.",
+            ".##############.",
+            "................",
+            "................",
+            ".#########......",
+            ".#########......",
+            "................",
+            "................",
+            ".##############.",
+            ".##############.",
+            "................",
+            "................",
+            ".#########......",
+            ".#########......",
+            "................",
+        ],
+    ),
+    (
+        "align_center",
+        [
+            "................",
+            ".##############.",
+            ".##############.",
+            "................",
+            "................",
+            "....########....",
+            "....########....",
+            "................",
+            "................",
+            ".##############.",
+            ".##############.",
+            "................",
+            "................",
+            "....########....",
+            "....########....",
+            "................",
+        ],
+    ),
+    (
+        "align_right",
+        [
+            "................",
+            ".##############.",
+            ".##############.",
+            "................",
+            "................",
+            "......#########.",
+            "......#########.",
+            "................",
+            "................",
+            ".##############.",
+            ".##############.",
+            "................",
+            "................",
+            "......#########.",
+            "......#########.",
+            "................",
+        ],
+    ),
+];
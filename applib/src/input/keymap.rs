@@ -8,6 +8,7 @@ pub enum EventType {
     EV_SYN = 0x0,
     EV_KEY = 0x1,
     EV_REL = 0x2,
+    EV_ABS = 0x3,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, enumn::N)]
@@ -16,6 +17,7 @@ pub enum EventType {
 pub enum Keycode {
     BTN_MOUSE_LEFT = 272,
     BTN_MOUSE_RIGHT = 273,
+    BTN_MOUSE_MIDDLE = 274,
     BTN_GEAR_DOWN = 336,
     BTN_GEAR_UP = 337,
 
@@ -37,6 +39,7 @@ pub enum Keycode {
     KEY_BACKSLASH = 43,
     KEY_SEMICOLON = 39,
     KEY_APOSTROPHE = 40,
+    KEY_GRAVE = 41,
     KEY_COMMA = 51,
     KEY_DOT = 52,
     KEY_SLASH = 53,
@@ -68,12 +71,41 @@ pub enum Keycode {
     KEY_N = 49,
     KEY_M = 50,
 
+    KEY_ESC = 1,
     KEY_BACKSPACE = 14,
+    KEY_TAB = 15,
     KEY_ENTER = 28,
     KEY_LEFTSHIFT = 42,
     KEY_RIGHTSHIFT = 54,
+    KEY_LEFTCTRL = 29,
+    KEY_RIGHTCTRL = 97,
+    KEY_LEFTALT = 56,
+    KEY_RIGHTALT = 100,
+    KEY_LEFTMETA = 125,
+    KEY_RIGHTMETA = 126,
+    KEY_CAPSLOCK = 58,
     KEY_SPACE = 57,
 
+    KEY_F1 = 59,
+    KEY_F2 = 60,
+    KEY_F3 = 61,
+    KEY_F4 = 62,
+    KEY_F5 = 63,
+    KEY_F6 = 64,
+    KEY_F7 = 65,
+    KEY_F8 = 66,
+    KEY_F9 = 67,
+    KEY_F10 = 68,
+    KEY_F11 = 87,
+    KEY_F12 = 88,
+    KEY_SYSRQ = 99,
+    KEY_INSERT = 110,
+    KEY_DELETE = 111,
+    KEY_HOME = 102,
+    KEY_END = 107,
+    KEY_PAGEUP = 104,
+    KEY_PAGEDOWN = 109,
+
     KEY_LEFT = 105,
     KEY_RIGHT = 106,
     KEY_UP = 103,
@@ -99,6 +131,7 @@ lazy_static! {
         (Keycode::KEY_BACKSLASH, (Some('\\'), Some('|'))),
         (Keycode::KEY_SEMICOLON, (Some(';'), Some(':'))),
         (Keycode::KEY_APOSTROPHE, (Some('\''), Some('"'))),
+        (Keycode::KEY_GRAVE, (Some('`'), Some('~'))),
         (Keycode::KEY_COMMA, (Some(','), Some('<'))),
         (Keycode::KEY_DOT, (Some('.'), Some('>'))),
         (Keycode::KEY_SLASH, (Some('/'), Some('?'))),
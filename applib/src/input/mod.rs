@@ -5,11 +5,19 @@ pub use keymap::{Keycode, CHARMAP};
 
 pub const MAX_EVENTS: usize = 10;
 
+// The input state is copied as is into the memory of the apps, so this must be bumped
+// whenever its layout changes, events included
+pub const INPUT_STATE_ABI_VERSION: u32 = 1;
+
 #[derive(Debug, Clone)]
 #[repr(C)]
 pub struct InputState {
     pub pointer: PointerState,
     pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    // Toggled by each press of the key
+    pub caps_lock: bool,
     pub events: [Option<InputEvent>; MAX_EVENTS],
     next_event_index: usize,
 }
@@ -22,12 +30,19 @@ impl InputState {
                 y: (h / 2).into(),
                 delta_x: 0,
                 delta_y: 0,
+                scroll_x: 0,
+                scroll_y: 0,
                 left_clicked: false,
                 right_clicked: false,
+                middle_clicked: false,
                 left_click_trigger: false,
                 right_click_trigger: false,
+                middle_click_trigger: false,
             },
             shift: false,
+            ctrl: false,
+            alt: false,
+            caps_lock: false,
             events: [None; MAX_EVENTS],
             next_event_index: 0,
         }
@@ -39,7 +54,7 @@ impl InputState {
     }
 
     pub fn add_event(&mut self, event: InputEvent) {
-        self.update_shift_key_state(&event);
+        self.update_modifier_keys_state(&event);
 
         if self.next_event_index < self.events.len() {
             self.events[self.next_event_index] = Some(event);
@@ -53,9 +68,32 @@ impl InputState {
         }
     }
 
+    // Drops the events for which f returns false, the others keep their order
+    pub fn retain_events<F: FnMut(&InputEvent) -> bool>(&mut self, mut f: F) {
+        let mut n = 0;
+        for i in 0..self.next_event_index {
+            if let Some(event) = self.events[i] {
+                if f(&event) {
+                    self.events[n] = Some(event);
+                    n += 1;
+                }
+            }
+        }
+        self.events[n..].fill(None);
+        self.next_event_index = n;
+    }
+
     pub fn change_origin(&mut self, origin: Point2D<i64>) {
         self.pointer.x -= origin.x;
         self.pointer.y -= origin.y;
+        for event in self.events.iter_mut().flatten() {
+            if let InputEvent::ButtonPress { x, y, .. } | InputEvent::ButtonRelease { x, y, .. } =
+                event
+            {
+                *x -= origin.x;
+                *y -= origin.y;
+            }
+        }
     }
 
     pub fn check_key_pressed(&self, kc: Keycode) -> bool {
@@ -65,13 +103,47 @@ impl InputState {
         })
     }
 
-    fn update_shift_key_state(&mut self, event: &InputEvent) {
+    // Presses in a row of the button, if it was pressed this frame: 1 for a single click, 2
+    // for a double click, and so on
+    pub fn click_count(&self, button: MouseButton) -> Option<u32> {
+        self.events.iter().flatten().find_map(|event| match event {
+            InputEvent::ButtonPress {
+                button: pressed,
+                clicks,
+                ..
+            } if *pressed == button => Some(*clicks),
+            _ => None,
+        })
+    }
+
+    // What the key types with the current modifiers. Caps Lock only changes letters.
+    pub fn key_char(&self, keycode: Keycode) -> Option<char> {
+        let (lower, upper) = CHARMAP.get(&keycode)?;
+        let letter = lower.is_some_and(|c| c.is_ascii_alphabetic());
+        match self.shift != (self.caps_lock && letter) {
+            true => *upper,
+            false => *lower,
+        }
+    }
+
+    fn update_modifier_keys_state(&mut self, event: &InputEvent) {
         let check_is_shift =
             |&keycode| keycode == Keycode::KEY_LEFTSHIFT || keycode == Keycode::KEY_RIGHTSHIFT;
+        let check_is_ctrl =
+            |&keycode| keycode == Keycode::KEY_LEFTCTRL || keycode == Keycode::KEY_RIGHTCTRL;
+        let check_is_alt =
+            |&keycode| keycode == Keycode::KEY_LEFTALT || keycode == Keycode::KEY_RIGHTALT;
 
         match event {
             InputEvent::KeyPress { keycode } if check_is_shift(keycode) => self.shift = true,
             InputEvent::KeyRelease { keycode } if check_is_shift(keycode) => self.shift = false,
+            InputEvent::KeyPress { keycode } if check_is_ctrl(keycode) => self.ctrl = true,
+            InputEvent::KeyRelease { keycode } if check_is_ctrl(keycode) => self.ctrl = false,
+            InputEvent::KeyPress { keycode } if check_is_alt(keycode) => self.alt = true,
+            InputEvent::KeyRelease { keycode } if check_is_alt(keycode) => self.alt = false,
+            InputEvent::KeyPress {
+                keycode: Keycode::KEY_CAPSLOCK,
+            } => self.caps_lock = !self.caps_lock,
             _ => (),
         }
     }
@@ -85,16 +157,54 @@ pub struct PointerState {
     pub y: i64,
     pub delta_x: i64,
     pub delta_y: i64,
+    // In wheel notches since the last frame, positive to the right and away from the user
+    pub scroll_x: i64,
+    pub scroll_y: i64,
     pub left_clicked: bool,
     pub right_clicked: bool,
+    pub middle_clicked: bool,
     pub left_click_trigger: bool,
     pub right_click_trigger: bool,
+    pub middle_click_trigger: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
 }
 
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub enum InputEvent {
-    KeyPress { keycode: Keycode },
-    KeyRelease { keycode: Keycode },
-    Scroll { delta: i64 },
+    KeyPress {
+        keycode: Keycode,
+    },
+    KeyRelease {
+        keycode: Keycode,
+    },
+    // At most one per frame, with the deltas of the pointer state
+    Scroll {
+        delta_x: i64,
+        delta_y: i64,
+    },
+    // A character that no single key gives, e.g. composed with a dead key
+    Text {
+        c: char,
+    },
+    // Where the pointer was. Presses close enough in time and space count as a series,
+    // clicks is 2 for the second press of a double click.
+    ButtonPress {
+        button: MouseButton,
+        x: i64,
+        y: i64,
+        clicks: u32,
+    },
+    ButtonRelease {
+        button: MouseButton,
+        x: i64,
+        y: i64,
+    },
 }
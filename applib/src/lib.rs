@@ -1,24 +1,45 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 extern crate alloc;
 
 use zune_png::PngDecoder;
 
+mod blend;
+pub mod color_utils;
 pub mod content;
+pub mod damage;
+mod deflate;
 pub mod drawing;
 pub mod geometry;
 pub mod hash;
+pub mod icons;
 pub mod input;
+mod png;
+mod qoi;
+mod rotation;
+mod scaling;
+pub mod shell;
+pub mod stats;
 mod stylesheet;
+#[cfg(test)]
+mod test_utils;
 pub mod uitk;
 
+use alloc::format;
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::ops;
+use damage::DamageList;
 use geometry::Vec2D;
 use input::InputState;
 
-pub use stylesheet::{StyleSheet, StyleSheetColors, StyleSheetText, TextSizes};
+pub use png::PngEncoder;
+pub use scaling::ScaleFilter;
+pub use stylesheet::{
+    ChromeImage, ChromeStyle, StyleSheet, StyleSheetColors, StyleSheetText, TextSizes,
+    TitleBarStyle, WidgetColors, WidgetState, WidgetStyle, STYLESHEET_ABI_VERSION,
+};
 
 #[derive(Clone, Copy, Hash, Debug, PartialEq)]
 #[repr(transparent)]
@@ -54,10 +75,46 @@ impl Color {
         (r, g, b, a)
     }
 
+    // Linear interpolation of each channel, t goes from 0 (self) to 1 (other)
+    pub fn lerp(&self, other: Color, t: f32) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        let (r1, g1, b1, a1) = self.as_rgba();
+        let (r2, g2, b2, a2) = other.as_rgba();
+        let mix = |c1: u8, c2: u8| (c1 as f32 + (c2 as f32 - c1 as f32) * t + 0.5) as u8;
+        Color::rgba(mix(r1, r2), mix(g1, g2), mix(b1, b2), mix(a1, a2))
+    }
+
     pub fn invert(&self) -> Self {
         let Color([r, g, b, a]) = *self;
         Color::rgba(255 - r, 255 - g, 255 - b, a)
     }
+
+    // "#RRGGBB", or "#RRGGBBAA" for translucent colors
+    pub fn to_hex(&self) -> String {
+        let (r, g, b, a) = self.as_rgba();
+        match a {
+            255 => format!("#{:02X}{:02X}{:02X}", r, g, b),
+            _ => format!("#{:02X}{:02X}{:02X}{:02X}", r, g, b, a),
+        }
+    }
+
+    // The "#" is optional, and the alpha defaults to opaque
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim();
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+
+        if !matches!(hex.len(), 6 | 8) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok();
+        let a = match hex.len() {
+            8 => channel(3)?,
+            _ => 255,
+        };
+
+        Some(Color::rgba(channel(0)?, channel(1)?, channel(2)?, a))
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Hash)]
@@ -168,6 +225,32 @@ impl Rect {
         }
     }
 
+    // What is left of self outside of other, as up to 4 disjoint rects
+    pub fn subtract(&self, other: &Rect) -> Vec<Rect> {
+        let Some(inter) = self.intersection(other) else {
+            return vec![self.clone()];
+        };
+
+        let [x0, y0, x1, y1] = self.as_xyxy();
+        let [ix0, iy0, ix1, iy1] = inter.as_xyxy();
+
+        // Full-width bands above and below, then what is left on each side
+        let mut parts = Vec::new();
+        if iy0 > y0 {
+            parts.push(Rect::from_xyxy([x0, y0, x1, iy0 - 1]));
+        }
+        if iy1 < y1 {
+            parts.push(Rect::from_xyxy([x0, iy1 + 1, x1, y1]));
+        }
+        if ix0 > x0 {
+            parts.push(Rect::from_xyxy([x0, iy0, ix0 - 1, iy1]));
+        }
+        if ix1 < x1 {
+            parts.push(Rect::from_xyxy([ix1 + 1, iy0, x1, iy1]));
+        }
+        parts
+    }
+
     pub fn as_xyxy(&self) -> [i64; 4] {
         let Rect { x0, y0, w, h } = *self;
         let (w, h) = (w as i64, h as i64);
@@ -249,6 +332,27 @@ pub struct Framebuffer<T> {
     data_w: u32,
     data_h: u32,
     rect: Rect,
+    // In data coordinates, where drawing is allowed: the intersection of the data bounds,
+    // the subregion rect and the pushed clip rects. Can be empty.
+    clip: Rect,
+    // Clips to restore on pop_clip()
+    clip_stack: Vec<Rect>,
+    // In data coordinates, only tracked once set
+    damage: Option<DamageList>,
+}
+
+// Unlike Rect::intersection(), empty rects are allowed, and the result can be empty
+fn clip_intersection(a: &Rect, b: &Rect) -> Rect {
+    let x0 = i64::max(a.x0, b.x0);
+    let y0 = i64::max(a.y0, b.y0);
+    let x1 = i64::min(a.x0 + a.w as i64, b.x0 + b.w as i64);
+    let y1 = i64::min(a.y0 + a.h as i64, b.y0 + b.h as i64);
+    Rect {
+        x0,
+        y0,
+        w: i64::max(0, x1 - x0) as u32,
+        h: i64::max(0, y1 - y0) as u32,
+    }
 }
 
 pub struct FbLineMut<'a> {
@@ -272,9 +376,7 @@ pub struct FbLineCoords {
 impl<'a> FbLineMut<'a> {
     fn fill(&mut self, color: Color, blend: bool) {
         if blend {
-            self.data.iter_mut().for_each(|pixel| {
-                *pixel = blend_colors(color, *pixel);
-            });
+            blend::blend_row_color(self.data, color);
         } else {
             self.data.fill(color)
         }
@@ -295,12 +397,10 @@ impl<'a> FbLineMut<'a> {
         let i2 = (new_x_data_start - other.x_data_start) as usize;
 
         if blend {
-            self.data[i1..i1 + copy_len]
-                .iter_mut()
-                .zip(other.data[i2..i2 + copy_len].iter())
-                .for_each(|(dst, src)| {
-                    *dst = blend_colors(*src, *dst);
-                });
+            blend::blend_row(
+                &mut self.data[i1..i1 + copy_len],
+                &other.data[i2..i2 + copy_len],
+            );
         } else {
             self.data[i1..i1 + copy_len].copy_from_slice(&other.data[i2..i2 + copy_len]);
         }
@@ -328,6 +428,39 @@ pub trait FbViewMut: FbView {
     fn fill_line(&mut self, x: i64, line_w: u32, y: i64, color: Color, blend: bool);
     fn fill(&mut self, color: Color);
     fn copy_from_fb<F1: FbView>(&mut self, src: &F1, dst: (i64, i64), blend: bool);
+    // Scales src_rect of src to fill dst_rect. Either can be partly outside of its
+    // framebuffer, only the pixels inside both are drawn.
+    fn copy_from_fb_scaled<F1: FbView>(
+        &mut self,
+        src: &F1,
+        src_rect: &Rect,
+        dst_rect: &Rect,
+        filter: ScaleFilter,
+        blend: bool,
+    );
+    // Turned clockwise by quarter_turns, with its top-left corner at dst
+    fn copy_from_fb_rotated<F1: FbView>(
+        &mut self,
+        src: &F1,
+        dst: (i64, i64),
+        quarter_turns: u32,
+        blend: bool,
+    );
+    // In place, around the middle of the region
+    fn flip_horizontal(&mut self);
+    fn flip_vertical(&mut self);
+    // Drawing is limited to the intersection of the pushed rects, until they are popped.
+    // Subregions start with the clip of their parent.
+    fn push_clip(&mut self, rect: &Rect);
+    fn pop_clip(&mut self);
+    fn clip_depth(&self) -> usize;
+    // Once a list is set, drawing calls add the pixels they may change to it. Subregions do
+    // not track damage, creating one marks its whole clip as damaged in its parent instead.
+    fn set_damage(&mut self, damage: Option<DamageList>);
+    fn take_damage(&mut self) -> Option<DamageList>;
+    // For changes made outside of drawing calls
+    fn add_damage(&mut self, rect: &Rect);
+    // Not clipped, the whole framebuffer is considered damaged
     fn get_data_mut(&mut self) -> &mut [Color];
     fn get_line_mut<'b>(&'b mut self, x: i64, line_w: u32, y: i64) -> FbLineMut<'b>;
 }
@@ -340,10 +473,14 @@ impl<'a> Framebuffer<BorrowedMutPixels<'a>> {
             data: BorrowedMutPixels(data),
             data_w: w,
             data_h: h,
+            clip: rect.clone(),
             rect,
+            clip_stack: Vec::new(),
+            damage: None,
         }
     }
 
+    // The bytes must be in the same order as those of Color, i.e. RGBA
     pub fn from_bytes<'b>(
         bytes: &'b mut [u8],
         w: u32,
@@ -371,10 +508,14 @@ impl<'a> Framebuffer<BorrowedPixels<'a>> {
             data: BorrowedPixels(data),
             data_w: w,
             data_h: h,
+            clip: rect.clone(),
             rect,
+            clip_stack: Vec::new(),
+            damage: None,
         }
     }
 
+    // See Framebuffer::<BorrowedMutPixels>::from_bytes()
     pub fn from_bytes<'b>(bytes: &'b [u8], w: u32, h: u32) -> Framebuffer<BorrowedPixels<'b>> {
         assert_eq!(bytes.len(), (4 * w * h) as usize);
 
@@ -402,7 +543,10 @@ impl Framebuffer<OwnedPixels> {
             data: OwnedPixels(data),
             data_w: w,
             data_h: h,
+            clip: rect.clone(),
             rect,
+            clip_stack: Vec::new(),
+            damage: None,
         }
     }
 
@@ -430,15 +574,96 @@ impl Framebuffer<OwnedPixels> {
             data: OwnedPixels(data),
             data_w: w,
             data_h: h,
+            clip: rect.clone(),
             rect,
+            clip_stack: Vec::new(),
+            damage: None,
         }
     }
 
+    // None if the data is not a valid QOI image
+    pub fn from_qoi(qoi_bytes: &[u8]) -> Option<Self> {
+        let (w, h, data) = qoi::decode(qoi_bytes)?;
+        let rect = Rect { x0: 0, y0: 0, w, h };
+
+        Some(Framebuffer {
+            data: OwnedPixels(data),
+            data_w: w,
+            data_h: h,
+            clip: rect.clone(),
+            rect,
+            clip_stack: Vec::new(),
+            damage: None,
+        })
+    }
+
     pub fn size_bytes(&self) -> usize {
         self.data.as_slice().len() * 4
     }
 }
 
+impl<T: FbData> Framebuffer<T> {
+    fn mark_damaged(&mut self, data_rect: &Rect) {
+        if let Some(damage) = self.damage.as_mut() {
+            damage.add(data_rect);
+        }
+    }
+
+    // Turned clockwise, with the width and height swapped for odd turns
+    pub fn rotated(&self, quarter_turns: u32) -> Framebuffer<OwnedPixels> {
+        let (w, h) = rotation::rotated_shape(self.shape(), quarter_turns);
+        let mut rotated = Framebuffer::new_owned(w, h);
+        rotated.copy_from_fb_rotated(self, (0, 0), quarter_turns, false);
+        rotated
+    }
+
+    // 8-bit RGBA, readable with from_png()
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode(self)
+    }
+
+    // Lossless like PNG, larger but much faster to decode. Readable with from_qoi().
+    pub fn to_qoi(&self) -> Vec<u8> {
+        qoi::encode(self)
+    }
+
+    fn data_rect(&self) -> Rect {
+        Rect {
+            x0: 0,
+            y0: 0,
+            w: self.data_w,
+            h: self.data_h,
+        }
+    }
+
+    // Part of a line inside bounds, which are in data coordinates and inside the data
+    fn line_coords_in(&self, bounds: &Rect, x: i64, line_w: u32, y: i64) -> FbLineCoords {
+        let (x, y) = self.to_data_coords(x, y);
+
+        let empty_line = FbLineCoords {
+            data_index: None,
+            data_len: 0,
+            x_data_start: line_w,
+        };
+
+        if line_w == 0 || y < bounds.y0 || y >= bounds.y0 + bounds.h as i64 {
+            return empty_line;
+        }
+
+        let x1 = i64::max(bounds.x0, x);
+        let x2 = i64::min(bounds.x0 + bounds.w as i64, x + line_w as i64);
+        if x1 >= x2 {
+            return empty_line;
+        }
+
+        FbLineCoords {
+            data_index: Some((y * self.data_w as i64 + x1) as usize),
+            data_len: (x2 - x1) as usize,
+            x_data_start: (x1 - x) as u32,
+        }
+    }
+}
+
 impl<T: FbData> FbView for Framebuffer<T> {
     fn shape(&self) -> (u32, u32) {
         (self.rect.w, self.rect.h)
@@ -452,11 +677,15 @@ impl<T: FbData> FbView for Framebuffer<T> {
         let Rect { x0, y0, w, h } = *rect;
         let (x0, y0) = self.to_data_coords(x0, y0);
 
+        let rect = Rect { x0, y0, w, h };
         Framebuffer {
             data: BorrowedPixels(self.data.as_slice()),
             data_w: self.data_w,
             data_h: self.data_h,
-            rect: Rect { x0, y0, w, h },
+            clip: clip_intersection(&self.clip, &rect),
+            rect,
+            clip_stack: Vec::new(),
+            damage: None,
         }
     }
 
@@ -495,41 +724,13 @@ impl<T: FbData> FbView for Framebuffer<T> {
     }
 
     fn get_line_coords(&self, x: i64, line_w: u32, y: i64) -> FbLineCoords {
-        let (x, y) = self.to_data_coords(x, y);
-
-        let empty_line = FbLineCoords {
-            data_index: None,
-            data_len: 0,
-            x_data_start: line_w,
-        };
-
-        if line_w == 0 || y < 0 || y >= self.data_h as i64 {
-            return empty_line;
-        }
-
-        let (x1, x2) = (x, x + line_w as i64 - 1);
-
-        // Clipping to subregion rect
-        let x1 = i64::max(self.rect.x0, x1);
-        let x2 = i64::min(self.rect.x0 + self.rect.w as i64 - 1, x2);
-
-        // Clipping to data bounds
-        let x1 = i64::max(0, x1);
-        let x2 = i64::min(self.data_w as i64 - 1, x2);
-
-        let i1 = self.get_offset_data_coords(x1, y);
-        let i2 = self.get_offset_data_coords(x2, y);
-
-        let (i1, i2) = match (i1, i2) {
-            (Some(i1), Some(i2)) => (i1, i2),
-            _ => return empty_line,
+        // Reading is not clipped vertically to the subregion
+        let bounds = Rect {
+            y0: 0,
+            h: self.data_h,
+            ..clip_intersection(&self.rect, &self.data_rect())
         };
-
-        FbLineCoords {
-            data_index: Some(i1),
-            data_len: (i2 - i1 + 1) as usize,
-            x_data_start: (x1 - x) as u32,
-        }
+        self.line_coords_in(&bounds, x, line_w, y)
     }
 
     fn get_line<'b>(&'b self, x: i64, line_w: u32, y: i64) -> FbLine<'b> {
@@ -560,18 +761,34 @@ impl<T: FbDataMut> FbViewMut for Framebuffer<T> {
         let Rect { x0, y0, w, h } = *rect;
         let (x0, y0) = self.to_data_coords(x0, y0);
 
+        let rect = Rect { x0, y0, w, h };
+        let clip = clip_intersection(&self.clip, &rect);
+        self.mark_damaged(&clip);
         Framebuffer {
             data: BorrowedMutPixels(self.data.as_mut_slice()),
             data_w: self.data_w,
             data_h: self.data_h,
-            rect: Rect { x0, y0, w, h },
+            clip,
+            rect,
+            clip_stack: Vec::new(),
+            damage: None,
         }
     }
 
     fn set_pixel(&mut self, x: i64, y: i64, color: Color) {
-        let offset = self.get_offset_region_coords(x, y);
-        let data = self.data.as_mut_slice();
-        offset.map(|i| data[i] = color);
+        let (x, y) = self.to_data_coords(x, y);
+        let Rect { x0, y0, w, h } = self.clip;
+        if x < x0 || y < y0 || x >= x0 + w as i64 || y >= y0 + h as i64 {
+            return;
+        }
+        let i = (y * self.data_w as i64 + x) as usize;
+        self.data.as_mut_slice()[i] = color;
+        self.mark_damaged(&Rect {
+            x0: x,
+            y0: y,
+            w: 1,
+            h: 1,
+        });
     }
 
     fn fill_line(&mut self, x: i64, line_w: u32, y: i64, color: Color, blend: bool) {
@@ -579,17 +796,55 @@ impl<T: FbDataMut> FbViewMut for Framebuffer<T> {
     }
 
     fn fill(&mut self, color: Color) {
-        let (w, h) = self.shape();
-        if w == self.data_w && h == self.data_h {
-            self.data.as_mut_slice().fill(color);
+        self.mark_damaged(&self.clip.clone());
+        let Rect { x0, y0, w, h } = self.clip;
+        let data_w = self.data_w as i64;
+        let data = self.data.as_mut_slice();
+        if w == self.data_w {
+            let i = (y0 * data_w) as usize;
+            data[i..i + (w * h) as usize].fill(color);
         } else {
-            for y in 0..h {
-                self.fill_line(0, w, y.into(), color, false)
+            for y in y0..y0 + h as i64 {
+                let i = (y * data_w + x0) as usize;
+                data[i..i + w as usize].fill(color);
             }
         }
     }
 
+    fn push_clip(&mut self, rect: &Rect) {
+        let Rect { x0, y0, w, h } = *rect;
+        let (x0, y0) = self.to_data_coords(x0, y0);
+        let clip = clip_intersection(&self.clip, &Rect { x0, y0, w, h });
+        self.clip_stack
+            .push(core::mem::replace(&mut self.clip, clip));
+    }
+
+    fn pop_clip(&mut self) {
+        match self.clip_stack.pop() {
+            Some(clip) => self.clip = clip,
+            None => debug_assert!(false, "pop_clip() without a matching push_clip()"),
+        }
+    }
+
+    fn clip_depth(&self) -> usize {
+        self.clip_stack.len()
+    }
+
+    fn set_damage(&mut self, damage: Option<DamageList>) {
+        self.damage = damage;
+    }
+
+    fn take_damage(&mut self) -> Option<DamageList> {
+        self.damage.take()
+    }
+
+    fn add_damage(&mut self, rect: &Rect) {
+        let (x0, y0) = self.to_data_coords(rect.x0, rect.y0);
+        self.mark_damaged(&Rect { x0, y0, ..*rect });
+    }
+
     fn get_data_mut(&mut self) -> &mut [Color] {
+        self.mark_damaged(&self.data_rect());
         self.data.as_mut_slice()
     }
 
@@ -599,7 +854,17 @@ impl<T: FbDataMut> FbViewMut for Framebuffer<T> {
             data_len,
             x_data_start,
             ..
-        } = self.get_line_coords(x, line_w, y);
+        } = self.line_coords_in(&self.clip, x, line_w, y);
+
+        if let Some(i) = data_index {
+            let data_w = self.data_w as usize;
+            self.mark_damaged(&Rect {
+                x0: (i % data_w) as i64,
+                y0: (i / data_w) as i64,
+                w: data_len as u32,
+                h: 1,
+            });
+        }
 
         let data = self.data.as_mut_slice();
 
@@ -616,15 +881,64 @@ impl<T: FbDataMut> FbViewMut for Framebuffer<T> {
     }
 
     fn copy_from_fb<F1: FbView>(&mut self, src: &F1, dst: (i64, i64), blend: bool) {
-        let (x0, y0) = dst;
         let (src_w, src_h) = src.shape();
+        let (x0, y0) = self.to_data_coords(dst.0, dst.1);
 
-        for y in 0..(src_h as i64) {
-            let src_line = src.get_line(0, src_w, y);
-            let mut dst_line = self.get_line_mut(x0, src_line.line_w, y0 + y);
+        // The part of the destination which can be drawn, once for all the rows
+        let dst_rect = Rect {
+            x0,
+            y0,
+            w: src_w,
+            h: src_h,
+        };
+        let area = clip_intersection(&self.clip, &dst_rect);
+        if area.w == 0 {
+            return;
+        }
+        self.mark_damaged(&area);
+
+        let data_w = self.data_w as i64;
+        let data = self.data.as_mut_slice();
+        for y in area.y0..area.y0 + area.h as i64 {
+            let src_line = src.get_line(area.x0 - x0, area.w, y - y0);
+            let i = (y * data_w + area.x0) as usize;
+            let mut dst_line = FbLineMut {
+                data: &mut data[i..i + area.w as usize],
+                x_data_start: 0,
+                line_w: area.w,
+            };
             dst_line.copy_from_line(&src_line, blend);
         }
     }
+
+    fn copy_from_fb_scaled<F1: FbView>(
+        &mut self,
+        src: &F1,
+        src_rect: &Rect,
+        dst_rect: &Rect,
+        filter: ScaleFilter,
+        blend: bool,
+    ) {
+        scaling::copy_scaled(self, src, src_rect, dst_rect, filter, blend);
+    }
+
+    fn copy_from_fb_rotated<F1: FbView>(
+        &mut self,
+        src: &F1,
+        dst: (i64, i64),
+        quarter_turns: u32,
+        blend: bool,
+    ) {
+        rotation::copy_rotated(self, src, dst, quarter_turns, blend);
+    }
+
+    fn flip_horizontal(&mut self) {
+        rotation::flip_horizontal(self);
+    }
+
+    fn flip_vertical(&mut self) {
+        rotation::flip_vertical(self);
+    }
 }
 
 fn blend_colors(c1: Color, c2: Color) -> Color {
@@ -655,3 +969,49 @@ pub fn decode_png(png_bytes: &[u8]) -> Vec<u8> {
         .u8()
         .expect("Invalid PNG bitmap")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_colors() {
+        let color = Color::rgb(0x12, 0xab, 0xef);
+        assert_eq!(color.to_hex(), "#12ABEF");
+        assert_eq!(color.with_alpha(0x80).to_hex(), "#12ABEF80");
+        assert_eq!(Color::ZERO.to_hex(), "#00000000");
+
+        assert_eq!(Color::from_hex("#12ABEF"), Some(color));
+        assert_eq!(Color::from_hex("12abef"), Some(color));
+        assert_eq!(Color::from_hex("  #12abef "), Some(color));
+        assert_eq!(Color::from_hex("#12ABEF80"), Some(color.with_alpha(0x80)));
+        assert_eq!(Color::from_hex("#12ABEFFF"), Some(color));
+
+        for invalid in [
+            "",
+            "#",
+            "#12ABE",
+            "#12ABEF8",
+            "#12ABEF800",
+            "#12ABEG",
+            "##12ABEF",
+        ] {
+            assert_eq!(Color::from_hex(invalid), None, "{}", invalid);
+        }
+        // Multi-byte characters must not split a channel
+        assert_eq!(Color::from_hex("#1é2345"), None);
+        assert_eq!(Color::from_hex("+1+2+3"), None);
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        for color in [
+            Color::WHITE,
+            Color::AQUA,
+            Color::rgba(1, 2, 3, 4),
+            Color::ZERO,
+        ] {
+            assert_eq!(Color::from_hex(&color.to_hex()), Some(color));
+        }
+    }
+}
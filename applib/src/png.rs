@@ -0,0 +1,363 @@
+use crate::deflate::ZlibEncoder;
+use crate::FbView;
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// IDAT chunks are written out as soon as there is that much compressed data
+const IDAT_CHUNK_SIZE: usize = 65536;
+// RGBA, 8 bits per channel
+const BYTES_PER_PIXEL: usize = 4;
+
+lazy_static! {
+    static ref CRC_TABLE: [u32; 256] = core::array::from_fn(|n| {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = match c & 1 {
+                1 => 0xedb8_8320 ^ (c >> 1),
+                _ => c >> 1,
+            };
+        }
+        c
+    });
+}
+
+// Encodes a framebuffer a few rows at a time, so that the work can be spread over several
+// frames. See Framebuffer::to_png() to encode one at once.
+pub struct PngEncoder {
+    shape: (u32, u32),
+    // Next row to encode
+    y: u32,
+    png: Vec<u8>,
+    prev_row: Vec<u8>,
+    row: Vec<u8>,
+    // Filter type byte, then the filtered row
+    filtered: Vec<u8>,
+    candidate: Vec<u8>,
+    zlib: ZlibEncoder,
+    // Compressed data not written out yet
+    idat: Vec<u8>,
+}
+
+impl PngEncoder {
+    pub fn new(shape: (u32, u32)) -> Self {
+        let (w, h) = shape;
+
+        let mut png = Vec::new();
+        png.extend_from_slice(&SIGNATURE);
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&w.to_be_bytes());
+        header.extend_from_slice(&h.to_be_bytes());
+        // Bit depth, color type (RGBA), compression, filter and interlace methods
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        write_chunk(&mut png, b"IHDR", &header);
+
+        let row_len = w as usize * BYTES_PER_PIXEL;
+
+        PngEncoder {
+            shape,
+            y: 0,
+            png,
+            prev_row: vec![0u8; row_len],
+            row: vec![0u8; row_len],
+            filtered: vec![0u8; row_len + 1],
+            candidate: vec![0u8; row_len],
+            zlib: ZlibEncoder::new(),
+            idat: Vec::new(),
+        }
+    }
+
+    // Up to `nb_rows` more rows of `fb`, which must have the shape given to new(). True
+    // once all rows are encoded.
+    pub fn encode_rows<F: FbView>(&mut self, fb: &F, nb_rows: u32) -> bool {
+        let (w, h) = self.shape;
+        assert_eq!(fb.shape(), self.shape, "Framebuffer shape changed");
+
+        let end = u32::min(h, self.y.saturating_add(nb_rows));
+        for y in self.y..end {
+            let line = fb.get_line(0, w, y as i64);
+            for (bytes, color) in self.row.chunks_exact_mut(BYTES_PER_PIXEL).zip(line.data) {
+                bytes.copy_from_slice(&color.0);
+            }
+
+            // The filter with the smallest sum of absolute differences usually compresses
+            // best
+            let mut best_score = u64::MAX;
+            for filter in Filter::ALL {
+                apply_filter(filter, &self.row, &self.prev_row, &mut self.candidate);
+                let score = self
+                    .candidate
+                    .iter()
+                    .map(|&v| (v as i8).unsigned_abs() as u64)
+                    .sum();
+                if score < best_score {
+                    best_score = score;
+                    self.filtered[0] = filter as u8;
+                    self.filtered[1..].copy_from_slice(&self.candidate);
+                }
+            }
+
+            self.zlib.write(&self.filtered);
+            core::mem::swap(&mut self.row, &mut self.prev_row);
+
+            self.idat.extend_from_slice(&self.zlib.take_output());
+            while self.idat.len() >= IDAT_CHUNK_SIZE {
+                write_chunk(&mut self.png, b"IDAT", &self.idat[..IDAT_CHUNK_SIZE]);
+                self.idat.drain(..IDAT_CHUNK_SIZE);
+            }
+        }
+        self.y = end;
+
+        self.y == h
+    }
+
+    // Once encode_rows() returned true
+    pub fn finish(mut self) -> Vec<u8> {
+        assert_eq!(self.y, self.shape.1, "Rows left to encode");
+
+        self.idat.extend_from_slice(&self.zlib.finish());
+        for part in self.idat.chunks(IDAT_CHUNK_SIZE) {
+            write_chunk(&mut self.png, b"IDAT", part);
+        }
+        write_chunk(&mut self.png, b"IEND", &[]);
+
+        self.png
+    }
+}
+
+// See Framebuffer::to_png()
+pub(crate) fn encode<F: FbView>(fb: &F) -> Vec<u8> {
+    let mut encoder = PngEncoder::new(fb.shape());
+    encoder.encode_rows(fb, fb.shape().1);
+    encoder.finish()
+}
+
+fn write_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let crc = data.iter().fold(0xffff_ffff, |c: u32, &byte| {
+        CRC_TABLE[((c ^ byte as u32) & 0xff) as usize] ^ (c >> 8)
+    });
+    crc ^ 0xffff_ffff
+}
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+enum Filter {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4,
+}
+
+impl Filter {
+    const ALL: [Filter; 5] = [
+        Filter::None,
+        Filter::Sub,
+        Filter::Up,
+        Filter::Average,
+        Filter::Paeth,
+    ];
+}
+
+fn apply_filter(filter: Filter, row: &[u8], prev_row: &[u8], out: &mut [u8]) {
+    // The bytes left of the first pixel count as zeros
+    let bpp = BYTES_PER_PIXEL;
+    let (first, rest) = out.split_at_mut(bpp);
+    match filter {
+        Filter::None => out.copy_from_slice(row),
+        Filter::Sub => {
+            first.copy_from_slice(&row[..bpp]);
+            for (i, v) in rest.iter_mut().enumerate() {
+                *v = row[i + bpp].wrapping_sub(row[i]);
+            }
+        }
+        Filter::Up => {
+            for ((v, &x), &b) in out.iter_mut().zip(row).zip(prev_row) {
+                *v = x.wrapping_sub(b);
+            }
+        }
+        Filter::Average => {
+            for i in 0..bpp {
+                first[i] = row[i].wrapping_sub(prev_row[i] / 2);
+            }
+            for (i, v) in rest.iter_mut().enumerate() {
+                let avg = (row[i] as u16 + prev_row[i + bpp] as u16) / 2;
+                *v = row[i + bpp].wrapping_sub(avg as u8);
+            }
+        }
+        Filter::Paeth => {
+            for i in 0..bpp {
+                first[i] = row[i].wrapping_sub(prev_row[i]);
+            }
+            for (i, v) in rest.iter_mut().enumerate() {
+                let predicted = paeth(row[i], prev_row[i + bpp], prev_row[i]);
+                *v = row[i + bpp].wrapping_sub(predicted);
+            }
+        }
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_same_pixels, noise, pattern};
+    use crate::{Color, FbViewMut, Framebuffer, Rect};
+
+    fn round_trip<F: FbView>(fb: &F) {
+        let decoded = Framebuffer::from_png(&encode(fb));
+        assert_same_pixels(&decoded, fb);
+    }
+
+    // Type and data of each chunk, after checking its CRC
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        assert_eq!(png[..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (typed, after) = rest[4..].split_at(4 + len);
+            let crc = u32::from_be_bytes(after[..4].try_into().unwrap());
+            assert_eq!(crc32(typed), crc, "Bad CRC");
+            chunks.push((typed[..4].try_into().unwrap(), &typed[4..]));
+            rest = &after[4..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn crc32_reference_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn round_trip_noise() {
+        round_trip(&noise(37, 23, 1));
+    }
+
+    #[test]
+    fn round_trip_pattern() {
+        round_trip(&pattern(200, 120));
+    }
+
+    #[test]
+    fn round_trip_flat() {
+        let fb = Framebuffer::new_owned_filled(300, 200, Color::rgba(12, 34, 56, 78));
+        let png = encode(&fb);
+        // Runs of identical bytes take a few bytes per 258-byte match
+        assert!(png.len() < 300 * 200 * 4 / 50, "{} bytes", png.len());
+        round_trip(&fb);
+    }
+
+    #[test]
+    fn round_trip_tiny() {
+        round_trip(&Framebuffer::new_owned_filled(1, 1, Color::RED));
+        round_trip(&noise(1, 9, 2));
+        round_trip(&noise(9, 1, 3));
+    }
+
+    #[test]
+    fn round_trip_subregion() {
+        let fb = noise(64, 64, 4);
+        let region = fb.subregion(&Rect {
+            x0: 10,
+            y0: 5,
+            w: 20,
+            h: 30,
+        });
+        round_trip(&region);
+    }
+
+    // Larger than the deflate window and split over several IDAT chunks
+    #[test]
+    fn round_trip_large() {
+        let mut fb = noise(300, 200, 5);
+        let pattern = pattern(300, 100);
+        fb.copy_from_fb(&pattern, (0, 50), false);
+        let png = encode(&fb);
+        let nb_idat = chunks(&png)
+            .iter()
+            .filter(|(chunk_type, _)| chunk_type == b"IDAT")
+            .count();
+        assert!(nb_idat > 1);
+        round_trip(&fb);
+    }
+
+    #[test]
+    fn chunk_layout() {
+        let png = encode(&noise(5, 3, 6));
+        let chunks = chunks(&png);
+        let types: Vec<&[u8; 4]> = chunks.iter().map(|(chunk_type, _)| chunk_type).collect();
+        assert_eq!(types, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 5, 0, 0, 0, 3, 8, 6, 0, 0, 0]);
+    }
+
+    #[test]
+    fn incremental_matches_one_shot() {
+        let fb = pattern(150, 70);
+        let mut encoder = PngEncoder::new(fb.shape());
+        let mut nb_calls = 0;
+        while !encoder.encode_rows(&fb, 8) {
+            nb_calls += 1;
+        }
+        assert_eq!(nb_calls, 8);
+        assert_eq!(encoder.finish(), encode(&fb));
+    }
+
+    #[test]
+    fn filters_invert() {
+        let mut rng = crate::test_utils::Rng::new(7);
+        let row: Vec<u8> = (0..40).map(|_| rng.next_u32() as u8).collect();
+        let prev_row: Vec<u8> = (0..40).map(|_| rng.next_u32() as u8).collect();
+        let bpp = BYTES_PER_PIXEL;
+
+        for filter in Filter::ALL {
+            let mut filtered = vec![0u8; row.len()];
+            apply_filter(filter, &row, &prev_row, &mut filtered);
+
+            // Decoding as in the PNG spec
+            let mut out = vec![0u8; row.len()];
+            for i in 0..row.len() {
+                let a = if i >= bpp { out[i - bpp] } else { 0 };
+                let b = prev_row[i];
+                let c = if i >= bpp { prev_row[i - bpp] } else { 0 };
+                let predicted = match filter {
+                    Filter::None => 0,
+                    Filter::Sub => a,
+                    Filter::Up => b,
+                    Filter::Average => ((a as u16 + b as u16) / 2) as u8,
+                    Filter::Paeth => paeth(a, b, c),
+                };
+                out[i] = filtered[i].wrapping_add(predicted);
+            }
+            assert_eq!(out, row, "Filter {}", filter as u8);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use crate::{Color, FbView};
+use alloc::vec::Vec;
+
+// See https://qoiformat.org/qoi-specification.pdf
+const MAGIC: [u8; 4] = *b"qoif";
+const HEADER_LEN: usize = 14;
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+// Same limit as the reference implementation, so that w * h cannot overflow
+const MAX_PIXELS: u64 = 400_000_000;
+
+const OP_INDEX: u8 = 0b0000_0000;
+const OP_DIFF: u8 = 0b0100_0000;
+const OP_LUMA: u8 = 0b1000_0000;
+const OP_RUN: u8 = 0b1100_0000;
+const OP_RGB: u8 = 0b1111_1110;
+const OP_RGBA: u8 = 0b1111_1111;
+const TAG_MASK: u8 = 0b1100_0000;
+
+const MAX_RUN: u8 = 62;
+
+// Position of a color in the table of recently seen ones
+fn index_of(color: Color) -> usize {
+    let (r, g, b, a) = color.as_rgba();
+    (r as usize * 3 + g as usize * 5 + b as usize * 7 + a as usize * 11) % 64
+}
+
+// See Framebuffer::to_qoi()
+pub(crate) fn encode<F: FbView>(fb: &F) -> Vec<u8> {
+    let (w, h) = fb.shape();
+
+    let mut qoi = Vec::with_capacity(HEADER_LEN + (w * h) as usize + END_MARKER.len());
+    qoi.extend_from_slice(&MAGIC);
+    qoi.extend_from_slice(&w.to_be_bytes());
+    qoi.extend_from_slice(&h.to_be_bytes());
+    // RGBA, sRGB with linear alpha
+    qoi.extend_from_slice(&[4, 0]);
+
+    let mut seen = [Color::ZERO; 64];
+    let mut prev = Color::rgba(0, 0, 0, 255);
+    let mut run = 0;
+
+    for y in 0..h as i64 {
+        let line = fb.get_line(0, w, y);
+        for &color in line.data {
+            if color == prev {
+                run += 1;
+                if run == MAX_RUN {
+                    qoi.push(OP_RUN | (run - 1));
+                    run = 0;
+                }
+                continue;
+            }
+
+            if run > 0 {
+                qoi.push(OP_RUN | (run - 1));
+                run = 0;
+            }
+
+            let i = index_of(color);
+            if seen[i] == color {
+                qoi.push(OP_INDEX | i as u8);
+            } else {
+                seen[i] = color;
+                push_color(&mut qoi, color, prev);
+            }
+            prev = color;
+        }
+    }
+
+    if run > 0 {
+        qoi.push(OP_RUN | (run - 1));
+    }
+
+    qoi.extend_from_slice(&END_MARKER);
+    qoi
+}
+
+// The smallest of the diff, luma and full color ops
+fn push_color(qoi: &mut Vec<u8>, color: Color, prev: Color) {
+    let (r, g, b, a) = color.as_rgba();
+    if a != prev.0[3] {
+        qoi.extend_from_slice(&[OP_RGBA, r, g, b, a]);
+        return;
+    }
+
+    let dr = r.wrapping_sub(prev.0[0]) as i8;
+    let dg = g.wrapping_sub(prev.0[1]) as i8;
+    let db = b.wrapping_sub(prev.0[2]) as i8;
+    let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+
+    if [dr, dg, db].iter().all(|d| (-2..=1).contains(d)) {
+        qoi.push(OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8);
+    } else if (-32..=31).contains(&dg) && [dr_dg, db_dg].iter().all(|d| (-8..=7).contains(d)) {
+        qoi.push(OP_LUMA | (dg + 32) as u8);
+        qoi.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+    } else {
+        qoi.extend_from_slice(&[OP_RGB, r, g, b]);
+    }
+}
+
+// Width, height and pixels. None if the data is not a complete QOI image, e.g. when
+// it was cut short.
+pub(crate) fn decode(qoi: &[u8]) -> Option<(u32, u32, Vec<Color>)> {
+    let header = qoi.get(..HEADER_LEN)?;
+    if header[..4] != MAGIC {
+        return None;
+    }
+    let w = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    let h = u32::from_be_bytes(header[8..12].try_into().unwrap());
+    let (channels, colorspace) = (header[12], header[13]);
+    if !matches!(channels, 3 | 4) || colorspace > 1 || w as u64 * h as u64 > MAX_PIXELS {
+        return None;
+    }
+
+    let nb_pixels = (w * h) as usize;
+    let ops = qoi.get(HEADER_LEN..qoi.len().checked_sub(END_MARKER.len())?)?;
+    if qoi[qoi.len() - END_MARKER.len()..] != END_MARKER {
+        return None;
+    }
+
+    // Not filled in advance, the pixel count in the header cannot be trusted
+    let mut pixels = Vec::with_capacity(usize::min(nb_pixels, ops.len() * MAX_RUN as usize));
+    let mut seen = [Color::ZERO; 64];
+    let mut prev = Color::rgba(0, 0, 0, 255);
+    let mut i = 0;
+
+    while pixels.len() < nb_pixels {
+        let op = *ops.get(i)?;
+        let mut bytes = |n: usize| {
+            let bytes = ops.get(i + 1..i + 1 + n);
+            i += 1 + n;
+            bytes
+        };
+
+        let color = match op {
+            OP_RGB => {
+                let rgb = bytes(3)?;
+                Color::rgba(rgb[0], rgb[1], rgb[2], prev.0[3])
+            }
+            OP_RGBA => {
+                let rgba = bytes(4)?;
+                Color::rgba(rgba[0], rgba[1], rgba[2], rgba[3])
+            }
+            _ => match op & TAG_MASK {
+                OP_INDEX => {
+                    bytes(0);
+                    seen[op as usize]
+                }
+                OP_DIFF => {
+                    bytes(0);
+                    let d = |shift: u8| ((op >> shift) & 0b11).wrapping_sub(2);
+                    let (r, g, b, a) = prev.as_rgba();
+                    Color::rgba(
+                        r.wrapping_add(d(4)),
+                        g.wrapping_add(d(2)),
+                        b.wrapping_add(d(0)),
+                        a,
+                    )
+                }
+                OP_LUMA => {
+                    let dg = (op & 0b0011_1111).wrapping_sub(32);
+                    let next = bytes(1)?[0];
+                    let dr = dg.wrapping_add(next >> 4).wrapping_sub(8);
+                    let db = dg.wrapping_add(next & 0b1111).wrapping_sub(8);
+                    let (r, g, b, a) = prev.as_rgba();
+                    Color::rgba(
+                        r.wrapping_add(dr),
+                        g.wrapping_add(dg),
+                        b.wrapping_add(db),
+                        a,
+                    )
+                }
+                _ => {
+                    bytes(0);
+                    let run = (op & 0b0011_1111) as usize + 1;
+                    let run = usize::min(run, nb_pixels - pixels.len());
+                    pixels.resize(pixels.len() + run, prev);
+                    continue;
+                }
+            },
+        };
+
+        seen[index_of(color)] = color;
+        pixels.push(color);
+        prev = color;
+    }
+
+    // Anything between the last pixel and the end marker means the data is not what
+    // the header says it is
+    if i != ops.len() {
+        return None;
+    }
+
+    Some((w, h, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_same_pixels, noise, pattern, pixels, Rng};
+    use crate::{FbViewMut, Framebuffer, Rect};
+
+    fn round_trip<F: FbView>(fb: &F) {
+        let decoded = Framebuffer::from_qoi(&encode(fb)).expect("Cannot decode");
+        assert_same_pixels(&decoded, fb);
+    }
+
+    fn header(w: u32, h: u32, channels: u8) -> Vec<u8> {
+        let mut qoi = MAGIC.to_vec();
+        qoi.extend_from_slice(&w.to_be_bytes());
+        qoi.extend_from_slice(&h.to_be_bytes());
+        qoi.extend_from_slice(&[channels, 0]);
+        qoi
+    }
+
+    #[test]
+    fn round_trip_noise() {
+        round_trip(&noise(37, 23, 1));
+        round_trip(&noise(256, 256, 2));
+    }
+
+    // Every op: small and large differences, alpha changes, repeats and runs
+    #[test]
+    fn round_trip_mixed() {
+        let mut rng = Rng::new(3);
+        let mut fb = pattern(120, 80);
+        for _ in 0..2000 {
+            let (x, y) = (rng.next_u32() % 120, rng.next_u32() % 80);
+            let old = fb.get_pixel(x as i64, y as i64).unwrap();
+            let d = (rng.next_u32() % 5) as u8;
+            let color = Color::rgba(old.0[0].wrapping_add(d), old.0[1], old.0[2], old.0[3]);
+            fb.set_pixel(x as i64, y as i64, color);
+        }
+        round_trip(&fb);
+    }
+
+    #[test]
+    fn round_trip_runs() {
+        // Longer than the longest run op, ending on a run
+        let fb = Framebuffer::new_owned_filled(100, 3, Color::rgba(0, 0, 0, 255));
+        assert_eq!(
+            encode(&fb).len(),
+            HEADER_LEN + 300 / 62 + 1 + END_MARKER.len()
+        );
+        round_trip(&fb);
+
+        round_trip(&Framebuffer::new_owned_filled(62, 1, Color::BLUE));
+        round_trip(&Framebuffer::new_owned_filled(63, 1, Color::BLUE));
+    }
+
+    #[test]
+    fn round_trip_degenerate_shapes() {
+        round_trip(&Framebuffer::new_owned(0, 0));
+        round_trip(&Framebuffer::new_owned(0, 5));
+        round_trip(&noise(1, 1, 4));
+        round_trip(&noise(1, 50, 5));
+    }
+
+    #[test]
+    fn round_trip_subregion() {
+        let fb = noise(50, 50, 6);
+        round_trip(&fb.subregion(&Rect {
+            x0: 7,
+            y0: 3,
+            w: 31,
+            h: 40,
+        }));
+    }
+
+    #[test]
+    fn round_trip_real_images() {
+        let icon =
+            Framebuffer::from_png(include_bytes!("../../wasm_apps/chronometer/icons/play.png"));
+        round_trip(&icon);
+
+        let wallpaper = Framebuffer::from_png(include_bytes!("../../wallpaper.png"));
+        let qoi = encode(&wallpaper);
+        assert!(qoi.len() < wallpaper.size_bytes() / 2);
+        round_trip(&wallpaper);
+    }
+
+    // Worked out by hand from the specification
+    #[test]
+    fn reference_encoding() {
+        let data = [
+            Color::rgba(255, 0, 0, 255),
+            Color::rgba(255, 0, 0, 255),
+            Color::rgba(254, 0, 0, 255),
+            Color::ZERO,
+        ];
+        let fb = Framebuffer::<crate::BorrowedPixels>::new(&data, 2, 2);
+
+        let mut expected = header(2, 2, 4);
+        // Diff of -1 on red twice around a run of one, then the zero color from the index
+        expected.extend_from_slice(&[0x5a, 0xc0, 0x5a, 0x00]);
+        expected.extend_from_slice(&END_MARKER);
+        assert_eq!(encode(&fb), expected);
+    }
+
+    #[test]
+    fn decodes_rgb_images() {
+        let mut qoi = header(2, 1, 3);
+        qoi.extend_from_slice(&[OP_RGB, 10, 20, 30, OP_DIFF | 0b11_10_01]);
+        qoi.extend_from_slice(&END_MARKER);
+        let (w, h, pixels) = decode(&qoi).unwrap();
+        assert_eq!((w, h), (2, 1));
+        assert_eq!(pixels, [Color::rgb(10, 20, 30), Color::rgb(11, 20, 29)]);
+    }
+
+    #[test]
+    fn rejects_truncated_streams() {
+        let qoi = encode(&pattern(20, 20));
+        for len in 0..qoi.len() {
+            assert!(decode(&qoi[..len]).is_none(), "Cut at {}", len);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let qoi = encode(&noise(4, 4, 7));
+
+        let mut bad_magic = qoi.clone();
+        bad_magic[0] = b'p';
+        assert!(decode(&bad_magic).is_none());
+
+        let mut bad_channels = qoi.clone();
+        bad_channels[12] = 5;
+        assert!(decode(&bad_channels).is_none());
+
+        let mut bad_colorspace = qoi.clone();
+        bad_colorspace[13] = 2;
+        assert!(decode(&bad_colorspace).is_none());
+
+        // A header claiming a huge image must not allocate it
+        let mut huge = header(u32::MAX, u32::MAX, 4);
+        huge.extend_from_slice(&END_MARKER);
+        assert!(decode(&huge).is_none());
+        let mut large = header(20_000, 20_000, 4);
+        large.extend_from_slice(&[OP_RUN | 61, OP_RUN | 61]);
+        large.extend_from_slice(&END_MARKER);
+        assert!(decode(&large).is_none());
+    }
+
+    #[test]
+    fn rejects_extra_or_missing_data() {
+        let qoi = encode(&noise(4, 4, 8));
+        let ops_end = qoi.len() - END_MARKER.len();
+
+        let mut extra = qoi[..ops_end].to_vec();
+        extra.push(OP_INDEX);
+        extra.extend_from_slice(&END_MARKER);
+        assert!(decode(&extra).is_none());
+
+        let mut bad_marker = qoi.clone();
+        *bad_marker.last_mut().unwrap() = 2;
+        assert!(decode(&bad_marker).is_none());
+    }
+
+    // Garbage must give an error or an image of the announced size, never a panic
+    #[test]
+    fn survives_corruption() {
+        let fb = pattern(16, 16);
+        let qoi = encode(&fb);
+        let mut rng = Rng::new(9);
+        for _ in 0..2000 {
+            let mut corrupt = qoi.clone();
+            for _ in 0..1 + rng.next_u32() % 4 {
+                let i = HEADER_LEN + (rng.next_u32() as usize % (corrupt.len() - HEADER_LEN));
+                corrupt[i] = rng.next_u32() as u8;
+            }
+            if let Some((w, h, pixels)) = decode(&corrupt) {
+                assert_eq!(pixels.len(), (w * h) as usize);
+            }
+        }
+        assert_eq!(decode(&qoi).unwrap().2, pixels(&fb));
+    }
+}
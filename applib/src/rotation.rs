@@ -0,0 +1,311 @@
+use crate::{Color, FbDataMut, FbLine, FbView, FbViewMut, Framebuffer, Rect};
+use alloc::vec::Vec;
+
+// Shape of a w x h image once turned
+pub(crate) fn rotated_shape(shape: (u32, u32), quarter_turns: u32) -> (u32, u32) {
+    let (w, h) = shape;
+    match quarter_turns % 2 {
+        0 => (w, h),
+        _ => (h, w),
+    }
+}
+
+// See FbViewMut::copy_from_fb_rotated(). The destination is written one line at a time,
+// only the reads from the source are scattered.
+pub(crate) fn copy_rotated<F0: FbViewMut, F1: FbView>(
+    dst: &mut F0,
+    src: &F1,
+    pos: (i64, i64),
+    quarter_turns: u32,
+    blend: bool,
+) {
+    let turns = quarter_turns % 4;
+    if turns == 0 {
+        return dst.copy_from_fb(src, pos, blend);
+    }
+
+    let (src_w, src_h) = src.shape();
+    let (w, h) = rotated_shape((src_w, src_h), turns);
+    let (src_w, src_h) = (src_w as i64, src_h as i64);
+    let src_data = src.get_data();
+    let mut row: Vec<Color> = Vec::with_capacity(w as usize);
+
+    for v in 0..h as i64 {
+        let mut dst_line = dst.get_line_mut(pos.0, w, pos.1 + v);
+        if dst_line.data.is_empty() {
+            continue;
+        }
+
+        // Source pixel of the first pixel of the line, and the step from one to the next
+        let ((x0, y0), (dx, dy)) = match turns {
+            1 => ((v, src_h - 1), (0, -1)),
+            2 => ((src_w - 1, src_h - 1 - v), (-1, 0)),
+            _ => ((src_w - 1 - v, 0), (0, 1)),
+        };
+
+        // Only the part of the line that can be drawn. Pixels outside of the source data
+        // are left as they are, they can only be at either end of the line.
+        let u_start = dst_line.x_data_start as i64;
+        let u_end = u_start + dst_line.data.len() as i64;
+        row.clear();
+        let mut x_data_start = u_start as u32;
+        for u in u_start..u_end {
+            match src.get_offset_region_coords(x0 + u * dx, y0 + u * dy) {
+                Some(i) => row.push(src_data[i]),
+                None if row.is_empty() => x_data_start += 1,
+                None => break,
+            }
+        }
+
+        let src_line = FbLine {
+            data: &row,
+            x_data_start,
+            line_w: w,
+        };
+        dst_line.copy_from_line(&src_line, blend);
+    }
+}
+
+// Mirrored around the middle of the region. Only the pixels whose mirror is also inside
+// the clip are moved.
+pub(crate) fn flip_horizontal<T: FbDataMut>(fb: &mut Framebuffer<T>) {
+    let (x0, x1) = symmetric_span(fb.rect.x0, fb.rect.w, fb.clip.x0, fb.clip.w);
+    if x1 <= x0 {
+        return;
+    }
+    let area = Rect {
+        x0,
+        w: (x1 - x0) as u32,
+        ..fb.clip.clone()
+    };
+    fb.mark_damaged(&area);
+
+    let data_w = fb.data_w as i64;
+    let data = fb.data.as_mut_slice();
+    for y in area.y0..area.y0 + area.h as i64 {
+        let i = (y * data_w) as usize;
+        data[i + x0 as usize..i + x1 as usize].reverse();
+    }
+}
+
+pub(crate) fn flip_vertical<T: FbDataMut>(fb: &mut Framebuffer<T>) {
+    let (y0, y1) = symmetric_span(fb.rect.y0, fb.rect.h, fb.clip.y0, fb.clip.h);
+    if y1 <= y0 {
+        return;
+    }
+    let area = Rect {
+        y0,
+        h: (y1 - y0) as u32,
+        ..fb.clip.clone()
+    };
+    fb.mark_damaged(&area);
+
+    let data_w = fb.data_w as usize;
+    let (x0, w) = (area.x0 as usize, area.w as usize);
+    let data = fb.data.as_mut_slice();
+    let (mut top, mut bottom) = (y0 as usize, y1 as usize - 1);
+    while top < bottom {
+        let (head, tail) = data.split_at_mut(bottom * data_w);
+        let i = top * data_w + x0;
+        head[i..i + w].swap_with_slice(&mut tail[x0..x0 + w]);
+        top += 1;
+        bottom -= 1;
+    }
+}
+
+// The part of the clip which is its own mirror around the middle of the region, along
+// one axis. Returns its start and end.
+fn symmetric_span(
+    region_start: i64,
+    region_len: u32,
+    clip_start: i64,
+    clip_len: u32,
+) -> (i64, i64) {
+    let clip_end = clip_start + clip_len as i64;
+    // A position p is mirrored to mirror_sum - 1 - p
+    let mirror_sum = 2 * region_start + region_len as i64;
+    let start = i64::max(clip_start, mirror_sum - clip_end);
+    let end = i64::min(clip_end, mirror_sum - clip_start);
+    (start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_same_pixels, noise, pixels};
+    use crate::{BorrowedMutPixels, BorrowedPixels, OwnedPixels};
+
+    // One clockwise quarter turn, pixel by pixel
+    fn turned_once<F: FbView>(src: &F) -> Framebuffer<OwnedPixels> {
+        let (w, h) = src.shape();
+        let mut turned = Framebuffer::new_owned(h, w);
+        for y in 0..h as i64 {
+            for x in 0..w as i64 {
+                let color = src.get_pixel(x, y).unwrap();
+                turned.set_pixel(h as i64 - 1 - y, x, color);
+            }
+        }
+        turned
+    }
+
+    fn turned(src: &Framebuffer<OwnedPixels>, quarter_turns: u32) -> Framebuffer<OwnedPixels> {
+        let mut turned = src.rotated(0);
+        for _ in 0..quarter_turns % 4 {
+            turned = turned_once(&turned);
+        }
+        turned
+    }
+
+    // Shapes with odd and even sides, and single rows and columns
+    const SHAPES: [(u32, u32); 6] = [(1, 1), (1, 7), (6, 1), (5, 3), (4, 8), (13, 11)];
+
+    #[test]
+    fn matches_pixel_by_pixel_turns() {
+        for (w, h) in SHAPES {
+            let src = noise(w, h, 1);
+            for quarter_turns in 0..9 {
+                assert_same_pixels(&src.rotated(quarter_turns), &turned(&src, quarter_turns));
+            }
+        }
+    }
+
+    #[test]
+    fn four_turns_are_identity() {
+        for (w, h) in SHAPES {
+            let owned = noise(w, h, 2);
+            let mut fb = owned.rotated(1);
+            for _ in 0..3 {
+                fb = fb.rotated(1);
+            }
+            assert_same_pixels(&fb, &owned);
+
+            let data = pixels(&owned);
+            let borrowed = Framebuffer::<BorrowedPixels>::new(&data, w, h);
+            let fb = borrowed.rotated(1).rotated(1).rotated(1).rotated(1);
+            assert_same_pixels(&fb, &borrowed);
+            assert_same_pixels(&borrowed.rotated(3).rotated(1), &borrowed);
+            assert_same_pixels(&borrowed.rotated(2).rotated(2), &borrowed);
+        }
+    }
+
+    #[test]
+    fn turns_of_a_subregion() {
+        let fb = noise(20, 20, 3);
+        let rect = Rect {
+            x0: 3,
+            y0: 5,
+            w: 9,
+            h: 4,
+        };
+        let region = fb.subregion(&rect);
+        let copy = region.rotated(0);
+        for quarter_turns in 0..4 {
+            assert_same_pixels(
+                &region.rotated(quarter_turns),
+                &turned(&copy, quarter_turns),
+            );
+        }
+    }
+
+    // Only the part inside the destination is drawn, at the right place
+    #[test]
+    fn clipped_copies() {
+        let src = noise(7, 4, 4);
+        for quarter_turns in 1..4 {
+            let expected_turn = turned(&src, quarter_turns);
+            for pos in [(-3, -2), (0, 0), (5, 6), (8, -1), (-10, 0)] {
+                let mut fb = Framebuffer::new_owned(10, 9);
+                fb.copy_from_fb_rotated(&src, pos, quarter_turns, false);
+
+                let mut expected = Framebuffer::new_owned(10, 9);
+                expected.copy_from_fb(&expected_turn, pos, false);
+                assert_same_pixels(&fb, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn flips_match_half_turns() {
+        for (w, h) in SHAPES {
+            let src = noise(w, h, 5);
+            let mut fb = src.rotated(0);
+            fb.flip_horizontal();
+            fb.flip_vertical();
+            assert_same_pixels(&fb, &src.rotated(2));
+
+            let mut fb = src.rotated(0);
+            fb.flip_horizontal();
+            for y in 0..h as i64 {
+                for x in 0..w as i64 {
+                    assert_eq!(fb.get_pixel(x, y), src.get_pixel(w as i64 - 1 - x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn flipping_twice_is_identity() {
+        for (w, h) in SHAPES {
+            let owned = noise(w, h, 6);
+            let mut fb = owned.rotated(0);
+            fb.flip_horizontal();
+            fb.flip_horizontal();
+            fb.flip_vertical();
+            fb.flip_vertical();
+            assert_same_pixels(&fb, &owned);
+
+            let mut data = pixels(&owned);
+            let mut borrowed = Framebuffer::<BorrowedMutPixels>::new(&mut data, w, h);
+            borrowed.flip_vertical();
+            borrowed.flip_horizontal();
+            borrowed.flip_vertical();
+            borrowed.flip_horizontal();
+            assert_same_pixels(&borrowed, &owned);
+        }
+    }
+
+    // Pixels outside of the region are left alone
+    #[test]
+    fn flips_of_a_subregion() {
+        let src = noise(12, 10, 7);
+        let rect = Rect {
+            x0: 2,
+            y0: 3,
+            w: 5,
+            h: 4,
+        };
+        let mut fb = src.rotated(0);
+        let mut region = fb.subregion_mut(&rect);
+        region.flip_horizontal();
+        region.flip_vertical();
+
+        let mut expected = src.rotated(0);
+        expected.copy_from_fb(&src.subregion(&rect).rotated(2), (2, 3), false);
+        assert_same_pixels(&fb, &expected);
+
+        let mut region = fb.subregion_mut(&rect);
+        region.flip_vertical();
+        region.flip_horizontal();
+        assert_same_pixels(&fb, &src);
+    }
+
+    // A region sticking out of the framebuffer is mirrored around its own middle, so only
+    // the pixels whose mirror is also visible can move
+    #[test]
+    fn flips_of_a_clipped_region() {
+        let src = noise(10, 1, 8);
+        let mut fb = src.rotated(0);
+        let mut region = fb.subregion_mut(&Rect {
+            x0: 6,
+            y0: 0,
+            w: 6,
+            h: 1,
+        });
+        region.flip_horizontal();
+
+        // Middle at 9: 8 and 9 swap, 6 and 7 mirror to 11 and 10 which are outside
+        let mut expected = pixels(&src);
+        expected.swap(8, 9);
+        assert_eq!(pixels(&fb), expected);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{Color, FbLine, FbView, FbViewMut, Rect};
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ScaleFilter {
+    // Sharp pixels, for pixel art and enlarged icons
+    Nearest,
+    // Smooth, for photos and shrunk icons
+    Bilinear,
+}
+
+// Source pixels of a destination pixel along one axis, and the weight of the second
+// one out of 256 (always 0 with the nearest filter)
+struct AxisSample {
+    i0: i64,
+    i1: i64,
+    frac: u32,
+}
+
+// See FbViewMut::copy_from_fb_scaled()
+pub(crate) fn copy_scaled<F0: FbViewMut, F1: FbView>(
+    dst: &mut F0,
+    src: &F1,
+    src_rect: &Rect,
+    dst_rect: &Rect,
+    filter: ScaleFilter,
+    blend: bool,
+) {
+    if [src_rect.w, src_rect.h, dst_rect.w, dst_rect.h].contains(&0) {
+        return;
+    }
+
+    let (src_w, src_h) = src.shape();
+    let (dst_w, dst_h) = dst.shape();
+
+    let (x_start, cols) = axis_samples(
+        (src_rect.x0, src_rect.w, src_w),
+        (dst_rect.x0, dst_rect.w, dst_w),
+        filter,
+    );
+    let (y_start, rows) = axis_samples(
+        (src_rect.y0, src_rect.h, src_h),
+        (dst_rect.y0, dst_rect.h, dst_h),
+        filter,
+    );
+    if cols.is_empty() {
+        return;
+    }
+
+    let mut row_buf = vec![Color::ZERO; cols.len()];
+
+    for (y, row) in (y_start..).zip(rows.iter()) {
+        // Whole rows of the source, the samples are all inside it
+        let line_0 = src.get_line(0, src_w, row.i0).data;
+        let line_1 = src.get_line(0, src_w, row.i1).data;
+
+        for (pixel, col) in row_buf.iter_mut().zip(cols.iter()) {
+            *pixel = match filter {
+                ScaleFilter::Nearest => line_0[col.i0 as usize],
+                ScaleFilter::Bilinear => sample_bilinear([line_0, line_1], col, row.frac),
+            };
+        }
+
+        let line_w = row_buf.len() as u32;
+        let src_line = FbLine {
+            data: &row_buf,
+            x_data_start: 0,
+            line_w,
+        };
+        dst.get_line_mut(x_start, line_w, y)
+            .copy_from_line(&src_line, blend);
+    }
+}
+
+// Destination pixels are mapped to source positions in 16.16 fixed point, stepping from
+// one pixel center to the next. Only the destination pixels that are inside the
+// destination and map inside the source get a sample, and since the mapping is monotonic
+// they are contiguous. Returns the first of them along with the samples.
+fn axis_samples(
+    src: (i64, u32, u32),
+    dst: (i64, u32, u32),
+    filter: ScaleFilter,
+) -> (i64, Vec<AxisSample>) {
+    let (src_start, src_len, src_size) = src;
+    let (dst_start, dst_len, dst_size) = dst;
+
+    // Source pixels that can be read
+    let lo = i64::max(src_start, 0);
+    let hi = i64::min(src_start + src_len as i64, src_size as i64) - 1;
+
+    let d_lo = i64::max(dst_start, 0);
+    let d_hi = i64::min(dst_start + dst_len as i64, dst_size as i64) - 1;
+
+    let mut samples = Vec::new();
+    let mut first = d_lo;
+    if lo > hi || d_lo > d_hi {
+        return (first, samples);
+    }
+
+    let step = ((src_len as i64) << 16) / dst_len as i64;
+    // i128, the destination rect may start very far out
+    let mut pos = (((src_start as i128) << 16)
+        + (2 * (d_lo - dst_start) as i128 + 1) * step as i128 / 2) as i64;
+
+    for d in d_lo..=d_hi {
+        let i = pos >> 16;
+        if (lo..=hi).contains(&i) {
+            if samples.is_empty() {
+                first = d;
+            }
+            let sample = match filter {
+                ScaleFilter::Nearest => AxisSample {
+                    i0: i,
+                    i1: i,
+                    frac: 0,
+                },
+                // Between the centers of the two closest pixels, clamped at the edges
+                ScaleFilter::Bilinear => {
+                    let p = (pos - 0x8000).clamp(lo << 16, hi << 16);
+                    let i0 = p >> 16;
+                    AxisSample {
+                        i0,
+                        i1: i64::min(i0 + 1, hi),
+                        frac: ((p & 0xffff) >> 8) as u32,
+                    }
+                }
+            };
+            samples.push(sample);
+        } else if !samples.is_empty() {
+            break;
+        }
+        pos += step;
+    }
+
+    (first, samples)
+}
+
+// Colors are weighted by their alpha, so that transparent pixels do not darken the edges
+fn sample_bilinear(lines: [&[Color]; 2], col: &AxisSample, fy: u32) -> Color {
+    let fx = col.frac;
+    let (i0, i1) = (col.i0 as usize, col.i1 as usize);
+    let samples = [
+        (lines[0][i0], (256 - fx) * (256 - fy)),
+        (lines[0][i1], fx * (256 - fy)),
+        (lines[1][i0], (256 - fx) * fy),
+        (lines[1][i1], fx * fy),
+    ];
+
+    // The weights add up to 2^16
+    let mut sums = [0u64; 4];
+    for (color, weight) in samples {
+        let (r, g, b, a) = color.as_rgba();
+        let weight = weight as u64 * a as u64;
+        sums[0] += r as u64 * weight;
+        sums[1] += g as u64 * weight;
+        sums[2] += b as u64 * weight;
+        sums[3] += weight;
+    }
+
+    let [r, g, b, a] = sums;
+    if a == 0 {
+        return Color::ZERO;
+    }
+    Color::rgba(
+        (r / a) as u8,
+        (g / a) as u8,
+        (b / a) as u8,
+        ((a + 0x8000) >> 16) as u8,
+    )
+}
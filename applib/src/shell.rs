@@ -0,0 +1,273 @@
+// Commands of the kernel's serial shell. Parsing is kept apart from running them, so
+// that it can be tested on the host.
+
+use alloc::string::{String, ToString};
+use core::str::FromStr;
+use log::LevelFilter;
+
+pub const HELP: &str = "\
+ps                           apps and their stats
+mem                          heap stats, by block size and by tag
+net                          network interface and sockets
+irq                          interrupt counts and input-to-cursor latency
+irq reset                    restart the latency measurement
+files                        stored files and disk space
+rm <file>                    delete a stored file
+mv <file> <new name>         rename a stored file, whose name has no spaces
+kill <app>                   close an app
+loglevel                     log levels
+loglevel <target> <level>    off, error, warn, info, debug, trace, or reset
+loglevel default <level>     level of the targets without their own
+capture                      packet capture status
+capture start|stop           capture frames, streamed over HTTP at /capture.pcap
+capture snaplen <bytes>      frames are cut to that length, 256 by default
+screenshot                   save the screen to storage
+reboot";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    Ps,
+    Mem,
+    Net,
+    Irq,
+    IrqReset,
+    Files,
+    Remove(String),
+    Rename {
+        name: String,
+        new_name: String,
+    },
+    Kill(String),
+    LogLevels,
+    // A None level gives the target the default one again
+    LogLevel {
+        target: String,
+        level: Option<LevelFilter>,
+    },
+    Capture,
+    CaptureStart,
+    CaptureStop,
+    CaptureSnaplen(usize),
+    Screenshot,
+    Reboot,
+}
+
+impl Command {
+    // None if the command is unknown or its arguments are wrong
+    pub fn parse(line: &str) -> Option<Command> {
+        let line = line.trim();
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim();
+
+        match (name, args) {
+            ("help", "") => Some(Command::Help),
+            ("ps", "") => Some(Command::Ps),
+            ("mem", "") => Some(Command::Mem),
+            ("net", "") => Some(Command::Net),
+            ("irq", "") => Some(Command::Irq),
+            ("irq", "reset") => Some(Command::IrqReset),
+            ("files", "") => Some(Command::Files),
+            ("rm", name) if !name.is_empty() => Some(Command::Remove(name.to_string())),
+            ("mv", args) => {
+                let (name, new_name) = args.split_once(' ')?;
+                Some(Command::Rename {
+                    name: name.to_string(),
+                    new_name: new_name.trim().to_string(),
+                })
+            }
+            ("kill", app_name) if !app_name.is_empty() => Some(Command::Kill(app_name.to_string())),
+            ("loglevel", "") => Some(Command::LogLevels),
+            ("loglevel", args) => {
+                // The level goes last, app targets may have spaces
+                let (target, level) = args.rsplit_once(' ')?;
+                let level = match level {
+                    "reset" => None,
+                    level => Some(LevelFilter::from_str(level).ok()?),
+                };
+                Some(Command::LogLevel {
+                    target: target.trim().to_string(),
+                    level,
+                })
+            }
+            ("capture", "") => Some(Command::Capture),
+            ("capture", "start") => Some(Command::CaptureStart),
+            ("capture", "stop") => Some(Command::CaptureStop),
+            ("capture", args) => {
+                let snaplen = usize::from_str(args.strip_prefix("snaplen ")?.trim()).ok()?;
+                match snaplen {
+                    0 => None,
+                    snaplen => Some(Command::CaptureSnaplen(snaplen)),
+                }
+            }
+            ("screenshot", "") => Some(Command::Screenshot),
+            ("reboot", "") => Some(Command::Reboot),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<Command> {
+        Command::parse(line)
+    }
+
+    fn log_level(target: &str, level: Option<LevelFilter>) -> Option<Command> {
+        Some(Command::LogLevel {
+            target: target.to_string(),
+            level,
+        })
+    }
+
+    #[test]
+    fn commands_without_arguments() {
+        assert_eq!(parse("help"), Some(Command::Help));
+        assert_eq!(parse("ps"), Some(Command::Ps));
+        assert_eq!(parse("  mem \t"), Some(Command::Mem));
+        assert_eq!(parse("net"), Some(Command::Net));
+        assert_eq!(parse("irq"), Some(Command::Irq));
+        assert_eq!(parse("irq reset"), Some(Command::IrqReset));
+        assert_eq!(parse("irq clear"), None);
+        assert_eq!(parse("files"), Some(Command::Files));
+        assert_eq!(parse("screenshot"), Some(Command::Screenshot));
+        assert_eq!(parse("reboot"), Some(Command::Reboot));
+
+        // No arguments expected, names are case-sensitive
+        assert_eq!(parse("reboot now"), None);
+        assert_eq!(parse("PS"), None);
+        assert_eq!(parse("psx"), None);
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("   "), None);
+    }
+
+    #[test]
+    fn files_and_apps() {
+        assert_eq!(
+            parse("rm notes.txt"),
+            Some(Command::Remove("notes.txt".into()))
+        );
+        assert_eq!(
+            parse("rm   my notes.txt "),
+            Some(Command::Remove("my notes.txt".into()))
+        );
+        assert_eq!(parse("rm"), None);
+        assert_eq!(parse("rm   "), None);
+
+        let rename = |name: &str, new_name: &str| {
+            Some(Command::Rename {
+                name: name.into(),
+                new_name: new_name.into(),
+            })
+        };
+        assert_eq!(parse("mv a.txt b.txt"), rename("a.txt", "b.txt"));
+        // Only the new name can have spaces
+        assert_eq!(
+            parse("mv a.txt  new name.txt"),
+            rename("a.txt", "new name.txt")
+        );
+        assert_eq!(parse("mv a.txt"), None);
+        assert_eq!(parse("mv"), None);
+
+        assert_eq!(
+            parse("kill Text Editor"),
+            Some(Command::Kill("Text Editor".into()))
+        );
+        assert_eq!(parse("kill"), None);
+    }
+
+    #[test]
+    fn log_levels() {
+        assert_eq!(parse("loglevel"), Some(Command::LogLevels));
+        assert_eq!(
+            parse("loglevel net debug"),
+            log_level("net", Some(LevelFilter::Debug))
+        );
+        assert_eq!(
+            parse("loglevel net  WARN"),
+            log_level("net", Some(LevelFilter::Warn))
+        );
+        assert_eq!(
+            parse("loglevel net off"),
+            log_level("net", Some(LevelFilter::Off))
+        );
+        assert_eq!(parse("loglevel net reset"), log_level("net", None));
+        assert_eq!(
+            parse("loglevel default trace"),
+            log_level("default", Some(LevelFilter::Trace))
+        );
+        // App targets may have spaces, the level is the last word
+        assert_eq!(
+            parse("loglevel app::Text Editor info"),
+            log_level("app::Text Editor", Some(LevelFilter::Info))
+        );
+
+        assert_eq!(parse("loglevel net"), None);
+        assert_eq!(parse("loglevel net loud"), None);
+        assert_eq!(parse("loglevel net 3"), None);
+    }
+
+    #[test]
+    fn capture() {
+        assert_eq!(parse("capture"), Some(Command::Capture));
+        assert_eq!(parse("capture start"), Some(Command::CaptureStart));
+        assert_eq!(parse("capture  stop "), Some(Command::CaptureStop));
+        assert_eq!(
+            parse("capture snaplen 128"),
+            Some(Command::CaptureSnaplen(128))
+        );
+        assert_eq!(
+            parse("capture snaplen  65535"),
+            Some(Command::CaptureSnaplen(65535))
+        );
+
+        assert_eq!(parse("capture snaplen 0"), None);
+        assert_eq!(parse("capture snaplen -1"), None);
+        assert_eq!(parse("capture snaplen"), None);
+        assert_eq!(parse("capture snaplen big"), None);
+        assert_eq!(parse("capture restart"), None);
+    }
+
+    // Every usage in the help parses once its placeholders are filled in
+    #[test]
+    fn help_matches_parser() {
+        let placeholders = [
+            ("<file>", "a.txt"),
+            ("<new name>", "b.txt"),
+            ("<app>", "Paint"),
+            ("<target>", "net"),
+            ("<level>", "info"),
+            ("<bytes>", "128"),
+        ];
+
+        for help_line in HELP.lines() {
+            let usage = help_line.split("  ").next().unwrap();
+            let mut line = usage.to_string();
+            for (placeholder, value) in placeholders {
+                line = line.replace(placeholder, value);
+            }
+
+            let alternatives = match line.split_once('|') {
+                Some((first, second)) => {
+                    let (prefix, first) = first.rsplit_once(' ').unwrap();
+                    [
+                        format!("{} {}", prefix, first),
+                        format!("{} {}", prefix, second),
+                    ]
+                    .to_vec()
+                }
+                None => [line].to_vec(),
+            };
+            for line in alternatives {
+                assert!(
+                    parse(&line).is_some(),
+                    "{:?} from help line {:?}",
+                    line,
+                    help_line
+                );
+            }
+        }
+    }
+}
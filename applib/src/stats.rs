@@ -0,0 +1,112 @@
+// Stats snapshots shared between the kernel and WASM apps.
+// Fixed-size types only, since those are copied as-is into WASM memory.
+
+pub const APP_NAME_MAX_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct SystemStatsEntry {
+    pub frametime_used: f64,
+    pub net_recv: u64,
+    pub net_sent: u64,
+    pub heap_allocated: u64,
+    pub heap_total: u64,
+    // Highest heap_allocated since boot
+    pub heap_peak: u64,
+    // Heap used so far, live or waiting to be reclaimed
+    pub heap_explored: u64,
+    pub flushed_pixels: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct AppStatsEntry {
+    name: [u8; APP_NAME_MAX_LEN],
+    name_len: u32,
+    running: u32,
+    pub mem_used: u64,
+    // Kernel heap bytes allocated for the app during its last step
+    pub kernel_alloc: u64,
+    pub net_recv: u64,
+    pub net_sent: u64,
+    pub frametime_used: f64,
+}
+
+impl AppStatsEntry {
+    pub fn new(name: &str, running: bool) -> Self {
+        let name_len = usize::min(name.len(), APP_NAME_MAX_LEN);
+        let mut name_buf = [0u8; APP_NAME_MAX_LEN];
+        name_buf[..name_len].copy_from_slice(&name.as_bytes()[..name_len]);
+
+        AppStatsEntry {
+            name: name_buf,
+            name_len: name_len as u32,
+            running: running as u32,
+            mem_used: 0,
+            kernel_alloc: 0,
+            net_recv: 0,
+            net_sent: 0,
+            frametime_used: 0.0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        let name_len = usize::min(self.name_len as usize, APP_NAME_MAX_LEN);
+        core::str::from_utf8(&self.name[..name_len]).unwrap_or("<invalid>")
+    }
+
+    pub fn running(&self) -> bool {
+        self.running != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct NetworkInterfaceEntry {
+    pub mac: [u8; 6],
+    // Of the subnet, 0 while the interface has no address
+    pub prefix_len: u8,
+    link_up: u8,
+    pub addr: [u8; 4],
+    // All zeroes without a gateway
+    pub gateway: [u8; 4],
+    dhcp: u32,
+    // Out of the interfaces with a gateway, the lowest one carries the traffic for none
+    // of the subnets
+    pub route_priority: u32,
+}
+
+impl NetworkInterfaceEntry {
+    pub fn new(link_up: bool, dhcp: bool) -> Self {
+        NetworkInterfaceEntry {
+            mac: [0; 6],
+            prefix_len: 0,
+            link_up: link_up as u8,
+            addr: [0; 4],
+            gateway: [0; 4],
+            dhcp: dhcp as u32,
+            route_priority: 0,
+        }
+    }
+
+    pub fn link_up(&self) -> bool {
+        self.link_up != 0
+    }
+
+    // Whether the address comes from DHCP rather than a static config
+    pub fn dhcp(&self) -> bool {
+        self.dhcp != 0
+    }
+}
+
+// How the wall clock is synced to the time server, all zeroes before the first sync
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct TimeSyncEntry {
+    // UNIX time, in s
+    pub last_sync: f64,
+    // Of the wall clock at the last sync, in s, positive when it was behind
+    pub offset: f64,
+    // Of the TSC frequency, in ppm, 0 until two syncs
+    pub drift_ppm: f64,
+}
@@ -1,6 +1,14 @@
+use crate::drawing::gradient::GradientDirection;
+use crate::drawing::nine_patch::{EdgeMode, Insets};
 use crate::Color;
 
 const FONT_FAMILY_NAME_MAX_LEN: usize = 64;
+const CHROME_NAME_MAX_LEN: usize = 32;
+
+// The stylesheet is copied as is into the memory of the apps, so this must be bumped
+// whenever its layout changes. The kernel refuses to give it to apps built with
+// another version.
+pub const STYLESHEET_ABI_VERSION: u32 = 8;
 
 #[derive(Clone)]
 #[repr(C)]
@@ -8,6 +16,15 @@ pub struct StyleSheet {
     pub colors: StyleSheetColors,
     pub margin: u32,
     pub text: StyleSheetText,
+    // In milliseconds, how long the pointer must rest on a widget before its tooltip shows
+    pub tooltip_delay: u32,
+    // Outline drawn around the widget that has the keyboard focus
+    pub focus_ring_width: u32,
+    // Length of the dashes and gaps of the focus ring, 0 for a solid line
+    pub focus_ring_dash: u32,
+    pub widgets: WidgetStyle,
+    pub titlebar: TitleBarStyle,
+    pub chrome: ChromeStyle,
 }
 
 #[derive(Clone)]
@@ -23,10 +40,17 @@ pub struct StyleSheetColors {
     pub purple: Color,
     pub element: Color,
     pub frame: Color,
+    // Decorations of the window with the keyboard focus
+    pub frame_focused: Color,
     pub text: Color,
     pub accent: Color,
     pub editable: Color,
     pub outline: Color,
+    // Text and marks of disabled widgets, and their background
+    pub disabled: Color,
+    pub disabled_element: Color,
+    pub tooltip: Color,
+    pub focus_ring: Color,
 }
 
 #[derive(Clone, Debug)]
@@ -39,6 +63,9 @@ pub struct StyleSheetText {
     font_family_len: u32,
 
     pub sizes: TextSizes,
+    // Subpixel positioning and gamma-correct blending of glyphs. Off, glyphs snap to
+    // whole pixels like in bitmap fonts.
+    pub smooth: bool,
 }
 
 impl StyleSheetText {
@@ -52,6 +79,7 @@ impl StyleSheetText {
             font_family_bytes,
             font_family_len: len as u32,
             sizes,
+            smooth: true,
         }
     }
 
@@ -68,3 +96,123 @@ pub struct TextSizes {
     pub medium: u32,
     pub large: u32,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WidgetState {
+    Normal,
+    Hover,
+    // Held down with the pointer
+    Pressed,
+    // Toggled on, or focused for text fields
+    Selected,
+    Disabled,
+}
+
+#[derive(Clone, Copy, Hash)]
+#[repr(C)]
+pub struct WidgetColors {
+    pub bg: Color,
+    pub border: Color,
+    pub text: Color,
+}
+
+impl WidgetColors {
+    // For transitions between states, t goes from 0 (self) to 1 (other)
+    pub fn lerp(&self, other: &WidgetColors, t: f32) -> WidgetColors {
+        WidgetColors {
+            bg: self.bg.lerp(other.bg, t),
+            border: self.border.lerp(other.border, t),
+            text: self.text.lerp(other.text, t),
+        }
+    }
+}
+
+// How buttons and text fields look in each state
+#[derive(Clone)]
+#[repr(C)]
+pub struct WidgetStyle {
+    pub normal: WidgetColors,
+    pub hover: WidgetColors,
+    pub pressed: WidgetColors,
+    pub selected: WidgetColors,
+    pub disabled: WidgetColors,
+    // No border if 0
+    pub border_width: u32,
+    pub border_radius: u32,
+    // Between the border and the contents
+    pub padding: u32,
+}
+
+impl WidgetStyle {
+    pub fn colors(&self, state: WidgetState) -> &WidgetColors {
+        match state {
+            WidgetState::Normal => &self.normal,
+            WidgetState::Hover => &self.hover,
+            WidgetState::Pressed => &self.pressed,
+            WidgetState::Selected => &self.selected,
+            WidgetState::Disabled => &self.disabled,
+        }
+    }
+}
+
+// Window decorations, drawn by the kernel
+#[derive(Clone)]
+#[repr(C)]
+pub struct TitleBarStyle {
+    // Otherwise, filled with the frame color
+    pub gradient: bool,
+    pub gradient_start: Color,
+    pub gradient_end: Color,
+    pub gradient_direction: GradientDirection,
+    // Of the frame colors on the decorations, the wallpaper and windows behind show
+    // through below 255. The gradient is not drawn then.
+    pub frame_alpha: u8,
+}
+
+// Nine-patch images of the icon registry, drawn instead of the flat fills when set.
+// Tintable images take the background color of the widget state.
+#[derive(Clone)]
+#[repr(C)]
+pub struct ChromeStyle {
+    pub button: ChromeImage,
+    // Dialogs
+    pub panel: ChromeImage,
+}
+
+#[derive(Clone, Debug)]
+#[repr(C)]
+pub struct ChromeImage {
+    // Same as StyleSheetText, no pointers allowed
+    name_bytes: [u8; CHROME_NAME_MAX_LEN],
+    name_len: u32,
+
+    pub insets: Insets,
+    pub edges: EdgeMode,
+}
+
+impl ChromeImage {
+    pub fn new(icon_name: &str, insets: Insets, edges: EdgeMode) -> Self {
+        let src_bytes = icon_name.as_bytes();
+        let len = src_bytes.len();
+        let mut name_bytes = [0u8; CHROME_NAME_MAX_LEN];
+        name_bytes[..len].copy_from_slice(src_bytes);
+
+        Self {
+            name_bytes,
+            name_len: len as u32,
+            insets,
+            edges,
+        }
+    }
+
+    // Flat fills
+    pub fn none() -> Self {
+        Self::new("", Insets::default(), EdgeMode::Stretch)
+    }
+
+    pub fn icon_name(&self) -> Option<&str> {
+        let name = str::from_utf8(&self.name_bytes[..self.name_len as usize])
+            .expect("Invalid stylesheet data");
+        (!name.is_empty()).then_some(name)
+    }
+}
@@ -0,0 +1,113 @@
+// Images for the unit tests, the same ones on every run
+
+use crate::{Color, FbView, FbViewMut, Framebuffer, OwnedPixels};
+use alloc::vec::Vec;
+
+// xorshift64, seeded by the caller
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 32) as u32
+    }
+}
+
+// Every channel random, alpha included
+pub fn noise(w: u32, h: u32, seed: u64) -> Framebuffer<OwnedPixels> {
+    let mut rng = Rng::new(seed);
+    let mut fb = Framebuffer::new_owned(w, h);
+    for y in 0..h {
+        for x in 0..w {
+            let color = Color(rng.next_u32().to_le_bytes());
+            fb.set_pixel(x as i64, y as i64, color);
+        }
+    }
+    fb
+}
+
+// Smooth areas with a few sharp edges, closer to what is on screen than noise
+pub fn pattern(w: u32, h: u32) -> Framebuffer<OwnedPixels> {
+    let mut fb = Framebuffer::new_owned(w, h);
+    for y in 0..h {
+        for x in 0..w {
+            let color = match (x / 16 + y / 16) % 3 {
+                0 => Color::rgb((x * 255 / w) as u8, (y * 255 / h) as u8, 128),
+                1 => Color::rgba(30, 60, 90, 200),
+                _ => Color::rgba(255, 255, 255, (x % 7 * 40) as u8),
+            };
+            fb.set_pixel(x as i64, y as i64, color);
+        }
+    }
+    fb
+}
+
+pub fn pixels<F: FbView>(fb: &F) -> Vec<Color> {
+    let (w, h) = fb.shape();
+    (0..h)
+        .flat_map(|y| (0..w).map(move |x| (x, y)))
+        .map(|(x, y)| fb.get_pixel(x as i64, y as i64).unwrap())
+        .collect()
+}
+
+pub fn assert_same_pixels<F1: FbView, F2: FbView>(a: &F1, b: &F2) {
+    assert_eq!(a.shape(), b.shape(), "Different shapes");
+    let (w, _) = a.shape();
+    let first_diff = pixels(a)
+        .iter()
+        .zip(pixels(b).iter())
+        .position(|(pa, pb)| pa != pb);
+    if let Some(i) = first_diff {
+        let (x, y) = (i as u32 % w, i as u32 / w);
+        panic!(
+            "Pixels differ at ({}, {}): {:?} != {:?}",
+            x,
+            y,
+            a.get_pixel(x as i64, y as i64).unwrap(),
+            b.get_pixel(x as i64, y as i64).unwrap()
+        );
+    }
+}
+
+// Text drawing depends on the global smooth text setting, tests that change it or draw
+// text hold this
+pub fn text_mode_lock() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Compares against applib/golden/<name>.png, within one level per channel to allow for
+// float differences between platforms. UPDATE_GOLDEN=1 writes the images instead.
+pub fn assert_golden<F: FbView>(name: &str, fb: &F) {
+    let path = format!("{}/golden/{}.png", env!("CARGO_MANIFEST_DIR"), name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, crate::png::encode(fb)).unwrap();
+        return;
+    }
+
+    let golden = std::fs::read(&path).unwrap_or_else(|_| {
+        panic!("No golden image {}, UPDATE_GOLDEN=1 creates it", path);
+    });
+    let golden = Framebuffer::from_png(&golden);
+    assert_eq!(golden.shape(), fb.shape(), "Shape of {}", name);
+
+    let (w, _) = fb.shape();
+    for (i, (a, b)) in pixels(&golden).iter().zip(pixels(fb).iter()).enumerate() {
+        let close = a.0.iter().zip(b.0.iter()).all(|(x, y)| x.abs_diff(*y) <= 1);
+        assert!(
+            close,
+            "{} differs at ({}, {}): {:?} != {:?}",
+            name,
+            i as u32 % w,
+            i as u32 / w,
+            a,
+            b
+        );
+    }
+}
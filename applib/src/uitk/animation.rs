@@ -0,0 +1,155 @@
+use alloc::collections::BTreeMap;
+use num::traits::float::FloatCore;
+
+use crate::content::ContentId;
+use crate::uitk::{UiContext, UiStore};
+use crate::FbViewMut;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    // Must be called every frame, returns the value eased toward the target.
+    // The first time, the value starts at the target.
+    pub fn animate(&mut self, id: ContentId, target: f32, duration_ms: f64) -> f32 {
+        self.animations
+            .animate(id, target, duration_ms, Easing::EaseOut)
+    }
+
+    pub fn animate_eased(
+        &mut self,
+        id: ContentId,
+        target: f32,
+        duration_ms: f64,
+        easing: Easing,
+    ) -> f32 {
+        self.animations.animate(id, target, duration_ms, easing)
+    }
+
+    // Jumps to a value without easing, e.g. so that something appearing animates from it
+    pub fn reset_animation(&mut self, id: ContentId, value: f32) {
+        self.animations.reset(id, value);
+    }
+}
+
+impl UiStore {
+    // Same as UiContext::animate(), with the time of the last context
+    pub fn animate(&mut self, id: ContentId, target: f32, duration_ms: f64) -> f32 {
+        self.animations
+            .animate(id, target, duration_ms, Easing::EaseOut)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    // For t between 0 and 1
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t.powi(3),
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => match t < 0.5 {
+                true => 4.0 * t.powi(3),
+                false => 1.0 - (2.0 - 2.0 * t).powi(3) / 2.0,
+            },
+        }
+    }
+}
+
+struct Animation {
+    from: f32,
+    to: f32,
+    // In ms, in the time of the contexts
+    start: f64,
+    duration: f64,
+    easing: Easing,
+    last_frame: f64,
+}
+
+impl Animation {
+    fn value(&self, time: f64) -> f32 {
+        if self.duration <= 0.0 {
+            return self.to;
+        }
+        let t = ((time - self.start) / self.duration) as f32;
+        self.from + (self.to - self.from) * self.easing.apply(t)
+    }
+}
+
+// Only driven by the times given to UiStore::get_context(), so that replaying the
+// same frames gives the same values
+pub(crate) struct AnimationState {
+    animations: BTreeMap<ContentId, Animation>,
+    frame_time: f64,
+}
+
+impl AnimationState {
+    pub(crate) fn new() -> Self {
+        AnimationState {
+            animations: BTreeMap::new(),
+            frame_time: f64::NEG_INFINITY,
+        }
+    }
+
+    // Animations not used during the previous frame are dropped. Apps may get several
+    // contexts in one frame, only the first one counts.
+    pub(crate) fn new_frame(&mut self, time: f64) {
+        if self.frame_time == time {
+            return;
+        }
+        let prev_time = self.frame_time;
+        self.animations
+            .retain(|_, animation| animation.last_frame >= prev_time);
+        self.frame_time = time;
+    }
+
+    pub(crate) fn animate(
+        &mut self,
+        id: ContentId,
+        target: f32,
+        duration_ms: f64,
+        easing: Easing,
+    ) -> f32 {
+        let time = self.frame_time;
+        let animation = self.animations.entry(id).or_insert(Animation {
+            from: target,
+            to: target,
+            start: time,
+            duration: duration_ms,
+            easing,
+            last_frame: time,
+        });
+
+        // Retargeting starts from where the value is now
+        if animation.to != target {
+            animation.from = animation.value(time);
+            animation.to = target;
+            animation.start = time;
+        }
+        animation.duration = duration_ms;
+        animation.easing = easing;
+        animation.last_frame = time;
+
+        animation.value(time)
+    }
+
+    pub(crate) fn reset(&mut self, id: ContentId, value: f32) {
+        let time = self.frame_time;
+        self.animations.insert(
+            id,
+            Animation {
+                from: value,
+                to: value,
+                start: time,
+                duration: 0.0,
+                easing: Easing::Linear,
+                last_frame: time,
+            },
+        );
+    }
+}
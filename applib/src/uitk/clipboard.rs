@@ -0,0 +1,28 @@
+use alloc::string::String;
+
+// Where the text widgets copy to and paste from. Apps can plug in the system
+// clipboard with UiStore::set_clipboard(), otherwise it is local to the UiStore.
+pub trait Clipboard {
+    fn get(&mut self) -> Option<String>;
+    fn set(&mut self, text: &str);
+}
+
+pub(crate) struct LocalClipboard {
+    text: Option<String>,
+}
+
+impl LocalClipboard {
+    pub(crate) fn new() -> Self {
+        LocalClipboard { text: None }
+    }
+}
+
+impl Clipboard for LocalClipboard {
+    fn get(&mut self) -> Option<String> {
+        self.text.clone()
+    }
+
+    fn set(&mut self, text: &str) {
+        self.text = Some(text.into());
+    }
+}
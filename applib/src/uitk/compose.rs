@@ -0,0 +1,260 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::content::ContentId;
+use crate::drawing::accents::{self, Accent};
+use crate::input::{InputEvent, InputState, Keycode};
+use crate::uitk::text::typed_char;
+use crate::uitk::UiStore;
+
+impl UiStore {
+    pub fn set_compose_config(&mut self, config: ComposeConfig) {
+        self.compose.config = config;
+        self.compose.pending = None;
+    }
+}
+
+#[derive(Clone)]
+pub struct ComposeConfig {
+    // Typed with Alt held, they combine with the next character, e.g. Alt+' then e gives é
+    pub dead_keys: Vec<(char, Accent)>,
+    // Pressed and released alone, it combines the next two characters, e.g. a then e gives æ
+    pub compose_key: Option<Keycode>,
+}
+
+impl Default for ComposeConfig {
+    fn default() -> Self {
+        ComposeConfig {
+            dead_keys: vec![
+                ('\'', Accent::Acute),
+                ('`', Accent::Grave),
+                ('^', Accent::Circumflex),
+                ('"', Accent::Diaeresis),
+                ('~', Accent::Tilde),
+            ],
+            compose_key: Some(Keycode::KEY_RIGHTCTRL),
+        }
+    }
+}
+
+// Two-character sequences of the compose key, also accepted in reverse order
+const COMPOSE_SEQUENCES: [(char, char, char); 35] = [
+    ('a', 'e', 'æ'),
+    ('A', 'E', 'Æ'),
+    ('s', 's', 'ß'),
+    ('t', 'h', 'þ'),
+    ('T', 'H', 'Þ'),
+    ('d', 'h', 'ð'),
+    ('D', 'H', 'Ð'),
+    ('!', '!', '¡'),
+    ('?', '?', '¿'),
+    ('<', '<', '«'),
+    ('>', '>', '»'),
+    ('o', 'c', '©'),
+    ('o', 'r', '®'),
+    ('+', '-', '±'),
+    ('o', 'o', '°'),
+    ('1', '2', '½'),
+    ('1', '4', '¼'),
+    ('3', '4', '¾'),
+    ('^', '1', '¹'),
+    ('^', '2', '²'),
+    ('^', '3', '³'),
+    ('L', '-', '£'),
+    ('Y', '=', '¥'),
+    ('c', '|', '¢'),
+    ('s', 'o', '§'),
+    ('P', '!', '¶'),
+    ('x', 'x', '×'),
+    (':', '-', '÷'),
+    ('.', '.', '·'),
+    ('m', 'u', 'µ'),
+    ('-', '-', '\u{ad}'),
+    (' ', ' ', '\u{a0}'),
+    ('a', '_', 'ª'),
+    ('o', '_', 'º'),
+    ('|', '|', '¦'),
+];
+
+// Also for the compose key, an accent char followed by a letter, e.g. ' then e gives é
+fn accent_of(c: char) -> Option<Accent> {
+    match c {
+        '\'' => Some(Accent::Acute),
+        '`' => Some(Accent::Grave),
+        '^' => Some(Accent::Circumflex),
+        '"' => Some(Accent::Diaeresis),
+        '~' => Some(Accent::Tilde),
+        '*' => Some(Accent::Ring),
+        ',' => Some(Accent::Cedilla),
+        '/' => Some(Accent::Stroke),
+        _ => None,
+    }
+}
+
+fn compose_sequence(first: char, second: char) -> Option<char> {
+    let from_table = |a: char, b: char| {
+        COMPOSE_SEQUENCES
+            .iter()
+            .find(|(c1, c2, _)| *c1 == a && *c2 == b)
+            .map(|(_, _, c)| *c)
+    };
+    let accented = |a: char, b: char| accent_of(a).and_then(|accent| accents::compose(b, accent));
+
+    from_table(first, second)
+        .or_else(|| from_table(second, first))
+        .or_else(|| accented(first, second))
+        .or_else(|| accented(second, first))
+}
+
+#[derive(Clone, Copy)]
+enum Pending {
+    DeadKey(Accent),
+    // With the first character, once typed
+    Compose(Option<char>),
+}
+
+// Shared by the text widgets, only the focused one feeds it
+pub(crate) struct ComposeState {
+    config: ComposeConfig,
+    pending: Option<Pending>,
+    // Widget the pending sequence was started in
+    owner: Option<ContentId>,
+    // The compose key is down, and no other key was pressed since
+    compose_armed: bool,
+}
+
+impl ComposeState {
+    pub(crate) fn new() -> Self {
+        ComposeState {
+            config: ComposeConfig::default(),
+            pending: None,
+            owner: None,
+            compose_armed: false,
+        }
+    }
+
+    // Returns a copy of the input state where the keys of the sequences are replaced
+    // with the characters they give, as InputEvent::Text
+    pub(crate) fn process(&mut self, owner: ContentId, input_state: &InputState) -> InputState {
+        if self.owner != Some(owner) {
+            self.owner = Some(owner);
+            self.pending = None;
+        }
+
+        let mut events = Vec::new();
+        for event in input_state.events.iter().flatten() {
+            match *event {
+                InputEvent::KeyPress { keycode } if Some(keycode) == self.config.compose_key => {
+                    self.compose_armed = true;
+                }
+                InputEvent::KeyRelease { keycode } if Some(keycode) == self.config.compose_key => {
+                    if self.compose_armed {
+                        self.pending = Some(Pending::Compose(None));
+                    }
+                    self.compose_armed = false;
+                }
+
+                // Escape and Backspace cancel the pending sequence, and only that
+                InputEvent::KeyPress {
+                    keycode: Keycode::KEY_ESC | Keycode::KEY_BACKSPACE,
+                } if self.pending.is_some() => {
+                    self.pending = None;
+                }
+
+                InputEvent::KeyPress { keycode } if !is_modifier(keycode) => {
+                    self.compose_armed = false;
+                    match typed_char(event, input_state) {
+                        Some(c) => {
+                            let typed = self.feed(c, input_state.alt);
+                            events.extend(typed.into_iter().map(|c| InputEvent::Text { c }));
+                        }
+                        // Other keys, like the arrows, end the sequence
+                        None => {
+                            self.pending = None;
+                            events.push(*event);
+                        }
+                    }
+                }
+
+                _ => events.push(*event),
+            }
+        }
+
+        let mut composed = input_state.clone();
+        composed.clear_events();
+        for event in events {
+            composed.add_event(event);
+        }
+        composed
+    }
+
+    fn feed(&mut self, c: char, alt: bool) -> Vec<char> {
+        let dead_key = match alt {
+            true => self
+                .config
+                .dead_keys
+                .iter()
+                .find(|(key_c, _)| *key_c == c)
+                .map(|(_, accent)| *accent),
+            false => None,
+        };
+
+        match (self.pending.take(), dead_key) {
+            (None, Some(accent)) => {
+                self.pending = Some(Pending::DeadKey(accent));
+                vec![]
+            }
+            (None, None) => vec![c],
+
+            // Typing the same dead key again, or a space, gives the accent itself
+            (Some(Pending::DeadKey(accent)), Some(dead_key)) if dead_key == accent => {
+                vec![accent.spacing_char()]
+            }
+            (Some(Pending::DeadKey(accent)), _) if c == ' ' => vec![accent.spacing_char()],
+            (Some(Pending::DeadKey(accent)), _) => match accents::compose(c, accent) {
+                Some(composed) => vec![composed],
+                None => vec![accent.spacing_char(), c],
+            },
+
+            (Some(Pending::Compose(None)), _) => {
+                self.pending = Some(Pending::Compose(Some(c)));
+                vec![]
+            }
+            // Unknown sequences are dropped
+            (Some(Pending::Compose(Some(first))), _) => {
+                compose_sequence(first, c).into_iter().collect()
+            }
+        }
+    }
+
+    // Drawn underlined at the cursor of the owner while a sequence is pending
+    pub(crate) fn placeholder(&self, owner: ContentId) -> Option<char> {
+        if self.owner != Some(owner) {
+            return None;
+        }
+        match self.pending? {
+            Pending::DeadKey(accent) => Some(accent.spacing_char()),
+            Pending::Compose(first) => Some(first.unwrap_or(' ')),
+        }
+    }
+
+    // When the owner loses the focus
+    pub(crate) fn cancel(&mut self, owner: ContentId) {
+        if self.owner == Some(owner) {
+            self.pending = None;
+        }
+    }
+}
+
+fn is_modifier(keycode: Keycode) -> bool {
+    matches!(
+        keycode,
+        Keycode::KEY_LEFTSHIFT
+            | Keycode::KEY_RIGHTSHIFT
+            | Keycode::KEY_LEFTCTRL
+            | Keycode::KEY_RIGHTCTRL
+            | Keycode::KEY_LEFTALT
+            | Keycode::KEY_RIGHTALT
+            | Keycode::KEY_CAPSLOCK
+    )
+}
@@ -0,0 +1,169 @@
+use alloc::vec::Vec;
+
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_rect_outline_styled, StrokeStyle};
+use crate::input::{InputEvent, InputState, Keycode};
+use crate::uitk::{InteractionState, UiContext};
+use crate::{FbViewMut, Rect};
+
+// Keyboard focus. The focused widget itself is InteractionState::focused, this keeps
+// track of the focusable widgets so that Tab can cycle through them.
+pub(crate) struct FocusState {
+    // Widgets registered this frame and during the previous one, in drawing order,
+    // and whether they are Tab stops
+    order: Vec<(ContentId, bool)>,
+    prev_order: Vec<(ContentId, bool)>,
+    // The widgets among them that take typed text, same as above
+    text_entries: Vec<ContentId>,
+    prev_text_entries: Vec<ContentId>,
+    frame_time: Option<f64>,
+    // A click reached the widgets but none of them took it
+    click_seen: bool,
+    click_taken: bool,
+}
+
+impl FocusState {
+    pub(crate) fn new() -> Self {
+        Self {
+            order: Vec::new(),
+            prev_order: Vec::new(),
+            text_entries: Vec::new(),
+            prev_text_entries: Vec::new(),
+            frame_time: None,
+            click_seen: false,
+            click_taken: false,
+        }
+    }
+
+    // Apps may get several contexts in one frame, only the first one counts
+    pub(crate) fn new_frame(
+        &mut self,
+        time: f64,
+        input_state: &InputState,
+        interaction: &mut InteractionState,
+    ) {
+        if self.frame_time == Some(time) {
+            return;
+        }
+        self.frame_time = Some(time);
+
+        self.prev_order = core::mem::take(&mut self.order);
+        self.prev_text_entries = core::mem::take(&mut self.text_entries);
+
+        // Clicking empty space clears the focus, and so does removing the focused widget
+        let focused_gone = interaction
+            .focused
+            .is_some_and(|id| !self.prev_order.iter().any(|(other, _)| *other == id));
+        if focused_gone || self.click_seen && !self.click_taken {
+            interaction.focused = None;
+        }
+        self.click_seen = false;
+        self.click_taken = false;
+
+        let tab_stops: Vec<ContentId> = self
+            .prev_order
+            .iter()
+            .filter(|(_, tab_stop)| *tab_stop)
+            .map(|(id, _)| *id)
+            .collect();
+
+        let tab_pressed = !input_state.ctrl && input_state.check_key_pressed(Keycode::KEY_TAB);
+        if !tab_pressed || tab_stops.is_empty() {
+            return;
+        }
+
+        let n = tab_stops.len();
+        let current = interaction
+            .focused
+            .and_then(|id| tab_stops.iter().position(|other| *other == id));
+        let next = match (current, input_state.shift) {
+            (Some(i), false) => (i + 1) % n,
+            (Some(i), true) => (i + n - 1) % n,
+            (None, false) => 0,
+            (None, true) => n - 1,
+        };
+        interaction.focused = Some(tab_stops[next]);
+    }
+}
+
+impl FocusState {
+    // Whether typing goes to a text widget, in which case shortcuts stay out of its way
+    pub(crate) fn text_entry_focused(&self, focused: Option<ContentId>) -> bool {
+        focused.is_some_and(|id| {
+            self.text_entries.contains(&id) || self.prev_text_entries.contains(&id)
+        })
+    }
+
+    pub(crate) fn register_text_entry(&mut self, id: ContentId) {
+        self.text_entries.push(id);
+    }
+}
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    // Called by focusable widgets every frame, returns whether the widget has the focus.
+    // Clicking the widget gives it the focus, and Tab stops are in registration order.
+    pub(crate) fn focusable(&mut self, id: ContentId, rect: &Rect, tab_stop: bool) -> bool {
+        let UiContext {
+            input_state,
+            blank_input_state,
+            interaction,
+            focus,
+            ..
+        } = self;
+
+        // Widgets under a popup or a modal keep the focus they have, but cannot get it
+        if core::ptr::eq(*input_state, *blank_input_state) {
+            focus.order.push((id, false));
+            return interaction.focused == Some(id);
+        }
+
+        focus.order.push((id, tab_stop));
+
+        let ps = &input_state.pointer;
+        if ps.left_click_trigger {
+            focus.click_seen = true;
+            if rect.check_contains_point(ps.x, ps.y) {
+                focus.click_taken = true;
+                interaction.focused = Some(id);
+            }
+        }
+
+        interaction.focused == Some(id)
+    }
+
+    // For widgets that take clicks without taking the focus, e.g. menu bars
+    pub(crate) fn claim_click(&mut self) {
+        self.focus.click_taken = true;
+    }
+
+    // For widgets that cannot be focused right now, e.g. disabled ones
+    pub(crate) fn unfocus(&mut self, id: ContentId) {
+        if self.interaction.focused == Some(id) {
+            self.interaction.focused = None;
+        }
+    }
+
+    pub(crate) fn draw_focus_ring(&mut self, rect: &Rect) {
+        let color = self.stylesheet.colors.focus_ring;
+        let width = self.stylesheet.focus_ring_width;
+        let dash = self.stylesheet.focus_ring_dash;
+        let pattern = [dash, dash];
+        let style = match dash {
+            0 => StrokeStyle::solid(width),
+            _ => StrokeStyle::dashed(width, &pattern, 0),
+        };
+        draw_rect_outline_styled(self.fb, rect, color, false, &style);
+    }
+}
+
+// Space or Enter, for buttons and toggles
+pub(crate) fn activation_pressed(input_state: &InputState) -> bool {
+    input_state.events.iter().any(|event| {
+        matches!(
+            event,
+            Some(InputEvent::KeyPress {
+                keycode: Keycode::KEY_SPACE | Keycode::KEY_ENTER
+            })
+        )
+    })
+}
@@ -0,0 +1,42 @@
+use crate::drawing::nine_patch::{draw_nine_patch, NinePatch};
+use crate::drawing::primitives::draw_rounded_rect;
+use crate::{icons, ChromeImage, Color, FbViewMut, Rect, WidgetColors, WidgetStyle};
+
+// Background and border of buttons and text fields, see StyleSheet::widgets.
+// Returns the rect left for the contents, clear of the border and its corners.
+pub(crate) fn draw_widget_frame<F: FbViewMut>(
+    fb: &mut F,
+    rect: &Rect,
+    style: &WidgetStyle,
+    colors: &WidgetColors,
+) -> Rect {
+    let border_w = style.border_width;
+    let radius = style.border_radius;
+
+    let border = (border_w > 0).then_some((border_w, colors.border));
+    draw_rounded_rect(fb, rect, radius, colors.bg, border);
+
+    let inner_rect = rect.offset(-(border_w as i64));
+
+    // Roughly how far the rounded corners reach into the rect
+    let corner_inset = radius.saturating_sub(border_w) * 3 / 10;
+    inner_rect.offset(-(corner_inset as i64))
+}
+
+// Draws the nine-patch image of the stylesheet, if it has one and it is registered.
+// Returns the rect inside its borders, or None when the caller should fall back to
+// flat fills.
+pub(crate) fn draw_chrome<F: FbViewMut>(
+    fb: &mut F,
+    rect: &Rect,
+    chrome: &ChromeImage,
+    tint: Color,
+) -> Option<Rect> {
+    let icon_name = chrome.icon_name()?;
+    let icon_fb = icons::get_unscaled(icon_name, Some(tint))?;
+
+    let np = NinePatch::from_fb(&icon_fb, chrome.insets).with_edges(chrome.edges);
+    draw_nine_patch(fb, &np, rect);
+
+    Some(np.content_rect(rect))
+}
@@ -0,0 +1,451 @@
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::uitk::text::{EditChars, EditableText};
+use crate::uitk::UuidProvider;
+
+const MAX_UNITS: usize = 200;
+const MAX_SIZE: usize = 1 << 20; // in bytes
+const COALESCE_PAUSE: f64 = 1000.0; // in ms
+
+#[derive(Clone, Copy, PartialEq)]
+enum UnitKind {
+    Typing,
+    Deleting,
+    Other,
+}
+
+struct UndoUnit {
+    kind: UnitKind,
+
+    ranges_before: Vec<(usize, usize)>,
+    removed: Vec<EditChars>,
+    ranges_after: Vec<(usize, usize)>,
+    inserted: Vec<EditChars>,
+
+    cursor_before: usize,
+    cursor_after: usize,
+    last_t: f64,
+}
+
+impl UndoUnit {
+    fn size_bytes(&self) -> usize {
+        self.removed
+            .iter()
+            .chain(self.inserted.iter())
+            .map(|chars| chars.size_bytes())
+            .sum()
+    }
+}
+
+pub struct EditHistory {
+    undo_stack: VecDeque<UndoUnit>,
+    redo_stack: Vec<UndoUnit>,
+    sealed: bool,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EditHistory {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            sealed: true,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.sealed = true;
+    }
+
+    // Prevents the next edit from being merged into the last undo unit
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn record_insert(&mut self, pos: usize, inserted: EditChars, time: f64) {
+        let is_newline = inserted.to_plain() == "\n";
+        let len = inserted.len();
+
+        if is_newline {
+            self.seal();
+        }
+
+        match self.coalescible_unit(UnitKind::Typing, pos, time) {
+            Some(unit) => {
+                unit.inserted[0].append(inserted);
+                unit.ranges_after[0].1 += len;
+                unit.cursor_after = pos + len;
+                unit.last_t = time;
+            }
+            None => self.push(UndoUnit {
+                kind: UnitKind::Typing,
+                ranges_before: vec![(pos, pos)],
+                removed: vec![EditChars::Plain("".into())],
+                ranges_after: vec![(pos, pos + len)],
+                inserted: vec![inserted],
+                cursor_before: pos,
+                cursor_after: pos + len,
+                last_t: time,
+            }),
+        }
+
+        if is_newline {
+            self.seal();
+        }
+    }
+
+    pub fn record_remove(&mut self, pos: usize, mut removed: EditChars, time: f64) {
+        let len = removed.len();
+
+        match self.coalescible_unit(UnitKind::Deleting, pos + len, time) {
+            Some(unit) => {
+                let prev_removed =
+                    core::mem::replace(&mut unit.removed[0], EditChars::Plain("".into()));
+                removed.append(prev_removed);
+                unit.removed[0] = removed;
+                unit.ranges_before[0].0 = pos;
+                unit.ranges_after[0] = (pos, pos);
+                unit.cursor_after = pos;
+                unit.last_t = time;
+            }
+            None => self.push(UndoUnit {
+                kind: UnitKind::Deleting,
+                ranges_before: vec![(pos, pos + len)],
+                removed: vec![removed],
+                ranges_after: vec![(pos, pos)],
+                inserted: vec![EditChars::Plain("".into())],
+                cursor_before: pos + len,
+                cursor_after: pos,
+                last_t: time,
+            }),
+        }
+    }
+
+    // Replaces all ranges (sorted, non-overlapping) as a single undo unit
+    pub fn replace<T: EditableText>(
+        &mut self,
+        text: &mut T,
+        uuid_provider: &mut UuidProvider,
+        ranges: &[(usize, usize)],
+        s: &str,
+        cursor_before: usize,
+        cursor_after: usize,
+    ) {
+        let removed: Vec<EditChars> = ranges
+            .iter()
+            .map(|&(start, end)| text.slice(start, end))
+            .collect();
+
+        let old_len = text.len();
+        text.replace(uuid_provider, ranges, s);
+        let removed_len: usize = ranges.iter().map(|(start, end)| end - start).sum();
+        let part_len = match ranges.len() {
+            0 => 0,
+            n => (text.len() + removed_len - old_len) / n,
+        };
+
+        let mut shift: i64 = 0;
+        let ranges_after: Vec<(usize, usize)> = ranges
+            .iter()
+            .map(|&(start, end)| {
+                let new_start = (start as i64 + shift) as usize;
+                shift += part_len as i64 - (end - start) as i64;
+                (new_start, new_start + part_len)
+            })
+            .collect();
+
+        let inserted = ranges_after
+            .iter()
+            .map(|&(start, end)| text.slice(start, end))
+            .collect();
+
+        self.seal();
+        self.push(UndoUnit {
+            kind: UnitKind::Other,
+            ranges_before: ranges.to_vec(),
+            removed,
+            ranges_after,
+            inserted,
+            cursor_before,
+            cursor_after,
+            last_t: 0.0,
+        });
+        self.seal();
+    }
+
+    pub fn undo<T: EditableText>(
+        &mut self,
+        text: &mut T,
+        uuid_provider: &mut UuidProvider,
+    ) -> Option<usize> {
+        let unit = self.undo_stack.pop_back()?;
+
+        if !Self::check_ranges(text, &unit.ranges_after) {
+            log::warn!("Text was modified outside of the edit history, clearing it");
+            self.clear();
+            return None;
+        }
+
+        text.splice(uuid_provider, &unit.ranges_after, &unit.removed);
+        let cursor = unit.cursor_before;
+        self.redo_stack.push(unit);
+        self.sealed = true;
+
+        Some(cursor)
+    }
+
+    pub fn redo<T: EditableText>(
+        &mut self,
+        text: &mut T,
+        uuid_provider: &mut UuidProvider,
+    ) -> Option<usize> {
+        let unit = self.redo_stack.pop()?;
+
+        if !Self::check_ranges(text, &unit.ranges_before) {
+            log::warn!("Text was modified outside of the edit history, clearing it");
+            self.clear();
+            return None;
+        }
+
+        text.splice(uuid_provider, &unit.ranges_before, &unit.inserted);
+        let cursor = unit.cursor_after;
+        self.undo_stack.push_back(unit);
+        self.sealed = true;
+
+        Some(cursor)
+    }
+
+    fn check_ranges<T: EditableText>(text: &T, ranges: &[(usize, usize)]) -> bool {
+        ranges.iter().all(|&(_, end)| end <= text.len())
+    }
+
+    fn coalescible_unit(&mut self, kind: UnitKind, pos: usize, time: f64) -> Option<&mut UndoUnit> {
+        if self.sealed {
+            return None;
+        }
+
+        let unit = self.undo_stack.back_mut()?;
+
+        let coalescible =
+            unit.kind == kind && unit.cursor_after == pos && time - unit.last_t < COALESCE_PAUSE;
+
+        match coalescible {
+            true => Some(unit),
+            false => None,
+        }
+    }
+
+    fn push(&mut self, unit: UndoUnit) {
+        self.redo_stack.clear();
+        self.undo_stack.push_back(unit);
+        self.sealed = false;
+
+        let mut total_size: usize = self.undo_stack.iter().map(|u| u.size_bytes()).sum();
+        while self.undo_stack.len() > MAX_UNITS
+            || (total_size > MAX_SIZE && self.undo_stack.len() > 1)
+        {
+            if let Some(dropped) = self.undo_stack.pop_front() {
+                total_size -= dropped.size_bytes();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    struct Editor {
+        text: String,
+        history: EditHistory,
+        uuid_provider: UuidProvider,
+    }
+
+    impl Editor {
+        fn new(text: &str) -> Self {
+            Self {
+                text: text.into(),
+                history: EditHistory::new(),
+                uuid_provider: UuidProvider::new(),
+            }
+        }
+
+        fn type_char(&mut self, pos: usize, c: char, time: f64) {
+            EditableText::insert(&mut self.text, &mut self.uuid_provider, pos, c);
+            let inserted = EditableText::slice(&self.text, pos, pos + 1);
+            self.history.record_insert(pos, inserted, time);
+        }
+
+        fn backspace(&mut self, pos: usize, time: f64) {
+            let removed = EditableText::slice(&self.text, pos - 1, pos);
+            EditableText::remove(&mut self.text, &mut self.uuid_provider, pos - 1);
+            self.history.record_remove(pos - 1, removed, time);
+        }
+
+        fn undo(&mut self) -> Option<usize> {
+            self.history.undo(&mut self.text, &mut self.uuid_provider)
+        }
+
+        fn redo(&mut self) -> Option<usize> {
+            self.history.redo(&mut self.text, &mut self.uuid_provider)
+        }
+    }
+
+    #[test]
+    fn typing_coalesces_until_a_pause() {
+        let mut editor = Editor::new("");
+        editor.type_char(0, 'a', 0.0);
+        editor.type_char(1, 'b', 500.0);
+        editor.type_char(2, 'c', 500.0 + COALESCE_PAUSE - 1.0);
+        editor.type_char(3, 'd', 500.0 + 2.0 * COALESCE_PAUSE);
+
+        assert_eq!(editor.undo(), Some(3));
+        assert_eq!(editor.text, "abc");
+        assert_eq!(editor.undo(), Some(0));
+        assert_eq!(editor.text, "");
+        assert_eq!(editor.undo(), None);
+    }
+
+    #[test]
+    fn cursor_jump_starts_a_new_unit() {
+        let mut editor = Editor::new("");
+        editor.type_char(0, 'a', 0.0);
+        editor.type_char(1, 'b', 10.0);
+        editor.type_char(0, 'x', 20.0);
+        editor.type_char(1, 'y', 30.0);
+        assert_eq!(editor.text, "xyab");
+
+        assert_eq!(editor.undo(), Some(0));
+        assert_eq!(editor.text, "ab");
+        assert_eq!(editor.undo(), Some(0));
+        assert_eq!(editor.text, "");
+    }
+
+    #[test]
+    fn newline_is_a_unit_of_its_own() {
+        let mut editor = Editor::new("");
+        editor.type_char(0, 'a', 0.0);
+        editor.type_char(1, '\n', 10.0);
+        editor.type_char(2, 'b', 20.0);
+
+        assert_eq!(editor.undo(), Some(2));
+        assert_eq!(editor.text, "a\n");
+        assert_eq!(editor.undo(), Some(1));
+        assert_eq!(editor.text, "a");
+        assert_eq!(editor.undo(), Some(0));
+        assert_eq!(editor.text, "");
+    }
+
+    #[test]
+    fn backspaces_coalesce_and_typing_does_not_join_them() {
+        let mut editor = Editor::new("hello");
+        editor.backspace(5, 0.0);
+        editor.backspace(4, 10.0);
+        editor.type_char(3, 'p', 20.0);
+        assert_eq!(editor.text, "help");
+
+        assert_eq!(editor.undo(), Some(3));
+        assert_eq!(editor.text, "hel");
+        assert_eq!(editor.undo(), Some(5));
+        assert_eq!(editor.text, "hello");
+        assert_eq!(editor.redo(), Some(3));
+        assert_eq!(editor.text, "hel");
+    }
+
+    #[test]
+    fn seal_stops_coalescing() {
+        let mut editor = Editor::new("");
+        editor.type_char(0, 'a', 0.0);
+        editor.history.seal();
+        editor.type_char(1, 'b', 10.0);
+
+        assert_eq!(editor.undo(), Some(1));
+        assert_eq!(editor.text, "a");
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut editor = Editor::new("");
+        editor.type_char(0, 'a', 0.0);
+        editor.undo();
+        assert!(editor.history.can_redo());
+        editor.type_char(0, 'b', 10.0);
+        assert!(!editor.history.can_redo());
+        assert_eq!(editor.redo(), None);
+    }
+
+    #[test]
+    fn replace_all_undoes_as_one_unit() {
+        let mut editor = Editor::new("été, thé et café");
+        editor.type_char(16, '!', 0.0);
+
+        // Every "é", the text is not ASCII so char and byte positions differ
+        let ranges = [(0, 1), (2, 3), (7, 8), (15, 16)];
+        editor.history.replace(
+            &mut editor.text,
+            &mut editor.uuid_provider,
+            &ranges,
+            "ee",
+            17,
+            21,
+        );
+        assert_eq!(editor.text, "eetee, thee et cafee!");
+
+        // Typing right after is not merged into the replacement
+        editor.type_char(21, '?', 10.0);
+        assert_eq!(editor.undo(), Some(21));
+
+        assert_eq!(editor.undo(), Some(17));
+        assert_eq!(editor.text, "été, thé et café!");
+        assert_eq!(editor.redo(), Some(21));
+        assert_eq!(editor.text, "eetee, thee et cafee!");
+        assert_eq!(editor.undo(), Some(17));
+        assert_eq!(editor.undo(), Some(16));
+        assert_eq!(editor.text, "été, thé et café");
+    }
+
+    #[test]
+    fn replace_with_empty_string() {
+        let mut editor = Editor::new("a-b-c");
+        editor.history.replace(
+            &mut editor.text,
+            &mut editor.uuid_provider,
+            &[(1, 2), (3, 4)],
+            "",
+            0,
+            0,
+        );
+        assert_eq!(editor.text, "abc");
+        editor.undo();
+        assert_eq!(editor.text, "a-b-c");
+    }
+
+    #[test]
+    fn outside_changes_clear_the_history() {
+        let mut editor = Editor::new("");
+        editor.type_char(0, 'a', 0.0);
+        editor.type_char(1, 'b', 10.0);
+        editor.text.clear();
+
+        assert_eq!(editor.undo(), None);
+        assert!(!editor.history.can_undo());
+    }
+}
@@ -0,0 +1,124 @@
+use alloc::collections::BTreeMap;
+
+use crate::content::ContentId;
+use crate::drawing::effects::{blend_with_opacity, render_shadow, Shadow};
+use crate::uitk::UiContext;
+use crate::{BorrowedMutPixels, Color, FbView, FbViewMut, Framebuffer, OwnedPixels, Rect};
+
+// Layers not drawn for that long are freed
+const LAYER_TTL: f64 = 1000.0; // in ms
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    // Draws func into an offscreen buffer the size of the rect, then composites it.
+    // Coordinates inside func are those of the parent, so input and overlays line up.
+    pub fn layer<G>(&mut self, config: &LayerConfig, func: G)
+    where
+        G: FnOnce(&mut UiContext<Framebuffer<BorrowedMutPixels>>),
+    {
+        let Rect { x0, y0, w, h } = config.rect;
+        let mut layer_fb = self.layers.take(config.id, w, h);
+
+        {
+            let mut view = layer_fb.subregion_mut(&Rect {
+                x0: -x0,
+                y0: -y0,
+                w: i64::max(0, x0 + w as i64) as u32,
+                h: i64::max(0, y0 + h as i64) as u32,
+            });
+            let mut ui = self.with_fb(&mut view);
+            func(&mut ui);
+        }
+
+        if let Some(shadow) = config.shadow.as_ref() {
+            let pos = shadow.origin((x0, y0));
+            match config.content_id {
+                Some(content_id) => {
+                    let shadow_id = ContentId::from_hash(&(content_id, shadow, w, h));
+                    let shadow_fb = self
+                        .tile_cache
+                        .fetch_or_create(shadow_id, self.time, || render_shadow(&layer_fb, shadow));
+                    blend_with_opacity(self.fb, shadow_fb, pos, config.opacity);
+                }
+                None => {
+                    let shadow_fb = render_shadow(&layer_fb, shadow);
+                    blend_with_opacity(self.fb, &shadow_fb, pos, config.opacity);
+                }
+            }
+        }
+        blend_with_opacity(self.fb, &layer_fb, (x0, y0), config.opacity);
+
+        self.layers.put_back(config.id, layer_fb, self.time);
+    }
+}
+
+#[derive(Clone)]
+pub struct LayerConfig {
+    // Must be stable across frames, the buffer is reused
+    pub id: ContentId,
+    pub rect: Rect,
+    // From 0 to 1
+    pub opacity: f32,
+    // Cast by the opaque parts of the layer
+    pub shadow: Option<Shadow>,
+    // Identifies what is drawn in the layer, if known. The shadow is then only rendered
+    // again when it changes, otherwise it is on every frame.
+    pub content_id: Option<ContentId>,
+}
+
+impl Default for LayerConfig {
+    fn default() -> Self {
+        LayerConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 0,
+                h: 0,
+            },
+            opacity: 1.0,
+            shadow: None,
+            content_id: None,
+        }
+    }
+}
+
+struct CachedLayer {
+    fb: Framebuffer<OwnedPixels>,
+    last_used_time: f64,
+}
+
+pub(crate) struct LayerCache {
+    layers: BTreeMap<ContentId, CachedLayer>,
+}
+
+impl LayerCache {
+    pub(crate) fn new() -> Self {
+        LayerCache {
+            layers: BTreeMap::new(),
+        }
+    }
+
+    // Cleared, reallocated only when the size changes
+    fn take(&mut self, id: ContentId, w: u32, h: u32) -> Framebuffer<OwnedPixels> {
+        match self.layers.remove(&id) {
+            Some(CachedLayer { mut fb, .. }) if fb.shape() == (w, h) => {
+                fb.fill(Color::ZERO);
+                fb
+            }
+            _ => Framebuffer::new_owned(w, h),
+        }
+    }
+
+    fn put_back(&mut self, id: ContentId, fb: Framebuffer<OwnedPixels>, time: f64) {
+        let layer = CachedLayer {
+            fb,
+            last_used_time: time,
+        };
+        self.layers.insert(id, layer);
+    }
+
+    pub(crate) fn cleanup(&mut self, time: f64) {
+        self.layers
+            .retain(|_, layer| time - layer.last_used_time < LAYER_TTL);
+    }
+}
@@ -118,3 +118,95 @@ pub enum LayoutItem {
     Fixed { size: u32 },
     Float,
 }
+
+// Sizes along the main axis of a box container, see UiContext::vbox()
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BoxItem {
+    Fixed(u32),
+    // Shares the leftover space with the other growing items in proportion to
+    // their weights, but never shrinks below the minimum
+    Grow { weight: u32, min: u32 },
+}
+
+impl BoxItem {
+    pub const fn grow(weight: u32) -> Self {
+        BoxItem::Grow { weight, min: 0 }
+    }
+
+    pub const fn min(min: u32) -> Self {
+        BoxItem::Grow { weight: 1, min }
+    }
+}
+
+// Offsets and sizes of the items along an axis of the given length. Items that
+// do not fit overflow at the end.
+pub fn solve_box_layout(total: u32, spacing: u32, items: &[BoxItem]) -> Vec<(i64, u32)> {
+    let n = items.len();
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let avail = total.saturating_sub((n as u32 - 1) * spacing);
+
+    let mut sizes: Vec<Option<f32>> = items
+        .iter()
+        .map(|item| match item {
+            BoxItem::Fixed(size) => Some(*size as f32),
+            BoxItem::Grow { weight: 0, min } => Some(*min as f32),
+            BoxItem::Grow { .. } => None,
+        })
+        .collect();
+
+    // Growing items that would end up below their minimum are pinned to it, and
+    // the rest is shared again among the others
+    loop {
+        let taken: f32 = sizes.iter().flatten().sum();
+        let left = f32::max(0.0, avail as f32 - taken);
+        let total_weight: u32 = items
+            .iter()
+            .zip(sizes.iter())
+            .filter(|(_, size)| size.is_none())
+            .map(|(item, _)| match item {
+                BoxItem::Grow { weight, .. } => *weight,
+                BoxItem::Fixed(_) => 0,
+            })
+            .sum();
+
+        if total_weight == 0 {
+            break;
+        }
+
+        let share = |weight: u32| left * weight as f32 / total_weight as f32;
+
+        let mut pinned = false;
+        for (item, size) in items.iter().zip(sizes.iter_mut()) {
+            if let (BoxItem::Grow { weight, min }, None) = (item, &size) {
+                if share(*weight) < *min as f32 {
+                    *size = Some(*min as f32);
+                    pinned = true;
+                }
+            }
+        }
+
+        if !pinned {
+            for (item, size) in items.iter().zip(sizes.iter_mut()) {
+                if let (BoxItem::Grow { weight, .. }, None) = (item, &size) {
+                    *size = Some(share(*weight));
+                }
+            }
+            break;
+        }
+    }
+
+    // Rounding the edges rather than the sizes, so that there are no gaps
+    let mut x = 0.0;
+    sizes
+        .iter()
+        .map(|size| {
+            let size = size.unwrap_or(0.0);
+            let (x1, x2) = (f32::round(x), f32::round(x + size));
+            x += size + spacing as f32;
+            (x1 as i64, (x2 - x1) as u32)
+        })
+        .collect()
+}
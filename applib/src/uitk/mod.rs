@@ -1,22 +1,71 @@
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::vec::Vec;
 
+mod animation;
+mod clipboard;
+mod compose;
+mod focus;
+mod frame;
+mod history;
+mod layer;
 pub mod layout;
+mod popup;
+mod shortcut;
 mod text;
 mod widgets;
 
-pub use text::{render_rich_text, string_input};
-pub use widgets::button::{ButtonConfig, ButtonIndicatorMode};
+pub use animation::Easing;
+pub use clipboard::Clipboard;
+pub use compose::ComposeConfig;
+pub use history::EditHistory;
+pub use layer::LayerConfig;
+pub use shortcut::ShortcutConfig;
+pub use text::{render_rich_text, string_input, EditChars, EditableText};
+pub use widgets::button::{ButtonConfig, ButtonEvent, ButtonIndicatorMode, RepeatConfig};
+pub use widgets::checkbox::{CheckState, CheckboxConfig};
+pub use widgets::color_picker::ColorPickerConfig;
+pub use widgets::container::LayoutCell;
+pub use widgets::context_menu::{ContextMenuConfig, MenuItem};
+pub use widgets::dropdown::DropdownConfig;
 pub use widgets::dynamic_canvas::TileRenderer;
 pub use widgets::graph::{GraphAggMode, GraphConfig, GraphSeries};
 pub use widgets::horiz_bar::{BarValue, HorizBarConfig};
-pub use widgets::progress_bar::ProgressBarConfig;
+pub use widgets::menu_bar::{Menu, MenuAction, MenuBarConfig};
+pub use widgets::modal::{ModalConfig, ModalResult};
+pub use widgets::number_input::NumberInputConfig;
+pub use widgets::progress_bar::{ProgressBarConfig, ProgressBarMode};
+pub use widgets::radio_group::{RadioGroupConfig, RadioOrientation};
+pub use widgets::slider::SliderConfig;
+pub use widgets::split_pane::{SplitConfig, SplitOrientation};
 pub use widgets::static_canvas::set_autoscroll;
+pub use widgets::tab_bar::{TabBarConfig, TabBarResponse, TabItem};
+pub use widgets::table::{ColumnDef, TableConfig, TableEvent, TableState};
 pub use widgets::text_box::{EditableRichText, FormattableText, TextBoxState};
+pub use widgets::text_input::{TextInputConfig, TextInputEvent, TextInputState};
+pub use widgets::toast::ToastConfig;
+pub use widgets::tree_view::{TreeAdapter, TreeViewConfig, TreeViewEvent, TreeViewState};
 
 pub use crate::content::{ContentId, UuidProvider};
+use crate::drawing::text::set_smooth_text;
+use crate::input::PointerState;
 use crate::{FbViewMut, Framebuffer, OwnedPixels};
-use crate::{InputState, StyleSheet};
+use crate::{InputState, Rect, StyleSheet};
+use alloc::boxed::Box;
+use animation::AnimationState;
+use clipboard::LocalClipboard;
+use compose::ComposeState;
+use focus::FocusState;
+use layer::LayerCache;
+use popup::Popup;
+use shortcut::ShortcutState;
+use widgets::button::ButtonPress;
+use widgets::color_picker::ColorPickerStore;
+use widgets::container::LayoutState;
+use widgets::dynamic_canvas::ScrollbarState;
+use widgets::modal::ModalState;
+use widgets::number_input::NumberInputState;
+use widgets::toast::ToastState;
+use widgets::tooltip::TooltipState;
 
 const TILE_CACHE_MAX_SIZE: usize = 20_000_000; // in bytes
 
@@ -100,6 +149,36 @@ impl TileCache {
     }
 }
 
+// Widgets being interacted with, identified by an ID chosen by the app so that
+// it stays the same from one frame to the next
+pub struct InteractionState {
+    pub dragged: Option<ContentId>,
+    pub focused: Option<ContentId>,
+    // For widgets that only show part of their items, e.g. the first visible tab
+    pub scroll_offsets: BTreeMap<ContentId, usize>,
+    pub(crate) scrollbars: BTreeMap<ContentId, ScrollbarState>,
+    // Clicked once, for double clicks in widgets without a state of their own
+    pub(crate) last_clicks: BTreeSet<ContentId>,
+    // Buttons held down with the pointer
+    pub(crate) button_presses: BTreeMap<ContentId, ButtonPress>,
+    // Typed text of the number inputs
+    pub(crate) number_inputs: BTreeMap<ContentId, NumberInputState>,
+}
+
+impl InteractionState {
+    fn new() -> Self {
+        Self {
+            dragged: None,
+            focused: None,
+            scroll_offsets: BTreeMap::new(),
+            scrollbars: BTreeMap::new(),
+            last_clicks: BTreeSet::new(),
+            button_presses: BTreeMap::new(),
+            number_inputs: BTreeMap::new(),
+        }
+    }
+}
+
 pub struct UiContext<'a, F: FbViewMut> {
     pub fb: &'a mut F,
 
@@ -109,6 +188,26 @@ pub struct UiContext<'a, F: FbViewMut> {
     pub time: f64,
 
     pub tile_cache: &'a mut TileCache,
+    pub interaction: &'a mut InteractionState,
+    popup: &'a mut Option<Popup>,
+    modal: &'a mut ModalState,
+    tooltip: &'a mut TooltipState,
+    toasts: &'a mut ToastState,
+    focus: &'a mut FocusState,
+    shortcuts: &'a mut ShortcutState,
+    layout: &'a mut LayoutState,
+    clipboard: &'a mut Box<dyn Clipboard>,
+    compose: &'a mut ComposeState,
+    cursor_hint: &'a mut CursorHint,
+    color_pickers: &'a mut ColorPickerStore,
+    animations: &'a mut AnimationState,
+    layers: &'a mut LayerCache,
+    // What the contents of an open modal see, and what everything else sees meanwhile
+    modal_input_state: &'a InputState,
+    blank_input_state: &'a InputState,
+    // Clips pushed on the framebuffer when the context was created, the widgets must
+    // pop all of theirs
+    clip_depth: usize,
 }
 
 impl<'a, F: FbViewMut> UiContext<'a, F> {
@@ -120,11 +219,29 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
             uuid_provider,
             time,
             tile_cache,
+            interaction,
+            popup,
+            modal,
+            tooltip,
+            toasts,
+            focus,
+            shortcuts,
+            layout,
+            clipboard,
+            compose,
+            cursor_hint,
+            color_pickers,
+            animations,
+            layers,
+            modal_input_state,
+            blank_input_state,
+            ..
         } = self;
 
         let mut new_stylesheet = stylesheet.clone();
         func(&mut new_stylesheet);
 
+        let clip_depth = fb.clip_depth();
         UiContext {
             fb,
             stylesheet: new_stylesheet,
@@ -132,21 +249,147 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
             uuid_provider,
             time: *time,
             tile_cache,
+            interaction,
+            popup,
+            modal,
+            tooltip,
+            toasts,
+            focus,
+            shortcuts,
+            layout,
+            clipboard,
+            compose,
+            cursor_hint,
+            color_pickers,
+            animations,
+            layers,
+            modal_input_state,
+            blank_input_state,
+            clip_depth,
+        }
+    }
+
+    // Same state, drawing into another framebuffer
+    fn with_fb<'b, G: FbViewMut>(&'b mut self, fb: &'b mut G) -> UiContext<'b, G> {
+        UiContext {
+            clip_depth: fb.clip_depth(),
+            fb,
+            stylesheet: self.stylesheet.clone(),
+            input_state: self.input_state,
+            uuid_provider: self.uuid_provider,
+            time: self.time,
+            tile_cache: self.tile_cache,
+            interaction: self.interaction,
+            popup: self.popup,
+            modal: self.modal,
+            tooltip: self.tooltip,
+            toasts: self.toasts,
+            focus: self.focus,
+            shortcuts: self.shortcuts,
+            layout: self.layout,
+            clipboard: self.clipboard,
+            compose: self.compose,
+            cursor_hint: self.cursor_hint,
+            color_pickers: self.color_pickers,
+            animations: self.animations,
+            layers: self.layers,
+            modal_input_state: self.modal_input_state,
+            blank_input_state: self.blank_input_state,
         }
     }
+
+    // Drawing done by func cannot get out of the rect
+    pub fn clipped<R>(&mut self, rect: &Rect, func: impl FnOnce(&mut Self) -> R) -> R {
+        let depth = self.fb.clip_depth();
+        self.fb.push_clip(rect);
+        let result = func(self);
+        debug_assert_eq!(self.fb.clip_depth(), depth + 1, "Unbalanced push_clip()");
+        self.fb.pop_clip();
+        result
+    }
+
+    // Shown by the system instead of the normal pointer, see UiStore::take_cursor_hint()
+    pub fn set_cursor_hint(&mut self, hint: CursorHint) {
+        *self.cursor_hint = hint;
+    }
+
+    // Must be called after all other widgets, so that the toasts, dropdown lists,
+    // context menus and tooltips are drawn over them
+    pub fn draw_overlay(&mut self) {
+        debug_assert_eq!(
+            self.fb.clip_depth(),
+            self.clip_depth,
+            "Unbalanced push_clip() and pop_clip() during the frame"
+        );
+        self.draw_toasts();
+        self.draw_popup();
+        self.draw_tooltip();
+    }
 }
 
 pub struct UiStore {
     tile_cache: TileCache,
+    interaction: InteractionState,
+    popup: Option<Popup>,
+    modal: ModalState,
+    tooltip: TooltipState,
+    toasts: ToastState,
+    focus: FocusState,
+    shortcuts: ShortcutState,
+    layout: LayoutState,
+    clipboard: Box<dyn Clipboard>,
+    compose: ComposeState,
+    cursor_hint: CursorHint,
+    color_pickers: ColorPickerStore,
+    animations: AnimationState,
+    layers: LayerCache,
+    modal_input: InputState,
+    blank_input: InputState,
 }
 
 impl UiStore {
     pub fn new() -> Self {
         Self {
             tile_cache: TileCache::new(),
+            interaction: InteractionState::new(),
+            popup: None,
+            modal: ModalState::new(),
+            tooltip: TooltipState::new(),
+            toasts: ToastState::new(),
+            focus: FocusState::new(),
+            shortcuts: ShortcutState::new(),
+            layout: LayoutState::new(),
+            clipboard: Box::new(LocalClipboard::new()),
+            compose: ComposeState::new(),
+            cursor_hint: CursorHint::Default,
+            color_pickers: ColorPickerStore::new(),
+            animations: AnimationState::new(),
+            layers: LayerCache::new(),
+            modal_input: InputState::new(0, 0),
+            blank_input: InputState::new(0, 0),
         }
     }
 
+    pub fn set_clipboard<C: Clipboard + 'static>(&mut self, clipboard: C) {
+        self.clipboard = Box::new(clipboard);
+    }
+
+    // Shown at the bottom of the window by draw_overlay()
+    pub fn push_toast(&mut self, config: ToastConfig) {
+        self.toasts.push(config);
+    }
+
+    // The ID of the toast button clicked since the last call, see ToastConfig::action
+    pub fn take_toast_action(&mut self) -> Option<ContentId> {
+        self.toasts.take_action()
+    }
+
+    // What the widgets asked the pointer to look like since the last call, to be
+    // passed on to the system (see guestlib::set_cursor_hint()) once the UI is drawn
+    pub fn take_cursor_hint(&mut self) -> CursorHint {
+        core::mem::replace(&mut self.cursor_hint, CursorHint::Default)
+    }
+
     pub fn get_context<'a, F: FbViewMut>(
         &'a mut self,
         fb: &'a mut F,
@@ -155,16 +398,112 @@ impl UiStore {
         uuid_provider: &'a mut UuidProvider,
         time: f64,
     ) -> UiContext<'a, F> {
+        set_smooth_text(stylesheet.text.smooth);
+
         // TODO: move that somewhere else
         self.tile_cache.cleanup();
+        self.layers.cleanup(time);
+
+        // An open popup captures all the input, including the click that closes it
+        let popup_open = match self.popup.as_mut() {
+            Some(popup) => {
+                popup.handle_input(input_state);
+                if popup.is_closed() {
+                    self.popup = None;
+                }
+                true
+            }
+            None => false,
+        };
+
+        // Toasts are drawn over everything but the popups
+        let toast_clicked = !popup_open && self.toasts.handle_input(input_state);
+
+        // Then an open modal gets what is left, and the rest of the UI nothing
+        let modal_open = self.modal.new_frame();
+
+        self.blank_input = blank_input(input_state);
+        if modal_open {
+            self.modal_input = match popup_open || toast_clicked {
+                true => self.blank_input.clone(),
+                false => input_state.clone(),
+            };
+        }
+
+        // Tab moves the focus, unless a popup is open
+        let focus_input = match popup_open || toast_clicked {
+            true => &self.blank_input,
+            false => input_state,
+        };
+        self.focus
+            .new_frame(time, focus_input, &mut self.interaction);
+        self.shortcuts.new_frame(time, input_state);
+        self.layout.new_frame();
+        self.animations.new_frame(time);
+
+        let input_state = match popup_open || modal_open || toast_clicked {
+            true => &self.blank_input,
+            false => input_state,
+        };
 
         UiContext {
+            clip_depth: fb.clip_depth(),
             fb,
             stylesheet: stylesheet.clone(),
             tile_cache: &mut self.tile_cache,
+            interaction: &mut self.interaction,
+            popup: &mut self.popup,
+            modal: &mut self.modal,
+            tooltip: &mut self.tooltip,
+            toasts: &mut self.toasts,
+            focus: &mut self.focus,
+            shortcuts: &mut self.shortcuts,
+            layout: &mut self.layout,
+            clipboard: &mut self.clipboard,
+            compose: &mut self.compose,
+            cursor_hint: &mut self.cursor_hint,
+            color_pickers: &mut self.color_pickers,
+            animations: &mut self.animations,
+            layers: &mut self.layers,
+            modal_input_state: &self.modal_input,
+            blank_input_state: &self.blank_input,
             input_state,
             uuid_provider,
             time,
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, enumn::N)]
+#[repr(u32)]
+pub enum CursorHint {
+    Default = 0,
+    // Double arrows, for dividers and other things that can be dragged along one axis
+    ResizeHorizontal = 1,
+    ResizeVertical = 2,
+    // From the top-left corner to the bottom-right one, and from the top-right to the
+    // bottom-left
+    ResizeDiagonal = 3,
+    ResizeAntiDiagonal = 4,
+}
+
+// What the widgets under a popup or modal see: the pointer is away and nothing is pressed
+fn blank_input(input_state: &InputState) -> InputState {
+    let mut blank = input_state.clone();
+    blank.clear_events();
+    blank.pointer = PointerState {
+        x: -1,
+        y: -1,
+        delta_x: 0,
+        delta_y: 0,
+        scroll_x: 0,
+        scroll_y: 0,
+        left_clicked: false,
+        right_clicked: false,
+        middle_clicked: false,
+        left_click_trigger: false,
+        right_click_trigger: false,
+        middle_click_trigger: false,
+    };
+    blank
+}
@@ -0,0 +1,77 @@
+use crate::input::InputState;
+use crate::uitk::animation::AnimationState;
+use crate::uitk::widgets::context_menu::MenuPopup;
+use crate::uitk::widgets::dropdown::DropdownPopup;
+use crate::uitk::widgets::menu_bar::MenuBarPopup;
+use crate::uitk::UiContext;
+use crate::{FbViewMut, StyleSheet};
+
+// What is drawn over the rest of the UI. There is at most one at a time, and while
+// it is open it receives all the input (see UiStore::get_context).
+pub(crate) enum Popup {
+    Dropdown(DropdownPopup),
+    ContextMenu(MenuPopup),
+    MenuBar(MenuBarPopup),
+}
+
+impl Popup {
+    pub(crate) fn handle_input(&mut self, input_state: &InputState) {
+        match self {
+            Popup::Dropdown(p) => p.handle_input(input_state),
+            Popup::ContextMenu(p) => p.handle_input(input_state),
+            Popup::MenuBar(p) => p.handle_input(input_state),
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        match self {
+            Popup::Dropdown(p) => p.is_closed(),
+            Popup::ContextMenu(p) => p.is_closed(),
+            Popup::MenuBar(p) => p.is_closed(),
+        }
+    }
+
+    // Whether the widget that owns it was drawn this frame
+    fn seen(&mut self) -> &mut bool {
+        match self {
+            Popup::Dropdown(p) => &mut p.seen,
+            Popup::ContextMenu(p) => &mut p.seen,
+            Popup::MenuBar(p) => &mut p.seen,
+        }
+    }
+
+    fn draw<F: FbViewMut>(
+        &self,
+        fb: &mut F,
+        stylesheet: &StyleSheet,
+        animations: &mut AnimationState,
+    ) {
+        match self {
+            Popup::Dropdown(p) => p.draw(fb, stylesheet, animations),
+            Popup::ContextMenu(p) => p.draw(fb, stylesheet),
+            Popup::MenuBar(p) => p.draw(fb, stylesheet),
+        }
+    }
+}
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    pub(crate) fn draw_popup(&mut self) {
+        let UiContext {
+            fb,
+            stylesheet,
+            popup,
+            animations,
+            ..
+        } = self;
+
+        // The widget that opened it is gone
+        if popup.as_mut().is_some_and(|p| !*p.seen()) {
+            **popup = None;
+        }
+
+        if let Some(p) = popup.as_mut() {
+            p.draw(*fb, stylesheet, animations);
+            *p.seen() = false;
+        }
+    }
+}
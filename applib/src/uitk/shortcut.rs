@@ -0,0 +1,179 @@
+use alloc::collections::BTreeSet;
+#[cfg(debug_assertions)]
+use alloc::vec::Vec;
+
+use crate::content::ContentId;
+use crate::input::{InputEvent, InputState, Keycode, MAX_EVENTS};
+use crate::uitk::UiContext;
+use crate::FbViewMut;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    // Must be called every frame the shortcut is available, returns whether it fired
+    pub fn shortcut(&mut self, config: &ShortcutConfig) -> bool {
+        let Some(combo) = Combo::from_keys(config.keys) else {
+            log::warn!("Shortcut {:?} has no key besides modifiers", config.keys);
+            return false;
+        };
+
+        let UiContext {
+            input_state,
+            interaction,
+            focus,
+            shortcuts,
+            ..
+        } = self;
+
+        #[cfg(debug_assertions)]
+        shortcuts.register(combo, config.id);
+
+        if !config.global && focus.text_entry_focused(interaction.focused) {
+            return false;
+        }
+
+        let modifiers_match = input_state.ctrl == combo.ctrl
+            && input_state.shift == combo.shift
+            && input_state.alt == combo.alt;
+
+        modifiers_match
+            && input_state
+                .events
+                .iter()
+                .zip(shortcuts.repeated.iter())
+                .any(|(event, repeated)| match event {
+                    Some(InputEvent::KeyPress { keycode }) => {
+                        *keycode == combo.key && (config.repeat || !repeated)
+                    }
+                    _ => false,
+                })
+    }
+}
+
+#[derive(Clone)]
+pub struct ShortcutConfig<'b> {
+    // Must be unique and stable across frames, conflicts are reported with it
+    pub id: ContentId,
+    // Modifiers and exactly one other key, e.g. [KEY_LEFTCTRL, KEY_F]. Either the
+    // left or right modifier can be given, and other modifiers must not be held.
+    pub keys: &'b [Keycode],
+    // Whether it fires while a text widget has the focus
+    pub global: bool,
+    // Whether it fires again while the keys are held down
+    pub repeat: bool,
+}
+
+impl Default for ShortcutConfig<'_> {
+    fn default() -> Self {
+        ShortcutConfig {
+            id: ContentId(0),
+            keys: &[],
+            global: false,
+            repeat: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Combo {
+    ctrl: bool,
+    shift: bool,
+    alt: bool,
+    key: Keycode,
+}
+
+impl Combo {
+    fn from_keys(keys: &[Keycode]) -> Option<Self> {
+        let has = |left, right| keys.iter().any(|k| *k == left || *k == right);
+        let key = keys.iter().copied().find(|k| !is_modifier(*k))?;
+
+        Some(Combo {
+            ctrl: has(Keycode::KEY_LEFTCTRL, Keycode::KEY_RIGHTCTRL),
+            shift: has(Keycode::KEY_LEFTSHIFT, Keycode::KEY_RIGHTSHIFT),
+            alt: has(Keycode::KEY_LEFTALT, Keycode::KEY_RIGHTALT),
+            key,
+        })
+    }
+}
+
+fn is_modifier(keycode: Keycode) -> bool {
+    matches!(
+        keycode,
+        Keycode::KEY_LEFTCTRL
+            | Keycode::KEY_RIGHTCTRL
+            | Keycode::KEY_LEFTSHIFT
+            | Keycode::KEY_RIGHTSHIFT
+            | Keycode::KEY_LEFTALT
+            | Keycode::KEY_RIGHTALT
+    )
+}
+
+// Tells key repeats apart from fresh presses, and keeps track of the registered
+// shortcuts to report conflicts
+pub(crate) struct ShortcutState {
+    held: BTreeSet<Keycode>,
+    // For each event of the current frame, whether it is a repeat of a held key
+    repeated: [bool; MAX_EVENTS],
+    frame_time: Option<f64>,
+    #[cfg(debug_assertions)]
+    registered: Vec<(Combo, ContentId)>,
+    // So that each conflict is only logged once
+    #[cfg(debug_assertions)]
+    reported: BTreeSet<(ContentId, ContentId)>,
+}
+
+impl ShortcutState {
+    pub(crate) fn new() -> Self {
+        ShortcutState {
+            held: BTreeSet::new(),
+            repeated: [false; MAX_EVENTS],
+            frame_time: None,
+            #[cfg(debug_assertions)]
+            registered: Vec::new(),
+            #[cfg(debug_assertions)]
+            reported: BTreeSet::new(),
+        }
+    }
+
+    // Apps may get several contexts in one frame, only the first one counts
+    pub(crate) fn new_frame(&mut self, time: f64, input_state: &InputState) {
+        if self.frame_time == Some(time) {
+            return;
+        }
+        self.frame_time = Some(time);
+
+        #[cfg(debug_assertions)]
+        self.registered.clear();
+
+        for (event, repeated) in input_state.events.iter().zip(self.repeated.iter_mut()) {
+            *repeated = match event {
+                Some(InputEvent::KeyPress { keycode }) => !self.held.insert(*keycode),
+                Some(InputEvent::KeyRelease { keycode }) => {
+                    self.held.remove(keycode);
+                    false
+                }
+                _ => false,
+            };
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn register(&mut self, combo: Combo, id: ContentId) {
+        let conflict = self
+            .registered
+            .iter()
+            .find(|(other_combo, other_id)| *other_combo == combo && *other_id != id);
+
+        match conflict {
+            Some((_, other_id)) => {
+                if self.reported.insert((*other_id, id)) {
+                    log::warn!(
+                        "Shortcut {:?} registered by both {:?} and {:?}",
+                        combo,
+                        other_id,
+                        id
+                    );
+                }
+            }
+            None => self.registered.push((combo, id)),
+        }
+    }
+}
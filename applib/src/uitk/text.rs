@@ -1,25 +1,38 @@
+use alloc::borrow::ToOwned;
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::mem::size_of;
 
 use crate::content::TrackedContent;
-use crate::drawing::text::{draw_rich_slice, FormattedRichText};
-use crate::input::{InputEvent, InputState};
-use crate::input::{Keycode, CHARMAP};
+use crate::drawing::text::{draw_rich_slice, is_supported_char, FormattedRichText, RichChar};
+use crate::input::{InputEvent, InputState, Keycode};
 use crate::Rect;
 use crate::{FbView, FbViewMut};
 
 use super::UuidProvider;
 
+// The selection goes from the anchor to the cursor, Shift+arrows extend it
 pub fn string_input<T: EditableText>(
     buffer: &mut T,
     input_state: &InputState,
     allow_newline: bool,
     cursor: &mut usize,
+    anchor: &mut Option<usize>,
     uuid_provider: &mut UuidProvider,
 ) {
     let buf_len = buffer.len();
     *cursor = usize::min(buf_len, *cursor);
 
+    let mut move_cursor = |cursor: &mut usize, new_cursor: usize| {
+        match input_state.shift {
+            true => {
+                anchor.get_or_insert(*cursor);
+            }
+            false => *anchor = None,
+        }
+        *cursor = new_cursor;
+    };
+
     enum TextUpdate {
         Newline,
         Backspace,
@@ -48,31 +61,27 @@ pub fn string_input<T: EditableText>(
             Some(InputEvent::KeyPress {
                 keycode: Keycode::KEY_LEFT,
             }) if *cursor > 0 => {
-                *cursor -= 1;
+                move_cursor(cursor, *cursor - 1);
             }
             Some(InputEvent::KeyPress {
                 keycode: Keycode::KEY_RIGHT,
             }) if *cursor < buf_len => {
-                *cursor += 1;
+                move_cursor(cursor, *cursor + 1);
             }
 
-            // Character input
-            Some(InputEvent::KeyPress { keycode }) => {
-                let new_char = CHARMAP
-                    .get(&keycode)
-                    .map(|(low_c, up_c)| if input_state.shift { *up_c } else { *low_c })
-                    .flatten();
-
-                if let Some(new_char) = new_char {
+            // Character input (Ctrl combinations are left to shortcuts)
+            Some(event) => {
+                if let Some(new_char) = typed_char(&event, input_state) {
                     updates.push(TextUpdate::Char(new_char))
                 }
             }
 
-            _ => (),
+            None => (),
         };
     }
 
     if !updates.is_empty() {
+        *anchor = None;
         for update in updates {
             match update {
                 TextUpdate::Newline => {
@@ -94,23 +103,265 @@ pub fn string_input<T: EditableText>(
     }
 }
 
+pub(crate) fn typed_char(event: &InputEvent, input_state: &InputState) -> Option<char> {
+    match event {
+        InputEvent::KeyPress { keycode } if !input_state.ctrl => input_state.key_char(*keycode),
+        InputEvent::Text { c } => Some(*c),
+        _ => None,
+    }
+}
+
+// Whether string_input() would edit the text (rather than just move the cursor)
+pub(crate) fn is_edit_event(
+    event: &InputEvent,
+    input_state: &InputState,
+    allow_newline: bool,
+) -> bool {
+    match event {
+        InputEvent::KeyPress {
+            keycode: Keycode::KEY_ENTER,
+        } => allow_newline,
+        InputEvent::KeyPress {
+            keycode: Keycode::KEY_BACKSPACE,
+        } => true,
+        event => typed_char(event, input_state).is_some(),
+    }
+}
+
+// Drops the typed characters that are refused, and those that would not fit
+pub(crate) fn filter_typed(
+    input_state: &InputState,
+    accept: &dyn Fn(char) -> bool,
+    mut room: usize,
+) -> InputState {
+    let mut filtered = input_state.clone();
+    for event in filtered.events.iter_mut() {
+        if let Some(c) = event.and_then(|event| typed_char(&event, input_state)) {
+            match accept(c) && room > 0 {
+                true => room -= 1,
+                false => *event = None,
+            }
+        }
+    }
+    filtered
+}
+
+// Pasted text is limited to what the fonts can draw
+pub(crate) fn sanitize_pasted(s: &str, allow_newline: bool) -> String {
+    s.chars()
+        .filter_map(|c| match c {
+            '\n' if allow_newline => Some('\n'),
+            '\n' | '\t' => Some(' '),
+            c if is_supported_char(c) => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+// Bounds of the word around the index, for double-click selection
+pub(crate) fn word_range(chars: &[char], index: usize) -> (usize, usize) {
+    let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+
+    let index = usize::min(index, chars.len());
+    if !chars.get(index).is_some_and(is_word) {
+        return (index, usize::min(index + 1, chars.len()));
+    }
+
+    let start = chars[..index]
+        .iter()
+        .rposition(|c| !is_word(c))
+        .map_or(0, |i| i + 1);
+    let end = chars[index..]
+        .iter()
+        .position(|c| !is_word(c))
+        .map_or(chars.len(), |i| index + i);
+
+    (start, end)
+}
+
+// Bounds of the line around the index, newline included, for triple-click selection
+pub(crate) fn line_range(chars: &[char], index: usize) -> (usize, usize) {
+    let index = usize::min(index, chars.len());
+
+    let start = chars[..index]
+        .iter()
+        .rposition(|c| *c == '\n')
+        .map_or(0, |i| i + 1);
+    let end = chars[index..]
+        .iter()
+        .position(|c| *c == '\n')
+        .map_or(chars.len(), |i| index + i + 1);
+
+    (start, end)
+}
+
 pub trait EditableText {
     fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     fn insert(&mut self, uuid_provider: &mut UuidProvider, pos: usize, c: char);
     fn remove(&mut self, uuid_provider: &mut UuidProvider, pos: usize);
+    fn slice(&self, start: usize, end: usize) -> EditChars;
+    fn splice(
+        &mut self,
+        uuid_provider: &mut UuidProvider,
+        ranges: &[(usize, usize)],
+        parts: &[EditChars],
+    );
+    fn replace(&mut self, uuid_provider: &mut UuidProvider, ranges: &[(usize, usize)], s: &str);
+}
+
+#[derive(Clone)]
+pub enum EditChars {
+    Plain(String),
+    Rich(Vec<RichChar>),
+}
+
+impl EditChars {
+    pub fn len(&self) -> usize {
+        match self {
+            EditChars::Plain(s) => s.chars().count(),
+            EditChars::Rich(chars) => chars.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            EditChars::Plain(s) => s.is_empty(),
+            EditChars::Rich(chars) => chars.is_empty(),
+        }
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        match self {
+            EditChars::Plain(s) => s.len(),
+            EditChars::Rich(chars) => chars.len() * size_of::<RichChar>(),
+        }
+    }
+
+    pub fn to_plain(&self) -> String {
+        match self {
+            EditChars::Plain(s) => s.clone(),
+            EditChars::Rich(chars) => chars.iter().map(|rc| rc.c).collect(),
+        }
+    }
+
+    pub fn append(&mut self, other: EditChars) {
+        match (self, other) {
+            (EditChars::Plain(s), other) => s.push_str(&other.to_plain()),
+            (EditChars::Rich(chars), EditChars::Rich(other)) => chars.extend(other),
+            (this, other) => {
+                let mut s = this.to_plain();
+                s.push_str(&other.to_plain());
+                *this = EditChars::Plain(s);
+            }
+        }
+    }
+}
+
+// Positions are in chars, this gives the byte offset of one
+fn byte_index(s: &str, pos: usize) -> usize {
+    s.char_indices().nth(pos).map_or(s.len(), |(i, _)| i)
+}
+
+fn char_slice(s: &str, start: usize, end: usize) -> &str {
+    &s[byte_index(s, start)..byte_index(s, end)]
+}
+
+// For widgets that track their content themselves
+impl EditableText for String {
+    fn len(&self) -> usize {
+        self.chars().count()
+    }
+
+    fn insert(&mut self, _uuid_provider: &mut UuidProvider, pos: usize, c: char) {
+        String::insert(self, byte_index(self, pos), c);
+    }
+
+    fn remove(&mut self, _uuid_provider: &mut UuidProvider, pos: usize) {
+        String::remove(self, byte_index(self, pos));
+    }
+
+    fn slice(&self, start: usize, end: usize) -> EditChars {
+        EditChars::Plain(char_slice(self, start, end).to_owned())
+    }
+
+    fn splice(
+        &mut self,
+        _uuid_provider: &mut UuidProvider,
+        ranges: &[(usize, usize)],
+        parts: &[EditChars],
+    ) {
+        let mut new = String::with_capacity(self.as_str().len());
+        let mut last_end = 0;
+
+        for (&(start, end), part) in ranges.iter().zip(parts.iter()) {
+            new.push_str(char_slice(self, last_end, start));
+            new.push_str(&part.to_plain());
+            last_end = end;
+        }
+
+        new.push_str(&self[byte_index(self, last_end)..]);
+
+        *self = new;
+    }
+
+    fn replace(&mut self, uuid_provider: &mut UuidProvider, ranges: &[(usize, usize)], s: &str) {
+        let parts: Vec<EditChars> = ranges
+            .iter()
+            .map(|_| EditChars::Plain(s.to_owned()))
+            .collect();
+        self.splice(uuid_provider, ranges, &parts);
+    }
 }
 
 impl EditableText for TrackedContent<String> {
     fn len(&self) -> usize {
-        self.as_ref().len()
+        self.as_ref().chars().count()
     }
 
     fn insert(&mut self, uuid_provider: &mut UuidProvider, pos: usize, c: char) {
-        self.mutate(uuid_provider).insert(pos, c);
+        let s = self.mutate(uuid_provider);
+        s.insert(byte_index(s, pos), c);
     }
 
     fn remove(&mut self, uuid_provider: &mut UuidProvider, pos: usize) {
-        self.mutate(uuid_provider).remove(pos);
+        let s = self.mutate(uuid_provider);
+        s.remove(byte_index(s, pos));
+    }
+
+    fn slice(&self, start: usize, end: usize) -> EditChars {
+        EditChars::Plain(char_slice(self.as_ref(), start, end).to_owned())
+    }
+
+    fn splice(
+        &mut self,
+        uuid_provider: &mut UuidProvider,
+        ranges: &[(usize, usize)],
+        parts: &[EditChars],
+    ) {
+        let old = self.as_ref();
+        let mut new = String::with_capacity(old.len());
+        let mut last_end = 0;
+
+        for (&(start, end), part) in ranges.iter().zip(parts.iter()) {
+            new.push_str(char_slice(old, last_end, start));
+            new.push_str(&part.to_plain());
+            last_end = end;
+        }
+
+        new.push_str(&old[byte_index(old, last_end)..]);
+
+        *self.mutate(uuid_provider) = new;
+    }
+
+    fn replace(&mut self, uuid_provider: &mut UuidProvider, ranges: &[(usize, usize)], s: &str) {
+        let parts: Vec<EditChars> = ranges
+            .iter()
+            .map(|_| EditChars::Plain(s.to_owned()))
+            .collect();
+        self.splice(uuid_provider, ranges, &parts);
     }
 }
 
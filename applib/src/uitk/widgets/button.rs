@@ -1,74 +1,215 @@
 use crate::drawing::primitives::{draw_rect, draw_rect_outline};
 use crate::drawing::text::{compute_text_bbox, draw_str, get_font};
+use crate::icons::{self, STOCK_ICON_SIZE};
+use crate::input::PointerState;
+use crate::uitk::focus::activation_pressed;
+use crate::uitk::frame::{draw_chrome, draw_widget_frame};
 use crate::uitk::{ContentId, UiContext};
-use crate::{Color, FbView, FbViewMut, Framebuffer, OwnedPixels, Rect, StyleSheet};
+use crate::{Color, FbView, FbViewMut, Framebuffer, OwnedPixels, Rect, StyleSheet, WidgetColors};
 use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
 use alloc::string::String;
+use num::traits::float::FloatCore;
+
+const DISABLED_ICON_DIM: u8 = 150;
+const HOVER_FADE_TIME: f64 = 120.0; // in ms
+const HOVER_STEPS: f32 = 8.0;
 
 impl<'a, F: FbViewMut> UiContext<'a, F> {
     pub fn button(&mut self, config: &ButtonConfig) -> bool {
+        self.button_event(config) == Some(ButtonEvent::Click)
+    }
+
+    // Like button(), but also reports long presses, see ButtonConfig::long_press_ms
+    pub fn button_event(&mut self, config: &ButtonConfig) -> Option<ButtonEvent> {
         let mut active = false;
-        self.button_inner(config, &mut active, false);
-        active
+        self.clipped(&config.rect, |ui| {
+            ui.button_inner(config, &mut active, false)
+        })
     }
 
     pub fn button_toggle(&mut self, config: &ButtonConfig, active: &mut bool) {
-        self.button_inner(config, active, false);
+        self.clipped(&config.rect, |ui| ui.button_inner(config, active, false));
     }
 
     pub fn button_toggle_once(&mut self, config: &ButtonConfig, active: &mut bool) {
-        self.button_inner(config, active, true);
+        self.clipped(&config.rect, |ui| ui.button_inner(config, active, true));
     }
 
-    fn button_inner(&mut self, config: &ButtonConfig, active: &mut bool, toggle_once: bool) {
-        let UiContext {
-            fb,
-            input_state,
-            stylesheet,
-            tile_cache,
-            ..
-        } = self;
+    fn button_inner(
+        &mut self,
+        config: &ButtonConfig,
+        active: &mut bool,
+        toggle_once: bool,
+    ) -> Option<ButtonEvent> {
+        // Buttons have no ID, their position is enough to tell them apart
+        let id = ContentId::from_hash(&config.rect);
+
+        let enabled = !(*active && toggle_once) && !config.disabled;
+        let focused = match enabled {
+            true => self.focusable(id, &config.rect, true),
+            false => {
+                self.unfocus(id);
+                false
+            }
+        };
 
-        let ps = &input_state.pointer;
-        let hovered = config.rect.check_contains_point(ps.x, ps.y) && !(*active && toggle_once);
-        let clicked = hovered && ps.left_click_trigger;
-
-        let state = {
-            if hovered && !clicked {
-                ButtonState::Hover
-            } else {
-                if hovered && clicked {
-                    *active = !(*active);
-                }
-                match *active {
-                    true => ButtonState::Clicked,
-                    false => ButtonState::Idle,
-                }
+        let ps = &self.input_state.pointer;
+        let hovered = config.rect.check_contains_point(ps.x, ps.y) && enabled;
+
+        let event = match enabled {
+            true => pointer_event(
+                &mut self.interaction.button_presses,
+                id,
+                config,
+                ps,
+                hovered,
+                self.time,
+            ),
+            false => {
+                self.interaction.button_presses.remove(&id);
+                None
             }
         };
+        let event = match focused && activation_pressed(self.input_state) {
+            true => Some(ButtonEvent::Click),
+            false => event,
+        };
+        let clicked = event == Some(ButtonEvent::Click);
+
+        if clicked {
+            *active = !(*active);
+        }
+
+        let pressed = hovered && self.interaction.button_presses.contains_key(&id);
+
+        // Hovering fades in and out, in a few steps so that the renders can be cached
+        let hover_target = match hovered && !clicked {
+            true => 1.0,
+            false => 0.0,
+        };
+        let hover = self.animate(
+            ContentId::from_hash(&(id, "hover")),
+            hover_target,
+            HOVER_FADE_TIME,
+        );
+        let hover = (hover * HOVER_STEPS).round() / HOVER_STEPS;
+
+        let style = &self.stylesheet.widgets;
+        let selected = *active && config.indicator_mode != ButtonIndicatorMode::Light;
+        let colors = match (config.disabled, pressed, selected) {
+            (true, _, _) => style.disabled,
+            (false, true, _) => style.pressed,
+            (false, false, true) => style.selected.lerp(&style.hover, hover),
+            (false, false, false) => style.normal.lerp(&style.hover, hover),
+        };
 
         let content_id = ContentId::from_hash(&(
-            state,
             &config.rect,
             &config.text,
             *active,
             config.icon.as_ref().map(|(name, _)| name),
+            config.icon_name.as_ref(),
+            colors,
+            (style.border_width, style.border_radius, style.padding),
         ));
 
-        let button_fb = tile_cache.fetch_or_create(content_id, self.time, || {
-            render_button(stylesheet, config, state, *active)
+        let UiContext {
+            fb,
+            stylesheet,
+            tile_cache,
+            time,
+            ..
+        } = self;
+
+        let button_fb = tile_cache.fetch_or_create(content_id, *time, || {
+            render_button(stylesheet, config, *active, &colors)
         });
 
+        // Rounded corners leave the background of the button transparent
         let Rect { x0, y0, .. } = config.rect;
-        fb.copy_from_fb(button_fb, (x0, y0), false);
+        fb.copy_from_fb(button_fb, (x0, y0), stylesheet.widgets.border_radius > 0);
+
+        if focused {
+            self.draw_focus_ring(&config.rect);
+        }
+
+        self.register_tooltip(id, &config.rect, &config.tooltip);
+
+        event
+    }
+}
+
+// Clicks, repeats and long presses from the pointer
+fn pointer_event(
+    presses: &mut BTreeMap<ContentId, ButtonPress>,
+    id: ContentId,
+    config: &ButtonConfig,
+    ps: &PointerState,
+    hovered: bool,
+    time: f64,
+) -> Option<ButtonEvent> {
+    // Repeating buttons fire right away, so they cannot tell long presses apart
+    let long_press_ms = match config.repeat {
+        Some(_) => None,
+        None => config.long_press_ms,
+    };
+
+    if hovered && ps.left_click_trigger {
+        let next_fire = config.repeat.as_ref().map_or(0.0, |repeat| repeat.delay_ms);
+        presses.insert(
+            id,
+            ButtonPress {
+                held_time: 0.0,
+                last_time: time,
+                next_fire,
+                long_pressed: false,
+            },
+        );
+        // With long presses, the click waits for the release
+        return match long_press_ms {
+            Some(_) => None,
+            None => Some(ButtonEvent::Click),
+        };
+    }
+
+    let press = presses.get_mut(&id)?;
+
+    if !ps.left_clicked {
+        let long_pressed = press.long_pressed;
+        presses.remove(&id);
+        let short_press = hovered && long_press_ms.is_some() && !long_pressed;
+        return short_press.then_some(ButtonEvent::Click);
+    }
+
+    // The pointer left the button, everything waits for it to come back
+    let dt = time - press.last_time;
+    press.last_time = time;
+    if !hovered {
+        return None;
     }
+    press.held_time += dt;
+
+    if let Some(repeat) = &config.repeat {
+        if press.held_time >= press.next_fire {
+            press.next_fire = press.held_time + repeat.interval_ms;
+            return Some(ButtonEvent::Click);
+        }
+    } else if let Some(long_press_ms) = long_press_ms {
+        if !press.long_pressed && press.held_time >= long_press_ms {
+            press.long_pressed = true;
+            return Some(ButtonEvent::LongPress);
+        }
+    }
+
+    None
 }
 
 fn render_button(
     stylesheet: &StyleSheet,
     config: &ButtonConfig,
-    state: ButtonState,
     active: bool,
+    colors: &WidgetColors,
 ) -> Framebuffer<OwnedPixels> {
     let rect = config.rect.zero_origin();
 
@@ -76,10 +217,30 @@ fn render_button(
     let mut button_fb = Framebuffer::new_owned(w, h);
 
     let colorsheet = &stylesheet.colors;
-
-    let button_rect = rect;
-
-    draw_rect(&mut button_fb, &button_rect, colorsheet.element, false);
+    let style = &stylesheet.widgets;
+
+    let button_rect = draw_chrome(&mut button_fb, &rect, &stylesheet.chrome.button, colors.bg)
+        .unwrap_or_else(|| draw_widget_frame(&mut button_fb, &rect, style, colors));
+
+    let bg_color = colors.bg;
+    let text_color = colors.text;
+
+    // Named icons come from the atlas, in the color of the text
+    let named_icon = match &config.icon {
+        Some(_) => None,
+        None => config.icon_name.as_ref().and_then(|name| {
+            let size = u32::min(
+                STOCK_ICON_SIZE,
+                button_rect.h.saturating_sub(2 * style.padding),
+            );
+            icons::get_tinted(name, size, text_color)
+        }),
+    };
+    let icon = config
+        .icon
+        .as_ref()
+        .map(|(_, icon_fb)| *icon_fb)
+        .or(named_icon.as_ref());
 
     let (mut x, gap) = match config.indicator_mode {
         ButtonIndicatorMode::Light => {
@@ -103,8 +264,8 @@ fn render_button(
             (x, gap)
         }
 
-        _ => match &config.icon {
-            Some((_, icon_fb)) => {
+        _ => match icon {
+            Some(icon_fb) => {
                 let (_icon_w, icon_h) = icon_fb.shape();
                 let gap = i64::max(0, button_rect.h as i64 - icon_h as i64) / 2;
                 (button_rect.x0, gap)
@@ -115,17 +276,17 @@ fn render_button(
     };
 
     if config.indicator_mode == ButtonIndicatorMode::Border && active {
+        // The selection border takes the padding, over the regular border if any
         draw_rect_outline(
             &mut button_fb,
             &button_rect,
-            Color::WHITE,
+            colors.border,
             false,
-            stylesheet.margin,
+            style.padding,
         );
     }
 
-    if let Some(icon) = &config.icon {
-        let (_, icon_fb) = icon;
+    if let Some(icon_fb) = icon {
         let (icon_w, icon_h) = icon_fb.shape();
 
         let mut icon_rect = Rect {
@@ -146,7 +307,14 @@ fn render_button(
             icon_rect.x0 = x + gap;
         }
 
-        button_fb.copy_from_fb(*icon_fb, (icon_rect.x0, icon_rect.y0), true);
+        button_fb.copy_from_fb(icon_fb, (icon_rect.x0, icon_rect.y0), true);
+
+        // Dimming the icon, named ones already have the disabled color
+        if config.disabled && config.icon.is_some() {
+            let (r, g, b, _) = bg_color.as_rgba();
+            let dim = Color::rgba(r, g, b, DISABLED_ICON_DIM);
+            draw_rect(&mut button_fb, &icon_rect, dim, true);
+        }
 
         let [_, _, x1, _] = icon_rect.as_xyxy();
         x = x1;
@@ -165,7 +333,7 @@ fn render_button(
         }
         .align_to_rect_vert(&button_rect);
 
-        if config.icon.is_none() && config.indicator_mode != ButtonIndicatorMode::Light {
+        if icon.is_none() && config.indicator_mode != ButtonIndicatorMode::Light {
             let content_rect = {
                 let [_, y0, x1, y1] = button_rect.as_xyxy();
                 Rect::from_xyxy([x, y0, x1, y1])
@@ -181,25 +349,14 @@ fn render_button(
             text_rect.x0,
             text_rect.y0,
             font,
-            colorsheet.text,
+            text_color,
             None,
         );
     }
 
-    if state == ButtonState::Hover {
-        draw_rect(&mut button_fb, &button_rect, colorsheet.hover_overlay, true);
-    }
-
     button_fb
 }
 
-#[derive(PartialEq, Hash, Clone, Copy)]
-enum ButtonState {
-    Idle,
-    Hover,
-    Clicked,
-}
-
 #[derive(PartialEq, Hash, Clone, Copy)]
 pub enum ButtonIndicatorMode {
     Off,
@@ -212,8 +369,18 @@ pub struct ButtonConfig {
     pub rect: Rect,
     pub text: String,
     pub icon: Option<(String, &'static Framebuffer<OwnedPixels>)>,
+    // A stock or registered icon (see applib::icons), used if there is no icon above
+    pub icon_name: Option<String>,
     pub untoggle: bool,
     pub indicator_mode: ButtonIndicatorMode,
+    pub tooltip: Option<String>,
+    // Drawn muted, cannot be hovered, clicked or focused
+    pub disabled: bool,
+    // Clicks again and again while held down
+    pub repeat: Option<RepeatConfig>,
+    // Held for that long (in ms), the button reports a long press instead of a click,
+    // and short presses only click on release. Ignored for repeating buttons.
+    pub long_press_ms: Option<f64>,
 }
 
 impl Default for ButtonConfig {
@@ -227,8 +394,44 @@ impl Default for ButtonConfig {
             },
             text: "".to_owned(),
             icon: None,
+            icon_name: None,
             untoggle: true,
             indicator_mode: ButtonIndicatorMode::Off,
+            tooltip: None,
+            disabled: false,
+            repeat: None,
+            long_press_ms: None,
         }
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct RepeatConfig {
+    // In ms, before the first repeat and between the next ones
+    pub delay_ms: f64,
+    pub interval_ms: f64,
+}
+
+impl Default for RepeatConfig {
+    fn default() -> Self {
+        RepeatConfig {
+            delay_ms: 400.0,
+            interval_ms: 80.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEvent {
+    Click,
+    LongPress,
+}
+
+// A button held down with the pointer
+pub(crate) struct ButtonPress {
+    // In ms, only counting the time spent over the button
+    held_time: f64,
+    last_time: f64,
+    next_fire: f64,
+    long_pressed: bool,
+}
@@ -0,0 +1,167 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_rect, draw_rect_outline};
+use crate::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use crate::uitk::focus::activation_pressed;
+use crate::uitk::UiContext;
+use crate::{FbViewMut, Rect};
+use alloc::string::String;
+
+const BOX_SIZE: u32 = 14;
+const MARK_INSET: i64 = 3;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    pub fn checkbox(&mut self, config: &CheckboxConfig, checked: &mut bool) {
+        let mut state = match *checked {
+            true => CheckState::Checked,
+            false => CheckState::Unchecked,
+        };
+        self.checkbox_tristate(config, &mut state);
+        *checked = state == CheckState::Checked;
+    }
+
+    // Toggling an indeterminate box checks it, the indeterminate state can only
+    // be set by the app (e.g. for a "select all" box over a partial selection)
+    pub fn checkbox_tristate(&mut self, config: &CheckboxConfig, state: &mut CheckState) {
+        self.clipped(&config.rect, |ui| ui.checkbox_tristate_inner(config, state))
+    }
+
+    fn checkbox_tristate_inner(&mut self, config: &CheckboxConfig, state: &mut CheckState) {
+        // The label is part of the hit area
+        let focused = match config.disabled {
+            true => {
+                self.unfocus(config.id);
+                false
+            }
+            false => self.focusable(config.id, &config.rect, true),
+        };
+
+        let UiContext {
+            fb,
+            stylesheet,
+            input_state,
+            ..
+        } = self;
+
+        let colorsheet = &stylesheet.colors;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+        let m = stylesheet.margin as i64;
+
+        let Rect { x0, y0, w, h } = config.rect;
+
+        //
+        // Interaction
+
+        let ps = &input_state.pointer;
+        let hovered = !config.disabled && config.rect.check_contains_point(ps.x, ps.y);
+
+        let clicked = hovered && ps.left_click_trigger;
+        let toggled = clicked || focused && activation_pressed(input_state);
+
+        if toggled {
+            *state = match *state {
+                CheckState::Checked => CheckState::Unchecked,
+                _ => CheckState::Checked,
+            };
+        }
+
+        //
+        // Drawing
+
+        let (box_color, mark_color, text_color) = match config.disabled {
+            true => (
+                colorsheet.disabled_element,
+                colorsheet.disabled,
+                colorsheet.disabled,
+            ),
+            false => (colorsheet.editable, colorsheet.accent, colorsheet.text),
+        };
+
+        let box_rect = Rect {
+            x0,
+            y0: y0 + (h as i64 - BOX_SIZE as i64) / 2,
+            w: BOX_SIZE,
+            h: BOX_SIZE,
+        };
+        draw_rect(*fb, &box_rect, box_color, false);
+        if hovered {
+            draw_rect(*fb, &box_rect, colorsheet.hover_overlay, true);
+        }
+
+        let inner_rect = box_rect.offset(-MARK_INSET);
+        match *state {
+            CheckState::Checked => draw_rect(*fb, &inner_rect, mark_color, false),
+            CheckState::Indeterminate => {
+                let bar_rect = Rect {
+                    y0: inner_rect.y0 + (inner_rect.h / 2) as i64 - 1,
+                    h: 2,
+                    ..inner_rect
+                };
+                draw_rect(*fb, &bar_rect, mark_color, false);
+            }
+            CheckState::Unchecked => (),
+        }
+
+        match focused {
+            true => draw_rect_outline(
+                *fb,
+                &box_rect,
+                colorsheet.focus_ring,
+                false,
+                stylesheet.focus_ring_width,
+            ),
+            false => draw_rect_outline(*fb, &box_rect, colorsheet.outline, false, 1),
+        }
+
+        let label_x0 = x0 + BOX_SIZE as i64 + 2 * m;
+        let label_rect = Rect {
+            x0: label_x0,
+            y0,
+            w: (x0 + w as i64 - label_x0).max(0) as u32,
+            h,
+        };
+        draw_line_in_rect(
+            *fb,
+            &config.label,
+            &label_rect,
+            font,
+            text_color,
+            TextJustification::Left,
+        );
+
+        self.register_tooltip(config.id, &config.rect, &config.tooltip);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckState {
+    Unchecked,
+    Checked,
+    Indeterminate,
+}
+
+#[derive(Clone)]
+pub struct CheckboxConfig {
+    // Must be unique and stable across frames, focus is tied to it
+    pub id: ContentId,
+    pub rect: Rect,
+    pub label: String,
+    pub disabled: bool,
+    pub tooltip: Option<String>,
+}
+
+impl Default for CheckboxConfig {
+    fn default() -> Self {
+        CheckboxConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 100,
+                h: 25,
+            },
+            label: String::new(),
+            disabled: false,
+            tooltip: None,
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_rect, draw_rect_outline};
+use crate::uitk::{TextInputConfig, TextInputEvent, TextInputState, UiContext};
+use crate::{Color, FbViewMut, Framebuffer, OwnedPixels, Rect};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use num::traits::float::FloatCore;
+
+const STRIP_W: u32 = 16;
+const ROW_H: u32 = 25;
+const SWATCH_SIZE: u32 = 18;
+const MARKER_SIZE: u32 = 7;
+const CHECKER_SIZE: u32 = 4;
+const MAX_RECENT: usize = 8;
+
+const CHECKER_COLORS: [Color; 2] = [Color::rgb(200, 200, 200), Color::rgb(120, 120, 120)];
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    pub fn color_picker(&mut self, config: &ColorPickerConfig, color: &mut Color) {
+        self.clipped(&config.rect, |ui| ui.color_picker_inner(config, color))
+    }
+
+    fn color_picker_inner(&mut self, config: &ColorPickerConfig, color: &mut Color) {
+        // Taken out while drawing, the hex field needs the whole context
+        let mut picker = self
+            .color_pickers
+            .pickers
+            .remove(&config.id)
+            .unwrap_or_else(|| PickerState::new(*color));
+
+        // The app may have changed the color since the last frame
+        if picker.color != *color {
+            picker.set_color(*color);
+            picker.hex = color.to_hex();
+        }
+
+        let layout = PickerLayout::new(config, self.stylesheet.margin);
+        let mut commit = false;
+
+        //
+        // Gradients
+
+        let sv_id = ContentId::from_hash(&(config.id, "sv"));
+        let hue_id = ContentId::from_hash(&(config.id, "hue"));
+        let alpha_id = ContentId::from_hash(&(config.id, "alpha"));
+
+        let ps = &self.input_state.pointer;
+        let areas = [
+            Some((sv_id, &layout.sv_rect)),
+            Some((hue_id, &layout.hue_rect)),
+            layout.alpha_rect.as_ref().map(|rect| (alpha_id, rect)),
+        ];
+        for (id, rect) in areas.into_iter().flatten() {
+            if ps.left_click_trigger && rect.check_contains_point(ps.x, ps.y) {
+                self.interaction.dragged = Some(id);
+            }
+        }
+
+        // Follows the pointer outside of the gradient, until released
+        let dragged = self
+            .interaction
+            .dragged
+            .filter(|id| [sv_id, hue_id, alpha_id].contains(id));
+
+        if let Some(id) = dragged {
+            match ps.left_clicked {
+                true => {
+                    let (mut h, mut s, mut v) = picker.hsv;
+                    let (r, g, b, mut a) = color.as_rgba();
+                    if id == sv_id {
+                        s = fraction(ps.x, layout.sv_rect.x0, layout.sv_rect.w);
+                        v = 1.0 - fraction(ps.y, layout.sv_rect.y0, layout.sv_rect.h);
+                    } else if id == hue_id {
+                        h = 360.0 * fraction(ps.y, layout.hue_rect.y0, layout.hue_rect.h);
+                    } else if let Some(rect) = &layout.alpha_rect {
+                        a = (255.0 * (1.0 - fraction(ps.y, rect.y0, rect.h))).round() as u8;
+                    }
+
+                    let new_color = match id == alpha_id {
+                        true => Color::rgba(r, g, b, a),
+                        false => Color::from_hsv(h, s, v, a),
+                    };
+                    picker.hsv = (h, s, v);
+                    picker.color = new_color;
+                    picker.hex = new_color.to_hex();
+                    *color = new_color;
+                }
+                false => {
+                    self.interaction.dragged = None;
+                    commit = true;
+                }
+            }
+        }
+
+        //
+        // Hex field
+
+        let event = self.text_input(
+            &TextInputConfig {
+                rect: layout.hex_rect.clone(),
+                max_len: Some(9),
+                filter: Some(is_hex_char),
+                ..Default::default()
+            },
+            &mut picker.hex,
+            &mut picker.hex_state,
+        );
+
+        if let Some(parsed) = Color::from_hex(&picker.hex) {
+            match event {
+                TextInputEvent::Changed => {
+                    picker.set_color(parsed);
+                    *color = parsed;
+                }
+                TextInputEvent::Submitted => commit = true,
+                TextInputEvent::None => (),
+            }
+        }
+
+        // Left with an incomplete value
+        if !picker.hex_state.is_focused() && Color::from_hex(&picker.hex) != Some(*color) {
+            picker.hex = color.to_hex();
+        }
+
+        //
+        // Recent colors
+
+        let ps = &self.input_state.pointer;
+        let recent = &mut self.color_pickers.recent;
+        let clicked_swatch = layout
+            .swatch_rects
+            .iter()
+            .zip(recent.iter())
+            .find(|(rect, _)| ps.left_click_trigger && rect.check_contains_point(ps.x, ps.y))
+            .map(|(_, swatch_color)| *swatch_color);
+
+        if let Some(swatch_color) = clicked_swatch {
+            picker.set_color(swatch_color);
+            picker.hex = swatch_color.to_hex();
+            *color = swatch_color;
+        }
+
+        if commit {
+            recent.retain(|c| *c != *color);
+            recent.insert(0, *color);
+            recent.truncate(MAX_RECENT);
+        }
+
+        //
+        // Drawing
+
+        self.draw_gradients(&layout, &picker, *color);
+
+        let colorsheet = &self.stylesheet.colors;
+        let (outline, text) = (colorsheet.outline, colorsheet.text);
+
+        let (h, s, v) = picker.hsv;
+        let sv_rect = &layout.sv_rect;
+        let marker_x = sv_rect.x0 + (s * (sv_rect.w - 1) as f32).round() as i64;
+        let marker_y = sv_rect.y0 + ((1.0 - v) * (sv_rect.h - 1) as f32).round() as i64;
+        draw_marker(
+            self.fb,
+            &Rect::from_center(marker_x, marker_y, MARKER_SIZE, MARKER_SIZE),
+        );
+
+        let hue_rect = &layout.hue_rect;
+        draw_strip_marker(self.fb, hue_rect, h / 360.0);
+
+        if let Some(alpha_rect) = &layout.alpha_rect {
+            let (_, _, _, a) = color.as_rgba();
+            draw_strip_marker(self.fb, alpha_rect, 1.0 - a as f32 / 255.0);
+        }
+
+        draw_swatch(self.fb, &layout.preview_rect, *color, outline);
+
+        let recent = &self.color_pickers.recent;
+        for (rect, swatch_color) in layout.swatch_rects.iter().zip(recent.iter()) {
+            let border = match *swatch_color == *color {
+                true => text,
+                false => outline,
+            };
+            draw_swatch(self.fb, rect, *swatch_color, border);
+        }
+
+        self.color_pickers.pickers.insert(config.id, picker);
+    }
+
+    // Cached, since they are costly to render and only change with the hue or color
+    fn draw_gradients(&mut self, layout: &PickerLayout, picker: &PickerState, color: Color) {
+        let UiContext {
+            fb,
+            tile_cache,
+            time,
+            stylesheet,
+            ..
+        } = self;
+
+        let (h, _, _) = picker.hsv;
+        let outline = stylesheet.colors.outline;
+        let (r, g, b, _) = color.as_rgba();
+
+        let sv_rect = &layout.sv_rect;
+        let sv_id = ContentId::from_hash(&("color_picker_sv", h.to_bits(), sv_rect.shape()));
+        let sv_fb = tile_cache.fetch_or_create(sv_id, *time, || render_sv_square(h, sv_rect));
+        fb.copy_from_fb(sv_fb, sv_rect.origin(), false);
+
+        let hue_rect = &layout.hue_rect;
+        let hue_id = ContentId::from_hash(&("color_picker_hue", hue_rect.shape()));
+        let hue_fb = tile_cache.fetch_or_create(hue_id, *time, || render_hue_strip(hue_rect));
+        fb.copy_from_fb(hue_fb, hue_rect.origin(), false);
+
+        let mut frames = Vec::from([sv_rect, hue_rect]);
+
+        if let Some(alpha_rect) = &layout.alpha_rect {
+            let alpha_id =
+                ContentId::from_hash(&("color_picker_alpha", (r, g, b), alpha_rect.shape()));
+            let alpha_fb = tile_cache.fetch_or_create(alpha_id, *time, || {
+                render_alpha_strip(Color::rgb(r, g, b), alpha_rect)
+            });
+            fb.copy_from_fb(alpha_fb, alpha_rect.origin(), false);
+            frames.push(alpha_rect);
+        }
+
+        for rect in frames {
+            draw_rect_outline(*fb, rect, outline, false, 1);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ColorPickerConfig {
+    // Must be unique and stable across frames, the hue and hex field are tied to it
+    pub id: ContentId,
+    pub rect: Rect,
+    pub show_alpha: bool,
+}
+
+impl Default for ColorPickerConfig {
+    fn default() -> Self {
+        ColorPickerConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 200,
+                h: 200,
+            },
+            show_alpha: false,
+        }
+    }
+}
+
+// The pickers of the app, and the colors recently picked in any of them
+pub(crate) struct ColorPickerStore {
+    pickers: BTreeMap<ContentId, PickerState>,
+    // Most recent first
+    recent: Vec<Color>,
+}
+
+impl ColorPickerStore {
+    pub(crate) fn new() -> Self {
+        ColorPickerStore {
+            pickers: BTreeMap::new(),
+            recent: Vec::new(),
+        }
+    }
+}
+
+struct PickerState {
+    // Kept apart from the color, so that greys do not lose their hue
+    hsv: (f32, f32, f32),
+    color: Color,
+    hex: String,
+    hex_state: TextInputState,
+}
+
+impl PickerState {
+    fn new(color: Color) -> Self {
+        PickerState {
+            hsv: color.to_hsv(),
+            color,
+            hex: color.to_hex(),
+            hex_state: TextInputState::new(),
+        }
+    }
+
+    fn set_color(&mut self, color: Color) {
+        let (h, s, v) = color.to_hsv();
+        let (old_h, old_s, _) = self.hsv;
+        self.hsv = match (s, v) {
+            (_, 0.0) => (old_h, old_s, v),
+            (0.0, _) => (old_h, s, v),
+            _ => (h, s, v),
+        };
+        self.color = color;
+    }
+}
+
+struct PickerLayout {
+    sv_rect: Rect,
+    hue_rect: Rect,
+    alpha_rect: Option<Rect>,
+    preview_rect: Rect,
+    hex_rect: Rect,
+    swatch_rects: Vec<Rect>,
+}
+
+impl PickerLayout {
+    // Gradients at the top, then the hex field and the recent colors
+    fn new(config: &ColorPickerConfig, margin: u32) -> Self {
+        let Rect { x0, y0, w, h } = config.rect;
+        let m = margin;
+
+        let nb_strips = match config.show_alpha {
+            true => 2,
+            false => 1,
+        };
+        let gradients_h = h.saturating_sub(ROW_H + SWATCH_SIZE + 2 * m);
+        let sv_w = w.saturating_sub(nb_strips * (STRIP_W + m));
+
+        let strip_rect = |i: u32| Rect {
+            x0: x0 + (sv_w + m + i * (STRIP_W + m)) as i64,
+            y0,
+            w: STRIP_W,
+            h: gradients_h,
+        };
+
+        let row_y0 = y0 + (gradients_h + m) as i64;
+        let swatches_y0 = row_y0 + (ROW_H + m) as i64;
+
+        let nb_swatches = usize::min(MAX_RECENT, ((w + m) / (SWATCH_SIZE + m)) as usize);
+        let swatch_rects = (0..nb_swatches)
+            .map(|i| Rect {
+                x0: x0 + (i as u32 * (SWATCH_SIZE + m)) as i64,
+                y0: swatches_y0,
+                w: SWATCH_SIZE,
+                h: SWATCH_SIZE,
+            })
+            .collect();
+
+        PickerLayout {
+            sv_rect: Rect {
+                x0,
+                y0,
+                w: sv_w,
+                h: gradients_h,
+            },
+            hue_rect: strip_rect(0),
+            alpha_rect: config.show_alpha.then(|| strip_rect(1)),
+            preview_rect: Rect {
+                x0,
+                y0: row_y0,
+                w: ROW_H,
+                h: ROW_H,
+            },
+            hex_rect: Rect {
+                x0: x0 + (ROW_H + m) as i64,
+                y0: row_y0,
+                w: w.saturating_sub(ROW_H + m),
+                h: ROW_H,
+            },
+            swatch_rects,
+        }
+    }
+}
+
+fn is_hex_char(c: char) -> bool {
+    c == '#' || c.is_ascii_hexdigit()
+}
+
+// Position of the pointer along a gradient, between 0 and 1
+fn fraction(pos: i64, start: i64, len: u32) -> f32 {
+    let len = u32::max(len, 2) - 1;
+    ((pos - start) as f32 / len as f32).clamp(0.0, 1.0)
+}
+
+fn render_sv_square(hue: f32, rect: &Rect) -> Framebuffer<OwnedPixels> {
+    let Rect { w, h, .. } = *rect;
+    let mut fb = Framebuffer::new_owned(w, h);
+    let (w_max, h_max) = (u32::max(w, 2) - 1, u32::max(h, 2) - 1);
+
+    for y in 0..h {
+        let v = 1.0 - y as f32 / h_max as f32;
+        for x in 0..w {
+            let s = x as f32 / w_max as f32;
+            fb.set_pixel(x as i64, y as i64, Color::from_hsv(hue, s, v, 255));
+        }
+    }
+
+    fb
+}
+
+fn render_hue_strip(rect: &Rect) -> Framebuffer<OwnedPixels> {
+    let Rect { w, h, .. } = *rect;
+    let mut fb = Framebuffer::new_owned(w, h);
+    let h_max = u32::max(h, 2) - 1;
+
+    for y in 0..h {
+        let hue = 360.0 * y as f32 / h_max as f32;
+        let row = Rect {
+            x0: 0,
+            y0: y as i64,
+            w,
+            h: 1,
+        };
+        draw_rect(&mut fb, &row, Color::from_hsv(hue, 1.0, 1.0, 255), false);
+    }
+
+    fb
+}
+
+// Opaque at the top, over a checkerboard to show the transparency
+fn render_alpha_strip(color: Color, rect: &Rect) -> Framebuffer<OwnedPixels> {
+    let Rect { w, h, .. } = *rect;
+    let mut fb = Framebuffer::new_owned(w, h);
+    let h_max = u32::max(h, 2) - 1;
+    let (r, g, b, _) = color.as_rgba();
+
+    draw_checkerboard(&mut fb, &rect.zero_origin());
+
+    for y in 0..h {
+        let a = (255.0 * (1.0 - y as f32 / h_max as f32)).round() as u8;
+        let row = Rect {
+            x0: 0,
+            y0: y as i64,
+            w,
+            h: 1,
+        };
+        draw_rect(&mut fb, &row, Color::rgba(r, g, b, a), true);
+    }
+
+    fb
+}
+
+fn draw_checkerboard<F: FbViewMut>(fb: &mut F, rect: &Rect) {
+    let nb_x = rect.w.div_ceil(CHECKER_SIZE);
+    let nb_y = rect.h.div_ceil(CHECKER_SIZE);
+
+    for j in 0..nb_y {
+        for i in 0..nb_x {
+            let cell = Rect {
+                x0: rect.x0 + (i * CHECKER_SIZE) as i64,
+                y0: rect.y0 + (j * CHECKER_SIZE) as i64,
+                w: u32::min(CHECKER_SIZE, rect.w - i * CHECKER_SIZE),
+                h: u32::min(CHECKER_SIZE, rect.h - j * CHECKER_SIZE),
+            };
+            draw_rect(fb, &cell, CHECKER_COLORS[((i + j) % 2) as usize], false);
+        }
+    }
+}
+
+fn draw_swatch<F: FbViewMut>(fb: &mut F, rect: &Rect, color: Color, border: Color) {
+    draw_checkerboard(fb, rect);
+    draw_rect(fb, rect, color, true);
+    draw_rect_outline(fb, rect, border, false, 1);
+}
+
+// Black and white, to show on any color
+fn draw_marker<F: FbViewMut>(fb: &mut F, rect: &Rect) {
+    draw_rect_outline(fb, rect, Color::BLACK, false, 1);
+    draw_rect_outline(fb, &rect.offset(-1), Color::WHITE, false, 1);
+}
+
+// Across a strip, at a position between 0 (top) and 1 (bottom)
+fn draw_strip_marker<F: FbViewMut>(fb: &mut F, strip_rect: &Rect, t: f32) {
+    let y = strip_rect.y0 + (t * (strip_rect.h.saturating_sub(1)) as f32).round() as i64;
+    let rect = Rect {
+        x0: strip_rect.x0 - 1,
+        y0: y - 2,
+        w: strip_rect.w + 2,
+        h: 5,
+    };
+    draw_marker(fb, &rect);
+}
@@ -0,0 +1,140 @@
+use crate::content::ContentId;
+use crate::uitk::layout::{solve_box_layout, BoxItem};
+use crate::uitk::UiContext;
+use crate::{FbViewMut, Rect};
+use alloc::vec::Vec;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    // Items from top to bottom
+    pub fn vbox(
+        &mut self,
+        rect: &Rect,
+        spacing: u32,
+        items: &[BoxItem],
+        func: impl FnOnce(&mut Self, &[LayoutCell]),
+    ) {
+        let rects = solve_box_layout(rect.h, spacing, items)
+            .into_iter()
+            .map(|(offset, size)| Rect {
+                y0: rect.y0 + offset,
+                h: size,
+                ..rect.clone()
+            })
+            .collect();
+        self.layout_container(rect, rects, func);
+    }
+
+    // Items from left to right
+    pub fn hbox(
+        &mut self,
+        rect: &Rect,
+        spacing: u32,
+        items: &[BoxItem],
+        func: impl FnOnce(&mut Self, &[LayoutCell]),
+    ) {
+        let rects = solve_box_layout(rect.w, spacing, items)
+            .into_iter()
+            .map(|(offset, size)| Rect {
+                x0: rect.x0 + offset,
+                w: size,
+                ..rect.clone()
+            })
+            .collect();
+        self.layout_container(rect, rects, func);
+    }
+
+    // Cells in row-major order
+    pub fn grid(
+        &mut self,
+        rect: &Rect,
+        spacing: u32,
+        columns: &[BoxItem],
+        rows: &[BoxItem],
+        func: impl FnOnce(&mut Self, &[LayoutCell]),
+    ) {
+        let x_sizes = solve_box_layout(rect.w, spacing, columns);
+        let y_sizes = solve_box_layout(rect.h, spacing, rows);
+
+        let rects = y_sizes
+            .iter()
+            .flat_map(|(y_offset, h)| {
+                x_sizes.iter().map(move |(x_offset, w)| Rect {
+                    x0: rect.x0 + x_offset,
+                    y0: rect.y0 + y_offset,
+                    w: *w,
+                    h: *h,
+                })
+            })
+            .collect();
+        self.layout_container(rect, rects, func);
+    }
+
+    fn layout_container(
+        &mut self,
+        rect: &Rect,
+        rects: Vec<Rect>,
+        func: impl FnOnce(&mut Self, &[LayoutCell]),
+    ) {
+        let id = self.layout.enter();
+
+        let cells: Vec<LayoutCell> = rects
+            .into_iter()
+            .enumerate()
+            .map(|(i, rect)| LayoutCell {
+                rect,
+                id: ContentId::from_hash(&(id, "cell", i)),
+            })
+            .collect();
+
+        self.clipped(rect, |ui| func(ui, &cells));
+
+        self.layout.exit();
+    }
+}
+
+pub struct LayoutCell {
+    pub rect: Rect,
+    // Derived from the position of the cell in the layout tree, so that it stays the
+    // same across frames and when containers are added elsewhere
+    pub id: ContentId,
+}
+
+// The containers being laid out, to give them IDs
+pub(crate) struct LayoutState {
+    // Container IDs, and how many containers each of them has seen inside so far
+    stack: Vec<(ContentId, usize)>,
+    nb_roots: usize,
+}
+
+impl LayoutState {
+    pub(crate) fn new() -> Self {
+        LayoutState {
+            stack: Vec::new(),
+            nb_roots: 0,
+        }
+    }
+
+    pub(crate) fn new_frame(&mut self) {
+        self.stack.clear();
+        self.nb_roots = 0;
+    }
+
+    fn enter(&mut self) -> ContentId {
+        let id = match self.stack.last_mut() {
+            Some((parent_id, nb_children)) => {
+                *nb_children += 1;
+                ContentId::from_hash(&(*parent_id, "box", *nb_children - 1))
+            }
+            None => {
+                self.nb_roots += 1;
+                ContentId::from_hash(&("layout_root", self.nb_roots - 1))
+            }
+        };
+        self.stack.push((id, 0));
+        id
+    }
+
+    fn exit(&mut self) {
+        self.stack.pop();
+    }
+}
@@ -0,0 +1,529 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_rect, draw_rect_outline};
+use crate::drawing::text::{
+    compute_text_bbox, draw_line_in_rect, get_font, Font, TextJustification,
+};
+use crate::input::{InputEvent, InputState, Keycode, PointerState};
+use crate::uitk::popup::Popup;
+use crate::uitk::UiContext;
+use crate::{FbViewMut, Rect, StyleSheet};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const MIN_MENU_W: u32 = 120;
+const SEPARATOR_H: u32 = 5;
+// Room for the ">" of submenus
+const ARROW_W: u32 = 16;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    // Must be called every frame, the menu closes otherwise. The caller opens it
+    // by setting open_at, typically to the pointer position when it sees a right-click.
+    pub fn context_menu(&mut self, config: &ContextMenuConfig) -> Option<usize> {
+        let UiContext {
+            fb,
+            stylesheet,
+            popup,
+            ..
+        } = self;
+
+        // A choice made in the menu is picked up here, in the same frame
+        match popup.as_mut() {
+            Some(Popup::ContextMenu(p)) if p.id == config.id => match p.choice {
+                Some(i) => {
+                    **popup = None;
+                    return Some(i);
+                }
+                None => {
+                    p.items = config.items.clone();
+                    p.seen = true;
+                }
+            },
+            _ => (),
+        }
+
+        if let Some(origin) = config.open_at {
+            let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+            let menu = MenuPopup::new(
+                config.id,
+                config.items.clone(),
+                origin,
+                fb.shape(),
+                font,
+                stylesheet.margin,
+            );
+            **popup = Some(Popup::ContextMenu(menu));
+        }
+
+        None
+    }
+}
+
+#[derive(Clone)]
+pub enum MenuItem {
+    // The shortcut is only a hint shown on the right, the app handles the keys itself
+    Entry {
+        label: String,
+        shortcut: Option<String>,
+        disabled: bool,
+    },
+    Submenu {
+        label: String,
+        items: Vec<MenuItem>,
+    },
+    Separator,
+}
+
+impl MenuItem {
+    pub fn entry(label: &str) -> Self {
+        MenuItem::Entry {
+            label: label.into(),
+            shortcut: None,
+            disabled: false,
+        }
+    }
+
+    pub fn entry_with_shortcut(label: &str, shortcut: &str) -> Self {
+        MenuItem::Entry {
+            label: label.into(),
+            shortcut: Some(shortcut.into()),
+            disabled: false,
+        }
+    }
+
+    // Number of items in the tree starting at this one
+    fn tree_len(&self) -> usize {
+        match self {
+            MenuItem::Submenu { items, .. } => {
+                1 + items.iter().map(MenuItem::tree_len).sum::<usize>()
+            }
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ContextMenuConfig {
+    // Must be unique and stable across frames, the open state is tied to it
+    pub id: ContentId,
+    // Items are numbered in depth-first order, submenus and separators included,
+    // so for a menu without submenus the returned index is the position in this list
+    pub items: Vec<MenuItem>,
+    // Opens the menu at this position, replacing any open one
+    pub open_at: Option<(i64, i64)>,
+}
+
+impl Default for ContextMenuConfig {
+    fn default() -> Self {
+        ContextMenuConfig {
+            id: ContentId(0),
+            items: Vec::new(),
+            open_at: None,
+        }
+    }
+}
+
+// An open context menu, with its open submenus
+pub(crate) struct MenuPopup {
+    pub(crate) id: ContentId,
+    pub(crate) items: Vec<MenuItem>,
+    origin: (i64, i64),
+    // The menu is kept inside these bounds (the window)
+    bounds: (u32, u32),
+    font: &'static Font,
+    margin: u32,
+    // For each level, the submenu opened from it
+    open_path: Vec<usize>,
+    // Depth and row, from the pointer or the arrow keys
+    hovered: Option<(usize, usize)>,
+    pub(crate) choice: Option<usize>,
+    closed: bool,
+    // Whether the widget that owns it was drawn this frame
+    pub(crate) seen: bool,
+}
+
+struct MenuLevel<'b> {
+    rect: Rect,
+    rows: Vec<Rect>,
+    items: &'b [MenuItem],
+    // Depth-first index of the first item
+    first_index: usize,
+}
+
+impl MenuPopup {
+    pub(crate) fn new(
+        id: ContentId,
+        items: Vec<MenuItem>,
+        origin: (i64, i64),
+        bounds: (u32, u32),
+        font: &'static Font,
+        margin: u32,
+    ) -> Self {
+        MenuPopup {
+            id,
+            items,
+            origin,
+            bounds,
+            font,
+            margin,
+            open_path: Vec::new(),
+            hovered: None,
+            choice: None,
+            closed: false,
+            seen: true,
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub(crate) fn row_h(&self) -> u32 {
+        self.font.char_h as u32 + 2 * self.margin
+    }
+
+    fn levels(&self) -> Vec<MenuLevel<'_>> {
+        let mut levels: Vec<MenuLevel> = Vec::new();
+        let mut items = self.items.as_slice();
+        let mut first_index = 0;
+
+        loop {
+            let (w, h) = self.level_shape(items);
+            let (bounds_w, bounds_h) = (self.bounds.0 as i64, self.bounds.1 as i64);
+
+            // Submenus open on the right of their row, or on the left if there is no room
+            let (x0, y0) = match levels.last() {
+                None => {
+                    let (x, y) = self.origin;
+                    (i64::min(x, bounds_w - w as i64), y)
+                }
+                Some(parent) => {
+                    let row = &parent.rows[self.open_path[levels.len() - 1]];
+                    let right_x0 = parent.rect.x0 + parent.rect.w as i64;
+                    let x0 = match right_x0 + w as i64 > bounds_w {
+                        true => parent.rect.x0 - w as i64,
+                        false => right_x0,
+                    };
+                    (x0, row.y0 - 1)
+                }
+            };
+
+            let rect = Rect {
+                x0: x0.max(0),
+                y0: i64::min(y0, bounds_h - h as i64).max(0),
+                w,
+                h,
+            };
+
+            let mut y = rect.y0 + 1;
+            let rows = items
+                .iter()
+                .map(|item| {
+                    let h = match item {
+                        MenuItem::Separator => SEPARATOR_H,
+                        _ => self.row_h(),
+                    };
+                    let row = Rect {
+                        x0: rect.x0 + 1,
+                        y0: y,
+                        w: rect.w.saturating_sub(2),
+                        h,
+                    };
+                    y += h as i64;
+                    row
+                })
+                .collect();
+
+            levels.push(MenuLevel {
+                rect,
+                rows,
+                items,
+                first_index,
+            });
+
+            let depth = levels.len() - 1;
+            match self.open_path.get(depth).and_then(|i| items.get(*i)) {
+                Some(MenuItem::Submenu {
+                    items: sub_items, ..
+                }) => {
+                    let i = self.open_path[depth];
+                    first_index += items[..i].iter().map(MenuItem::tree_len).sum::<usize>() + 1;
+                    items = sub_items;
+                }
+                _ => return levels,
+            }
+        }
+    }
+
+    fn level_shape(&self, items: &[MenuItem]) -> (u32, u32) {
+        let m = self.margin;
+        let text_w = items
+            .iter()
+            .map(|item| match item {
+                MenuItem::Entry {
+                    label,
+                    shortcut: Some(shortcut),
+                    ..
+                } => {
+                    compute_text_bbox(label, self.font).0
+                        + 2 * m
+                        + compute_text_bbox(shortcut, self.font).0
+                }
+                MenuItem::Entry { label, .. } | MenuItem::Submenu { label, .. } => {
+                    compute_text_bbox(label, self.font).0
+                }
+                MenuItem::Separator => 0,
+            })
+            .max()
+            .unwrap_or(0);
+
+        let h = items
+            .iter()
+            .map(|item| match item {
+                MenuItem::Separator => SEPARATOR_H,
+                _ => self.row_h(),
+            })
+            .sum::<u32>();
+
+        (u32::max(MIN_MENU_W, text_w + 4 * m + ARROW_W), h + 2)
+    }
+
+    pub(crate) fn handle_input(&mut self, input_state: &InputState) {
+        for event in input_state.events.iter() {
+            if let Some(InputEvent::KeyPress { keycode }) = event {
+                self.handle_key(*keycode);
+            }
+        }
+
+        self.handle_pointer(&input_state.pointer);
+    }
+
+    // Returns whether the key was used, Left and Right are not at the top level
+    pub(crate) fn handle_key(&mut self, keycode: Keycode) -> bool {
+        let levels = self.levels();
+
+        let (depth, current) = match self.hovered {
+            Some((depth, i)) => (depth, Some(i)),
+            None => (levels.len() - 1, None),
+        };
+        let items = levels[depth].items;
+        let current_item = current.map(|i| &items[i]);
+
+        let selectable = |i: &usize| !matches!(items[*i], MenuItem::Separator);
+        let n = items.len();
+        let next_row = |forward: bool| {
+            let start = current.unwrap_or(match forward {
+                true => n.saturating_sub(1),
+                false => 0,
+            });
+            (1..=n)
+                .map(|k| match forward {
+                    true => (start + k) % n,
+                    false => (start + n - k) % n,
+                })
+                .find(selectable)
+        };
+
+        let choice = match (current, current_item) {
+            (
+                Some(i),
+                Some(MenuItem::Entry {
+                    disabled: false, ..
+                }),
+            ) => Some(choice_index(&levels[depth], i)),
+            _ => None,
+        };
+        let is_submenu = matches!(current_item, Some(MenuItem::Submenu { .. }));
+        let first_sub_row = match current_item {
+            Some(MenuItem::Submenu { items, .. }) => items
+                .iter()
+                .position(|item| !matches!(item, MenuItem::Separator)),
+            _ => None,
+        };
+
+        drop(levels);
+
+        match keycode {
+            Keycode::KEY_ESC => self.closed = true,
+            Keycode::KEY_UP | Keycode::KEY_DOWN => {
+                if let Some(i) = next_row(keycode == Keycode::KEY_DOWN) {
+                    self.open_path.truncate(depth);
+                    self.hovered = Some((depth, i));
+                }
+            }
+            Keycode::KEY_RIGHT | Keycode::KEY_ENTER | Keycode::KEY_SPACE if is_submenu => {
+                self.open_path.truncate(depth);
+                self.open_path.extend(current);
+                self.hovered = first_sub_row.map(|i| (depth + 1, i));
+            }
+            Keycode::KEY_ENTER | Keycode::KEY_SPACE => self.choice = choice,
+            Keycode::KEY_LEFT if depth > 0 => {
+                self.hovered = Some((depth - 1, self.open_path[depth - 1]));
+                self.open_path.truncate(depth - 1);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    pub(crate) fn handle_pointer(&mut self, ps: &PointerState) {
+        let levels = self.levels();
+
+        // Deepest first, submenus can overlap their parent
+        let inside = levels
+            .iter()
+            .any(|level| level.rect.check_contains_point(ps.x, ps.y));
+        let hovered = levels.iter().enumerate().rev().find_map(|(depth, level)| {
+            match level.rect.check_contains_point(ps.x, ps.y) {
+                true => level
+                    .rows
+                    .iter()
+                    .position(|row| row.check_contains_point(ps.x, ps.y))
+                    .map(|i| (depth, i)),
+                false => None,
+            }
+        });
+
+        let clicked = ps.left_click_trigger || ps.right_click_trigger;
+
+        let choice = match hovered {
+            Some((depth, i)) if clicked => match &levels[depth].items[i] {
+                MenuItem::Entry {
+                    disabled: false, ..
+                } => Some(choice_index(&levels[depth], i)),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let is_submenu = hovered
+            .is_some_and(|(depth, i)| matches!(levels[depth].items[i], MenuItem::Submenu { .. }));
+
+        drop(levels);
+
+        // A still pointer leaves the row picked with the arrow keys alone
+        let moved = ps.delta_x != 0 || ps.delta_y != 0;
+
+        // Submenus open on hover, and stay open while the pointer is outside the menu
+        if let Some((depth, i)) = hovered {
+            if moved || clicked {
+                self.open_path.truncate(depth);
+                if is_submenu {
+                    self.open_path.push(i);
+                }
+            }
+        }
+
+        if moved || clicked {
+            self.hovered = hovered;
+        }
+        if choice.is_some() {
+            self.choice = choice;
+        }
+
+        if clicked && !inside {
+            self.closed = true;
+        }
+    }
+
+    // For menus opened from the keyboard
+    pub(crate) fn hover_first(&mut self) {
+        self.hovered = self
+            .items
+            .iter()
+            .position(|item| !matches!(item, MenuItem::Separator))
+            .map(|i| (0, i));
+    }
+
+    pub(crate) fn draw<F: FbViewMut>(&self, fb: &mut F, stylesheet: &StyleSheet) {
+        let colorsheet = &stylesheet.colors;
+        let m = self.margin;
+
+        for (depth, level) in self.levels().iter().enumerate() {
+            draw_rect(fb, &level.rect, colorsheet.element, false);
+
+            for (i, (item, row)) in level.items.iter().zip(level.rows.iter()).enumerate() {
+                let hovered = self.hovered == Some((depth, i));
+                let text_rect = Rect {
+                    x0: row.x0 + 2 * m as i64,
+                    w: row.w.saturating_sub(4 * m),
+                    ..row.clone()
+                };
+
+                match item {
+                    MenuItem::Separator => {
+                        let line_rect = Rect {
+                            y0: row.y0 + (row.h / 2) as i64,
+                            h: 1,
+                            ..text_rect
+                        };
+                        draw_rect(fb, &line_rect, colorsheet.outline, false);
+                    }
+                    MenuItem::Entry {
+                        label,
+                        shortcut,
+                        disabled,
+                    } => {
+                        let text_color = match disabled {
+                            true => colorsheet.disabled,
+                            false => colorsheet.text,
+                        };
+                        if hovered && !disabled {
+                            draw_rect(fb, row, colorsheet.hover_overlay, true);
+                        }
+                        draw_line_in_rect(
+                            fb,
+                            label,
+                            &text_rect,
+                            self.font,
+                            text_color,
+                            TextJustification::Left,
+                        );
+                        if let Some(shortcut) = shortcut {
+                            draw_line_in_rect(
+                                fb,
+                                shortcut,
+                                &text_rect,
+                                self.font,
+                                colorsheet.disabled,
+                                TextJustification::Right,
+                            );
+                        }
+                    }
+                    MenuItem::Submenu { label, .. } => {
+                        if hovered || self.open_path.get(depth) == Some(&i) {
+                            draw_rect(fb, row, colorsheet.hover_overlay, true);
+                        }
+                        draw_line_in_rect(
+                            fb,
+                            label,
+                            &text_rect,
+                            self.font,
+                            colorsheet.text,
+                            TextJustification::Left,
+                        );
+                        draw_line_in_rect(
+                            fb,
+                            ">",
+                            &text_rect,
+                            self.font,
+                            colorsheet.text,
+                            TextJustification::Right,
+                        );
+                    }
+                }
+            }
+
+            draw_rect_outline(fb, &level.rect, colorsheet.outline, false, 1);
+        }
+    }
+}
+
+// Depth-first index of a row, as returned to the app
+fn choice_index(level: &MenuLevel, i: usize) -> usize {
+    let offset = level.items[..i]
+        .iter()
+        .map(MenuItem::tree_len)
+        .sum::<usize>();
+    level.first_index + offset
+}
@@ -0,0 +1,402 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_rect, draw_rect_outline};
+use crate::drawing::text::{draw_line_in_rect, get_font, Font, TextJustification};
+use crate::input::{InputEvent, InputState, Keycode};
+use crate::uitk::animation::{AnimationState, Easing};
+use crate::uitk::focus::activation_pressed;
+use crate::uitk::popup::Popup;
+use crate::uitk::UiContext;
+use crate::{Color, FbViewMut, Rect, StyleSheet};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const CHEVRON_W: u32 = 9;
+const SCROLLBAR_W: u32 = 4;
+const OPEN_TIME: f64 = 120.0; // in ms
+
+// With less room than that below the widget, the list opens upwards
+const MIN_ROWS_BELOW: usize = 3;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    pub fn dropdown(&mut self, config: &DropdownConfig, selected: &mut usize) {
+        self.clipped(&config.rect, |ui| ui.dropdown_inner(config, selected))
+    }
+
+    fn dropdown_inner(&mut self, config: &DropdownConfig, selected: &mut usize) {
+        let focused = match config.disabled {
+            true => {
+                self.unfocus(config.id);
+                false
+            }
+            false => self.focusable(config.id, &config.rect, true),
+        };
+
+        let UiContext {
+            fb,
+            stylesheet,
+            input_state,
+            popup,
+            animations,
+            ..
+        } = self;
+
+        let colorsheet = &stylesheet.colors;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+        let m = stylesheet.margin;
+
+        let Rect { x0, y0, w, h } = config.rect;
+
+        // A choice made in the list is picked up here, in the same frame
+        match popup.as_mut() {
+            Some(Popup::Dropdown(p)) if p.id == config.id => match p.choice {
+                Some(i) => {
+                    *selected = i;
+                    **popup = None;
+                }
+                None => p.seen = true,
+            },
+            _ => (),
+        }
+
+        *selected = usize::min(*selected, config.options.len().saturating_sub(1));
+
+        // Disabled while open
+        if config.disabled
+            && matches!(popup.as_ref(), Some(Popup::Dropdown(p)) if p.id == config.id)
+        {
+            **popup = None;
+        }
+
+        let open = matches!(popup.as_ref(), Some(Popup::Dropdown(p)) if p.id == config.id);
+
+        //
+        // Interaction
+
+        // Clicks only get there while no popup is open, the list captures them otherwise
+        let ps = &input_state.pointer;
+        let hovered = !config.disabled && config.rect.check_contains_point(ps.x, ps.y);
+
+        // While focused, the arrow keys change the selection and Space or Enter open the list
+        let mut open_list = hovered && ps.left_click_trigger;
+        if focused {
+            open_list |= activation_pressed(input_state);
+            for event in input_state.events.iter() {
+                match event {
+                    Some(InputEvent::KeyPress {
+                        keycode: Keycode::KEY_UP,
+                    }) => *selected = selected.saturating_sub(1),
+                    Some(InputEvent::KeyPress {
+                        keycode: Keycode::KEY_DOWN,
+                    }) => *selected += 1,
+                    _ => (),
+                }
+            }
+            *selected = usize::min(*selected, config.options.len().saturating_sub(1));
+        }
+
+        if open_list && !open && !config.options.is_empty() {
+            let (_, fb_h) = fb.shape();
+            let list = DropdownPopup::new(config, *selected, row_height(font, m), fb_h);
+            **popup = Some(Popup::Dropdown(list));
+            animations.reset(open_animation_id(config.id), 0.0);
+        }
+
+        //
+        // Drawing
+
+        let (bg_color, text_color) = match config.disabled {
+            true => (colorsheet.disabled_element, colorsheet.disabled),
+            false => (colorsheet.element, colorsheet.text),
+        };
+
+        draw_rect(*fb, &config.rect, bg_color, false);
+        if hovered || open {
+            draw_rect(*fb, &config.rect, colorsheet.hover_overlay, true);
+        }
+
+        let text = config.options.get(*selected).map_or("", String::as_str);
+        let text_rect = Rect {
+            x0: x0 + m as i64,
+            y0,
+            w: w.saturating_sub(3 * m + CHEVRON_W),
+            h,
+        };
+        draw_line_in_rect(
+            *fb,
+            text,
+            &text_rect,
+            font,
+            text_color,
+            TextJustification::Left,
+        );
+
+        let chevron_x0 = x0 + (w - m - CHEVRON_W) as i64;
+        let chevron_y0 = y0 + (h / 2) as i64 - (CHEVRON_W / 4) as i64;
+        draw_chevron(*fb, chevron_x0, chevron_y0, text_color, open);
+
+        if focused {
+            self.draw_focus_ring(&config.rect);
+        }
+
+        self.register_tooltip(config.id, &config.rect, &config.tooltip);
+    }
+}
+
+#[derive(Clone)]
+pub struct DropdownConfig {
+    // Must be unique and stable across frames, the open state is tied to it
+    pub id: ContentId,
+    pub rect: Rect,
+    pub options: Vec<String>,
+    pub max_visible_items: usize,
+    pub tooltip: Option<String>,
+    pub disabled: bool,
+}
+
+impl Default for DropdownConfig {
+    fn default() -> Self {
+        DropdownConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 100,
+                h: 25,
+            },
+            options: Vec::new(),
+            max_visible_items: 8,
+            tooltip: None,
+            disabled: false,
+        }
+    }
+}
+
+// The list of an open dropdown
+pub(crate) struct DropdownPopup {
+    id: ContentId,
+    rect: Rect,
+    // Whether the list opens below the widget or above it
+    below: bool,
+    options: Vec<String>,
+    row_h: u32,
+    nb_visible: usize,
+    selected: usize,
+    hovered: Option<usize>,
+    scroll: usize,
+    choice: Option<usize>,
+    closed: bool,
+    // Whether the dropdown that owns it was drawn this frame
+    pub(crate) seen: bool,
+}
+
+impl DropdownPopup {
+    fn new(config: &DropdownConfig, selected: usize, row_h: u32, fb_h: u32) -> Self {
+        let Rect { x0, y0, w, h } = config.rect;
+        let n = config.options.len();
+        let max_rows = usize::max(1, usize::min(n, config.max_visible_items));
+
+        let rows_below = (fb_h as i64 - y0 - h as i64).max(0) as usize / row_h as usize;
+        let rows_above = y0.max(0) as usize / row_h as usize;
+
+        let below = rows_below >= usize::min(max_rows, MIN_ROWS_BELOW) || rows_below >= rows_above;
+        let nb_visible = match below {
+            true => rows_below,
+            false => rows_above,
+        };
+        let nb_visible = usize::max(1, usize::min(nb_visible, max_rows));
+
+        let list_h = nb_visible as u32 * row_h;
+        let rect = Rect {
+            x0,
+            y0: match below {
+                true => y0 + h as i64,
+                false => y0 - list_h as i64,
+            },
+            w,
+            h: list_h,
+        };
+
+        DropdownPopup {
+            id: config.id,
+            rect,
+            below,
+            options: config.options.clone(),
+            row_h,
+            nb_visible,
+            selected,
+            hovered: None,
+            // Opens with the current choice in view
+            scroll: usize::min(selected, n - nb_visible),
+            choice: None,
+            closed: false,
+            seen: true,
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub(crate) fn handle_input(&mut self, input_state: &InputState) {
+        let ps = &input_state.pointer;
+        let inside = self.rect.check_contains_point(ps.x, ps.y);
+        let max_scroll = self.options.len() - self.nb_visible;
+
+        for event in input_state.events.iter() {
+            match event {
+                Some(InputEvent::Scroll { delta_y, .. }) if inside => {
+                    let scroll = self.scroll as i64 - delta_y;
+                    self.scroll = scroll.clamp(0, max_scroll as i64) as usize;
+                }
+                Some(InputEvent::KeyPress {
+                    keycode: Keycode::KEY_ESC,
+                }) => self.closed = true,
+                _ => (),
+            }
+        }
+
+        self.hovered = match inside {
+            true => {
+                let row = ((ps.y - self.rect.y0) / self.row_h as i64) as usize;
+                Some(self.scroll + row).filter(|i| *i < self.options.len())
+            }
+            false => None,
+        };
+
+        if ps.left_click_trigger {
+            match self.hovered {
+                Some(i) => self.choice = Some(i),
+                None => self.closed = true,
+            }
+        }
+    }
+
+    pub(crate) fn draw<F: FbViewMut>(
+        &self,
+        fb: &mut F,
+        stylesheet: &StyleSheet,
+        animations: &mut AnimationState,
+    ) {
+        let reveal =
+            animations.animate(open_animation_id(self.id), 1.0, OPEN_TIME, Easing::EaseOut);
+
+        // While opening, the list slides out of the widget and is cut where it meets it
+        let Rect { x0, y0, w, h } = self.rect;
+        let shown_h = (reveal.clamp(0.0, 1.0) * h as f32) as u32;
+        let shown_rect = Rect {
+            x0,
+            y0: match self.below {
+                true => y0,
+                false => y0 + (h - shown_h) as i64,
+            },
+            w,
+            h: shown_h,
+        };
+
+        let (fb_w, fb_h) = fb.shape();
+        let Some(clip_rect) = shown_rect.intersection(&Rect {
+            x0: 0,
+            y0: 0,
+            w: fb_w,
+            h: fb_h,
+        }) else {
+            return;
+        };
+
+        let list_rect = Rect {
+            x0: x0 - clip_rect.x0,
+            y0: match self.below {
+                true => y0 + shown_h as i64 - h as i64 - clip_rect.y0,
+                false => shown_rect.y0 - clip_rect.y0,
+            },
+            w,
+            h,
+        };
+        self.draw_list(&mut fb.subregion_mut(&clip_rect), stylesheet, &list_rect);
+    }
+
+    fn draw_list<F: FbViewMut>(&self, fb: &mut F, stylesheet: &StyleSheet, rect: &Rect) {
+        let colorsheet = &stylesheet.colors;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+        let m = stylesheet.margin;
+
+        let Rect { x0, y0, w, h } = *rect;
+        let scrollable = self.nb_visible < self.options.len();
+        let rows_w = match scrollable {
+            true => w.saturating_sub(SCROLLBAR_W),
+            false => w,
+        };
+
+        draw_rect(fb, rect, colorsheet.element, false);
+
+        let visible = self.scroll..self.scroll + self.nb_visible;
+        for (row, i) in visible.enumerate() {
+            let row_rect = Rect {
+                x0,
+                y0: y0 + (row as u32 * self.row_h) as i64,
+                w: rows_w,
+                h: self.row_h,
+            };
+
+            if i == self.selected {
+                draw_rect(fb, &row_rect, colorsheet.selected_overlay, true);
+            }
+            if self.hovered == Some(i) {
+                draw_rect(fb, &row_rect, colorsheet.hover_overlay, true);
+            }
+
+            let text_rect = Rect {
+                x0: x0 + m as i64,
+                w: rows_w.saturating_sub(2 * m),
+                ..row_rect
+            };
+            draw_line_in_rect(
+                fb,
+                &self.options[i],
+                &text_rect,
+                font,
+                colorsheet.text,
+                TextJustification::Left,
+            );
+        }
+
+        if scrollable {
+            let n = self.options.len() as u32;
+            let thumb_rect = Rect {
+                x0: x0 + rows_w as i64,
+                y0: y0 + (self.scroll as u32 * h / n) as i64,
+                w: SCROLLBAR_W,
+                h: u32::max(1, self.nb_visible as u32 * h / n),
+            };
+            draw_rect(fb, &thumb_rect, colorsheet.accent, false);
+        }
+
+        draw_rect_outline(fb, rect, colorsheet.outline, false, 1);
+    }
+}
+
+fn open_animation_id(id: ContentId) -> ContentId {
+    ContentId::from_hash(&(id, "open"))
+}
+
+fn row_height(font: &Font, margin: u32) -> u32 {
+    font.char_h as u32 + margin
+}
+
+// Points down when closed, up when open
+fn draw_chevron<F: FbViewMut>(fb: &mut F, x0: i64, y0: i64, color: Color, open: bool) {
+    let nb_rows = CHEVRON_W.div_ceil(2);
+    for row in 0..nb_rows {
+        let half_w = match open {
+            true => row,
+            false => nb_rows - 1 - row,
+        };
+        let row_rect = Rect {
+            x0: x0 + (nb_rows - 1 - half_w) as i64,
+            y0: y0 + row as i64,
+            w: 2 * half_w + 1,
+            h: 1,
+        };
+        draw_rect(fb, &row_rect, color, false);
+    }
+}
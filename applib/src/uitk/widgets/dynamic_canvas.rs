@@ -2,7 +2,7 @@ use alloc::vec::Vec;
 
 use crate::content::ContentId;
 use crate::drawing::primitives::draw_rect;
-use crate::input::InputEvent;
+use crate::input::{InputEvent, PointerState};
 use crate::Color;
 use crate::Rect;
 use crate::{FbView, FbViewMut, Framebuffer};
@@ -13,6 +13,11 @@ const DISABLE_CACHING: bool = false;
 const SCROLL_SPEED: u32 = 10;
 const SBAR_OUTER_W: u32 = 16;
 const SBAR_INNER_W: u32 = 12;
+const SBAR_MIN_THUMB_LEN: u32 = 20;
+
+// In milliseconds, scrollbars fade out after some time without being used
+const SBAR_HIDE_DELAY: f64 = 1000.0;
+const SBAR_FADE_TIME: f64 = 300.0;
 
 pub trait TileRenderer {
     fn shape(&self) -> (u32, u32);
@@ -28,12 +33,25 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
         renderer: &T,
         offsets: &mut (i64, i64),
         dragging: &mut (bool, bool),
+    ) {
+        self.clipped(dst_rect, |ui| {
+            ui.dynamic_canvas_inner(dst_rect, renderer, offsets, dragging)
+        })
+    }
+
+    fn dynamic_canvas_inner<T: TileRenderer>(
+        &mut self,
+        dst_rect: &Rect,
+        renderer: &T,
+        offsets: &mut (i64, i64),
+        dragging: &mut (bool, bool),
     ) {
         let UiContext {
             fb: dst_fb,
             tile_cache,
             input_state,
             stylesheet,
+            interaction,
             ..
         } = self;
 
@@ -70,112 +88,283 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
         let p_state = &input_state.pointer;
         let (x_dragging, y_dragging) = dragging;
 
-        let get_sbar_color = |dragging, hover| {
-            if dragging {
-                stylesheet.colors.selected_overlay
-            } else if hover {
-                stylesheet.colors.hover_overlay
-            } else {
-                stylesheet.colors.accent
-            }
+        // Scrollbars are identified by the position of their canvas
+        let sbar_id = ContentId::from_hash(&(dst_rect.x0, dst_rect.y0));
+        let sbar_state = interaction
+            .scrollbars
+            .entry(sbar_id)
+            .or_insert(ScrollbarState {
+                offsets: (*scroll_x0, *scroll_y0),
+                last_active: f64::NEG_INFINITY,
+                grab: 0,
+            });
+
+        let mut active = false;
+
+        // Leave the bottom-right corner free when both scrollbars are shown
+        let corner_w = match x_scroll_enabled && y_scroll_enabled {
+            true => SBAR_OUTER_W,
+            false => 0,
         };
 
         //
         // Vertical scrollbar
 
-        if y_scroll_enabled {
-            if *y_dragging {
-                *scroll_y0 +=
-                    (src_max_h as i64) * input_state.pointer.delta_y / (dst_rect.h as i64);
-            } else {
-                for event in input_state.events {
-                    if let Some(InputEvent::Scroll { delta }) = event {
-                        let x = input_state.pointer.x;
-                        let y = input_state.pointer.y;
-                        if dst_rect.check_contains_point(x, y) {
-                            *scroll_y0 -= delta * (SCROLL_SPEED as i64);
+        let y_sbar = match y_scroll_enabled {
+            false => None,
+            true => {
+                if !*y_dragging {
+                    for event in input_state.events {
+                        if let Some(InputEvent::Scroll { delta_y, .. }) = event {
+                            if dst_rect.check_contains_point(p_state.x, p_state.y) {
+                                *scroll_y0 -= delta_y * (SCROLL_SPEED as i64);
+                            }
                         }
                     }
                 }
+
+                let track_rect = Rect {
+                    x0: dst_rect.x0 + (dst_rect.w.saturating_sub(SBAR_OUTER_W)) as i64,
+                    y0: dst_rect.y0,
+                    w: u32::min(SBAR_OUTER_W, dst_rect.w),
+                    h: dst_rect.h.saturating_sub(corner_w),
+                };
+
+                let axis = ScrollAxis {
+                    vertical: true,
+                    content_len: src_max_h,
+                    view_len: dst_rect.h,
+                };
+
+                let sbar = axis.update(
+                    track_rect,
+                    scroll_y0,
+                    y_dragging,
+                    &mut sbar_state.grab,
+                    p_state,
+                );
+                active |= sbar.active;
+                Some(sbar)
             }
+        };
+
+        //
+        // Horizontal scrollbar
 
-            *scroll_y0 = i64::max(0, *scroll_y0);
-            *scroll_y0 = i64::min((src_max_h - dst_rect.h - 1).into(), *scroll_y0);
+        let x_sbar = match x_scroll_enabled {
+            false => None,
+            true => {
+                if !*x_dragging {
+                    for event in input_state.events {
+                        if let Some(InputEvent::Scroll { delta_x, .. }) = event {
+                            if dst_rect.check_contains_point(p_state.x, p_state.y) {
+                                *scroll_x0 += delta_x * (SCROLL_SPEED as i64);
+                            }
+                        }
+                    }
+                }
 
-            let sbar_outer_rect = Rect {
-                x0: (dst_rect.w - SBAR_OUTER_W).into(),
-                y0: dst_rect.y0,
-                w: SBAR_OUTER_W,
-                h: dst_rect.h,
-            };
+                let track_rect = Rect {
+                    x0: dst_rect.x0,
+                    y0: dst_rect.y0 + (dst_rect.h.saturating_sub(SBAR_OUTER_W)) as i64,
+                    w: dst_rect.w.saturating_sub(corner_w),
+                    h: u32::min(SBAR_OUTER_W, dst_rect.h),
+                };
+
+                let axis = ScrollAxis {
+                    vertical: false,
+                    content_len: src_max_w,
+                    view_len: dst_rect.w,
+                };
+
+                let sbar = axis.update(
+                    track_rect,
+                    scroll_x0,
+                    x_dragging,
+                    &mut sbar_state.grab,
+                    p_state,
+                );
+                active |= sbar.active;
+                Some(sbar)
+            }
+        };
 
-            let sbar_inner_rect = Rect {
-                x0: (dst_rect.w - SBAR_OUTER_W + (SBAR_OUTER_W - SBAR_INNER_W) / 2) as i64
-                    + dst_rect.x0,
-                y0: (dst_rect.h as i64) * (*scroll_y0) / (src_max_h as i64) + dst_rect.y0,
-                w: SBAR_INNER_W,
-                h: sbar_outer_rect.h * dst_rect.h / src_max_h,
-            };
+        //
+        // Auto-hide
 
-            let sbar_hover = sbar_inner_rect.check_contains_point(p_state.x, p_state.y);
+        // Offsets can also be changed by the app, e.g. when autoscrolling
+        if sbar_state.offsets != (*scroll_x0, *scroll_y0) {
+            sbar_state.offsets = (*scroll_x0, *scroll_y0);
+            active = true;
+        }
 
-            let sbar_color = get_sbar_color(*y_dragging, sbar_hover);
+        if active {
+            sbar_state.last_active = self.time;
+        }
 
-            draw_rect(*dst_fb, &sbar_inner_rect, sbar_color, false);
+        let inactive_time = self.time - sbar_state.last_active;
+        let alpha = 1.0 - ((inactive_time - SBAR_HIDE_DELAY) / SBAR_FADE_TIME).clamp(0.0, 1.0);
 
-            if p_state.left_clicked {
-                if p_state.left_click_trigger && sbar_hover {
-                    *y_dragging = true;
-                }
-            } else {
-                *y_dragging = false;
-            }
+        // Forget the scrollbars that have been hidden for a while, they start hidden anyway
+        let time = self.time;
+        interaction
+            .scrollbars
+            .retain(|_, state| time - state.last_active < SBAR_HIDE_DELAY + SBAR_FADE_TIME);
+
+        if alpha <= 0.0 {
+            return;
         }
 
         //
-        // Horizontal scrollbar
+        // Drawing
 
-        if x_scroll_enabled {
-            if *x_dragging {
-                *scroll_x0 +=
-                    (src_max_w as i64) * input_state.pointer.delta_x / (dst_rect.w as i64);
-            }
+        let colorsheet = &stylesheet.colors;
 
-            *scroll_x0 = i64::max(0, *scroll_x0);
-            *scroll_x0 = i64::min((src_max_w - dst_rect.w - 1).into(), *scroll_x0);
+        for (sbar, dragging) in [(y_sbar, *y_dragging), (x_sbar, *x_dragging)] {
+            let Some(sbar) = sbar else { continue };
 
-            let sbar_outer_rect = Rect {
-                x0: dst_rect.x0,
-                y0: dst_rect.y0 + (dst_rect.h - SBAR_OUTER_W) as i64,
-                w: dst_rect.w,
-                h: SBAR_OUTER_W,
+            let thumb_color = if dragging {
+                colorsheet.selected_overlay
+            } else if sbar.hovered {
+                colorsheet.hover_overlay
+            } else {
+                colorsheet.accent
             };
 
-            let sbar_inner_rect = Rect {
-                y0: (dst_rect.h - SBAR_OUTER_W + (SBAR_OUTER_W - SBAR_INNER_W) / 2) as i64
-                    + dst_rect.y0,
-                x0: (dst_rect.w as i64) * (*scroll_x0) / (src_max_w as i64) + dst_rect.x0,
-                h: SBAR_INNER_W,
-                w: sbar_outer_rect.w * dst_rect.w / src_max_w,
-            };
+            draw_rect(
+                *dst_fb,
+                &sbar.track_rect,
+                fade_color(colorsheet.frame, alpha),
+                true,
+            );
+            draw_rect(
+                *dst_fb,
+                &sbar.thumb_rect,
+                fade_color(thumb_color, alpha),
+                true,
+            );
+        }
+    }
+}
+
+// Scroll state of a canvas that is not part of the offsets owned by the app
+pub(crate) struct ScrollbarState {
+    // Offsets at the previous frame, to detect changes
+    offsets: (i64, i64),
+    last_active: f64,
+    // Position of the pointer within the thumb being dragged
+    grab: i64,
+}
+
+struct ScrollAxis {
+    vertical: bool,
+    content_len: u32,
+    view_len: u32,
+}
+
+struct Scrollbar {
+    track_rect: Rect,
+    thumb_rect: Rect,
+    hovered: bool,
+    // Whether the scrollbar was hovered or used this frame
+    active: bool,
+}
+
+impl ScrollAxis {
+    fn update(
+        &self,
+        track_rect: Rect,
+        scroll: &mut i64,
+        dragging: &mut bool,
+        grab: &mut i64,
+        p_state: &PointerState,
+    ) -> Scrollbar {
+        let (track_start, track_len, p) = match self.vertical {
+            true => (track_rect.y0, track_rect.h, p_state.y),
+            false => (track_rect.x0, track_rect.w, p_state.x),
+        };
 
-            let sbar_hover = sbar_inner_rect.check_contains_point(p_state.x, p_state.y);
+        let max_scroll = self.content_len as i64 - self.view_len as i64 - 1;
+        let thumb_len = u32::min(
+            u32::max(
+                SBAR_MIN_THUMB_LEN,
+                track_len * self.view_len / self.content_len,
+            ),
+            track_len,
+        );
+        let free_len = (track_len - thumb_len) as i64;
 
-            let sbar_color = get_sbar_color(*x_dragging, sbar_hover);
+        let thumb_rect = |scroll: i64| {
+            let start = match max_scroll > 0 {
+                true => track_start + free_len * scroll / max_scroll,
+                false => track_start,
+            };
+            let pad = ((SBAR_OUTER_W - SBAR_INNER_W) / 2) as i64;
+            match self.vertical {
+                true => Rect {
+                    x0: track_rect.x0 + pad,
+                    y0: start,
+                    w: SBAR_INNER_W,
+                    h: thumb_len,
+                },
+                false => Rect {
+                    x0: start,
+                    y0: track_rect.y0 + pad,
+                    w: thumb_len,
+                    h: SBAR_INNER_W,
+                },
+            }
+        };
 
-            draw_rect(*dst_fb, &sbar_inner_rect, sbar_color, false);
+        let thumb_start = |scroll: i64| match self.vertical {
+            true => thumb_rect(scroll).y0,
+            false => thumb_rect(scroll).x0,
+        };
 
-            if p_state.left_clicked {
-                if p_state.left_click_trigger && sbar_hover {
-                    *x_dragging = true;
+        let track_hovered = track_rect.check_contains_point(p_state.x, p_state.y);
+        let thumb_hovered = thumb_rect(*scroll).check_contains_point(p_state.x, p_state.y);
+
+        if p_state.left_clicked {
+            if p_state.left_click_trigger && thumb_hovered {
+                *dragging = true;
+                *grab = p - thumb_start(*scroll);
+            } else if p_state.left_click_trigger && track_hovered {
+                // Clicking the track scrolls by one page towards the pointer
+                match p < thumb_start(*scroll) {
+                    true => *scroll -= self.view_len as i64,
+                    false => *scroll += self.view_len as i64,
                 }
-            } else {
-                *x_dragging = false;
             }
+        } else {
+            *dragging = false;
+        }
+
+        // The scroll position is derived from the absolute pointer position rather than
+        // accumulated from its motion, so it stays accurate if the content size changes
+        if *dragging && free_len > 0 {
+            *scroll = (p - *grab - track_start) * max_scroll / free_len;
+        }
+
+        *scroll = i64::min(*scroll, max_scroll);
+        *scroll = i64::max(0, *scroll);
+
+        let thumb_rect = thumb_rect(*scroll);
+        let hovered = thumb_rect.check_contains_point(p_state.x, p_state.y);
+
+        Scrollbar {
+            track_rect,
+            thumb_rect,
+            hovered,
+            active: *dragging || track_hovered,
         }
     }
 }
 
+fn fade_color(color: Color, alpha: f64) -> Color {
+    let (r, g, b, a) = color.as_rgba();
+    Color::rgba(r, g, b, (a as f64 * alpha) as u8)
+}
+
 fn draw_tiles<F: FbViewMut, T: TileRenderer>(
     renderer: &T,
     dst_fb: &mut F,
@@ -5,6 +5,10 @@ use num::traits::float::FloatCore;
 
 impl<'a, F: FbViewMut> UiContext<'a, F> {
     pub fn graph(&mut self, config: &GraphConfig, series_list: &[GraphSeries]) {
+        self.clipped(&config.rect, |ui| ui.graph_inner(config, series_list))
+    }
+
+    fn graph_inner(&mut self, config: &GraphConfig, series_list: &[GraphSeries]) {
         if let Some(bg_color) = config.bg_color {
             draw_rect(self.fb, &config.rect, bg_color, false);
         }
@@ -5,6 +5,10 @@ use num::traits::float::FloatCore;
 
 impl<'a, F: FbViewMut> UiContext<'a, F> {
     pub fn horiz_bar(&mut self, config: &HorizBarConfig, values: &[BarValue]) {
+        self.clipped(&config.rect, |ui| ui.horiz_bar_inner(config, values))
+    }
+
+    fn horiz_bar_inner(&mut self, config: &HorizBarConfig, values: &[BarValue]) {
         const MARGIN: u32 = 2;
 
         draw_rect(self.fb, &config.rect, self.stylesheet.colors.element, false);
@@ -0,0 +1,343 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::draw_rect;
+use crate::drawing::text::{
+    compute_text_bbox, draw_line_in_rect, get_font, Font, TextJustification,
+};
+use crate::input::{InputEvent, InputState, Keycode, CHARMAP};
+use crate::uitk::popup::Popup;
+use crate::uitk::widgets::context_menu::MenuPopup;
+use crate::uitk::{MenuItem, UiContext};
+use crate::{FbViewMut, Rect, StyleSheet};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    // Must be called every frame, the open menu closes otherwise
+    pub fn menu_bar(&mut self, config: &MenuBarConfig) -> Option<MenuAction> {
+        self.clipped(&config.rect, |ui| ui.menu_bar_inner(config))
+    }
+
+    fn menu_bar_inner(&mut self, config: &MenuBarConfig) -> Option<MenuAction> {
+        let UiContext {
+            fb,
+            stylesheet,
+            input_state,
+            popup,
+            ..
+        } = self;
+
+        let colorsheet = &stylesheet.colors;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+        let m = stylesheet.margin;
+
+        let title_rects = title_rects(config, font, m);
+
+        // A choice made in a menu is picked up here, in the same frame
+        let mut action = None;
+        let mut open_menu = None;
+        match popup.as_mut() {
+            Some(Popup::MenuBar(p)) if p.id == config.id => match p.choice {
+                Some(choice) => {
+                    action = Some(choice);
+                    **popup = None;
+                }
+                None => {
+                    p.update(config);
+                    p.seen = true;
+                    open_menu = Some(p.current);
+                }
+            },
+            _ => (),
+        }
+
+        //
+        // Interaction
+
+        // Only while no popup is open, the open menu handles the input otherwise
+        let ps = &input_state.pointer;
+        let hovered = title_rects
+            .iter()
+            .position(|rect| rect.check_contains_point(ps.x, ps.y));
+
+        let clicked = hovered.filter(|_| ps.left_click_trigger);
+        let mnemonic = mnemonic_pressed(config, input_state);
+
+        if let (None, Some(i)) = (open_menu, clicked.or(mnemonic)) {
+            let from_keyboard = clicked.is_none();
+            let menu =
+                MenuBarPopup::new(config, &title_rects, i, from_keyboard, fb.shape(), font, m);
+            **popup = Some(Popup::MenuBar(menu));
+            open_menu = Some(i);
+        }
+
+        //
+        // Drawing
+
+        draw_rect(*fb, &config.rect, colorsheet.element, false);
+
+        for (i, (menu, rect)) in config.menus.iter().zip(title_rects.iter()).enumerate() {
+            if open_menu == Some(i) || hovered == Some(i) {
+                draw_rect(*fb, rect, colorsheet.hover_overlay, true);
+            }
+
+            draw_line_in_rect(
+                *fb,
+                &menu.title,
+                rect,
+                font,
+                colorsheet.text,
+                TextJustification::Center,
+            );
+
+            // Underlining the mnemonic letter
+            if let Some(pos) = mnemonic_position(menu) {
+                let (text_w, _) = compute_text_bbox(&menu.title, font);
+                let text_x0 = rect.x0 + (rect.w as i64 - text_w as i64) / 2;
+                let offsets = font.char_offsets(&menu.title);
+                let underline_rect = Rect {
+                    x0: text_x0 + offsets[pos],
+                    y0: rect.y0 + (rect.h as i64 + font.char_h as i64) / 2,
+                    w: (offsets[pos + 1] - offsets[pos]) as u32,
+                    h: 1,
+                };
+                draw_rect(*fb, &underline_rect, colorsheet.text, false);
+            }
+        }
+
+        if clicked.is_some() {
+            self.claim_click();
+        }
+
+        action
+    }
+}
+
+#[derive(Clone)]
+pub struct Menu {
+    pub title: String,
+    // Alt+letter opens the menu. Defaults to the first letter of the title.
+    pub mnemonic: Option<char>,
+    pub items: Vec<MenuItem>,
+}
+
+impl Menu {
+    pub fn new(title: &str, items: Vec<MenuItem>) -> Self {
+        Menu {
+            title: title.into(),
+            mnemonic: None,
+            items,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MenuBarConfig {
+    // Must be unique and stable across frames, the open state is tied to it
+    pub id: ContentId,
+    pub rect: Rect,
+    pub menus: Vec<Menu>,
+}
+
+impl Default for MenuBarConfig {
+    fn default() -> Self {
+        MenuBarConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 300,
+                h: 25,
+            },
+            menus: Vec::new(),
+        }
+    }
+}
+
+// Items are numbered as in context menus: in depth-first order, separators included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MenuAction {
+    pub menu: usize,
+    pub item: usize,
+}
+
+// An open menu of the bar. It reuses the context menu popup, and switches to
+// another menu of the bar on hover, Left/Right or Alt+letter.
+pub(crate) struct MenuBarPopup {
+    pub(crate) id: ContentId,
+    menus: Vec<Vec<MenuItem>>,
+    mnemonics: Vec<Option<Keycode>>,
+    title_rects: Vec<Rect>,
+    bounds: (u32, u32),
+    font: &'static Font,
+    margin: u32,
+    current: usize,
+    menu: MenuPopup,
+    choice: Option<MenuAction>,
+    closed: bool,
+    // Whether the widget that owns it was drawn this frame
+    pub(crate) seen: bool,
+}
+
+impl MenuBarPopup {
+    fn new(
+        config: &MenuBarConfig,
+        title_rects: &[Rect],
+        current: usize,
+        from_keyboard: bool,
+        bounds: (u32, u32),
+        font: &'static Font,
+        margin: u32,
+    ) -> Self {
+        let mut popup = MenuBarPopup {
+            id: config.id,
+            menus: Vec::new(),
+            mnemonics: Vec::new(),
+            title_rects: title_rects.to_vec(),
+            bounds,
+            font,
+            margin,
+            current,
+            menu: MenuPopup::new(config.id, Vec::new(), (0, 0), bounds, font, margin),
+            choice: None,
+            closed: false,
+            seen: true,
+        };
+        popup.update(config);
+        popup.open(current, from_keyboard);
+        popup
+    }
+
+    // The app may enable or disable items while the menu is open
+    fn update(&mut self, config: &MenuBarConfig) {
+        self.menus = config.menus.iter().map(|menu| menu.items.clone()).collect();
+        self.mnemonics = config
+            .menus
+            .iter()
+            .map(|menu| mnemonic_position(menu).and_then(|pos| mnemonic_keycode(menu, pos)))
+            .collect();
+        self.current = usize::min(self.current, self.menus.len().saturating_sub(1));
+        if let Some(items) = self.menus.get(self.current) {
+            self.menu.items = items.clone();
+        }
+    }
+
+    fn open(&mut self, i: usize, from_keyboard: bool) {
+        let title_rect = &self.title_rects[i];
+        let origin = (title_rect.x0, title_rect.y0 + title_rect.h as i64);
+        let items = self.menus.get(i).cloned().unwrap_or_default();
+        self.current = i;
+        self.menu = MenuPopup::new(self.id, items, origin, self.bounds, self.font, self.margin);
+        if from_keyboard {
+            self.menu.hover_first();
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub(crate) fn handle_input(&mut self, input_state: &InputState) {
+        let n = self.menus.len();
+        if n == 0 {
+            self.closed = true;
+            return;
+        }
+
+        for event in input_state.events.iter() {
+            let Some(InputEvent::KeyPress { keycode }) = event else {
+                continue;
+            };
+
+            let mnemonic = self.mnemonics.iter().position(|k| *k == Some(*keycode));
+
+            match mnemonic {
+                Some(i) if input_state.alt => self.open(i, true),
+                _ => {
+                    if !self.menu.handle_key(*keycode) {
+                        match keycode {
+                            Keycode::KEY_LEFT => self.open((self.current + n - 1) % n, true),
+                            Keycode::KEY_RIGHT => self.open((self.current + 1) % n, true),
+                            _ => (),
+                        }
+                    }
+                }
+            }
+        }
+
+        let ps = &input_state.pointer;
+        let moved = ps.delta_x != 0 || ps.delta_y != 0;
+        let hovered_title = self
+            .title_rects
+            .iter()
+            .position(|rect| rect.check_contains_point(ps.x, ps.y));
+
+        // Clicking the title of the open menu closes it, hovering another one opens it
+        match hovered_title {
+            Some(i) if ps.left_click_trigger && i == self.current => self.closed = true,
+            Some(i) if (moved || ps.left_click_trigger) && i != self.current => self.open(i, false),
+            Some(_) => (),
+            None => self.menu.handle_pointer(ps),
+        }
+
+        if let Some(item) = self.menu.choice {
+            self.choice = Some(MenuAction {
+                menu: self.current,
+                item,
+            });
+        }
+
+        self.closed |= self.menu.is_closed();
+    }
+
+    pub(crate) fn draw<F: FbViewMut>(&self, fb: &mut F, stylesheet: &StyleSheet) {
+        self.menu.draw(fb, stylesheet);
+    }
+}
+
+fn title_rects(config: &MenuBarConfig, font: &Font, m: u32) -> Vec<Rect> {
+    let mut x0 = config.rect.x0;
+    config
+        .menus
+        .iter()
+        .map(|menu| {
+            let (text_w, _) = compute_text_bbox(&menu.title, font);
+            let rect = Rect {
+                x0,
+                y0: config.rect.y0,
+                w: text_w + 4 * m,
+                h: config.rect.h,
+            };
+            x0 += rect.w as i64;
+            rect
+        })
+        .collect()
+}
+
+// Position of the mnemonic in the title, in chars
+fn mnemonic_position(menu: &Menu) -> Option<usize> {
+    let mut chars = menu.title.chars().map(|c| c.to_ascii_lowercase());
+    match menu.mnemonic {
+        Some(c) => chars.position(|other| other == c.to_ascii_lowercase()),
+        None => chars.position(|c| c.is_ascii_alphanumeric()),
+    }
+}
+
+fn mnemonic_keycode(menu: &Menu, pos: usize) -> Option<Keycode> {
+    let c = menu.title.chars().nth(pos)?.to_ascii_lowercase();
+    CHARMAP
+        .iter()
+        .find(|(_, (lower, _))| *lower == Some(c))
+        .map(|(keycode, _)| *keycode)
+}
+
+fn mnemonic_pressed(config: &MenuBarConfig, input_state: &InputState) -> Option<usize> {
+    if !input_state.alt {
+        return None;
+    }
+
+    config.menus.iter().position(|menu| {
+        mnemonic_position(menu)
+            .and_then(|pos| mnemonic_keycode(menu, pos))
+            .is_some_and(|keycode| input_state.check_key_pressed(keycode))
+    })
+}
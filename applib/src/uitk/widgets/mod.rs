@@ -1,9 +1,25 @@
 pub mod button;
+pub mod checkbox;
+pub mod color_picker;
+pub mod container;
+pub mod context_menu;
+pub mod dropdown;
 pub mod dynamic_canvas;
 pub mod graph;
 pub mod horiz_bar;
+pub mod menu_bar;
+pub mod modal;
+pub mod number_input;
 pub mod progress_bar;
+pub mod radio_group;
 pub mod section;
+pub mod slider;
+pub mod split_pane;
 pub mod static_canvas;
+pub mod tab_bar;
+pub mod table;
 pub mod text_box;
+pub mod text_input;
+pub mod toast;
 pub mod tooltip;
+pub mod tree_view;
@@ -0,0 +1,216 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_rect, draw_rect_outline};
+use crate::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use crate::input::{InputEvent, Keycode};
+use crate::uitk::frame::draw_chrome;
+use crate::uitk::layout::{make_horizontal_layout, make_vertical_layout, LayoutItem};
+use crate::uitk::{ButtonConfig, UiContext};
+use crate::{Color, FbViewMut, Rect};
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const DIM_COLOR: Color = Color::rgba(0, 0, 0, 150);
+const BUTTON_H: u32 = 30;
+const BUTTON_W: u32 = 100;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    // Must be called after all other widgets, everything drawn before is dimmed.
+    // The modal stays open for as long as it is called every frame.
+    pub fn modal<G>(&mut self, config: &ModalConfig, contents: G) -> ModalResult
+    where
+        G: FnOnce(&mut UiContext<'a, F>, &Rect),
+    {
+        match self.modal.active {
+            Some(id) if id != config.id && self.modal.seen => {
+                debug_assert!(false, "Only one modal can be open at a time");
+                return ModalResult::Open;
+            }
+            _ => (),
+        }
+
+        // Input only goes to the modal from the frame after it opened, so that
+        // the click that opened it does not land on its buttons
+        let capturing = self.modal.capturing && self.modal.active == Some(config.id);
+        self.modal.active = Some(config.id);
+        self.modal.seen = true;
+
+        let stylesheet = self.stylesheet.clone();
+        let colorsheet = &stylesheet.colors;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.medium);
+        let m = stylesheet.margin;
+
+        //
+        // Layout
+
+        let win_rect = self.fb.shape_as_rect();
+        let (xc, yc) = win_rect.center();
+        let dialog_rect = Rect::from_center(
+            xc,
+            yc,
+            u32::min(config.w, win_rect.w),
+            u32::min(config.h, win_rect.h),
+        );
+
+        let title_h = font.char_h as u32 + 2 * m;
+        let dialog_layout = make_vertical_layout(
+            &dialog_rect.offset(-(m as i64)),
+            m,
+            &[
+                LayoutItem::Fixed { size: title_h },
+                LayoutItem::Float,
+                LayoutItem::Fixed { size: BUTTON_H },
+            ],
+        );
+
+        let buttons: Vec<(&String, ModalResult)> = [
+            (&config.ok_text, ModalResult::Confirmed),
+            (&config.cancel_text, ModalResult::Cancelled),
+        ]
+        .into_iter()
+        .filter_map(|(text, result)| text.as_ref().map(|text| (text, result)))
+        .collect();
+
+        let buttons_layout = make_horizontal_layout(
+            &dialog_layout[2],
+            m,
+            &[
+                vec![LayoutItem::Float],
+                vec![LayoutItem::Fixed { size: BUTTON_W }; buttons.len()],
+            ]
+            .concat(),
+        );
+
+        //
+        // Drawing
+
+        draw_rect(self.fb, &win_rect, DIM_COLOR, true);
+        let panel = &stylesheet.chrome.panel;
+        if draw_chrome(self.fb, &dialog_rect, panel, colorsheet.background).is_none() {
+            draw_rect(self.fb, &dialog_rect, colorsheet.background, false);
+            draw_rect_outline(self.fb, &dialog_rect, colorsheet.outline, false, 1);
+        }
+
+        draw_rect(self.fb, &dialog_layout[0], colorsheet.frame, false);
+        draw_line_in_rect(
+            self.fb,
+            &config.title,
+            &dialog_layout[0].offset(-(m as i64)),
+            font,
+            colorsheet.text,
+            TextJustification::Left,
+        );
+
+        //
+        // Contents and buttons, with the input reserved for the modal
+
+        let prev_input_state = self.input_state;
+        self.input_state = match capturing {
+            true => self.modal_input_state,
+            false => self.blank_input_state,
+        };
+
+        contents(self, &dialog_layout[1]);
+
+        let mut result = ModalResult::Open;
+
+        for ((text, button_result), rect) in buttons.iter().zip(buttons_layout.iter().skip(1)) {
+            let clicked = self.button(&ButtonConfig {
+                rect: rect.clone(),
+                text: (*text).to_owned(),
+                ..Default::default()
+            });
+            if clicked {
+                result = *button_result;
+            }
+        }
+
+        // Enter on a focused button presses that button rather than OK
+        let button_pressed = result != ModalResult::Open;
+        for event in self.input_state.events.iter() {
+            match event {
+                Some(InputEvent::KeyPress {
+                    keycode: Keycode::KEY_ENTER,
+                }) if config.ok_text.is_some() && !button_pressed => {
+                    result = ModalResult::Confirmed
+                }
+                Some(InputEvent::KeyPress {
+                    keycode: Keycode::KEY_ESC,
+                }) => result = ModalResult::Cancelled,
+                _ => (),
+            }
+        }
+
+        self.input_state = prev_input_state;
+
+        // Closed right away, so that the next frame does not keep blocking the input
+        if result != ModalResult::Open {
+            self.modal.active = None;
+            self.modal.seen = false;
+        }
+
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModalResult {
+    Open,
+    Confirmed,
+    Cancelled,
+}
+
+#[derive(Clone)]
+pub struct ModalConfig {
+    // Must be unique and stable across frames
+    pub id: ContentId,
+    pub title: String,
+    pub w: u32,
+    pub h: u32,
+    // Buttons are not shown when None
+    pub ok_text: Option<String>,
+    pub cancel_text: Option<String>,
+}
+
+impl Default for ModalConfig {
+    fn default() -> Self {
+        ModalConfig {
+            id: ContentId(0),
+            title: String::new(),
+            w: 400,
+            h: 160,
+            ok_text: Some("OK".to_owned()),
+            cancel_text: Some("Cancel".to_owned()),
+        }
+    }
+}
+
+// Which modal is open, if any. There can only be one per UiStore.
+pub struct ModalState {
+    active: Option<ContentId>,
+    // Whether the modal was drawn during the current frame
+    seen: bool,
+    // Whether the input has been reserved for it this frame
+    capturing: bool,
+}
+
+impl ModalState {
+    pub(crate) fn new() -> Self {
+        ModalState {
+            active: None,
+            seen: false,
+            capturing: false,
+        }
+    }
+
+    // Called at the start of each frame, returns whether the input should go to the modal
+    pub(crate) fn new_frame(&mut self) -> bool {
+        if !self.seen {
+            self.active = None;
+        }
+        self.seen = false;
+        self.capturing = self.active.is_some();
+        self.capturing
+    }
+}
@@ -0,0 +1,291 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::draw_rect;
+use crate::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use crate::input::InputEvent;
+use crate::uitk::{
+    ButtonConfig, CursorHint, RepeatConfig, TextInputConfig, TextInputEvent, TextInputState,
+    UiContext,
+};
+use crate::{Color, FbViewMut, Rect};
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+use num::traits::float::FloatCore;
+
+const FLASH_TIME: f64 = 400.0; // in ms
+const FLASH_ALPHA: f64 = 150.0;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    pub fn number_input(&mut self, config: &NumberInputConfig, value: &mut i64) {
+        let config = NumberInputConfig {
+            step: f64::max(config.step, 1.0).round(),
+            decimals: 0,
+            ..config.clone()
+        };
+        let mut f_value = *value as f64;
+        self.clipped(&config.rect, |ui| {
+            ui.number_input_inner(&config, &mut f_value)
+        });
+        *value = f_value.round() as i64;
+    }
+
+    pub fn number_input_f64(&mut self, config: &NumberInputConfig, value: &mut f64) {
+        self.clipped(&config.rect, |ui| ui.number_input_inner(config, value));
+    }
+
+    fn number_input_inner(&mut self, config: &NumberInputConfig, value: &mut f64) {
+        // Taken out while drawing, the text field and buttons need the whole context
+        let mut state = self
+            .interaction
+            .number_inputs
+            .remove(&config.id)
+            .unwrap_or_else(|| NumberInputState::new(config.format(*value)));
+
+        let font = get_font(
+            self.stylesheet.text.font_family(),
+            self.stylesheet.text.sizes.small,
+        );
+        let m = self.stylesheet.margin;
+
+        //
+        // Layout: label, text field, then the - and + buttons
+
+        let Rect { x0, y0, w, h } = config.rect;
+        let label_w = match config.label.is_empty() {
+            true => 0,
+            false => font.text_width(&config.label) + 2 * m,
+        };
+        let buttons_w = 2 * h;
+
+        let label_rect = Rect {
+            x0,
+            y0,
+            w: label_w,
+            h,
+        };
+        let field_rect = Rect {
+            x0: x0 + label_w as i64,
+            y0,
+            w: w.saturating_sub(label_w + buttons_w),
+            h,
+        };
+        let minus_rect = Rect {
+            x0: x0 + w.saturating_sub(buttons_w) as i64,
+            y0,
+            w: h,
+            h,
+        };
+        let plus_rect = Rect {
+            x0: minus_rect.x0 + h as i64,
+            ..minus_rect.clone()
+        };
+
+        //
+        // Text field, only applied when committed
+
+        let event = self.text_input(
+            &TextInputConfig {
+                rect: field_rect.clone(),
+                filter: Some(match config.decimals {
+                    0 => is_int_char,
+                    _ => is_float_char,
+                }),
+                ..Default::default()
+            },
+            &mut state.text,
+            &mut state.text_state,
+        );
+
+        let focused = state.text_state.is_focused();
+        let committed = event == TextInputEvent::Submitted || state.was_focused && !focused;
+        state.was_focused = focused;
+
+        if committed && state.text != config.format(*value) {
+            match state.text.trim().parse::<f64>() {
+                Ok(parsed) if parsed >= config.min && parsed <= config.max => {
+                    *value = config.snap(parsed);
+                }
+                _ => state.flash_start = Some(self.time),
+            }
+        }
+
+        //
+        // Buttons, scroll wheel and scrubbing, clamped to the range
+
+        let mut adjusted = *value;
+
+        let button_config = |rect: &Rect, text: &str| ButtonConfig {
+            rect: rect.clone(),
+            text: text.to_owned(),
+            repeat: Some(RepeatConfig::default()),
+            ..Default::default()
+        };
+        if self.button(&button_config(&minus_rect, "-")) {
+            adjusted -= config.step;
+        }
+        if self.button(&button_config(&plus_rect, "+")) {
+            adjusted += config.step;
+        }
+
+        let ps = &self.input_state.pointer;
+        if config.rect.check_contains_point(ps.x, ps.y) {
+            for event in self.input_state.events.iter() {
+                if let Some(InputEvent::Scroll { delta_y, .. }) = event {
+                    adjusted += *delta_y as f64 * config.step;
+                }
+            }
+        }
+
+        let scrub_id = ContentId::from_hash(&(config.id, "scrub"));
+        let label_hovered = label_rect.check_contains_point(ps.x, ps.y);
+        if label_hovered && ps.left_click_trigger {
+            self.interaction.dragged = Some(scrub_id);
+            state.scrub_origin = Some((ps.x, adjusted));
+        }
+
+        let scrubbing = self.interaction.dragged == Some(scrub_id);
+        if scrubbing {
+            match (ps.left_clicked, state.scrub_origin) {
+                (true, Some((origin_x, origin_value))) => {
+                    let px_per_step = i64::max(1, config.drag_px_per_step as i64);
+                    let nb_steps = (ps.x - origin_x) / px_per_step;
+                    adjusted = origin_value + nb_steps as f64 * config.step;
+                }
+                _ => {
+                    self.interaction.dragged = None;
+                    state.scrub_origin = None;
+                }
+            }
+        }
+
+        if adjusted != *value {
+            *value = config.snap(adjusted);
+        }
+
+        // Shows the value, unless it is being typed
+        if !focused || committed {
+            let text = config.format(*value);
+            if state.text != text {
+                state.text_state.move_to_end(&text);
+                state.text = text;
+            }
+        }
+
+        //
+        // Drawing
+
+        let colorsheet = &self.stylesheet.colors;
+
+        if label_w > 0 {
+            draw_rect(self.fb, &label_rect, colorsheet.element, false);
+            if label_hovered || scrubbing {
+                draw_rect(self.fb, &label_rect, colorsheet.hover_overlay, true);
+            }
+            draw_line_in_rect(
+                self.fb,
+                &config.label,
+                &label_rect,
+                font,
+                colorsheet.text,
+                TextJustification::Center,
+            );
+        }
+
+        // Rejected entry
+        if let Some(flash_start) = state.flash_start {
+            let progress = (self.time - flash_start) / FLASH_TIME;
+            match progress < 1.0 {
+                true => {
+                    let (r, g, b, _) = colorsheet.red.as_rgba();
+                    let alpha = ((1.0 - progress) * FLASH_ALPHA) as u8;
+                    draw_rect(self.fb, &field_rect, Color::rgba(r, g, b, alpha), true);
+                }
+                false => state.flash_start = None,
+            }
+        }
+
+        if label_hovered || scrubbing {
+            self.set_cursor_hint(CursorHint::ResizeHorizontal);
+        }
+
+        self.interaction.number_inputs.insert(config.id, state);
+    }
+}
+
+#[derive(Clone)]
+pub struct NumberInputConfig {
+    // Must be unique and stable across frames, the typed text is tied to it
+    pub id: ContentId,
+    pub rect: Rect,
+    // Dragging it sideways changes the value
+    pub label: String,
+    pub min: f64,
+    pub max: f64,
+    // For the buttons, the scroll wheel and scrubbing
+    pub step: f64,
+    // How far the pointer must be dragged for each step
+    pub drag_px_per_step: u32,
+    // Only used by number_input_f64()
+    pub decimals: u32,
+}
+
+impl NumberInputConfig {
+    fn snap(&self, value: f64) -> f64 {
+        let factor = 10.0_f64.powi(self.decimals as i32);
+        let value = (value * factor).round() / factor;
+        f64::min(f64::max(value, self.min), self.max)
+    }
+
+    fn format(&self, value: f64) -> String {
+        format!("{:.*}", self.decimals as usize, value)
+    }
+}
+
+impl Default for NumberInputConfig {
+    fn default() -> Self {
+        NumberInputConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 120,
+                h: 25,
+            },
+            label: String::new(),
+            min: 0.0,
+            max: 100.0,
+            step: 1.0,
+            drag_px_per_step: 4,
+            decimals: 2,
+        }
+    }
+}
+
+pub(crate) struct NumberInputState {
+    text: String,
+    text_state: TextInputState,
+    was_focused: bool,
+    // Pointer position and value when scrubbing started
+    scrub_origin: Option<(i64, f64)>,
+    flash_start: Option<f64>,
+}
+
+impl NumberInputState {
+    fn new(text: String) -> Self {
+        NumberInputState {
+            text,
+            text_state: TextInputState::new(),
+            was_focused: false,
+            scrub_origin: None,
+            flash_start: None,
+        }
+    }
+}
+
+fn is_int_char(c: char) -> bool {
+    c == '-' || c.is_ascii_digit()
+}
+
+fn is_float_char(c: char) -> bool {
+    c == '.' || is_int_char(c)
+}
@@ -1,64 +1,178 @@
-use crate::drawing::primitives::draw_rect;
-use crate::drawing::text::{draw_str, get_font};
+use crate::drawing::primitives::{draw_arc, draw_rect, ArcMode};
+use crate::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use crate::geometry::Point2D;
 use crate::uitk::UiContext;
 use crate::{FbViewMut, Rect};
+use alloc::format;
+use alloc::string::String;
+use core::f32::consts::PI;
+use num::traits::float::FloatCore;
+
+// In milliseconds, like the UI time
+const SWEEP_PERIOD: f64 = 1500.0;
+const SPINNER_PERIOD: f64 = 1000.0;
+
+// Fraction of the bar covered by the sweeping segment in indeterminate mode
+const SWEEP_W_FRAC: f32 = 0.25;
 
 impl<'a, F: FbViewMut> UiContext<'a, F> {
-    pub fn progress_bar(&mut self, config: &ProgressBarConfig, progress: u64, text: &str) {
-        let UiContext { fb, stylesheet, .. } = self;
+    pub fn progress_bar(&mut self, config: &ProgressBarConfig, fraction: f32) {
+        self.clipped(&config.rect, |ui| ui.progress_bar_inner(config, fraction))
+    }
+
+    fn progress_bar_inner(&mut self, config: &ProgressBarConfig, fraction: f32) {
+        let UiContext {
+            fb,
+            stylesheet,
+            time,
+            ..
+        } = self;
 
         let colorsheet = &stylesheet.colors;
+        let Rect { x0, y0, w, h } = config.rect;
 
-        let container_rect = {
-            let m = stylesheet.margin as i64;
-            let [x0, y0, x1, y1] = config.rect.as_xyxy();
-            Rect::from_xyxy([x0 + m, y0 + m, x1 - m, y1 - m])
-        };
+        if w == 0 || h == 0 {
+            return;
+        }
 
-        let bar_w = ((config.rect.w as u64) * progress / config.max_val) as u32;
+        draw_rect(*fb, &config.rect, colorsheet.background, false);
 
-        let bar_rect = {
-            let Rect { x0, y0, h, .. } = config.rect;
-            Rect {
-                x0,
-                y0,
-                h,
-                w: bar_w,
+        let bar_rect = match config.mode {
+            ProgressBarMode::Determinate => {
+                let fraction = match fraction.is_nan() {
+                    true => 0.0,
+                    false => fraction.clamp(0.0, 1.0),
+                };
+                Rect {
+                    x0,
+                    y0,
+                    w: (w as f32 * fraction).round() as u32,
+                    h,
+                }
+            }
+            // The segment enters on the left and leaves on the right, clipped to the bar
+            ProgressBarMode::Indeterminate => {
+                let sweep_w = u32::max(1, (w as f32 * SWEEP_W_FRAC) as u32);
+                let t = (*time % SWEEP_PERIOD) / SWEEP_PERIOD;
+                let offset = (t * (w + sweep_w) as f64) as i64 - sweep_w as i64;
+                let segment_rect = Rect {
+                    x0: x0 + offset,
+                    y0,
+                    w: sweep_w,
+                    h,
+                };
+                segment_rect.intersection(&config.rect).unwrap_or(Rect {
+                    w: 0,
+                    ..segment_rect
+                })
             }
         };
 
-        draw_rect(*fb, &config.rect, colorsheet.background, false);
         draw_rect(*fb, &bar_rect, colorsheet.accent, false);
 
-        let font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.medium);
-
-        let text_w = (text.len() * font.char_w) as u32;
-        let text_h = font.char_h as u32;
+        // No text on bars too thin to hold it
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.medium);
+        if h < font.char_h as u32 {
+            return;
+        }
 
+        let m = stylesheet.margin;
         let text_rect = Rect {
-            x0: config.rect.x0 + stylesheet.margin as i64,
-            y0: 0,
-            w: text_w,
-            h: text_h,
+            x0: x0 + m as i64,
+            y0,
+            w: w.saturating_sub(2 * m),
+            h,
+        };
+
+        if !config.text.is_empty() {
+            draw_line_in_rect(
+                *fb,
+                &config.text,
+                &text_rect,
+                font,
+                colorsheet.text,
+                TextJustification::Left,
+            );
         }
-        .align_to_rect_vert(&config.rect);
 
-        draw_str(
+        if config.show_percentage && config.mode == ProgressBarMode::Determinate {
+            let percentage = match fraction.is_nan() {
+                true => 0.0,
+                false => (100.0 * fraction).clamp(0.0, 100.0),
+            };
+            draw_line_in_rect(
+                *fb,
+                &format!("{:.0}%", percentage),
+                &text_rect,
+                font,
+                colorsheet.text,
+                TextJustification::Center,
+            );
+        }
+    }
+
+    pub fn spinner(&mut self, rect: &Rect) {
+        self.clipped(rect, |ui| ui.spinner_inner(rect))
+    }
+
+    fn spinner_inner(&mut self, rect: &Rect) {
+        let UiContext {
+            fb,
+            stylesheet,
+            time,
+            ..
+        } = self;
+
+        let colorsheet = &stylesheet.colors;
+
+        let r_outer = (u32::min(rect.w, rect.h) / 2) as f32;
+        if r_outer < 2.0 {
+            return;
+        }
+        let r_inner = 0.6 * r_outer;
+
+        let (xc, yc) = rect.center();
+        let center = Point2D { x: xc, y: yc };
+        let px_per_pt = f32::max(1.0, r_outer / 4.0);
+
+        draw_arc(
             *fb,
-            &text,
-            text_rect.x0,
-            text_rect.y0,
-            font,
-            colorsheet.text,
-            None,
+            center,
+            r_inner,
+            r_outer,
+            ArcMode::Full,
+            px_per_pt,
+            colorsheet.element,
+            false,
+        );
+
+        let a0 = 2.0 * PI * ((*time % SPINNER_PERIOD) / SPINNER_PERIOD) as f32;
+        draw_arc(
+            *fb,
+            center,
+            r_inner,
+            r_outer,
+            ArcMode::AngleRange(a0, a0 + PI / 2.0),
+            px_per_pt,
+            colorsheet.accent,
+            false,
         );
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressBarMode {
+    Determinate,
+    // For operations of unknown length, the fraction is ignored
+    Indeterminate,
+}
+
 #[derive(Clone)]
 pub struct ProgressBarConfig {
     pub rect: Rect,
-    pub max_val: u64,
+    pub mode: ProgressBarMode,
+    pub text: String,
+    pub show_percentage: bool,
 }
 
 impl Default for ProgressBarConfig {
@@ -70,7 +184,9 @@ impl Default for ProgressBarConfig {
                 w: 100,
                 h: 25,
             },
-            max_val: 100,
+            mode: ProgressBarMode::Determinate,
+            text: String::new(),
+            show_percentage: false,
         }
     }
 }
@@ -0,0 +1,205 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_arc, ArcMode};
+use crate::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use crate::geometry::Point2D;
+use crate::input::{InputEvent, Keycode};
+use crate::uitk::layout::{make_horizontal_layout, make_vertical_layout, LayoutItem};
+use crate::uitk::UiContext;
+use crate::{FbViewMut, Rect};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const RADIO_R: f32 = 7.0;
+const DOT_R: f32 = 3.0;
+const PX_PER_PT: f32 = 2.0;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    pub fn radio_group(&mut self, config: &RadioGroupConfig, selected: &mut usize) {
+        self.clipped(&config.rect, |ui| ui.radio_group_inner(config, selected))
+    }
+
+    fn radio_group_inner(&mut self, config: &RadioGroupConfig, selected: &mut usize) {
+        let focused = match config.disabled {
+            true => {
+                self.unfocus(config.id);
+                false
+            }
+            false => self.focusable(config.id, &config.rect, true),
+        };
+
+        let UiContext {
+            fb,
+            stylesheet,
+            input_state,
+            ..
+        } = self;
+
+        let colorsheet = &stylesheet.colors;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+        let m = stylesheet.margin;
+
+        let n = config.options.len();
+        if n == 0 {
+            return;
+        }
+
+        let items = vec![LayoutItem::Float; n];
+        let layout = match config.orientation {
+            RadioOrientation::Vertical => make_vertical_layout(&config.rect, m, &items),
+            RadioOrientation::Horizontal => make_horizontal_layout(&config.rect, m, &items),
+        };
+
+        //
+        // Interaction
+
+        // Each option is clickable on its whole rect, label included
+        let ps = &input_state.pointer;
+        let hovered = match config.disabled {
+            true => None,
+            false => layout
+                .iter()
+                .position(|rect| rect.check_contains_point(ps.x, ps.y)),
+        };
+
+        if let (true, Some(i)) = (ps.left_click_trigger, hovered) {
+            *selected = i;
+        }
+
+        if focused {
+            for event in input_state.events.iter() {
+                match event {
+                    Some(InputEvent::KeyPress {
+                        keycode: Keycode::KEY_UP | Keycode::KEY_LEFT,
+                    }) => *selected = selected.saturating_sub(1),
+                    Some(InputEvent::KeyPress {
+                        keycode: Keycode::KEY_DOWN | Keycode::KEY_RIGHT,
+                    }) => *selected += 1,
+                    _ => (),
+                }
+            }
+        }
+
+        *selected = usize::min(*selected, n - 1);
+
+        //
+        // Drawing
+
+        let (circle_color, dot_color, text_color) = match config.disabled {
+            true => (
+                colorsheet.disabled_element,
+                colorsheet.disabled,
+                colorsheet.disabled,
+            ),
+            false => (colorsheet.editable, colorsheet.accent, colorsheet.text),
+        };
+
+        let ring_color = match focused {
+            true => colorsheet.focus_ring,
+            false => colorsheet.outline,
+        };
+
+        for (i, (option, rect)) in config.options.iter().zip(layout.iter()).enumerate() {
+            let Rect { x0, y0, w, h } = rect.clone();
+
+            let center = Point2D {
+                x: x0 + RADIO_R as i64,
+                y: y0 + (h / 2) as i64,
+            };
+
+            draw_arc(
+                *fb,
+                center,
+                0.0,
+                RADIO_R,
+                ArcMode::Full,
+                PX_PER_PT,
+                circle_color,
+                false,
+            );
+            if hovered == Some(i) {
+                draw_arc(
+                    *fb,
+                    center,
+                    0.0,
+                    RADIO_R,
+                    ArcMode::Full,
+                    PX_PER_PT,
+                    colorsheet.hover_overlay,
+                    true,
+                );
+            }
+            draw_arc(
+                *fb,
+                center,
+                RADIO_R - 1.0,
+                RADIO_R,
+                ArcMode::Full,
+                PX_PER_PT,
+                ring_color,
+                false,
+            );
+            if i == *selected {
+                draw_arc(
+                    *fb,
+                    center,
+                    0.0,
+                    DOT_R,
+                    ArcMode::Full,
+                    PX_PER_PT,
+                    dot_color,
+                    false,
+                );
+            }
+
+            let label_x0 = x0 + 2 * RADIO_R as i64 + 2 * m as i64;
+            let label_rect = Rect {
+                x0: label_x0,
+                y0,
+                w: (x0 + w as i64 - label_x0).max(0) as u32,
+                h,
+            };
+            draw_line_in_rect(
+                *fb,
+                option,
+                &label_rect,
+                font,
+                text_color,
+                TextJustification::Left,
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RadioOrientation {
+    Vertical,
+    Horizontal,
+}
+
+#[derive(Clone)]
+pub struct RadioGroupConfig {
+    // Must be unique and stable across frames, focus is tied to it
+    pub id: ContentId,
+    pub rect: Rect,
+    pub options: Vec<String>,
+    pub orientation: RadioOrientation,
+    pub disabled: bool,
+}
+
+impl Default for RadioGroupConfig {
+    fn default() -> Self {
+        RadioGroupConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 100,
+                h: 25,
+            },
+            options: Vec::new(),
+            orientation: RadioOrientation::Vertical,
+            disabled: false,
+        }
+    }
+}
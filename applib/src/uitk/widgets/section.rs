@@ -1,7 +1,7 @@
 use crate::drawing::primitives::draw_rect;
 use crate::drawing::text::{draw_line_in_rect, get_font, TextJustification};
 use crate::uitk::UiContext;
-use crate::{Color, FbViewMut, Rect};
+use crate::{FbViewMut, Rect};
 use num::traits::real::Real;
 
 impl<'a, F: FbViewMut> UiContext<'a, F> {
@@ -18,7 +18,8 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
             self.stylesheet.text.sizes.medium,
         );
 
-        let color = Color::WHITE;
+        let color = self.stylesheet.widgets.normal.text;
+        let line_color = self.stylesheet.widgets.normal.border;
 
         let text_rect = Rect {
             x0: x0 + MARGIN as i64,
@@ -42,7 +43,7 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
             x0 + (w - MARGIN) as i64 - 1,
             yc,
         ]);
-        draw_rect(self.fb, &line_rect, color, false);
+        draw_rect(self.fb, &line_rect, line_color, false);
 
         let inner_rect = Rect {
             x0: x0 + MARGIN as i64,
@@ -51,7 +52,7 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
             h: h - (2 * MARGIN + text_rect.h),
         };
 
-        func(self, &inner_rect);
+        self.clipped(rect, |ui| func(ui, &inner_rect));
     }
 
     pub fn layout_box<S>(
@@ -75,6 +76,6 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
 
         let box_rect = Rect::from_xyxy([x0 + dx0, y0 + dy0, x1 - dx1, y1 - dy1]);
 
-        func(self, &box_rect)
+        self.clipped(&box_rect, |ui| func(ui, &box_rect))
     }
 }
@@ -0,0 +1,251 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_rect, draw_rect_outline};
+use crate::drawing::text::{draw_str, get_font};
+use crate::input::{InputEvent, Keycode};
+use crate::uitk::UiContext;
+use crate::{FbViewMut, Rect};
+use alloc::format;
+use alloc::string::String;
+use num::traits::float::FloatCore;
+
+const TRACK_H: u32 = 4;
+const THUMB_W: u32 = 10;
+const TICK_H: u32 = 4;
+
+// Number of keyboard steps to go from min to max, for continuous sliders
+const KEY_STEPS: f32 = 20.0;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    pub fn slider(&mut self, config: &SliderConfig, value: &mut f32) {
+        self.clipped(&config.rect, |ui| ui.slider_inner(config, value));
+    }
+
+    pub fn slider_int(&mut self, config: &SliderConfig, value: &mut i64) {
+        let config = SliderConfig {
+            step: f32::max(config.step, 1.0).round(),
+            ..config.clone()
+        };
+        let mut f_value = *value as f32;
+        self.clipped(&config.rect, |ui| ui.slider_inner(&config, &mut f_value));
+        *value = f_value.round() as i64;
+    }
+
+    fn slider_inner(&mut self, config: &SliderConfig, value: &mut f32) {
+        let focused = match config.disabled {
+            true => {
+                self.unfocus(config.id);
+                false
+            }
+            false => self.focusable(config.id, &config.rect, true),
+        };
+
+        let UiContext {
+            fb,
+            stylesheet,
+            input_state,
+            interaction,
+            ..
+        } = self;
+
+        let colorsheet = &stylesheet.colors;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+        let m = stylesheet.margin as i64;
+
+        let Rect { x0, y0, w, h } = config.rect;
+
+        //
+        // Layout
+
+        let (min_label, max_label) = (format_label(config.min), format_label(config.max));
+        let label_w = |s: &str| match config.show_labels {
+            true => font.text_width(s) as i64 + m,
+            false => 0,
+        };
+        let (min_label_w, max_label_w) = (label_w(&min_label), label_w(&max_label));
+
+        // The thumb center moves between the ends of the track
+        let half_thumb = (THUMB_W / 2) as i64;
+        let track_x0 = x0 + min_label_w + half_thumb;
+        let track_x1 = x0 + w as i64 - max_label_w - half_thumb;
+        let track_w = i64::max(1, track_x1 - track_x0);
+
+        let y_center = y0 + h as i64 / 2;
+
+        //
+        // Interaction
+
+        let ps = &input_state.pointer;
+        let hovered = !config.disabled && config.rect.check_contains_point(ps.x, ps.y);
+
+        if hovered && ps.left_click_trigger {
+            interaction.dragged = Some(config.id);
+        }
+
+        // Disabled while being dragged
+        if config.disabled && interaction.dragged == Some(config.id) {
+            interaction.dragged = None;
+        }
+
+        let dragged = interaction.dragged == Some(config.id);
+
+        // Keeps following the pointer after it leaves the rect, until released.
+        // Clicking the track also lands there, since dragging starts right away.
+        if dragged {
+            if ps.left_clicked {
+                let t = (ps.x - track_x0) as f32 / track_w as f32;
+                *value = config.min + t * (config.max - config.min);
+            } else {
+                interaction.dragged = None;
+            }
+        }
+
+        if focused {
+            let key_step = match config.step > 0.0 {
+                true => config.step,
+                false => (config.max - config.min) / KEY_STEPS,
+            };
+            for event in input_state.events.iter() {
+                match event {
+                    Some(InputEvent::KeyPress {
+                        keycode: Keycode::KEY_LEFT,
+                    }) => *value -= key_step,
+                    Some(InputEvent::KeyPress {
+                        keycode: Keycode::KEY_RIGHT,
+                    }) => *value += key_step,
+                    _ => (),
+                }
+            }
+        }
+
+        *value = config.snap(*value);
+
+        //
+        // Drawing
+
+        let t = match config.max > config.min {
+            true => (*value - config.min) / (config.max - config.min),
+            false => 0.0,
+        };
+        let thumb_x = track_x0 + (t * track_w as f32).round() as i64;
+
+        let track_rect = Rect {
+            x0: track_x0,
+            y0: y_center - (TRACK_H / 2) as i64,
+            w: track_w as u32,
+            h: TRACK_H,
+        };
+        let filled_rect = Rect {
+            w: (thumb_x - track_x0) as u32,
+            ..track_rect.clone()
+        };
+        let (track_color, fill_color, thumb_color, text_color) = match config.disabled {
+            true => (
+                colorsheet.disabled_element,
+                colorsheet.disabled,
+                colorsheet.disabled_element,
+                colorsheet.disabled,
+            ),
+            false => (
+                colorsheet.element,
+                colorsheet.accent,
+                colorsheet.element,
+                colorsheet.text,
+            ),
+        };
+
+        draw_rect(*fb, &track_rect, track_color, false);
+        draw_rect(*fb, &filled_rect, fill_color, false);
+
+        if config.nb_ticks >= 2 {
+            let tick_y0 = track_rect.y0 + (TRACK_H + TICK_H) as i64;
+            for i in 0..config.nb_ticks {
+                let x = track_x0 + i as i64 * track_w / (config.nb_ticks - 1) as i64;
+                let tick_rect = Rect {
+                    x0: x,
+                    y0: tick_y0,
+                    w: 1,
+                    h: TICK_H,
+                };
+                draw_rect(*fb, &tick_rect, colorsheet.outline, false);
+            }
+        }
+
+        let thumb_h = u32::min(h, 2 * TRACK_H + 2 * TICK_H + 4);
+        let thumb_rect = Rect {
+            x0: thumb_x - half_thumb,
+            y0: y_center - (thumb_h / 2) as i64,
+            w: THUMB_W,
+            h: thumb_h,
+        };
+        draw_rect(*fb, &thumb_rect, thumb_color, false);
+        if hovered || dragged {
+            draw_rect(*fb, &thumb_rect, colorsheet.hover_overlay, true);
+        }
+        if focused {
+            draw_rect_outline(
+                *fb,
+                &thumb_rect,
+                colorsheet.focus_ring,
+                false,
+                stylesheet.focus_ring_width,
+            );
+        }
+
+        if config.show_labels {
+            let text_y = y_center - (font.char_h / 2) as i64;
+            draw_str(*fb, &min_label, x0, text_y, font, text_color, None);
+            let max_x = x0 + w as i64 - max_label_w + m;
+            draw_str(*fb, &max_label, max_x, text_y, font, text_color, None);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SliderConfig {
+    // Must be unique and stable across frames, drag and focus state are tied to it
+    pub id: ContentId,
+    pub rect: Rect,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32, // 0 for a continuous slider
+    pub nb_ticks: u32,
+    pub show_labels: bool,
+    pub disabled: bool,
+}
+
+impl SliderConfig {
+    fn snap(&self, value: f32) -> f32 {
+        let value = match self.step > 0.0 {
+            true => self.min + ((value - self.min) / self.step).round() * self.step,
+            false => value,
+        };
+        f32::min(f32::max(value, self.min), self.max)
+    }
+}
+
+impl Default for SliderConfig {
+    fn default() -> Self {
+        SliderConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 100,
+                h: 25,
+            },
+            min: 0.0,
+            max: 1.0,
+            step: 0.0,
+            nb_ticks: 0,
+            show_labels: false,
+            disabled: false,
+        }
+    }
+}
+
+fn format_label(value: f32) -> String {
+    match value.fract() == 0.0 {
+        true => format!("{}", value as i64),
+        false => format!("{:.2}", value),
+    }
+}
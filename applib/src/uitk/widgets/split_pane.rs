@@ -0,0 +1,198 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::draw_rect;
+use crate::input::MouseButton;
+use crate::uitk::{CursorHint, UiContext};
+use crate::{FbViewMut, Rect};
+use num::traits::float::FloatCore;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    pub fn split_pane(
+        &mut self,
+        config: &SplitConfig,
+        ratio: &mut f32,
+        left: impl FnOnce(&mut UiContext<'a, F>, &Rect),
+        right: impl FnOnce(&mut UiContext<'a, F>, &Rect),
+    ) {
+        let UiContext {
+            fb,
+            stylesheet,
+            input_state,
+            interaction,
+            ..
+        } = self;
+
+        let Rect { x0, y0, w, h } = config.rect;
+        let divider_w = config.divider_w;
+
+        // Everything is computed along the split axis
+        let (origin, length) = match config.orientation {
+            SplitOrientation::Horizontal => (x0, w),
+            SplitOrientation::Vertical => (y0, h),
+        };
+        let avail = length.saturating_sub(divider_w);
+
+        //
+        // Interaction
+
+        let ps = &input_state.pointer;
+        let pointer_pos = match config.orientation {
+            SplitOrientation::Horizontal => ps.x,
+            SplitOrientation::Vertical => ps.y,
+        };
+
+        *ratio = config.clamp_ratio(*ratio, avail);
+        let divider_rect = config.divider_rect(first_size(*ratio, avail));
+        let hovered = divider_rect.check_contains_point(ps.x, ps.y);
+
+        if hovered && ps.left_click_trigger {
+            let is_double_click = input_state
+                .click_count(MouseButton::Left)
+                .is_some_and(|clicks| clicks >= 2)
+                && interaction.last_clicks.contains(&config.id);
+
+            match is_double_click {
+                true => {
+                    interaction.last_clicks.remove(&config.id);
+                    *ratio = config.default_ratio;
+                }
+                false => {
+                    interaction.last_clicks.insert(config.id);
+                    interaction.dragged = Some(config.id);
+                }
+            }
+        }
+
+        // Keyed by id, so that an inner split does not move along with the outer one
+        let dragged = interaction.dragged == Some(config.id);
+
+        if dragged {
+            if ps.left_clicked {
+                let size = pointer_pos - origin - (divider_w / 2) as i64;
+                *ratio = match avail {
+                    0 => config.default_ratio,
+                    _ => size as f32 / avail as f32,
+                };
+            } else {
+                interaction.dragged = None;
+            }
+        }
+
+        *ratio = config.clamp_ratio(*ratio, avail);
+        let size = first_size(*ratio, avail);
+        let divider_rect = config.divider_rect(size);
+
+        //
+        // Drawing
+
+        draw_rect(*fb, &divider_rect, stylesheet.colors.frame, false);
+        if hovered || dragged {
+            draw_rect(*fb, &divider_rect, stylesheet.colors.hover_overlay, true);
+            self.set_cursor_hint(match config.orientation {
+                SplitOrientation::Horizontal => CursorHint::ResizeHorizontal,
+                SplitOrientation::Vertical => CursorHint::ResizeVertical,
+            });
+        }
+
+        let second_size = avail - size;
+        let (first_rect, second_rect) = match config.orientation {
+            SplitOrientation::Horizontal => (
+                Rect { x0, y0, w: size, h },
+                Rect {
+                    x0: x0 + (size + divider_w) as i64,
+                    y0,
+                    w: second_size,
+                    h,
+                },
+            ),
+            SplitOrientation::Vertical => (
+                Rect { x0, y0, w, h: size },
+                Rect {
+                    x0,
+                    y0: y0 + (size + divider_w) as i64,
+                    w,
+                    h: second_size,
+                },
+            ),
+        };
+
+        self.clipped(&first_rect, |ui| left(ui, &first_rect));
+        self.clipped(&second_rect, |ui| right(ui, &second_rect));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitOrientation {
+    // Panes side by side, with a vertical divider
+    Horizontal,
+    // Panes on top of each other, with a horizontal divider
+    Vertical,
+}
+
+#[derive(Clone)]
+pub struct SplitConfig {
+    // Must be unique and stable across frames, the drag state is tied to it
+    pub id: ContentId,
+    pub rect: Rect,
+    pub orientation: SplitOrientation,
+    pub divider_w: u32,
+    // Of the first and second pane, along the split axis
+    pub min_sizes: (u32, u32),
+    // Restored by double-clicking the divider
+    pub default_ratio: f32,
+}
+
+impl SplitConfig {
+    // The ratio is the share of the first pane in the space left by the divider
+    fn clamp_ratio(&self, ratio: f32, avail: u32) -> f32 {
+        if avail == 0 {
+            return self.default_ratio;
+        }
+
+        let (min_first, min_second) = self.min_sizes;
+        let min = min_first as f32 / avail as f32;
+        let max = 1.0 - min_second as f32 / avail as f32;
+
+        // The first pane wins if both minimums cannot be met
+        f32::max(f32::min(ratio, max), min).clamp(0.0, 1.0)
+    }
+
+    fn divider_rect(&self, first_size: u32) -> Rect {
+        let Rect { x0, y0, w, h } = self.rect;
+        match self.orientation {
+            SplitOrientation::Horizontal => Rect {
+                x0: x0 + first_size as i64,
+                y0,
+                w: self.divider_w,
+                h,
+            },
+            SplitOrientation::Vertical => Rect {
+                x0,
+                y0: y0 + first_size as i64,
+                w,
+                h: self.divider_w,
+            },
+        }
+    }
+}
+
+impl Default for SplitConfig {
+    fn default() -> Self {
+        SplitConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 400,
+                h: 300,
+            },
+            orientation: SplitOrientation::Horizontal,
+            divider_w: 6,
+            min_sizes: (50, 50),
+            default_ratio: 0.5,
+        }
+    }
+}
+
+fn first_size(ratio: f32, avail: u32) -> u32 {
+    u32::min((ratio * avail as f32).round() as u32, avail)
+}
@@ -0,0 +1,360 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_arc, draw_rect, ArcMode};
+use crate::drawing::text::{draw_line_in_rect, ellipsize_text, get_font, Font, TextJustification};
+use crate::geometry::Point2D;
+use crate::uitk::UiContext;
+use crate::{FbViewMut, Rect, StyleSheetColors};
+use alloc::string::String;
+
+const CLOSE_SIZE: u32 = 12;
+// The close button is tiny, so a few pixels around it still count as hitting it
+const CLOSE_HIT_MARGIN: i64 = 2;
+const DOT_R: f32 = 3.0;
+const ACTIVE_BAR_H: u32 = 2;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    pub fn tab_bar(
+        &mut self,
+        config: &TabBarConfig,
+        tabs: &[TabItem],
+        active: &mut usize,
+    ) -> TabBarResponse {
+        self.clipped(&config.rect, |ui| ui.tab_bar_inner(config, tabs, active))
+    }
+
+    fn tab_bar_inner(
+        &mut self,
+        config: &TabBarConfig,
+        tabs: &[TabItem],
+        active: &mut usize,
+    ) -> TabBarResponse {
+        let UiContext {
+            fb,
+            stylesheet,
+            input_state,
+            interaction,
+            ..
+        } = self;
+
+        let colorsheet = &stylesheet.colors;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+        let m = stylesheet.margin;
+
+        let mut response = TabBarResponse::default();
+
+        let n = tabs.len();
+        let Rect { x0, y0, w, h } = config.rect;
+
+        // The "+" and arrow buttons are square
+        let button_w = h;
+
+        //
+        // Layout
+
+        // Tabs shrink down to min_tab_w, past that they overflow and arrows appear
+        let mut tabs_w = match config.show_new_button {
+            true => w.saturating_sub(button_w + m),
+            false => w,
+        };
+        let fit_w = match n {
+            0 => config.max_tab_w,
+            n => ((tabs_w + m) / n as u32).saturating_sub(m),
+        };
+        let tab_w = u32::max(u32::min(fit_w, config.max_tab_w), config.min_tab_w);
+
+        let overflow = n as u32 * (tab_w + m) > tabs_w + m;
+        if overflow {
+            tabs_w = tabs_w.saturating_sub(2 * (button_w + m));
+        }
+        let nb_visible = match overflow {
+            true => usize::max(1, ((tabs_w + m) / (tab_w + m)) as usize),
+            false => n,
+        };
+
+        *active = usize::min(*active, n.saturating_sub(1));
+
+        // The visible tabs follow the active one
+        let offset = interaction.scroll_offsets.entry(config.id).or_insert(0);
+        if *active < *offset {
+            *offset = *active;
+        } else if *active >= *offset + nb_visible {
+            *offset = *active + 1 - nb_visible;
+        }
+        *offset = usize::min(*offset, n.saturating_sub(nb_visible));
+        let offset = *offset;
+
+        let visible = offset..usize::min(offset + nb_visible, n);
+
+        let tab_rect = |i: usize| Rect {
+            x0: x0 + ((i - offset) as u32 * (tab_w + m)) as i64,
+            y0,
+            w: tab_w,
+            h,
+        };
+
+        let close_rect = |tab_rect: &Rect| Rect {
+            x0: tab_rect.x0 + tab_rect.w.saturating_sub(m + CLOSE_SIZE) as i64,
+            y0: tab_rect.y0 + (tab_rect.h as i64 - CLOSE_SIZE as i64) / 2,
+            w: CLOSE_SIZE,
+            h: CLOSE_SIZE,
+        };
+
+        let mut buttons_x0 = x0 + (visible.len() as u32 * (tab_w + m)) as i64;
+        let mut next_button_rect = || {
+            let rect = Rect {
+                x0: buttons_x0,
+                y0,
+                w: button_w,
+                h,
+            };
+            buttons_x0 += (button_w + m) as i64;
+            rect
+        };
+
+        let arrow_rects = match overflow {
+            true => Some((next_button_rect(), next_button_rect())),
+            false => None,
+        };
+        let new_rect = match config.show_new_button {
+            true => Some(next_button_rect()),
+            false => None,
+        };
+
+        //
+        // Interaction
+
+        let ps = &input_state.pointer;
+        let is_hovered = |rect: &Rect| rect.check_contains_point(ps.x, ps.y);
+
+        let hovered_tab = visible.clone().find(|i| is_hovered(&tab_rect(*i)));
+
+        // Checked first, so that clicking the close button does not also activate the tab
+        let hovered_close = hovered_tab.filter(|i| {
+            let close_hit_rect = close_rect(&tab_rect(*i)).offset(CLOSE_HIT_MARGIN);
+            tabs[*i].closable && is_hovered(&close_hit_rect)
+        });
+
+        let hovered_prev = arrow_rects.as_ref().is_some_and(|(r, _)| is_hovered(r));
+        let hovered_next = arrow_rects.as_ref().is_some_and(|(_, r)| is_hovered(r));
+        let hovered_new = new_rect.as_ref().is_some_and(is_hovered);
+
+        // Dragging moves the active tab, since pressing on a tab activates it
+        if interaction.dragged == Some(config.id) {
+            if ps.left_clicked && n > 0 {
+                let slot = (ps.x - x0).max(0) / (tab_w + m) as i64;
+                let target = usize::min(offset + slot as usize, visible.end - 1);
+                if target != *active {
+                    response.moved = Some((*active, target));
+                    *active = target;
+                }
+            } else {
+                interaction.dragged = None;
+            }
+        }
+
+        if ps.left_click_trigger {
+            let clicked_tab = match (hovered_tab, hovered_close) {
+                (_, Some(i)) => {
+                    response.closed = Some(i);
+                    None
+                }
+                (Some(i), None) => {
+                    interaction.dragged = Some(config.id);
+                    Some(i)
+                }
+                (None, None) if hovered_prev && *active > 0 => Some(*active - 1),
+                (None, None) if hovered_next && *active + 1 < n => Some(*active + 1),
+                (None, None) => {
+                    response.new_requested = hovered_new;
+                    None
+                }
+            };
+
+            if let Some(i) = clicked_tab.filter(|i| *i != *active) {
+                *active = i;
+                response.activated = Some(i);
+            }
+        }
+
+        //
+        // Drawing
+
+        for i in visible.clone() {
+            let tab = &tabs[i];
+            let rect = tab_rect(i);
+            let is_active = i == *active;
+
+            let bg_color = match is_active {
+                true => colorsheet.element,
+                false => colorsheet.frame,
+            };
+            draw_rect(*fb, &rect, bg_color, false);
+            if hovered_tab == Some(i) && hovered_close.is_none() {
+                draw_rect(*fb, &rect, colorsheet.hover_overlay, true);
+            }
+
+            if is_active {
+                let bar_rect = Rect {
+                    y0: rect.y0 + (rect.h.saturating_sub(ACTIVE_BAR_H)) as i64,
+                    h: u32::min(ACTIVE_BAR_H, rect.h),
+                    ..rect.clone()
+                };
+                draw_rect(*fb, &bar_rect, colorsheet.accent, false);
+            }
+
+            let mut label_x0 = rect.x0 + m as i64;
+            let (_, yc) = rect.center();
+
+            // Unsaved changes
+            if tab.dirty {
+                let center = Point2D {
+                    x: label_x0 + DOT_R as i64,
+                    y: yc,
+                };
+                draw_arc(
+                    *fb,
+                    center,
+                    0.0,
+                    DOT_R,
+                    ArcMode::Full,
+                    2.0,
+                    colorsheet.accent,
+                    false,
+                );
+                label_x0 += 2 * DOT_R as i64 + m as i64;
+            }
+
+            let label_x1 = match tab.closable {
+                true => close_rect(&rect).x0 - m as i64,
+                false => rect.x0 + rect.w as i64 - m as i64,
+            };
+
+            let label_w = (label_x1 - label_x0).max(0) as u32;
+            let label_rect = Rect {
+                x0: label_x0,
+                y0: rect.y0,
+                w: label_w,
+                h: rect.h,
+            };
+            draw_line_in_rect(
+                *fb,
+                &ellipsize_text(&tab.label, font, label_w),
+                &label_rect,
+                font,
+                colorsheet.text,
+                TextJustification::Left,
+            );
+
+            if tab.closable {
+                let close_rect = close_rect(&rect);
+                if hovered_close == Some(i) {
+                    draw_rect(*fb, &close_rect, colorsheet.hover_overlay, true);
+                }
+                draw_line_in_rect(
+                    *fb,
+                    "x",
+                    &close_rect,
+                    font,
+                    colorsheet.text,
+                    TextJustification::Center,
+                );
+            }
+        }
+
+        if let Some((prev_rect, next_rect)) = &arrow_rects {
+            let prev_enabled = *active > 0;
+            let next_enabled = *active + 1 < n;
+            draw_button(
+                *fb,
+                prev_rect,
+                "<",
+                hovered_prev,
+                prev_enabled,
+                colorsheet,
+                font,
+            );
+            draw_button(
+                *fb,
+                next_rect,
+                ">",
+                hovered_next,
+                next_enabled,
+                colorsheet,
+                font,
+            );
+        }
+
+        if let Some(new_rect) = &new_rect {
+            draw_button(*fb, new_rect, "+", hovered_new, true, colorsheet, font);
+        }
+
+        response
+    }
+}
+
+fn draw_button<F: FbViewMut>(
+    fb: &mut F,
+    rect: &Rect,
+    text: &str,
+    hovered: bool,
+    enabled: bool,
+    colorsheet: &StyleSheetColors,
+    font: &Font,
+) {
+    draw_rect(fb, rect, colorsheet.element, false);
+
+    let text_color = match enabled {
+        true => colorsheet.text,
+        false => colorsheet.disabled,
+    };
+    if hovered && enabled {
+        draw_rect(fb, rect, colorsheet.hover_overlay, true);
+    }
+
+    draw_line_in_rect(fb, text, rect, font, text_color, TextJustification::Center);
+}
+
+#[derive(Clone)]
+pub struct TabItem {
+    pub label: String,
+    // Shown as a dot before the label
+    pub dirty: bool,
+    pub closable: bool,
+}
+
+#[derive(Clone)]
+pub struct TabBarConfig {
+    // Must be unique and stable across frames, the scroll and drag state is tied to it
+    pub id: ContentId,
+    pub rect: Rect,
+    pub min_tab_w: u32,
+    pub max_tab_w: u32,
+    pub show_new_button: bool,
+}
+
+impl Default for TabBarConfig {
+    fn default() -> Self {
+        TabBarConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 100,
+                h: 25,
+            },
+            min_tab_w: 60,
+            max_tab_w: 150,
+            show_new_button: false,
+        }
+    }
+}
+
+// The caller owns the tabs, so it is up to it to apply these
+#[derive(Debug, Default)]
+pub struct TabBarResponse {
+    pub activated: Option<usize>,
+    pub closed: Option<usize>,
+    pub new_requested: bool,
+    // (from, to): the tab at index from should be moved to index to
+    pub moved: Option<(usize, usize)>,
+}
@@ -0,0 +1,581 @@
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_rect, draw_rect_outline, draw_triangle};
+use crate::drawing::text::{draw_line_in_rect, get_font, Font, TextJustification};
+use crate::geometry::{Point2D, Triangle2D};
+use crate::input::{InputEvent, Keycode, MouseButton};
+use crate::uitk::{CursorHint, TileRenderer, UiContext};
+use crate::{Color, FbViewMut, Rect};
+
+// How far from a column edge the pointer can grab it
+const HANDLE_HALF_W: i64 = 3;
+const SORT_INDICATOR_W: u32 = 8;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    // Only the visible rows are queried from cell_fn(row, column). Sorting is left to
+    // the app: clicking a header only reports the order the user asked for.
+    pub fn table<C: Fn(usize, usize) -> String>(
+        &mut self,
+        config: &TableConfig,
+        columns: &[ColumnDef],
+        row_count: usize,
+        cell_fn: C,
+        state: &mut TableState,
+    ) -> TableEvent {
+        self.clipped(&config.rect, |ui| {
+            ui.table_inner(config, columns, row_count, cell_fn, state)
+        })
+    }
+
+    fn table_inner<C: Fn(usize, usize) -> String>(
+        &mut self,
+        config: &TableConfig,
+        columns: &[ColumnDef],
+        row_count: usize,
+        cell_fn: C,
+        state: &mut TableState,
+    ) -> TableEvent {
+        let focused = self.focusable(config.id, &config.rect, true);
+        state.focused = focused;
+
+        if state.widths.len() != columns.len() {
+            state.widths = columns.iter().map(|col| col.width).collect();
+        }
+        state.nb_rows = row_count;
+        state.row_h = config.row_h;
+
+        let Rect { x0, y0, w, h } = config.rect;
+        let header_h = u32::min(config.row_h, h);
+        let header_rect = Rect {
+            x0,
+            y0,
+            w,
+            h: header_h,
+        };
+        let body_rect = Rect {
+            x0,
+            y0: y0 + header_h as i64,
+            w,
+            h: h - header_h,
+        };
+        state.body_rect = body_rect.clone();
+
+        let mut event = TableEvent::None;
+
+        //
+        // Header
+
+        let ps = &self.input_state.pointer;
+        let (scroll_x, _) = state.offsets;
+        let columns_x0 = x0 - scroll_x;
+
+        // Right edges of the columns
+        let edges: Vec<i64> = state
+            .widths
+            .iter()
+            .scan(columns_x0, |x, col_w| {
+                *x += *col_w as i64;
+                Some(*x)
+            })
+            .collect();
+
+        let in_header = header_rect.check_contains_point(ps.x, ps.y);
+        let hovered_handle = match in_header {
+            true => edges
+                .iter()
+                .position(|edge| (ps.x - edge).abs() <= HANDLE_HALF_W),
+            false => None,
+        };
+        let hovered_column = match in_header && hovered_handle.is_none() {
+            true => edges.iter().position(|edge| ps.x < *edge),
+            false => None,
+        };
+
+        let resize_id = ContentId::from_hash(&(config.id, "resize"));
+
+        if ps.left_click_trigger {
+            if let Some(col) = hovered_handle {
+                self.interaction.dragged = Some(resize_id);
+                state.resizing = Some((col, ps.x, state.widths[col]));
+            } else if let Some(col) = hovered_column.filter(|col| columns[*col].sortable) {
+                let ascending = match state.sort {
+                    Some((sorted, ascending)) if sorted == col => !ascending,
+                    _ => true,
+                };
+                state.sort = Some((col, ascending));
+                event = TableEvent::Sort {
+                    column: col,
+                    ascending,
+                };
+            }
+        }
+
+        let resizing = self.interaction.dragged == Some(resize_id);
+        if resizing {
+            match (ps.left_clicked, state.resizing) {
+                (true, Some((col, origin_x, origin_w))) => {
+                    let col_w = origin_w as i64 + ps.x - origin_x;
+                    state.widths[col] = i64::max(columns[col].min_width as i64, col_w) as u32;
+                }
+                _ => {
+                    self.interaction.dragged = None;
+                    state.resizing = None;
+                }
+            }
+        }
+
+        if hovered_handle.is_some() || resizing {
+            self.set_cursor_hint(CursorHint::ResizeHorizontal);
+        }
+
+        //
+        // Rows
+
+        // Clicks are matched against the rows as they were drawn last frame
+        let (_, scroll_y) = state.offsets;
+        let scrolling = state.dragging.0 || state.dragging.1;
+        let clicked_row = match ps.left_click_trigger && !scrolling && !resizing {
+            true if body_rect.check_contains_point(ps.x, ps.y) => {
+                let i = ((ps.y - body_rect.y0 + scroll_y) / config.row_h as i64) as usize;
+                Some((i < row_count).then_some(i))
+            }
+            _ => None,
+        };
+
+        // Same as the file lists: Ctrl toggles a row, a double click activates it
+        match clicked_row {
+            Some(Some(i)) => {
+                let is_double_click = self
+                    .input_state
+                    .click_count(MouseButton::Left)
+                    .is_some_and(|clicks| clicks >= 2)
+                    && state.last_click == Some(i);
+                state.last_click = Some(i);
+                state.cursor = Some(i);
+
+                if self.input_state.ctrl {
+                    if !state.selection.remove(&i) {
+                        state.selection.insert(i);
+                    }
+                    event = TableEvent::Selected(i);
+                } else if is_double_click {
+                    state.last_click = None;
+                    event = TableEvent::Activated(i);
+                } else {
+                    state.selection = BTreeSet::from([i]);
+                    event = TableEvent::Selected(i);
+                }
+            }
+            Some(None) => {
+                state.selection.clear();
+                state.cursor = None;
+            }
+            None => (),
+        }
+
+        //
+        // Keyboard
+
+        if focused && row_count > 0 {
+            for input_event in self.input_state.events.iter() {
+                let Some(InputEvent::KeyPress { keycode }) = input_event else {
+                    continue;
+                };
+
+                let new_cursor = match (keycode, state.cursor) {
+                    (Keycode::KEY_UP | Keycode::KEY_DOWN, None) => Some(0),
+                    (Keycode::KEY_UP, Some(i)) => Some(i.saturating_sub(1)),
+                    (Keycode::KEY_DOWN, Some(i)) => Some(usize::min(i + 1, row_count - 1)),
+                    (Keycode::KEY_ENTER, Some(i)) => {
+                        event = TableEvent::Activated(i);
+                        None
+                    }
+                    _ => None,
+                };
+
+                if let Some(i) = new_cursor {
+                    state.cursor = Some(i);
+                    state.selection = BTreeSet::from([i]);
+                    state.scroll_to_row(i);
+                    event = TableEvent::Selected(i);
+                }
+            }
+        }
+
+        state.cursor = state.cursor.filter(|i| *i < row_count);
+        state.selection.retain(|i| *i < row_count);
+
+        //
+        // Drawing
+
+        let stylesheet = &self.stylesheet;
+        let colorsheet = &stylesheet.colors;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+        let m = stylesheet.margin;
+        let colors = TableColors {
+            bg: colorsheet.element,
+            bg_alt: colorsheet.frame,
+            selected: colorsheet.accent,
+            cursor: colorsheet.text,
+            text: colorsheet.text,
+        };
+
+        let columns_w: u32 = state.widths.iter().sum();
+        let renderer = TableRenderer {
+            w: u32::max(columns_w, body_rect.w),
+            row_h: config.row_h,
+            columns,
+            widths: &state.widths,
+            row_count,
+            cell_fn: &cell_fn,
+            selection: &state.selection,
+            cursor: state.cursor,
+            font,
+            colors,
+        };
+
+        self.dynamic_canvas(
+            &body_rect,
+            &renderer,
+            &mut state.offsets,
+            &mut state.dragging,
+        );
+
+        // Scrolled along with the rows
+        let (scroll_x, _) = state.offsets;
+        let colorsheet = &self.stylesheet.colors;
+        let mut header_fb = self.fb.subregion_mut(&header_rect);
+        header_fb.fill(colorsheet.frame);
+
+        let mut col_x0 = -scroll_x;
+        for (i, (col, col_w)) in columns.iter().zip(state.widths.iter()).enumerate() {
+            let cell_rect = Rect {
+                x0: col_x0,
+                y0: 0,
+                w: *col_w,
+                h: header_h,
+            };
+            col_x0 += *col_w as i64;
+
+            if hovered_column == Some(i) && col.sortable && !resizing {
+                draw_rect(&mut header_fb, &cell_rect, colorsheet.hover_overlay, true);
+            }
+
+            let sort = state.sort.filter(|(sorted, _)| *sorted == i);
+            let indicator_w = match sort {
+                Some(_) => SORT_INDICATOR_W + 2 * m,
+                None => 0,
+            };
+
+            let text_rect = Rect {
+                w: col_w.saturating_sub(indicator_w),
+                ..cell_rect.clone()
+            };
+            draw_line_in_rect(
+                &mut header_fb,
+                &col.title,
+                &text_rect,
+                font,
+                colorsheet.text,
+                col.justification,
+            );
+
+            if let Some((_, ascending)) = sort {
+                let xc = cell_rect.x0 + (*col_w - indicator_w / 2) as i64;
+                let yc = (header_h / 2) as i64;
+                draw_sort_indicator(&mut header_fb, xc, yc, ascending, colorsheet.text);
+            }
+
+            // Column edge, where it can be resized
+            let edge_color =
+                match hovered_handle == Some(i) || state.resizing.is_some_and(|r| r.0 == i) {
+                    true => colorsheet.accent,
+                    false => colorsheet.outline,
+                };
+            let edge_rect = Rect {
+                x0: col_x0 - 1,
+                y0: 0,
+                w: 1,
+                h: header_h,
+            };
+            draw_rect(&mut header_fb, &edge_rect, edge_color, false);
+        }
+
+        if focused {
+            self.draw_focus_ring(&config.rect);
+        }
+
+        event
+    }
+}
+
+#[derive(Clone)]
+pub struct TableConfig {
+    // Must be unique and stable across frames, the keyboard focus is tied to it
+    pub id: ContentId,
+    pub rect: Rect,
+    // Also the height of the header
+    pub row_h: u32,
+}
+
+impl Default for TableConfig {
+    fn default() -> Self {
+        TableConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 300,
+                h: 300,
+            },
+            row_h: 20,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ColumnDef {
+    pub title: String,
+    // Initial width, the user can resize the column afterwards
+    pub width: u32,
+    pub min_width: u32,
+    pub justification: TextJustification,
+    pub sortable: bool,
+}
+
+impl Default for ColumnDef {
+    fn default() -> Self {
+        ColumnDef {
+            title: String::new(),
+            width: 100,
+            min_width: 30,
+            justification: TextJustification::Left,
+            sortable: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TableEvent {
+    None,
+    // A header was clicked, the app is expected to reorder its rows
+    Sort { column: usize, ascending: bool },
+    // By a click or the arrow keys
+    Selected(usize),
+    // By a double click or Enter
+    Activated(usize),
+}
+
+pub struct TableState {
+    pub selection: BTreeSet<usize>,
+    pub cursor: Option<usize>,
+    // Column and whether it is ascending, as shown in the header
+    pub sort: Option<(usize, bool)>,
+    widths: Vec<u32>,
+    offsets: (i64, i64),
+    dragging: (bool, bool),
+    // Column, pointer x and column width when the resize started
+    resizing: Option<(usize, i64, u32)>,
+    last_click: Option<usize>,
+    // As of the last time the table was drawn
+    body_rect: Rect,
+    row_h: u32,
+    nb_rows: usize,
+    focused: bool,
+}
+
+impl Default for TableState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TableState {
+    pub fn new() -> Self {
+        TableState {
+            selection: BTreeSet::new(),
+            cursor: None,
+            sort: None,
+            widths: Vec::new(),
+            offsets: (0, 0),
+            dragging: (false, false),
+            resizing: None,
+            last_click: None,
+            body_rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 0,
+                h: 0,
+            },
+            row_h: 1,
+            nb_rows: 0,
+            focused: false,
+        }
+    }
+
+    // As of the last time the table was drawn
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn column_widths(&self) -> &[u32] {
+        &self.widths
+    }
+
+    pub fn scroll_offsets(&self) -> (i64, i64) {
+        self.offsets
+    }
+
+    // Area where the rows were drawn last frame, below the header
+    pub fn body_rect(&self) -> &Rect {
+        &self.body_rect
+    }
+
+    // Back to the first row, e.g. when the rows are replaced
+    pub fn reset(&mut self) {
+        self.selection.clear();
+        self.cursor = None;
+        self.last_click = None;
+        self.offsets = (0, 0);
+    }
+
+    // Index of the row under a point, for the layout of the last frame
+    pub fn row_at(&self, x: i64, y: i64) -> Option<usize> {
+        if !self.body_rect.check_contains_point(x, y) {
+            return None;
+        }
+        let (_, scroll_y) = self.offsets;
+        let i = ((y - self.body_rect.y0 + scroll_y) / self.row_h as i64) as usize;
+        (i < self.nb_rows).then_some(i)
+    }
+
+    // Scrolls so that a row is fully visible
+    pub fn scroll_to_row(&mut self, row: usize) {
+        let (_, scroll_y) = &mut self.offsets;
+        let row_y0 = (row as u32 * self.row_h) as i64;
+        let row_y1 = row_y0 + self.row_h as i64;
+        let view_h = self.body_rect.h as i64;
+
+        if row_y0 < *scroll_y {
+            *scroll_y = row_y0;
+        } else if row_y1 > *scroll_y + view_h {
+            *scroll_y = row_y1 - view_h;
+        }
+    }
+}
+
+// Pointing up when ascending
+fn draw_sort_indicator<F: FbViewMut>(fb: &mut F, xc: i64, yc: i64, ascending: bool, color: Color) {
+    let s = (SORT_INDICATOR_W / 2) as i64;
+    let points = match ascending {
+        true => [(xc - s, yc + s / 2), (xc + s, yc + s / 2), (xc, yc - s / 2)],
+        false => [(xc - s, yc - s / 2), (xc + s, yc - s / 2), (xc, yc + s / 2)],
+    };
+    let tri = Triangle2D {
+        points: points.map(|(x, y)| Point2D { x, y }),
+    };
+    draw_triangle(fb, &tri, color, false);
+}
+
+#[derive(Clone, Copy, Hash)]
+struct TableColors {
+    bg: Color,
+    bg_alt: Color,
+    selected: Color,
+    cursor: Color,
+    text: Color,
+}
+
+// One tile per row, spanning all the columns
+struct TableRenderer<'a, C: Fn(usize, usize) -> String> {
+    w: u32,
+    row_h: u32,
+    columns: &'a [ColumnDef],
+    widths: &'a [u32],
+    row_count: usize,
+    cell_fn: &'a C,
+    selection: &'a BTreeSet<usize>,
+    cursor: Option<usize>,
+    font: &'static Font,
+    colors: TableColors,
+}
+
+impl<'a, C: Fn(usize, usize) -> String> TableRenderer<'a, C> {
+    fn row_index(&self, viewport_rect: &Rect) -> usize {
+        viewport_rect.y0 as usize / self.row_h as usize
+    }
+}
+
+impl<'a, C: Fn(usize, usize) -> String> TileRenderer for TableRenderer<'a, C> {
+    fn shape(&self) -> (u32, u32) {
+        (self.w, self.row_count as u32 * self.row_h)
+    }
+
+    fn tile_shape(&self) -> (u32, u32) {
+        (self.w, self.row_h)
+    }
+
+    fn content_id(&self, viewport_rect: &Rect) -> ContentId {
+        let i = self.row_index(viewport_rect);
+        let row = (i < self.row_count).then(|| {
+            let cells: Vec<String> = (0..self.columns.len())
+                .map(|col| (self.cell_fn)(i, col))
+                .collect();
+            (cells, self.selection.contains(&i), self.cursor == Some(i))
+        });
+
+        ContentId::from_hash(&(self.w, self.row_h, self.widths, i % 2, row, self.colors))
+    }
+
+    fn render<F: FbViewMut>(&self, dst_fb: &mut F, viewport_rect: &Rect) {
+        let i = self.row_index(viewport_rect);
+        let colors = &self.colors;
+
+        if i >= self.row_count {
+            dst_fb.fill(colors.bg);
+            return;
+        }
+
+        let bg = match (self.selection.contains(&i), i % 2) {
+            (true, _) => colors.selected,
+            (false, 0) => colors.bg,
+            (false, _) => colors.bg_alt,
+        };
+        dst_fb.fill(bg);
+
+        let mut x0 = 0;
+        for (col, (def, col_w)) in self.columns.iter().zip(self.widths).enumerate() {
+            let cell_rect = Rect {
+                x0,
+                y0: 0,
+                w: *col_w,
+                h: self.row_h,
+            };
+            x0 += *col_w as i64;
+
+            // Long values are cut at the column edge
+            let mut cell_fb = dst_fb.subregion_mut(&cell_rect);
+            draw_line_in_rect(
+                &mut cell_fb,
+                &(self.cell_fn)(i, col),
+                &cell_rect.zero_origin(),
+                self.font,
+                colors.text,
+                def.justification,
+            );
+        }
+
+        if self.cursor == Some(i) {
+            let row_rect = Rect {
+                x0: 0,
+                y0: 0,
+                w: self.w,
+                h: self.row_h,
+            };
+            draw_rect_outline(dst_fb, &row_rect, colors.cursor, false, 1);
+        }
+    }
+}
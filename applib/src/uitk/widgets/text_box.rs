@@ -1,21 +1,34 @@
 use alloc::string::String;
+use alloc::vec::Vec;
 
 use crate::content::{ContentId, TrackedContent};
 use crate::drawing::primitives::draw_rect;
 use crate::drawing::text::{
-    draw_rich_slice, format_rich_lines, get_font, Font, FormattedRichText, RichText,
-    TextJustification,
+    draw_char, draw_rich_slice, format_rich_lines, get_font, Font, FormattedRichText, RichChar,
+    RichText, TextJustification,
 };
+use crate::drawing::text_cache::draw_cached_run;
+use crate::input::{InputEvent, Keycode, MouseButton, PointerState};
 use crate::Color;
 use crate::Rect;
 use crate::{FbView, FbViewMut};
+use crate::{WidgetColors, WidgetState};
 
-use crate::uitk::{TileRenderer, UiContext};
+use crate::uitk::frame::draw_widget_frame;
+use crate::uitk::{TileRenderer, ToastConfig, UiContext};
 
-use crate::uitk::text::{string_input, EditableText};
+use crate::uitk::history::EditHistory;
+use crate::uitk::text::{
+    filter_typed, is_edit_event, line_range, sanitize_pasted, string_input, word_range, EditChars,
+    EditableText,
+};
 use crate::uitk::UuidProvider;
 
 const CURSOR_BLINK_PERIOD: u64 = 1;
+const COPIED_TOAST_DURATION: f64 = 1000.0; // in ms
+
+// Space kept between the cursor and the edges of the box when scrolling to it
+const CURSOR_SCROLL_MARGIN: i64 = 8;
 
 impl<'a, F: FbViewMut> UiContext<'a, F> {
     pub fn text_box<T: FormattableText>(
@@ -25,11 +38,16 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
         state: &mut TextBoxState,
         autoscroll: bool,
     ) {
+        // Read-only boxes take the focus for Ctrl+A and Ctrl+C, but are not Tab stops
+        self.text_box_focus(dst_rect, state, false);
+
         let prelude: Option<&T> = None;
-        let bg_color = self.stylesheet.colors.element;
-        self.text_box_inner(
-            dst_rect, text, bg_color, state, autoscroll, false, prelude, false,
-        );
+        let bg_color = self.stylesheet.widgets.normal.bg;
+        self.clipped(dst_rect, |ui| {
+            ui.text_box_inner(
+                dst_rect, text, bg_color, state, autoscroll, false, prelude, false,
+            )
+        });
     }
 
     pub fn editable_text_box<T: FormattableText + EditableText, U: FormattableText>(
@@ -41,36 +59,261 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
         allow_newline: bool,
         prelude: Option<&U>,
     ) {
+        if state.disabled {
+            return self.disabled_text_box(dst_rect, text, state, prelude);
+        }
+
+        // Key presses only go to the focused box
+        let focused = self.text_box_focus(dst_rect, state, true);
+
+        let old_cursor = state.cursor;
+
+        if focused {
+            let rules = EditRules {
+                allow_newline,
+                ..Default::default()
+            };
+            let edited = self.keyboard_edit(text, state, &rules);
+
+            // Keeps the cursor in view while typing or moving it with the keyboard
+            if edited || state.cursor != old_cursor {
+                state.scroll_to_cursor = true;
+            }
+        }
+
+        let cursor_changed = state.cursor != old_cursor;
+        let bg_color = self.stylesheet.colors.editable;
+
+        self.clipped(dst_rect, |ui| {
+            ui.text_box_inner(
+                dst_rect,
+                text,
+                bg_color,
+                state,
+                cursor_changed,
+                autoscroll,
+                prelude,
+                true,
+            )
+        });
+    }
+
+    // Drawn muted and without a cursor, the pointer and keyboard are ignored
+    fn disabled_text_box<T: FormattableText, U: FormattableText>(
+        &mut self,
+        dst_rect: &Rect,
+        text: &T,
+        state: &mut TextBoxState,
+        prelude: Option<&U>,
+    ) {
+        if let Some(id) = state.id {
+            self.unfocus(id);
+        }
+        state.focused = false;
+        state.selecting = false;
+
+        let bg_color = self.stylesheet.widgets.disabled.bg;
+        let input_state = core::mem::replace(&mut self.input_state, self.blank_input_state);
+        self.clipped(dst_rect, |ui| {
+            ui.text_box_inner(
+                dst_rect, text, bg_color, state, false, false, prelude, false,
+            )
+        });
+        self.input_state = input_state;
+    }
+
+    // Typing, undo/redo and clipboard shortcuts, shared by the text widgets.
+    // Returns whether the text was edited.
+    pub(crate) fn keyboard_edit<T: EditableText>(
+        &mut self,
+        text: &mut T,
+        state: &mut TextBoxState,
+        rules: &EditRules,
+    ) -> bool {
+        let EditRules {
+            allow_newline,
+            accept,
+            max_len,
+            allow_copy,
+        } = *rules;
         let UiContext {
             input_state,
             uuid_provider,
+            time,
+            clipboard,
+            compose,
             ..
         } = self;
 
-        let bg_color = self.stylesheet.colors.editable;
-
         let old_cursor = state.cursor;
 
-        string_input(
+        let undo =
+            input_state.ctrl && !input_state.shift && input_state.check_key_pressed(Keycode::KEY_Z);
+        let redo = input_state.ctrl
+            && (input_state.check_key_pressed(Keycode::KEY_Y)
+                || input_state.shift && input_state.check_key_pressed(Keycode::KEY_Z));
+
+        if undo || redo {
+            let cursor = match undo {
+                true => state.history.undo(text, *uuid_provider),
+                false => state.history.redo(text, *uuid_provider),
+            };
+            if let Some(cursor) = cursor {
+                state.cursor = cursor;
+                state.anchor = None;
+            }
+            return cursor.is_some();
+        }
+
+        let selection = state.selection();
+        let selected_len = selection.map_or(0, |(start, end)| end - start);
+        let room = max_len.saturating_sub(text.len() - selected_len);
+
+        // Dead keys and compose sequences are turned into the characters they give
+        let composed = match state.id {
+            Some(id) => compose.process(id, input_state),
+            None => (*input_state).clone(),
+        };
+        let mut filtered = filter_typed(&composed, accept, room);
+
+        let cut = input_state.ctrl && input_state.check_key_pressed(Keycode::KEY_X);
+        let paste = input_state.ctrl && input_state.check_key_pressed(Keycode::KEY_V);
+        let typing = filtered
+            .events
+            .iter()
+            .flatten()
+            .any(|event| is_edit_event(event, &filtered, allow_newline));
+
+        if let (true, true, Some((start, end))) = (allow_copy, cut, selection) {
+            clipboard.set(&text.slice(start, end).to_plain());
+        }
+
+        let pasted = match paste {
+            true => clipboard
+                .get()
+                .map(|s| {
+                    sanitize_pasted(&s, allow_newline)
+                        .chars()
+                        .filter(|c| accept(*c))
+                        .take(room)
+                        .collect::<String>()
+                })
+                .filter(|s| !s.is_empty()),
+            false => None,
+        };
+
+        // Typing, cutting or pasting over a selection replaces it
+        let replaced = match selection {
+            Some(range) if cut || typing || pasted.is_some() => Some(range),
+            _ => pasted.as_ref().map(|_| (state.cursor, state.cursor)),
+        };
+
+        if let Some((start, end)) = replaced {
+            let s = pasted.unwrap_or_default();
+            let new_cursor = start + s.chars().count();
+            state.replace_ranges(text, uuid_provider, &[(start, end)], &s, new_cursor);
+        }
+
+        // The selection was already deleted by the Backspace
+        if selection.is_some() && replaced.is_some() {
+            for event in filtered.events.iter_mut() {
+                if let Some(InputEvent::KeyPress {
+                    keycode: Keycode::KEY_BACKSPACE,
+                }) = event
+                {
+                    *event = None;
+                }
+            }
+        }
+
+        let mut recording = RecordingText {
             text,
-            input_state,
+            history: &mut state.history,
+            time: *time,
+            edited: false,
+        };
+
+        string_input(
+            &mut recording,
+            &filtered,
             allow_newline,
             &mut state.cursor,
-            *uuid_provider,
+            &mut state.anchor,
+            uuid_provider,
         );
 
-        let cursor_changed = state.cursor != old_cursor;
+        let edited = recording.edited || replaced.is_some();
 
-        self.text_box_inner(
-            dst_rect,
-            text,
-            bg_color,
-            state,
-            cursor_changed,
-            autoscroll,
-            prelude,
-            true,
-        );
+        // Moving the cursor around ends the current undo unit
+        if !recording.edited && state.cursor != old_cursor {
+            state.history.seal();
+        }
+
+        edited
+    }
+
+    // Ctrl+A and Ctrl+C, for the focused text widget. Returns whether the cursor moved.
+    pub(crate) fn selection_shortcuts(
+        &mut self,
+        state: &mut TextBoxState,
+        text_len: usize,
+        allow_copy: bool,
+        text_chars: impl Fn() -> Vec<char>,
+    ) -> bool {
+        if !state.focused || !self.input_state.ctrl {
+            return false;
+        }
+
+        if allow_copy && self.input_state.check_key_pressed(Keycode::KEY_C) {
+            if let Some((start, end)) = state.selection() {
+                let selected: String = text_chars()[start..end].iter().collect();
+                self.clipboard.set(&selected);
+                self.push_toast(ToastConfig {
+                    text: "Copied".into(),
+                    duration: COPIED_TOAST_DURATION,
+                    ..Default::default()
+                });
+            }
+        }
+
+        match self.input_state.check_key_pressed(Keycode::KEY_A) {
+            true => {
+                state.anchor = Some(0);
+                state.cursor = text_len;
+                true
+            }
+            false => false,
+        }
+    }
+
+    pub(crate) fn text_box_focus(
+        &mut self,
+        dst_rect: &Rect,
+        state: &mut TextBoxState,
+        tab_stop: bool,
+    ) -> bool {
+        let id = *state.id.get_or_insert_with(|| self.uuid_provider.make_id());
+        if state.focus_requested {
+            self.interaction.focused = Some(id);
+            state.focus_requested = false;
+        }
+        // Only the editable boxes are Tab stops, and they take the typed keys
+        if tab_stop {
+            self.focus.register_text_entry(id);
+        }
+        state.focused = self.focusable(id, dst_rect, tab_stop);
+        if !state.focused {
+            self.compose.cancel(id);
+        }
+        state.focused
+    }
+
+    // The pending dead key or compose sequence of the focused text widget
+    pub(crate) fn compose_placeholder(&self, state: &TextBoxState) -> Option<char> {
+        match (state.id, state.focused) {
+            (Some(id), true) => self.compose.placeholder(id),
+            _ => None,
+        }
     }
 
     fn text_box_inner<T: FormattableText, U: FormattableText>(
@@ -84,15 +327,39 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
         prelude: Option<&U>,
         cursor_enabled: bool,
     ) {
-        let time_sec = (self.time as u64) / 1000;
-
         // Only used if text is not already a RichText
         let font = get_font(
             &self.stylesheet.text.font_family(),
             self.stylesheet.text.sizes.medium,
         );
 
-        let color = self.stylesheet.colors.text;
+        let style = &self.stylesheet.widgets;
+        let p = &self.input_state.pointer;
+        let widget_state = match (state.disabled, state.focused) {
+            (true, _) => WidgetState::Disabled,
+            (false, true) => WidgetState::Selected,
+            (false, false) if dst_rect.check_contains_point(p.x, p.y) => WidgetState::Hover,
+            (false, false) => WidgetState::Normal,
+        };
+        let colors = style.colors(widget_state);
+        let color = match state.disabled {
+            true => colors.text,
+            false => style.normal.text,
+        };
+
+        // The contents are drawn over the whole rect when there is no border to leave room for
+        let frame_rect;
+        let dst_rect = match style.border_width > 0 || style.border_radius > 0 {
+            true => {
+                let frame_colors = WidgetColors {
+                    bg: bg_color,
+                    ..*colors
+                };
+                frame_rect = draw_widget_frame(self.fb, dst_rect, style, &frame_colors);
+                &frame_rect
+            }
+            false => dst_rect,
+        };
 
         let (rich_text, prelude_len) = match prelude {
             None => {
@@ -120,68 +387,143 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
             TrackedContent::new_with_id(formatted, content_id)
         };
 
+        let text_len = rich_text.as_ref().len() - prelude_len;
+
+        // The selection is kept when text is only appended (e.g. to a log), but not
+        // when the content is replaced
+        match state.selection_content {
+            Some((content_id, len)) if content_id != rich_text.get_id() && text_len <= len => {
+                state.anchor = None;
+            }
+            _ => (),
+        }
+        state.selection_content = Some((rich_text.get_id(), text_len));
+        state.cursor = usize::min(state.cursor, text_len);
+        state.anchor = state.anchor.map(|anchor| usize::min(anchor, text_len));
+
         let p = &self.input_state.pointer;
         let vr = dst_rect;
 
         let mut shadow_cursor = None;
 
+        let (ox, oy) = state.scroll_offsets;
+        let (x_text, y_text) = (p.x - vr.x0 + ox, p.y - vr.y0 + oy);
+        let text_chars = || -> Vec<char> {
+            rich_text
+                .as_ref()
+                .chars()
+                .skip(prelude_len)
+                .map(|rc| rc.c)
+                .collect()
+        };
+
         if dst_rect.check_contains_point(p.x, p.y) {
-            let (ox, oy) = state.scroll_offsets;
-            let (x_text, y_text) = (p.x - vr.x0 + ox, p.y - vr.y0 + oy);
             if let Some(index) = formatted.as_ref().xy_to_index((x_text, y_text)) {
+                let index = index.saturating_sub(prelude_len);
                 if p.left_click_trigger {
-                    state.cursor = index - prelude_len;
+                    let clicks = self.input_state.click_count(MouseButton::Left).unwrap_or(1);
+                    state.click_select(index, clicks, self.input_state.shift, text_chars);
                     cursor_changed = true;
-                } else {
-                    shadow_cursor = Some(index - prelude_len);
+                } else if !p.left_clicked {
+                    shadow_cursor = Some(index);
                 }
             }
         }
 
-        if !cursor_enabled {
-            state.cursor_visible = false;
-        } else if cursor_changed {
-            state.last_blink_t = time_sec;
-            state.cursor_visible = true;
-        } else if time_sec - state.last_blink_t > CURSOR_BLINK_PERIOD {
-            state.last_blink_t = time_sec;
-            state.cursor_visible = !state.cursor_visible;
+        let dragged = state.drag_select(p, text_len, || {
+            formatted
+                .as_ref()
+                .xy_to_nearest_index((x_text, y_text))
+                .saturating_sub(prelude_len)
+        });
+        if dragged {
+            state.scroll_to_cursor = true;
+            cursor_changed = true;
+        }
+
+        cursor_changed |= self.selection_shortcuts(state, text_len, true, text_chars);
+
+        state.update_blink(self.time, cursor_enabled, cursor_changed);
+
+        if state.scroll_to_cursor {
+            let (_, cursor_y, cursor_h) =
+                formatted.as_ref().index_to_xy(prelude_len + state.cursor);
+            let view_h = dst_rect.h as i64;
+            let margin = i64::min(CURSOR_SCROLL_MARGIN, (view_h - cursor_h as i64) / 2).max(0);
+            let (_, scroll_y0) = &mut state.scroll_offsets;
+            let lowest_y0 = cursor_y + cursor_h as i64 + margin - view_h;
+            let highest_y0 = cursor_y - margin;
+            *scroll_y0 = i64::max(0, i64::min(i64::max(*scroll_y0, lowest_y0), highest_y0));
+            state.scroll_to_cursor = false;
         }
 
         let formatted_content_id = formatted.get_id();
 
+        let highlights = state
+            .highlights
+            .iter()
+            .map(|(start, end)| (prelude_len + start, prelude_len + end))
+            .collect();
+
+        let selection = state
+            .selection()
+            .map(|(start, end)| (prelude_len + start, prelude_len + end));
+
+        let pending = match cursor_enabled {
+            true => self.compose_placeholder(state).map(|c| (c, font, color)),
+            false => None,
+        };
+
         let renderer = TextRenderer {
             formatted,
             bg_color,
+            highlight_color: self.stylesheet.colors.yellow,
+            highlights,
+            selection_color: self.stylesheet.colors.accent,
+            selection,
             cursor: state.cursor,
             cursor_visible: state.cursor_visible,
             shadow_cursor,
             prelude_len,
+            pending,
         };
 
+        let (content_w, content_h) = renderer.shape();
+        let max_x0 = max_scroll(content_w, dst_rect.w);
+        let max_y0 = max_scroll(content_h, dst_rect.h);
+
+        // New content is followed, unless the user scrolled up to read older content
         if autoscroll {
             let TextBoxState {
                 content_id,
                 scroll_offsets,
+                at_bottom,
                 ..
             } = state;
-            match content_id {
-                Some(content_id) if *content_id == formatted_content_id => (),
-                _ => {
-                    let (_, scroll_y0) = scroll_offsets;
-                    let (_, max_h) = renderer.shape();
-                    *scroll_y0 = (max_h - dst_rect.h - 1).into();
-                    *content_id = Some(formatted_content_id);
-                }
+            if *content_id != Some(formatted_content_id) && *at_bottom {
+                scroll_offsets.1 = max_y0;
             }
+            *content_id = Some(formatted_content_id);
         }
 
+        // The content may have shrunk, after a resize or a font change
+        let (scroll_x0, scroll_y0) = &mut state.scroll_offsets;
+        *scroll_x0 = i64::clamp(*scroll_x0, 0, max_x0);
+        *scroll_y0 = i64::clamp(*scroll_y0, 0, max_y0);
+
         self.dynamic_canvas(
             dst_rect,
             &renderer,
             &mut state.scroll_offsets,
             &mut state.scroll_dragging,
         );
+
+        let (_, scroll_y0) = state.scroll_offsets;
+        state.at_bottom = scroll_y0 >= max_y0;
+
+        if state.focused {
+            self.draw_focus_ring(dst_rect);
+        }
     }
 }
 
@@ -191,9 +533,30 @@ pub struct TextBoxState {
     pub scroll_dragging: (bool, bool),
     pub cursor: usize,
     pub justif: TextJustification,
+    pub highlights: Vec<(usize, usize)>,
+    pub scroll_to_cursor: bool,
+    pub history: EditHistory,
+    // The selection goes from the anchor to the cursor
+    pub anchor: Option<usize>,
+    // Only applies to editable boxes
+    pub disabled: bool,
 
+    // Focus ID, assigned on the first draw
+    id: Option<ContentId>,
+    focused: bool,
+    focus_requested: bool,
     cursor_visible: bool,
     last_blink_t: u64,
+    // Whether the mouse is extending the selection
+    selecting: bool,
+    // Index of the last click
+    last_click: Option<usize>,
+    // 1 for a single click, 2 for a double click, 3 for a triple click
+    click_count: u32,
+    // Content the selection applies to, and its length
+    selection_content: Option<(ContentId, usize)>,
+    // Whether the view was scrolled to the end, for autoscroll
+    at_bottom: bool,
 }
 
 impl TextBoxState {
@@ -204,9 +567,191 @@ impl TextBoxState {
             scroll_dragging: (false, false),
             cursor: 0,
             justif: TextJustification::Left,
+            highlights: Vec::new(),
+            scroll_to_cursor: false,
+            history: EditHistory::new(),
+            anchor: None,
+            disabled: false,
+            id: None,
+            focused: false,
+            focus_requested: false,
             cursor_visible: true,
             last_blink_t: 0,
+            selecting: false,
+            last_click: None,
+            click_count: 0,
+            selection_content: None,
+            at_bottom: true,
+        }
+    }
+
+    // Gives the keyboard focus to the box the next time it is drawn
+    pub fn focus(&mut self) {
+        self.focus_requested = true;
+    }
+
+    // As of the last time the box was drawn
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    // Sorted bounds of the selected range, if not empty
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        match self.anchor {
+            Some(anchor) if anchor != self.cursor => Some((
+                usize::min(anchor, self.cursor),
+                usize::max(anchor, self.cursor),
+            )),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn update_blink(&mut self, time: f64, cursor_enabled: bool, cursor_changed: bool) {
+        let time_sec = (time as u64) / 1000;
+        if !cursor_enabled || !self.focused {
+            self.cursor_visible = false;
+        } else if cursor_changed {
+            self.last_blink_t = time_sec;
+            self.cursor_visible = true;
+        } else if time_sec - self.last_blink_t > CURSOR_BLINK_PERIOD {
+            self.last_blink_t = time_sec;
+            self.cursor_visible = !self.cursor_visible;
+        }
+    }
+
+    pub(crate) fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    // A click moves the cursor and starts a selection, Shift+click extends the
+    // selection, and double and triple clicks select a word and a line
+    pub(crate) fn click_select(
+        &mut self,
+        index: usize,
+        clicks: u32,
+        shift: bool,
+        text_chars: impl Fn() -> Vec<char>,
+    ) {
+        let is_repeat = clicks >= 2 && self.last_click == Some(index);
+        self.click_count = match is_repeat {
+            true => self.click_count % 3 + 1,
+            false => 1,
+        };
+        self.last_click = Some(index);
+
+        let (anchor, cursor) = match self.click_count {
+            1 if shift => (self.anchor.unwrap_or(self.cursor), index),
+            1 => (index, index),
+            2 => word_range(&text_chars(), index),
+            _ => line_range(&text_chars(), index),
+        };
+        self.anchor = Some(anchor);
+        self.cursor = cursor;
+        self.selecting = self.click_count == 1;
+        self.history.seal();
+    }
+
+    // Dragging extends the selection, also outside of the widget.
+    // Returns whether the cursor moved.
+    pub(crate) fn drag_select(
+        &mut self,
+        pointer: &PointerState,
+        text_len: usize,
+        pointer_index: impl FnOnce() -> usize,
+    ) -> bool {
+        let mut moved = false;
+        if self.selecting && pointer.left_clicked && !pointer.left_click_trigger {
+            let index = usize::min(pointer_index(), text_len);
+            moved = index != self.cursor;
+            self.cursor = index;
         }
+        if !pointer.left_clicked {
+            self.selecting = false;
+        }
+        moved
+    }
+
+    /// Replaces the given ranges as a single undoable edit, and moves the cursor
+    pub fn replace_ranges<T: EditableText>(
+        &mut self,
+        text: &mut T,
+        uuid_provider: &mut UuidProvider,
+        ranges: &[(usize, usize)],
+        s: &str,
+        new_cursor: usize,
+    ) {
+        self.history
+            .replace(text, uuid_provider, ranges, s, self.cursor, new_cursor);
+        self.cursor = new_cursor;
+        self.anchor = None;
+    }
+}
+
+// What keyboard_edit() lets through
+pub(crate) struct EditRules<'b> {
+    pub allow_newline: bool,
+    // Typed and pasted characters are dropped unless accepted
+    pub accept: &'b dyn Fn(char) -> bool,
+    // In chars, the edits that would go over it are dropped
+    pub max_len: usize,
+    // Whether Ctrl+C and Ctrl+X put the selection in the clipboard
+    pub allow_copy: bool,
+}
+
+impl<'b> Default for EditRules<'b> {
+    fn default() -> Self {
+        EditRules {
+            allow_newline: false,
+            accept: &|_| true,
+            max_len: usize::MAX,
+            allow_copy: true,
+        }
+    }
+}
+
+// Forwards edits to the underlying text while recording them into the undo history
+struct RecordingText<'a, T: EditableText> {
+    text: &'a mut T,
+    history: &'a mut EditHistory,
+    time: f64,
+    edited: bool,
+}
+
+impl<'a, T: EditableText> EditableText for RecordingText<'a, T> {
+    fn len(&self) -> usize {
+        self.text.len()
+    }
+
+    fn insert(&mut self, uuid_provider: &mut UuidProvider, pos: usize, c: char) {
+        self.text.insert(uuid_provider, pos, c);
+        let inserted = self.text.slice(pos, pos + 1);
+        self.history.record_insert(pos, inserted, self.time);
+        self.edited = true;
+    }
+
+    fn remove(&mut self, uuid_provider: &mut UuidProvider, pos: usize) {
+        // string_input() only ever removes a single unit
+        let removed = self.text.slice(pos, pos + 1);
+        self.text.remove(uuid_provider, pos);
+        self.history.record_remove(pos, removed, self.time);
+        self.edited = true;
+    }
+
+    fn slice(&self, start: usize, end: usize) -> EditChars {
+        self.text.slice(start, end)
+    }
+
+    fn splice(
+        &mut self,
+        uuid_provider: &mut UuidProvider,
+        ranges: &[(usize, usize)],
+        parts: &[EditChars],
+    ) {
+        self.text.splice(uuid_provider, ranges, parts);
+    }
+
+    fn replace(&mut self, uuid_provider: &mut UuidProvider, ranges: &[(usize, usize)], s: &str) {
+        self.text.replace(uuid_provider, ranges, s);
     }
 }
 
@@ -250,6 +795,45 @@ impl<'a> EditableText for EditableRichText<'a> {
     fn remove(&mut self, uuid_provider: &mut UuidProvider, pos: usize) {
         self.rich_text.mutate(uuid_provider).remove(pos);
     }
+
+    fn slice(&self, start: usize, end: usize) -> EditChars {
+        EditChars::Rich(self.rich_text.as_ref().slice(start, end))
+    }
+
+    fn splice(
+        &mut self,
+        uuid_provider: &mut UuidProvider,
+        ranges: &[(usize, usize)],
+        parts: &[EditChars],
+    ) {
+        // Plain text takes the style of the run it goes into
+        let rich_text = self.rich_text.as_ref();
+        let parts = parts
+            .iter()
+            .zip(ranges)
+            .map(|(part, &(start, end))| match part {
+                EditChars::Rich(chars) => chars.clone(),
+                EditChars::Plain(s) => {
+                    let style = match start == end {
+                        true => rich_text.run_style(start),
+                        false => rich_text.get_char(start).style,
+                    };
+                    s.chars()
+                        .map(|c| RichChar::new(c, self.color, self.font).with_style(style))
+                        .collect()
+                }
+            })
+            .collect();
+        self.rich_text
+            .mutate(uuid_provider)
+            .splice_ranges(ranges, parts);
+    }
+
+    fn replace(&mut self, uuid_provider: &mut UuidProvider, ranges: &[(usize, usize)], s: &str) {
+        self.rich_text
+            .mutate(uuid_provider)
+            .replace_ranges(ranges, s);
+    }
 }
 
 impl<'a> FormattableText for EditableRichText<'a> {
@@ -261,16 +845,27 @@ impl<'a> FormattableText for EditableRichText<'a> {
 struct TextRenderer {
     formatted: TrackedContent<FormattedRichText>,
     bg_color: Color,
+    highlight_color: Color,
+    highlights: Vec<(usize, usize)>,
+    selection_color: Color,
+    selection: Option<(usize, usize)>,
     cursor: usize,
     shadow_cursor: Option<usize>,
     prelude_len: usize,
     cursor_visible: bool,
+    // Pending dead key or compose sequence, drawn at the cursor
+    pending: Option<(char, &'static Font, Color)>,
 }
 
 const CURSOR_W: u32 = 2;
 const MIN_TILE_W: u32 = 200;
 const TILE_H: u32 = 200;
 
+// Same as the scrollbars of dynamic_canvas()
+fn max_scroll(content_len: u32, view_len: u32) -> i64 {
+    i64::max(0, content_len as i64 - view_len as i64 - 1)
+}
+
 impl TileRenderer for TextRenderer {
     fn shape(&self) -> (u32, u32) {
         let FormattedRichText { w, h, .. } = *self.formatted.as_ref();
@@ -309,6 +904,10 @@ impl TileRenderer for TextRenderer {
                 self.cursor_visible,
                 self.shadow_cursor,
                 self.bg_color,
+                &self.highlights,
+                self.selection,
+                self.pending
+                    .map(|(c, font, color)| (c, font.name.as_str(), color)),
             ))
         }
     }
@@ -322,8 +921,36 @@ impl TileRenderer for TextRenderer {
             return;
         }
 
+        let highlight_rects = self
+            .formatted
+            .as_ref()
+            .ranges_to_rects(&self.highlights, tile_rect);
+
+        for rect in highlight_rects {
+            let rect = Rect {
+                x0: rect.x0 - ox,
+                y0: rect.y0 - oy,
+                ..rect
+            };
+            draw_rect(dst_fb, &rect, self.highlight_color, false);
+        }
+
+        let selection_rects = self
+            .formatted
+            .as_ref()
+            .ranges_to_rects(self.selection.as_slice(), tile_rect);
+
+        for rect in selection_rects {
+            let rect = Rect {
+                x0: rect.x0 - ox,
+                y0: rect.y0 - oy,
+                ..rect
+            };
+            draw_rect(dst_fb, &rect, self.selection_color, false);
+        }
+
         let mut y = 0;
-        for line in self.formatted.as_ref().lines.iter() {
+        for (line_index, line) in self.formatted.as_ref().lines.iter().enumerate() {
             let line_x0 = line.x_offset as i64;
 
             // Bounding box of line in source
@@ -334,8 +961,19 @@ impl TileRenderer for TextRenderer {
                 h: line.h,
             };
 
+            // Lines stay the same for as long as the formatted text keeps its ID, so
+            // redrawing a tile (e.g for the cursor) only blits them
             if tile_rect.intersection(&line_rect).is_some() {
-                draw_rich_slice(dst_fb, &line.chars, line_x0, y - oy);
+                let m = (line.h / 2) as i64;
+                let run_id = ContentId::from_hash(&(self.formatted.get_id(), line_index));
+                let run_size = (line.w + 2 * m as u32, line.h + 2 * m as u32);
+                draw_cached_run(
+                    dst_fb,
+                    run_id,
+                    (line_x0 - m, y - oy - m),
+                    || run_size,
+                    |run_fb| draw_rich_slice(run_fb, &line.chars, m, m),
+                );
             }
 
             y += line.h as i64;
@@ -364,5 +1002,39 @@ impl TileRenderer for TextRenderer {
         if let Some(shadow_cursor) = self.shadow_cursor {
             draw_cursor(shadow_cursor);
         }
+
+        if let Some((c, font, color)) = self.pending {
+            let index = self.prelude_len + self.cursor;
+            let (x, y, h) = self.formatted.as_ref().index_to_xy(index);
+            let y = y + h as i64 - font.char_h as i64;
+            draw_compose_placeholder(dst_fb, c, x - ox, y - oy, font, color, self.bg_color);
+        }
     }
 }
+
+// Underlined, over whatever is at the cursor
+pub(crate) fn draw_compose_placeholder<F: FbViewMut>(
+    fb: &mut F,
+    c: char,
+    x0: i64,
+    y0: i64,
+    font: &Font,
+    color: Color,
+    bg_color: Color,
+) {
+    let cell = Rect {
+        x0,
+        y0,
+        w: font.char_advance(c),
+        h: font.char_h as u32,
+    };
+    draw_rect(fb, &cell, bg_color, false);
+    draw_char(fb, c, x0, y0, font, color, true);
+
+    let underline = Rect {
+        y0: y0 + cell.h as i64 - 1,
+        h: 1,
+        ..cell
+    };
+    draw_rect(fb, &underline, color, false);
+}
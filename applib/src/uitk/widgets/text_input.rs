@@ -0,0 +1,279 @@
+use crate::drawing::primitives::draw_rect;
+use crate::drawing::text::{draw_str, get_font};
+use crate::input::{Keycode, MouseButton};
+use crate::uitk::frame::draw_widget_frame;
+use crate::uitk::widgets::text_box::{draw_compose_placeholder, EditRules};
+use crate::uitk::{TextBoxState, UiContext};
+use crate::{FbViewMut, Rect, WidgetColors, WidgetState};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const CURSOR_W: u32 = 2;
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    // Single-line counterpart of editable_text_box(), for form fields
+    pub fn text_input(
+        &mut self,
+        config: &TextInputConfig,
+        value: &mut String,
+        state: &mut TextInputState,
+    ) -> TextInputEvent {
+        self.clipped(&config.rect, |ui| ui.text_input_inner(config, value, state))
+    }
+
+    fn text_input_inner(
+        &mut self,
+        config: &TextInputConfig,
+        value: &mut String,
+        state: &mut TextInputState,
+    ) -> TextInputEvent {
+        let rect = &config.rect;
+        let focused = self.text_box_focus(rect, &mut state.inner, true);
+
+        // The app may have changed the value since the last frame
+        let inner = &mut state.inner;
+        let value_len = value.chars().count();
+        inner.cursor = usize::min(inner.cursor, value_len);
+        inner.anchor = inner.anchor.map(|anchor| usize::min(anchor, value_len));
+
+        let old_cursor = inner.cursor;
+
+        //
+        // Keyboard
+
+        let mut changed = false;
+        if focused {
+            let accept = |c: char| config.filter.is_none_or(|filter| filter(c));
+            let rules = EditRules {
+                accept: &accept,
+                max_len: config.max_len.unwrap_or(usize::MAX),
+                allow_copy: !config.password,
+                ..Default::default()
+            };
+            changed = self.keyboard_edit(value, &mut state.inner, &rules);
+        }
+
+        let submitted = focused && self.input_state.check_key_pressed(Keycode::KEY_ENTER);
+
+        //
+        // Pointer
+
+        let stylesheet = &self.stylesheet;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.medium);
+        let style = &stylesheet.widgets;
+        let m = (style.border_width + style.padding) as i64;
+
+        let text_rect = Rect {
+            x0: rect.x0 + m,
+            y0: rect.y0,
+            w: rect.w.saturating_sub(2 * m as u32),
+            h: rect.h,
+        };
+
+        let shown: String = match config.password {
+            true => "*".repeat(value.chars().count()),
+            false => value.clone(),
+        };
+        let text_chars = || -> Vec<char> { shown.chars().collect() };
+        let text_len = shown.chars().count();
+        let offsets = font.char_offsets(&shown);
+
+        let p = &self.input_state.pointer;
+        let scroll_x = state.scroll_x;
+        // Closest character boundary
+        let index_at = |x: i64| -> usize {
+            let x = x - text_rect.x0 + scroll_x;
+            let after = offsets.partition_point(|&offset| offset < x);
+            match after {
+                0 => 0,
+                i if i > text_len => text_len,
+                i if x - offsets[i - 1] < offsets[i] - x => i - 1,
+                i => i,
+            }
+        };
+
+        let inner = &mut state.inner;
+        if rect.check_contains_point(p.x, p.y) && p.left_click_trigger {
+            let clicks = self.input_state.click_count(MouseButton::Left).unwrap_or(1);
+            inner.click_select(index_at(p.x), clicks, self.input_state.shift, text_chars);
+        }
+        inner.drag_select(p, text_len, || index_at(p.x));
+
+        self.selection_shortcuts(&mut state.inner, text_len, !config.password, text_chars);
+
+        let inner = &mut state.inner;
+        let cursor_changed = inner.cursor != old_cursor || changed;
+        inner.update_blink(self.time, true, cursor_changed);
+
+        //
+        // Horizontal scrolling, so that the cursor stays in view
+
+        let text_w = offsets[text_len];
+        let view_w = i64::max(0, text_rect.w as i64 - CURSOR_W as i64);
+        let cursor_x = offsets[inner.cursor];
+        let max_scroll = i64::max(0, text_w - view_w);
+        state.scroll_x = state
+            .scroll_x
+            .clamp(cursor_x - view_w, cursor_x)
+            .clamp(0, max_scroll);
+
+        //
+        // Drawing
+
+        let placeholder = self.compose_placeholder(&state.inner);
+        let hovered = rect.check_contains_point(p.x, p.y);
+        let UiContext { fb, stylesheet, .. } = self;
+        let colorsheet = &stylesheet.colors;
+        let style = &stylesheet.widgets;
+
+        let widget_state = match (state.inner.is_focused(), hovered) {
+            (true, _) => WidgetState::Selected,
+            (false, true) => WidgetState::Hover,
+            (false, false) => WidgetState::Normal,
+        };
+        let frame_colors = WidgetColors {
+            bg: colorsheet.editable,
+            ..*style.colors(widget_state)
+        };
+        draw_widget_frame(*fb, rect, style, &frame_colors);
+
+        let inner = &state.inner;
+        let text_y = (text_rect.h as i64 - font.char_h as i64) / 2;
+        let x_of = |index: usize| offsets[index] - state.scroll_x;
+
+        {
+            let mut text_fb = fb.subregion_mut(&text_rect);
+
+            if let Some((start, end)) = inner.selection() {
+                let selection_rect = Rect {
+                    x0: x_of(start),
+                    y0: text_y,
+                    w: (offsets[end] - offsets[start]) as u32,
+                    h: font.char_h as u32,
+                };
+                draw_rect(&mut text_fb, &selection_rect, colorsheet.accent, false);
+            }
+
+            match value.is_empty() && !focused {
+                true => draw_str(
+                    &mut text_fb,
+                    &config.placeholder,
+                    0,
+                    text_y,
+                    font,
+                    colorsheet.disabled,
+                    None,
+                ),
+                false => draw_str(
+                    &mut text_fb,
+                    &shown,
+                    x_of(0),
+                    text_y,
+                    font,
+                    style.normal.text,
+                    None,
+                ),
+            }
+
+            if inner.cursor_visible() {
+                let cursor_rect = Rect {
+                    x0: x_of(inner.cursor),
+                    y0: text_y,
+                    w: CURSOR_W,
+                    h: font.char_h as u32,
+                };
+                draw_rect(&mut text_fb, &cursor_rect, style.normal.text, false);
+            }
+
+            if let Some(c) = placeholder {
+                let (x, color, bg_color) =
+                    (x_of(inner.cursor), style.normal.text, colorsheet.editable);
+                draw_compose_placeholder(&mut text_fb, c, x, text_y, font, color, bg_color);
+            }
+        }
+
+        if focused {
+            self.draw_focus_ring(rect);
+        }
+
+        match (submitted, changed) {
+            (true, _) => TextInputEvent::Submitted,
+            (false, true) => TextInputEvent::Changed,
+            (false, false) => TextInputEvent::None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TextInputConfig {
+    pub rect: Rect,
+    // Shown in place of an empty value, while the input does not have the focus
+    pub placeholder: String,
+    // Draws the value as asterisks, and keeps it out of the clipboard
+    pub password: bool,
+    // In chars
+    pub max_len: Option<usize>,
+    // Typed and pasted characters are dropped unless this returns true
+    pub filter: Option<fn(char) -> bool>,
+}
+
+impl Default for TextInputConfig {
+    fn default() -> Self {
+        TextInputConfig {
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 100,
+                h: 25,
+            },
+            placeholder: String::new(),
+            password: false,
+            max_len: None,
+            filter: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextInputEvent {
+    None,
+    Changed,
+    // Enter was pressed while the input had the focus
+    Submitted,
+}
+
+pub struct TextInputState {
+    // Cursor, selection, undo history and focus work as in text boxes
+    inner: TextBoxState,
+    scroll_x: i64,
+}
+
+impl Default for TextInputState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextInputState {
+    pub fn new() -> Self {
+        TextInputState {
+            inner: TextBoxState::new(),
+            scroll_x: 0,
+        }
+    }
+
+    // Gives the keyboard focus to the input the next time it is drawn
+    pub fn focus(&mut self) {
+        self.inner.focus();
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.inner.is_focused()
+    }
+
+    // Moves the cursor to the end, e.g. after the app sets the value
+    pub fn move_to_end(&mut self, value: &str) {
+        self.inner.cursor = value.chars().count();
+        self.inner.anchor = None;
+    }
+}
@@ -0,0 +1,290 @@
+use crate::content::ContentId;
+use crate::drawing::primitives::draw_rounded_rect;
+use crate::drawing::text::{compute_text_bbox, draw_line_in_rect, get_font, TextJustification};
+use crate::input::InputState;
+use crate::uitk::animation::Easing;
+use crate::uitk::UiContext;
+use crate::{Color, FbViewMut, Framebuffer, Rect};
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const MAX_VISIBLE: usize = 3;
+const TOAST_MARGIN: u32 = 8;
+const TOAST_MAX_W: u32 = 400;
+const SLIDE_IN_TIME: f64 = 150.0; // in ms
+const SLIDE_TIME: f64 = 200.0; // in ms
+const FADE_OUT_TIME: f64 = 300.0; // in ms
+
+// Longer gaps between two frames are not counted, so that toasts wait while the
+// app is paused or hidden
+const MAX_FRAME_GAP: f64 = 100.0; // in ms
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    pub fn push_toast(&mut self, config: ToastConfig) {
+        self.toasts.push(config);
+    }
+
+    pub(crate) fn draw_toasts(&mut self) {
+        let UiContext {
+            fb,
+            stylesheet,
+            toasts,
+            time,
+            animations,
+            ..
+        } = self;
+
+        toasts.advance(*time);
+
+        let colorsheet = &stylesheet.colors;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+        let (fb_w, fb_h) = fb.shape();
+        let m = TOAST_MARGIN;
+
+        // Stacked from the bottom of the window, newest first
+        let mut y1 = fb_h as i64 - m as i64;
+        let mut rects = Vec::with_capacity(toasts.queue.len());
+
+        for toast in toasts.queue.iter_mut().rev() {
+            let (text_w, text_h) = compute_text_bbox(&toast.config.text, font);
+            let button_w = match &toast.config.action {
+                Some((label, _)) => compute_text_bbox(label, font).0 + 3 * m,
+                None => 0,
+            };
+
+            let w = u32::min(text_w + button_w + 2 * m, u32::min(TOAST_MAX_W, fb_w));
+            let h = text_h + 2 * m;
+
+            // New toasts slide in from below, and the others ease toward their slot
+            // when the stack changes. Fading ones give up their slot right away.
+            if !toast.shown {
+                animations.reset(toast.id, (fb_h + m) as f32);
+                toast.shown = true;
+            }
+            let target_y0 = y1 - h as i64;
+            if !toast.is_fading() {
+                y1 -= (h + m) as i64;
+            }
+            let y0 = animations.animate(toast.id, target_y0 as f32, SLIDE_TIME, Easing::EaseOut);
+
+            let rect = Rect {
+                x0: (fb_w as i64 - w as i64) / 2,
+                y0: y0 as i64,
+                w,
+                h,
+            };
+
+            let button_rect = toast.config.action.as_ref().map(|_| Rect {
+                x0: rect.x0 + w.saturating_sub(button_w) as i64 + m as i64,
+                y0: rect.y0,
+                w: button_w - m,
+                h,
+            });
+
+            // Drawn off-screen first, so that the whole toast can fade at once
+            let mut toast_fb = Framebuffer::new_owned(w, h);
+            draw_rounded_rect(
+                &mut toast_fb,
+                &rect.zero_origin(),
+                stylesheet.widgets.border_radius,
+                colorsheet.tooltip,
+                Some((1, colorsheet.outline)),
+            );
+
+            let text_rect = Rect {
+                x0: m as i64,
+                y0: 0,
+                w: w.saturating_sub(button_w + 2 * m),
+                h,
+            };
+            draw_line_in_rect(
+                &mut toast_fb,
+                &toast.config.text,
+                &text_rect,
+                font,
+                colorsheet.text,
+                TextJustification::Left,
+            );
+
+            if let (Some((label, _)), Some(button_rect)) = (&toast.config.action, &button_rect) {
+                let local_rect = Rect {
+                    x0: button_rect.x0 - rect.x0,
+                    y0: 0,
+                    ..button_rect.clone()
+                };
+                draw_line_in_rect(
+                    &mut toast_fb,
+                    label,
+                    &local_rect,
+                    font,
+                    colorsheet.accent,
+                    TextJustification::Center,
+                );
+            }
+
+            let alpha = toast.alpha();
+            if alpha < 1.0 {
+                for pixel in toast_fb.get_data_mut().iter_mut() {
+                    let (r, g, b, a) = pixel.as_rgba();
+                    *pixel = Color::rgba(r, g, b, (a as f64 * alpha) as u8);
+                }
+            }
+
+            fb.copy_from_fb(&toast_fb, (rect.x0, rect.y0), true);
+
+            rects.push(button_rect.zip(toast.config.action.as_ref().map(|(_, id)| *id)));
+        }
+
+        toasts.buttons = rects.into_iter().flatten().collect();
+    }
+}
+
+#[derive(Clone)]
+pub struct ToastConfig {
+    pub text: String,
+    // In ms, not counting the slide in and fade out
+    pub duration: f64,
+    // Label and ID of an optional button, see UiStore::take_toast_action()
+    pub action: Option<(String, ContentId)>,
+}
+
+impl Default for ToastConfig {
+    fn default() -> Self {
+        ToastConfig {
+            text: String::new(),
+            duration: 3000.0,
+            action: None,
+        }
+    }
+}
+
+struct Toast {
+    // For its position animation
+    id: ContentId,
+    config: ToastConfig,
+    shown: bool,
+    // In ms, only counting the frames where the UI was drawn
+    age: f64,
+}
+
+impl Toast {
+    fn fade_start(&self) -> f64 {
+        SLIDE_IN_TIME + self.config.duration
+    }
+
+    fn fade_progress(&self) -> f64 {
+        f64::clamp((self.age - self.fade_start()) / FADE_OUT_TIME, 0.0, 1.0)
+    }
+
+    fn is_fading(&self) -> bool {
+        self.age >= self.fade_start()
+    }
+
+    fn is_done(&self) -> bool {
+        self.age >= self.fade_start() + FADE_OUT_TIME
+    }
+
+    fn fade_out(&mut self) {
+        self.age = f64::max(self.age, self.fade_start());
+    }
+
+    fn alpha(&self) -> f64 {
+        1.0 - self.fade_progress()
+    }
+}
+
+pub(crate) struct ToastState {
+    queue: VecDeque<Toast>,
+    next_id: u64,
+    last_time: Option<f64>,
+    // Where the action buttons were drawn last frame
+    buttons: Vec<(Rect, ContentId)>,
+    clicked_action: Option<ContentId>,
+}
+
+impl ToastState {
+    pub(crate) fn new() -> Self {
+        ToastState {
+            queue: VecDeque::new(),
+            next_id: 0,
+            last_time: None,
+            buttons: Vec::new(),
+            clicked_action: None,
+        }
+    }
+
+    pub(crate) fn push(&mut self, config: ToastConfig) {
+        let id = ContentId::from_hash(&("toast", self.next_id));
+        self.next_id += 1;
+        self.queue.push_back(Toast {
+            id,
+            config,
+            shown: false,
+            age: 0.0,
+        });
+
+        // The oldest ones beyond the limit collapse right away
+        let mut nb_showing = 0;
+        for toast in self.queue.iter_mut().rev() {
+            if toast.is_fading() {
+                continue;
+            }
+            nb_showing += 1;
+            if nb_showing > MAX_VISIBLE {
+                toast.fade_out();
+            }
+        }
+    }
+
+    fn advance(&mut self, time: f64) {
+        let dt = match self.last_time {
+            Some(last_time) if time - last_time <= MAX_FRAME_GAP => f64::max(0.0, time - last_time),
+            _ => 0.0,
+        };
+        self.last_time = Some(time);
+
+        for toast in self.queue.iter_mut() {
+            toast.age += dt;
+        }
+        self.queue.retain(|toast| !toast.is_done());
+    }
+
+    // Returns whether the click landed on an action button, so that the widgets
+    // under it do not get it
+    pub(crate) fn handle_input(&mut self, input_state: &InputState) -> bool {
+        let ps = &input_state.pointer;
+        if !ps.left_click_trigger {
+            return false;
+        }
+
+        let clicked = self
+            .buttons
+            .iter()
+            .find(|(rect, _)| rect.check_contains_point(ps.x, ps.y))
+            .map(|(_, id)| *id);
+
+        let Some(id) = clicked else {
+            return false;
+        };
+
+        self.clicked_action = Some(id);
+        self.buttons.clear();
+        for toast in self.queue.iter_mut() {
+            if toast
+                .config
+                .action
+                .as_ref()
+                .is_some_and(|(_, other)| *other == id)
+            {
+                toast.fade_out();
+            }
+        }
+
+        true
+    }
+
+    pub(crate) fn take_action(&mut self) -> Option<ContentId> {
+        self.clicked_action.take()
+    }
+}
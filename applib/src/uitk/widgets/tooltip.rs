@@ -1,7 +1,9 @@
-use crate::drawing::primitives::draw_rect;
+use crate::content::ContentId;
+use crate::drawing::primitives::{draw_rect, draw_rect_outline};
 use crate::drawing::text::{compute_text_bbox, draw_line_in_rect, get_font, TextJustification};
 use crate::uitk::UiContext;
 use crate::{FbViewMut, Rect};
+use alloc::string::String;
 
 impl<'a, F: FbViewMut> UiContext<'a, F> {
     pub fn tooltip(&mut self, trigger: &Rect, offset: (i64, i64), text: &str) {
@@ -30,3 +32,109 @@ impl<'a, F: FbViewMut> UiContext<'a, F> {
         }
     }
 }
+
+const TOOLTIP_MARGIN: u32 = 4;
+// Where the bubble goes relative to the pointer, so that the cursor does not cover it
+const POINTER_OFFSET: (i64, i64) = (12, 18);
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    // Called by widgets, the tooltip is only shown later in draw_overlay()
+    pub(crate) fn register_tooltip(&mut self, id: ContentId, rect: &Rect, text: &Option<String>) {
+        let ps = &self.input_state.pointer;
+        if let Some(text) = text {
+            if rect.check_contains_point(ps.x, ps.y) {
+                self.tooltip.pending = Some((id, text.clone()));
+            }
+        }
+    }
+
+    pub(crate) fn draw_tooltip(&mut self) {
+        let UiContext {
+            fb,
+            stylesheet,
+            input_state,
+            tooltip,
+            time,
+            ..
+        } = self;
+
+        let ps = &input_state.pointer;
+        let pointer = (ps.x, ps.y);
+        let pointer_moved = pointer != tooltip.last_pointer;
+        tooltip.last_pointer = pointer;
+
+        let Some((id, text)) = tooltip.pending.take() else {
+            tooltip.hovered = None;
+            return;
+        };
+
+        // The hover delay restarts whenever the pointer moves
+        if tooltip.hovered != Some(id) || pointer_moved {
+            tooltip.hovered = Some(id);
+            tooltip.hover_start = *time;
+            tooltip.dismissed = false;
+        }
+
+        if ps.left_click_trigger || ps.right_click_trigger {
+            tooltip.dismissed = true;
+        }
+
+        if tooltip.dismissed || *time - tooltip.hover_start < stylesheet.tooltip_delay as f64 {
+            return;
+        }
+
+        let colorsheet = &stylesheet.colors;
+        let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+
+        let (text_w, text_h) = compute_text_bbox(&text, font);
+        let (fb_w, fb_h) = fb.shape();
+        let (dx, dy) = POINTER_OFFSET;
+
+        let w = u32::min(text_w + 2 * TOOLTIP_MARGIN, fb_w);
+        let h = u32::min(text_h + 2 * TOOLTIP_MARGIN, fb_h);
+
+        // Kept inside the window, flipping above the pointer near the bottom edge
+        let x0 = i64::min(ps.x + dx, (fb_w - w) as i64).max(0);
+        let y0 = match ps.y + dy + h as i64 > fb_h as i64 {
+            true => ps.y - dy - h as i64,
+            false => ps.y + dy,
+        }
+        .clamp(0, (fb_h - h) as i64);
+
+        let rect = Rect { x0, y0, w, h };
+
+        draw_rect(*fb, &rect, colorsheet.tooltip, false);
+        draw_rect_outline(*fb, &rect, colorsheet.outline, false, 1);
+        draw_line_in_rect(
+            *fb,
+            &text,
+            &rect,
+            font,
+            colorsheet.text,
+            TextJustification::Center,
+        );
+    }
+}
+
+// Hover tracking for the widget tooltips
+pub(crate) struct TooltipState {
+    // Registered by the widget under the pointer during the current frame
+    pending: Option<(ContentId, String)>,
+    hovered: Option<ContentId>,
+    hover_start: f64,
+    last_pointer: (i64, i64),
+    // Clicking hides the tooltip until the pointer leaves the widget or moves
+    dismissed: bool,
+}
+
+impl TooltipState {
+    pub(crate) fn new() -> Self {
+        TooltipState {
+            pending: None,
+            hovered: None,
+            hover_start: 0.0,
+            last_pointer: (0, 0),
+            dismissed: false,
+        }
+    }
+}
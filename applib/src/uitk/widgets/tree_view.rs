@@ -0,0 +1,428 @@
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::content::ContentId;
+use crate::drawing::primitives::draw_triangle;
+use crate::drawing::text::{draw_line_in_rect, get_font, Font, TextJustification};
+use crate::geometry::{Point2D, Triangle2D};
+use crate::input::{InputEvent, Keycode, MouseButton};
+use crate::uitk::{TileRenderer, UiContext};
+use crate::{Color, FbViewMut, Rect};
+
+const INDENT_W: u32 = 14;
+const EXPANDER_SIZE: i64 = 8;
+
+// How the tree view walks the app's data. Children are only queried for expanded
+// nodes, so they can be loaded lazily.
+pub trait TreeAdapter {
+    type Node: Clone;
+
+    // Must be unique and stable across frames, expansion and selection are tied to it
+    fn id(&self, node: &Self::Node) -> ContentId;
+    fn roots(&self) -> impl Iterator<Item = Self::Node>;
+    fn children(&self, node: &Self::Node) -> impl Iterator<Item = Self::Node>;
+    fn label(&self, node: &Self::Node) -> String;
+
+    // Whether to draw an expander, without having to query the children
+    fn has_children(&self, _node: &Self::Node) -> bool {
+        true
+    }
+}
+
+impl<'a, F: FbViewMut> UiContext<'a, F> {
+    pub fn tree_view<A: TreeAdapter>(
+        &mut self,
+        config: &TreeViewConfig,
+        adapter: &A,
+        state: &mut TreeViewState,
+    ) -> TreeViewEvent<A::Node> {
+        self.clipped(&config.rect, |ui| {
+            ui.tree_view_inner(config, adapter, state)
+        })
+    }
+
+    fn tree_view_inner<A: TreeAdapter>(
+        &mut self,
+        config: &TreeViewConfig,
+        adapter: &A,
+        state: &mut TreeViewState,
+    ) -> TreeViewEvent<A::Node> {
+        let focused = self.focusable(config.id, &config.rect, true);
+        state.focused = focused;
+
+        let rect = &config.rect;
+        let row_h = config.row_h;
+        let mut event = TreeViewEvent::None;
+
+        let rows = flatten(adapter, &state.expanded);
+
+        //
+        // Pointer
+
+        // Clicks are matched against the rows as they were drawn last frame
+        let ps = &self.input_state.pointer;
+        let (_, scroll_y) = state.offsets;
+        let scrolling = state.dragging.0 || state.dragging.1;
+        let clicked_index = match ps.left_click_trigger && !scrolling {
+            true if rect.check_contains_point(ps.x, ps.y) => {
+                let i = ((ps.y - rect.y0 + scroll_y) / row_h as i64) as usize;
+                (i < rows.len()).then_some(i)
+            }
+            _ => None,
+        };
+
+        if let Some(i) = clicked_index {
+            let row = &rows[i];
+            let expander_x0 = rect.x0 + (row.depth as u32 * INDENT_W) as i64;
+            let on_expander =
+                row.expandable && (expander_x0..expander_x0 + INDENT_W as i64).contains(&ps.x);
+
+            // The expander toggles the node without changing the selection
+            if on_expander {
+                state.set_expanded(row.id, !row.expanded);
+            } else {
+                let is_double_click = self
+                    .input_state
+                    .click_count(MouseButton::Left)
+                    .is_some_and(|clicks| clicks >= 2)
+                    && state.last_click == Some(row.id);
+                state.last_click = Some(row.id);
+                state.selected = Some(row.id);
+                event = match is_double_click {
+                    true => {
+                        state.last_click = None;
+                        TreeViewEvent::Activated(row.node.clone())
+                    }
+                    false => TreeViewEvent::Selected(row.node.clone()),
+                };
+            }
+        }
+
+        //
+        // Keyboard
+
+        let mut moved = false;
+        if focused && !rows.is_empty() {
+            for input_event in self.input_state.events.iter() {
+                let Some(InputEvent::KeyPress { keycode }) = input_event else {
+                    continue;
+                };
+
+                let current = state
+                    .selected
+                    .and_then(|id| rows.iter().position(|row| row.id == id));
+
+                // Arrows move within the rows as they were at the start of the frame
+                let new_index = match (keycode, current) {
+                    (Keycode::KEY_UP | Keycode::KEY_DOWN, None) => Some(0),
+                    (Keycode::KEY_UP, Some(i)) => Some(i.saturating_sub(1)),
+                    (Keycode::KEY_DOWN, Some(i)) => Some(usize::min(i + 1, rows.len() - 1)),
+                    (Keycode::KEY_RIGHT, Some(i)) => {
+                        let row = &rows[i];
+                        match (row.expandable, state.is_expanded(row.id)) {
+                            (true, false) => {
+                                state.set_expanded(row.id, true);
+                                None
+                            }
+                            _ => rows
+                                .get(i + 1)
+                                .filter(|next| next.depth > row.depth && row.expanded)
+                                .map(|_| i + 1),
+                        }
+                    }
+                    (Keycode::KEY_LEFT, Some(i)) => {
+                        let row = &rows[i];
+                        match state.is_expanded(row.id) {
+                            true => {
+                                state.set_expanded(row.id, false);
+                                None
+                            }
+                            false => row.parent,
+                        }
+                    }
+                    (Keycode::KEY_ENTER, Some(i)) => {
+                        event = TreeViewEvent::Activated(rows[i].node.clone());
+                        None
+                    }
+                    _ => None,
+                };
+
+                if let Some(i) = new_index {
+                    if state.selected != Some(rows[i].id) {
+                        state.selected = Some(rows[i].id);
+                        event = TreeViewEvent::Selected(rows[i].node.clone());
+                    }
+                    moved = true;
+                }
+            }
+        }
+
+        //
+        // Drawing
+
+        // Expanding or collapsing changes the rows
+        let rows = flatten(adapter, &state.expanded);
+
+        if moved {
+            let selected_index = state
+                .selected
+                .and_then(|id| rows.iter().position(|row| row.id == id));
+            if let Some(i) = selected_index {
+                let (_, scroll_y) = &mut state.offsets;
+                let row_y0 = (i as u32 * row_h) as i64;
+                let row_y1 = row_y0 + row_h as i64;
+                if row_y0 < *scroll_y {
+                    *scroll_y = row_y0;
+                } else if row_y1 > *scroll_y + rect.h as i64 {
+                    *scroll_y = row_y1 - rect.h as i64;
+                }
+            }
+        }
+
+        let stylesheet = &self.stylesheet;
+        let renderer = TreeRenderer {
+            w: rect.w,
+            row_h,
+            rows: &rows,
+            adapter,
+            selected: state.selected,
+            font: get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small),
+            colors: TreeColors {
+                bg: stylesheet.colors.element,
+                selected: stylesheet.colors.accent,
+                text: stylesheet.colors.text,
+            },
+        };
+
+        self.dynamic_canvas(rect, &renderer, &mut state.offsets, &mut state.dragging);
+
+        if focused {
+            self.draw_focus_ring(rect);
+        }
+
+        event
+    }
+}
+
+#[derive(Clone)]
+pub struct TreeViewConfig {
+    // Must be unique and stable across frames, the keyboard focus is tied to it
+    pub id: ContentId,
+    pub rect: Rect,
+    pub row_h: u32,
+}
+
+impl Default for TreeViewConfig {
+    fn default() -> Self {
+        TreeViewConfig {
+            id: ContentId(0),
+            rect: Rect {
+                x0: 0,
+                y0: 0,
+                w: 200,
+                h: 300,
+            },
+            row_h: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeViewEvent<N> {
+    None,
+    // By a click or the arrow keys
+    Selected(N),
+    // By a double click or Enter
+    Activated(N),
+}
+
+pub struct TreeViewState {
+    expanded: BTreeSet<ContentId>,
+    pub selected: Option<ContentId>,
+    offsets: (i64, i64),
+    dragging: (bool, bool),
+    last_click: Option<ContentId>,
+    focused: bool,
+}
+
+impl Default for TreeViewState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TreeViewState {
+    pub fn new() -> Self {
+        TreeViewState {
+            expanded: BTreeSet::new(),
+            selected: None,
+            offsets: (0, 0),
+            dragging: (false, false),
+            last_click: None,
+            focused: false,
+        }
+    }
+
+    // As of the last time the tree was drawn
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn is_expanded(&self, id: ContentId) -> bool {
+        self.expanded.contains(&id)
+    }
+
+    pub fn set_expanded(&mut self, id: ContentId, expanded: bool) {
+        match expanded {
+            true => self.expanded.insert(id),
+            false => self.expanded.remove(&id),
+        };
+    }
+}
+
+struct Row<N> {
+    node: N,
+    id: ContentId,
+    depth: usize,
+    parent: Option<usize>,
+    expandable: bool,
+    expanded: bool,
+}
+
+// The visible rows, in drawing order. Collapsed subtrees are never walked.
+fn flatten<A: TreeAdapter>(adapter: &A, expanded: &BTreeSet<ContentId>) -> Vec<Row<A::Node>> {
+    let mut rows = Vec::new();
+    for root in adapter.roots() {
+        push_rows(adapter, expanded, root, 0, None, &mut rows);
+    }
+    rows
+}
+
+fn push_rows<A: TreeAdapter>(
+    adapter: &A,
+    expanded: &BTreeSet<ContentId>,
+    node: A::Node,
+    depth: usize,
+    parent: Option<usize>,
+    rows: &mut Vec<Row<A::Node>>,
+) {
+    let id = adapter.id(&node);
+    let expandable = adapter.has_children(&node);
+    let is_expanded = expandable && expanded.contains(&id);
+
+    let index = rows.len();
+    let children: Vec<A::Node> = match is_expanded {
+        true => adapter.children(&node).collect(),
+        false => Vec::new(),
+    };
+
+    rows.push(Row {
+        node,
+        id,
+        depth,
+        parent,
+        expandable,
+        expanded: is_expanded,
+    });
+
+    for child in children {
+        push_rows(adapter, expanded, child, depth + 1, Some(index), rows);
+    }
+}
+
+#[derive(Clone, Copy, Hash)]
+struct TreeColors {
+    bg: Color,
+    selected: Color,
+    text: Color,
+}
+
+// One tile per row, so that only the visible labels are queried and rendered
+struct TreeRenderer<'a, A: TreeAdapter> {
+    w: u32,
+    row_h: u32,
+    rows: &'a [Row<A::Node>],
+    adapter: &'a A,
+    selected: Option<ContentId>,
+    font: &'static Font,
+    colors: TreeColors,
+}
+
+impl<'a, A: TreeAdapter> TreeRenderer<'a, A> {
+    fn row_index(&self, viewport_rect: &Rect) -> usize {
+        viewport_rect.y0 as usize / self.row_h as usize
+    }
+}
+
+impl<'a, A: TreeAdapter> TileRenderer for TreeRenderer<'a, A> {
+    fn shape(&self) -> (u32, u32) {
+        (self.w, self.rows.len() as u32 * self.row_h)
+    }
+
+    fn tile_shape(&self) -> (u32, u32) {
+        (self.w, self.row_h)
+    }
+
+    fn content_id(&self, viewport_rect: &Rect) -> ContentId {
+        let row = self.rows.get(self.row_index(viewport_rect)).map(|row| {
+            (
+                row.id,
+                row.depth,
+                row.expandable,
+                row.expanded,
+                self.selected == Some(row.id),
+                self.adapter.label(&row.node),
+            )
+        });
+
+        ContentId::from_hash(&(self.w, self.row_h, row, self.colors))
+    }
+
+    fn render<F: FbViewMut>(&self, dst_fb: &mut F, viewport_rect: &Rect) {
+        let Some(row) = self.rows.get(self.row_index(viewport_rect)) else {
+            dst_fb.fill(self.colors.bg);
+            return;
+        };
+
+        let bg = match self.selected == Some(row.id) {
+            true => self.colors.selected,
+            false => self.colors.bg,
+        };
+        dst_fb.fill(bg);
+
+        let indent = row.depth as u32 * INDENT_W;
+
+        if row.expandable {
+            let xc = (indent + INDENT_W / 2) as i64;
+            let yc = (self.row_h / 2) as i64;
+            let s = EXPANDER_SIZE / 2;
+
+            // Pointing right when collapsed, down when expanded
+            let points = match row.expanded {
+                false => [(xc - s / 2, yc - s), (xc + s / 2, yc), (xc - s / 2, yc + s)],
+                true => [(xc - s, yc - s / 2), (xc + s, yc - s / 2), (xc, yc + s / 2)],
+            };
+            let tri = Triangle2D {
+                points: points.map(|(x, y)| Point2D { x, y }),
+            };
+            draw_triangle(dst_fb, &tri, self.colors.text, false);
+        }
+
+        let text_rect = Rect {
+            x0: (indent + INDENT_W) as i64,
+            y0: 0,
+            w: self.w.saturating_sub(indent + INDENT_W),
+            h: self.row_h,
+        };
+
+        draw_line_in_rect(
+            dst_fb,
+            &self.adapter.label(&row.node),
+            &text_rect,
+            self.font,
+            self.colors.text,
+            TextJustification::Left,
+        );
+    }
+}
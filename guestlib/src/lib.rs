@@ -2,10 +2,15 @@
 extern crate alloc;
 
 use alloc::format;
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
-use applib::StyleSheet;
-use applib::{input::InputState, BorrowedMutPixels, Color, Framebuffer, Rect};
+use applib::damage::DamageList;
+use applib::input::{InputState, INPUT_STATE_ABI_VERSION};
+use applib::stats::{AppStatsEntry, NetworkInterfaceEntry, SystemStatsEntry, TimeSyncEntry};
+use applib::uitk::{Clipboard, CursorHint};
+use applib::{BorrowedMutPixels, Color, Framebuffer, Rect};
+use applib::{StyleSheet, STYLESHEET_ABI_VERSION};
 use core::fmt::Debug;
 use core::mem::size_of;
 use log::{Log, Metadata, Record};
@@ -16,7 +21,7 @@ static ALLOC: dlmalloc::GlobalDlmalloc = dlmalloc::GlobalDlmalloc;
 extern "C" {
 
     fn host_log(addr: i32, len: i32, level: i32);
-    fn host_get_input_state(addr: i32);
+    fn host_get_input_state(addr: i32, len: i32, version: i32) -> i32;
     fn host_get_win_rect(addr: i32);
     fn host_set_framebuffer(addr: i32, w: i32, h: i32);
 
@@ -27,7 +32,33 @@ extern "C" {
     fn host_tcp_read(addr: i32, len: i32, handle_id: i32) -> i32;
     fn host_tcp_close(handle_id: i32);
     fn host_get_time(buf: i32);
-    fn host_get_stylesheet(buf: i32);
+    fn host_get_stylesheet(buf: i32, len: i32, version: i32) -> i32;
+
+    fn host_storage_list(addr: i32, len: i32) -> i32;
+    fn host_storage_read(name_addr: i32, name_len: i32, addr: i32, len: i32) -> i32;
+    fn host_storage_write(name_addr: i32, name_len: i32, addr: i32, len: i32) -> i32;
+    fn host_storage_delete(name_addr: i32, name_len: i32) -> i32;
+    fn host_storage_stat(name_addr: i32, name_len: i32, addr: i32) -> i32;
+
+    fn host_request_focus();
+    fn host_notify(addr: i32, len: i32);
+
+    fn host_clipboard_set(addr: i32, len: i32);
+    fn host_clipboard_get(addr: i32, len: i32) -> i32;
+    fn host_set_cursor_hint(hint: i32);
+    fn host_set_damage(addr: i32, nb_rects: i32);
+
+    fn host_get_stats(system_addr: i32, apps_addr: i32, max_apps: i32) -> i32;
+    fn host_get_network_status(addr: i32, max_entries: i32, sync_addr: i32) -> i32;
+    fn host_get_timings(name_addr: i32, name_len: i32, addr: i32, len: i32) -> i32;
+    fn host_kernel_log_read(position_addr: i32, addr: i32, len: i32) -> i32;
+    fn host_close_app(name_addr: i32, name_len: i32) -> i32;
+    fn host_close();
+    fn host_set_close_guard(enabled: i32);
+    fn host_take_close_request() -> i32;
+
+    fn host_open_with(name_addr: i32, name_len: i32, path_addr: i32, path_len: i32) -> i32;
+    fn host_take_open_request(addr: i32, len: i32) -> i32;
 
     fn host_get_consumed_fuel(addr: i32);
     fn host_save_timing(key_addr: i32, key_len: i32, consumed_addr: i32);
@@ -70,10 +101,15 @@ impl FramebufferHandle {
 pub fn get_input_state() -> InputState {
     let mut buf = [0u8; size_of::<InputState>()];
     let addr = buf.as_mut_ptr() as i32;
-    unsafe {
-        host_get_input_state(addr);
-        core::mem::transmute(buf)
+    let len = buf.len() as i32;
+    let retval = unsafe { host_get_input_state(addr, len, INPUT_STATE_ABI_VERSION as i32) };
+    if retval < 0 {
+        panic!(
+            "Input state ABI mismatch (app built with version {}), the app must be rebuilt",
+            INPUT_STATE_ABI_VERSION
+        );
     }
+    unsafe { core::mem::transmute(buf) }
 }
 
 pub fn get_win_rect() -> Rect {
@@ -190,9 +226,384 @@ pub fn get_time() -> f64 {
 pub fn get_stylesheet() -> StyleSheet {
     let mut buf = [0u8; size_of::<StyleSheet>()];
     let addr = buf.as_mut_ptr() as i32;
-    unsafe {
-        host_get_stylesheet(addr);
-        core::mem::transmute(buf)
+    let len = buf.len() as i32;
+    let retval = unsafe { host_get_stylesheet(addr, len, STYLESHEET_ABI_VERSION as i32) };
+    if retval < 0 {
+        panic!(
+            "Stylesheet ABI mismatch (app built with version {}), the app must be rebuilt",
+            STYLESHEET_ABI_VERSION
+        );
+    }
+    unsafe { core::mem::transmute(buf) }
+}
+
+fn storage_error(retval: i32) -> anyhow::Error {
+    match retval {
+        -1 => anyhow::Error::msg("File not found"),
+        -2 => anyhow::Error::msg("Invalid file name"),
+        -3 => anyhow::Error::msg("Storage quota exceeded"),
+        -4 => anyhow::Error::msg("Disk error"),
+        _ => anyhow::Error::msg("Storage error"),
+    }
+}
+
+pub fn storage_list() -> anyhow::Result<Vec<String>> {
+    let mut buf = vec![0u8; 1024];
+
+    loop {
+        let retval = unsafe { host_storage_list(buf.as_mut_ptr() as i32, buf.len() as i32) };
+
+        if retval < 0 {
+            return Err(storage_error(retval));
+        }
+
+        let listing_len = retval as usize;
+        if listing_len > buf.len() {
+            buf.resize(listing_len, 0);
+            continue;
+        }
+
+        let listing = core::str::from_utf8(&buf[..listing_len]).map_err(anyhow::Error::msg)?;
+        let names = listing
+            .split('\n')
+            .filter(|name| !name.is_empty())
+            .map(|name| name.into())
+            .collect();
+
+        return Ok(names);
+    }
+}
+
+pub fn storage_read(name: &str) -> anyhow::Result<Vec<u8>> {
+    let name_buf = name.as_bytes();
+    let mut buf = vec![0u8; 4096];
+
+    loop {
+        let retval = unsafe {
+            host_storage_read(
+                name_buf.as_ptr() as i32,
+                name_buf.len() as i32,
+                buf.as_mut_ptr() as i32,
+                buf.len() as i32,
+            )
+        };
+
+        if retval < 0 {
+            return Err(storage_error(retval));
+        }
+
+        let data_len = retval as usize;
+        if data_len > buf.len() {
+            buf.resize(data_len, 0);
+            continue;
+        }
+
+        buf.truncate(data_len);
+        return Ok(buf);
+    }
+}
+
+pub fn storage_write(name: &str, data: &[u8]) -> anyhow::Result<()> {
+    let name_buf = name.as_bytes();
+    let retval = unsafe {
+        host_storage_write(
+            name_buf.as_ptr() as i32,
+            name_buf.len() as i32,
+            data.as_ptr() as i32,
+            data.len() as i32,
+        )
+    };
+
+    if retval < 0 {
+        Err(storage_error(retval))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn storage_delete(name: &str) -> anyhow::Result<()> {
+    let name_buf = name.as_bytes();
+    let retval = unsafe { host_storage_delete(name_buf.as_ptr() as i32, name_buf.len() as i32) };
+
+    if retval < 0 {
+        Err(storage_error(retval))
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileInfo {
+    pub size: usize,
+    pub modified: f64, // UNIX time in ms
+}
+
+pub fn storage_stat(name: &str) -> anyhow::Result<FileInfo> {
+    let name_buf = name.as_bytes();
+    let mut buf = [0u8; 16];
+    let retval = unsafe {
+        host_storage_stat(
+            name_buf.as_ptr() as i32,
+            name_buf.len() as i32,
+            buf.as_mut_ptr() as i32,
+        )
+    };
+
+    if retval < 0 {
+        return Err(storage_error(retval));
+    }
+
+    let (size_buf, modified_buf) = buf.split_at(8);
+
+    Ok(FileInfo {
+        size: u64::from_le_bytes(size_buf.try_into().unwrap()) as usize,
+        modified: f64::from_le_bytes(modified_buf.try_into().unwrap()),
+    })
+}
+
+/// Asks the window manager to bring the app window to the foreground
+pub fn request_focus() {
+    unsafe { host_request_focus() };
+}
+
+/// Shows a short message on the app window, if it is in the background
+pub fn notify(text: &str) {
+    unsafe { host_notify(text.as_ptr() as i32, text.len() as i32) };
+}
+
+/// Changes how the pointer looks over the app window, for the current frame only
+pub fn set_cursor_hint(hint: CursorHint) {
+    unsafe { host_set_cursor_hint(hint as i32) };
+}
+
+/// Parts of the framebuffer which changed, for the current frame only. Without it, the whole
+/// window is assumed to have changed.
+pub fn set_damage(damage: &DamageList) {
+    let data: Vec<i32> = damage
+        .rects()
+        .iter()
+        .flat_map(|r| [r.x0 as i32, r.y0 as i32, r.w as i32, r.h as i32])
+        .collect();
+    unsafe { host_set_damage(data.as_ptr() as i32, (data.len() / 4) as i32) };
+}
+
+pub fn clipboard_set(text: &str) {
+    unsafe { host_clipboard_set(text.as_ptr() as i32, text.len() as i32) };
+}
+
+pub fn clipboard_get() -> String {
+    let mut buf = vec![0u8; 1024];
+
+    loop {
+        let text_len =
+            unsafe { host_clipboard_get(buf.as_mut_ptr() as i32, buf.len() as i32) } as usize;
+
+        if text_len > buf.len() {
+            buf.resize(text_len, 0);
+            continue;
+        }
+
+        buf.truncate(text_len);
+        return String::from_utf8(buf).unwrap_or_default();
+    }
+}
+
+/// Plugs the system clipboard into the text widgets, see UiStore::set_clipboard()
+pub struct HostClipboard;
+
+impl Clipboard for HostClipboard {
+    fn get(&mut self) -> Option<String> {
+        let text = clipboard_get();
+        match text.is_empty() {
+            true => None,
+            false => Some(text),
+        }
+    }
+
+    fn set(&mut self, text: &str) {
+        clipboard_set(text);
+    }
+}
+
+/// Returns the stats of the previous frame, for the whole system and for each app
+pub fn get_system_stats() -> (SystemStatsEntry, Vec<AppStatsEntry>) {
+    let mut system_entry = SystemStatsEntry::default();
+    let mut app_entries = vec![AppStatsEntry::new("", false); 16];
+
+    loop {
+        let nb_apps = unsafe {
+            host_get_stats(
+                &mut system_entry as *mut SystemStatsEntry as i32,
+                app_entries.as_mut_ptr() as i32,
+                app_entries.len() as i32,
+            )
+        } as usize;
+
+        if nb_apps > app_entries.len() {
+            app_entries.resize(nb_apps, AppStatsEntry::new("", false));
+            continue;
+        }
+
+        app_entries.truncate(nb_apps);
+        return (system_entry, app_entries);
+    }
+}
+
+/// Returns every network interface, the index of an entry is the id of its interface, and
+/// how the wall clock is synced to the time server
+pub fn get_network_status() -> (Vec<NetworkInterfaceEntry>, TimeSyncEntry) {
+    let mut entries = vec![NetworkInterfaceEntry::new(false, false); 4];
+    let mut sync_entry = TimeSyncEntry::default();
+
+    loop {
+        let nb_entries = unsafe {
+            host_get_network_status(
+                entries.as_mut_ptr() as i32,
+                entries.len() as i32,
+                &mut sync_entry as *mut TimeSyncEntry as i32,
+            )
+        } as usize;
+
+        if nb_entries > entries.len() {
+            entries.resize(nb_entries, NetworkInterfaceEntry::new(false, false));
+            continue;
+        }
+
+        entries.truncate(nb_entries);
+        return (entries, sync_entry);
+    }
+}
+
+/// Returns the fuel consumed per timing key during the last step of an app
+pub fn get_timings(app_name: &str) -> anyhow::Result<Vec<(String, u64)>> {
+    let name_buf = app_name.as_bytes();
+    let mut buf = vec![0u8; 1024];
+
+    loop {
+        let retval = unsafe {
+            host_get_timings(
+                name_buf.as_ptr() as i32,
+                name_buf.len() as i32,
+                buf.as_mut_ptr() as i32,
+                buf.len() as i32,
+            )
+        };
+
+        if retval < 0 {
+            return Err(anyhow::Error::msg(format!("Unknown app {}", app_name)));
+        }
+
+        let listing_len = retval as usize;
+        if listing_len > buf.len() {
+            buf.resize(listing_len, 0);
+            continue;
+        }
+
+        let listing = core::str::from_utf8(&buf[..listing_len]).map_err(anyhow::Error::msg)?;
+        let timings = listing
+            .split('\n')
+            .filter_map(|line| {
+                let (key, consumed) = line.rsplit_once(' ')?;
+                Some((key.into(), consumed.parse().ok()?))
+            })
+            .collect();
+
+        return Ok(timings);
+    }
+}
+
+/// Returns the kernel log lines from `position` on, and moves it past them. Starting from 0
+/// gives all the lines the kernel still keeps.
+pub fn kernel_log_read(position: &mut u64) -> String {
+    let mut text = String::new();
+    let mut buf = vec![0u8; 4096];
+
+    loop {
+        let mut position_buf = position.to_le_bytes();
+        let read_len = unsafe {
+            host_kernel_log_read(
+                position_buf.as_mut_ptr() as i32,
+                buf.as_mut_ptr() as i32,
+                buf.len() as i32,
+            )
+        };
+        *position = u64::from_le_bytes(position_buf);
+
+        if read_len <= 0 {
+            return text;
+        }
+
+        text.push_str(&String::from_utf8_lossy(&buf[..read_len as usize]));
+    }
+}
+
+/// Closes another app (or this one) and discards its state
+pub fn close_app(app_name: &str) -> anyhow::Result<()> {
+    let name_buf = app_name.as_bytes();
+    let retval = unsafe { host_close_app(name_buf.as_ptr() as i32, name_buf.len() as i32) };
+
+    if retval < 0 {
+        Err(anyhow::Error::msg(format!("Unknown app {}", app_name)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Closes this app and discards its state
+pub fn close() {
+    unsafe { host_close() }
+}
+
+/// While enabled, closing the window from the desktop does not close the app, it only
+/// makes `take_close_request` return true. The app then calls `close` if it agrees.
+pub fn set_close_guard(enabled: bool) {
+    unsafe { host_set_close_guard(enabled as i32) }
+}
+
+/// True once for each time the window was asked to close while guarded
+pub fn take_close_request() -> bool {
+    unsafe { host_take_close_request() != 0 }
+}
+
+/// Hands a storage path over to another app, which is opened and brought to the foreground
+pub fn open_with(app_name: &str, path: &str) -> anyhow::Result<()> {
+    let name_buf = app_name.as_bytes();
+    let path_buf = path.as_bytes();
+    let retval = unsafe {
+        host_open_with(
+            name_buf.as_ptr() as i32,
+            name_buf.len() as i32,
+            path_buf.as_ptr() as i32,
+            path_buf.len() as i32,
+        )
+    };
+
+    if retval < 0 {
+        Err(anyhow::Error::msg(format!("Unknown app {}", app_name)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the next path sent to this app with `open_with`, if any
+pub fn take_open_request() -> Option<String> {
+    let mut buf = vec![0u8; 256];
+
+    loop {
+        let retval = unsafe { host_take_open_request(buf.as_mut_ptr() as i32, buf.len() as i32) };
+
+        if retval < 0 {
+            return None;
+        }
+
+        let path_len = retval as usize;
+        if path_len > buf.len() {
+            buf.resize(path_len, 0);
+            continue;
+        }
+
+        buf.truncate(path_len);
+        return String::from_utf8(buf).ok();
     }
 }
 
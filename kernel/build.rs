@@ -0,0 +1,30 @@
+use applib::Framebuffer;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The wallpaper and icons are embedded as QOI, which decodes several times faster than
+// PNG. They are kept as PNG in the repo and converted here.
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    let icons_dir = Path::new("../icons/png");
+    println!("cargo:rerun-if-changed={}", icons_dir.display());
+
+    let mut png_paths = vec![PathBuf::from("../wallpaper.png")];
+    for entry in fs::read_dir(icons_dir).expect("Cannot read the icons directory") {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "png") {
+            png_paths.push(path);
+        }
+    }
+
+    for png_path in png_paths {
+        println!("cargo:rerun-if-changed={}", png_path.display());
+        let png = fs::read(&png_path).expect("Cannot read PNG asset");
+        let fb = Framebuffer::from_png(&png);
+        let name = png_path.file_stem().unwrap().to_str().unwrap();
+        fs::write(out_dir.join(format!("{}.qoi", name)), fb.to_qoi())
+            .expect("Cannot write QOI asset");
+    }
+}
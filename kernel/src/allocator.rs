@@ -3,15 +3,44 @@ use core::cell::UnsafeCell;
 
 use x86_64::VirtAddr;
 
-const NB_BLOCK_SIZES: usize = 28;
+// Blocks are 2^i bytes, for i below that
+pub const NB_BLOCK_SIZES: usize = 28;
+
+// Slot 0 counts the allocations made without a tag
+pub const MAX_ALLOC_TAGS: usize = 64;
+
+// Regions the heap can grow into, smaller ones are left out
+pub const MAX_HEAP_REGIONS: usize = 64;
+
+// The heap starts with one chunk, and grows by one when it is full. Larger blocks take
+// a larger chunk.
+const HEAP_CHUNK_SIZE: usize = 32 * 1024 * 1024;
 
 pub struct SimpleAllocator {
     pub heap: UnsafeCell<Option<SimpleHeap>>,
 }
 
+// Mapped memory the heap may use
+#[derive(Debug, Clone, Copy)]
+pub struct HeapRegion {
+    pub start: VirtAddr,
+    pub size: usize,
+}
+
+impl HeapRegion {
+    pub const EMPTY: HeapRegion = HeapRegion {
+        start: VirtAddr::zero(),
+        size: 0,
+    };
+}
+
 pub struct SimpleHeap {
-    start: *mut u8,
+    // New blocks are carved out of the current chunk, from ptr to end
     ptr: *mut u8,
+    end: *mut u8,
+
+    // What is left of the regions, chunks are taken from their start
+    reserve: [HeapRegion; MAX_HEAP_REGIONS],
 
     // This represents a matrix
     // <nb of possible block sizes> x <nb of possible block alignments>
@@ -20,15 +49,50 @@ pub struct SimpleHeap {
     trackers: [Option<*mut u8>; NB_BLOCK_SIZES * NB_BLOCK_SIZES],
 
     stats: AllocStats,
+
+    // Allocations and deallocations are counted for this tag until it changes
+    tag: usize,
+    tag_stats: [TagStats; MAX_ALLOC_TAGS],
 }
 
 #[derive(Debug, Clone)]
 pub struct AllocStats {
+    // Added to the heap so far
     pub total: usize,
+    // Not added yet, the heap can grow into it
+    pub reserve: usize,
+    pub nb_grows: usize,
     pub explored: usize,
     pub allocated: usize,
     pub lost: usize,
     pub reclaimable: usize,
+    // Highest value of `allocated` since boot
+    pub peak: usize,
+    // Blocks currently allocated
+    pub nb_allocs: usize,
+    // Blocks of 2^i bytes currently allocated, and waiting to be reclaimed
+    pub size_classes: [usize; NB_BLOCK_SIZES],
+    pub free_size_classes: [usize; NB_BLOCK_SIZES],
+}
+
+impl AllocStats {
+    // Part of the explored heap not holding live data, between 0 and 1. Freed blocks are
+    // only reused for the same size, so it grows when the size mix changes.
+    pub fn fragmentation(&self) -> f64 {
+        match self.explored {
+            0 => 0.0,
+            explored => explored.saturating_sub(self.allocated) as f64 / explored as f64,
+        }
+    }
+}
+
+// Bytes allocated and freed while a tag was set. Blocks freed under another tag than
+// the one they were allocated with are not matched, so the difference is approximate.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TagStats {
+    pub allocated: usize,
+    pub freed: usize,
+    pub nb_allocs: usize,
 }
 
 impl SimpleAllocator {
@@ -38,36 +102,73 @@ impl SimpleAllocator {
         }
     }
 
-    pub fn init(&self, heap_addr: VirtAddr, heap_size: usize) {
+    // The regions must be mapped and unused, at most MAX_HEAP_REGIONS of them are kept
+    pub fn init(&self, regions: &[HeapRegion]) {
         let heap = self.heap.get();
-        let start_ptr = heap_addr.as_mut_ptr();
+
+        let mut reserve = [HeapRegion::EMPTY; MAX_HEAP_REGIONS];
+        for (dst, src) in reserve.iter_mut().zip(regions) {
+            *dst = *src;
+        }
+        let reserve_size = reserve.iter().map(|region| region.size).sum();
 
         unsafe {
             *heap = Some(SimpleHeap {
-                start: start_ptr,
-                ptr: start_ptr,
+                ptr: core::ptr::null_mut(),
+                end: core::ptr::null_mut(),
+
+                reserve,
 
                 trackers: [None; NB_BLOCK_SIZES * NB_BLOCK_SIZES],
 
                 stats: AllocStats {
-                    total: heap_size,
+                    total: 0,
+                    reserve: reserve_size,
+                    nb_grows: 0,
                     explored: 0,
                     allocated: 0,
                     lost: 0,
                     reclaimable: 0,
+                    peak: 0,
+                    nb_allocs: 0,
+                    size_classes: [0; NB_BLOCK_SIZES],
+                    free_size_classes: [0; NB_BLOCK_SIZES],
                 },
+
+                tag: 0,
+                tag_stats: [TagStats::default(); MAX_ALLOC_TAGS],
             })
         }
+
+        // The initial chunk
+        self.get_heap_mut().grow(HEAP_CHUNK_SIZE);
     }
 
     pub fn size(&self) -> usize {
         self.get_heap().stats.total
     }
 
+    pub fn nb_grows(&self) -> usize {
+        self.get_heap().stats.nb_grows
+    }
+
     pub fn get_stats(&self) -> AllocStats {
         self.get_heap().stats.clone()
     }
 
+    pub fn get_tag_stats(&self) -> [TagStats; MAX_ALLOC_TAGS] {
+        self.get_heap().tag_stats
+    }
+
+    // 0 to stop tagging, tags past MAX_ALLOC_TAGS are counted as untagged
+    pub fn set_tag(&self, tag: usize) {
+        let tag = match tag < MAX_ALLOC_TAGS {
+            true => tag,
+            false => 0,
+        };
+        self.get_heap_mut().tag = tag;
+    }
+
     fn get_heap(&self) -> &SimpleHeap {
         unsafe {
             self.heap
@@ -99,11 +200,10 @@ unsafe impl GlobalAlloc for SimpleAllocator {
         let align = layout.align();
 
         let (tracker_index, block_size) = get_tracker(size, align);
+        let size_index = tracker_index / NB_BLOCK_SIZES;
 
         let heap = self.get_heap_mut();
 
-        heap.stats.allocated += size;
-
         let alloc_ptr = match heap.trackers[tracker_index] {
             // Reclaiming a previously allocated and unused block
             Some(tracker_ptr) => {
@@ -125,29 +225,28 @@ unsafe impl GlobalAlloc for SimpleAllocator {
                 };
 
                 heap.stats.reclaimable -= block_size;
+                heap.stats.free_size_classes[size_index] -= 1;
 
                 tracker_ptr
             }
 
             // Creating a new block
-            None => {
-                let offset = heap.ptr.align_offset(align);
-                heap.ptr = heap.ptr.add(offset);
-
-                let alloc_ptr = heap.ptr;
-
-                heap.ptr = heap.ptr.add(block_size);
-                heap.stats.lost += offset;
-                heap.stats.explored = {
-                    let p0 = heap.start as usize;
-                    let p1 = heap.ptr as usize;
-                    p1 - p0
-                };
-
-                alloc_ptr
-            }
+            None => match heap.new_block(block_size, align) {
+                Some(alloc_ptr) => alloc_ptr,
+                // Out of memory, the reserve is used up
+                None => return core::ptr::null_mut(),
+            },
         };
 
+        heap.stats.allocated += size;
+        heap.stats.peak = usize::max(heap.stats.peak, heap.stats.allocated);
+        heap.stats.nb_allocs += 1;
+        heap.stats.size_classes[size_index] += 1;
+
+        let tag_stats = &mut heap.tag_stats[heap.tag];
+        tag_stats.allocated += size;
+        tag_stats.nb_allocs += 1;
+
         alloc_ptr
     }
 
@@ -156,6 +255,7 @@ unsafe impl GlobalAlloc for SimpleAllocator {
         let align = layout.align();
 
         let (tracker_index, block_size) = get_tracker(size, align);
+        let size_index = tracker_index / NB_BLOCK_SIZES;
 
         let heap = self.get_heap_mut();
 
@@ -173,6 +273,75 @@ unsafe impl GlobalAlloc for SimpleAllocator {
 
         heap.stats.allocated -= size;
         heap.stats.reclaimable += block_size;
+        heap.stats.nb_allocs -= 1;
+        heap.stats.size_classes[size_index] -= 1;
+        heap.stats.free_size_classes[size_index] += 1;
+        heap.tag_stats[heap.tag].freed += size;
+    }
+}
+
+impl SimpleHeap {
+    // None if the heap cannot grow enough for the block
+    unsafe fn new_block(&mut self, block_size: usize, align: usize) -> Option<*mut u8> {
+        loop {
+            let offset = self.ptr.align_offset(align);
+            let room = self.end as usize - self.ptr as usize;
+
+            if offset + block_size <= room {
+                self.ptr = self.ptr.add(offset);
+
+                let alloc_ptr = self.ptr;
+
+                self.ptr = self.ptr.add(block_size);
+                self.stats.lost += offset;
+                self.stats.explored += offset + block_size;
+
+                return Some(alloc_ptr);
+            }
+
+            // Enough for the block wherever the chunk starts
+            if !self.grow(block_size + align) {
+                return None;
+            }
+        }
+    }
+
+    // Adds a chunk of at least min_size bytes to the heap, from the end of the current
+    // one if its region has room left. Returns false if no region has.
+    fn grow(&mut self, min_size: usize) -> bool {
+        let end = VirtAddr::from_ptr(self.end);
+        let index = self
+            .reserve
+            .iter()
+            .position(|region| region.start == end && region.size >= min_size)
+            .or_else(|| {
+                self.reserve
+                    .iter()
+                    .position(|region| region.size >= min_size)
+            });
+
+        let Some(index) = index else {
+            return false;
+        };
+
+        let region = &mut self.reserve[index];
+        let size = usize::min(region.size, usize::max(HEAP_CHUNK_SIZE, min_size));
+        let start: *mut u8 = region.start.as_mut_ptr();
+        region.start += size as u64;
+        region.size -= size;
+
+        // Otherwise, the rest of the current chunk is never used
+        if start != self.end {
+            self.stats.lost += self.end as usize - self.ptr as usize;
+            self.ptr = start;
+        }
+        self.end = unsafe { start.add(size) };
+
+        self.stats.total += size;
+        self.stats.reserve -= size;
+        self.stats.nb_grows += 1;
+
+        true
     }
 }
 
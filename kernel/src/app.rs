@@ -6,17 +6,31 @@ use applib::input::PointerState;
 use applib::{FbView, StyleSheet};
 
 use crate::shell::{pie_menu, PieDrawCalls, PieMenuEntry};
-use crate::stats::SystemStats;
-use applib::content::TrackedContent;
-use applib::drawing::primitives::{draw_rect, draw_rect_outline};
-use applib::drawing::text::{compute_text_bbox, draw_line_in_rect, draw_str, get_font, Font, TextJustification};
+use crate::shortcuts::ShortcutAction;
+use crate::stats::{AppDataPoint, SystemStats};
+use applib::content::{ContentId, TrackedContent};
+use applib::drawing::effects::{blend_with_opacity, render_shadow, Shadow};
+use applib::drawing::gradient::fill_linear_gradient;
+use applib::drawing::primitives::{draw_line, draw_rect, draw_rect_outline, StrokeStyle};
+use applib::drawing::text::{compute_text_bbox, draw_line_in_rect, ellipsize_text, get_font, Font, TextJustification};
 use applib::geometry::{Point2D, Vec2D};
-use applib::uitk::{self, GraphSeries, TextBoxState};
+use applib::uitk::{self, CursorHint, GraphSeries, TextBoxState};
+use applib::input::keymap::Keycode;
 use applib::{input::InputState, Color, FbViewMut, Framebuffer, OwnedPixels, Rect};
 
+use crate::autostart::{self, AutostartEntry};
+use crate::crash_panel::{self, CrashButton};
+use crate::network::TcpStack;
+use crate::overview::{self, DropTarget, OverviewDrag, OverviewWindow, ThumbnailCache};
 use crate::system::System;
-use crate::wasm::{WasmApp, WasmEngine};
-use crate::{resources, TOPBAR_H};
+use crate::taskbar::{
+    LauncherMenu, QuickSetting, QuickSettingsMenu, Taskbar, TaskbarEntry, TaskbarItem,
+    WorkspaceEntry,
+};
+use crate::tray::{CalendarPopup, Notification, NotificationsPanel, TrayItem, TrayStatus};
+use crate::wasm::{AppCrash, WasmApp, WasmEngine};
+use crate::{resources, TASKBAR_H, TOPBAR_H};
+use chrono::{DateTime, Timelike, Utc};
 
 #[derive(Clone)]
 pub struct AppDescriptor {
@@ -27,11 +41,64 @@ pub struct AppDescriptor {
     pub icon: &'static Framebuffer<OwnedPixels>,
 }
 
+// TODO: ideally, this should be computed dynamically...
+const TOPBAR_GAP: u32 = 68;
+const BORDER_THICKNESS: u32 = 8;
+// How much of the titlebar stays on screen when a window is dragged out
+const DRAG_MIN_VISIBLE: u32 = 40;
+// Snapping distances, from the pointer to the screen edges and between window sides
+const EDGE_SNAP_DIST: u32 = 8;
+const WINDOW_SNAP_DIST: u32 = 8;
+// Picked in turn from the window menu
+const OPACITY_STEPS: [f32; 3] = [1.0, 0.85, 0.7];
+// Of the windows without the focus when they are shaded
+const SHADE_OPACITY: f32 = 0.7;
+const NB_WORKSPACES: usize = 4;
+// Older notifications are dropped from the tray log
+const NOTIFICATION_LOG_LEN: usize = 50;
+// How long the network indicator blips after some traffic
+const NET_ACTIVITY_FRAMES: usize = 10;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum HoverKind {
     Titlebar,
-    Resize,
+    Button(TitlebarButton),
+    Resize(ResizeEdges),
     Window,
+    CrashButton(CrashButton),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TitlebarButton {
+    Minimize,
+    Maximize,
+    Close,
+}
+
+// From left to right, at the end of the titlebar
+const TITLEBAR_BUTTONS: [TitlebarButton; 3] = [
+    TitlebarButton::Minimize,
+    TitlebarButton::Maximize,
+    TitlebarButton::Close,
+];
+
+// The sides of the window a resize moves, along each axis: -1 for the left or top one,
+// 1 for the right or bottom one, 0 for neither
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResizeEdges {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl ResizeEdges {
+    fn cursor_hint(&self) -> CursorHint {
+        match (self.x, self.y) {
+            (_, 0) => CursorHint::ResizeHorizontal,
+            (0, _) => CursorHint::ResizeVertical,
+            (x, y) if x == y => CursorHint::ResizeDiagonal,
+            _ => CursorHint::ResizeAntiDiagonal,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -48,12 +115,36 @@ pub enum AppsInteractionState {
         // In "toggle" mode, another click is required to get out of the TitlebarHold state
         toggle: bool,
     },
+    // Triggers the button if released over it
+    ButtonHold {
+        app_name: &'static str,
+        button: TitlebarButton,
+    },
     ResizeHold {
         app_name: &'static str,
+        edges: ResizeEdges,
+        // Window rect and pointer position when the drag started
+        start_xyxy: [i64; 4],
+        anchor: Point2D<i64>,
     },
     PieDesktopMenu {
         anchor: Point2D<i64>,
     },
+    LauncherMenu,
+    QuickSettingsMenu,
+    // Closed by a click anywhere else, or replaced by the popup of another indicator
+    TrayPopup(TrayItem),
+    // Super+Tab or the top-left corner. Windows move to and from their thumbnail during
+    // the transition started at `since`.
+    Overview {
+        since: f64,
+        leaving: bool,
+        drag: Option<OverviewDrag>,
+    },
+    // Alt+Tab, the selected window gets the focus when Alt is released
+    WindowSwitcher {
+        selected: usize,
+    },
     PieAppMenu {
         app_name: &'static str,
         anchor: Point2D<i64>,
@@ -62,6 +153,18 @@ pub enum AppsInteractionState {
 
 pub struct AppsManager {
     z_ordered: Vec<App>,
+    // Minimized by "show desktop", from the bottom one up
+    desktop_hidden: Vec<&'static str>,
+    // Windows of the other workspaces are not drawn. The z-order and the focus of each
+    // workspace are the ones of its windows in z_ordered.
+    active_workspace: usize,
+    // Shown in the tray notifications panel, oldest first
+    notifications: Vec<Notification>,
+    // Last shown by the tray
+    tray_status: Option<TrayStatus>,
+    // The overview is toggled when the pointer gets there, not while it stays
+    in_hot_corner: bool,
+    thumbnails: ThumbnailCache,
 }
 
 impl AppsManager {
@@ -79,6 +182,40 @@ pub struct App {
     pub is_open: bool,
     pub rect: Rect,
     pub time_used: f64,
+
+    // Paths sent by other apps, delivered once this app is running
+    pub pending_opens: Vec<String>,
+
+    // Shown in the titlebar until the app is brought to the foreground
+    pub notification: Option<String>,
+
+    // Area covered by the window in the last frame, its depth and opacity
+    pub drawn: Option<(Rect, usize, f32)>,
+
+    // Drop shadow of the window, rendered again when it is resized
+    pub shadow: Option<Framebuffer<OwnedPixels>>,
+
+    // Hidden and not stepped, but still running
+    pub minimized: bool,
+
+    // Where the window was before it was maximized, None if it is not
+    pub restore_rect: Option<Rect>,
+
+    // From 0 to 1, the window is blended over what is behind it below 1
+    pub opacity: f32,
+
+    // Index of the workspace the window is on, from 0
+    pub workspace: usize,
+
+    // Changes with what the app draws, overview thumbnails are rendered again then
+    pub content_id: ContentId,
+}
+
+impl App {
+    // Drawn, and can get the pointer and the focus
+    fn is_shown(&self, active_workspace: usize) -> bool {
+        self.is_open && !self.minimized && self.workspace == active_workspace
+    }
 }
 
 pub enum AppState {
@@ -88,8 +225,9 @@ pub enum AppState {
         wasm_app: WasmApp,
         audit_mode: AppAuditMode,
     },
+    // Torn down, a panel with the error and the restart and close buttons is shown instead
     Crashed {
-        error: anyhow::Error,
+        crash: AppCrash,
     },
 }
 
@@ -127,7 +265,15 @@ impl AppAuditMode {
 
 impl AppsManager {
     pub fn new(apps: Vec<App>) -> Self {
-        Self { z_ordered: apps }
+        Self {
+            z_ordered: apps,
+            desktop_hidden: Vec::new(),
+            active_workspace: 0,
+            notifications: Vec::new(),
+            tray_status: None,
+            in_hot_corner: false,
+            thumbnails: ThumbnailCache::new(),
+        }
     }
 
     fn get_mut(&mut self, app_name: &'static str) -> &mut App {
@@ -146,8 +292,207 @@ impl AppsManager {
         let app = self.z_ordered.remove(index);
         self.z_ordered.push(app);
     }
+
+    pub fn apps(&self) -> impl Iterator<Item = &App> {
+        self.z_ordered.iter()
+    }
+
+    // False if there is no such app
+    pub fn close(&mut self, app_name: &str, system: &mut System) -> bool {
+        let app = self
+            .z_ordered
+            .iter_mut()
+            .find(|app| app.descriptor.name == app_name);
+        match app {
+            Some(app) => {
+                close_app(app, system);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // False if there is no such app running
+    pub fn set_paused(&mut self, app_name: &str, paused: bool) -> bool {
+        let app = self
+            .z_ordered
+            .iter_mut()
+            .find(|app| app.descriptor.name == app_name);
+        match app.map(|app| &mut app.app_state) {
+            Some(AppState::Active { paused: p, .. }) => {
+                *p = paused;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Puts the window on top of its workspace, and shows that workspace
+    fn activate(&mut self, app_name: &'static str) {
+        self.active_workspace = self.get_mut(app_name).workspace;
+        self.set_on_top(app_name);
+    }
+
+    // Opens the window where it was saved, without switching to its workspace. The app
+    // is instantiated by run_apps().
+    pub fn autostart(&mut self, entry: &AutostartEntry, fb_shape: (u32, u32)) {
+        let Some(app) = self
+            .z_ordered
+            .iter_mut()
+            .find(|app| app.descriptor.name == entry.app_name)
+        else {
+            log::warn!("Cannot autostart unknown app {}", entry.app_name);
+            return;
+        };
+        if app.is_open {
+            return;
+        }
+
+        log::info!("Autostarting app {}", entry.app_name);
+        app.rect = position_window(&entry.rect, fb_shape);
+        app.workspace = usize::min(entry.workspace, NB_WORKSPACES - 1);
+        app.is_open = true;
+        app.minimized = false;
+
+        let app_name = app.descriptor.name;
+        self.set_on_top(app_name);
+    }
+
+    // After a display mode switch. Maximized and snapped windows keep their layout,
+    // the others are made to fit. Apps see their new rect on their next step.
+    pub fn fit_to_screen(&mut self, old_shape: (u32, u32), new_shape: (u32, u32)) {
+        let old_layouts = snap_layouts(old_shape);
+        let new_layouts = snap_layouts(new_shape);
+
+        for app in self.z_ordered.iter_mut() {
+            let old_rect = app.rect.clone();
+            app.rect = match old_layouts.iter().position(|rect| *rect == app.rect) {
+                Some(i) if app.restore_rect.is_some() => new_layouts[i].clone(),
+                _ => clamp_to_workspace(&app.rect, new_shape),
+            };
+            if let Some(restore_rect) = app.restore_rect.as_mut() {
+                *restore_rect = clamp_to_workspace(restore_rect, new_shape);
+            }
+
+            if app.is_open && app.rect != old_rect {
+                log::info!("Fitted the {} window to the screen", app.descriptor.name);
+            }
+        }
+    }
+
+    // Stops at the first and last workspaces
+    fn adjacent_workspace(&self, offset: isize) -> usize {
+        self.active_workspace
+            .saturating_add_signed(offset)
+            .min(NB_WORKSPACES - 1)
+    }
+
+    // The topmost window that is shown, it is the only one getting key events
+    fn focused(&self) -> Option<&'static str> {
+        self.z_ordered
+            .iter()
+            .rev()
+            .find(|app| app.is_shown(self.active_workspace))
+            .map(|app| app.descriptor.name)
+    }
+
+    // Open windows of the active workspace from the top, minimized ones included
+    fn switcher_order(&self) -> Vec<&App> {
+        self.z_ordered
+            .iter()
+            .rev()
+            .filter(|app| app.is_open && app.workspace == self.active_workspace)
+            .collect()
+    }
+
+    // Minimizes all windows of the active workspace, or brings back the ones it minimized
+    // if none was shown since
+    fn toggle_desktop(&mut self) {
+        let shown: Vec<&'static str> = self
+            .z_ordered
+            .iter()
+            .filter(|app| app.is_shown(self.active_workspace))
+            .map(|app| app.descriptor.name)
+            .collect();
+
+        if shown.is_empty() {
+            for app_name in core::mem::take(&mut self.desktop_hidden) {
+                let active_workspace = self.active_workspace;
+                let app = self.get_mut(app_name);
+                // May have been closed or moved in the meantime
+                if app.is_open && app.workspace == active_workspace {
+                    app.minimized = false;
+                    self.set_on_top(app_name);
+                }
+            }
+        } else {
+            for app_name in shown.iter() {
+                self.get_mut(app_name).minimized = true;
+            }
+            self.desktop_hidden = shown;
+        }
+    }
+
+    // So that the window under it becomes the foreground one
+    fn set_at_bottom(&mut self, app_name: &'static str) {
+        let index = self
+            .z_ordered
+            .iter()
+            .position(|app| app.descriptor.name == app_name)
+            .unwrap();
+        let app = self.z_ordered.remove(index);
+        self.z_ordered.insert(0, app);
+    }
 }
 
+// In place of run_apps() while the screen is locked. No app gets input or is drawn, the
+// open ones keep running unless paused.
+pub fn step_locked_apps(
+    system: &mut System,
+    uuid_provider: &mut uitk::UuidProvider,
+    apps_manager: &mut AppsManager,
+    input_state: &InputState,
+    pause: bool,
+) {
+    let active_workspace = apps_manager.active_workspace;
+
+    for app in apps_manager.z_ordered.iter_mut() {
+        *system.stats.get_app_point_mut(app.descriptor.name) = AppDataPoint::default();
+
+        let hidden = app.minimized || app.workspace != active_workspace;
+        if let AppState::Active {
+            wasm_app, paused, ..
+        } = &mut app.app_state
+        {
+            let wasm_res = wasm_app.step(
+                system,
+                uuid_provider,
+                input_state,
+                &app.rect,
+                false,
+                false,
+                pause || *paused || hidden,
+            );
+            if let Err(error) = wasm_res {
+                crash_app(&mut app.app_state, error, &mut system.tcp_stack);
+            }
+        }
+    }
+}
+
+// Found out by run_apps before anything is drawn, then passed on to draw_apps
+pub struct AppsFrame {
+    pub cursor_hint: CursorHint,
+    // Content reported by the apps, and windows moved, restacked, opened or closed
+    pub damage: Vec<Rect>,
+    // Anything else which changes what is drawn, the whole screen is then drawn again
+    pub changed: bool,
+    hover_state: Option<(&'static str, HoverKind)>,
+    pie_draw_calls: Option<PieDrawCalls>,
+    tray_status: TrayStatus,
+}
+
+// Handles the input and steps the apps, nothing is drawn until draw_apps()
 pub fn run_apps<F: FbViewMut>(
     uitk_context: &mut uitk::UiContext<F>,
     system: &mut System,
@@ -155,11 +500,116 @@ pub fn run_apps<F: FbViewMut>(
     apps_manager: &mut AppsManager,
     input_state: &InputState,
     interaction_state: &mut AppsInteractionState,
-) {
-    let stylesheet = system.stylesheet.clone();
+    shortcuts: &[ShortcutAction],
+    datetime: DateTime<Utc>,
+) -> AppsFrame {
+    let stylesheet = system.theme.stylesheet.clone();
     let pointer = &input_state.pointer;
+    let fb_shape = uitk_context.fb.shape();
     let mut pie_draw_calls: Option<PieDrawCalls> = None;
 
+    //
+    // Kernel shortcuts
+
+    for action in shortcuts {
+        match *action {
+            ShortcutAction::CloseWindow => {
+                if let Some(app_name) = apps_manager.focused() {
+                    request_close(apps_manager, app_name, system);
+                }
+            }
+            ShortcutAction::ShowDesktop => apps_manager.toggle_desktop(),
+            ShortcutAction::FocusApp(i) => {
+                // Same order as the launcher menu
+                let mut app_names: Vec<&'static str> = apps_manager
+                    .z_ordered
+                    .iter()
+                    .map(|app| app.descriptor.name)
+                    .collect();
+                app_names.sort();
+
+                if let Some(&app_name) = app_names.get(i) {
+                    let active_workspace = apps_manager.active_workspace;
+                    let app = apps_manager.get_mut(app_name);
+                    let preferred_rect = app.rect.clone();
+                    show_window(app, &preferred_rect, fb_shape, active_workspace);
+                    apps_manager.activate(app_name);
+                }
+            }
+            ShortcutAction::SwitchWorkspace(offset) => {
+                apps_manager.active_workspace = apps_manager.adjacent_workspace(offset);
+                // Drags and menus of the windows left behind
+                *interaction_state = AppsInteractionState::Idle;
+            }
+            ShortcutAction::MoveToWorkspace(offset) => {
+                if let Some(app_name) = apps_manager.focused() {
+                    let workspace = apps_manager.adjacent_workspace(offset);
+                    apps_manager.get_mut(app_name).workspace = workspace;
+                    // The window keeps the focus
+                    apps_manager.activate(app_name);
+                    *interaction_state = AppsInteractionState::Idle;
+                }
+            }
+            ShortcutAction::Overview => toggle_overview(interaction_state, uitk_context.time),
+            // Taken by the main loop
+            ShortcutAction::Lock | ShortcutAction::Screenshot | ShortcutAction::ToggleOverlay => (),
+        }
+    }
+
+    //
+    // Taskbar
+
+    let taskbar = make_taskbar(apps_manager, fb_shape);
+    let launcher_menu = LauncherMenu::new(&taskbar);
+    let quick_settings_menu = QuickSettingsMenu::new(&taskbar, &system.display_modes);
+    let launcher_open = *interaction_state == AppsInteractionState::LauncherMenu;
+    let quick_settings_open = *interaction_state == AppsInteractionState::QuickSettingsMenu;
+    let notifications_panel =
+        NotificationsPanel::new(&taskbar.tray, apps_manager.notifications.len());
+    let calendar_popup = CalendarPopup::new(&taskbar.tray);
+    let tray_popup_rect = match *interaction_state {
+        AppsInteractionState::TrayPopup(TrayItem::Notifications) => Some(&notifications_panel.rect),
+        AppsInteractionState::TrayPopup(TrayItem::Clock) => Some(&calendar_popup.rect),
+        _ => None,
+    };
+
+    // Windows under the taskbar and its menus do not get the pointer
+    let in_launcher_menu = launcher_menu
+        .rect
+        .check_contains_point(pointer.x, pointer.y);
+    let in_quick_settings_menu = quick_settings_menu
+        .rect
+        .check_contains_point(pointer.x, pointer.y);
+    let in_tray_popup =
+        tray_popup_rect.is_some_and(|rect| rect.check_contains_point(pointer.x, pointer.y));
+    let over_taskbar = taskbar.rect.check_contains_point(pointer.x, pointer.y)
+        || (launcher_open && in_launcher_menu)
+        || (quick_settings_open && in_quick_settings_menu)
+        || in_tray_popup;
+    let taskbar_hover = taskbar.item_at(pointer.x, pointer.y);
+    let launcher_hover = launcher_menu.app_at(pointer.x, pointer.y);
+    let quick_settings_hover = quick_settings_menu.setting_at(pointer.x, pointer.y);
+
+    //
+    // Window switcher
+
+    let switcher_apps: Vec<&'static str> = apps_manager
+        .switcher_order()
+        .iter()
+        .map(|app| app.descriptor.name)
+        .collect();
+    let nb_switcher_apps = switcher_apps.len();
+    let tab_pressed = input_state.alt && input_state.check_key_pressed(Keycode::KEY_TAB);
+
+    //
+    // Overview
+
+    let in_overview = matches!(*interaction_state, AppsInteractionState::Overview { .. });
+    let overview_windows = overview_windows(apps_manager, input_state, fb_shape);
+    let in_hot_corner = pointer.x <= 0 && pointer.y <= 0;
+    let hot_corner_hit = in_hot_corner && !apps_manager.in_hot_corner;
+    apps_manager.in_hot_corner = in_hot_corner;
+
     //
     // Hover
 
@@ -173,13 +623,23 @@ pub fn run_apps<F: FbViewMut>(
         })
         .find_map(|(app, deco)| {
             let app_name = app.descriptor.name;
+            let crash_button_hover = match &app.app_state {
+                AppState::Crashed { .. } if deco.window_hover => {
+                    crash_panel::button_at(&deco.content_rect, pointer.x, pointer.y)
+                }
+                _ => None,
+            };
 
-            if !app.is_open {
+            if !app.is_shown(apps_manager.active_workspace) || over_taskbar || in_overview {
                 None
+            } else if let Some(button) = deco.button_hover {
+                Some((app_name, HoverKind::Button(button)))
             } else if deco.titlebar_hover {
                 Some((app_name, HoverKind::Titlebar))
-            } else if deco.resize_hover {
-                Some((app_name, HoverKind::Resize))
+            } else if let Some(edges) = deco.resize_edges {
+                Some((app_name, HoverKind::Resize(edges)))
+            } else if let Some(button) = crash_button_hover {
+                Some((app_name, HoverKind::CrashButton(button)))
             } else if deco.window_hover {
                 Some((app_name, HoverKind::Window))
             } else {
@@ -192,15 +652,73 @@ pub fn run_apps<F: FbViewMut>(
 
     let is = interaction_state;
 
+    let can_toggle = matches!(
+        *is,
+        AppsInteractionState::Idle
+            | AppsInteractionState::AppHover { .. }
+            | AppsInteractionState::Overview { .. }
+    );
+    let previous_state = *is;
+    if hot_corner_hit && can_toggle {
+        toggle_overview(is, uitk_context.time);
+    }
+
     match *is {
+        AppsInteractionState::Idle | AppsInteractionState::AppHover { .. }
+            if tab_pressed && nb_switcher_apps > 0 =>
+        {
+            // Starts on the window below the focused one, or the bottom one with Shift
+            let selected = match input_state.shift {
+                false => 1,
+                true => nb_switcher_apps - 1,
+            };
+            *is = AppsInteractionState::WindowSwitcher {
+                selected: selected % nb_switcher_apps,
+            };
+        }
+
+        AppsInteractionState::WindowSwitcher { selected } if input_state.alt => {
+            if tab_pressed && nb_switcher_apps > 0 {
+                let step = match input_state.shift {
+                    false => 1,
+                    true => nb_switcher_apps - 1,
+                };
+                *is = AppsInteractionState::WindowSwitcher {
+                    selected: (selected + step) % nb_switcher_apps,
+                };
+            }
+        }
+
+        AppsInteractionState::WindowSwitcher { selected } => {
+            *is = AppsInteractionState::Idle;
+            if let Some(&app_name) = switcher_apps.get(selected) {
+                apps_manager.get_mut(app_name).minimized = false;
+                apps_manager.set_on_top(app_name);
+            }
+        }
+
         AppsInteractionState::Idle => match hover_state {
-            None if pointer.right_click_trigger => {
+            None if pointer.right_click_trigger && !over_taskbar => {
                 let anchor = Point2D {
                     x: pointer.x,
                     y: pointer.y,
                 };
                 *is = AppsInteractionState::PieDesktopMenu { anchor };
             }
+            None if pointer.left_click_trigger => match taskbar_hover {
+                Some(TaskbarItem::Launcher) => *is = AppsInteractionState::LauncherMenu,
+                Some(TaskbarItem::QuickSettings) => *is = AppsInteractionState::QuickSettingsMenu,
+                Some(TaskbarItem::Tray(item)) if item.has_popup() => {
+                    *is = AppsInteractionState::TrayPopup(item)
+                }
+                Some(TaskbarItem::Tray(_)) => (),
+                Some(TaskbarItem::Workspace(i)) => apps_manager.active_workspace = i,
+                Some(TaskbarItem::App(app_name)) => {
+                    apps_manager.get_mut(app_name).minimized = false;
+                    apps_manager.set_on_top(app_name);
+                }
+                None => (),
+            },
             None => (),
             Some((app_name, hover_kind)) => {
                 *is = AppsInteractionState::AppHover {
@@ -236,7 +754,33 @@ pub fn run_apps<F: FbViewMut>(
                     };
                 }
 
-                HoverKind::Resize => *is = AppsInteractionState::ResizeHold { app_name },
+                HoverKind::Button(button) => {
+                    *is = AppsInteractionState::ButtonHold { app_name, button };
+                }
+
+                HoverKind::Resize(edges) => {
+                    let app = apps_manager.get_mut(app_name);
+                    *is = AppsInteractionState::ResizeHold {
+                        app_name,
+                        edges,
+                        start_xyxy: app.rect.as_xyxy(),
+                        anchor: Point2D {
+                            x: pointer.x,
+                            y: pointer.y,
+                        },
+                    };
+                }
+
+                HoverKind::CrashButton(CrashButton::Restart) => {
+                    let app = apps_manager.get_mut(app_name);
+                    log::info!("Restarting {}", app_name);
+                    system.stats.clear_app(app_name);
+                    app.app_state = AppState::Init;
+                }
+
+                HoverKind::CrashButton(CrashButton::Close) => {
+                    close_app(apps_manager.get_mut(app_name), system);
+                }
 
                 HoverKind::Window => (),
             }
@@ -252,35 +796,107 @@ pub fn run_apps<F: FbViewMut>(
             }
         },
 
-        AppsInteractionState::TitlebarHold { toggle, .. } if !toggle && !pointer.left_clicked => {
+        AppsInteractionState::TitlebarHold {
+            app_name, toggle, ..
+        } if !toggle && !pointer.left_clicked => {
             *is = AppsInteractionState::Idle;
+            drop_window(apps_manager.get_mut(app_name), pointer, fb_shape);
         }
 
-        AppsInteractionState::TitlebarHold { toggle, .. }
-            if toggle && (pointer.left_click_trigger || pointer.right_click_trigger) =>
-        {
+        AppsInteractionState::TitlebarHold {
+            app_name, toggle, ..
+        } if toggle && (pointer.left_click_trigger || pointer.right_click_trigger) => {
             *is = AppsInteractionState::Idle;
+            drop_window(apps_manager.get_mut(app_name), pointer, fb_shape);
         }
 
         AppsInteractionState::TitlebarHold {
-            app_name, anchor, ..
+            app_name,
+            mut anchor,
+            toggle,
         } => {
+            let other_rects: Vec<Rect> = apps_manager
+                .z_ordered
+                .iter()
+                .filter(|app| {
+                    app.is_shown(apps_manager.active_workspace) && app.descriptor.name != app_name
+                })
+                .map(|app| app.rect.offset(BORDER_THICKNESS as i64))
+                .collect();
+
             let app = apps_manager.get_mut(app_name);
-            app.rect.x0 = pointer.x - anchor.x;
-            app.rect.y0 = pointer.y - anchor.y;
+            let origin = (pointer.x - anchor.x, pointer.y - anchor.y);
+
+            // A snapped or maximized window gets its size back when dragged away, under the
+            // same part of the titlebar
+            if origin != app.rect.origin() {
+                if let Some(restore_rect) = app.restore_rect.take() {
+                    anchor.x = anchor.x * restore_rect.w as i64 / app.rect.w as i64;
+                    app.rect.w = restore_rect.w;
+                    app.rect.h = restore_rect.h;
+                    *is = AppsInteractionState::TitlebarHold {
+                        app_name,
+                        anchor,
+                        toggle,
+                    };
+                }
+            }
+
+            let moved = Rect {
+                x0: pointer.x - anchor.x,
+                y0: pointer.y - anchor.y,
+                ..app.rect.clone()
+            };
+            let (dx, dy) = snap_to_windows(&moved.offset(BORDER_THICKNESS as i64), &other_rects);
+            let snapped = moved + Vec2D { x: dx, y: dy };
+
+            let deco = compute_decorations(app, input_state);
+            (app.rect.x0, app.rect.y0) =
+                constrain_drag(&app.rect, &deco, snapped.origin(), fb_shape);
+        }
+
+        AppsInteractionState::ButtonHold { .. } if pointer.left_clicked => (),
+
+        AppsInteractionState::ButtonHold { app_name, button } => {
+            *is = AppsInteractionState::Idle;
+
+            if hover_state == Some((app_name, HoverKind::Button(button))) {
+                let app = apps_manager.get_mut(app_name);
+                match button {
+                    TitlebarButton::Close => request_close(apps_manager, app_name, system),
+                    TitlebarButton::Minimize => {
+                        app.minimized = true;
+                        apps_manager.set_at_bottom(app_name);
+                    }
+                    TitlebarButton::Maximize => toggle_maximized(app, fb_shape),
+                }
+            }
         }
 
         AppsInteractionState::ResizeHold { .. } if !pointer.left_clicked => {
             *is = AppsInteractionState::Idle;
         }
 
-        AppsInteractionState::ResizeHold { app_name } => {
+        AppsInteractionState::ResizeHold {
+            app_name,
+            edges,
+            start_xyxy,
+            anchor,
+        } => {
+            // The app gets the new rect when it is stepped below
             let app = apps_manager.get_mut(app_name);
-            let (min_w, min_h) = app.descriptor.min_size;
-            let [x1, y1, _, _] = app.rect.as_xyxy();
-            let x2 = i64::max(x1 + min_w as i64, pointer.x);
-            let y2 = i64::max(y1 + min_h as i64, pointer.y);
-            app.rect = Rect::from_xyxy([x1, y1, x2, y2]);
+            let delta = (pointer.x - anchor.x, pointer.y - anchor.y);
+            let rect = resize_window(
+                start_xyxy,
+                edges,
+                delta,
+                app.descriptor.min_size,
+                uitk_context.fb.shape(),
+            );
+            if rect != app.rect {
+                app.restore_rect = None;
+            }
+            app.rect = rect;
         }
 
         AppsInteractionState::PieAppMenu { app_name, anchor } => {
@@ -344,7 +960,30 @@ pub fn run_apps<F: FbViewMut>(
                     text_color: stylesheet.colors.text,
                     weight: 1.0,
                 },
-                PieMenuEntry::Spacer { weight: 3.0 },
+                PieMenuEntry::Button {
+                    icon: &resources::UI_ICON,
+                    color: stylesheet.colors.accent,
+                    text: "Opacity".to_owned(),
+                    text_color: stylesheet.colors.text,
+                    weight: 1.0,
+                },
+                match autostart::contains(&system.storage, app_name) {
+                    false => PieMenuEntry::Button {
+                        icon: &resources::CHIP_ICON,
+                        color: stylesheet.colors.blue,
+                        text: "Autostart".to_owned(),
+                        text_color: stylesheet.colors.text,
+                        weight: 1.0,
+                    },
+                    true => PieMenuEntry::Button {
+                        icon: &resources::CHIP_ICON,
+                        color: stylesheet.colors.blue,
+                        text: "No autostart".to_owned(),
+                        text_color: stylesheet.colors.text,
+                        weight: 1.0,
+                    },
+                },
+                PieMenuEntry::Spacer { weight: 1.0 },
             ];
 
             let (selected, draw_calls) = pie_menu(uitk_context, &entries, anchor);
@@ -353,7 +992,7 @@ pub fn run_apps<F: FbViewMut>(
 
             match selected {
                 Some("Close") => {
-                    app.is_open = false;
+                    request_close(apps_manager, app_name, system);
                     *is = AppsInteractionState::Idle;
                 }
                 Some("Move") => {
@@ -364,6 +1003,26 @@ pub fn run_apps<F: FbViewMut>(
                         toggle: true,
                     };
                 }
+                Some("Opacity") => {
+                    let next = OPACITY_STEPS
+                        .iter()
+                        .position(|opacity| *opacity == app.opacity)
+                        .map(|i| (i + 1) % OPACITY_STEPS.len())
+                        .unwrap_or(0);
+                    app.opacity = OPACITY_STEPS[next];
+                    *is = AppsInteractionState::Idle;
+                }
+                Some("Autostart") | Some("No autostart") => {
+                    let time = system.clock.time();
+                    autostart::toggle(
+                        &mut system.storage,
+                        app_name,
+                        &app.rect,
+                        app.workspace,
+                        time,
+                    );
+                    *is = AppsInteractionState::Idle;
+                }
                 Some("Reload") => {
                     log::info!("De-loading app {}", app.descriptor.name);
                     app.app_state = AppState::Init;
@@ -425,17 +1084,13 @@ pub fn run_apps<F: FbViewMut>(
 
             match selected {
                 Some(selected_app_name) => {
+                    let active_workspace = apps_manager.active_workspace;
                     let app = apps_manager.get_by_name(selected_app_name);
-
-                    let deco = compute_decorations(app, input_state);
-
                     let preferred_rect =
                         Rect::from_center(pointer.x, pointer.y, app.rect.w, app.rect.h);
-
-                    app.is_open = true;
-                    app.rect = position_window(&preferred_rect, uitk_context.fb.shape(), &deco);
+                    show_window(app, &preferred_rect, fb_shape, active_workspace);
                     let app_name = app.descriptor.name;
-                    apps_manager.set_on_top(app_name);
+                    apps_manager.activate(app_name);
                 }
 
                 _ => (),
@@ -445,22 +1100,500 @@ pub fn run_apps<F: FbViewMut>(
                 *is = AppsInteractionState::Idle
             }
         }
+
+        AppsInteractionState::LauncherMenu => {
+            if pointer.left_click_trigger {
+                if let Some(app_name) = launcher_hover {
+                    let active_workspace = apps_manager.active_workspace;
+                    let app = apps_manager.get_mut(app_name);
+                    let preferred_rect = app.rect.clone();
+                    show_window(app, &preferred_rect, fb_shape, active_workspace);
+                    apps_manager.activate(app_name);
+                }
+            }
+
+            if pointer.right_click_trigger || pointer.left_click_trigger {
+                *is = AppsInteractionState::Idle
+            }
+        }
+
+        AppsInteractionState::QuickSettingsMenu => {
+            if pointer.left_click_trigger {
+                match quick_settings_hover {
+                    Some(QuickSetting::Theme(i)) => {
+                        let theme = &resources::THEMES[i];
+                        log::info!("Switching to the {} theme", theme.name);
+                        system.theme = theme;
+                        // So that the chrome is drawn with it this frame already
+                        uitk_context.stylesheet = theme.stylesheet.clone();
+                    }
+                    Some(QuickSetting::Wallpaper(i)) => system.wallpaper.selected = i,
+                    Some(QuickSetting::Scaling(mode)) => system.wallpaper.mode = mode,
+                    Some(QuickSetting::ShadeUnfocused(shade)) => system.shade_unfocused = shade,
+                    Some(QuickSetting::Resolution(w, h)) => system.requested_mode = Some((w, h)),
+                    None => (),
+                }
+            }
+
+            if pointer.right_click_trigger || pointer.left_click_trigger {
+                *is = AppsInteractionState::Idle
+            }
+        }
+
+        AppsInteractionState::TrayPopup(item) => {
+            if pointer.left_click_trigger
+                && item == TrayItem::Notifications
+                && notifications_panel.clear_all_at(pointer.x, pointer.y)
+            {
+                apps_manager.notifications.clear();
+            }
+
+            let clicked = pointer.right_click_trigger || pointer.left_click_trigger;
+            match taskbar_hover {
+                Some(TaskbarItem::Tray(other))
+                    if pointer.left_click_trigger && other != item && other.has_popup() =>
+                {
+                    *is = AppsInteractionState::TrayPopup(other)
+                }
+                _ if clicked && !in_tray_popup => *is = AppsInteractionState::Idle,
+                _ => (),
+            }
+        }
+
+        AppsInteractionState::Overview {
+            since,
+            leaving: true,
+            ..
+        } => {
+            if uitk_context.time - since >= overview::ANIMATION_DURATION {
+                *is = AppsInteractionState::Idle;
+            }
+        }
+
+        AppsInteractionState::Overview { drag, .. } => {
+            let time = uitk_context.time;
+            let escape = input_state.check_key_pressed(Keycode::KEY_ESC);
+
+            let drag = match drag {
+                _ if escape || pointer.right_click_trigger => {
+                    toggle_overview(is, time);
+                    None
+                }
+                None if pointer.left_click_trigger => {
+                    let app_name = overview::window_at(&overview_windows, pointer.x, pointer.y);
+                    if app_name.is_none() {
+                        // Clicking next to the windows leaves
+                        toggle_overview(is, time);
+                    }
+                    app_name.map(|app_name| OverviewDrag {
+                        app_name,
+                        start: Point2D {
+                            x: pointer.x,
+                            y: pointer.y,
+                        },
+                        moved: false,
+                    })
+                }
+                Some(mut drag) if pointer.left_clicked => {
+                    drag.update(pointer.x, pointer.y);
+                    Some(drag)
+                }
+                // Released, a click focuses the window and leaves
+                Some(drag) => {
+                    let target = overview::drop_target(pointer.x, pointer.y, fb_shape);
+                    match (drag.moved, target) {
+                        (false, _) => {
+                            apps_manager.set_on_top(drag.app_name);
+                            toggle_overview(is, time);
+                        }
+                        (true, Some(DropTarget::Close)) => {
+                            log::info!("Closing app {} from the overview", drag.app_name);
+                            request_close(apps_manager, drag.app_name, system);
+                        }
+                        (true, Some(DropTarget::Workspace(offset))) => {
+                            let workspace = apps_manager.adjacent_workspace(offset);
+                            apps_manager.get_mut(drag.app_name).workspace = workspace;
+                        }
+                        (true, None) => (),
+                    }
+                    None
+                }
+                None => None,
+            };
+
+            if let AppsInteractionState::Overview {
+                drag: state_drag,
+                leaving: false,
+                ..
+            } = is
+            {
+                *state_drag = drag;
+            }
+        }
     }
 
     //
-    // Step and draw apps
+    // Step apps
+
+    let focused = apps_manager.focused();
+    let switching = matches!(
+        *is,
+        AppsInteractionState::WindowSwitcher { .. } | AppsInteractionState::Overview { .. }
+    );
+    let mut focus_requests = Vec::new();
+    let mut close_requests = Vec::new();
+    let mut open_requests = Vec::new();
+    let mut cursor_hint = CursorHint::Default;
+    let mut damage = Vec::new();
+
+    // Drags, menus and the switcher are drawn again on every frame, and once more when
+    // they end (e.g. the overview once its transition is over)
+    let mut changed = !shortcuts.is_empty()
+        || *is != previous_state
+        || !matches!(
+            *is,
+            AppsInteractionState::Idle | AppsInteractionState::AppHover { .. }
+        );
+
+    for (i, app) in apps_manager.z_ordered.iter_mut().enumerate() {
+        // Overwritten below if the app is stepped this frame
+        *system.stats.get_app_point_mut(app.descriptor.name) = AppDataPoint::default();
 
+        if !app.is_open {
+            // What was under the window must be shown again
+            damage.extend(app.drawn.take().map(|(rect, ..)| rect));
+            app.shadow = None;
+            continue;
+        }
+
+        // Treated as occluded on other workspaces
+        if app.minimized || app.workspace != apps_manager.active_workspace {
+            damage.extend(app.drawn.take().map(|(rect, ..)| rect));
+            // Autostarted there
+            if let AppState::Init = app.app_state {
+                instantiate_app(
+                    app,
+                    system,
+                    wasm_engine,
+                    uitk_context.uuid_provider,
+                    input_state,
+                );
+                continue;
+            }
+            // Stepped as paused so that it keeps showing in the stats
+            if let AppState::Active { wasm_app, .. } = &mut app.app_state {
+                let wasm_res = wasm_app.step(
+                    system,
+                    uitk_context.uuid_provider,
+                    input_state,
+                    &app.rect,
+                    false,
+                    false,
+                    true,
+                );
+                if let Err(error) = wasm_res {
+                    crash_app(&mut app.app_state, error, &mut system.tcp_stack);
+                    changed = true;
+                }
+            }
+            continue;
+        }
+
+        let app_name = &app.descriptor.name;
+        let deco = compute_decorations(&app, input_state);
+        let is_foreground = focused == Some(*app_name);
+
+        // Moved, resized, opened, brought over other windows, or faded
+        let drawn = (
+            window_area(&deco),
+            i,
+            window_opacity(app, is_foreground, system.shade_unfocused),
+        );
+        if app.drawn.as_ref() != Some(&drawn) {
+            damage.extend(app.drawn.take().map(|(rect, ..)| rect));
+            damage.push(drawn.0.clone());
+            app.drawn = Some(drawn);
+        }
+
+        // Its titlebar is drawn again without it
+        if is_foreground && app.notification.take().is_some() {
+            changed = true;
+        }
+
+        match &mut app.app_state {
+            AppState::Init => {
+                instantiate_app(
+                    app,
+                    system,
+                    wasm_engine,
+                    uitk_context.uuid_provider,
+                    input_state,
+                );
+                changed = true;
+            }
+
+            AppState::Active {
+                wasm_app,
+                audit_mode,
+                paused,
+            } => {
+                for path in app.pending_opens.drain(..) {
+                    wasm_app.push_open_request(path);
+                }
+
+                let hovered = matches!(
+                    hover_state,
+                    Some((hovered_name, HoverKind::Window)) if hovered_name == *app_name
+                );
+
+                let wasm_res = wasm_app.step(
+                    system,
+                    uitk_context.uuid_provider,
+                    input_state,
+                    &app.rect,
+                    // Alt+Tab and the overview keys are not passed on
+                    is_foreground && !switching,
+                    hovered && !switching,
+                    *paused,
+                );
+
+                // From the app under the pointer, or the foreground one while it drags something
+                if hovered || (is_foreground && wasm_app.cursor_hint() != CursorHint::Default) {
+                    cursor_hint = wasm_app.cursor_hint();
+                }
+
+                if wasm_app.take_focus_request() {
+                    focus_requests.push(app.descriptor.name);
+                }
+
+                if let Some(notification) = wasm_app.take_notification() {
+                    apps_manager.notifications.push(Notification {
+                        app_name: app.descriptor.name,
+                        text: notification.clone(),
+                        datetime,
+                    });
+                    if !is_foreground {
+                        app.notification = Some(notification);
+                        changed = true;
+                    }
+                }
+
+                close_requests.extend(wasm_app.take_close_requests());
+                open_requests.extend(wasm_app.take_open_requests());
+
+                // Shows the stats of every frame
+                if let AppAuditMode::Enabled { .. } = audit_mode {
+                    changed = true;
+                }
+
+                match wasm_res {
+                    // Only the parts reported by the app are damaged
+                    Ok(()) if wasm_app.get_framebuffer().is_some() => {
+                        let (x0, y0) = deco.content_rect.origin();
+                        let content_damage = wasm_app.take_damage();
+                        if !matches!(&content_damage, Some(rects) if rects.is_empty()) {
+                            app.content_id = ContentId(app.content_id.0 + 1);
+                        }
+                        match content_damage {
+                            Some(rects) => damage.extend(rects.iter().filter_map(|r| {
+                                let r = Rect {
+                                    x0: r.x0 + x0,
+                                    y0: r.y0 + y0,
+                                    ..r.clone()
+                                };
+                                r.intersection(&deco.content_rect)
+                            })),
+                            None => damage.push(deco.content_rect.clone()),
+                        }
+                    }
+                    Ok(()) => (),
+                    Err(error) => {
+                        // The app framebuffer is not shown anymore
+                        damage.push(deco.content_rect.clone());
+                        crash_app(&mut app.app_state, error, &mut system.tcp_stack);
+                        changed = true;
+                    }
+                }
+            }
+
+            AppState::Crashed { .. } => (),
+        }
+    }
+
+    // The taskbar changes too
+    changed |=
+        !focus_requests.is_empty() || !close_requests.is_empty() || !open_requests.is_empty();
+
+    // Takes effect on the next frame
+    for app_name in focus_requests {
+        apps_manager.set_on_top(app_name);
+    }
+
+    for app_name in close_requests {
+        let app = apps_manager.get_by_name(&app_name);
+        log::info!("Closing app {} on request", app.descriptor.name);
+        close_app(app, system);
+    }
+
+    for (app_name, path) in open_requests {
+        let workspace = apps_manager.active_workspace;
+        let app = apps_manager.get_by_name(&app_name);
+        log::info!("Opening {} with {}", path, app.descriptor.name);
+        if !app.is_open {
+            app.workspace = workspace;
+        }
+        app.is_open = true;
+        app.minimized = false;
+        app.pending_opens.push(path);
+
+        let app_name = app.descriptor.name;
+        apps_manager.set_on_top(app_name);
+    }
+
+    //
+    // Tray
+
+    let nb_dropped = apps_manager
+        .notifications
+        .len()
+        .saturating_sub(NOTIFICATION_LOG_LEN);
+    apps_manager.notifications.drain(..nb_dropped);
+
+    let recv_history = system.stats.get_system_history(|dp| dp.net_recv);
+    let sent_history = system.stats.get_system_history(|dp| dp.net_sent);
+    let tray_status = TrayStatus {
+        link_up: system.tcp_stack.link_up(),
+        receiving: recv_history
+            .iter()
+            .take(NET_ACTIVITY_FRAMES)
+            .any(|&n| n > 0),
+        sending: sent_history
+            .iter()
+            .take(NET_ACTIVITY_FRAMES)
+            .any(|&n| n > 0),
+        nb_notifications: apps_manager.notifications.len(),
+        clock: format!("{:02}:{:02}", datetime.hour(), datetime.minute()),
+        date: datetime.date_naive(),
+    };
+
+    // Only the taskbar is drawn again when an indicator changes
+    if apps_manager.tray_status.as_ref() != Some(&tray_status) {
+        damage.push(taskbar.rect.clone());
+        apps_manager.tray_status = Some(tray_status.clone());
+    }
+
+    let cursor_hint = match *is {
+        AppsInteractionState::AppHover {
+            hover_kind: HoverKind::Resize(edges),
+            ..
+        }
+        | AppsInteractionState::ResizeHold { edges, .. } => edges.cursor_hint(),
+        _ => cursor_hint,
+    };
+
+    AppsFrame {
+        cursor_hint,
+        damage,
+        changed,
+        hover_state,
+        pie_draw_calls,
+        tray_status,
+    }
+}
+
+// Draws the windows over the wallpaper, then the taskbar and the menus. Windows outside of
+// the region, if any, or hidden behind opaque ones are skipped. Returns how many windows
+// were drawn and skipped.
+pub fn draw_apps<F: FbViewMut>(
+    uitk_context: &mut uitk::UiContext<F>,
+    system: &System,
+    apps_manager: &mut AppsManager,
+    input_state: &InputState,
+    interaction_state: &AppsInteractionState,
+    frame: AppsFrame,
+    region: Option<&Rect>,
+) -> (usize, usize) {
+    let stylesheet = system.theme.stylesheet.clone();
+    let pointer = &input_state.pointer;
+    let fb_shape = uitk_context.fb.shape();
     let font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.medium);
+    let is = interaction_state;
+    let focused = apps_manager.focused();
+    let active_workspace = apps_manager.active_workspace;
+
+    // Windows are only drawn as thumbnails then
+    let overview_state = match *is {
+        AppsInteractionState::Overview {
+            since,
+            leaving,
+            drag,
+        } => Some((overview::progress(since, leaving, uitk_context.time), drag)),
+        _ => None,
+    };
+
+    // The app framebuffer is copied as is over the whole content rect
+    let opaque_rects: Vec<Option<Rect>> = apps_manager
+        .z_ordered
+        .iter()
+        .map(|app| match (&app.app_state, &app.drawn) {
+            (AppState::Active { wasm_app, .. }, Some((_, _, opacity)))
+                if app.is_shown(active_workspace) && *opacity >= 1.0 =>
+            {
+                let content_rect = compute_decorations(app, input_state).content_rect;
+                let (w, h) = wasm_app.get_framebuffer()?.shape();
+                (w >= content_rect.w && h >= content_rect.h).then_some(content_rect)
+            }
+            _ => None,
+        })
+        .collect();
 
-    let n = apps_manager.z_ordered.len();
+    let mut nb_drawn = 0;
+    let mut nb_skipped = 0;
 
     for (i, app) in apps_manager.z_ordered.iter_mut().enumerate() {
-        if !app.is_open {
+        if !app.is_shown(active_workspace) || overview_state.is_some() {
+            continue;
+        }
+        let Some((window_area, _, opacity)) = app.drawn.clone() else {
+            continue;
+        };
+
+        // The audit window goes past the window area
+        let audited = matches!(
+            app.app_state,
+            AppState::Active {
+                audit_mode: AppAuditMode::Enabled { .. },
+                ..
+            }
+        );
+        let outside = region.is_some_and(|region| region.intersection(&window_area).is_none());
+        let occluded = is_occluded(&window_area, opaque_rects[i + 1..].iter().flatten());
+        if !audited && (outside || occluded) {
+            nb_skipped += 1;
             continue;
         }
+        nb_drawn += 1;
 
         let app_name = &app.descriptor.name;
         let deco = compute_decorations(&app, input_state);
+        let is_foreground = focused == Some(*app_name);
+
+        // So that overlapping windows can be told apart
+        let shadow_rect = shadow_rect(&deco.window_rect);
+        if app.shadow.as_ref().map(|fb| fb.shape()) != Some(shadow_rect.shape()) {
+            let (win_w, win_h) = deco.window_rect.shape();
+            let silhouette = Framebuffer::new_owned_filled(win_w, win_h, Color::BLACK);
+            app.shadow = Some(render_shadow(&silhouette, &Shadow::default()));
+        }
+
+        // Only changes with the window area, damaged by run_apps
+        if let Some(shadow_fb) = app.shadow.as_ref() {
+            let damage = uitk_context.fb.take_damage();
+            uitk_context
+                .fb
+                .copy_from_fb(shadow_fb, shadow_rect.origin(), true);
+            uitk_context.fb.set_damage(damage);
+        }
 
         let highlight = match *is {
             AppsInteractionState::AppHover {
@@ -470,7 +1603,26 @@ pub fn run_apps<F: FbViewMut>(
             _ => false,
         };
 
-        let is_foreground = i == n - 1;
+        // Pressed while the pointer stays on it
+        let pressed = match (*is, frame.hover_state) {
+            (
+                AppsInteractionState::ButtonHold {
+                    app_name: held_app_name,
+                    button,
+                },
+                Some((hover_app_name, HoverKind::Button(hover_button))),
+            ) => (held_app_name == *app_name && hover_app_name == held_app_name)
+                .then_some(button)
+                .filter(|button| *button == hover_button),
+            _ => None,
+        };
+        let hovered_button = match *is {
+            AppsInteractionState::AppHover {
+                app_name: hover_app_name,
+                hover_kind: HoverKind::Button(button),
+            } if hover_app_name == *app_name => Some(button),
+            _ => pressed,
+        };
 
         draw_decorations(
             uitk_context.fb,
@@ -479,29 +1631,26 @@ pub fn run_apps<F: FbViewMut>(
             &app.descriptor,
             &deco,
             highlight,
+            is_foreground,
+            hovered_button.map(|button| (button, pressed.is_some())),
+            app.restore_rect.is_some(),
+            opacity,
         );
 
-        match &mut app.app_state {
-            AppState::Init => {
-                let desc = &app.descriptor;
-
-                log::info!("Initializing app {}", desc.name);
-                let wasm_app = wasm_engine.instantiate_app(
-                    system,
-                    uitk_context.uuid_provider,
-                    input_state,
-                    desc.data,
-                    desc.name,
-                    &app.rect,
-                );
-
-                app.app_state = AppState::Active {
-                    wasm_app,
-                    audit_mode: AppAuditMode::Disabled,
-                    paused: false,
-                };
-            }
+        if let Some(notification) = &app.notification {
+            // Kept to half of the titlebar so that the app name stays visible
+            let text = ellipsize_text(notification, font, deco.title_rect.w / 2);
+            draw_line_in_rect(
+                uitk_context.fb,
+                &text,
+                &deco.title_rect,
+                font,
+                Color::YELLOW,
+                TextJustification::Right,
+            );
+        }
 
+        match &mut app.app_state {
             AppState::Active {
                 wasm_app,
                 audit_mode,
@@ -511,93 +1660,278 @@ pub fn run_apps<F: FbViewMut>(
                     draw_line_in_rect(
                         uitk_context.fb,
                         "PAUSED",
-                        &deco.titlebar_rect,
+                        &deco.title_rect,
                         font,
                         Color::YELLOW,
                         TextJustification::Center,
                     );
                 }
 
-                let wasm_res = wasm_app.step(
-                    system,
-                    uitk_context.uuid_provider,
-                    input_state,
-                    &app.rect,
-                    is_foreground,
-                    *paused,
-                );
-
-                match wasm_res {
-                    Ok(()) => {
-                        if let Some(app_fb) = wasm_app.get_framebuffer() {
-                            // To avoid visual glitches when resizing a paused app
-                            let (src_w, src_h) = app_fb.shape();
-                            let Rect {
-                                w: dst_w, h: dst_h, ..
-                            } = deco.content_rect;
-                            if src_w < dst_w || src_h < dst_h {
-                                draw_rect(
-                                    uitk_context.fb,
-                                    &deco.content_rect,
-                                    Color::rgba(0, 0, 0, 200),
-                                    true,
-                                );
-                            }
-
-                            let src = app_fb.subregion(&Rect {
-                                x0: 0,
-                                y0: 0,
-                                w: dst_w,
-                                h: dst_h,
-                            });
+                if let Some(app_fb) = wasm_app.get_framebuffer() {
+                    // To avoid visual glitches when resizing a paused app
+                    let (src_w, src_h) = app_fb.shape();
+                    let Rect {
+                        w: dst_w, h: dst_h, ..
+                    } = deco.content_rect;
+                    if src_w < dst_w || src_h < dst_h {
+                        draw_rect(
+                            uitk_context.fb,
+                            &deco.content_rect,
+                            Color::rgba(0, 0, 0, 200),
+                            true,
+                        );
+                    }
 
+                    let src = app_fb.subregion(&Rect {
+                        x0: 0,
+                        y0: 0,
+                        w: dst_w,
+                        h: dst_h,
+                    });
+
+                    // Damaged by run_apps, from what the app reported
+                    let damage = uitk_context.fb.take_damage();
+                    match opacity < 1.0 {
+                        true => blend_with_opacity(
+                            uitk_context.fb,
+                            &src,
+                            deco.content_rect.origin(),
+                            opacity,
+                        ),
+                        false => {
                             uitk_context
                                 .fb
-                                .copy_from_fb(&src, deco.content_rect.origin(), false);
-
-                            audit_mode.audit_window(
-                                uitk_context,
-                                &app.descriptor.name,
-                                &deco,
-                                &system.stats,
-                                wasm_app.get_console_output(),
-                            );
+                                .copy_from_fb(&src, deco.content_rect.origin(), false)
                         }
                     }
-                    Err(error) => app.app_state = AppState::Crashed { error },
+                    uitk_context.fb.set_damage(damage);
+
+                    audit_mode.audit_window(
+                        uitk_context,
+                        &app.descriptor.name,
+                        &deco,
+                        &system.stats,
+                        wasm_app.get_console_output(),
+                    );
                 }
             }
 
-            AppState::Crashed { error } => {
-                let (x0, y0) = deco.content_rect.origin();
-                draw_str(
+            AppState::Crashed { crash } => {
+                let hovered = match *is {
+                    AppsInteractionState::AppHover {
+                        app_name: hover_app_name,
+                        hover_kind: HoverKind::CrashButton(button),
+                    } if hover_app_name == *app_name => Some(button),
+                    _ => None,
+                };
+                crash_panel::draw(
                     uitk_context.fb,
-                    &format!("{:?}", error),
-                    x0,
-                    y0,
-                    font,
-                    Color::WHITE,
-                    None,
+                    &stylesheet,
+                    app_name,
+                    crash,
+                    &deco.content_rect,
+                    hovered,
                 );
             }
+
+            // Instantiated by run_apps
+            AppState::Init => (),
+        }
+    }
+
+    if let Some((t, drag)) = overview_state {
+        let windows = overview_windows(apps_manager, input_state, fb_shape);
+
+        // Crashed and starting apps have no thumbnail
+        let mut rendered = Vec::new();
+        for window in windows.iter() {
+            let app = apps_manager
+                .z_ordered
+                .iter()
+                .find(|app| app.descriptor.name == window.app_name)
+                .unwrap();
+            let AppState::Active { wasm_app, .. } = &app.app_state else {
+                continue;
+            };
+            let Some(app_fb) = wasm_app.get_framebuffer() else {
+                continue;
+            };
+
+            // Only the part of the framebuffer shown in the window
+            let (src_w, src_h) = app_fb.shape();
+            let (content_w, content_h) = window.content_rect.shape();
+            let src_rect = Rect {
+                x0: 0,
+                y0: 0,
+                w: u32::min(src_w, content_w),
+                h: u32::min(src_h, content_h),
+            };
+            apps_manager.thumbnails.update(
+                window.app_name,
+                app.content_id,
+                &app_fb,
+                &src_rect,
+                window.thumbnail_rect.shape(),
+            );
+            rendered.push(window.app_name);
+        }
+        apps_manager.thumbnails.retain(&rendered);
+
+        overview::draw(
+            uitk_context.fb,
+            &stylesheet,
+            font,
+            &windows,
+            &apps_manager.thumbnails,
+            t,
+            overview::window_at(&windows, pointer.x, pointer.y),
+            (pointer.x, pointer.y),
+            drag,
+        );
+    }
+
+    // Built again, windows may have been opened or closed by run_apps
+    let taskbar = make_taskbar(apps_manager, fb_shape);
+    let launcher_menu = LauncherMenu::new(&taskbar);
+    let quick_settings_menu = QuickSettingsMenu::new(&taskbar, &system.display_modes);
+    let launcher_open = *is == AppsInteractionState::LauncherMenu;
+    let quick_settings_open = *is == AppsInteractionState::QuickSettingsMenu;
+    let tray_open = match *is {
+        AppsInteractionState::TrayPopup(item) => Some(item),
+        _ => None,
+    };
+    taskbar.draw(
+        uitk_context.fb,
+        &stylesheet,
+        font,
+        taskbar.item_at(pointer.x, pointer.y),
+        launcher_open,
+        quick_settings_open,
+        &frame.tray_status,
+        tray_open,
+    );
+    if launcher_open {
+        let launcher_hover = launcher_menu.app_at(pointer.x, pointer.y);
+        launcher_menu.draw(uitk_context.fb, &stylesheet, font, launcher_hover);
+    }
+    if quick_settings_open {
+        let theme_index = resources::THEMES
+            .iter()
+            .position(|theme| core::ptr::eq(theme, system.theme))
+            .unwrap();
+        let current = [
+            QuickSetting::Theme(theme_index),
+            QuickSetting::Wallpaper(system.wallpaper.selected),
+            QuickSetting::Scaling(system.wallpaper.mode),
+            QuickSetting::ShadeUnfocused(system.shade_unfocused),
+            QuickSetting::Resolution(fb_shape.0, fb_shape.1),
+        ];
+        quick_settings_menu.draw(
+            uitk_context.fb,
+            &stylesheet,
+            font,
+            &current,
+            quick_settings_menu.setting_at(pointer.x, pointer.y),
+        );
+    }
+    match tray_open {
+        Some(TrayItem::Notifications) => {
+            let notifications = &apps_manager.notifications;
+            let panel = NotificationsPanel::new(&taskbar.tray, notifications.len());
+            let clear_hovered = panel.clear_all_at(pointer.x, pointer.y);
+            panel.draw(
+                uitk_context.fb,
+                &stylesheet,
+                font,
+                notifications,
+                clear_hovered,
+            );
+        }
+        Some(TrayItem::Clock) => {
+            let calendar = CalendarPopup::new(&taskbar.tray);
+            calendar.draw(uitk_context.fb, &stylesheet, font, frame.tray_status.date);
+        }
+        _ => (),
+    }
+
+    // Where the dragged window would snap
+    if let AppsInteractionState::TitlebarHold { .. } = *is {
+        if let Some(rect) = edge_snap_rect(pointer, fb_shape) {
+            let (r, g, b, _) = stylesheet.colors.accent.as_rgba();
+            let rect = rect.offset(BORDER_THICKNESS as i64);
+            draw_rect(uitk_context.fb, &rect, Color::rgba(r, g, b, 80), true);
         }
     }
 
-    if let Some(draw_calls) = pie_draw_calls {
+    if let AppsInteractionState::WindowSwitcher { selected } = *is {
+        let apps = apps_manager.switcher_order();
+        draw_window_switcher(uitk_context.fb, &stylesheet, font, &apps, selected);
+    }
+
+    if let Some(draw_calls) = frame.pie_draw_calls {
         draw_calls.draw(uitk_context.fb);
     }
+
+    (nb_drawn, nb_skipped)
+}
+
+// Covered by the window, its decorations and its shadow
+fn window_area(deco: &AppDecorations) -> Rect {
+    deco.window_rect
+        .bounding_box(&deco.icon_rect)
+        .bounding_box(&deco.titlebar_rect)
+        .bounding_box(&shadow_rect(&deco.window_rect))
+}
+
+fn shadow_rect(window_rect: &Rect) -> Rect {
+    let shadow = Shadow::default();
+    let (x0, y0) = shadow.origin(window_rect.origin());
+    let (w, h) = window_rect.shape();
+    Rect {
+        x0,
+        y0,
+        w: w + 2 * shadow.radius,
+        h: h + 2 * shadow.radius,
+    }
+}
+
+fn window_opacity(app: &App, is_foreground: bool, shade_unfocused: bool) -> f32 {
+    match shade_unfocused && !is_foreground {
+        true => app.opacity * SHADE_OPACITY,
+        false => app.opacity,
+    }
+}
+
+// Whether nothing of the area is left once the covering rects are taken out of it
+fn is_occluded<'a>(area: &Rect, covers: impl Iterator<Item = &'a Rect>) -> bool {
+    let mut visible = Vec::from([area.clone()]);
+    for cover in covers {
+        visible = visible
+            .iter()
+            .flat_map(|rect| rect.subtract(cover))
+            .collect();
+        if visible.is_empty() {
+            return true;
+        }
+    }
+    false
 }
 
 struct AppDecorations {
     content_rect: Rect,
     window_rect: Rect,
     titlebar_rect: Rect,
+    // Part of the titlebar left for the text, next to the buttons
+    title_rect: Rect,
+    button_rects: [Rect; 3],
+    button_hover: Option<TitlebarButton>,
     icon_rect: Rect,
     resize_zone_rect: Rect,
     border_rects: [Rect; 4],
     handle_rects: [Rect; 2],
     titlebar_hover: bool,
-    resize_hover: bool,
+    // Where a drag from the pointer would resize the window, if anywhere
+    resize_edges: Option<ResizeEdges>,
     window_hover: bool,
     handle_h: u32,
 }
@@ -809,10 +2143,100 @@ fn get_hold_anchor(pointer: &PointerState, rect: &Rect) -> Point2D<i64> {
     Point2D { x: dx, y: dy }
 }
 
-fn position_window(preferred_rect: &Rect, fb_shape: (u32, u32), deco: &AppDecorations) -> Rect {
-    // TODO: ideally, this should be computed dynamically...
-    const TOPBAR_GAP: u32 = 68;
+// Where the window goes if dropped with the pointer there: a half of the workspace
+// next to the left and right screen edges, all of it next to the top one
+fn edge_snap_rect(pointer: &PointerState, fb_shape: (u32, u32)) -> Option<Rect> {
+    let (fb_w, _) = fb_shape;
+    let [maximized, left, right] = snap_layouts(fb_shape);
+
+    if pointer.y < TOPBAR_H as i64 {
+        Some(maximized)
+    } else if pointer.x < EDGE_SNAP_DIST as i64 {
+        Some(left)
+    } else if pointer.x >= (fb_w - EDGE_SNAP_DIST) as i64 {
+        Some(right)
+    } else {
+        None
+    }
+}
+
+// Maximized, left half and right half
+fn snap_layouts(fb_shape: (u32, u32)) -> [Rect; 3] {
+    let (fb_w, _) = fb_shape;
+    let [x0, y0, x1, y1] = workspace_rect(fb_shape).as_xyxy();
+    let mid = (fb_w / 2) as i64;
+    let border = BORDER_THICKNESS as i64;
+
+    [
+        workspace_rect(fb_shape),
+        Rect::from_xyxy([x0, y0, mid - border - 1, y1]),
+        Rect::from_xyxy([mid + border, y0, x1, y1]),
+    ]
+}
+
+// The rect before snapping is kept to be restored when the window is dragged away
+fn drop_window(app: &mut App, pointer: &PointerState, fb_shape: (u32, u32)) {
+    if let Some(rect) = edge_snap_rect(pointer, fb_shape) {
+        app.restore_rect = Some(app.restore_rect.take().unwrap_or(app.rect.clone()));
+        app.rect = rect;
+    }
+}
+
+// Offset which lines up the sides of the window with the nearest sides of the other
+// windows, if they are close enough. Rects are the outer ones, borders included.
+fn snap_to_windows(rect: &Rect, other_rects: &[Rect]) -> (i64, i64) {
+    let [x0, y0, x1, y1] = rect.as_xyxy();
+    let d = WINDOW_SNAP_DIST as i64;
+
+    let mut best_dx: Option<i64> = None;
+    let mut best_dy: Option<i64> = None;
+    let consider = |best: &mut Option<i64>, offset: i64| {
+        if offset.abs() <= d && best.map_or(true, |b| offset.abs() < b.abs()) {
+            *best = Some(offset);
+        }
+    };
+
+    for other in other_rects {
+        let [ox0, oy0, ox1, oy1] = other.as_xyxy();
+
+        // Only windows which are side by side, or about to be
+        if y0 <= oy1 + d && oy0 <= y1 + d {
+            for offset in [ox1 + 1 - x0, ox0 - x0, ox0 - 1 - x1, ox1 - x1] {
+                consider(&mut best_dx, offset);
+            }
+        }
+        if x0 <= ox1 + d && ox0 <= x1 + d {
+            for offset in [oy1 + 1 - y0, oy0 - y0, oy0 - 1 - y1, oy1 - y1] {
+                consider(&mut best_dy, offset);
+            }
+        }
+    }
+
+    (best_dx.unwrap_or(0), best_dy.unwrap_or(0))
+}
+
+// Origin of the window once moved so that the titlebar stays between the topbar and the
+// taskbar, with at least some of it on screen horizontally
+fn constrain_drag(
+    rect: &Rect,
+    deco: &AppDecorations,
+    origin: (i64, i64),
+    fb_shape: (u32, u32),
+) -> (i64, i64) {
+    let (fb_w, fb_h) = fb_shape;
+    let titlebar = &deco.titlebar_rect;
+    let (dx, dy) = (titlebar.x0 - rect.x0, titlebar.y0 - rect.y0);
+    let visible = DRAG_MIN_VISIBLE as i64;
+
+    let x0 = i64::max(visible - titlebar.w as i64, origin.0 + dx);
+    let x0 = i64::min(fb_w as i64 - visible, x0);
+    let y0 = i64::max(TOPBAR_H as i64, origin.1 + dy);
+    let y0 = i64::min((fb_h - TASKBAR_H - titlebar.h) as i64, y0);
 
+    (x0 - dx, y0 - dy)
+}
+
+fn position_window(preferred_rect: &Rect, fb_shape: (u32, u32)) -> Rect {
     let (fb_w, fb_h) = fb_shape;
     let Rect {
         mut x0,
@@ -823,17 +2247,285 @@ fn position_window(preferred_rect: &Rect, fb_shape: (u32, u32), deco: &AppDecora
 
     let min_y0 = TOPBAR_H + TOPBAR_GAP;
 
+    // The top left corner stays on screen if the window is too large for it
+    x0 = i64::min(fb_w as i64 - w as i64 - 1, x0);
+    y0 = i64::min(fb_h as i64 - (TASKBAR_H + h) as i64 - 1, y0);
     x0 = i64::max(0, x0);
     y0 = i64::max(min_y0 as i64, y0);
-    x0 = i64::min((fb_w - w - 1) as i64, x0);
-    y0 = i64::min((fb_h - h - 1) as i64, y0);
 
     Rect { x0, y0, w, h }
 }
 
+// Shrunk if needed, then moved inside the workspace
+fn clamp_to_workspace(rect: &Rect, fb_shape: (u32, u32)) -> Rect {
+    let workspace = workspace_rect(fb_shape);
+    let [ws_x0, ws_y0, ws_x1, ws_y1] = workspace.as_xyxy();
+    let w = u32::min(rect.w, workspace.w);
+    let h = u32::min(rect.h, workspace.h);
+
+    Rect {
+        x0: rect.x0.clamp(ws_x0, ws_x1 + 1 - w as i64),
+        y0: rect.y0.clamp(ws_y0, ws_y1 + 1 - h as i64),
+        w,
+        h,
+    }
+}
+
+// Where window contents can go, between the topbar and the taskbar and with room for
+// the borders
+fn workspace_rect(fb_shape: (u32, u32)) -> Rect {
+    let (fb_w, fb_h) = fb_shape;
+    let border = BORDER_THICKNESS as i64;
+    Rect::from_xyxy([
+        border,
+        (TOPBAR_H + TOPBAR_GAP) as i64,
+        fb_w as i64 - border - 1,
+        (fb_h - TASKBAR_H) as i64 - border - 1,
+    ])
+}
+
+// Brings the window up, where it was if it is minimized. A window being opened lands on
+// the active workspace, an open one stays on its own.
+// A crash is shown in the window, other apps are not affected
+fn instantiate_app(
+    app: &mut App,
+    system: &mut System,
+    wasm_engine: &WasmEngine,
+    uuid_provider: &mut uitk::UuidProvider,
+    input_state: &InputState,
+) {
+    let desc = &app.descriptor;
+
+    log::info!("Initializing app {}", desc.name);
+    let wasm_res = wasm_engine.instantiate_app(
+        system,
+        uuid_provider,
+        input_state,
+        desc.data,
+        desc.name,
+        &app.rect,
+    );
+
+    app.app_state = match wasm_res {
+        Ok(wasm_app) => AppState::Active {
+            wasm_app,
+            audit_mode: AppAuditMode::Disabled,
+            paused: false,
+        },
+        Err(crash) => AppState::Crashed { crash },
+    };
+}
+
+fn show_window(
+    app: &mut App,
+    preferred_rect: &Rect,
+    fb_shape: (u32, u32),
+    active_workspace: usize,
+) {
+    if !app.is_open {
+        app.workspace = active_workspace;
+    }
+    if !app.minimized {
+        app.rect = position_window(preferred_rect, fb_shape);
+    }
+    app.is_open = true;
+    app.minimized = false;
+}
+
+// Picks up from where the transition is if it was going the other way
+fn toggle_overview(is: &mut AppsInteractionState, time: f64) {
+    *is = match *is {
+        AppsInteractionState::Overview { since, leaving, .. } => AppsInteractionState::Overview {
+            since: overview::reversed_since(since, time),
+            leaving: !leaving,
+            drag: None,
+        },
+        _ => AppsInteractionState::Overview {
+            since: time,
+            leaving: false,
+            drag: None,
+        },
+    };
+}
+
+// Shown windows of the active workspace, in the same order as in the taskbar
+fn overview_windows(
+    apps_manager: &AppsManager,
+    input_state: &InputState,
+    fb_shape: (u32, u32),
+) -> Vec<OverviewWindow> {
+    let mut windows: Vec<(&'static str, Rect)> = apps_manager
+        .z_ordered
+        .iter()
+        .filter(|app| app.is_shown(apps_manager.active_workspace))
+        .map(|app| {
+            let content_rect = compute_decorations(app, input_state).content_rect;
+            (app.descriptor.name, content_rect)
+        })
+        .collect();
+    windows.sort_by_key(|(app_name, _)| *app_name);
+
+    overview::layout(windows, fb_shape)
+}
+
+// Buttons are sorted by name, so that they do not move around when the focus changes
+fn make_taskbar(apps_manager: &AppsManager, fb_shape: (u32, u32)) -> Taskbar {
+    let focused = apps_manager.focused();
+    let active_workspace = apps_manager.active_workspace;
+
+    let workspaces = (0..NB_WORKSPACES)
+        .map(|i| WorkspaceEntry {
+            active: i == active_workspace,
+            occupied: apps_manager
+                .z_ordered
+                .iter()
+                .any(|app| app.is_open && app.workspace == i),
+        })
+        .collect();
+
+    let mut entries: Vec<TaskbarEntry> = apps_manager
+        .z_ordered
+        .iter()
+        .filter(|app| app.is_open && app.workspace == active_workspace)
+        .map(|app| TaskbarEntry {
+            name: app.descriptor.name,
+            minimized: app.minimized,
+            focused: focused == Some(app.descriptor.name),
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.name);
+
+    Taskbar::new(fb_shape, workspaces, entries)
+}
+
+fn toggle_maximized(app: &mut App, fb_shape: (u32, u32)) {
+    match app.restore_rect.take() {
+        Some(rect) => app.rect = rect,
+        None => {
+            app.restore_rect = Some(app.rect.clone());
+            app.rect = workspace_rect(fb_shape);
+        }
+    }
+}
+
+fn crash_app(app_state: &mut AppState, error: anyhow::Error, tcp_stack: &mut TcpStack) {
+    if let AppState::Active { wasm_app, .. } = core::mem::replace(app_state, AppState::Init) {
+        let crash = wasm_app.crash(error, tcp_stack);
+        *app_state = AppState::Crashed { crash };
+    }
+}
+
+// Closing from the desktop. Apps that guard it are brought to the front and asked
+// instead, and close themselves once they agree. Closing again before the app took the
+// request closes it anyway, so that a stuck or paused app can still be closed.
+fn request_close(apps_manager: &mut AppsManager, app_name: &'static str, system: &mut System) {
+    let app = apps_manager.get_mut(app_name);
+    match &mut app.app_state {
+        AppState::Active { wasm_app, .. } if wasm_app.close_guarded() => {
+            if wasm_app.close_pending() {
+                log::warn!(
+                    "App {} did not answer the close request, closing it",
+                    app_name
+                );
+                close_app(app, system);
+            } else {
+                wasm_app.request_close();
+                app.minimized = false;
+                apps_manager.activate(app_name);
+            }
+        }
+        _ => close_app(app, system),
+    }
+}
+
+// The app starts from scratch when it is opened again
+fn close_app(app: &mut App, system: &mut System) {
+    let app_state = core::mem::replace(&mut app.app_state, AppState::Init);
+    if let AppState::Active { wasm_app, .. } = app_state {
+        wasm_app.teardown(&mut system.tcp_stack);
+    }
+    system.stats.clear_app(app.descriptor.name);
+    app.is_open = false;
+    app.minimized = false;
+    app.restore_rect = None;
+}
+
+// The moved sides follow the pointer, as long as the window keeps its minimum size and
+// stays on screen. Sides which were already off screen can stay there.
+fn resize_window(
+    start_xyxy: [i64; 4],
+    edges: ResizeEdges,
+    delta: (i64, i64),
+    min_size: (u32, u32),
+    fb_shape: (u32, u32),
+) -> Rect {
+    let [x0, y0, x1, y1] = start_xyxy;
+    let [bx0, by0, bx1, by1] = workspace_rect(fb_shape).as_xyxy();
+
+    let (min_w, min_h) = min_size;
+    let x_bounds = (bx0, bx1);
+    let y_bounds = (by0, by1);
+    let (x0, x1) = resize_axis(edges.x, (x0, x1), delta.0, min_w, x_bounds);
+    let (y0, y1) = resize_axis(edges.y, (y0, y1), delta.1, min_h, y_bounds);
+
+    Rect::from_xyxy([x0, y0, x1, y1])
+}
+
+// Start and end of the window along one axis
+fn resize_axis(
+    side: i64,
+    span: (i64, i64),
+    d: i64,
+    min_len: u32,
+    bounds: (i64, i64),
+) -> (i64, i64) {
+    let (start, end) = span;
+    let min_len = min_len as i64;
+    match side {
+        -1 => {
+            let start = i64::max(start + d, i64::min(start, bounds.0));
+            (i64::min(start, end - min_len + 1), end)
+        }
+        1 => {
+            let end = i64::min(end + d, i64::max(end, bounds.1));
+            (start, i64::max(end, start + min_len - 1))
+        }
+        _ => (start, end),
+    }
+}
+
+// On the border of the window, near a corner it resizes along both axes
+fn border_resize_edges(
+    window_rect: &Rect,
+    content_rect: &Rect,
+    x: i64,
+    y: i64,
+) -> Option<ResizeEdges> {
+    const CORNER_LEN: i64 = 32;
+
+    if !window_rect.check_contains_point(x, y) || content_rect.check_contains_point(x, y) {
+        return None;
+    }
+
+    let [x0, y0, x1, y1] = window_rect.as_xyxy();
+    let side = |pos: i64, start: i64, end: i64| {
+        if pos < start + CORNER_LEN {
+            -1
+        } else if pos > end - CORNER_LEN {
+            1
+        } else {
+            0
+        }
+    };
+
+    Some(ResizeEdges {
+        x: side(x, x0, x1),
+        y: side(y, y0, y1),
+    })
+}
+
 fn compute_decorations(app: &App, input_state: &InputState) -> AppDecorations {
     const TITLEBAR_HEIGHT: u32 = 32;
-    const BORDER_THICKNESS: u32 = 8;
     const RESIZE_HANDLE_LEN: u32 = 32;
     const RESIZE_HANDLE_GAP: u32 = 2;
     const RESIZE_ZONE_LEN: u32 = 32;
@@ -907,11 +2599,32 @@ fn compute_decorations(app: &App, input_state: &InputState) -> AppDecorations {
         h: RESIZE_HANDLE_LEN - BORDER_THICKNESS,
     };
 
+    let buttons_w = TITLEBAR_BUTTONS.len() as u32 * TITLEBAR_HEIGHT;
+    let title_rect = Rect {
+        w: titlebar_rect.w.saturating_sub(buttons_w),
+        ..titlebar_rect.clone()
+    };
+    let button_rects = core::array::from_fn(|i| Rect {
+        x0: title_rect.x0 + (title_rect.w + i as u32 * TITLEBAR_HEIGHT) as i64,
+        y0: titlebar_rect.y0,
+        w: TITLEBAR_HEIGHT,
+        h: TITLEBAR_HEIGHT,
+    });
+
     let pointer = &input_state.pointer;
+    let button_hover = TITLEBAR_BUTTONS
+        .iter()
+        .zip(button_rects.iter())
+        .find(|(_, rect)| rect.check_contains_point(pointer.x, pointer.y))
+        .map(|(button, _)| *button);
     let titlebar_hover = icon_rect.check_contains_point(pointer.x, pointer.y)
         || titlebar_rect.check_contains_point(pointer.x, pointer.y);
     let resize_hover = resize_zone_rect.check_contains_point(pointer.x, pointer.y);
     let window_hover = window_rect.check_contains_point(pointer.x, pointer.y);
+    let resize_edges = match resize_hover {
+        true => Some(ResizeEdges { x: 1, y: 1 }),
+        false => border_resize_edges(&window_rect, &app.rect, pointer.x, pointer.y),
+    };
 
     if resize_hover {
         let offet_vec = Vec2D { x: 1, y: 1 } * RESIZE_HANDLE_OFFSET as i64;
@@ -924,8 +2637,11 @@ fn compute_decorations(app: &App, input_state: &InputState) -> AppDecorations {
         window_rect,
         icon_rect,
         titlebar_rect,
+        title_rect,
+        button_rects,
+        button_hover,
         titlebar_hover,
-        resize_hover,
+        resize_edges,
         window_hover,
         border_rects: [
             left_border_rect,
@@ -939,6 +2655,126 @@ fn compute_decorations(app: &App, input_state: &InputState) -> AppDecorations {
     }
 }
 
+// Centered on the screen, lists the windows from the top one down
+fn draw_window_switcher<F: FbViewMut>(
+    fb: &mut F,
+    stylesheet: &StyleSheet,
+    font: &Font,
+    apps: &[&App],
+    selected: usize,
+) {
+    const ROW_W: u32 = 320;
+    const ROW_H: u32 = 44;
+    const ICON_MARGIN_W: u32 = 8;
+
+    let (fb_w, fb_h) = fb.shape();
+    let padding = stylesheet.widgets.padding;
+    let rect = Rect::from_center(
+        (fb_w / 2) as i64,
+        (fb_h / 2) as i64,
+        ROW_W + 2 * padding,
+        apps.len() as u32 * ROW_H + 2 * padding,
+    );
+
+    draw_rect(fb, &rect, stylesheet.colors.background, false);
+    draw_rect_outline(fb, &rect, Color::BLACK, false, stylesheet.margin);
+
+    for (i, app) in apps.iter().enumerate() {
+        let row_rect = Rect {
+            x0: rect.x0 + padding as i64,
+            y0: rect.y0 + (padding + i as u32 * ROW_H) as i64,
+            w: ROW_W,
+            h: ROW_H,
+        };
+
+        if i == selected {
+            draw_rect(fb, &row_rect, stylesheet.widgets.selected.bg, false);
+        }
+
+        let icon = app.descriptor.icon;
+        let (icon_w, icon_h) = icon.shape();
+        let icon_rect = Rect {
+            x0: row_rect.x0 + ICON_MARGIN_W as i64,
+            y0: 0,
+            w: icon_w,
+            h: icon_h,
+        }
+        .align_to_rect_vert(&row_rect);
+        fb.copy_from_fb(icon, icon_rect.origin(), true);
+
+        // Dimmed while the window is hidden
+        let text_color = match (i == selected, app.minimized) {
+            (true, _) => stylesheet.widgets.selected.text,
+            (false, true) => stylesheet.widgets.disabled.text,
+            (false, false) => stylesheet.colors.text,
+        };
+        let text_x0 = icon_rect.x0 + (icon_w + ICON_MARGIN_W) as i64;
+        let [_, y0, x1, y1] = row_rect.as_xyxy();
+        let text_rect = Rect::from_xyxy([text_x0, y0, x1, y1]);
+        let text = ellipsize_text(app.descriptor.name, font, text_rect.w);
+        draw_line_in_rect(
+            fb,
+            &text,
+            &text_rect,
+            font,
+            text_color,
+            TextJustification::Left,
+        );
+    }
+}
+
+// State is None when the button is not hovered, otherwise whether it is pressed
+fn draw_titlebar_button<F: FbViewMut>(
+    fb: &mut F,
+    stylesheet: &StyleSheet,
+    button: TitlebarButton,
+    rect: &Rect,
+    state: Option<bool>,
+    maximized: bool,
+) {
+    let widgets = &stylesheet.widgets;
+    let (bg, color) = match (state, button) {
+        (Some(true), _) => (Some(widgets.pressed.bg), widgets.pressed.text),
+        (Some(false), TitlebarButton::Close) => (Some(stylesheet.colors.red), widgets.hover.text),
+        (Some(false), _) => (Some(widgets.hover.bg), widgets.hover.text),
+        (None, _) => (None, stylesheet.colors.text),
+    };
+
+    // Inset so that the titlebar outline stays visible
+    if let Some(bg) = bg {
+        draw_rect(fb, &rect.offset(-(stylesheet.margin as i64)), bg, false);
+    }
+
+    const MARK_W: u32 = 10;
+    let (xc, yc) = rect.center();
+    let mark = Rect::from_center(xc, yc, MARK_W, MARK_W);
+    let [x0, y0, x1, y1] = mark.as_xyxy();
+
+    match button {
+        TitlebarButton::Minimize => {
+            draw_rect(fb, &Rect::from_xyxy([x0, y1 - 1, x1, y1]), color, false);
+        }
+        TitlebarButton::Maximize if maximized => {
+            // Two overlapping windows, only the top and right sides of the back one show
+            let front = Rect::from_xyxy([x0, y0 + 3, x1 - 3, y1]);
+            draw_rect(fb, &Rect::from_xyxy([x0 + 3, y0, x1, y0]), color, false);
+            draw_rect(fb, &Rect::from_xyxy([x1, y0, x1, y1 - 3]), color, false);
+            draw_rect_outline(fb, &front, color, false, 1);
+        }
+        TitlebarButton::Maximize => {
+            draw_rect_outline(fb, &mark, color, false, 1);
+            draw_rect(fb, &Rect::from_xyxy([x0, y0, x1, y0 + 1]), color, false);
+        }
+        TitlebarButton::Close => {
+            let style = StrokeStyle::solid(2);
+            let (p0, p1) = (Point2D { x: x0, y: y0 }, Point2D { x: x1, y: y1 });
+            let (p2, p3) = (Point2D { x: x0, y: y1 }, Point2D { x: x1, y: y0 });
+            draw_line(fb, p0, p1, color, &style, true);
+            draw_line(fb, p2, p3, color, &style, true);
+        }
+    }
+}
+
 fn draw_decorations<F: FbViewMut>(
     fb: &mut F,
     stylesheet: &StyleSheet,
@@ -946,13 +2782,41 @@ fn draw_decorations<F: FbViewMut>(
     app_descriptor: &AppDescriptor,
     deco: &AppDecorations,
     highlight: bool,
+    focused: bool,
+    button_state: Option<(TitlebarButton, bool)>,
+    maximized: bool,
+    opacity: f32,
 ) {
-    let color_deco = match highlight {
-        true => stylesheet.colors.hover_overlay,
-        false => stylesheet.colors.frame,
+    let titlebar = &stylesheet.titlebar;
+    let frame_alpha = (titlebar.frame_alpha as f32 * opacity.clamp(0.0, 1.0)) as u8;
+    let translucent = |color: Color| {
+        let (r, g, b, _) = color.as_rgba();
+        Color::rgba(r, g, b, frame_alpha)
+    };
+
+    // The hover overlay is blended over the frame color
+    let color_deco = match focused {
+        true => translucent(stylesheet.colors.frame_focused),
+        false => translucent(stylesheet.colors.frame),
+    };
+    let fill_deco = |fb: &mut F, rect: &Rect| {
+        draw_rect(fb, rect, color_deco, true);
+        if highlight {
+            draw_rect(fb, rect, stylesheet.colors.hover_overlay, true);
+        }
     };
 
-    draw_rect(fb, &deco.titlebar_rect, color_deco, false);
+    // Windows without the focus and translucent ones have a plain titlebar
+    match titlebar.gradient && focused && !highlight && frame_alpha == 255 {
+        true => fill_linear_gradient(
+            fb,
+            &deco.titlebar_rect,
+            titlebar.gradient_start,
+            titlebar.gradient_end,
+            titlebar.gradient_direction,
+        ),
+        false => fill_deco(fb, &deco.titlebar_rect),
+    }
     draw_rect_outline(
         fb,
         &deco.titlebar_rect,
@@ -961,10 +2825,10 @@ fn draw_decorations<F: FbViewMut>(
         stylesheet.margin,
     );
     for rect in deco.border_rects.iter() {
-        draw_rect(fb, rect, color_deco, false);
+        fill_deco(fb, rect);
     }
 
-    draw_rect(fb, &deco.icon_rect, color_deco, false);
+    fill_deco(fb, &deco.icon_rect);
     draw_rect_outline(fb, &deco.icon_rect, Color::BLACK, false, stylesheet.margin);
     let icon_fb_rect = {
         let (xc, yc) = deco.icon_rect.center();
@@ -973,17 +2837,24 @@ fn draw_decorations<F: FbViewMut>(
     };
     fb.copy_from_fb(app_descriptor.icon, icon_fb_rect.origin(), true);
 
-    let ellipsized_title = ellipsize_text(app_descriptor.name, font, deco.titlebar_rect.w);
+    let ellipsized_title = ellipsize_text(app_descriptor.name, font, deco.title_rect.w);
 
     draw_line_in_rect(
         fb,
         &ellipsized_title,
-        &deco.titlebar_rect,
+        &deco.title_rect,
         font,
         stylesheet.colors.text,
         TextJustification::Left,
     );
 
+    for (button, rect) in TITLEBAR_BUTTONS.iter().zip(deco.button_rects.iter()) {
+        let state = button_state
+            .filter(|(b, _)| b == button)
+            .map(|(_, pressed)| pressed);
+        draw_titlebar_button(fb, stylesheet, *button, rect, state, maximized);
+    }
+
     for rect in deco.handle_rects.iter() {
         draw_rect(fb, rect, stylesheet.colors.accent, false);
     }
@@ -1024,16 +2895,3 @@ fn draw_decorations<F: FbViewMut>(
         false,
     );
 }
-
-fn ellipsize_text(txt: &str, font: &Font, max_len: u32) -> String {
-    let max_chars = max_len as usize / font.char_w;
-
-    if txt.len() <= max_chars {
-        txt.to_owned()
-    } else if max_chars < 3 {
-        String::new()
-    } else {
-        let s = txt.chars().take(max_chars - 3).collect::<String>();
-        format!("{}...", s)
-    }
-}
@@ -0,0 +1,159 @@
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use applib::Rect;
+
+use crate::storage::{Storage, StorageError};
+
+// One app per line, as "x0 y0 w h workspace name", in launch order
+const AUTOSTART_FILE: &str = "kernel.autostart.cfg";
+
+// Holding Shift until then skips autostart
+const START_DELAY: f64 = 1000.0; // ms
+
+// Between two apps, so that instantiating them does not stall a single frame
+const STAGGER_DELAY: f64 = 200.0; // ms
+
+#[derive(Debug, Clone)]
+pub struct AutostartEntry {
+    pub app_name: String,
+    pub rect: Rect,
+    pub workspace: usize,
+}
+
+pub fn load(storage: &Storage) -> Vec<AutostartEntry> {
+    let data = match storage.read(AUTOSTART_FILE) {
+        Ok(data) => data,
+        Err(StorageError::NotFound) => return Vec::new(),
+        Err(err) => {
+            log::error!("Cannot read {}: {:?}", AUTOSTART_FILE, err);
+            return Vec::new();
+        }
+    };
+
+    let Ok(text) = core::str::from_utf8(data) else {
+        log::error!("{} is not valid UTF-8", AUTOSTART_FILE);
+        return Vec::new();
+    };
+
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let entry = parse_line(line);
+            if entry.is_none() {
+                log::warn!("Ignoring autostart line {:?}", line);
+            }
+            entry
+        })
+        .collect()
+}
+
+fn save(storage: &mut Storage, entries: &[AutostartEntry], time: f64) {
+    let text: String = entries
+        .iter()
+        .map(|entry| {
+            let Rect { x0, y0, w, h } = entry.rect;
+            format!(
+                "{} {} {} {} {} {}\n",
+                x0, y0, w, h, entry.workspace, entry.app_name
+            )
+        })
+        .collect();
+
+    if let Err(err) = storage.write(AUTOSTART_FILE, text.as_bytes(), time) {
+        log::error!("Cannot write {}: {:?}", AUTOSTART_FILE, err);
+    }
+}
+
+fn parse_line(line: &str) -> Option<AutostartEntry> {
+    // The name goes last, it may have spaces
+    let mut fields = line.splitn(6, ' ');
+    let mut numbers = [0i64; 5];
+    for number in numbers.iter_mut() {
+        *number = fields.next()?.parse().ok()?;
+    }
+    let [x0, y0, w, h, workspace] = numbers;
+    let app_name = fields.next()?.trim();
+
+    let valid = w > 0 && h > 0 && workspace >= 0 && !app_name.is_empty();
+    valid.then(|| AutostartEntry {
+        app_name: app_name.to_string(),
+        rect: Rect {
+            x0,
+            y0,
+            w: w as u32,
+            h: h as u32,
+        },
+        workspace: workspace as usize,
+    })
+}
+
+pub fn contains(storage: &Storage, app_name: &str) -> bool {
+    load(storage).iter().any(|entry| entry.app_name == app_name)
+}
+
+// Adds the app with where its window is now, or removes it if it was in the list
+pub fn toggle(storage: &mut Storage, app_name: &str, rect: &Rect, workspace: usize, time: f64) {
+    let mut entries = load(storage);
+    match entries.iter().position(|entry| entry.app_name == app_name) {
+        Some(i) => {
+            entries.remove(i);
+            log::info!("{} removed from autostart", app_name);
+        }
+        None => {
+            entries.push(AutostartEntry {
+                app_name: app_name.to_string(),
+                rect: rect.clone(),
+                workspace,
+            });
+            log::info!("{} added to autostart", app_name);
+        }
+    }
+    save(storage, &entries, time);
+}
+
+// Hands out the apps to open at boot one at a time
+pub struct Autostart {
+    pending: VecDeque<AutostartEntry>,
+    // Set on the first poll, the main loop may start long after boot began
+    next_at: Option<f64>,
+    started: bool,
+}
+
+impl Autostart {
+    pub fn new(storage: &Storage) -> Self {
+        let pending: VecDeque<AutostartEntry> = load(storage).into();
+        if !pending.is_empty() {
+            log::info!("{} apps to autostart, hold Shift to skip", pending.len());
+        }
+        Autostart {
+            pending,
+            next_at: None,
+            started: false,
+        }
+    }
+
+    // The next app to open, if it is time. Shift only skips autostart before the first
+    // app is opened, so that typing in it does not.
+    pub fn poll(&mut self, shift: bool, time: f64) -> Option<AutostartEntry> {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        if shift && !self.started {
+            log::info!("Shift is held, skipping autostart");
+            self.pending.clear();
+            return None;
+        }
+
+        let next_at = *self.next_at.get_or_insert(time + START_DELAY);
+        if time < next_at {
+            return None;
+        }
+
+        self.started = true;
+        self.next_at = Some(time + STAGGER_DELAY);
+        self.pending.pop_front()
+    }
+}
@@ -0,0 +1,25 @@
+pub const SECTOR_SIZE: usize = 512;
+
+pub type Sector = [u8; SECTOR_SIZE];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockError {
+    OutOfRange,
+    ReadOnly,
+    // The device failed the request
+    Io,
+    Unsupported,
+}
+
+// Storage addressed by sector, for a filesystem to sit on top of
+pub trait BlockDevice {
+    fn nb_sectors(&self) -> u64;
+
+    fn read_sector(&mut self, lba: u64, buf: &mut Sector) -> Result<(), BlockError>;
+
+    // May return before the data reaches the device, errors then come out of sync()
+    fn write_sector(&mut self, lba: u64, buf: &Sector) -> Result<(), BlockError>;
+
+    // Returns once everything written before is on persistent storage
+    fn sync(&mut self) -> Result<(), BlockError>;
+}
@@ -0,0 +1,161 @@
+use alloc::format;
+use applib::drawing::primitives::draw_rect;
+use applib::drawing::text::{draw_line_in_rect, ellipsize_text, get_font, Font, TextJustification};
+use applib::{Color, FbViewMut, Rect, StyleSheet};
+
+use crate::wasm::AppCrash;
+
+const PADDING: u32 = 16;
+const BUTTON_W: u32 = 100;
+const BUTTON_H: u32 = 32;
+
+// Drawn by the kernel in place of the app, which cannot take input anymore
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrashButton {
+    Restart,
+    Close,
+}
+
+// At the bottom right of the window content, in that order
+fn button_rects(content_rect: &Rect) -> [(CrashButton, Rect); 2] {
+    let [_, _, x1, y1] = content_rect.as_xyxy();
+    let y0 = y1 + 1 - (PADDING + BUTTON_H) as i64;
+    let close_x0 = x1 + 1 - (PADDING + BUTTON_W) as i64;
+    let restart_x0 = close_x0 - (PADDING + BUTTON_W) as i64;
+
+    let button_rect = |x0: i64| Rect {
+        x0,
+        y0,
+        w: BUTTON_W,
+        h: BUTTON_H,
+    };
+
+    [
+        (CrashButton::Restart, button_rect(restart_x0)),
+        (CrashButton::Close, button_rect(close_x0)),
+    ]
+}
+
+pub fn button_at(content_rect: &Rect, x: i64, y: i64) -> Option<CrashButton> {
+    button_rects(content_rect)
+        .into_iter()
+        .find(|(_, rect)| rect.check_contains_point(x, y))
+        .map(|(button, _)| button)
+}
+
+// The damage is left to the caller except for the buttons, as they are the only part
+// which changes from a frame to the next
+pub fn draw<F: FbViewMut>(
+    fb: &mut F,
+    stylesheet: &StyleSheet,
+    app_name: &str,
+    crash: &AppCrash,
+    content_rect: &Rect,
+    hovered: Option<CrashButton>,
+) {
+    let colors = &stylesheet.colors;
+    let title_font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.large);
+    let font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.small);
+
+    let damage = fb.take_damage();
+    draw_rect(fb, content_rect, colors.background, false);
+
+    let [x0, y0, x1, y1] = content_rect.as_xyxy();
+    let text_rect = Rect::from_xyxy([
+        x0 + PADDING as i64,
+        y0 + PADDING as i64,
+        x1 - PADDING as i64,
+        y1 - (2 * PADDING + BUTTON_H) as i64,
+    ]);
+    let mut y = text_rect.y0;
+
+    //
+    // What happened
+
+    let title = format!("{} has crashed", app_name);
+    draw_text_line(fb, &title, title_font, colors.text, &text_rect, &mut y);
+    y += PADDING as i64;
+
+    for line in format!("{:#}", crash.error).lines() {
+        draw_text_line(fb, line, font, colors.red, &text_rect, &mut y);
+    }
+    y += PADDING as i64;
+
+    //
+    // Console output, the last lines which fit
+
+    let [_, _, text_x1, text_y1] = text_rect.as_xyxy();
+    if y < text_y1 {
+        let console_rect = Rect::from_xyxy([text_rect.x0, y, text_x1, text_y1]);
+        draw_rect(fb, &console_rect, colors.editable, false);
+
+        let max_lines = (console_rect.h / font.char_h as u32) as usize;
+        let lines = &crash.console_tail;
+        match lines.is_empty() {
+            true => draw_text_line(
+                fb,
+                "No console output",
+                font,
+                colors.disabled,
+                &text_rect,
+                &mut y,
+            ),
+            false => {
+                for line in lines.iter().skip(lines.len().saturating_sub(max_lines)) {
+                    draw_text_line(fb, line, font, colors.text, &text_rect, &mut y);
+                }
+            }
+        }
+    }
+
+    fb.set_damage(damage);
+
+    //
+    // Buttons
+
+    for (button, rect) in button_rects(content_rect) {
+        let widget_colors = match hovered == Some(button) {
+            true => &stylesheet.widgets.hover,
+            false => &stylesheet.widgets.normal,
+        };
+        let text = match button {
+            CrashButton::Restart => "Restart",
+            CrashButton::Close => "Close",
+        };
+        draw_rect(fb, &rect, widget_colors.bg, false);
+        draw_line_in_rect(
+            fb,
+            text,
+            &rect,
+            font,
+            widget_colors.text,
+            TextJustification::Center,
+        );
+    }
+}
+
+// Cut to the width of rect, and not drawn at all below it
+fn draw_text_line<F: FbViewMut>(
+    fb: &mut F,
+    text: &str,
+    font: &Font,
+    color: Color,
+    rect: &Rect,
+    y: &mut i64,
+) {
+    let line_h = font.char_h as u32;
+    let [_, _, _, y1] = rect.as_xyxy();
+    if *y + line_h as i64 - 1 > y1 {
+        return;
+    }
+
+    let line_rect = Rect {
+        x0: rect.x0,
+        y0: *y,
+        w: rect.w,
+        h: line_h,
+    };
+    let text = ellipsize_text(text, font, rect.w);
+    draw_line_in_rect(fb, &text, &line_rect, font, color, TextJustification::Left);
+    *y += line_h as i64;
+}
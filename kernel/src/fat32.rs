@@ -0,0 +1,944 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use chrono::{DateTime, Datelike, NaiveDate, Timelike};
+
+use crate::block::{BlockDevice, BlockError, SECTOR_SIZE};
+
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+
+// Entries are 28 bits, the top 4 are reserved and left as they are
+const FAT_ENTRY_MASK: u32 = 0x0fff_ffff;
+const FAT_FREE: u32 = 0;
+const FAT_EOC: u32 = 0x0fff_ffff;
+// Any entry from that one on ends a chain
+const FAT_EOC_MIN: u32 = 0x0fff_fff8;
+
+// With fewer clusters, the volume is FAT12 or FAT16 whatever its boot sector says
+const MIN_CLUSTERS: u32 = 65525;
+
+const DIR_ENTRY_SIZE: usize = 32;
+const ENTRIES_PER_SECTOR: usize = SECTOR_SIZE / DIR_ENTRY_SIZE;
+const SHORT_NAME_LEN: usize = 11;
+
+const ENTRY_END: u8 = 0x00;
+const ENTRY_DELETED: u8 = 0xe5;
+// A short name starting with 0xe5 is stored with that byte instead
+const ENTRY_KANJI: u8 = 0x05;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0f;
+const ATTR_LONG_NAME_MASK: u8 = 0x3f;
+
+// In byte 12 of short entries, for names that are all lowercase
+const CASE_LOWER_BASE: u8 = 0x08;
+const CASE_LOWER_EXT: u8 = 0x10;
+
+const LFN_LAST: u8 = 0x40;
+const LFN_ORDER_MASK: u8 = 0x1f;
+const LFN_CHARS: usize = 13;
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+// In UTF-16 code units
+const MAX_NAME_LEN: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsError {
+    // No FAT32 volume on the device
+    NotFormatted,
+    // Metadata that does not add up, e.g. a cluster chain that loops
+    Corrupt,
+    NotFound,
+    AlreadyExists,
+    InvalidName,
+    NotADirectory,
+    IsADirectory,
+    DirectoryNotEmpty,
+    NoSpace,
+    Device(BlockError),
+}
+
+impl From<BlockError> for FsError {
+    fn from(err: BlockError) -> Self {
+        FsError::Device(err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u32,
+    pub modified: f64, // UNIX time in ms
+    first_cluster: u32,
+    // Index of the short entry in the directory, and of how many entries the whole
+    // name takes up, the short one included
+    slot: usize,
+    nb_slots: usize,
+    short_entry: [u8; DIR_ENTRY_SIZE],
+}
+
+// A directory as stored, one 32-byte entry per slot
+struct RawDir {
+    clusters: Vec<u32>,
+    slots: Vec<[u8; DIR_ENTRY_SIZE]>,
+}
+
+// A FAT32 volume. Metadata is written through: each call that changes something has
+// the device persist it before returning.
+pub struct Fat32 {
+    device: Box<dyn BlockDevice>,
+    sectors_per_cluster: u32,
+    nb_fats: u32,
+    // In sectors, per copy
+    fat_size: u32,
+    fat_start: u64,
+    data_start: u64,
+    // Data clusters are numbered from 2
+    nb_clusters: u32,
+    root_cluster: u32,
+    fsinfo_sector: Option<u64>,
+    free_clusters: u32,
+    // Where the search for a free cluster starts
+    next_free: u32,
+}
+
+impl Fat32 {
+    pub fn mount(mut device: Box<dyn BlockDevice>) -> Result<Self, FsError> {
+        let mut boot = [0; SECTOR_SIZE];
+        device.read_sector(0, &mut boot)?;
+        if boot[SECTOR_SIZE - 2..] != BOOT_SIGNATURE {
+            return Err(FsError::NotFormatted);
+        }
+
+        let bytes_per_sector = le16(&boot, 11) as usize;
+        let sectors_per_cluster = boot[13] as u32;
+        let reserved_sectors = le16(&boot, 14) as u64;
+        let nb_fats = boot[16] as u32;
+        let root_entries = le16(&boot, 17);
+        let total_sectors = match le16(&boot, 19) {
+            0 => le32(&boot, 32) as u64,
+            total => total as u64,
+        };
+        let media = boot[21];
+        let fat_size_16 = le16(&boot, 22);
+        let fat_size = le32(&boot, 36);
+        let root_cluster = le32(&boot, 44);
+        let fsinfo_sector = le16(&boot, 48) as u64;
+
+        // FAT12 and FAT16 have a fixed root directory and a 16-bit FAT size
+        let is_fat32 = bytes_per_sector == SECTOR_SIZE
+            && sectors_per_cluster.is_power_of_two()
+            && reserved_sectors > 0
+            && nb_fats > 0
+            && root_entries == 0
+            && fat_size_16 == 0
+            && fat_size > 0;
+        if !is_fat32 {
+            return Err(FsError::NotFormatted);
+        }
+
+        let fat_start = reserved_sectors;
+        let data_start = fat_start + nb_fats as u64 * fat_size as u64;
+        if total_sectors > device.nb_sectors() || data_start >= total_sectors {
+            return Err(FsError::Corrupt);
+        }
+
+        let nb_clusters = ((total_sectors - data_start) / sectors_per_cluster as u64) as u32;
+        let fat_capacity = fat_size as u64 * (SECTOR_SIZE / 4) as u64;
+        if nb_clusters < MIN_CLUSTERS || nb_clusters as u64 + 2 > fat_capacity {
+            return Err(FsError::Corrupt);
+        }
+
+        let mut fat32 = Fat32 {
+            device,
+            sectors_per_cluster,
+            nb_fats,
+            fat_size,
+            fat_start,
+            data_start,
+            nb_clusters,
+            root_cluster,
+            fsinfo_sector: None,
+            free_clusters: 0,
+            next_free: 2,
+        };
+
+        // The first FAT entry holds the media byte
+        if !fat32.is_data_cluster(root_cluster) || fat32.fat_entry(0)? & 0xff != media as u32 {
+            return Err(FsError::Corrupt);
+        }
+        fat32.read_dir(root_cluster)?;
+
+        let fsinfo = match fsinfo_sector {
+            lba if lba > 0 && lba < reserved_sectors => fat32.read_fsinfo(lba)?,
+            _ => None,
+        };
+        match fsinfo {
+            Some((free_clusters, next_free)) => {
+                fat32.fsinfo_sector = Some(fsinfo_sector);
+                // 0xffffffff when not known
+                fat32.free_clusters = match free_clusters {
+                    n if n <= nb_clusters => n,
+                    _ => fat32.count_free_clusters()?,
+                };
+                if fat32.is_data_cluster(next_free) {
+                    fat32.next_free = next_free;
+                }
+            }
+            None => fat32.free_clusters = fat32.count_free_clusters()?,
+        }
+
+        log::info!(
+            "Mounted a FAT32 volume, {} of {} kB free",
+            fat32.free_space() / 1000,
+            fat32.total_space() / 1000
+        );
+
+        Ok(fat32)
+    }
+
+    pub fn free_space(&self) -> u64 {
+        self.free_clusters as u64 * self.cluster_size() as u64
+    }
+
+    pub fn total_space(&self) -> u64 {
+        self.nb_clusters as u64 * self.cluster_size() as u64
+    }
+
+    // Paths are relative to the root directory, e.g. "storage/notes.txt"
+    pub fn list_dir(&mut self, path: &str) -> Result<Vec<DirEntry>, FsError> {
+        let cluster = self.dir_cluster(path)?;
+        let dir = self.read_dir(cluster)?;
+        Ok(parse_dir(&dir.slots))
+    }
+
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, FsError> {
+        let (_, entry) = self.lookup(path)?;
+        if entry.is_dir {
+            return Err(FsError::IsADirectory);
+        }
+
+        let size = entry.size as usize;
+        let clusters = self.chain(entry.first_cluster)?;
+        if clusters.len() * self.cluster_size() < size {
+            return Err(FsError::Corrupt);
+        }
+
+        let mut data = Vec::with_capacity(size);
+        let mut sector = [0; SECTOR_SIZE];
+        'clusters: for cluster in clusters {
+            for i in 0..self.sectors_per_cluster as u64 {
+                if data.len() >= size {
+                    break 'clusters;
+                }
+                self.device
+                    .read_sector(self.cluster_lba(cluster) + i, &mut sector)?;
+                let len = usize::min(SECTOR_SIZE, size - data.len());
+                data.extend_from_slice(&sector[..len]);
+            }
+        }
+
+        Ok(data)
+    }
+
+    // Creates the file or replaces its content. The new content goes to new clusters,
+    // the old ones are freed once the entry no longer points to them.
+    pub fn write_file(&mut self, path: &str, data: &[u8], time: f64) -> Result<(), FsError> {
+        let (parent, name) = split_path(path);
+        if !is_valid_name(name) {
+            return Err(FsError::InvalidName);
+        }
+        let size: u32 = data.len().try_into().map_err(|_| FsError::NoSpace)?;
+
+        let dir_cluster = self.dir_cluster(parent)?;
+        let mut dir = self.read_dir(dir_cluster)?;
+        let existing = find_entry(&parse_dir(&dir.slots), name).cloned();
+        if existing.as_ref().is_some_and(|entry| entry.is_dir) {
+            return Err(FsError::IsADirectory);
+        }
+
+        let first_cluster = self.write_chain(data)?;
+        let mut short_entry = match &existing {
+            Some(entry) => entry.short_entry,
+            None => [0; DIR_ENTRY_SIZE],
+        };
+        short_entry[11] = ATTR_ARCHIVE;
+        set_entry_cluster(&mut short_entry, first_cluster);
+        short_entry[28..32].copy_from_slice(&size.to_le_bytes());
+        set_entry_time(&mut short_entry, time);
+
+        let res = match &existing {
+            Some(entry) if entry.name == name => {
+                self.write_slots(&mut dir, entry.slot, &[short_entry])
+            }
+            // Also when only the case of the name changes
+            _ => self.add_entry(&mut dir, name, short_entry),
+        };
+        if let Err(err) = res {
+            self.free_chain(first_cluster)?;
+            return Err(err);
+        }
+
+        if let Some(entry) = existing {
+            if entry.name != name {
+                self.remove_entry(&mut dir, &entry)?;
+            }
+            self.free_chain(entry.first_cluster)?;
+        }
+
+        self.commit()
+    }
+
+    pub fn create_dir(&mut self, path: &str, time: f64) -> Result<(), FsError> {
+        let (parent, name) = split_path(path);
+        if !is_valid_name(name) {
+            return Err(FsError::InvalidName);
+        }
+
+        let parent_cluster = self.dir_cluster(parent)?;
+        let mut dir = self.read_dir(parent_cluster)?;
+        if find_entry(&parse_dir(&dir.slots), name).is_some() {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let cluster = self.alloc_cluster()?;
+        self.zero_cluster(cluster)?;
+
+        let mut short_entry = [0; DIR_ENTRY_SIZE];
+        short_entry[11] = ATTR_DIRECTORY;
+        set_entry_time(&mut short_entry, time);
+
+        // "." and "..", the latter points to cluster 0 for the root directory
+        let mut dot = short_entry;
+        dot[..SHORT_NAME_LEN].copy_from_slice(b".          ");
+        set_entry_cluster(&mut dot, cluster);
+        let mut dot_dot = short_entry;
+        dot_dot[..SHORT_NAME_LEN].copy_from_slice(b"..         ");
+        match parent_cluster == self.root_cluster {
+            true => set_entry_cluster(&mut dot_dot, 0),
+            false => set_entry_cluster(&mut dot_dot, parent_cluster),
+        }
+        let mut new_dir = RawDir {
+            clusters: Vec::from([cluster]),
+            slots: Vec::new(),
+        };
+        new_dir
+            .slots
+            .resize(self.entries_per_cluster(), [0; DIR_ENTRY_SIZE]);
+        self.write_slots(&mut new_dir, 0, &[dot, dot_dot])?;
+
+        set_entry_cluster(&mut short_entry, cluster);
+        if let Err(err) = self.add_entry(&mut dir, name, short_entry) {
+            self.free_chain(cluster)?;
+            return Err(err);
+        }
+
+        self.commit()
+    }
+
+    // Directories must be empty
+    pub fn delete(&mut self, path: &str) -> Result<(), FsError> {
+        let (mut dir, entry) = self.lookup(path)?;
+        if entry.is_dir {
+            let content = self.read_dir(entry.first_cluster)?;
+            if !parse_dir(&content.slots).is_empty() {
+                return Err(FsError::DirectoryNotEmpty);
+            }
+        }
+
+        self.remove_entry(&mut dir, &entry)?;
+        self.free_chain(entry.first_cluster)?;
+
+        self.commit()
+    }
+
+    // Within the same directory
+    pub fn rename(&mut self, path: &str, new_name: &str) -> Result<(), FsError> {
+        if !is_valid_name(new_name) {
+            return Err(FsError::InvalidName);
+        }
+
+        let (mut dir, entry) = self.lookup(path)?;
+        let entries = parse_dir(&dir.slots);
+        let other = find_entry(&entries, new_name);
+        if other.is_some_and(|other| other.slot != entry.slot) {
+            return Err(FsError::AlreadyExists);
+        }
+
+        // Added first, a crash in between leaves two names rather than none
+        self.add_entry(&mut dir, new_name, entry.short_entry)?;
+        self.remove_entry(&mut dir, &entry)?;
+
+        self.commit()
+    }
+
+    fn lookup(&mut self, path: &str) -> Result<(RawDir, DirEntry), FsError> {
+        let (parent, name) = split_path(path);
+        let dir_cluster = self.dir_cluster(parent)?;
+        let dir = self.read_dir(dir_cluster)?;
+        let entry = find_entry(&parse_dir(&dir.slots), name)
+            .cloned()
+            .ok_or(FsError::NotFound)?;
+        Ok((dir, entry))
+    }
+
+    // Of the directory at `path`, the root one for an empty path
+    fn dir_cluster(&mut self, path: &str) -> Result<u32, FsError> {
+        let mut cluster = self.root_cluster;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let dir = self.read_dir(cluster)?;
+            let entries = parse_dir(&dir.slots);
+            let entry = find_entry(&entries, name).ok_or(FsError::NotFound)?;
+            if !entry.is_dir {
+                return Err(FsError::NotADirectory);
+            }
+            cluster = entry.first_cluster;
+        }
+        Ok(cluster)
+    }
+
+    fn read_dir(&mut self, first_cluster: u32) -> Result<RawDir, FsError> {
+        let clusters = self.chain(first_cluster)?;
+        if clusters.is_empty() {
+            return Err(FsError::Corrupt);
+        }
+
+        let mut slots = Vec::with_capacity(clusters.len() * self.entries_per_cluster());
+        let mut sector = [0; SECTOR_SIZE];
+        for cluster in clusters.iter() {
+            for i in 0..self.sectors_per_cluster as u64 {
+                self.device
+                    .read_sector(self.cluster_lba(*cluster) + i, &mut sector)?;
+                for entry in sector.chunks_exact(DIR_ENTRY_SIZE) {
+                    slots.push(entry.try_into().unwrap());
+                }
+            }
+        }
+
+        Ok(RawDir { clusters, slots })
+    }
+
+    // Writes the long name entries, then the short one made from `short_entry` with a
+    // short name of its own
+    fn add_entry(
+        &mut self,
+        dir: &mut RawDir,
+        name: &str,
+        mut short_entry: [u8; DIR_ENTRY_SIZE],
+    ) -> Result<(), FsError> {
+        let taken: Vec<[u8; SHORT_NAME_LEN]> = dir
+            .slots
+            .iter()
+            .filter(|slot| slot[0] != ENTRY_END && slot[0] != ENTRY_DELETED)
+            .filter(|slot| slot[11] & ATTR_LONG_NAME_MASK != ATTR_LONG_NAME)
+            .map(|slot| slot[..SHORT_NAME_LEN].try_into().unwrap())
+            .collect();
+        let short_name = make_short_name(name, &taken).ok_or(FsError::NoSpace)?;
+        short_entry[..SHORT_NAME_LEN].copy_from_slice(&short_name);
+        short_entry[12] = 0;
+
+        let mut slots = long_name_slots(name, checksum(&short_name));
+        slots.push(short_entry);
+
+        let first = self.find_free_slots(dir, slots.len())?;
+        self.write_slots(dir, first, &slots)
+    }
+
+    fn remove_entry(&mut self, dir: &mut RawDir, entry: &DirEntry) -> Result<(), FsError> {
+        let first = entry.slot + 1 - entry.nb_slots;
+        let mut slots = dir.slots[first..=entry.slot].to_vec();
+        for slot in slots.iter_mut() {
+            slot[0] = ENTRY_DELETED;
+        }
+        self.write_slots(dir, first, &slots)
+    }
+
+    // The first of `count` free slots in a row. The directory grows if there are not
+    // enough.
+    fn find_free_slots(&mut self, dir: &mut RawDir, count: usize) -> Result<usize, FsError> {
+        loop {
+            let mut run = 0;
+            for (i, slot) in dir.slots.iter().enumerate() {
+                match slot[0] {
+                    ENTRY_END | ENTRY_DELETED => run += 1,
+                    _ => run = 0,
+                }
+                if run == count {
+                    return Ok(i + 1 - count);
+                }
+            }
+
+            let cluster = self.alloc_cluster()?;
+            self.zero_cluster(cluster)?;
+            self.set_fat_entry(*dir.clusters.last().unwrap(), cluster)?;
+            dir.clusters.push(cluster);
+            let nb_slots = dir.slots.len() + self.entries_per_cluster();
+            dir.slots.resize(nb_slots, [0; DIR_ENTRY_SIZE]);
+        }
+    }
+
+    fn write_slots(
+        &mut self,
+        dir: &mut RawDir,
+        first: usize,
+        slots: &[[u8; DIR_ENTRY_SIZE]],
+    ) -> Result<(), FsError> {
+        let mut sector = [0; SECTOR_SIZE];
+        for (i, slot) in slots.iter().enumerate() {
+            let index = first + i;
+            let in_cluster = index % self.entries_per_cluster();
+            let cluster = dir.clusters[index / self.entries_per_cluster()];
+            let lba = self.cluster_lba(cluster) + (in_cluster / ENTRIES_PER_SECTOR) as u64;
+            let offset = (in_cluster % ENTRIES_PER_SECTOR) * DIR_ENTRY_SIZE;
+
+            self.device.read_sector(lba, &mut sector)?;
+            sector[offset..offset + DIR_ENTRY_SIZE].copy_from_slice(slot);
+            self.device.write_sector(lba, &sector)?;
+            dir.slots[index] = *slot;
+        }
+        Ok(())
+    }
+
+    // The first cluster of a new chain holding `data`, 0 for no data
+    fn write_chain(&mut self, data: &[u8]) -> Result<u32, FsError> {
+        let mut first_cluster = 0;
+        let mut prev_cluster = None;
+        for chunk in data.chunks(self.cluster_size()) {
+            let cluster = match self.alloc_cluster() {
+                Ok(cluster) => cluster,
+                Err(err) => {
+                    self.free_chain(first_cluster)?;
+                    return Err(err);
+                }
+            };
+            match prev_cluster {
+                Some(prev_cluster) => self.set_fat_entry(prev_cluster, cluster)?,
+                None => first_cluster = cluster,
+            }
+            prev_cluster = Some(cluster);
+
+            for (i, part) in chunk.chunks(SECTOR_SIZE).enumerate() {
+                let mut sector = [0; SECTOR_SIZE];
+                sector[..part.len()].copy_from_slice(part);
+                self.device
+                    .write_sector(self.cluster_lba(cluster) + i as u64, &sector)?;
+            }
+        }
+        Ok(first_cluster)
+    }
+
+    fn zero_cluster(&mut self, cluster: u32) -> Result<(), FsError> {
+        for i in 0..self.sectors_per_cluster as u64 {
+            self.device
+                .write_sector(self.cluster_lba(cluster) + i, &[0; SECTOR_SIZE])?;
+        }
+        Ok(())
+    }
+
+    // Errors out on chains that loop or leave the volume, rather than following them
+    fn chain(&mut self, first_cluster: u32) -> Result<Vec<u32>, FsError> {
+        let mut clusters = Vec::new();
+        if first_cluster == 0 {
+            return Ok(clusters);
+        }
+
+        let mut cluster = first_cluster;
+        loop {
+            if !self.is_data_cluster(cluster) || clusters.len() >= self.nb_clusters as usize {
+                return Err(FsError::Corrupt);
+            }
+            clusters.push(cluster);
+
+            match self.fat_entry(cluster)? {
+                next if next >= FAT_EOC_MIN => return Ok(clusters),
+                next => cluster = next,
+            }
+        }
+    }
+
+    fn alloc_cluster(&mut self) -> Result<u32, FsError> {
+        for i in 0..self.nb_clusters {
+            let cluster = 2 + (self.next_free - 2 + i) % self.nb_clusters;
+            if self.fat_entry(cluster)? == FAT_FREE {
+                self.set_fat_entry(cluster, FAT_EOC)?;
+                self.free_clusters = self.free_clusters.saturating_sub(1);
+                self.next_free = 2 + (cluster - 1) % self.nb_clusters;
+                return Ok(cluster);
+            }
+        }
+        Err(FsError::NoSpace)
+    }
+
+    fn free_chain(&mut self, first_cluster: u32) -> Result<(), FsError> {
+        for cluster in self.chain(first_cluster)? {
+            self.set_fat_entry(cluster, FAT_FREE)?;
+            self.free_clusters = u32::min(self.free_clusters + 1, self.nb_clusters);
+        }
+        Ok(())
+    }
+
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32, FsError> {
+        let offset = cluster as usize * 4;
+        let lba = self.fat_start + (offset / SECTOR_SIZE) as u64;
+        let mut sector = [0; SECTOR_SIZE];
+        self.device.read_sector(lba, &mut sector)?;
+        Ok(le32(&sector, offset % SECTOR_SIZE) & FAT_ENTRY_MASK)
+    }
+
+    // In all copies of the FAT
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), FsError> {
+        let offset = cluster as usize * 4;
+        let mut sector = [0; SECTOR_SIZE];
+        for fat in 0..self.nb_fats as u64 {
+            let lba = self.fat_start + fat * self.fat_size as u64 + (offset / SECTOR_SIZE) as u64;
+            self.device.read_sector(lba, &mut sector)?;
+            let i = offset % SECTOR_SIZE;
+            let entry = (le32(&sector, i) & !FAT_ENTRY_MASK) | (value & FAT_ENTRY_MASK);
+            sector[i..i + 4].copy_from_slice(&entry.to_le_bytes());
+            self.device.write_sector(lba, &sector)?;
+        }
+        Ok(())
+    }
+
+    fn count_free_clusters(&mut self) -> Result<u32, FsError> {
+        let mut nb_free = 0;
+        for cluster in 2..self.nb_clusters + 2 {
+            if self.fat_entry(cluster)? == FAT_FREE {
+                nb_free += 1;
+            }
+        }
+        Ok(nb_free)
+    }
+
+    // The free cluster count and the next free cluster hint, None if the sector is not
+    // valid
+    fn read_fsinfo(&mut self, lba: u64) -> Result<Option<(u32, u32)>, FsError> {
+        let mut sector = [0; SECTOR_SIZE];
+        self.device.read_sector(lba, &mut sector)?;
+        let valid = le32(&sector, 0) == FSINFO_LEAD_SIGNATURE
+            && le32(&sector, 484) == FSINFO_STRUCT_SIGNATURE
+            && sector[SECTOR_SIZE - 2..] == BOOT_SIGNATURE;
+        Ok(valid.then_some((le32(&sector, 488), le32(&sector, 492))))
+    }
+
+    // Has the device persist everything written so far
+    fn commit(&mut self) -> Result<(), FsError> {
+        if let Some(lba) = self.fsinfo_sector {
+            let mut sector = [0; SECTOR_SIZE];
+            self.device.read_sector(lba, &mut sector)?;
+            sector[488..492].copy_from_slice(&self.free_clusters.to_le_bytes());
+            sector[492..496].copy_from_slice(&self.next_free.to_le_bytes());
+            self.device.write_sector(lba, &sector)?;
+        }
+        self.device.sync()?;
+        Ok(())
+    }
+
+    fn is_data_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.nb_clusters + 2
+    }
+
+    fn cluster_lba(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster - 2) as u64 * self.sectors_per_cluster as u64
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * SECTOR_SIZE
+    }
+
+    fn entries_per_cluster(&self) -> usize {
+        self.sectors_per_cluster as usize * ENTRIES_PER_SECTOR
+    }
+}
+
+// Of a file or directory, long names cannot have those
+pub fn is_valid_name(name: &str) -> bool {
+    let forbidden = |c: char| c < ' ' || "\"*/:<>?\\|".contains(c);
+    !name.is_empty()
+        && name.encode_utf16().count() <= MAX_NAME_LEN
+        && name != "."
+        && name != ".."
+        && !name.ends_with(['.', ' '])
+        && !name.contains(forbidden)
+}
+
+// Into the parent directory and the name
+fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+// Names are compared without regard to case, like other systems do
+fn find_entry<'a>(entries: &'a [DirEntry], name: &str) -> Option<&'a DirEntry> {
+    entries
+        .iter()
+        .find(|entry| entry.name.eq_ignore_ascii_case(name))
+}
+
+// Entries of a directory, without "." and ".." and the volume label
+fn parse_dir(slots: &[[u8; DIR_ENTRY_SIZE]]) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+    let mut long_name = LongName::new();
+
+    for (i, slot) in slots.iter().enumerate() {
+        match slot[0] {
+            ENTRY_END => break,
+            ENTRY_DELETED => {
+                long_name.reset();
+                continue;
+            }
+            _ => (),
+        }
+
+        let attr = slot[11];
+        if attr & ATTR_LONG_NAME_MASK == ATTR_LONG_NAME {
+            long_name.push(slot);
+            continue;
+        }
+        if attr & ATTR_VOLUME_ID != 0 || slot[0] == b'.' {
+            long_name.reset();
+            continue;
+        }
+
+        let short_name: &[u8; SHORT_NAME_LEN] = slot[..SHORT_NAME_LEN].try_into().unwrap();
+        let (name, nb_slots) = match long_name.take(checksum(short_name)) {
+            Some((name, nb_long)) => (name, nb_long + 1),
+            None => (short_name_string(slot), 1),
+        };
+
+        entries.push(DirEntry {
+            name,
+            is_dir: attr & ATTR_DIRECTORY != 0,
+            size: le32(slot, 28),
+            modified: entry_time(slot),
+            first_cluster: ((le16(slot, 20) as u32) << 16) | le16(slot, 26) as u32,
+            slot: i,
+            nb_slots,
+            short_entry: *slot,
+        });
+    }
+
+    entries
+}
+
+// Long name entries come right before the short entry they belong to, the end of the
+// name first
+struct LongName {
+    units: Vec<u16>,
+    nb_slots: usize,
+    // Order of the last entry seen, down to 1
+    order: u8,
+    checksum: u8,
+    valid: bool,
+}
+
+impl LongName {
+    fn new() -> Self {
+        LongName {
+            units: Vec::new(),
+            nb_slots: 0,
+            order: 0,
+            checksum: 0,
+            valid: false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.valid = false;
+    }
+
+    fn push(&mut self, slot: &[u8; DIR_ENTRY_SIZE]) {
+        let order = slot[0] & LFN_ORDER_MASK;
+
+        if slot[0] & LFN_LAST != 0 {
+            self.units.clear();
+            self.units.resize(order as usize * LFN_CHARS, 0xffff);
+            self.nb_slots = order as usize;
+            self.checksum = slot[13];
+            self.valid = order > 0;
+        } else if !(self.valid && order + 1 == self.order && slot[13] == self.checksum) {
+            self.valid = false;
+        }
+
+        if !self.valid {
+            return;
+        }
+        self.order = order;
+
+        let start = (order as usize - 1) * LFN_CHARS;
+        for (i, offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            self.units[start + i] = le16(slot, *offset);
+        }
+    }
+
+    // The name and how many entries it took up, if it is complete and belongs to the
+    // short entry with that checksum
+    fn take(&mut self, checksum: u8) -> Option<(String, usize)> {
+        let complete = self.valid && self.order == 1 && self.checksum == checksum;
+        self.reset();
+        if !complete {
+            return None;
+        }
+
+        let len = self
+            .units
+            .iter()
+            .position(|unit| *unit == 0x0000 || *unit == 0xffff)
+            .unwrap_or(self.units.len());
+        let name = char::decode_utf16(self.units[..len].iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+
+        Some((name, self.nb_slots))
+    }
+}
+
+// In the order they are stored, the end of the name first
+fn long_name_slots(name: &str, checksum: u8) -> Vec<[u8; DIR_ENTRY_SIZE]> {
+    let units: Vec<u16> = name.encode_utf16().collect();
+    let nb_slots = units.len().div_ceil(LFN_CHARS);
+
+    (1..=nb_slots)
+        .rev()
+        .map(|order| {
+            let mut slot = [0; DIR_ENTRY_SIZE];
+            slot[0] = order as u8;
+            if order == nb_slots {
+                slot[0] |= LFN_LAST;
+            }
+            slot[11] = ATTR_LONG_NAME;
+            slot[13] = checksum;
+
+            // The name ends with a null unit if there is room, then padding
+            for (i, offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                let pos = (order - 1) * LFN_CHARS + i;
+                let unit = match pos.cmp(&units.len()) {
+                    core::cmp::Ordering::Less => units[pos],
+                    core::cmp::Ordering::Equal => 0x0000,
+                    core::cmp::Ordering::Greater => 0xffff,
+                };
+                slot[*offset..*offset + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+
+            slot
+        })
+        .collect()
+}
+
+// A short name like "REPORT~1TXT" that no other entry has. Other systems show the long
+// name, so it only has to be unique.
+fn make_short_name(name: &str, taken: &[[u8; SHORT_NAME_LEN]]) -> Option<[u8; SHORT_NAME_LEN]> {
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((base, ext)) if !base.is_empty() => (base, ext),
+        _ => (name, ""),
+    };
+    let clean = |part: &str| -> Vec<u8> {
+        part.chars()
+            .filter(|c| *c != ' ' && *c != '.')
+            .map(|c| match c.is_ascii_alphanumeric() {
+                true => c.to_ascii_uppercase() as u8,
+                false => b'_',
+            })
+            .collect()
+    };
+    let (base, ext) = (clean(base), clean(ext));
+
+    let mut short_name = [b' '; SHORT_NAME_LEN];
+    let ext_len = usize::min(ext.len(), 3);
+    short_name[8..8 + ext_len].copy_from_slice(&ext[..ext_len]);
+
+    for n in 1..1_000_000 {
+        let suffix = format!("~{}", n);
+        let base_len = usize::min(base.len(), 8 - suffix.len());
+        short_name[..8].fill(b' ');
+        short_name[..base_len].copy_from_slice(&base[..base_len]);
+        short_name[base_len..base_len + suffix.len()].copy_from_slice(suffix.as_bytes());
+
+        if !taken.contains(&short_name) {
+            return Some(short_name);
+        }
+    }
+
+    None
+}
+
+fn short_name_string(slot: &[u8; DIR_ENTRY_SIZE]) -> String {
+    let lower_base = slot[12] & CASE_LOWER_BASE != 0;
+    let lower_ext = slot[12] & CASE_LOWER_EXT != 0;
+
+    let part = |bytes: &[u8], lower: bool| -> String {
+        bytes
+            .iter()
+            .map(|b| *b as char)
+            .map(|c| match lower {
+                true => c.to_ascii_lowercase(),
+                false => c,
+            })
+            .collect::<String>()
+            .trim_end()
+            .into()
+    };
+
+    let mut base = part(&slot[..8], lower_base);
+    if slot[0] == ENTRY_KANJI {
+        base.replace_range(..1, "\u{e5}");
+    }
+    let ext = part(&slot[8..SHORT_NAME_LEN], lower_ext);
+
+    match ext.is_empty() {
+        true => base,
+        false => format!("{}.{}", base, ext),
+    }
+}
+
+fn checksum(short_name: &[u8; SHORT_NAME_LEN]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, b| sum.rotate_right(1).wrapping_add(*b))
+}
+
+fn set_entry_cluster(slot: &mut [u8; DIR_ENTRY_SIZE], cluster: u32) {
+    slot[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    slot[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+}
+
+// FAT times have a 2 s resolution and no time zone, they are taken as UTC
+fn set_entry_time(slot: &mut [u8; DIR_ENTRY_SIZE], time: f64) {
+    let Some(datetime) = DateTime::from_timestamp_millis(time as i64) else {
+        return;
+    };
+    let year = datetime.year().clamp(1980, 2107) as u16;
+    let date = ((year - 1980) << 9) | ((datetime.month() as u16) << 5) | datetime.day() as u16;
+    let time = ((datetime.hour() as u16) << 11)
+        | ((datetime.minute() as u16) << 5)
+        | (datetime.second() as u16 / 2);
+
+    // Creation, then last write
+    for (time_offset, date_offset) in [(14, 16), (22, 24)] {
+        slot[time_offset..time_offset + 2].copy_from_slice(&time.to_le_bytes());
+        slot[date_offset..date_offset + 2].copy_from_slice(&date.to_le_bytes());
+    }
+    slot[18..20].copy_from_slice(&date.to_le_bytes());
+}
+
+// Of the last write, 0 if not set
+fn entry_time(slot: &[u8; DIR_ENTRY_SIZE]) -> f64 {
+    let (time, date) = (le16(slot, 22) as u32, le16(slot, 24) as u32);
+    NaiveDate::from_ymd_opt(1980 + (date >> 9) as i32, (date >> 5) & 0xf, date & 0x1f)
+        .and_then(|date| date.and_hms_opt(time >> 11, (time >> 5) & 0x3f, (time & 0x1f) * 2))
+        .map(|datetime| datetime.and_utc().timestamp_millis() as f64)
+        .unwrap_or(0.0)
+}
+
+fn le16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn le32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
@@ -0,0 +1,284 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::str::FromStr;
+use log::{Level, LevelFilter};
+
+use super::json::{JsonWriter, ToJson};
+use super::protocol::{Request, Response};
+use crate::app::{App, AppState, AppsManager};
+use crate::system::System;
+use crate::{logging, memory};
+
+const INDEX: &str = "\
+GET  /stats                         frame timing, heap, network and per-app stats
+GET  /apps                          apps and their instances
+POST /apps/<name>/close             close an app
+POST /apps/<name>/pause
+POST /apps/<name>/resume
+GET  /log?level=<level>&tail=<n>    kernel log, at most that verbose and the last n lines
+POST /log/level?target=<t>&level=<level>
+                                    off, error, warn, info, debug, trace, or reset
+GET  /screenshot.png                the screen, without the cursor
+GET  /capture.pcap                  captured frames, live while the capture runs (see the
+                                    serial shell capture command)
+";
+
+pub enum Reply {
+    Response(Response),
+    // Sent once the next frame is encoded
+    Screenshot,
+    // Streamed from the packet capture
+    Capture,
+}
+
+// HEAD is routed like GET, the body is left out when the response is sent
+pub fn route(request: &Request, system: &mut System, apps_manager: &mut AppsManager) -> Reply {
+    let method = match request.method.as_str() {
+        "HEAD" => "GET",
+        method => method,
+    };
+
+    let response = match (method, request.path.as_str()) {
+        ("GET", "/") => Response::text(200, String::from(INDEX)),
+        ("GET", "/stats") => get_stats(system),
+        ("GET", "/apps") => get_apps(apps_manager),
+        ("GET", "/log") => get_log(request),
+        ("POST", "/log/level") => set_log_level(request),
+        ("GET", "/screenshot.png") => return Reply::Screenshot,
+        ("GET", "/capture.pcap") => return Reply::Capture,
+        (_, "/" | "/stats" | "/apps" | "/log" | "/screenshot.png" | "/capture.pcap") => {
+            Response::method_not_allowed("GET, HEAD")
+        }
+        (_, "/log/level") => Response::method_not_allowed("POST"),
+        (method, path) => match path.strip_prefix("/apps/") {
+            Some(rest) => {
+                let (app_name, action) = rest.rsplit_once('/').unwrap_or((rest, ""));
+                match method {
+                    "POST" => app_action(app_name, action, system, apps_manager),
+                    _ => Response::method_not_allowed("POST"),
+                }
+            }
+            None => Response::error(404),
+        },
+    };
+
+    Reply::Response(response)
+}
+
+fn get_stats(system: &System) -> Response {
+    let stats = &system.stats;
+
+    // Over the frames kept in the history, the current one is still being filled
+    let frametimes: Vec<f64> = stats
+        .get_system_history(|dp| dp.frametime_used)
+        .into_iter()
+        .skip(1)
+        .collect();
+    let intervals: Vec<f64> = stats
+        .get_system_history(|dp| dp.frame_interval)
+        .into_iter()
+        .skip(1)
+        .collect();
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+
+    let mut json = JsonWriter::new();
+    json.object(|json| {
+        json.field("uptime_ms", &system.clock.time());
+        json.field("frame", stats.get_last_system_point());
+        json.field_with("timing", |json| {
+            json.object(|json| {
+                json.field("frametime_mean", &mean(&frametimes));
+                json.field(
+                    "frametime_max",
+                    &frametimes.iter().copied().fold(0.0, f64::max),
+                );
+                json.field("frame_interval_mean", &mean(&intervals));
+            })
+        });
+        json.field("memory", &memory::stats());
+        json.field_with("network", |json| {
+            json.object(|json| {
+                json.field("link_up", &system.tcp_stack.link_up());
+                json.field("interfaces", &system.tcp_stack.interface_entries()[..]);
+                json.field("capture", &system.tcp_stack.capture().status());
+                // Null before the first sync
+                json.field("time_sync", &system.clock.last_sync());
+            })
+        });
+        json.field_with("apps", |json| {
+            json.object(|json| {
+                for (app_name, point) in stats.get_last_app_points() {
+                    json.field(app_name, point);
+                }
+            })
+        });
+    });
+
+    Response::json(json.finish())
+}
+
+fn get_apps(apps_manager: &AppsManager) -> Response {
+    let mut apps: Vec<&App> = apps_manager.apps().collect();
+    apps.sort_by_key(|app| app.descriptor.name);
+
+    let mut json = JsonWriter::new();
+    json.object(|json| {
+        json.field_with("apps", |json| {
+            json.array(|json| {
+                for app in apps {
+                    json.value(app);
+                }
+            })
+        });
+    });
+
+    Response::json(json.finish())
+}
+
+fn app_action(
+    app_name: &str,
+    action: &str,
+    system: &mut System,
+    apps_manager: &mut AppsManager,
+) -> Response {
+    if !apps_manager
+        .apps()
+        .any(|app| app.descriptor.name == app_name)
+    {
+        return Response::error(404);
+    }
+
+    let done = match action {
+        "close" => apps_manager.close(app_name, system),
+        "pause" => apps_manager.set_paused(app_name, true),
+        "resume" => apps_manager.set_paused(app_name, false),
+        _ => return Response::error(404),
+    };
+    // Only running apps can be paused or resumed
+    if !done {
+        return Response::text(409, format!("{} is not running\n", app_name));
+    }
+    log::info!("{} {} over HTTP", action, app_name);
+
+    let mut json = JsonWriter::new();
+    if let Some(app) = apps_manager
+        .apps()
+        .find(|app| app.descriptor.name == app_name)
+    {
+        json.value(app);
+    }
+    Response::json(json.finish())
+}
+
+fn get_log(request: &Request) -> Response {
+    let level = match request.query("level") {
+        Some(level) => match LevelFilter::from_str(level) {
+            Ok(level) => level,
+            Err(_) => return Response::text(400, format!("Unknown level {}\n", level)),
+        },
+        None => LevelFilter::Trace,
+    };
+    let tail = match request.query("tail").map(usize::from_str) {
+        Some(Ok(tail)) => Some(tail),
+        Some(Err(_)) => return Response::text(400, String::from("tail is not a number\n")),
+        None => None,
+    };
+
+    let (data, _) = logging::read_log(0, usize::MAX);
+    let data = String::from_utf8_lossy(&data);
+
+    // Lines start with their level
+    let lines: Vec<&str> = data
+        .lines()
+        .filter(|line| {
+            let line_level = line.split(' ').next().and_then(|s| Level::from_str(s).ok());
+            line_level.is_none_or(|line_level| line_level <= level)
+        })
+        .collect();
+    let start = match tail {
+        Some(tail) => lines.len().saturating_sub(tail),
+        None => 0,
+    };
+
+    // The levels come first, so that the targets to raise can be found
+    let mut body = log_levels();
+    for line in &lines[start..] {
+        body.push_str(line);
+        body.push('\n');
+    }
+
+    Response::text(200, body)
+}
+
+fn set_log_level(request: &Request) -> Response {
+    let (Some(target), Some(level)) = (request.query("target"), request.query("level")) else {
+        return Response::text(400, String::from("target and level are needed\n"));
+    };
+    let level = match level {
+        "reset" => None,
+        level => match LevelFilter::from_str(level) {
+            Ok(level) => Some(level),
+            Err(_) => return Response::text(400, format!("Unknown level {}\n", level)),
+        },
+    };
+
+    match (target, level) {
+        ("default", Some(level)) => logging::set_default_level(level),
+        ("default", None) => {
+            return Response::text(400, String::from("The default level cannot be reset\n"))
+        }
+        (target, level) => logging::set_target_level(target, level),
+    }
+    log::info!("Log level of {} set to {:?} over HTTP", target, level);
+
+    Response::text(200, log_levels())
+}
+
+// As "# target: level" lines, the default one first
+fn log_levels() -> String {
+    let (default_level, target_levels) = logging::levels();
+    let mut s = format!("# default: {}\n", default_level);
+    for (target, level) in target_levels {
+        writeln!(s, "# {}: {}", target, level).unwrap();
+    }
+    s
+}
+
+// The descriptor, then the instance unless the app was never started or was closed
+impl ToJson for App {
+    fn to_json(&self, json: &mut JsonWriter) {
+        let descriptor = &self.descriptor;
+        json.object(|json| {
+            json.field("name", descriptor.name);
+            json.field("wasm_size", &descriptor.data.len());
+            json.field(
+                "min_size",
+                &[descriptor.min_size.0, descriptor.min_size.1][..],
+            );
+
+            let state = match &self.app_state {
+                AppState::Init if !self.is_open => None,
+                // Instantiated on the next frame
+                AppState::Init => Some("starting"),
+                AppState::Active { paused: true, .. } => Some("paused"),
+                AppState::Active { .. } => Some("running"),
+                AppState::Crashed { .. } => Some("crashed"),
+            };
+            json.field_with("instance", |json| match state {
+                None => json.null(),
+                Some(state) => json.object(|json| {
+                    json.field("state", state);
+                    if let AppState::Crashed { crash } = &self.app_state {
+                        json.field("error", format!("{}", crash.error).as_str());
+                    }
+                    json.field("minimized", &self.minimized);
+                    json.field("workspace", &self.workspace);
+                    json.field("rect", &self.rect);
+                    json.field("time_used", &self.time_used);
+                }),
+            });
+        });
+    }
+}
@@ -0,0 +1,311 @@
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Write;
+
+use applib::stats::NetworkInterfaceEntry;
+use applib::Rect;
+use smoltcp::wire::{EthernetAddress, Ipv4Address};
+
+use crate::allocator::{AllocStats, TagStats};
+use crate::memory::MemoryStats;
+use crate::network::capture::CaptureStatus;
+use crate::stats::{AppDataPoint, SystemDataPoint};
+use crate::time::TimeSync;
+
+// Writes JSON straight into a string. Objects and arrays are filled by closures, and
+// commas are put between their members as they are written.
+pub struct JsonWriter {
+    out: String,
+    // The next value follows another one in the same object or array
+    comma: bool,
+}
+
+pub trait ToJson {
+    fn to_json(&self, json: &mut JsonWriter);
+}
+
+impl JsonWriter {
+    pub fn new() -> Self {
+        JsonWriter {
+            out: String::new(),
+            comma: false,
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    pub fn object<F: FnOnce(&mut JsonWriter)>(&mut self, f: F) {
+        self.begin_value();
+        self.out.push('{');
+        self.comma = false;
+        f(self);
+        self.out.push('}');
+        self.comma = true;
+    }
+
+    // Elements are written with value(), or with object() and array()
+    pub fn array<F: FnOnce(&mut JsonWriter)>(&mut self, f: F) {
+        self.begin_value();
+        self.out.push('[');
+        self.comma = false;
+        f(self);
+        self.out.push(']');
+        self.comma = true;
+    }
+
+    pub fn value<T: ToJson + ?Sized>(&mut self, value: &T) {
+        value.to_json(self);
+    }
+
+    // Inside of an object
+    pub fn field<T: ToJson + ?Sized>(&mut self, key: &str, value: &T) {
+        self.key(key);
+        value.to_json(self);
+    }
+
+    // The value is written by `f`
+    pub fn field_with<F: FnOnce(&mut JsonWriter)>(&mut self, key: &str, f: F) {
+        self.key(key);
+        f(self);
+    }
+
+    pub fn null(&mut self) {
+        self.raw(format_args!("null"));
+    }
+
+    fn key(&mut self, key: &str) {
+        key.to_json(self);
+        self.out.push(':');
+        self.comma = false;
+    }
+
+    fn begin_value(&mut self) {
+        if self.comma {
+            self.out.push(',');
+        }
+    }
+
+    // For numbers and literals, written as they are
+    fn raw(&mut self, args: core::fmt::Arguments) {
+        self.begin_value();
+        self.out.write_fmt(args).unwrap();
+        self.comma = true;
+    }
+
+    fn string(&mut self, s: &str) {
+        self.begin_value();
+        self.out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                c if (c as u32) < 0x20 => write!(self.out, "\\u{:04x}", c as u32).unwrap(),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+        self.comma = true;
+    }
+}
+
+macro_rules! impl_to_json_int {
+    ($($t:ty),*) => {
+        $(impl ToJson for $t {
+            fn to_json(&self, json: &mut JsonWriter) {
+                json.raw(format_args!("{}", self));
+            }
+        })*
+    };
+}
+
+impl_to_json_int!(u8, u16, u32, u64, usize, i64);
+
+// Infinities and NaN have no JSON form
+impl ToJson for f64 {
+    fn to_json(&self, json: &mut JsonWriter) {
+        match self.is_finite() {
+            true => json.raw(format_args!("{}", self)),
+            false => json.null(),
+        }
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self, json: &mut JsonWriter) {
+        json.raw(format_args!("{}", self));
+    }
+}
+
+impl ToJson for str {
+    fn to_json(&self, json: &mut JsonWriter) {
+        json.string(self);
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for &T {
+    fn to_json(&self, json: &mut JsonWriter) {
+        (**self).to_json(json);
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self, json: &mut JsonWriter) {
+        match self {
+            Some(value) => value.to_json(json),
+            None => json.null(),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self, json: &mut JsonWriter) {
+        json.array(|json| {
+            for value in self {
+                json.value(value);
+            }
+        });
+    }
+}
+
+impl ToJson for Rect {
+    fn to_json(&self, json: &mut JsonWriter) {
+        json.object(|json| {
+            json.field("x0", &self.x0);
+            json.field("y0", &self.y0);
+            json.field("w", &self.w);
+            json.field("h", &self.h);
+        });
+    }
+}
+
+// Heap stats are written on their own, from memory::stats()
+impl ToJson for SystemDataPoint {
+    fn to_json(&self, json: &mut JsonWriter) {
+        json.object(|json| {
+            json.field("frametime_used", &self.frametime_used);
+            json.field("netpoll_used", &self.netpoll_used);
+            json.field("frame_interval", &self.frame_interval);
+            json.field("composited", &self.composited);
+            json.field("damaged_pixels", &self.damaged_pixels);
+            json.field("flushed_pixels", &self.flushed_pixels);
+            json.field("windows_drawn", &self.windows_drawn);
+            json.field("windows_skipped", &self.windows_skipped);
+            json.field("net_recv", &self.net_recv);
+            json.field("net_sent", &self.net_sent);
+            json.field("virtio_notified", &self.virtio_notified);
+            json.field("virtio_suppressed", &self.virtio_suppressed);
+        });
+    }
+}
+
+impl ToJson for AppDataPoint {
+    fn to_json(&self, json: &mut JsonWriter) {
+        json.object(|json| {
+            json.field("running", &self.running);
+            json.field("mem_used", &self.mem_used);
+            json.field("kernel_alloc", &self.kernel_alloc);
+            json.field("frametime_used", &self.frametime_used);
+            json.field("net_recv", &self.net_recv);
+            json.field("net_sent", &self.net_sent);
+        });
+    }
+}
+
+impl ToJson for AllocStats {
+    fn to_json(&self, json: &mut JsonWriter) {
+        json.object(|json| {
+            json.field("total", &self.total);
+            json.field("reserve", &self.reserve);
+            json.field("nb_grows", &self.nb_grows);
+            json.field("explored", &self.explored);
+            json.field("allocated", &self.allocated);
+            json.field("peak", &self.peak);
+            json.field("lost", &self.lost);
+            json.field("reclaimable", &self.reclaimable);
+            json.field("nb_allocs", &self.nb_allocs);
+            json.field("fragmentation", &self.fragmentation());
+            // Indexed by the log2 of the block size
+            json.field("size_classes", &self.size_classes[..]);
+            json.field("free_size_classes", &self.free_size_classes[..]);
+        });
+    }
+}
+
+impl ToJson for TagStats {
+    fn to_json(&self, json: &mut JsonWriter) {
+        json.object(|json| {
+            json.field("allocated", &self.allocated);
+            json.field("freed", &self.freed);
+            json.field("nb_allocs", &self.nb_allocs);
+        });
+    }
+}
+
+impl ToJson for MemoryStats {
+    fn to_json(&self, json: &mut JsonWriter) {
+        json.object(|json| {
+            json.field("heap", &self.heap);
+            json.field_with("by_tag", |json| {
+                json.object(|json| {
+                    for (tag, tag_stats) in self.by_tag.iter() {
+                        json.field(tag, tag_stats);
+                    }
+                })
+            });
+        });
+    }
+}
+
+impl ToJson for NetworkInterfaceEntry {
+    fn to_json(&self, json: &mut JsonWriter) {
+        // 0 while the interface has no address
+        let addr = match self.prefix_len {
+            0 => None,
+            _ => Some(format!("{}", Ipv4Address(self.addr))),
+        };
+        let gateway = match self.gateway {
+            [0, 0, 0, 0] => None,
+            gateway => Some(format!("{}", Ipv4Address(gateway))),
+        };
+
+        json.object(|json| {
+            json.field("mac", format!("{}", EthernetAddress(self.mac)).as_str());
+            json.field("link_up", &self.link_up());
+            json.field("dhcp", &self.dhcp());
+            json.field("addr", &addr.as_deref());
+            json.field("prefix_len", &self.prefix_len);
+            json.field("gateway", &gateway.as_deref());
+            json.field("route_priority", &self.route_priority);
+        });
+    }
+}
+
+impl ToJson for CaptureStatus {
+    fn to_json(&self, json: &mut JsonWriter) {
+        json.object(|json| {
+            json.field("enabled", &self.enabled);
+            json.field("snaplen", &self.snaplen);
+            json.field("records", &self.nb_records);
+            // Oldest records dropped when the ring was full, before all readers got them
+            json.field("dropped", &self.nb_dropped);
+            json.field("buffered", &self.buffered);
+        });
+    }
+}
+
+impl ToJson for TimeSync {
+    fn to_json(&self, json: &mut JsonWriter) {
+        json.object(|json| {
+            // UNIX time, in s
+            json.field("last_sync", &self.time);
+            json.field("offset", &self.offset);
+            json.field("stepped", &self.stepped);
+            json.field("drift_ppm", &self.drift_ppm);
+        });
+    }
+}
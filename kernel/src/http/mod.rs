@@ -0,0 +1,390 @@
+mod api;
+mod json;
+mod protocol;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use applib::{FbView, FbViewMut, Framebuffer, OwnedPixels, PngEncoder};
+use smoltcp::socket::tcp::State;
+
+use crate::app::AppsManager;
+use crate::network::capture::{CaptureCursor, PacketCapture};
+use crate::network::{TcpHandle, TcpStack};
+use crate::system::System;
+use api::Reply;
+use protocol::{ParseResult, Request, Response};
+
+// New connections are refused until one of them is closed
+const MAX_CONNECTIONS: usize = 8;
+// In ms without anything received or sent, e.g. between keep-alive requests
+const IDLE_TIMEOUT: f64 = 10_000.0;
+// Screenshots are encoded that many rows per frame, so that frames do not take much longer
+const SCREENSHOT_ROWS_PER_FRAME: u32 = 32;
+// Read from a socket at once
+const READ_CHUNK_SIZE: usize = 1024;
+// Of the capture records sent at once
+const CAPTURE_CHUNK_SIZE: usize = 16 * 1024;
+
+// Debug surface with the kernel stats and a control API, see api.rs for the routes. It is
+// polled from the main loop and never waits: requests are handled once fully received,
+// responses are sent as fast as the sockets take them, and screenshots are encoded over
+// several frames.
+pub struct HttpServer {
+    port: u16,
+    // Waiting for a connection, by interface id
+    listeners: Vec<Option<TcpHandle>>,
+    connections: Vec<Connection>,
+    // Captured and being encoded
+    screenshot: Option<Screenshot>,
+}
+
+struct Connection {
+    handle: TcpHandle,
+    // Not handled yet, pipelined requests included
+    received: Vec<u8>,
+    state: ConnectionState,
+    // Of the last data received or sent
+    last_activity: f64,
+}
+
+enum ConnectionState {
+    // Until a whole request is received
+    Reading,
+    WaitingScreenshot {
+        head_only: bool,
+        keep_alive: bool,
+    },
+    Writing {
+        data: Vec<u8>,
+        sent: usize,
+        keep_alive: bool,
+    },
+    // Records from the capture, sent as they come until it is stopped
+    Streaming {
+        cursor: CaptureCursor,
+        // Framed and not sent yet
+        data: Vec<u8>,
+        sent: usize,
+        chunked: bool,
+        keep_alive: bool,
+    },
+    // FIN sent, until the peer closes its side too
+    Closing,
+}
+
+struct Screenshot {
+    fb: Framebuffer<OwnedPixels>,
+    encoder: PngEncoder,
+}
+
+impl HttpServer {
+    pub fn new(port: u16, tcp_stack: &TcpStack) -> Self {
+        log::info!("HTTP server on port {}", port);
+        HttpServer {
+            port,
+            listeners: vec![None; tcp_stack.nb_interfaces()],
+            connections: Vec::new(),
+            screenshot: None,
+        }
+    }
+
+    // Accepts connections, then moves each one forward by at most one request
+    pub fn poll(&mut self, system: &mut System, apps_manager: &mut AppsManager) {
+        let time = system.clock.time();
+
+        self.accept(&mut system.tcp_stack, time);
+        let png = self.encode_screenshot();
+
+        self.connections.retain_mut(|conn| {
+            if let Some(png) = png.as_ref() {
+                conn.send_screenshot(png);
+            }
+            let open = conn.poll(system, apps_manager, time);
+            if !open {
+                system.tcp_stack.close(conn.handle);
+            }
+            open
+        });
+    }
+
+    // A connection waits for the next frame to be captured
+    pub fn wants_screenshot(&self) -> bool {
+        self.screenshot.is_none()
+            && self
+                .connections
+                .iter()
+                .any(|conn| matches!(conn.state, ConnectionState::WaitingScreenshot { .. }))
+    }
+
+    // Copied right away, and encoded over the next frames
+    pub fn capture_screenshot<F: FbView>(&mut self, fb: &F) {
+        let (w, h) = fb.shape();
+        let mut copy = Framebuffer::new_owned(w, h);
+        copy.copy_from_fb(fb, (0, 0), false);
+        self.screenshot = Some(Screenshot {
+            fb: copy,
+            encoder: PngEncoder::new((w, h)),
+        });
+    }
+
+    fn accept(&mut self, tcp_stack: &mut TcpStack, time: f64) {
+        for (iface, listener) in self.listeners.iter_mut().enumerate() {
+            if let Some(handle) = *listener {
+                match tcp_stack.get_socket_state(handle) {
+                    // A failed handshake puts the socket back to listening
+                    State::Listen | State::SynReceived => continue,
+                    _ => {
+                        log::debug!("HTTP connection on interface {} ({:?})", iface, handle);
+                        self.connections.push(Connection {
+                            handle,
+                            received: Vec::new(),
+                            state: ConnectionState::Reading,
+                            last_activity: time,
+                        });
+                        *listener = None;
+                    }
+                }
+            }
+
+            if self.connections.len() < MAX_CONNECTIONS {
+                match tcp_stack.listen(iface, self.port) {
+                    Ok(handle) => *listener = Some(handle),
+                    Err(err) => log::error!("Cannot listen on interface {}: {}", iface, err),
+                }
+            }
+        }
+    }
+
+    // Once all rows are, the PNG
+    fn encode_screenshot(&mut self) -> Option<Vec<u8>> {
+        let screenshot = self.screenshot.as_mut()?;
+        if !screenshot
+            .encoder
+            .encode_rows(&screenshot.fb, SCREENSHOT_ROWS_PER_FRAME)
+        {
+            return None;
+        }
+
+        let screenshot = self.screenshot.take()?;
+        Some(screenshot.encoder.finish())
+    }
+}
+
+impl Connection {
+    // False once the connection can be dropped
+    fn poll(&mut self, system: &mut System, apps_manager: &mut AppsManager, time: f64) -> bool {
+        let handle = self.handle;
+
+        match system.tcp_stack.get_socket_state(handle) {
+            State::Closed | State::TimeWait => return false,
+            _ => (),
+        }
+
+        if time - self.last_activity > IDLE_TIMEOUT {
+            match self.state {
+                ConnectionState::Reading => {
+                    log::debug!("Closing idle HTTP connection {:?}", handle);
+                    self.shutdown(&mut system.tcp_stack, time);
+                }
+                // The peer stopped reading, or does not close its side
+                _ => return false,
+            }
+        }
+
+        match &mut self.state {
+            ConnectionState::Reading => {
+                let tcp_stack = &mut system.tcp_stack;
+                if tcp_stack.can_recv(handle) {
+                    let mut buf = [0u8; READ_CHUNK_SIZE];
+                    let Ok(len) = tcp_stack.read(handle, &mut buf) else {
+                        return false;
+                    };
+                    self.received.extend_from_slice(&buf[..len]);
+                    self.last_activity = time;
+                }
+
+                match protocol::parse_request(&self.received) {
+                    // Unless the peer closed its side before a whole request
+                    ParseResult::Incomplete => {
+                        if !tcp_stack.may_recv(handle) {
+                            self.shutdown(tcp_stack, time);
+                        }
+                    }
+                    ParseResult::Error(response) => {
+                        log::debug!("Bad HTTP request ({})", response.status);
+                        self.received.clear();
+                        self.respond(&response, false, false);
+                    }
+                    ParseResult::Complete(request, len) => {
+                        self.received.drain(..len);
+                        let head_only = request.method == "HEAD";
+                        let keep_alive = request.keep_alive;
+                        match api::route(&request, system, apps_manager) {
+                            Reply::Response(response) => {
+                                log::debug!(
+                                    "{} {} {}",
+                                    request.method,
+                                    request.path,
+                                    response.status
+                                );
+                                self.respond(&response, head_only, keep_alive);
+                            }
+                            Reply::Screenshot => {
+                                self.state = ConnectionState::WaitingScreenshot {
+                                    head_only,
+                                    keep_alive,
+                                };
+                            }
+                            Reply::Capture => {
+                                log::debug!("{} {} streaming", request.method, request.path);
+                                self.stream_capture(
+                                    system.tcp_stack.capture(),
+                                    &request,
+                                    head_only,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            ConnectionState::WaitingScreenshot { .. } => (),
+            ConnectionState::Writing {
+                data,
+                sent,
+                keep_alive,
+            } => {
+                let tcp_stack = &mut system.tcp_stack;
+                if !tcp_stack.may_send(handle) {
+                    return false;
+                }
+                if tcp_stack.can_send(handle) {
+                    let Ok(len) = tcp_stack.write(handle, &data[*sent..]) else {
+                        return false;
+                    };
+                    *sent += len;
+                    self.last_activity = time;
+                }
+
+                if *sent == data.len() {
+                    match *keep_alive {
+                        true => self.state = ConnectionState::Reading,
+                        false => self.shutdown(tcp_stack, time),
+                    }
+                }
+            }
+            ConnectionState::Streaming {
+                cursor,
+                data,
+                sent,
+                chunked,
+                keep_alive,
+            } => {
+                let tcp_stack = &mut system.tcp_stack;
+                if !tcp_stack.may_send(handle) {
+                    return false;
+                }
+
+                // Waiting for packets is not the peer being idle
+                if *sent == data.len() {
+                    data.clear();
+                    *sent = 0;
+                    self.last_activity = time;
+
+                    let mut records = Vec::new();
+                    match tcp_stack
+                        .capture()
+                        .read(cursor, &mut records, CAPTURE_CHUNK_SIZE)
+                    {
+                        Some(missed) => {
+                            if missed > 0 {
+                                log::debug!(
+                                    "{} capture records dropped before {:?} read them",
+                                    missed,
+                                    handle
+                                );
+                            }
+                            if !records.is_empty() {
+                                protocol::push_chunk(data, &records, *chunked);
+                            }
+                        }
+                        // The capture was stopped, or restarted
+                        None => {
+                            let mut end = Vec::new();
+                            if *chunked {
+                                protocol::push_chunk(&mut end, &[], true);
+                            }
+                            self.state = ConnectionState::Writing {
+                                data: end,
+                                sent: 0,
+                                keep_alive: *keep_alive,
+                            };
+                            return true;
+                        }
+                    }
+                }
+
+                if *sent < data.len() && tcp_stack.can_send(handle) {
+                    let Ok(len) = tcp_stack.write(handle, &data[*sent..]) else {
+                        return false;
+                    };
+                    *sent += len;
+                    self.last_activity = time;
+                }
+            }
+            ConnectionState::Closing => (),
+        }
+
+        true
+    }
+
+    fn respond(&mut self, response: &Response, head_only: bool, keep_alive: bool) {
+        self.state = ConnectionState::Writing {
+            data: response.serialize(head_only, keep_alive),
+            sent: 0,
+            keep_alive,
+        };
+    }
+
+    fn send_screenshot(&mut self, png: &[u8]) {
+        if let ConnectionState::WaitingScreenshot {
+            head_only,
+            keep_alive,
+        } = self.state
+        {
+            let response = Response::new(200, "image/png", png.to_vec());
+            self.respond(&response, head_only, keep_alive);
+        }
+    }
+
+    // HTTP/1.0 clients read the body until the connection is closed
+    fn stream_capture(&mut self, capture: &PacketCapture, request: &Request, head_only: bool) {
+        let chunked = request.http_1_1;
+        let keep_alive = request.keep_alive && chunked;
+        let mut data =
+            protocol::streaming_head("application/vnd.tcpdump.pcap", chunked, keep_alive);
+
+        self.state = match head_only {
+            true => ConnectionState::Writing {
+                data,
+                sent: 0,
+                keep_alive,
+            },
+            false => {
+                protocol::push_chunk(&mut data, &PacketCapture::file_header(), chunked);
+                ConnectionState::Streaming {
+                    cursor: capture.cursor(),
+                    data,
+                    sent: 0,
+                    chunked,
+                    keep_alive,
+                }
+            }
+        };
+    }
+
+    fn shutdown(&mut self, tcp_stack: &mut TcpStack, time: f64) {
+        tcp_stack.shutdown(self.handle);
+        self.state = ConnectionState::Closing;
+        self.last_activity = time;
+    }
+}
@@ -0,0 +1,265 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+// Of the request line and headers together, larger ones get a 431
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 32;
+// Larger bodies get a 413, none of the routes takes much
+const MAX_BODY_SIZE: usize = 4096;
+
+pub struct Request {
+    pub method: String,
+    // Percent-decoded, without the query
+    pub path: String,
+    // Percent-decoded, in the order they came
+    pub query: Vec<(String, String)>,
+    // Whether the connection stays open after the response
+    pub keep_alive: bool,
+    // HTTP/1.0 clients do not take chunked responses
+    pub http_1_1: bool,
+}
+
+pub enum ParseResult {
+    // Waiting for more data
+    Incomplete,
+    // With the number of bytes it took, body included
+    Complete(Request, usize),
+    // The request cannot be read, the connection is closed after the error response
+    Error(Response),
+}
+
+impl Request {
+    pub fn query(&self, key: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+// Parses the request at the start of `data`, the bytes after it belong to the next ones
+pub fn parse_request(data: &[u8]) -> ParseResult {
+    let Some(head_len) = data.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return match data.len() > MAX_HEAD_SIZE {
+            true => ParseResult::Error(Response::error(431)),
+            false => ParseResult::Incomplete,
+        };
+    };
+    if head_len > MAX_HEAD_SIZE {
+        return ParseResult::Error(Response::error(431));
+    }
+
+    let Ok(head) = core::str::from_utf8(&data[..head_len]) else {
+        return ParseResult::Error(Response::error(400));
+    };
+    let mut lines = head.split("\r\n");
+
+    let request_line = lines.next().unwrap_or("");
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return ParseResult::Error(Response::error(400));
+    };
+    // HTTP/1.0 clients only keep the connection when they ask for it
+    let mut keep_alive = match version {
+        "HTTP/1.1" => true,
+        "HTTP/1.0" => false,
+        _ if version.starts_with("HTTP/") => return ParseResult::Error(Response::error(505)),
+        _ => return ParseResult::Error(Response::error(400)),
+    };
+    if method.is_empty() || !target.starts_with('/') {
+        return ParseResult::Error(Response::error(400));
+    }
+
+    let mut content_length = 0;
+    for (i, line) in lines.enumerate() {
+        if i >= MAX_HEADERS {
+            return ParseResult::Error(Response::error(431));
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return ParseResult::Error(Response::error(400));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            let Ok(len) = value.parse::<usize>() else {
+                return ParseResult::Error(Response::error(400));
+            };
+            content_length = len;
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            // Chunked request bodies are not supported
+            return ParseResult::Error(Response::error(501));
+        } else if name.eq_ignore_ascii_case("connection") {
+            for option in value.split(',').map(str::trim) {
+                if option.eq_ignore_ascii_case("close") {
+                    keep_alive = false;
+                } else if option.eq_ignore_ascii_case("keep-alive") {
+                    keep_alive = true;
+                }
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return ParseResult::Error(Response::error(413));
+    }
+    // The body is not used by any route, it is only skipped
+    let request_len = head_len + 4 + content_length;
+    if data.len() < request_len {
+        return ParseResult::Incomplete;
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let Some(path) = percent_decode(path) else {
+        return ParseResult::Error(Response::error(400));
+    };
+    let mut query_pairs = Vec::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let (Some(key), Some(value)) = (percent_decode(key), percent_decode(value)) else {
+            return ParseResult::Error(Response::error(400));
+        };
+        query_pairs.push((key, value));
+    }
+
+    let request = Request {
+        method: method.to_string(),
+        path,
+        query: query_pairs,
+        keep_alive,
+        http_1_1: version == "HTTP/1.1",
+    };
+
+    ParseResult::Complete(request, request_len)
+}
+
+// "+" is only a space in queries, but no route has one in its path
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'%' => {
+                let hex = [iter.next()?, iter.next()?];
+                let hex = core::str::from_utf8(&hex).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            b'+' => bytes.push(b' '),
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+pub struct Response {
+    pub status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+    // Methods of the path, for a 405
+    allow: Option<&'static str>,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &'static str, body: Vec<u8>) -> Self {
+        Response {
+            status,
+            content_type,
+            body,
+            allow: None,
+        }
+    }
+
+    pub fn json(body: String) -> Self {
+        Response::new(200, "application/json", body.into_bytes())
+    }
+
+    pub fn text(status: u16, body: String) -> Self {
+        Response::new(status, "text/plain; charset=utf-8", body.into_bytes())
+    }
+
+    // With the reason phrase as the body
+    pub fn error(status: u16) -> Self {
+        Response::text(status, format!("{} {}\n", status, reason_phrase(status)))
+    }
+
+    pub fn method_not_allowed(allow: &'static str) -> Self {
+        Response {
+            allow: Some(allow),
+            ..Response::error(405)
+        }
+    }
+
+    // Status line, headers and body. Responses to HEAD requests have the headers of the
+    // body without the body.
+    pub fn serialize(&self, head_only: bool, keep_alive: bool) -> Vec<u8> {
+        let connection = match keep_alive {
+            true => "keep-alive",
+            false => "close",
+        };
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: {}\r\nCache-Control: no-store\r\n",
+            self.status,
+            reason_phrase(self.status),
+            self.content_type,
+            self.body.len(),
+            connection
+        );
+        if let Some(allow) = self.allow {
+            head.push_str(&format!("Allow: {}\r\n", allow));
+        }
+        head.push_str("\r\n");
+
+        let mut data = head.into_bytes();
+        if !head_only {
+            data.extend_from_slice(&self.body);
+        }
+        data
+    }
+}
+
+// Of a 200 response whose body is sent as it comes, in chunks or else until the connection
+// is closed
+pub fn streaming_head(content_type: &'static str, chunked: bool, keep_alive: bool) -> Vec<u8> {
+    let connection = match keep_alive {
+        true => "keep-alive",
+        false => "close",
+    };
+    let mut head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nConnection: {}\r\nCache-Control: no-store\r\n",
+        content_type, connection
+    );
+    if chunked {
+        head.push_str("Transfer-Encoding: chunked\r\n");
+    }
+    head.push_str("\r\n");
+    head.into_bytes()
+}
+
+// Appends `data` to a streamed body, an empty chunk ends a chunked one
+pub fn push_chunk(out: &mut Vec<u8>, data: &[u8], chunked: bool) {
+    if !chunked {
+        out.extend_from_slice(data);
+        return;
+    }
+    out.extend_from_slice(format!("{:x}\r\n", data.len()).as_bytes());
+    out.extend_from_slice(data);
+    out.extend_from_slice(b"\r\n");
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Content Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
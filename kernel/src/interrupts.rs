@@ -0,0 +1,374 @@
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use pic8259::ChainedPics;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::Cr2;
+use x86_64::registers::model_specific::Msr;
+use x86_64::structures::idt::{
+    HandlerFunc, InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode,
+};
+use x86_64::PhysAddr;
+
+use crate::memory;
+use crate::time::SystemClock;
+
+// The legacy PICs are moved there, only their spurious interrupts can still come in
+const PIC_OFFSET: u8 = 0x20;
+const TIMER_VECTOR: u8 = 0x30;
+// Handed out to devices from there on
+const FIRST_DEVICE_VECTOR: u8 = 0x31;
+const NB_DEVICE_VECTORS: usize = 16;
+const SPURIOUS_VECTOR: u8 = 0xff;
+
+// Wakes the CPU from hlt at least that often (in ms), for the frame deadline
+const TIMER_PERIOD_MS: f64 = 1.0;
+// How long the APIC timer is measured against the TSC at boot (in ms)
+const TIMER_CALIBRATION_MS: f64 = 10.0;
+
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+// Local APIC registers
+const APIC_ID: usize = 0x20;
+const APIC_EOI: usize = 0xb0;
+const APIC_SPURIOUS: usize = 0xf0;
+const APIC_LVT_TIMER: usize = 0x320;
+const APIC_TIMER_INITIAL: usize = 0x380;
+const APIC_TIMER_CURRENT: usize = 0x390;
+const APIC_TIMER_DIVIDE: usize = 0x3e0;
+
+const APIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_PERIODIC: u32 = 1 << 17;
+const TIMER_DIVIDE_BY_16: u32 = 0x3;
+
+// Where MSIs are written to reach a local APIC
+const MSI_ADDRESS: u64 = 0xfee0_0000;
+
+// Virtual address of the local APIC registers, 0 until init() succeeded
+static APIC: AtomicU64 = AtomicU64::new(0);
+// Drivers wait for their interrupts, otherwise they poll every frame
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+// By device vector, from FIRST_DEVICE_VECTOR on
+static PENDING: [AtomicBool; NB_DEVICE_VECTORS] =
+    [const { AtomicBool::new(false) }; NB_DEVICE_VECTORS];
+static COUNTS: [AtomicU64; NB_DEVICE_VECTORS] = [const { AtomicU64::new(0) }; NB_DEVICE_VECTORS];
+// TSC when the vector was raised, while it is pending
+static RAISED_AT: [AtomicU64; NB_DEVICE_VECTORS] = [const { AtomicU64::new(0) }; NB_DEVICE_VECTORS];
+// Names of the vectors handed out so far
+static VECTORS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+// A device interrupt came in since idle_until() last looked
+static WOKEN: AtomicBool = AtomicBool::new(false);
+static TIMER_TICKS: AtomicU64 = AtomicU64::new(0);
+static UNEXPECTED: AtomicU64 = AtomicU64::new(0);
+
+// From an input interrupt to the cursor moving on screen, in TSC cycles
+static LATENCY_SAMPLES: AtomicU64 = AtomicU64::new(0);
+static LATENCY_SUM: AtomicU64 = AtomicU64::new(0);
+static LATENCY_MAX: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+
+        idt.divide_error.set_handler_fn(divide_error);
+        idt.invalid_opcode.set_handler_fn(invalid_opcode);
+        idt.general_protection_fault
+            .set_handler_fn(general_protection_fault);
+        idt.page_fault.set_handler_fn(page_fault);
+        // Without a stack of its own, a stack overflow still ends in a triple fault
+        idt.double_fault.set_handler_fn(double_fault);
+
+        for vector in PIC_OFFSET..=u8::MAX {
+            idt[vector].set_handler_fn(unexpected_interrupt);
+        }
+        for vector in PIC_OFFSET..PIC_OFFSET + 16 {
+            idt[vector].set_handler_fn(legacy_interrupt);
+        }
+        idt[TIMER_VECTOR].set_handler_fn(timer_interrupt);
+        for (i, handler) in DEVICE_HANDLERS.into_iter().enumerate() {
+            idt[FIRST_DEVICE_VECTOR + i as u8].set_handler_fn(handler);
+        }
+        idt[SPURIOUS_VECTOR].set_handler_fn(spurious_interrupt);
+
+        idt
+    };
+}
+
+// One handler per device vector, they cannot tell otherwise which one fired
+macro_rules! device_handlers {
+    ($($index:literal)*) => {
+        [$({
+            extern "x86-interrupt" fn handler(_frame: InterruptStackFrame) {
+                device_interrupt($index);
+            }
+            handler as HandlerFunc
+        }),*]
+    };
+}
+
+const DEVICE_HANDLERS: [HandlerFunc; NB_DEVICE_VECTORS] =
+    device_handlers!(0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15);
+
+// Loads the IDT and enables the local APIC, so that devices can be given MSI vectors.
+// With `armed`, drivers wait for them, otherwise they keep polling and interrupts are
+// only counted. Without a local APIC, devices are polled.
+pub fn init(armed: bool) {
+    IDT.load();
+
+    // The PICs are never used, but their spurious interrupts must not look like exceptions
+    unsafe {
+        let mut pics = ChainedPics::new(PIC_OFFSET, PIC_OFFSET + 8);
+        pics.initialize();
+        pics.disable();
+    }
+
+    let has_apic = unsafe { core::arch::x86_64::__cpuid(1).edx } & (1 << 9) != 0;
+    if !has_apic {
+        log::warn!("No local APIC, devices are polled");
+        return;
+    }
+
+    let mut apic_base_msr = Msr::new(IA32_APIC_BASE);
+    let apic_base = unsafe { apic_base_msr.read() };
+    if apic_base & APIC_BASE_ENABLE == 0 {
+        unsafe { apic_base_msr.write(apic_base | APIC_BASE_ENABLE) };
+    }
+
+    let phys_addr = PhysAddr::new(apic_base & 0xffff_f000);
+    let virt_addr = memory::get_mapper().phys_to_virt(phys_addr);
+    APIC.store(virt_addr.as_u64(), Ordering::Relaxed);
+
+    // Whatever the firmware left running stops
+    apic_write(APIC_LVT_TIMER, LVT_MASKED);
+    apic_write(APIC_SPURIOUS, APIC_SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32);
+
+    ARMED.store(armed, Ordering::Relaxed);
+    interrupts::enable();
+
+    log::info!(
+        "Local APIC {} at {:#x}, drivers {}",
+        apic_id(),
+        phys_addr.as_u64(),
+        match armed {
+            true => "wait for interrupts",
+            false => "poll",
+        }
+    );
+}
+
+// Wakes the CPU periodically while it waits for the next frame. Only needed when drivers
+// wait for interrupts, as the CPU spins otherwise.
+pub fn start_timer(clock: &SystemClock) {
+    if !armed() {
+        return;
+    }
+
+    // Its frequency is unknown, it is measured against the TSC
+    apic_write(APIC_TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+    apic_write(APIC_LVT_TIMER, LVT_MASKED | TIMER_VECTOR as u32);
+    apic_write(APIC_TIMER_INITIAL, u32::MAX);
+    clock.spin_delay(TIMER_CALIBRATION_MS);
+    let counted = u32::MAX - apic_read(APIC_TIMER_CURRENT);
+
+    let period = (counted as f64 * TIMER_PERIOD_MS / TIMER_CALIBRATION_MS) as u32;
+    apic_write(APIC_LVT_TIMER, LVT_PERIODIC | TIMER_VECTOR as u32);
+    apic_write(APIC_TIMER_INITIAL, u32::max(period, 1));
+
+    log::info!(
+        "APIC timer ticking every {}ms ({} counts)",
+        TIMER_PERIOD_MS,
+        period
+    );
+}
+
+pub fn armed() -> bool {
+    ARMED.load(Ordering::Relaxed)
+}
+
+// A vector for a device to raise, None without a local APIC or when all are taken
+pub fn alloc_vector(name: &'static str) -> Option<u8> {
+    if APIC.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+
+    let mut vectors = VECTORS.lock();
+    if vectors.len() >= NB_DEVICE_VECTORS {
+        log::warn!("No interrupt vector left for {}", name);
+        return None;
+    }
+    let vector = FIRST_DEVICE_VECTOR + vectors.len() as u8;
+    vectors.push(name);
+
+    Some(vector)
+}
+
+// MSI address and data for the device to raise `vector` on this CPU
+pub fn msi_message(vector: u8) -> (u64, u32) {
+    (MSI_ADDRESS | ((apic_id() as u64) << 12), vector as u32)
+}
+
+// Clears the vector, and returns the TSC when it was raised if it was
+pub fn take(vector: u8) -> Option<u64> {
+    let i = (vector - FIRST_DEVICE_VECTOR) as usize;
+    interrupts::without_interrupts(|| {
+        PENDING[i]
+            .swap(false, Ordering::Relaxed)
+            .then(|| RAISED_AT[i].load(Ordering::Relaxed))
+    })
+}
+
+// Returns once the clock reaches `deadline` (in ms). When drivers wait for interrupts, the
+// CPU halts in between and `on_wake` is called after each device interrupt. Otherwise,
+// it spins.
+pub fn idle_until<F: FnMut()>(clock: &SystemClock, deadline: f64, mut on_wake: F) {
+    while clock.time() < deadline {
+        if !armed() {
+            core::hint::spin_loop();
+            continue;
+        }
+
+        // Nothing can come in between the check and hlt
+        interrupts::disable();
+        match WOKEN.swap(false, Ordering::Relaxed) {
+            true => {
+                interrupts::enable();
+                on_wake();
+            }
+            false => interrupts::enable_and_hlt(),
+        }
+    }
+}
+
+// Vectors handed out, with their name and how many times they fired
+pub fn vectors() -> Vec<(u8, &'static str, u64)> {
+    let vectors = VECTORS.lock();
+    vectors
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let count = COUNTS[i].load(Ordering::Relaxed);
+            (FIRST_DEVICE_VECTOR + i as u8, *name, count)
+        })
+        .collect()
+}
+
+pub fn timer_ticks() -> u64 {
+    TIMER_TICKS.load(Ordering::Relaxed)
+}
+
+pub fn unexpected_count() -> u64 {
+    UNEXPECTED.load(Ordering::Relaxed)
+}
+
+// `raised_at` is what take() returned for the input events that moved the cursor
+pub fn record_cursor_latency(raised_at: u64) {
+    let now = unsafe { core::arch::x86_64::_rdtsc() };
+    let cycles = now.saturating_sub(raised_at);
+    LATENCY_SAMPLES.fetch_add(1, Ordering::Relaxed);
+    LATENCY_SUM.fetch_add(cycles, Ordering::Relaxed);
+    LATENCY_MAX.fetch_max(cycles, Ordering::Relaxed);
+}
+
+// So that a measurement leaves out what came before, like boot
+pub fn reset_cursor_latency() {
+    LATENCY_SAMPLES.store(0, Ordering::Relaxed);
+    LATENCY_SUM.store(0, Ordering::Relaxed);
+    LATENCY_MAX.store(0, Ordering::Relaxed);
+}
+
+// Number of samples, mean and max in TSC cycles. None before the cursor first moved.
+pub fn cursor_latency() -> Option<(u64, u64, u64)> {
+    let samples = LATENCY_SAMPLES.load(Ordering::Relaxed);
+    if samples == 0 {
+        return None;
+    }
+    let sum = LATENCY_SUM.load(Ordering::Relaxed);
+    let max = LATENCY_MAX.load(Ordering::Relaxed);
+    Some((samples, sum / samples, max))
+}
+
+fn apic_read(register: usize) -> u32 {
+    let ptr = (APIC.load(Ordering::Relaxed) as usize + register) as *const u32;
+    unsafe { read_volatile(ptr) }
+}
+
+fn apic_write(register: usize, val: u32) {
+    let ptr = (APIC.load(Ordering::Relaxed) as usize + register) as *mut u32;
+    unsafe { write_volatile(ptr, val) }
+}
+
+fn apic_id() -> u8 {
+    (apic_read(APIC_ID) >> 24) as u8
+}
+
+fn end_of_interrupt() {
+    apic_write(APIC_EOI, 0);
+}
+
+fn device_interrupt(i: usize) {
+    COUNTS[i].fetch_add(1, Ordering::Relaxed);
+    // Only the first one counts until the driver takes it
+    if !PENDING[i].load(Ordering::Relaxed) {
+        let now = unsafe { core::arch::x86_64::_rdtsc() };
+        RAISED_AT[i].store(now, Ordering::Relaxed);
+        PENDING[i].store(true, Ordering::Relaxed);
+    }
+    WOKEN.store(true, Ordering::Relaxed);
+    end_of_interrupt();
+}
+
+extern "x86-interrupt" fn timer_interrupt(_frame: InterruptStackFrame) {
+    TIMER_TICKS.fetch_add(1, Ordering::Relaxed);
+    end_of_interrupt();
+}
+
+extern "x86-interrupt" fn unexpected_interrupt(_frame: InterruptStackFrame) {
+    UNEXPECTED.fetch_add(1, Ordering::Relaxed);
+    end_of_interrupt();
+}
+
+// Spurious interrupts, from the masked PICs or the local APIC, are not acknowledged
+extern "x86-interrupt" fn legacy_interrupt(_frame: InterruptStackFrame) {}
+
+extern "x86-interrupt" fn spurious_interrupt(_frame: InterruptStackFrame) {}
+
+extern "x86-interrupt" fn divide_error(frame: InterruptStackFrame) {
+    panic!(
+        "Division by zero at {:#x}",
+        frame.instruction_pointer.as_u64()
+    );
+}
+
+extern "x86-interrupt" fn invalid_opcode(frame: InterruptStackFrame) {
+    panic!(
+        "Invalid opcode at {:#x}",
+        frame.instruction_pointer.as_u64()
+    );
+}
+
+extern "x86-interrupt" fn general_protection_fault(frame: InterruptStackFrame, error_code: u64) {
+    panic!(
+        "General protection fault at {:#x} (error code {:#x})",
+        frame.instruction_pointer.as_u64(),
+        error_code
+    );
+}
+
+extern "x86-interrupt" fn page_fault(frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
+    panic!(
+        "Page fault at {:#x} accessing {:#x} ({:?})",
+        frame.instruction_pointer.as_u64(),
+        Cr2::read_raw(),
+        error_code
+    );
+}
+
+extern "x86-interrupt" fn double_fault(frame: InterruptStackFrame, _error_code: u64) -> ! {
+    panic!("Double fault at {:#x}", frame.instruction_pointer.as_u64());
+}
@@ -0,0 +1,98 @@
+use alloc::collections::BTreeSet;
+use applib::input::{InputEvent, InputState, Keycode};
+
+// After a long frame, the repeats missed in between are not all sent at once
+const MAX_REPEATS_PER_FRAME: usize = 2;
+
+// Turns what the virtio keyboard reports into the key events apps get. The device does
+// not repeat held keys, so that is done here.
+pub struct Keyboard {
+    // In ms, before a held key first repeats, then between repeats
+    repeat_delay: f64,
+    repeat_interval: f64,
+    // As the device reported them
+    held: BTreeSet<Keycode>,
+    // The last key pressed, and when it repeats next while it is held
+    repeating: Option<(Keycode, f64)>,
+}
+
+impl Keyboard {
+    // `repeat_rate` is in repeats per second
+    pub fn new(repeat_delay: f64, repeat_rate: f64) -> Self {
+        Keyboard {
+            repeat_delay,
+            repeat_interval: 1000.0 / repeat_rate,
+            held: BTreeSet::new(),
+            repeating: None,
+        }
+    }
+
+    // `value` is 0 for a release, 1 for a press and 2 for a repeat by the device
+    pub fn key_event(
+        &mut self,
+        input_state: &mut InputState,
+        keycode: Keycode,
+        value: u32,
+        time: f64,
+    ) {
+        match value {
+            0 => {
+                // A key already down at boot is not released to the apps
+                if self.held.remove(&keycode) {
+                    input_state.add_event(InputEvent::KeyRelease { keycode });
+                }
+                if self
+                    .repeating
+                    .is_some_and(|(repeated, _)| repeated == keycode)
+                {
+                    self.repeating = None;
+                }
+            }
+            1 | 2 => {
+                // Repeats from the device or the host are dropped, keys repeat at the
+                // rate set here
+                if !self.held.insert(keycode) {
+                    return;
+                }
+                input_state.add_event(InputEvent::KeyPress { keycode });
+                // Pressing a modifier does not stop another key from repeating
+                if repeats(keycode) {
+                    self.repeating = Some((keycode, time + self.repeat_delay));
+                }
+            }
+            val => log::warn!("Unknown key state {}", val),
+        }
+    }
+
+    // Presses of the held key that are due by `time`
+    pub fn repeat(&mut self, input_state: &mut InputState, time: f64) {
+        let Some((keycode, next_t)) = self.repeating.as_mut() else {
+            return;
+        };
+
+        let mut nb_repeats = 0;
+        while *next_t <= time && nb_repeats < MAX_REPEATS_PER_FRAME {
+            input_state.add_event(InputEvent::KeyPress { keycode: *keycode });
+            *next_t += self.repeat_interval;
+            nb_repeats += 1;
+        }
+        if *next_t <= time {
+            *next_t = time + self.repeat_interval;
+        }
+    }
+}
+
+fn repeats(keycode: Keycode) -> bool {
+    !matches!(
+        keycode,
+        Keycode::KEY_LEFTSHIFT
+            | Keycode::KEY_RIGHTSHIFT
+            | Keycode::KEY_LEFTCTRL
+            | Keycode::KEY_RIGHTCTRL
+            | Keycode::KEY_LEFTALT
+            | Keycode::KEY_RIGHTALT
+            | Keycode::KEY_LEFTMETA
+            | Keycode::KEY_RIGHTMETA
+            | Keycode::KEY_CAPSLOCK
+    )
+}
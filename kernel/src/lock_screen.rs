@@ -0,0 +1,352 @@
+use alloc::format;
+use alloc::string::String;
+use applib::drawing::primitives::draw_rect;
+use applib::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use applib::input::{InputEvent, InputState, Keycode};
+use applib::{FbViewMut, Rect, StyleSheet};
+use chrono::{DateTime, Datelike, Month, Timelike, Utc};
+use num_traits::Float;
+use rand::Rng;
+
+use crate::random::SystemRng;
+use crate::sha256::sha256;
+use crate::storage::Storage;
+
+// Salt then hash, written when the passphrase is first chosen
+const PASSPHRASE_FILE: &str = "kernel.lock_screen.hash";
+const SALT_LEN: usize = 16;
+const HASH_ROUNDS: usize = 10_000;
+const MAX_PASSPHRASE_LEN: usize = 64;
+
+// Doubled after each failed attempt, from the first one
+const RETRY_DELAY: f64 = 1000.0; // ms
+const MAX_RETRY_DELAY: f64 = 60_000.0; // ms
+
+const FIELD_W: u32 = 320;
+const FIELD_H: u32 = 36;
+const GAP_H: u32 = 16;
+const DOT_SIZE: u32 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+enum LockState {
+    Unlocked,
+    // The passphrase is typed twice on the first lock
+    Choosing,
+    Confirming(String),
+    Locked,
+}
+
+// While locked, the kernel takes all the input and the apps are not drawn
+pub struct LockScreen {
+    state: LockState,
+    // Never drawn, only its length is
+    typed: String,
+    failed_attempts: u32,
+    // Typing is ignored until then
+    retry_at: f64,
+    message: Option<&'static str>,
+    last_activity: f64,
+}
+
+impl LockScreen {
+    pub fn new(time: f64) -> Self {
+        LockScreen {
+            state: LockState::Unlocked,
+            typed: String::new(),
+            failed_attempts: 0,
+            retry_at: 0.0,
+            message: None,
+            last_activity: time,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.state != LockState::Unlocked
+    }
+
+    // Until then, locking asks for one to be chosen
+    pub fn has_passphrase(&self, storage: &Storage) -> bool {
+        matches!(storage.read(PASSPHRASE_FILE), Ok(stored) if stored.len() == SALT_LEN + 32)
+    }
+
+    pub fn lock(&mut self, storage: &Storage) {
+        if self.is_locked() {
+            return;
+        }
+
+        log::info!("Locking the screen");
+        self.state = match self.has_passphrase(storage) {
+            true => LockState::Locked,
+            false => LockState::Choosing,
+        };
+        self.typed.clear();
+        self.message = None;
+    }
+
+    // Any input event, key or pointer
+    pub fn record_activity(&mut self, time: f64) {
+        self.last_activity = time;
+    }
+
+    pub fn idle_time(&self, time: f64) -> f64 {
+        time - self.last_activity
+    }
+
+    pub fn update(
+        &mut self,
+        input_state: &InputState,
+        storage: &mut Storage,
+        rng: &mut SystemRng,
+        time: f64,
+    ) {
+        if time < self.retry_at {
+            return;
+        }
+
+        for event in input_state.events.iter().flatten() {
+            let InputEvent::KeyPress { keycode } = *event else {
+                continue;
+            };
+            match keycode {
+                Keycode::KEY_ENTER => self.submit(storage, rng, time),
+                Keycode::KEY_BACKSPACE => {
+                    self.typed.pop();
+                }
+                Keycode::KEY_ESC => self.typed.clear(),
+                keycode => {
+                    if let Some(c) = input_state.key_char(keycode) {
+                        if self.typed.chars().count() < MAX_PASSPHRASE_LEN {
+                            self.typed.push(c);
+                        }
+                    }
+                }
+            }
+
+            // Keys typed after a failed attempt are dropped
+            if time < self.retry_at {
+                break;
+            }
+        }
+    }
+
+    fn submit(&mut self, storage: &mut Storage, rng: &mut SystemRng, time: f64) {
+        let typed = core::mem::take(&mut self.typed);
+
+        match core::mem::replace(&mut self.state, LockState::Unlocked) {
+            LockState::Unlocked => (),
+
+            LockState::Choosing if typed.is_empty() => {
+                self.state = LockState::Choosing;
+                self.message = Some("The passphrase cannot be empty");
+            }
+            LockState::Choosing => {
+                self.state = LockState::Confirming(typed);
+                self.message = None;
+            }
+
+            LockState::Confirming(chosen) if chosen != typed => {
+                self.state = LockState::Choosing;
+                self.message = Some("The passphrases do not match");
+            }
+            LockState::Confirming(_) => {
+                // Not guessable from the boot time alone
+                let mut seed = [0u8; 16];
+                rng.fill(&mut seed);
+                seed[..8].copy_from_slice(&time.to_le_bytes());
+                let mut salt = [0u8; SALT_LEN];
+                salt.copy_from_slice(&sha256(&seed)[..SALT_LEN]);
+
+                let mut data = salt.to_vec();
+                data.extend_from_slice(&hash_passphrase(&salt, &typed));
+                match storage.write(PASSPHRASE_FILE, &data, time) {
+                    Ok(()) => {
+                        log::info!("Lock screen passphrase set");
+                        self.state = LockState::Locked;
+                        self.message = Some("Passphrase set");
+                    }
+                    // Unlocked rather than locked for good
+                    Err(err) => {
+                        log::error!("Cannot save the lock screen passphrase: {:?}", err);
+                        self.message = None;
+                    }
+                }
+            }
+
+            LockState::Locked => {
+                // A missing or damaged file would otherwise lock the user out for good
+                let stored = match storage.read(PASSPHRASE_FILE) {
+                    Ok(stored) if stored.len() == SALT_LEN + 32 => stored,
+                    Ok(stored) => {
+                        log::error!(
+                            "Invalid lock screen passphrase file ({} bytes)",
+                            stored.len()
+                        );
+                        self.choose_again();
+                        return;
+                    }
+                    Err(err) => {
+                        log::error!("Cannot read the lock screen passphrase: {:?}", err);
+                        self.choose_again();
+                        return;
+                    }
+                };
+
+                let (salt, hash) = stored.split_at(SALT_LEN);
+                // Same time whatever the first differing byte
+                let hashed = hash_passphrase(salt, &typed);
+                let matches = hashed.iter().zip(hash).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0;
+
+                match matches {
+                    true => {
+                        log::info!("Screen unlocked");
+                        self.failed_attempts = 0;
+                        self.message = None;
+                    }
+                    false => {
+                        self.state = LockState::Locked;
+                        self.failed_attempts += 1;
+                        let delay = RETRY_DELAY * 2f64.powi(self.failed_attempts as i32 - 1);
+                        self.retry_at = time + f64::min(delay, MAX_RETRY_DELAY);
+                        self.message = Some("Wrong passphrase");
+                        log::warn!("Failed unlock attempt ({})", self.failed_attempts);
+                    }
+                }
+            }
+        }
+    }
+
+    fn choose_again(&mut self) {
+        self.state = LockState::Choosing;
+        self.failed_attempts = 0;
+        self.message = Some("The passphrase was lost, choose a new one");
+    }
+
+    // Covers the whole screen. The damage is left to the caller except for the clock, the
+    // prompt and the field, as they are the only parts which change while locked.
+    pub fn draw<F: FbViewMut>(
+        &self,
+        fb: &mut F,
+        stylesheet: &StyleSheet,
+        datetime: DateTime<Utc>,
+        time: f64,
+    ) {
+        let colors = &stylesheet.colors;
+        let clock_font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.large);
+        let font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.medium);
+
+        let screen_rect = {
+            let (w, h) = fb.shape();
+            Rect { x0: 0, y0: 0, w, h }
+        };
+
+        let damage = fb.take_damage();
+        draw_rect(fb, &screen_rect, colors.background, false);
+        fb.set_damage(damage);
+
+        let (xc, yc) = screen_rect.center();
+        let line_rect = |y0: i64, h: u32| Rect {
+            x0: xc - FIELD_W as i64 / 2,
+            y0,
+            w: FIELD_W,
+            h,
+        };
+
+        //
+        // Clock
+
+        let clock_h = clock_font.char_h as u32;
+        let clock_rect = line_rect(yc - (2 * clock_h + 4 * GAP_H) as i64, clock_h);
+        let clock_str = format!("{:02}:{:02}", datetime.hour(), datetime.minute());
+        draw_line_in_rect(
+            fb,
+            &clock_str,
+            &clock_rect,
+            clock_font,
+            colors.text,
+            TextJustification::Center,
+        );
+
+        let date_rect = line_rect(clock_rect.y0 + clock_h as i64, font.char_h as u32);
+        let month_str = Month::try_from(datetime.month() as u8).unwrap().name();
+        let date_str = format!("{}, {} {}", datetime.weekday(), month_str, datetime.day());
+        draw_line_in_rect(
+            fb,
+            &date_str,
+            &date_rect,
+            font,
+            colors.disabled,
+            TextJustification::Center,
+        );
+
+        //
+        // Passphrase
+
+        let prompt = match self.state {
+            LockState::Choosing => "Choose a passphrase",
+            LockState::Confirming(_) => "Type it again",
+            _ => "Type the passphrase to unlock",
+        };
+        let prompt_rect = line_rect(yc - (font.char_h as u32 + GAP_H) as i64, font.char_h as u32);
+        draw_line_in_rect(
+            fb,
+            prompt,
+            &prompt_rect,
+            font,
+            colors.text,
+            TextJustification::Center,
+        );
+
+        let field_rect = line_rect(yc, FIELD_H);
+        draw_rect(fb, &field_rect, colors.editable, false);
+        let nb_dots = self.typed.chars().count() as u32;
+        let dots_w = u32::min(nb_dots * 2 * DOT_SIZE, FIELD_W);
+        let (_, field_yc) = field_rect.center();
+        for i in 0..(dots_w / (2 * DOT_SIZE)) {
+            let dot_rect = Rect {
+                x0: xc - dots_w as i64 / 2 + (i * 2 * DOT_SIZE + DOT_SIZE / 2) as i64,
+                y0: field_yc - DOT_SIZE as i64 / 2,
+                w: DOT_SIZE,
+                h: DOT_SIZE,
+            };
+            draw_rect(fb, &dot_rect, colors.text, false);
+        }
+
+        // The delay counter replaces the message until typing is possible again
+        let message_rect = line_rect(yc + (FIELD_H + GAP_H) as i64, font.char_h as u32);
+        let retry_in = ((self.retry_at - time) / 1000.0).ceil();
+        let message = match retry_in > 0.0 {
+            true => Some(format!("Try again in {} s", retry_in as u64)),
+            false => self.message.map(String::from),
+        };
+        if let Some(message) = message {
+            let color = match self.state {
+                LockState::Locked if self.failed_attempts > 0 => colors.red,
+                _ => colors.text,
+            };
+            draw_line_in_rect(
+                fb,
+                &message,
+                &message_rect,
+                font,
+                color,
+                TextJustification::Center,
+            );
+        }
+    }
+}
+
+// Salted, and slow enough to make guessing from a copy of the stored hash costly
+fn hash_passphrase(salt: &[u8], passphrase: &str) -> [u8; 32] {
+    let mut data = salt.to_vec();
+    data.extend_from_slice(passphrase.as_bytes());
+    let mut hash = sha256(&data);
+
+    for _ in 1..HASH_ROUNDS {
+        data.clear();
+        data.extend_from_slice(&hash);
+        data.extend_from_slice(salt);
+        hash = sha256(&data);
+    }
+
+    hash
+}
@@ -1,13 +1,50 @@
-use log::{Log, Metadata, Record};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use log::{LevelFilter, Log, Metadata, Record};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
 
 use crate::serial_println;
 
+// The oldest records are overwritten first
+const LOG_RING_SIZE: usize = 256 * 1024;
+
+// Every record also goes there, as a "LEVEL uptime_ms target -- message" line. Static,
+// because the kernel logs before the allocator is set up.
+static LOG_RING: Mutex<LogRing> = Mutex::new(LogRing::new());
+
+// Of the TSC in seconds, as f64 bits. Records have a 0 uptime until the clock is
+// calibrated.
+static CYCLE_PERIOD: AtomicU64 = AtomicU64::new(0);
+
+// Levels of the targets that have their own, sorted by target. A target also applies to
+// the modules under it, e.g. "virtio" to "kernel::virtio::gpu", and the longest match
+// wins.
+static TARGET_LEVELS: Mutex<Vec<(String, LevelFilter)>> = Mutex::new(Vec::new());
+// TARGET_LEVELS is only locked when it has entries, otherwise records are filtered with
+// the default level alone
+static HAS_TARGET_LEVELS: AtomicBool = AtomicBool::new(false);
+static DEFAULT_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Trace as usize);
+
 pub struct SerialLogger;
 
 impl Log for SerialLogger {
-    // TODO
-    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
-        true
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        let level = metadata.level() as usize;
+        if !HAS_TARGET_LEVELS.load(Ordering::Relaxed) {
+            return level <= DEFAULT_LEVEL.load(Ordering::Relaxed);
+        }
+
+        let target_level = without_interrupts(|| {
+            let target_levels = TARGET_LEVELS.lock();
+            find_target_level(&target_levels, metadata.target())
+        });
+        match target_level {
+            Some(target_level) => level <= target_level as usize,
+            None => level <= DEFAULT_LEVEL.load(Ordering::Relaxed),
+        }
     }
 
     fn log(&self, record: &Record<'_>) {
@@ -15,13 +52,177 @@ impl Log for SerialLogger {
             return;
         }
 
+        // The module path, unless the record names another target
         serial_println!(
             "{}: {} -- {}",
             record.level(),
-            record.module_path().unwrap(), // Not sure why this can fail?
+            record.target(),
             record.args(),
         );
+
+        // Formatted right into the ring, nothing is allocated
+        without_interrupts(|| {
+            let mut ring = LOG_RING.lock();
+            let _ = write!(
+                ring,
+                "{} {:.3} {} -- {}",
+                record.level(),
+                uptime_ms(),
+                record.target(),
+                record.args(),
+            );
+            ring.push(b"\n");
+        });
     }
 
     fn flush(&self) {}
 }
+
+fn find_target_level(target_levels: &[(String, LevelFilter)], target: &str) -> Option<LevelFilter> {
+    let target = target.strip_prefix("kernel::").unwrap_or(target);
+    target_levels
+        .iter()
+        .filter(|(prefix, _)| {
+            let rest = target.strip_prefix(prefix.as_str());
+            rest.is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        })
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, level)| *level)
+}
+
+pub fn set_default_level(level: LevelFilter) {
+    DEFAULT_LEVEL.store(level as usize, Ordering::Relaxed);
+    update_max_level();
+}
+
+// None gives the target the default level again
+pub fn set_target_level(target: &str, level: Option<LevelFilter>) {
+    without_interrupts(|| {
+        let mut target_levels = TARGET_LEVELS.lock();
+        let index = target_levels.binary_search_by(|(other, _)| other.as_str().cmp(target));
+        match (index, level) {
+            (Ok(i), Some(level)) => target_levels[i].1 = level,
+            (Err(i), Some(level)) => target_levels.insert(i, (target.to_string(), level)),
+            (Ok(i), None) => {
+                target_levels.remove(i);
+            }
+            (Err(_), None) => (),
+        }
+        HAS_TARGET_LEVELS.store(!target_levels.is_empty(), Ordering::Relaxed);
+    });
+    update_max_level();
+}
+
+// The default level, then the targets with their own
+pub fn levels() -> (LevelFilter, Vec<(String, LevelFilter)>) {
+    let target_levels = without_interrupts(|| TARGET_LEVELS.lock().clone());
+    (default_level(), target_levels)
+}
+
+fn default_level() -> LevelFilter {
+    let level = DEFAULT_LEVEL.load(Ordering::Relaxed);
+    LevelFilter::iter()
+        .find(|filter| *filter as usize == level)
+        .unwrap_or(LevelFilter::Trace)
+}
+
+// Records above it are dropped by the log macros before the logger sees them
+fn update_max_level() {
+    let target_levels = without_interrupts(|| TARGET_LEVELS.lock().clone());
+    let max_level = target_levels
+        .iter()
+        .map(|(_, level)| *level)
+        .fold(default_level(), Ord::max);
+    log::set_max_level(max_level);
+}
+
+pub fn set_cycle_period(period_s: f64) {
+    CYCLE_PERIOD.store(period_s.to_bits(), Ordering::Relaxed);
+}
+
+pub fn uptime_ms() -> f64 {
+    let period_s = f64::from_bits(CYCLE_PERIOD.load(Ordering::Relaxed));
+    let n = unsafe { core::arch::x86_64::_rdtsc() };
+    1000.0 * n as f64 * period_s
+}
+
+// Whole lines logged from `position` on, at most `max_len` bytes of them, and the
+// position to read from next. Positions count the bytes logged since boot, so lines
+// already overwritten are skipped.
+pub fn read_log(position: u64, max_len: usize) -> (Vec<u8>, u64) {
+    without_interrupts(|| {
+        let ring = LOG_RING.lock();
+
+        let oldest = ring.written.saturating_sub(LOG_RING_SIZE as u64);
+        let start = u64::clamp(position, oldest, ring.written);
+        let mut data: Vec<u8> = ring.bytes_from(start).take(max_len).copied().collect();
+
+        // The first line is cut if it was partly overwritten
+        let mut skipped = 0;
+        if position < oldest {
+            skipped = match data.iter().position(|b| *b == b'\n') {
+                Some(i) => i + 1,
+                None => data.len(),
+            };
+            data.drain(..skipped);
+        }
+
+        // A line longer than max_len is returned in pieces
+        if let Some(i) = data.iter().rposition(|b| *b == b'\n') {
+            data.truncate(i + 1);
+        }
+
+        let next = start + (skipped + data.len()) as u64;
+        (data, next)
+    })
+}
+
+struct LogRing {
+    buffer: [u8; LOG_RING_SIZE],
+    // Bytes written since boot, the ring holds the last LOG_RING_SIZE of them
+    written: u64,
+}
+
+impl LogRing {
+    const fn new() -> Self {
+        LogRing {
+            buffer: [0; LOG_RING_SIZE],
+            written: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        // Only the end of what does not fit is kept
+        let bytes = &bytes[bytes.len().saturating_sub(LOG_RING_SIZE)..];
+
+        let start = (self.written % LOG_RING_SIZE as u64) as usize;
+        let first = usize::min(bytes.len(), LOG_RING_SIZE - start);
+        self.buffer[start..start + first].copy_from_slice(&bytes[..first]);
+        self.buffer[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+
+        self.written += bytes.len() as u64;
+    }
+
+    // `position` must still be in the ring
+    fn bytes_from(&self, position: u64) -> impl Iterator<Item = &u8> {
+        let start = (position % LOG_RING_SIZE as u64) as usize;
+        let len = (self.written - position) as usize;
+        let first = usize::min(len, LOG_RING_SIZE - start);
+        self.buffer[start..start + first]
+            .iter()
+            .chain(self.buffer[..len - first].iter())
+    }
+}
+
+// Newlines in messages would split records into several lines
+impl Write for LogRing {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for (i, part) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.push(b" ");
+            }
+            self.push(part.as_bytes());
+        }
+        Ok(())
+    }
+}
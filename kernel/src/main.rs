@@ -3,60 +3,149 @@
 #![feature(alloc_error_handler)]
 #![feature(abi_x86_interrupt)]
 
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
+use core::f32::consts::FRAC_1_SQRT_2;
 use core::panic::PanicInfo;
 use num_traits::Float;
-use rand::rngs::SmallRng;
-use rand::SeedableRng;
 use uefi::prelude::{entry, Boot, Handle, Status, SystemTable};
 use uefi::table::boot::MemoryType;
+use uefi::table::runtime::ResetType;
 
-use applib::drawing::primitives::draw_rect;
-use applib::input::{InputEvent, InputState};
-use applib::uitk::{self};
-use applib::{BorrowedMutPixels, Color, FbViewMut, Framebuffer, OwnedPixels, Rect};
+use applib::content::ContentId;
+use applib::damage::DamageList;
+use applib::drawing::primitives::{draw_rect, draw_rect_outline, draw_triangle};
+use applib::geometry::{Point2D, Triangle2D};
+use applib::input::{InputEvent, InputState, MouseButton};
+use applib::uitk::{self, CursorHint};
+use applib::{BorrowedMutPixels, Color, FbView, FbViewMut, Framebuffer, OwnedPixels, Rect};
 
 extern crate alloc;
 
 mod allocator;
 mod app;
+mod autostart;
+mod block;
+mod crash_panel;
+mod fat32;
+mod http;
+mod interrupts;
+mod keyboard;
+mod lock_screen;
 mod logging;
 mod memory;
+mod mouse;
 mod network;
+mod overlay;
+mod overview;
+mod panic_screen;
 mod pci;
+mod random;
 mod resources;
 mod serial;
+mod serial_shell;
+mod sha256;
 mod shell;
+mod shortcuts;
+mod sntp;
 mod stats;
+mod storage;
 mod system;
+mod taskbar;
 mod time;
 mod topbar;
+mod tray;
 mod virtio;
+mod wallpaper;
 mod wasm;
 
 use time::SystemClock;
 
-use virtio::gpu::VirtioGPU;
-use virtio::input::VirtioInput;
+use virtio::block::VirtioBlock;
+use virtio::entropy::VirtioEntropy;
+use virtio::gpu::{PixelFormat, VirtioGPU, CURSOR_SIZE};
+use virtio::input::{AbsRanges, InputKind, VirtioInput, VirtioInputEvent};
 use virtio::network::VirtioNetwork;
 
-use app::{run_apps, App, AppState, AppsInteractionState, AppsManager};
+use app::{run_apps, App, AppState, AppsFrame, AppsInteractionState, AppsManager};
 use applib::input::keymap::{EventType, Keycode};
-use resources::{APPLICATIONS, STYLESHEET, WALLPAPER};
+use block::BlockDevice;
+use resources::{APPLICATIONS, THEMES};
+use serial_shell::ShellAction;
+use shortcuts::{ShortcutAction, ShortcutFilter};
 use system::System;
 use wasm::WasmEngine;
 
 pub const FPS_TARGET: f64 = 60.0;
 const LIMIT_FPS: bool = true;
+// Leaves the performance overlay out of screenshots
+const HIDE_OVERLAY_IN_SCREENSHOTS: bool = true;
+// At boot, it can then be switched from the taskbar
+const DARK_THEME: bool = false;
+// Outlines the regions damaged each frame
+const DEBUG_DAMAGE: bool = false;
+// Shows red, green and blue bars at boot, to check the scanout pixel format
+const DISPLAY_TEST_PATTERN: bool = false;
+// More regions are merged together, each one costs a round trip to the GPU
+const MAX_FLUSH_REGIONS: usize = 8;
+// Without any input for that long (in ms), the screen locks. Only once a passphrase was
+// chosen by locking it with the shortcut.
+const IDLE_LOCK_DELAY: Option<f64> = Some(10.0 * 60_000.0);
+// Otherwise, apps keep running behind the lock screen
+const PAUSE_APPS_WHEN_LOCKED: bool = false;
+// After a panic, reboots after that long (in s) instead of halting
+const PANIC_REBOOT_DELAY: Option<f64> = None;
+// Kernel heap allocations made while an app steps are counted under its name
+pub const TRACK_APP_ALLOCATIONS: bool = true;
+// Fills the heap at boot to check how it grows, then halts
+const HEAP_STRESS_TEST: bool = false;
+// Writes to the last sector of the block device at boot and reads it back. Its content is
+// restored after, but a crash in between would lose it.
+const BLOCK_WRITE_TEST: bool = false;
+// Drivers wait for device interrupts and the CPU halts between frames. Otherwise, devices
+// are polled every frame and the CPU spins, but interrupts are still counted, so that
+// both modes report their input-to-cursor latency.
+const USE_INTERRUPTS: bool = true;
+// A held key repeats after that long (in ms), at that rate (per second)
+const KEY_REPEAT_DELAY: f64 = 500.0;
+const KEY_REPEAT_RATE: f64 = 30.0;
+// The second press of a double click comes within that long (in ms) and that far (in
+// pixels) from the first one
+const DOUBLE_CLICK_TIME: f64 = 400.0;
+const DOUBLE_CLICK_DISTANCE: i64 = 4;
+// The stats and control API are served there on every network interface. QEMU forwards
+// port 8080 of the host to it, see run.sh.
+const HTTP_PORT: u16 = 80;
+// The wall clock is synced to it, a name or an IPv4 address, at boot then every interval
+// (in ms). Until then, it is the RTC.
+const NTP_SERVER: &str = "pool.ntp.org";
+const NTP_SYNC_INTERVAL: f64 = 4.0 * 3_600_000.0;
 
 static LOGGER: logging::SerialLogger = logging::SerialLogger;
 const LOGGING_LEVEL: log::LevelFilter = log::LevelFilter::Debug;
+// Targets logged at another level, e.g. ("virtio", Trace) or ("app::Terminal", Off). Apps
+// log under "app::<name>". Both can be changed at runtime.
+const TARGET_LOGGING_LEVELS: &[(&str, log::LevelFilter)] = &[];
 
 pub const TOPBAR_H: u32 = 40;
+pub const TASKBAR_H: u32 = 40;
+
+// How much of the screen a frame draws again
+#[derive(Debug, Clone, PartialEq)]
+enum Composite {
+    // Nothing changed, the last frame stays on screen
+    Skip,
+    // Windows outside of it are not drawn at all
+    Region(Rect),
+    Full,
+}
 
 #[entry]
 fn main(image: Handle, system_table: SystemTable<Boot>) -> Status {
-    log::set_max_level(LOGGING_LEVEL);
+    logging::set_default_level(LOGGING_LEVEL);
     log::set_logger(&LOGGER).unwrap();
 
     log::info!("Booting kernel");
@@ -68,51 +157,126 @@ fn main(image: Handle, system_table: SystemTable<Boot>) -> Status {
     memory::init_mapper();
     memory::init_allocator(&memory_map);
 
+    if HEAP_STRESS_TEST {
+        memory::heap_stress_test();
+    }
+
+    for (target, level) in TARGET_LOGGING_LEVELS {
+        logging::set_target_level(target, Some(*level));
+    }
+
+    // Before the devices, so that they can be given vectors
+    interrupts::init(USE_INTERRUPTS);
+
     let mut pci_devices = pci::enumerate();
 
     let mut virtio_gpu = VirtioGPU::new(&mut pci_devices);
-    let mut virtio_inputs = [
-        VirtioInput::new(&mut pci_devices),
-        VirtioInput::new(&mut pci_devices),
-    ];
-    let virtio_net = VirtioNetwork::new(&mut pci_devices);
+    let mut virtio_inputs: Vec<VirtioInput> =
+        core::iter::from_fn(|| VirtioInput::new(&mut pci_devices)).collect();
+    if !virtio_inputs
+        .iter()
+        .any(|inp| inp.kind == InputKind::Keyboard)
+    {
+        log::warn!("No virtio keyboard");
+    }
+    let virtio_nets: Vec<VirtioNetwork> =
+        core::iter::from_fn(|| VirtioNetwork::new(&mut pci_devices)).collect();
+    if virtio_nets.is_empty() {
+        log::warn!("No virtio-net device");
+    }
+    let virtio_entropy = VirtioEntropy::new(&mut pci_devices);
+    let mut virtio_block = VirtioBlock::new(&mut pci_devices);
 
     log::info!("All VirtIO devices created");
 
+    match virtio_block.as_mut() {
+        Some(virtio_block) => {
+            if let Err(err) = virtio_block.self_test(BLOCK_WRITE_TEST) {
+                log::error!("Block device self-test failed: {:?}", err);
+            }
+        }
+        None => log::warn!("No virtio-blk device"),
+    }
+
     let runtime_services = unsafe { system_table.runtime_services() };
     let clock = SystemClock::new(runtime_services);
+    logging::set_cycle_period(clock.cycle_period());
 
     log::info!("System clock initialized");
 
+    interrupts::start_timer(&clock);
+
     virtio_gpu.init_framebuffer();
     virtio_gpu.flush();
 
-    log::info!("Display initialized");
+    // Frames are drawn here and converted when the scanout is not in applib's byte order
+    let mut rgba_buffer = match virtio_gpu.format {
+        PixelFormat::Rgba => None,
+        _ => {
+            let (w, h) = virtio_gpu.get_dims();
+            Some(vec![Color::ZERO; w * h])
+        }
+    };
+
+    log::info!("Display initialized with format {:?}", virtio_gpu.format);
+
+    // Panics are shown on screen from now on
+    panic_screen::register(&mut virtio_gpu, runtime_services);
 
-    let tcp_stack = network::TcpStack::new(&clock, virtio_net);
+    // Otherwise, the cursor is drawn in the framebuffer
+    let hw_cursor = virtio_gpu.init_cursor();
+    match hw_cursor {
+        true => log::info!("Using the GPU cursor plane"),
+        false => log::warn!("No GPU cursor plane, falling back to the software cursor"),
+    }
+
+    if DISPLAY_TEST_PATTERN {
+        show_test_pattern(&mut virtio_gpu, &clock);
+    }
+
+    let tcp_stack = network::TcpStack::new(&clock, virtio_nets);
 
     //let socket_handle = tcp_stack.borrow_mut().connect(Ipv4Address([93, 184, 216, 34]), 80);
 
     log::info!("TCP stack initialized");
 
+    // Falls back to keeping files in memory without a usable disk
+    let block_device = virtio_block.map(|device| Box::new(device) as Box<dyn BlockDevice>);
+    let storage = storage::Storage::new(storage::STORAGE_QUOTA, block_device, clock.time());
+
+    let display_modes = virtio_gpu.display_modes();
+    log::info!("Display modes: {:?}", display_modes);
+
     let (w, h) = virtio_gpu.get_dims();
-    let (w, h) = (w as u32, h as u32);
+    let (mut w, mut h) = (w as u32, h as u32);
     let wasm_engine = WasmEngine::new();
 
     let mut input_state = InputState::new(w, h);
+    let mut keyboard = keyboard::Keyboard::new(KEY_REPEAT_DELAY, KEY_REPEAT_RATE);
+    let mut mouse = mouse::Mouse::new(DOUBLE_CLICK_TIME, DOUBLE_CLICK_DISTANCE);
 
     let app_names: Vec<&str> = APPLICATIONS.iter().map(|desc| desc.name).collect();
 
-    let alloc_stats = memory::ALLOCATOR.get_stats();
+    let alloc_stats = memory::stats().heap;
 
     let system_stats = stats::SystemStats::new(&alloc_stats, &app_names);
 
     let mut system = System {
         clock,
         tcp_stack,
-        rng: SmallRng::seed_from_u64(0),
-        stylesheet: &STYLESHEET,
+        rng: random::SystemRng::new(virtio_entropy),
+        theme: match DARK_THEME {
+            true => &THEMES[1],
+            false => &THEMES[0],
+        },
+        wallpaper: wallpaper::Wallpaper::new(),
+        shade_unfocused: false,
+        display_modes,
+        requested_mode: None,
         stats: system_stats,
+        storage,
+        clipboard: String::new(),
+        keybindings: shortcuts::default_keybindings(),
     };
 
     let apps: Vec<App> = APPLICATIONS
@@ -123,6 +287,15 @@ fn main(image: Handle, system_table: SystemTable<Boot>) -> Status {
             is_open: false,
             rect: app_desc.init_win_rect.clone(),
             time_used: 0.0,
+            pending_opens: Vec::new(),
+            notification: None,
+            drawn: None,
+            shadow: None,
+            minimized: false,
+            restore_rect: None,
+            opacity: 1.0,
+            workspace: 0,
+            content_id: ContentId(0),
         })
         .collect();
 
@@ -136,6 +309,23 @@ fn main(image: Handle, system_table: SystemTable<Boot>) -> Status {
     let mut uuid_provider = uitk::UuidProvider::new();
 
     let mut apps_interaction_state = AppsInteractionState::Idle;
+    let mut shortcut_filter = ShortcutFilter::new();
+    let mut perf_overlay = overlay::PerfOverlay::new();
+    let mut topbar = topbar::Topbar::new();
+    let mut lock_screen = lock_screen::LockScreen::new(system.clock.time());
+    let mut autostart = autostart::Autostart::new(&system.storage);
+    let mut serial_shell = serial_shell::SerialShell::new();
+    let mut http_server = http::HttpServer::new(HTTP_PORT, &system.tcp_stack);
+    let mut sntp_client = sntp::SntpClient::new(NTP_SERVER, NTP_SYNC_INTERVAL);
+    let mut was_locked = false;
+
+    // The screen was fully flushed at init
+    let mut last_damage = DamageList::new(&Rect { x0: 0, y0: 0, w, h }, MAX_FLUSH_REGIONS);
+    let mut last_t0 = system.clock.time();
+    // What the GPU cursor plane currently shows
+    let mut hw_cursor_hint: Option<CursorHint> = None;
+    let mut hw_cursor_pos = (0, 0);
+    let mut last_cursor_hint = CursorHint::Default;
 
     log::info!("Entering main loop");
 
@@ -144,73 +334,395 @@ fn main(image: Handle, system_table: SystemTable<Boot>) -> Status {
 
         {
             let System {
-                clock, tcp_stack, ..
+                clock,
+                tcp_stack,
+                rng,
+                ..
             } = &mut system;
             fps_manager.start_frame(clock);
-            tcp_stack.poll_interface(clock);
+            tcp_stack.poll_interfaces(clock);
+            // Right after, so that answers are timestamped as soon as they are received
+            sntp_client.poll(clock, tcp_stack);
+            rng.refill();
         }
 
+        let netpoll_used = system.clock.time() - t0;
+        let frame_interval = t0 - last_t0;
+        last_t0 = t0;
+
         let time = system.clock.time();
 
-        let datetime = SystemClock::utc_datetime(runtime_services);
+        let datetime = system.clock.utc_now();
 
-        update_input_state(&mut input_state, (w, h), &mut virtio_inputs);
+        // Picked in the quick settings, or the QEMU window was resized
+        let mut mode_switched = false;
+        let config_change = virtio_gpu.on_config_change();
+        if config_change.reset {
+            hw_cursor_hint = None;
+        }
+        let new_mode = match config_change.mode {
+            Some(mode) => {
+                system.display_modes = virtio_gpu.display_modes();
+                Some(mode)
+            }
+            None => system.requested_mode.take(),
+        };
+        if let Some(new_shape) = new_mode.filter(|&mode| mode != (w, h)) {
+            if virtio_gpu.set_mode(new_shape.0 as usize, new_shape.1 as usize) {
+                log::info!("Switched to {}x{}", new_shape.0, new_shape.1);
+                apps_manager.fit_to_screen((w, h), new_shape);
+                (w, h) = new_shape;
+                if let Some(buffer) = rgba_buffer.as_mut() {
+                    *buffer = vec![Color::ZERO; (w * h) as usize];
+                }
+                let pointer = &mut input_state.pointer;
+                pointer.x = i64::min(pointer.x, w as i64 - 1);
+                pointer.y = i64::min(pointer.y, h as i64 - 1);
+                // Everything is drawn again, the wallpaper is rendered for the new size
+                last_damage = DamageList::new(&Rect { x0: 0, y0: 0, w, h }, MAX_FLUSH_REGIONS);
+                mode_switched = true;
+            }
+        }
 
-        let mut framebuffer =
-            Framebuffer::<BorrowedMutPixels>::from_bytes(&mut virtio_gpu.framebuffer, w, h);
+        let input_activity = update_input_state(
+            &mut input_state,
+            (w, h),
+            &mut virtio_inputs,
+            &mut keyboard,
+            &mut mouse,
+            time,
+        );
+        if input_activity {
+            lock_screen.record_activity(time);
+        }
 
-        let wallpaper: &Framebuffer<OwnedPixels> = &WALLPAPER;
-        framebuffer.copy_from_fb(wallpaper, (0, 0), false);
+        // Right away, so that the cursor does not wait for the frame to be drawn
+        let pointer_pos = (input_state.pointer.x as u32, input_state.pointer.y as u32);
+        let cursor_moved = hw_cursor && pointer_pos != hw_cursor_pos;
+        if cursor_moved {
+            virtio_gpu.move_cursor(pointer_pos);
+            hw_cursor_pos = pointer_pos;
+        }
+        record_cursor_latency(&mut virtio_inputs, cursor_moved);
+        let shortcuts = shortcut_filter.intercept(&mut input_state, &system.keybindings);
+        if shortcuts.contains(&ShortcutAction::ToggleOverlay) {
+            perf_overlay.toggle();
+        }
+
+        let idle = IDLE_LOCK_DELAY.is_some_and(|delay| lock_screen.idle_time(time) > delay)
+            && lock_screen.has_passphrase(&system.storage);
+        if shortcuts.contains(&ShortcutAction::Lock) || idle {
+            lock_screen.lock(&system.storage);
+        }
+        let locked = lock_screen.is_locked();
+
+        if !locked {
+            if let Some(entry) = autostart.poll(input_state.shift, time) {
+                apps_manager.autostart(&entry, (w, h));
+            }
+        }
+
+        let mut shell_screenshot = false;
+        match serial_shell.poll(&mut system, &mut apps_manager) {
+            Some(ShellAction::Screenshot) => shell_screenshot = true,
+            Some(ShellAction::Reboot) => {
+                log::info!("Rebooting");
+                runtime_services.reset(ResetType::COLD, Status::SUCCESS, None);
+            }
+            None => (),
+        }
+
+        http_server.poll(&mut system, &mut apps_manager);
+        let http_screenshot = http_server.wants_screenshot();
+
+        let mut framebuffer = match rgba_buffer.as_mut() {
+            Some(buffer) => Framebuffer::<BorrowedMutPixels>::new(buffer, w, h),
+            // Same byte order as Color
+            None => Framebuffer::<BorrowedMutPixels>::from_bytes(&mut virtio_gpu.framebuffer, w, h),
+        };
+
+        let wallpaper_changed = system.wallpaper.update((w, h), system.theme);
+
+        // The wallpaper rarely changes, otherwise only what is drawn on top of it is
+        // tracked
+        let screen_rect = framebuffer.shape_as_rect();
+        framebuffer.set_damage(Some(DamageList::new(&screen_rect, MAX_FLUSH_REGIONS)));
+        // Locking or unlocking changes the whole screen too
+        let redraw_all = wallpaper_changed || mode_switched || locked != was_locked;
+        if redraw_all {
+            framebuffer.add_damage(&screen_rect);
+        }
+        was_locked = locked;
 
         let mut uitk_context = ui_store.get_context(
             &mut framebuffer,
-            &system.stylesheet,
+            system.theme.stylesheet,
             &input_state,
             &mut uuid_provider,
             time,
         );
 
-        run_apps(
-            &mut uitk_context,
-            &mut system,
-            &wasm_engine,
-            &mut apps_manager,
-            &input_state,
-            &mut apps_interaction_state,
-        );
+        let mut frame: Option<AppsFrame> = match locked {
+            false => Some(run_apps(
+                &mut uitk_context,
+                &mut system,
+                &wasm_engine,
+                &mut apps_manager,
+                &input_state,
+                &mut apps_interaction_state,
+                &shortcuts,
+                datetime,
+            )),
+            true => {
+                let System { storage, rng, .. } = &mut system;
+                lock_screen.update(&input_state, storage, rng, time);
+
+                // Apps only see an idle pointer in the middle of the screen, and any drag
+                // or menu is dropped
+                app::step_locked_apps(
+                    &mut system,
+                    uitk_context.uuid_provider,
+                    &mut apps_manager,
+                    &InputState::new(w, h),
+                    PAUSE_APPS_WHEN_LOCKED,
+                );
+                apps_interaction_state = AppsInteractionState::Idle;
+                None
+            }
+        };
+        let cursor_hint = frame
+            .as_ref()
+            .map_or(CursorHint::Default, |frame| frame.cursor_hint);
+
+        let topbar_changed = topbar.update(&system.stats, datetime, time);
+        let overlay_rect = perf_overlay.update(&system.stats, &uitk_context.stylesheet, time);
+
+        // Whatever the damage does not track gets the whole screen drawn again. Topbar
+        // tooltips show up a while after the pointer stops over them.
+        let tracked = frame.as_ref().is_some_and(|frame| !frame.changed)
+            && !input_activity
+            && !redraw_all
+            && !shell_screenshot
+            && !http_screenshot
+            && cursor_hint == last_cursor_hint
+            && input_state.pointer.y >= TOPBAR_H as i64;
+        last_cursor_hint = cursor_hint;
+
+        let mut dirty = frame
+            .as_mut()
+            .map(|frame| core::mem::take(&mut frame.damage))
+            .unwrap_or_default();
+        if topbar_changed {
+            dirty.push(Rect {
+                x0: 0,
+                y0: 0,
+                w,
+                h: TOPBAR_H,
+            });
+        }
+        dirty.extend(overlay_rect);
+
+        let composite = match tracked {
+            false => Composite::Full,
+            true => match dirty.iter().cloned().reduce(|a, b| a.bounding_box(&b)) {
+                None => Composite::Skip,
+                Some(region) => Composite::Region(region),
+            },
+        };
 
-        topbar::topbar(&mut uitk_context, &system.stats, datetime);
+        let mut windows = (0, 0);
+        if composite != Composite::Skip {
+            if let Composite::Region(region) = &composite {
+                uitk_context.fb.push_clip(region);
+            }
 
-        draw_cursor(uitk_context.fb, &input_state);
+            // Damaged below when it is covered by something which changed
+            let damage = uitk_context.fb.take_damage();
+            uitk_context
+                .fb
+                .copy_from_fb(system.wallpaper.framebuffer(), (0, 0), false);
+            uitk_context.fb.set_damage(damage);
+            for rect in dirty.iter() {
+                uitk_context.fb.add_damage(rect);
+            }
+
+            match frame {
+                Some(frame) => {
+                    let region = match &composite {
+                        Composite::Region(region) => Some(region),
+                        _ => None,
+                    };
+                    windows = app::draw_apps(
+                        &mut uitk_context,
+                        &system,
+                        &mut apps_manager,
+                        &input_state,
+                        &apps_interaction_state,
+                        frame,
+                        region,
+                    );
+                    topbar.draw(&mut uitk_context);
+                }
+                None => lock_screen.draw(uitk_context.fb, system.theme.stylesheet, datetime, time),
+            }
+
+            // Never with the cursor, it is drawn after this or on the GPU cursor plane
+            let screenshot = shortcuts.contains(&ShortcutAction::Screenshot) || shell_screenshot;
+            if screenshot && HIDE_OVERLAY_IN_SCREENSHOTS {
+                save_screenshot(uitk_context.fb, &mut system.storage, system.clock.time());
+            }
+            if http_screenshot && HIDE_OVERLAY_IN_SCREENSHOTS {
+                http_server.capture_screenshot(uitk_context.fb);
+            }
+
+            perf_overlay.draw(uitk_context.fb, &uitk_context.stylesheet);
+
+            if screenshot && !HIDE_OVERLAY_IN_SCREENSHOTS {
+                save_screenshot(uitk_context.fb, &mut system.storage, system.clock.time());
+            }
+            if http_screenshot && !HIDE_OVERLAY_IN_SCREENSHOTS {
+                http_server.capture_screenshot(uitk_context.fb);
+            }
+
+            if !hw_cursor {
+                let pointer = &input_state.pointer;
+                draw_cursor(uitk_context.fb, pointer.x, pointer.y, cursor_hint);
+            }
+
+            if let Composite::Region(_) = composite {
+                uitk_context.fb.pop_clip();
+            }
+        }
+
+        // What was drawn last frame may be gone now (cursor, menus, tooltips...), unless
+        // nothing was drawn at all
+        let damage = framebuffer.take_damage().expect("Damage not tracked");
+        let mut flushed = damage.clone();
+        if composite != Composite::Skip {
+            flushed.add_list(&last_damage);
+        }
+
+        if DEBUG_DAMAGE {
+            for rect in damage.rects() {
+                draw_rect_outline(&mut framebuffer, rect, Color::FUCHSIA, false, 1);
+            }
+        }
 
         let (net_recv, net_sent) = system.tcp_stack.pop_counters();
+        let (virtio_notified, virtio_suppressed) = virtio::pop_notify_counters();
 
         let t1 = system.clock.time();
 
-        let heap_stats = memory::ALLOCATOR.get_stats();
+        memory::log_heap_growth();
+        let heap_stats = memory::stats().heap;
 
         *system.stats.get_system_point_mut() = stats::SystemDataPoint {
             alloc: heap_stats,
             frametime_used: t1 - t0,
+            netpoll_used,
+            frame_interval,
             net_recv,
             net_sent,
+            damaged_pixels: damage.area() as usize,
+            flushed_pixels: flushed.area() as usize,
+            composited: composite != Composite::Skip,
+            windows_drawn: windows.0,
+            windows_skipped: windows.1,
+            virtio_notified,
+            virtio_suppressed,
         };
 
+        // The cursor plane only has to be uploaded to when the cursor changes shape
+        if hw_cursor && hw_cursor_hint != Some(cursor_hint) {
+            let (image, hot_spot) = render_cursor_image(cursor_hint);
+            virtio_gpu.update_cursor(image.get_data(), hot_spot, hw_cursor_pos);
+            hw_cursor_hint = Some(cursor_hint);
+        }
+
         system.stats.next_frame();
-        fps_manager.end_frame(&system.clock);
-        virtio_gpu.flush();
+
+        // While waiting for the next frame, the cursor follows the pointer as soon as the
+        // input devices raise their interrupt. Their events are still handed to the next
+        // frame.
+        let mut idle_pointer = (input_state.pointer.x, input_state.pointer.y);
+        let has_tablet = virtio_inputs
+            .iter()
+            .any(|inp| inp.kind == InputKind::Tablet);
+        fps_manager.end_frame(&system.clock, || {
+            for virtio_inp in virtio_inputs.iter_mut() {
+                let abs_ranges = virtio_inp.abs_ranges;
+                for event in virtio_inp.fetch() {
+                    let motion =
+                        pointer_motion(event, abs_ranges, has_tablet, idle_pointer, (w, h));
+                    if let Some(pos) = motion {
+                        idle_pointer = pos;
+                    }
+                }
+            }
+            let pointer_pos = (idle_pointer.0 as u32, idle_pointer.1 as u32);
+            let cursor_moved = hw_cursor && pointer_pos != hw_cursor_pos;
+            if cursor_moved {
+                virtio_gpu.move_cursor(pointer_pos);
+                hw_cursor_pos = pointer_pos;
+            }
+            record_cursor_latency(&mut virtio_inputs, cursor_moved);
+        });
+        if let Some(buffer) = rgba_buffer.as_ref() {
+            virtio_gpu.blit_regions(buffer, flushed.rects());
+        }
+        virtio_gpu.flush_regions(flushed.rects());
+        last_damage = damage;
     }
 
     //loop { x86_64::instructions::hlt(); }
 }
 
-fn draw_cursor<F: FbViewMut>(fb: &mut F, input_state: &InputState) {
+fn save_screenshot(fb: &Framebuffer<BorrowedMutPixels>, storage: &mut storage::Storage, time: f64) {
+    let name = format!("screenshot_{:.0}.qoi", time);
+    match storage.write(&name, &fb.to_qoi(), time) {
+        Ok(()) => log::info!("Saved {}", name),
+        Err(err) => log::error!("Cannot save {}: {:?}", name, err),
+    }
+}
+
+fn show_test_pattern(virtio_gpu: &mut VirtioGPU, clock: &SystemClock) {
+    let (w, h) = virtio_gpu.get_dims();
+    let (w, h) = (w as u32, h as u32);
+    let bar_w = w / 3;
+
+    let mut fb = Framebuffer::new_owned(w, h);
+    for (i, color) in [Color::RED, Color::GREEN, Color::BLUE].into_iter().enumerate() {
+        let x0 = (i as u32 * bar_w) as i64;
+        draw_rect(&mut fb, &Rect { x0, y0: 0, w: bar_w, h }, color, false);
+    }
+
+    virtio_gpu.blit_regions(fb.get_data(), &[fb.shape_as_rect()]);
+    virtio_gpu.flush();
+
+    log::info!("Display test pattern: expect red left, green middle, blue right");
+    clock.spin_delay(3000.0);
+}
+
+// For the GPU cursor plane, drawn like the software cursor. Returns the image and the
+// position of the pointer in it.
+fn render_cursor_image(hint: CursorHint) -> (Framebuffer<OwnedPixels>, (u32, u32)) {
+    let hot_spot = match hint {
+        CursorHint::Default => (0, 0),
+        _ => (CURSOR_SIZE / 2, CURSOR_SIZE / 2),
+    };
+    let mut image = Framebuffer::new_owned(CURSOR_SIZE, CURSOR_SIZE);
+    draw_cursor(&mut image, hot_spot.0 as i64, hot_spot.1 as i64, hint);
+    (image, hot_spot)
+}
+
+fn draw_cursor<F: FbViewMut>(fb: &mut F, x: i64, y: i64, hint: CursorHint) {
     const SIZE: u32 = 5;
     const BORDER: u32 = 1;
 
-    let pointer_state = &input_state.pointer;
-    let x = pointer_state.x;
-    let y = pointer_state.y;
+    match hint {
+        CursorHint::Default => (),
+        _ => return draw_resize_cursor(fb, x, y, hint),
+    }
 
     let rect_outer = Rect {
         x0: x,
@@ -229,59 +741,108 @@ fn draw_cursor<F: FbViewMut>(fb: &mut F, input_state: &InputState) {
     draw_rect(fb, &rect_inner, Color::WHITE, false);
 }
 
+// A double arrow centered on the pointer
+fn draw_resize_cursor<F: FbViewMut>(fb: &mut F, x: i64, y: i64, hint: CursorHint) {
+    const HALF_LEN: f32 = 8.0;
+    const HEAD: f32 = 4.0;
+    const D: f32 = FRAC_1_SQRT_2;
+
+    // Drawn along the x axis, then turned. The straight arrows stay aligned on pixels.
+    let (along, across) = match hint {
+        CursorHint::ResizeVertical => ((0.0, 1.0), (1.0, 0.0)),
+        CursorHint::ResizeDiagonal => ((D, D), (D, -D)),
+        CursorHint::ResizeAntiDiagonal => ((D, -D), (D, D)),
+        _ => ((1.0, 0.0), (0.0, 1.0)),
+    };
+    let point = |u: f32, v: f32| Point2D {
+        x: x + (u * along.0 + v * across.0).round() as i64,
+        y: y + (u * along.1 + v * across.1).round() as i64,
+    };
+
+    for (color, grow) in [(Color::BLACK, 1.0), (Color::WHITE, 0.0)] {
+        let (bar_len, bar_w) = (HALF_LEN - HEAD, 1.0 + grow);
+        let (x0, x1, y0, y1) = (-bar_len, bar_len, -bar_w, bar_w + 1.0);
+        let bar = [point(x0, y0), point(x1, y0), point(x1, y1), point(x0, y1)];
+        fill_triangle(fb, [bar[0], bar[1], bar[2]], color);
+        fill_triangle(fb, [bar[0], bar[2], bar[3]], color);
+
+        for side in [-1.0, 1.0] {
+            let head = [
+                point(side * (HALF_LEN + grow), 0.0),
+                point(side * (HALF_LEN - HEAD - grow), -HEAD - grow),
+                point(side * (HALF_LEN - HEAD - grow), HEAD + grow),
+            ];
+            fill_triangle(fb, head, color);
+        }
+    }
+}
+
+// draw_triangle() only fills triangles whose points go clockwise on screen
+fn fill_triangle<F: FbViewMut>(fb: &mut F, points: [Point2D<i64>; 3], color: Color) {
+    let [a, b, c] = points;
+    let cross = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+    let points = match cross < 0 {
+        true => [a, c, b],
+        false => [a, b, c],
+    };
+    draw_triangle(fb, &Triangle2D { points }, color, false);
+}
+
+// Returns whether any event was received, for the idle lock
 fn update_input_state(
     input_state: &mut InputState,
     dims: (u32, u32),
     virtio_inputs: &mut [VirtioInput],
-) {
+    keyboard: &mut keyboard::Keyboard,
+    mouse: &mut mouse::Mouse,
+    time: f64,
+) -> bool {
     let (w, h) = dims;
     let (w, h) = (w as i32, h as i32);
 
     input_state.clear_events();
     input_state.pointer.left_click_trigger = false;
     input_state.pointer.right_click_trigger = false;
+    input_state.pointer.middle_click_trigger = false;
     input_state.pointer.delta_x = 0;
     input_state.pointer.delta_y = 0;
+    input_state.pointer.scroll_x = 0;
+    input_state.pointer.scroll_y = 0;
 
+    // With a tablet, which follows the host cursor, mice only add their buttons and wheels
+    let has_tablet = virtio_inputs
+        .iter()
+        .any(|inp| inp.kind == InputKind::Tablet);
+
+    let mut activity = false;
     for virtio_inp in virtio_inputs.iter_mut() {
         for event in virtio_inp.poll() {
             //log::debug!("{:?}", event);
+            activity = true;
 
             match EventType::n(event._type) {
                 Some(EventType::EV_SYN) => {}
 
                 Some(EventType::EV_KEY) => match Keycode::n(event.code) {
                     // Mouse click
-                    Some(Keycode::BTN_MOUSE_LEFT) => match event.value {
-                        1 => {
-                            if !input_state.pointer.left_clicked {
-                                input_state.pointer.left_click_trigger = true;
-                            }
-                            input_state.pointer.left_clicked = true;
-                        }
-                        _ => input_state.pointer.left_clicked = false,
-                    },
-                    Some(Keycode::BTN_MOUSE_RIGHT) => match event.value {
-                        1 => {
-                            if !input_state.pointer.right_clicked {
-                                input_state.pointer.right_click_trigger = true;
-                            }
-                            input_state.pointer.right_clicked = true;
-                        }
-                        _ => input_state.pointer.right_clicked = false,
-                    },
+                    Some(Keycode::BTN_MOUSE_LEFT) => {
+                        mouse.button_event(input_state, MouseButton::Left, event.value, time)
+                    }
+                    Some(Keycode::BTN_MOUSE_RIGHT) => {
+                        mouse.button_event(input_state, MouseButton::Right, event.value, time)
+                    }
+                    Some(Keycode::BTN_MOUSE_MIDDLE) => {
+                        mouse.button_event(input_state, MouseButton::Middle, event.value, time)
+                    }
 
                     // Keyboard
-                    Some(keycode) => match event.value {
-                        0 => input_state.add_event(InputEvent::KeyRelease { keycode }),
-                        1 => input_state.add_event(InputEvent::KeyPress { keycode }),
-                        val => log::warn!("Unknown key state {}", val),
-                    },
+                    Some(keycode) => keyboard.key_event(input_state, keycode, event.value, time),
                     None => log::warn!("Unknown keycode {} for keyboard event", event.code),
                 },
 
                 // Mouse movement
                 Some(EventType::EV_REL) => match event.code {
+                    0 | 1 if has_tablet => {}
                     0 => {
                         // X axis
                         let dx = (event.value as i32) as i64;
@@ -300,18 +861,81 @@ fn update_input_state(
                         pointer_state.delta_y += dy;
                         pointer_state.y = new_y;
                     }
-                    8 => {
-                        // Scroll wheel
-                        let delta = (event.value as i32) as i64;
-                        input_state.add_event(InputEvent::Scroll { delta });
-                    }
-                    _ => log::warn!("Unknown event code {} for pointer event", event.code),
+                    // Scroll wheels
+                    _ => match virtio_inp.scroll_notches(&event) {
+                        Some((dx, dy)) => {
+                            input_state.pointer.scroll_x += dx;
+                            input_state.pointer.scroll_y += dy;
+                        }
+                        None => log::warn!("Unknown event code {} for pointer event", event.code),
+                    },
                 },
 
+                // Tablet movement
+                Some(EventType::EV_ABS) => {
+                    let pointer_state = &mut input_state.pointer;
+                    let (x, y) = (pointer_state.x, pointer_state.y);
+                    let new_pos = virtio_inp
+                        .abs_ranges
+                        .and_then(|ranges| ranges.position(&event, (x, y), dims));
+                    match new_pos {
+                        Some((new_x, new_y)) => {
+                            pointer_state.delta_x += new_x - x;
+                            pointer_state.delta_y += new_y - y;
+                            pointer_state.x = new_x;
+                            pointer_state.y = new_y;
+                        }
+                        None => log::warn!("Unknown event code {} for tablet event", event.code),
+                    }
+                }
+
                 _ => log::warn!("Unknown event type {}", event._type),
             };
         }
     }
+
+    // Wheel events of a frame are merged, a fast spin would fill up the events otherwise
+    let (delta_x, delta_y) = (input_state.pointer.scroll_x, input_state.pointer.scroll_y);
+    if delta_x != 0 || delta_y != 0 {
+        input_state.add_event(InputEvent::Scroll { delta_x, delta_y });
+    }
+
+    keyboard.repeat(input_state, time);
+
+    activity
+}
+
+// Where a motion event moves the pointer, None for other events. With a tablet, relative
+// motion is ignored.
+fn pointer_motion(
+    event: &VirtioInputEvent,
+    abs_ranges: Option<AbsRanges>,
+    has_tablet: bool,
+    pos: (i64, i64),
+    dims: (u32, u32),
+) -> Option<(i64, i64)> {
+    let (x, y) = pos;
+    let (w, h) = (dims.0 as i64, dims.1 as i64);
+    let delta = (event.value as i32) as i64;
+    match (EventType::n(event._type), event.code) {
+        (Some(EventType::EV_ABS), _) => abs_ranges?.position(event, pos, dims),
+        (Some(EventType::EV_REL), 0 | 1) if has_tablet => None,
+        (Some(EventType::EV_REL), 0) => Some((i64::clamp(x + delta, 0, w - 1), y)),
+        (Some(EventType::EV_REL), 1) => Some((x, i64::clamp(y + delta, 0, h - 1))),
+        _ => None,
+    }
+}
+
+// Only the GPU cursor plane is timed, the software cursor waits for the frame anyway.
+// Events that did not move the cursor are not counted.
+fn record_cursor_latency(virtio_inputs: &mut [VirtioInput], cursor_moved: bool) {
+    for virtio_inp in virtio_inputs.iter_mut() {
+        if let Some(raised_at) = virtio_inp.take_raised_at() {
+            if cursor_moved {
+                interrupts::record_cursor_latency(raised_at);
+            }
+        }
+    }
 }
 
 struct FpsManager {
@@ -335,7 +959,8 @@ impl FpsManager {
         self.frame_start_t = clock.time();
     }
 
-    fn end_frame(&mut self, clock: &SystemClock) {
+    // `on_wake` is called on device interrupts while waiting for the frame time target
+    fn end_frame<F: FnMut()>(&mut self, clock: &SystemClock, on_wake: F) {
         const SMOOTHING: f64 = 0.8;
 
         let frametime_target = 1000.0 / self.fps_target;
@@ -346,7 +971,7 @@ impl FpsManager {
 
         let new_frametime = match (self.used < frametime_target) && LIMIT_FPS {
             true => {
-                clock.spin_delay(frametime_target - self.used);
+                interrupts::idle_until(clock, self.frame_start_t + frametime_target, on_wake);
                 frametime_target
             }
             false => self.used,
@@ -358,6 +983,5 @@ impl FpsManager {
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    log::error!("{}", info);
-    loop {}
+    panic_screen::handle(info, PANIC_REBOOT_DELAY)
 }
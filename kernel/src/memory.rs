@@ -1,39 +1,263 @@
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::cell::OnceCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
 use uefi::table::boot::{MemoryMap, MemoryType};
-use x86_64::structures::paging::{mapper::TranslateResult, OffsetPageTable, PageTable, Translate};
+use x86_64::structures::paging::mapper::{MapToError, TranslateResult};
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags, PhysFrame,
+    Size4KiB, Translate,
+};
 use x86_64::{PhysAddr, VirtAddr};
 
-use super::allocator::SimpleAllocator;
+use super::allocator::{AllocStats, HeapRegion, SimpleAllocator, TagStats, MAX_HEAP_REGIONS};
 
 #[global_allocator]
 pub static ALLOCATOR: SimpleAllocator = SimpleAllocator::new();
 
+// Names of the allocation tags, ALLOC_TAGS[i] is tag i + 1
+static ALLOC_TAGS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+// Heap growth up to there was logged
+static LOGGED_HEAP_GROWS: AtomicUsize = AtomicUsize::new(0);
+
+// Regions smaller than that are not worth adding to the heap
+const MIN_HEAP_REGION_SIZE: usize = 1024 * 1024;
+
 pub static mut MAPPER: OnceCell<MemoryMapper> = OnceCell::new();
 
+// The heap starts small and grows into the conventional memory left by UEFI. Nothing
+// can be allocated until it is set up, so the regions are kept in an array.
 pub fn init_allocator(memory_map: &MemoryMap) {
     log::info!("Initializing heap allocator");
 
-    let desc = memory_map
-        .entries()
-        .filter(|desc| desc.ty == MemoryType::CONVENTIONAL)
-        .max_by_key(|desc| desc.page_count)
-        .expect("Cannot find suitable memory region for heap");
+    let mapper = unsafe {
+        (*core::ptr::addr_of_mut!(MAPPER))
+            .get_mut()
+            .expect("Memory mapper not initialized?")
+    };
+
+    // The largest ones, sorted by decreasing size
+    let mut regions = [HeapRegion::EMPTY; MAX_HEAP_REGIONS];
+    let mut nb_regions = 0;
+    let mut nb_skipped = 0;
+
+    for desc in memory_map.entries() {
+        let size = 4096 * desc.page_count as usize;
+        if desc.ty != MemoryType::CONVENTIONAL || size < MIN_HEAP_REGION_SIZE {
+            continue;
+        }
+
+        let region = HeapRegion {
+            start: mapper.phys_to_virt(PhysAddr::new(desc.phys_start)),
+            size,
+        };
+
+        if nb_regions < MAX_HEAP_REGIONS {
+            regions[nb_regions] = region;
+            nb_regions += 1;
+        } else if size > regions[MAX_HEAP_REGIONS - 1].size {
+            regions[MAX_HEAP_REGIONS - 1] = region;
+            nb_skipped += 1;
+        } else {
+            nb_skipped += 1;
+        }
+        regions[..nb_regions].sort_unstable_by(|a, b| b.size.cmp(&a.size));
+    }
+
+    // UEFI may not have mapped all of them. The page tables needed for the missing pages
+    // are taken from the start of the largest region which is fully mapped already.
+    let donor = regions[..nb_regions]
+        .iter()
+        .position(|region| mapper.is_offset_mapped(region));
+    let mut frames = RegionFrames {
+        next: donor.map(|i| mapper.virt_to_phys(regions[i].start)),
+        nb_used: 0,
+        // Half of it at most, that is already more page tables than any region needs
+        max: donor.map_or(0, |i| regions[i].size / 2 / Size4KiB::SIZE as usize),
+    };
+    for (i, region) in regions[..nb_regions].iter_mut().enumerate() {
+        if Some(i) == donor {
+            continue;
+        }
+        match mapper.map_offset(region, &mut frames) {
+            Ok(0) => (),
+            Ok(nb_pages) => log::debug!(
+                "Mapped {} pages of the memory region at {:#x}",
+                nb_pages,
+                region.start
+            ),
+            Err(err) => {
+                log::warn!(
+                    "Cannot map the memory region at {:#x} ({:?}), skipping it",
+                    region.start,
+                    err
+                );
+                region.size = 0;
+            }
+        }
+    }
+    if let Some(i) = donor {
+        let used = frames.nb_used * Size4KiB::SIZE as usize;
+        regions[i].start += used as u64;
+        regions[i].size -= used;
+    }
+
+    let nb_dropped = regions[..nb_regions]
+        .iter()
+        .filter(|region| region.size == 0)
+        .count();
+    regions[..nb_regions].sort_unstable_by(|a, b| b.size.cmp(&a.size));
+    nb_regions -= nb_dropped;
+    nb_skipped += nb_dropped;
+
+    let regions = &regions[..nb_regions];
+    assert!(
+        !regions.is_empty(),
+        "Cannot find suitable memory region for heap"
+    );
 
+    let usable: usize = regions.iter().map(|region| region.size).sum();
     log::debug!(
-        "Found suitable memory region for heap at {:#x} ({} pages, {}B)",
-        desc.phys_start,
-        desc.page_count,
-        desc.page_count * 4096,
+        "Found {} memory regions for the heap ({}MB), {} left out",
+        nb_regions,
+        usable / 1_000_000,
+        nb_skipped,
     );
 
-    let mapper = get_mapper();
+    ALLOCATOR.init(regions);
+
+    let stats = ALLOCATOR.get_stats();
+    LOGGED_HEAP_GROWS.store(stats.nb_grows, Ordering::Relaxed);
+    log::info!(
+        "Heap initialized with {}MB, can grow to {}MB",
+        stats.total / 1_000_000,
+        (stats.total + stats.reserve) / 1_000_000,
+    );
+}
+
+// The allocator cannot log when it grows, the logger may be the one allocating
+pub fn log_heap_growth() {
+    let nb_grows = ALLOCATOR.nb_grows();
+    let logged = LOGGED_HEAP_GROWS.swap(nb_grows, Ordering::Relaxed);
+    if nb_grows != logged {
+        let stats = ALLOCATOR.get_stats();
+        log::info!(
+            "Heap grew {} times to {}MB, {}MB left to grow into",
+            nb_grows - logged,
+            stats.total / 1_000_000,
+            stats.reserve / 1_000_000,
+        );
+    }
+}
 
-    let heap_addr_phys = PhysAddr::new(desc.phys_start);
-    let heap_add_virt = mapper.phys_to_virt(heap_addr_phys);
+// Allocates 1MB blocks until the heap cannot grow anymore, checks the accounting and
+// halts. The heap is left full of 1MB blocks, so the system cannot go on.
+pub fn heap_stress_test() -> ! {
+    const BLOCK_SIZE: usize = 1024 * 1024;
 
-    let heap_size = 4096 * desc.page_count as usize;
+    log::info!("Heap stress test: allocating until the heap is full");
 
-    ALLOCATOR.init(heap_add_virt, heap_size);
+    let before = ALLOCATOR.get_stats();
+    let capacity = before.total + before.reserve;
+    let mut blocks: Vec<Vec<u8>> = Vec::with_capacity(capacity / BLOCK_SIZE + 1);
+
+    loop {
+        let mut block: Vec<u8> = Vec::new();
+        if block.try_reserve_exact(BLOCK_SIZE).is_err() {
+            break;
+        }
+        // Written all over, to check that it is mapped
+        block.resize(BLOCK_SIZE, 0xaa);
+        blocks.push(block);
+        log_heap_growth();
+    }
+
+    let full = ALLOCATOR.get_stats();
+    let allocated = full.allocated - before.allocated;
+    let checks = [
+        (
+            "heap and reserve add up",
+            full.total + full.reserve == capacity,
+        ),
+        (
+            "blocks are accounted",
+            allocated >= blocks.len() * BLOCK_SIZE,
+        ),
+        ("heap is within its regions", full.explored <= full.total),
+    ];
+
+    log::info!(
+        "Heap stress test: {} blocks, heap {}MB out of {}MB, {}MB of reserve left in pieces too small",
+        blocks.len(),
+        full.total / 1_000_000,
+        capacity / 1_000_000,
+        full.reserve / 1_000_000,
+    );
+
+    drop(blocks);
+    let after = ALLOCATOR.get_stats();
+
+    for (name, ok) in checks
+        .into_iter()
+        .chain([("everything is freed", after.allocated == before.allocated)])
+    {
+        match ok {
+            true => log::info!("Heap stress test: {}: OK", name),
+            false => log::error!("Heap stress test: {}: FAILED", name),
+        }
+    }
+
+    log::info!("Heap stress test done, halting");
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+pub struct MemoryStats {
+    pub heap: AllocStats,
+    // Untagged allocations first, then the tags that were set at least once
+    pub by_tag: Vec<(&'static str, TagStats)>,
+}
+
+pub fn stats() -> MemoryStats {
+    let tag_stats = ALLOCATOR.get_tag_stats();
+    let tags = ALLOC_TAGS.lock();
+    let by_tag = core::iter::once("untagged")
+        .chain(tags.iter().copied())
+        .zip(tag_stats)
+        .collect();
+
+    MemoryStats {
+        heap: ALLOCATOR.get_stats(),
+        by_tag,
+    }
+}
+
+// Heap allocations are counted under that name until the tag is set again, e.g. to
+// attribute those made for an app. Names are kept until reboot.
+pub fn set_alloc_tag(name: Option<&str>) {
+    let tag = match name {
+        None => 0,
+        Some(name) => {
+            let mut tags = ALLOC_TAGS.lock();
+            match tags.iter().position(|other| *other == name) {
+                Some(i) => i + 1,
+                None => {
+                    tags.push(String::from(name).leak());
+                    tags.len()
+                }
+            }
+        }
+    };
+    ALLOCATOR.set_tag(tag);
+}
+
+pub fn tag_stats(name: &str) -> Option<TagStats> {
+    let tags = ALLOC_TAGS.lock();
+    let i = tags.iter().position(|other| *other == name)?;
+    ALLOCATOR.get_tag_stats().get(i + 1).copied()
 }
 
 #[derive(Debug)]
@@ -63,6 +287,79 @@ impl MemoryMapper {
         let virt = VirtAddr::new(p as *const T as *const usize as u64);
         self.virt_to_phys(virt)
     }
+
+    // Bytes from `virt` to the end of the page mapping it, 0 if it is not mapped where
+    // the offset mapping says
+    fn offset_mapped_len(&self, virt: VirtAddr) -> u64 {
+        let phys = virt - self.phys_offset;
+        match self.page_table.translate(virt) {
+            TranslateResult::Mapped { frame, offset, .. }
+                if (frame.start_address() + offset).as_u64() == phys =>
+            {
+                frame.size() - offset
+            }
+            _ => 0,
+        }
+    }
+
+    // Every page is checked, huge pages are skipped over in one go
+    fn is_offset_mapped(&self, region: &HeapRegion) -> bool {
+        let end = region.start + region.size as u64;
+        let mut virt = region.start;
+        while virt < end {
+            match self.offset_mapped_len(virt) {
+                0 => return false,
+                len => virt += len,
+            }
+        }
+        true
+    }
+
+    // Maps the pages of the region which are not offset-mapped yet, returns how many
+    fn map_offset(
+        &mut self,
+        region: &HeapRegion,
+        frames: &mut impl FrameAllocator<Size4KiB>,
+    ) -> Result<usize, MapToError<Size4KiB>> {
+        let end = region.start + region.size as u64;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let mut virt = region.start;
+        let mut nb_mapped = 0;
+        while virt < end {
+            match self.offset_mapped_len(virt) {
+                0 => {
+                    let page = Page::<Size4KiB>::containing_address(virt);
+                    let frame =
+                        PhysFrame::containing_address(PhysAddr::new(virt - self.phys_offset));
+                    unsafe { self.page_table.map_to(page, frame, flags, frames)? }.flush();
+                    nb_mapped += 1;
+                    virt += Size4KiB::SIZE;
+                }
+                len => virt += len,
+            }
+        }
+        Ok(nb_mapped)
+    }
+}
+
+// Hands out the frames at the start of an offset-mapped region, for new page tables.
+// The region shrinks by the frames used afterwards.
+struct RegionFrames {
+    next: Option<PhysAddr>,
+    nb_used: usize,
+    max: usize,
+}
+
+unsafe impl FrameAllocator<Size4KiB> for RegionFrames {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        let next = self.next?;
+        if self.nb_used == self.max {
+            return None;
+        }
+        let frame = PhysFrame::containing_address(next + (self.nb_used as u64) * Size4KiB::SIZE);
+        self.nb_used += 1;
+        Some(frame)
+    }
 }
 
 pub fn init_mapper() {
@@ -0,0 +1,88 @@
+use applib::input::{InputEvent, InputState, MouseButton};
+
+// Turns what the virtio mouse reports for its buttons into the pointer state and button
+// events apps get. Double clicks are told apart here, so that every app agrees on them.
+pub struct Mouse {
+    // In ms and pixels, between two presses of a double click
+    double_click_time: f64,
+    double_click_distance: i64,
+    last_press: Option<Press>,
+}
+
+#[derive(Clone, Copy)]
+struct Press {
+    button: MouseButton,
+    time: f64,
+    x: i64,
+    y: i64,
+    // In the series it is part of
+    clicks: u32,
+}
+
+impl Mouse {
+    pub fn new(double_click_time: f64, double_click_distance: i64) -> Self {
+        Mouse {
+            double_click_time,
+            double_click_distance,
+            last_press: None,
+        }
+    }
+
+    // `value` is 0 for a release and 1 for a press
+    pub fn button_event(
+        &mut self,
+        input_state: &mut InputState,
+        button: MouseButton,
+        value: u32,
+        time: f64,
+    ) {
+        let pointer = &mut input_state.pointer;
+        let (x, y) = (pointer.x, pointer.y);
+        let (clicked, trigger) = match button {
+            MouseButton::Left => (&mut pointer.left_clicked, &mut pointer.left_click_trigger),
+            MouseButton::Right => (&mut pointer.right_clicked, &mut pointer.right_click_trigger),
+            MouseButton::Middle => (
+                &mut pointer.middle_clicked,
+                &mut pointer.middle_click_trigger,
+            ),
+        };
+
+        let pressed = value == 1;
+        // Presses reported twice, and releases of a button already down at boot, are dropped
+        if pressed == *clicked {
+            return;
+        }
+        *clicked = pressed;
+
+        if !pressed {
+            input_state.add_event(InputEvent::ButtonRelease { button, x, y });
+            return;
+        }
+
+        *trigger = true;
+        let clicks = match self.last_press {
+            Some(last)
+                if last.button == button
+                    && time - last.time <= self.double_click_time
+                    && i64::max((x - last.x).abs(), (y - last.y).abs())
+                        <= self.double_click_distance =>
+            {
+                last.clicks + 1
+            }
+            _ => 1,
+        };
+        self.last_press = Some(Press {
+            button,
+            time,
+            x,
+            y,
+            clicks,
+        });
+        input_state.add_event(InputEvent::ButtonPress {
+            button,
+            x,
+            y,
+            clicks,
+        });
+    }
+}
@@ -0,0 +1,211 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::time::SystemClock;
+
+pub const DEFAULT_SNAPLEN: usize = 256;
+// Of the ring, the oldest records are dropped to make room for new ones
+const RING_SIZE: usize = 1024 * 1024;
+const RECORD_HEADER_SIZE: usize = 16;
+// Classic pcap with microsecond timestamps, in little-endian
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const LINKTYPE_ETHERNET: u32 = 1;
+// Of the file header, records can be shortened more than that
+const PCAP_SNAPLEN: u32 = 65535;
+
+// Copies of the frames sent and received on all interfaces, as pcap records, while it is
+// started. The ring is allocated at the first start, so recording a frame only copies it.
+pub struct PacketCapture {
+    enabled: bool,
+    // Frames are cut to that many bytes
+    snaplen: usize,
+    // Whole records, each one a header and the frame
+    ring: VecDeque<u8>,
+    // Incremented at each start, readers of an older capture are done
+    session: u32,
+    // Records of this capture, dropped ones included
+    nb_records: u64,
+    // Those dropped from the ring to make room
+    nb_dropped: u64,
+    // Position of the first record in the ring, in records and in bytes since the start
+    start_seq: u64,
+    start_pos: u64,
+}
+
+// Where a reader is in a capture, see PacketCapture::read()
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureCursor {
+    session: u32,
+    seq: u64,
+    pos: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureStatus {
+    pub enabled: bool,
+    pub snaplen: usize,
+    pub nb_records: u64,
+    pub nb_dropped: u64,
+    // In the ring now
+    pub buffered: usize,
+}
+
+impl PacketCapture {
+    pub fn new() -> Self {
+        PacketCapture {
+            enabled: false,
+            snaplen: DEFAULT_SNAPLEN,
+            ring: VecDeque::new(),
+            session: 0,
+            nb_records: 0,
+            nb_dropped: 0,
+            start_seq: 0,
+            start_pos: 0,
+        }
+    }
+
+    // Records from an earlier capture are dropped
+    pub fn start(&mut self) {
+        if self.ring.capacity() < RING_SIZE {
+            self.ring = VecDeque::with_capacity(RING_SIZE);
+        }
+        self.ring.clear();
+        self.enabled = true;
+        self.session += 1;
+        self.nb_records = 0;
+        self.nb_dropped = 0;
+        self.start_seq = 0;
+        self.start_pos = 0;
+        log::info!("Packet capture started, snaplen {}", self.snaplen);
+    }
+
+    // The records stay readable until the next start
+    pub fn stop(&mut self) {
+        self.enabled = false;
+        log::info!(
+            "Packet capture stopped, {} records, {} dropped",
+            self.nb_records,
+            self.nb_dropped
+        );
+    }
+
+    pub fn set_snaplen(&mut self, snaplen: usize) {
+        self.snaplen = snaplen;
+    }
+
+    pub fn status(&self) -> CaptureStatus {
+        CaptureStatus {
+            enabled: self.enabled,
+            snaplen: self.snaplen,
+            nb_records: self.nb_records,
+            nb_dropped: self.nb_dropped,
+            buffered: self.ring.len(),
+        }
+    }
+
+    // Called for every frame, sent or received
+    pub fn record(&mut self, clock: &SystemClock, frame: &[u8]) {
+        if !self.enabled {
+            return;
+        }
+
+        let incl_len = usize::min(frame.len(), self.snaplen);
+        let record_len = RECORD_HEADER_SIZE + incl_len;
+        while !self.ring.is_empty() && self.ring.len() + record_len > RING_SIZE {
+            self.drop_oldest();
+        }
+
+        let time = clock.unix_time();
+        let secs = time as u32;
+        let micros = ((time - secs as f64) * 1e6) as u32;
+
+        self.ring.extend(secs.to_le_bytes());
+        self.ring.extend(u32::min(micros, 999_999).to_le_bytes());
+        self.ring.extend((incl_len as u32).to_le_bytes());
+        self.ring.extend((frame.len() as u32).to_le_bytes());
+        self.ring.extend(&frame[..incl_len]);
+        self.nb_records += 1;
+    }
+
+    fn drop_oldest(&mut self) {
+        let record_len = self.record_len_at(0);
+        self.ring.drain(..record_len);
+        self.start_seq += 1;
+        self.start_pos += record_len as u64;
+        self.nb_dropped += 1;
+    }
+
+    // Of the record starting at that offset in the ring
+    fn record_len_at(&self, offset: usize) -> usize {
+        let mut incl_len = [0u8; 4];
+        for (b, v) in incl_len
+            .iter_mut()
+            .zip(self.ring.range(offset + 8..offset + 12))
+        {
+            *b = *v;
+        }
+        RECORD_HEADER_SIZE + u32::from_le_bytes(incl_len) as usize
+    }
+
+    // Goes before the records
+    pub fn file_header() -> [u8; 24] {
+        let mut header = [0u8; 24];
+        header[0..4].copy_from_slice(&PCAP_MAGIC.to_le_bytes());
+        // Version 2.4, then the time zone and accuracy, both 0
+        header[4..6].copy_from_slice(&2u16.to_le_bytes());
+        header[6..8].copy_from_slice(&4u16.to_le_bytes());
+        header[16..20].copy_from_slice(&PCAP_SNAPLEN.to_le_bytes());
+        header[20..24].copy_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        header
+    }
+
+    // At the oldest record still in the ring
+    pub fn cursor(&self) -> CaptureCursor {
+        CaptureCursor {
+            session: self.session,
+            seq: self.start_seq,
+            pos: self.start_pos,
+        }
+    }
+
+    // Appends whole records from the cursor on to `out`, at most `max_len` bytes of them
+    // unless the first one is larger, and moves the cursor past them. Records dropped
+    // before they were read are skipped, and their number returned. None once the
+    // capture is stopped and all of it was read, or another one was started.
+    pub fn read(
+        &self,
+        cursor: &mut CaptureCursor,
+        out: &mut Vec<u8>,
+        max_len: usize,
+    ) -> Option<u64> {
+        if cursor.session != self.session {
+            return None;
+        }
+
+        let mut missed = 0;
+        if cursor.seq < self.start_seq {
+            missed = self.start_seq - cursor.seq;
+            cursor.seq = self.start_seq;
+            cursor.pos = self.start_pos;
+        }
+
+        let end = self.start_pos + self.ring.len() as u64;
+        if !self.enabled && cursor.pos == end {
+            return None;
+        }
+
+        let start_len = out.len();
+        while cursor.pos < end {
+            let offset = (cursor.pos - self.start_pos) as usize;
+            let record_len = self.record_len_at(offset);
+            if out.len() > start_len && out.len() - start_len + record_len > max_len {
+                break;
+            }
+            out.extend(self.ring.range(offset..offset + record_len));
+            cursor.seq += 1;
+            cursor.pos += record_len as u64;
+        }
+
+        Some(missed)
+    }
+}
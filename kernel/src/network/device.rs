@@ -8,21 +8,26 @@ use tinyvec::ArrayVec;
 use smoltcp::phy::{self, Device, DeviceCapabilities, Medium};
 use smoltcp::time::Instant;
 
+use super::capture::PacketCapture;
+use crate::time::SystemClock;
 use crate::virtio::network::{VirtioNetwork, MAX_PACKET_SIZE};
 
-pub struct SmolTcpVirtio {
-    pub virtio_dev: VirtioNetwork,
+// Made for each poll of an interface. Frames are tapped on their way to the capture.
+pub struct SmolTcpVirtio<'a> {
+    pub virtio_dev: &'a mut VirtioNetwork,
+    pub capture: &'a mut PacketCapture,
+    pub clock: &'a SystemClock,
 }
 
-impl SmolTcpVirtio {
-    pub fn new(virtio_dev: VirtioNetwork) -> SmolTcpVirtio {
-        SmolTcpVirtio { virtio_dev }
-    }
-}
-
-impl Device for SmolTcpVirtio {
-    type RxToken<'a> = RxToken;
-    type TxToken<'a> = TxToken<'a>;
+impl<'d> Device for SmolTcpVirtio<'d> {
+    type RxToken<'a>
+        = RxToken
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = TxToken<'a>
+    where
+        Self: 'a;
 
     fn capabilities(&self) -> DeviceCapabilities {
         let mut caps = DeviceCapabilities::default();
@@ -33,18 +38,23 @@ impl Device for SmolTcpVirtio {
     }
 
     fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        self.virtio_dev.try_recv().map(move |buffer| {
-            let rx = RxToken { buffer };
-            let tx = TxToken {
-                virtio_dev: &mut self.virtio_dev,
-            };
-            (rx, tx)
-        })
+        let (buffer, len) = self.virtio_dev.try_recv()?;
+        self.capture.record(self.clock, &buffer[..len]);
+
+        let rx = RxToken { buffer, len };
+        let tx = TxToken {
+            virtio_dev: self.virtio_dev,
+            capture: self.capture,
+            clock: self.clock,
+        };
+        Some((rx, tx))
     }
 
     fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
         Some(TxToken {
-            virtio_dev: &mut self.virtio_dev,
+            virtio_dev: self.virtio_dev,
+            capture: self.capture,
+            clock: self.clock,
         })
     }
 }
@@ -52,6 +62,7 @@ impl Device for SmolTcpVirtio {
 #[doc(hidden)]
 pub struct RxToken {
     buffer: [u8; MAX_PACKET_SIZE],
+    len: usize,
 }
 
 impl phy::RxToken for RxToken {
@@ -59,13 +70,15 @@ impl phy::RxToken for RxToken {
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        f(&mut self.buffer)
+        f(&mut self.buffer[..self.len])
     }
 }
 
 #[doc(hidden)]
 pub struct TxToken<'a> {
     virtio_dev: &'a mut VirtioNetwork,
+    capture: &'a mut PacketCapture,
+    clock: &'a SystemClock,
 }
 
 impl<'a> phy::TxToken for TxToken<'a> {
@@ -78,6 +91,7 @@ impl<'a> phy::TxToken for TxToken<'a> {
             buffer.push(0x00);
         }
         let result = f(&mut buffer);
+        self.capture.record(self.clock, &buffer);
         self.virtio_dev.send(buffer);
         result
     }
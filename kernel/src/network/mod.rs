@@ -1,108 +1,275 @@
+pub mod capture;
 mod device;
 
+use alloc::format;
+use alloc::string::String;
 use alloc::vec;
+use alloc::vec::Vec;
+use applib::stats::NetworkInterfaceEntry;
 
 use crate::time::SystemClock;
 use crate::virtio::network::VirtioNetwork;
 
+use capture::PacketCapture;
 use device::SmolTcpVirtio;
-use lazy_static::lazy_static;
 use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
 use smoltcp::phy::{Device, Medium};
-use smoltcp::socket::tcp;
+use smoltcp::socket::dns::{self, GetQueryResultError};
+use smoltcp::socket::{dhcpv4, tcp, udp, AnySocket};
 use smoltcp::time::Instant;
-use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, Ipv4Address};
+use smoltcp::wire::{
+    DnsQueryType, EthernetAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address, Ipv4Cidr,
+};
 
-lazy_static! {
-    static ref IFACE_ADDR: IpCidr = IpCidr::new(IpAddress::v4(10, 0, 2, 15), 24);
-    static ref GATEWAY_ADDR: Ipv4Address = Ipv4Address([10, 0, 2, 2]);
+const BUF_SIZE: usize = 4096;
+// Of the sockets accepting connections, which send more (e.g. whole files) than they get
+const SERVER_TX_BUF_SIZE: usize = 64 * 1024;
+// Datagrams queued each way on a UDP socket
+const UDP_PACKETS: usize = 4;
+
+// Index of the interface, in the order the devices are on the PCI bus
+pub type InterfaceId = usize;
+
+// How an interface gets its address
+#[derive(Debug, Clone, Copy)]
+pub enum IpConfig {
+    Static {
+        cidr: Ipv4Cidr,
+        gateway: Option<Ipv4Address>,
+        dns_server: Option<Ipv4Address>,
+    },
+    Dhcp,
 }
 
-const BUF_SIZE: usize = 4096;
+#[derive(Debug, Clone, Copy)]
+pub struct InterfaceConfig {
+    pub ip: IpConfig,
+    // Traffic for none of the subnets goes through the interface with a gateway and the
+    // lowest value
+    pub route_priority: u8,
+}
 
-pub struct TcpStack {
-    device: SmolTcpVirtio,
-    interface: Interface,
-    sockets: SocketSet<'static>,
-    next_port: u16,
+impl InterfaceConfig {
+    // The first interface is QEMU's user network, the way out to the internet. The others
+    // (e.g. a host-only network) ask for an address and only carry their own subnets,
+    // unless the first one is down.
+    pub fn for_interface(id: InterfaceId) -> Self {
+        match id {
+            0 => InterfaceConfig {
+                ip: IpConfig::Static {
+                    cidr: Ipv4Cidr::new(Ipv4Address([10, 0, 2, 15]), 24),
+                    gateway: Some(Ipv4Address([10, 0, 2, 2])),
+                    dns_server: Some(Ipv4Address([10, 0, 2, 3])),
+                },
+                route_priority: 0,
+            },
+            _ => InterfaceConfig {
+                ip: IpConfig::Dhcp,
+                route_priority: u8::try_from(id).unwrap_or(u8::MAX),
+            },
+        }
+    }
 }
 
-impl TcpStack {
-    pub fn new<'a>(clock: &SystemClock, virtio_dev: VirtioNetwork) -> Self {
-        let mut device = SmolTcpVirtio::new(virtio_dev);
-        let mac_addr = device.virtio_dev.mac_addr;
+// Sockets are tied to the interface they were opened on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpHandle {
+    iface: InterfaceId,
+    socket: SocketHandle,
+}
 
-        let config = match device.capabilities().medium {
-            Medium::Ethernet => Config::new(EthernetAddress(mac_addr).into()),
-        };
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdpHandle {
+    iface: InterfaceId,
+    socket: SocketHandle,
+}
 
-        let timestamp = clock.time();
+// A name lookup, each one has its own socket
+#[derive(Clone, Copy)]
+pub struct DnsHandle {
+    iface: InterfaceId,
+    socket: SocketHandle,
+    query: dns::QueryHandle,
+}
 
-        let mut interface =
-            Interface::new(config, &mut device, Instant::from_millis(timestamp as i64));
-        interface.update_ip_addrs(|ip_addrs| {
-            ip_addrs.push(*IFACE_ADDR).unwrap();
-        });
+pub struct TcpStack {
+    interfaces: Vec<NetInterface>,
+    // Of the frames of all interfaces
+    capture: PacketCapture,
+    next_port: u16,
+}
 
-        interface
-            .routes_mut()
-            .add_default_ipv4_route(*GATEWAY_ADDR)
-            .unwrap();
+struct NetInterface {
+    config: InterfaceConfig,
+    virtio_dev: VirtioNetwork,
+    interface: Interface,
+    sockets: SocketSet<'static>,
+    // Only with IpConfig::Dhcp
+    dhcp_handle: Option<SocketHandle>,
+    // None until DHCP gets an address
+    cidr: Option<Ipv4Cidr>,
+    gateway: Option<Ipv4Address>,
+    dns_servers: Vec<Ipv4Address>,
+}
 
-        let sockets_storage: [_; 1] = Default::default();
-        let sockets = SocketSet::new(sockets_storage);
+impl TcpStack {
+    pub fn new(clock: &SystemClock, virtio_devs: Vec<VirtioNetwork>) -> Self {
+        let mut capture = PacketCapture::new();
+        let interfaces = virtio_devs
+            .into_iter()
+            .enumerate()
+            .map(|(id, virtio_dev)| {
+                let config = InterfaceConfig::for_interface(id);
+                log::info!("Network interface {}: {:?}", id, config);
+                NetInterface::new(clock, virtio_dev, config, &mut capture)
+            })
+            .collect();
 
         TcpStack {
-            device,
-            interface,
-            sockets,
+            interfaces,
+            capture,
             next_port: 65000,
         }
     }
 
-    pub fn connect(&mut self, addr: Ipv4Address, port: u16) -> anyhow::Result<SocketHandle> {
+    // Through `iface` if given, or else the one route() picks
+    pub fn connect(
+        &mut self,
+        addr: Ipv4Address,
+        port: u16,
+        iface: Option<InterfaceId>,
+    ) -> anyhow::Result<TcpHandle> {
+        let id = match iface {
+            Some(id) => id,
+            None => self
+                .route(addr)
+                .ok_or_else(|| anyhow::format_err!("No route to {}", addr))?,
+        };
+        let net_iface = self
+            .interfaces
+            .get_mut(id)
+            .ok_or_else(|| anyhow::format_err!("No network interface {}", id))?;
+
         let mut socket = {
             let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0u8; BUF_SIZE]);
             let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0u8; BUF_SIZE]);
             tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer)
         };
 
-        let cx = self.interface.context();
+        let cx = net_iface.interface.context();
 
         socket
             .connect(cx, (addr, port), self.next_port)
             .map_err(anyhow::Error::msg)?;
         self.next_port += 1;
 
-        let socket_handle = self.sockets.add(socket);
+        let handle = TcpHandle {
+            iface: id,
+            socket: net_iface.sockets.add(socket),
+        };
+
+        log::debug!("Connected to port {} ({:?})", port, handle);
+
+        Ok(handle)
+    }
+
+    // Accepts one connection to `port` through `iface`. The socket leaves the Listen state
+    // when a peer connects, another one has to be opened for the next connection.
+    pub fn listen(&mut self, iface: InterfaceId, port: u16) -> anyhow::Result<TcpHandle> {
+        let net_iface = self
+            .interfaces
+            .get_mut(iface)
+            .ok_or_else(|| anyhow::format_err!("No network interface {}", iface))?;
+
+        let mut socket = {
+            let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0u8; BUF_SIZE]);
+            let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0u8; SERVER_TX_BUF_SIZE]);
+            tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer)
+        };
+        socket.listen(port).map_err(anyhow::Error::msg)?;
+
+        let handle = TcpHandle {
+            iface,
+            socket: net_iface.sockets.add(socket),
+        };
+
+        log::debug!("Listening on port {} ({:?})", port, handle);
+
+        Ok(handle)
+    }
+
+    pub fn nb_interfaces(&self) -> usize {
+        self.interfaces.len()
+    }
+
+    // The interface with `addr` in its subnet, or else the default route. Those with their
+    // link down are left out.
+    pub fn route(&self, addr: Ipv4Address) -> Option<InterfaceId> {
+        self.usable_interfaces()
+            .find(|(_, net_iface)| net_iface.cidr.is_some_and(|cidr| cidr.contains_addr(&addr)))
+            .map(|(id, _)| id)
+            .or_else(|| self.default_route())
+    }
+
+    // The interface with a gateway and the lowest route priority
+    pub fn default_route(&self) -> Option<InterfaceId> {
+        self.usable_interfaces()
+            .filter(|(_, net_iface)| net_iface.gateway.is_some())
+            .min_by_key(|(_, net_iface)| net_iface.config.route_priority)
+            .map(|(id, _)| id)
+    }
 
-        log::debug!("Connected to port {} ({:?})", port, socket_handle);
+    fn usable_interfaces(&self) -> impl Iterator<Item = (InterfaceId, &NetInterface)> {
+        self.interfaces
+            .iter()
+            .enumerate()
+            .filter(|(_, net_iface)| net_iface.virtio_dev.link_up())
+    }
+
+    fn socket(&self, handle: TcpHandle) -> &tcp::Socket<'static> {
+        self.interfaces[handle.iface]
+            .sockets
+            .get::<tcp::Socket>(handle.socket)
+    }
+
+    fn socket_mut(&mut self, handle: TcpHandle) -> &mut tcp::Socket<'static> {
+        self.interfaces[handle.iface]
+            .sockets
+            .get_mut::<tcp::Socket>(handle.socket)
+    }
 
-        Ok(socket_handle)
+    pub fn get_socket_state(&self, handle: TcpHandle) -> tcp::State {
+        self.socket(handle).state()
     }
 
-    pub fn get_socket_state(&self, handle: SocketHandle) -> tcp::State {
-        self.sockets.get::<tcp::Socket>(handle).state()
+    pub fn may_send(&self, handle: TcpHandle) -> bool {
+        self.socket(handle).may_send()
     }
 
-    pub fn may_send(&self, handle: SocketHandle) -> bool {
-        self.sockets.get::<tcp::Socket>(handle).may_send()
+    pub fn may_recv(&self, handle: TcpHandle) -> bool {
+        self.socket(handle).may_recv()
     }
 
-    pub fn may_recv(&self, handle: SocketHandle) -> bool {
-        self.sockets.get::<tcp::Socket>(handle).may_recv()
+    // Some data was received and not read yet
+    pub fn can_recv(&self, handle: TcpHandle) -> bool {
+        self.socket(handle).can_recv()
     }
 
-    pub fn write(&mut self, handle: SocketHandle, buf: &[u8]) -> anyhow::Result<usize> {
-        let socket = self.sockets.get_mut::<tcp::Socket>(handle);
+    // Some room is left to write to
+    pub fn can_send(&self, handle: TcpHandle) -> bool {
+        self.socket(handle).can_send()
+    }
+
+    pub fn write(&mut self, handle: TcpHandle, buf: &[u8]) -> anyhow::Result<usize> {
+        let socket = self.socket_mut(handle);
         log::debug!("Writing {}B to socket {:?}", buf.len(), handle);
         let sent_len = socket.send_slice(buf).map_err(anyhow::Error::msg)?;
         log::debug!("{}B sent", sent_len);
         Ok(sent_len)
     }
 
-    pub fn read(&mut self, handle: SocketHandle, buf: &mut [u8]) -> anyhow::Result<usize> {
-        let socket = self.sockets.get_mut::<tcp::Socket>(handle);
+    pub fn read(&mut self, handle: TcpHandle, buf: &mut [u8]) -> anyhow::Result<usize> {
+        let socket = self.socket_mut(handle);
 
         let recv_len = socket
             .recv(|recv_buffer| {
@@ -120,21 +287,345 @@ impl TcpStack {
         Ok(recv_len)
     }
 
-    pub fn close(&mut self, handle: SocketHandle) {
+    // Sends a FIN once all the data written is, the socket is kept until close()
+    pub fn shutdown(&mut self, handle: TcpHandle) {
+        log::debug!("Shutting down socket {:?}", handle);
+        self.socket_mut(handle).close();
+    }
+
+    pub fn close(&mut self, handle: TcpHandle) {
         log::debug!("Closing socket {:?}", handle);
-        let socket = self.sockets.get_mut::<tcp::Socket>(handle);
-        socket.close();
-        self.sockets.remove(handle);
+        self.socket_mut(handle).close();
+        self.interfaces[handle.iface].sockets.remove(handle.socket);
     }
 
-    pub fn poll_interface(&mut self, clock: &SystemClock) {
+    // Bound to the next free port, on the interface route() picks for `addr`
+    pub fn udp_open(&mut self, addr: Ipv4Address) -> anyhow::Result<UdpHandle> {
+        let id = self
+            .route(addr)
+            .ok_or_else(|| anyhow::format_err!("No route to {}", addr))?;
+        let net_iface = &mut self.interfaces[id];
+
+        let mut socket = {
+            let udp_rx_buffer = udp::PacketBuffer::new(
+                vec![udp::PacketMetadata::EMPTY; UDP_PACKETS],
+                vec![0u8; BUF_SIZE],
+            );
+            let udp_tx_buffer = udp::PacketBuffer::new(
+                vec![udp::PacketMetadata::EMPTY; UDP_PACKETS],
+                vec![0u8; BUF_SIZE],
+            );
+            udp::Socket::new(udp_rx_buffer, udp_tx_buffer)
+        };
+        socket.bind(self.next_port).map_err(anyhow::Error::msg)?;
+        self.next_port += 1;
+
+        let handle = UdpHandle {
+            iface: id,
+            socket: net_iface.sockets.add(socket),
+        };
+
+        log::debug!("Opened UDP socket {:?}", handle);
+
+        Ok(handle)
+    }
+
+    // Queued, sent on the next poll
+    pub fn udp_send(
+        &mut self,
+        handle: UdpHandle,
+        addr: Ipv4Address,
+        port: u16,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        self.interfaces[handle.iface]
+            .sockets
+            .get_mut::<udp::Socket>(handle.socket)
+            .send_slice(data, IpEndpoint::new(addr.into(), port))
+            .map_err(anyhow::Error::msg)
+    }
+
+    // The next datagram received, cut to the size of `buf`
+    pub fn udp_recv(&mut self, handle: UdpHandle, buf: &mut [u8]) -> Option<usize> {
+        let socket = self.interfaces[handle.iface]
+            .sockets
+            .get_mut::<udp::Socket>(handle.socket);
+        let (len, _) = socket.recv_slice(buf).ok()?;
+        Some(len)
+    }
+
+    pub fn udp_close(&mut self, handle: UdpHandle) {
+        log::debug!("Closing UDP socket {:?}", handle);
+        self.interfaces[handle.iface].sockets.remove(handle.socket);
+    }
+
+    // Looks up the IPv4 address of `name`, through the DNS servers of the default route
+    pub fn dns_query(&mut self, name: &str) -> anyhow::Result<DnsHandle> {
+        let id = self
+            .default_route()
+            .ok_or_else(|| anyhow::format_err!("No default route"))?;
+        let net_iface = &mut self.interfaces[id];
+        if net_iface.dns_servers.is_empty() {
+            anyhow::bail!("No DNS server on network interface {}", id);
+        }
+
+        let servers: Vec<IpAddress> = net_iface
+            .dns_servers
+            .iter()
+            .map(|&server| server.into())
+            .collect();
+        let mut socket = dns::Socket::new(&servers, [None]);
+        let query = socket
+            .start_query(net_iface.interface.context(), name, DnsQueryType::A)
+            .map_err(anyhow::Error::msg)?;
+
+        let handle = DnsHandle {
+            iface: id,
+            socket: net_iface.sockets.add(socket),
+            query,
+        };
+
+        log::debug!("Looking up {} on network interface {}", name, id);
+
+        Ok(handle)
+    }
+
+    // None while waiting for the servers. The query is over once something is returned.
+    pub fn dns_result(&mut self, handle: DnsHandle) -> Option<anyhow::Result<Ipv4Address>> {
+        let sockets = &mut self.interfaces[handle.iface].sockets;
+        let result = match sockets
+            .get_mut::<dns::Socket>(handle.socket)
+            .get_query_result(handle.query)
+        {
+            Err(GetQueryResultError::Pending) => return None,
+            Err(err) => Err(anyhow::Error::msg(err)),
+            Ok(addrs) => addrs
+                .first()
+                .map(|addr| Ipv4Address::from_bytes(addr.as_bytes()))
+                .ok_or_else(|| anyhow::format_err!("No address found")),
+        };
+        sockets.remove(handle.socket);
+        Some(result)
+    }
+
+    pub fn dns_cancel(&mut self, handle: DnsHandle) {
+        self.interfaces[handle.iface].sockets.remove(handle.socket);
+    }
+
+    pub fn poll_interfaces(&mut self, clock: &SystemClock) {
         let timestamp = clock.time();
         let elapsed = Instant::from_millis(timestamp as i64);
-        self.interface
-            .poll(elapsed, &mut self.device, &mut self.sockets);
+
+        for (id, net_iface) in self.interfaces.iter_mut().enumerate() {
+            net_iface.virtio_dev.on_config_change();
+            let mut device = SmolTcpVirtio {
+                virtio_dev: &mut net_iface.virtio_dev,
+                capture: &mut self.capture,
+                clock,
+            };
+            net_iface
+                .interface
+                .poll(elapsed, &mut device, &mut net_iface.sockets);
+            net_iface.poll_dhcp(id);
+        }
+    }
+
+    // Whether any interface is up
+    pub fn link_up(&self) -> bool {
+        self.interfaces
+            .iter()
+            .any(|net_iface| net_iface.virtio_dev.link_up())
+    }
+
+    // Indexed by interface id
+    pub fn interface_entries(&self) -> Vec<NetworkInterfaceEntry> {
+        self.interfaces
+            .iter()
+            .map(|net_iface| {
+                let dhcp = matches!(net_iface.config.ip, IpConfig::Dhcp);
+                let link_up = net_iface.virtio_dev.link_up();
+                let mut entry = NetworkInterfaceEntry::new(link_up, dhcp);
+                entry.mac = net_iface.virtio_dev.mac_addr;
+                entry.prefix_len = net_iface.cidr.map_or(0, |cidr| cidr.prefix_len());
+                entry.addr = net_iface.cidr.map_or([0; 4], |cidr| cidr.address().0);
+                entry.gateway = net_iface.gateway.map_or([0; 4], |gateway| gateway.0);
+                entry.route_priority = net_iface.config.route_priority.into();
+                entry
+            })
+            .collect()
+    }
+
+    // For each interface, one line for the interface then one per socket
+    pub fn dump(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for (id, net_iface) in self.interfaces.iter().enumerate() {
+            let link = match net_iface.virtio_dev.link_up() {
+                true => "up",
+                false => "down",
+            };
+            let addr = match net_iface.cidr {
+                Some(cidr) => format!("{}", cidr),
+                None => String::from("no address"),
+            };
+            let gateway = match net_iface.gateway {
+                Some(gateway) => format!("{}", gateway),
+                None => String::from("none"),
+            };
+            lines.push(format!(
+                "if{} {} {} gateway {} priority {} link {}",
+                id,
+                EthernetAddress(net_iface.virtio_dev.mac_addr),
+                addr,
+                gateway,
+                net_iface.config.route_priority,
+                link
+            ));
+
+            for (handle, socket) in net_iface.sockets.iter() {
+                let Some(socket) = tcp::Socket::downcast(socket) else {
+                    continue;
+                };
+                lines.push(format!(
+                    "  {:?} {} {:?} -> {:?} recv queue {}B send queue {}B",
+                    handle,
+                    socket.state(),
+                    socket.local_endpoint(),
+                    socket.remote_endpoint(),
+                    socket.recv_queue(),
+                    socket.send_queue()
+                ));
+            }
+        }
+
+        lines
+    }
+
+    pub fn capture(&self) -> &PacketCapture {
+        &self.capture
     }
 
+    pub fn capture_mut(&mut self) -> &mut PacketCapture {
+        &mut self.capture
+    }
+
+    // Summed over the interfaces
     pub fn pop_counters(&mut self) -> (usize, usize) {
-        self.device.virtio_dev.get_counters()
+        self.interfaces
+            .iter_mut()
+            .map(|net_iface| net_iface.virtio_dev.get_counters())
+            .fold((0, 0), |(recv, sent), (r, s)| (recv + r, sent + s))
+    }
+}
+
+impl NetInterface {
+    fn new(
+        clock: &SystemClock,
+        mut virtio_dev: VirtioNetwork,
+        config: InterfaceConfig,
+        capture: &mut PacketCapture,
+    ) -> Self {
+        let mut device = SmolTcpVirtio {
+            virtio_dev: &mut virtio_dev,
+            capture,
+            clock,
+        };
+        let mac_addr = device.virtio_dev.mac_addr;
+
+        let iface_config = match device.capabilities().medium {
+            Medium::Ethernet => Config::new(EthernetAddress(mac_addr).into()),
+        };
+
+        let timestamp = clock.time();
+
+        let interface = Interface::new(
+            iface_config,
+            &mut device,
+            Instant::from_millis(timestamp as i64),
+        );
+
+        let sockets_storage: [_; 1] = Default::default();
+        let mut sockets = SocketSet::new(sockets_storage);
+
+        let dhcp_handle = match config.ip {
+            IpConfig::Static { .. } => None,
+            IpConfig::Dhcp => Some(sockets.add(dhcpv4::Socket::new())),
+        };
+
+        let mut net_iface = NetInterface {
+            config,
+            virtio_dev,
+            interface,
+            sockets,
+            dhcp_handle,
+            cidr: None,
+            gateway: None,
+            dns_servers: Vec::new(),
+        };
+
+        if let IpConfig::Static {
+            cidr,
+            gateway,
+            dns_server,
+        } = config.ip
+        {
+            net_iface.set_ipv4(Some(cidr), gateway);
+            net_iface.dns_servers.extend(dns_server);
+        }
+
+        net_iface
+    }
+
+    // None to leave the interface without an address
+    fn set_ipv4(&mut self, cidr: Option<Ipv4Cidr>, gateway: Option<Ipv4Address>) {
+        self.interface.update_ip_addrs(|ip_addrs| {
+            ip_addrs.clear();
+            if let Some(cidr) = cidr {
+                ip_addrs.push(IpCidr::Ipv4(cidr)).unwrap();
+            }
+        });
+
+        let routes = self.interface.routes_mut();
+        match gateway {
+            Some(gateway) => {
+                routes.add_default_ipv4_route(gateway).unwrap();
+            }
+            None => {
+                routes.remove_default_ipv4_route();
+            }
+        }
+
+        self.cidr = cidr;
+        self.gateway = gateway;
+    }
+
+    // Applies what the DHCP socket got during the last poll
+    fn poll_dhcp(&mut self, id: InterfaceId) {
+        let Some(dhcp_handle) = self.dhcp_handle else {
+            return;
+        };
+
+        let event = self.sockets.get_mut::<dhcpv4::Socket>(dhcp_handle).poll();
+        match event {
+            None => (),
+            Some(dhcpv4::Event::Configured(dhcp_config)) => {
+                // Copied out, the config borrows the socket set
+                let (address, router) = (dhcp_config.address, dhcp_config.router);
+                let dns_servers = dhcp_config.dns_servers.to_vec();
+                log::info!(
+                    "Network interface {} got {} from DHCP, gateway {:?}",
+                    id,
+                    address,
+                    router
+                );
+                self.set_ipv4(Some(address), router);
+                self.dns_servers = dns_servers;
+            }
+            Some(dhcpv4::Event::Deconfigured) => {
+                log::info!("Network interface {} lost its DHCP lease", id);
+                self.set_ipv4(None, None);
+                self.dns_servers.clear();
+            }
+        }
     }
 }
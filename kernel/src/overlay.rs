@@ -0,0 +1,254 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use applib::drawing::graph::{draw_series, GraphStyle};
+use applib::drawing::primitives::{draw_rect, draw_rect_outline};
+use applib::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use applib::{Color, FbViewMut, Rect, StyleSheet};
+
+use crate::stats::SystemStats;
+use crate::{FPS_TARGET, TOPBAR_H};
+
+const OVERLAY_W: u32 = 280;
+const GRAPH_H: u32 = 40;
+const NB_GRAPH_SAMPLES: usize = 120;
+const PADDING: u32 = 8;
+// For the average FPS, in ms
+const AVERAGE_WINDOW: f64 = 1000.0;
+// So that the overlay does not damage every frame, in ms
+const REFRESH_DELAY: f64 = 250.0;
+
+// Frame rate and where the frame time goes, drawn over everything else
+pub struct PerfOverlay {
+    pub enabled: bool,
+    // Shown until the next refresh
+    summary: Option<PerfSummary>,
+    refreshed_at: f64,
+}
+
+impl PerfOverlay {
+    pub fn new() -> Self {
+        PerfOverlay {
+            enabled: false,
+            summary: None,
+            refreshed_at: 0.0,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.summary = None;
+    }
+
+    // Where the overlay changed, if it was refreshed
+    pub fn update(
+        &mut self,
+        stats: &SystemStats,
+        stylesheet: &StyleSheet,
+        time: f64,
+    ) -> Option<Rect> {
+        let due = self.summary.is_none() || time - self.refreshed_at >= REFRESH_DELAY;
+        if !self.enabled || !due {
+            return None;
+        }
+
+        let font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.small);
+        let line_h = font.char_h as u32;
+        let old_rect = self
+            .summary
+            .as_ref()
+            .map(|summary| overlay_rect(summary.lines().len(), line_h));
+
+        let summary = PerfSummary::new(stats);
+        let rect = overlay_rect(summary.lines().len(), line_h);
+        self.summary = Some(summary);
+        self.refreshed_at = time;
+
+        match old_rect {
+            Some(old_rect) => Some(old_rect.bounding_box(&rect)),
+            None => Some(rect),
+        }
+    }
+
+    pub fn draw<F: FbViewMut>(&self, fb: &mut F, stylesheet: &StyleSheet) {
+        let Some(summary) = self.summary.as_ref().filter(|_| self.enabled) else {
+            return;
+        };
+
+        let font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.small);
+
+        let budget = 1000.0 / FPS_TARGET;
+        let lines = summary.lines();
+
+        let line_h = font.char_h as u32;
+        let rect = overlay_rect(lines.len(), line_h);
+        draw_rect(fb, &rect, stylesheet.colors.background, false);
+        draw_rect_outline(fb, &rect, Color::BLACK, false, stylesheet.margin);
+
+        for (i, line) in lines.iter().enumerate() {
+            let line_rect = Rect {
+                x0: rect.x0 + PADDING as i64,
+                y0: rect.y0 + (PADDING + i as u32 * line_h) as i64,
+                w: rect.w - 2 * PADDING,
+                h: line_h,
+            };
+            draw_line_in_rect(
+                fb,
+                line,
+                &line_rect,
+                font,
+                stylesheet.colors.text,
+                TextJustification::Left,
+            );
+        }
+
+        // The budget is at half height, frames over twice the budget are clipped
+        let graph_rect = Rect {
+            x0: rect.x0 + PADDING as i64,
+            y0: rect.y0 + rect.h as i64 - (PADDING + GRAPH_H) as i64,
+            w: rect.w - 2 * PADDING,
+            h: GRAPH_H,
+        };
+        let (r, g, b, _) = stylesheet.colors.accent.as_rgba();
+        let style = GraphStyle {
+            line_color: stylesheet.colors.accent,
+            fill_color: Some(Color::rgba(r, g, b, 80)),
+            bg_color: stylesheet.colors.editable,
+            y_min: Some(0.0),
+            y_max: Some(2.0 * budget as f32),
+            ..Default::default()
+        };
+        draw_series(fb, &graph_rect, &summary.frametimes, &style);
+
+        let budget_rect = Rect {
+            y0: graph_rect.y0 + (GRAPH_H / 2) as i64,
+            h: 1,
+            ..graph_rect
+        };
+        draw_rect(fb, &budget_rect, stylesheet.colors.red, false);
+    }
+}
+
+fn overlay_rect(nb_lines: usize, line_h: u32) -> Rect {
+    Rect {
+        x0: PADDING as i64,
+        y0: (TOPBAR_H + PADDING) as i64,
+        w: OVERLAY_W,
+        h: nb_lines as u32 * line_h + GRAPH_H + 3 * PADDING,
+    }
+}
+
+// Worked out from the stats of the last frames, the current one is still in progress
+struct PerfSummary {
+    fps: f64,
+    avg_fps: f64,
+    frametime: f64,
+    compositing: f64,
+    netpoll: f64,
+    apps: Vec<(&'static str, f64)>,
+    // Oldest first
+    frametimes: [f32; NB_GRAPH_SAMPLES],
+    damaged_pixels: usize,
+    flushed_pixels: usize,
+    // Fractions over the last second
+    skipped_frames: f64,
+    skipped_windows: f64,
+    virtio_notified: usize,
+    virtio_suppressed: usize,
+}
+
+impl PerfSummary {
+    fn new(stats: &SystemStats) -> Self {
+        let last = stats.get_last_system_point();
+
+        let apps: Vec<(&'static str, f64)> = stats
+            .get_last_app_points()
+            .filter(|(_, app_point)| app_point.running)
+            .map(|(app_name, app_point)| (app_name, app_point.frametime_used))
+            .collect();
+        let apps_total: f64 = apps.iter().map(|(_, frametime)| frametime).sum();
+
+        // Frames that started during the last second
+        let intervals = stats.get_system_history(|dp| dp.frame_interval);
+        let mut elapsed = 0.0;
+        let mut nb_frames = 0;
+        for interval in intervals.iter().skip(1) {
+            if elapsed >= AVERAGE_WINDOW || *interval <= 0.0 {
+                break;
+            }
+            elapsed += interval;
+            nb_frames += 1;
+        }
+
+        let frametime_history = stats.get_system_history(|dp| dp.frametime_used as f32);
+
+        // Out of the same frames as the average FPS
+        let composited = stats.get_system_history(|dp| dp.composited);
+        let nb_skipped = composited
+            .iter()
+            .skip(1)
+            .take(nb_frames)
+            .filter(|composited| !**composited)
+            .count();
+        let windows = stats.get_system_history(|dp| (dp.windows_drawn, dp.windows_skipped));
+        let (windows_drawn, windows_skipped) = windows
+            .iter()
+            .skip(1)
+            .take(nb_frames)
+            .fold((0, 0), |(drawn, skipped), (d, s)| (drawn + d, skipped + s));
+        let fraction = |n: usize, total: usize| match total > 0 {
+            true => n as f64 / total as f64,
+            false => 0.0,
+        };
+
+        PerfSummary {
+            fps: match last.frame_interval > 0.0 {
+                true => 1000.0 / last.frame_interval,
+                false => 0.0,
+            },
+            avg_fps: match elapsed > 0.0 {
+                true => nb_frames as f64 * 1000.0 / elapsed,
+                false => 0.0,
+            },
+            frametime: last.frametime_used,
+            compositing: f64::max(0.0, last.frametime_used - last.netpoll_used - apps_total),
+            netpoll: last.netpoll_used,
+            apps,
+            frametimes: core::array::from_fn(|i| frametime_history[NB_GRAPH_SAMPLES - i]),
+            damaged_pixels: last.damaged_pixels,
+            flushed_pixels: last.flushed_pixels,
+            skipped_frames: fraction(nb_skipped, nb_frames),
+            skipped_windows: fraction(windows_skipped, windows_drawn + windows_skipped),
+            virtio_notified: last.virtio_notified,
+            virtio_suppressed: last.virtio_suppressed,
+        }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let budget = 1000.0 / FPS_TARGET;
+
+        let mut lines: Vec<String> = Vec::from([
+            format!("{:.0} FPS ({:.1} avg)", self.fps, self.avg_fps),
+            format!("Frame {:.2}/{:.2}ms", self.frametime, budget),
+            format!("  Compositing {:.2}ms", self.compositing),
+            format!("  Network poll {:.2}ms", self.netpoll),
+        ]);
+        for (app_name, frametime) in self.apps.iter() {
+            lines.push(format!("  {} {:.2}ms", app_name, frametime));
+        }
+        lines.push(format!(
+            "Damaged {}px, flushed {}px",
+            self.damaged_pixels, self.flushed_pixels
+        ));
+        lines.push(format!(
+            "Skipped {:.0}% of frames, {:.0}% of windows",
+            100.0 * self.skipped_frames,
+            100.0 * self.skipped_windows
+        ));
+        lines.push(format!(
+            "Virtio notified {}, suppressed {}",
+            self.virtio_notified, self.virtio_suppressed
+        ));
+        lines
+    }
+}
@@ -0,0 +1,360 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use applib::content::ContentId;
+use applib::drawing::primitives::{draw_rect, draw_rect_outline};
+use applib::drawing::text::{draw_line_in_rect, ellipsize_text, Font, TextJustification};
+use applib::geometry::Point2D;
+use applib::{Color, FbView, FbViewMut, Framebuffer, OwnedPixels, Rect, ScaleFilter, StyleSheet};
+
+use crate::{TASKBAR_H, TOPBAR_H};
+
+// Of the transition in and out of the overview, in ms
+pub const ANIMATION_DURATION: f64 = 200.0;
+const GRID_GAP: u32 = 24;
+const TITLE_H: u32 = 28;
+// Above the taskbar, windows dropped there are closed
+const CLOSE_ZONE_H: u32 = 72;
+// From the left and right sides of the screen, windows dropped there go to the workspace
+// on that side
+const EDGE_ZONE_W: u32 = 24;
+// Below that, releasing a thumbnail is a click
+const DRAG_THRESHOLD: i64 = 6;
+const HIGHLIGHT_THICKNESS: u32 = 3;
+// Of the black layer over the wallpaper, once the overview is fully shown
+const DIM_ALPHA: f32 = 120.0;
+
+// A thumbnail held with the pointer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverviewDrag {
+    pub app_name: &'static str,
+    pub start: Point2D<i64>,
+    // Went past DRAG_THRESHOLD, it is not a click anymore
+    pub moved: bool,
+}
+
+impl OverviewDrag {
+    pub fn update(&mut self, x: i64, y: i64) {
+        let (dx, dy) = (x - self.start.x, y - self.start.y);
+        self.moved |= dx.abs() > DRAG_THRESHOLD || dy.abs() > DRAG_THRESHOLD;
+    }
+}
+
+// Where a dragged thumbnail is dropped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DropTarget {
+    Close,
+    // To the previous (-1) or next (1) workspace
+    Workspace(isize),
+}
+
+pub fn drop_target(x: i64, y: i64, fb_shape: (u32, u32)) -> Option<DropTarget> {
+    let (w, _) = fb_shape;
+    if close_zone_rect(fb_shape).check_contains_point(x, y) {
+        Some(DropTarget::Close)
+    } else if x < EDGE_ZONE_W as i64 {
+        Some(DropTarget::Workspace(-1))
+    } else if x >= w.saturating_sub(EDGE_ZONE_W) as i64 {
+        Some(DropTarget::Workspace(1))
+    } else {
+        None
+    }
+}
+
+fn close_zone_rect(fb_shape: (u32, u32)) -> Rect {
+    let (w, h) = fb_shape;
+    Rect {
+        x0: 0,
+        y0: h as i64 - (TASKBAR_H + CLOSE_ZONE_H) as i64,
+        w,
+        h: CLOSE_ZONE_H,
+    }
+}
+
+fn edge_zone_rect(offset: isize, fb_shape: (u32, u32)) -> Rect {
+    let (w, h) = fb_shape;
+    let x0 = match offset < 0 {
+        true => 0,
+        false => w.saturating_sub(EDGE_ZONE_W) as i64,
+    };
+    Rect {
+        x0,
+        y0: TOPBAR_H as i64,
+        w: EDGE_ZONE_W,
+        h: h.saturating_sub(TOPBAR_H + TASKBAR_H),
+    }
+}
+
+// From 0 (windows in place) to 1 (windows in the grid)
+pub fn progress(since: f64, leaving: bool, time: f64) -> f32 {
+    let t = f64::clamp((time - since) / ANIMATION_DURATION, 0.0, 1.0) as f32;
+    // Smoothstep, so that windows ease in and out of place
+    let t = t * t * (3.0 - 2.0 * t);
+    match leaving {
+        true => 1.0 - t,
+        false => t,
+    }
+}
+
+// Start of the transition going the other way, from where the current one is at
+pub fn reversed_since(since: f64, time: f64) -> f64 {
+    let elapsed = f64::min(time - since, ANIMATION_DURATION);
+    time - (ANIMATION_DURATION - elapsed)
+}
+
+pub struct OverviewWindow {
+    pub app_name: &'static str,
+    // Where the window content is outside of the overview
+    pub content_rect: Rect,
+    pub thumbnail_rect: Rect,
+    title_rect: Rect,
+}
+
+// Side by side in a grid between the topbar and the close zone, the last row is centered.
+// Thumbnails keep the aspect ratio of the windows and are never larger than them.
+pub fn layout(windows: Vec<(&'static str, Rect)>, fb_shape: (u32, u32)) -> Vec<OverviewWindow> {
+    let (w, h) = fb_shape;
+    let nb_windows = windows.len() as u32;
+    if nb_windows == 0 {
+        return Vec::new();
+    }
+
+    let area = Rect::from_xyxy([
+        GRID_GAP as i64,
+        (TOPBAR_H + GRID_GAP) as i64,
+        w as i64 - GRID_GAP as i64 - 1,
+        h as i64 - (TASKBAR_H + CLOSE_ZONE_H + GRID_GAP) as i64 - 1,
+    ]);
+
+    let nb_cols = (1..).find(|n| n * n >= nb_windows).unwrap();
+    let nb_rows = nb_windows.div_ceil(nb_cols);
+    let cell_w = area.w.saturating_sub((nb_cols - 1) * GRID_GAP) / nb_cols;
+    let cell_h = area.h.saturating_sub((nb_rows - 1) * GRID_GAP) / nb_rows;
+    let max_thumbnail_h = cell_h.saturating_sub(TITLE_H);
+
+    windows
+        .into_iter()
+        .enumerate()
+        .map(|(i, (app_name, content_rect))| {
+            let (col, row) = (i as u32 % nb_cols, i as u32 / nb_cols);
+            let nb_in_row = u32::min(nb_cols, nb_windows - row * nb_cols);
+            let row_offset = (nb_cols - nb_in_row) * (cell_w + GRID_GAP) / 2;
+            let cell = Rect {
+                x0: area.x0 + (row_offset + col * (cell_w + GRID_GAP)) as i64,
+                y0: area.y0 + (row * (cell_h + GRID_GAP)) as i64,
+                w: cell_w,
+                h: cell_h,
+            };
+
+            let (win_w, win_h) = content_rect.shape();
+            let scale = f32::min(
+                1.0,
+                f32::min(
+                    cell_w as f32 / u32::max(win_w, 1) as f32,
+                    max_thumbnail_h as f32 / u32::max(win_h, 1) as f32,
+                ),
+            );
+            let thumbnail_w = u32::max(1, (win_w as f32 * scale) as u32);
+            let thumbnail_h = u32::max(1, (win_h as f32 * scale) as u32);
+            let (xc, _) = cell.center();
+            let thumbnail_rect = Rect {
+                x0: xc - (thumbnail_w / 2) as i64,
+                y0: cell.y0 + (max_thumbnail_h.saturating_sub(thumbnail_h) / 2) as i64,
+                w: thumbnail_w,
+                h: thumbnail_h,
+            };
+            let title_rect = Rect {
+                y0: thumbnail_rect.y0 + thumbnail_h as i64,
+                h: TITLE_H,
+                ..cell
+            };
+
+            OverviewWindow {
+                app_name,
+                content_rect,
+                thumbnail_rect,
+                title_rect,
+            }
+        })
+        .collect()
+}
+
+pub fn window_at(windows: &[OverviewWindow], x: i64, y: i64) -> Option<&'static str> {
+    windows
+        .iter()
+        .rev()
+        .find(|window| window.thumbnail_rect.check_contains_point(x, y))
+        .map(|window| window.app_name)
+}
+
+struct Thumbnail {
+    content_id: ContentId,
+    fb: Framebuffer<OwnedPixels>,
+}
+
+// Scaled down window contents, rendered again only when the content of the window or the
+// size of its thumbnail changes
+pub struct ThumbnailCache {
+    thumbnails: BTreeMap<&'static str, Thumbnail>,
+}
+
+impl ThumbnailCache {
+    pub fn new() -> Self {
+        ThumbnailCache {
+            thumbnails: BTreeMap::new(),
+        }
+    }
+
+    pub fn update<F: FbView>(
+        &mut self,
+        app_name: &'static str,
+        content_id: ContentId,
+        src: &F,
+        src_rect: &Rect,
+        shape: (u32, u32),
+    ) {
+        let up_to_date = self
+            .thumbnails
+            .get(app_name)
+            .is_some_and(|thumb| thumb.content_id == content_id && thumb.fb.shape() == shape);
+        if up_to_date {
+            return;
+        }
+
+        let (w, h) = shape;
+        let mut fb = Framebuffer::new_owned(w, h);
+        let dst_rect = fb.shape_as_rect();
+        fb.copy_from_fb_scaled(src, src_rect, &dst_rect, ScaleFilter::Bilinear, false);
+        self.thumbnails
+            .insert(app_name, Thumbnail { content_id, fb });
+    }
+
+    // Of windows which are not in the overview anymore
+    pub fn retain(&mut self, app_names: &[&'static str]) {
+        self.thumbnails
+            .retain(|app_name, _| app_names.contains(app_name));
+    }
+
+    fn get(&self, app_name: &str) -> Option<&Framebuffer<OwnedPixels>> {
+        self.thumbnails.get(app_name).map(|thumb| &thumb.fb)
+    }
+}
+
+fn lerp_rect(a: &Rect, b: &Rect, t: f32) -> Rect {
+    let lerp = |a: i64, b: i64| a + ((b - a) as f32 * t) as i64;
+    let [ax0, ay0, ax1, ay1] = a.as_xyxy();
+    let [bx0, by0, bx1, by1] = b.as_xyxy();
+    Rect::from_xyxy([
+        lerp(ax0, bx0),
+        lerp(ay0, by0),
+        lerp(ax1, bx1),
+        lerp(ay1, by1),
+    ])
+}
+
+// Windows move from their place to their thumbnail as t goes from 0 to 1. Titles, the
+// hover highlight and the drop zones only show once the overview is fully in.
+pub fn draw<F: FbViewMut>(
+    fb: &mut F,
+    stylesheet: &StyleSheet,
+    font: &Font,
+    windows: &[OverviewWindow],
+    thumbnails: &ThumbnailCache,
+    t: f32,
+    hovered: Option<&'static str>,
+    pointer: (i64, i64),
+    drag: Option<OverviewDrag>,
+) {
+    let fb_shape = fb.shape();
+    let (px, py) = pointer;
+    let shown = t >= 1.0;
+
+    let screen_rect = fb.shape_as_rect();
+    let alpha = (DIM_ALPHA * t) as u8;
+    draw_rect(fb, &screen_rect, Color::rgba(0, 0, 0, alpha), true);
+
+    let drag = drag.filter(|drag| drag.moved && shown);
+    if drag.is_some() {
+        let target = drop_target(px, py, fb_shape);
+        let (r, g, b, _) = stylesheet.colors.accent.as_rgba();
+        let zone_color = |active: bool| match active {
+            true => Color::rgba(r, g, b, 120),
+            false => Color::rgba(r, g, b, 40),
+        };
+
+        let close_rect = close_zone_rect(fb_shape);
+        let close_active = target == Some(DropTarget::Close);
+        draw_rect(fb, &close_rect, zone_color(close_active), true);
+        draw_line_in_rect(
+            fb,
+            "Drop here to close",
+            &close_rect,
+            font,
+            stylesheet.colors.text,
+            TextJustification::Center,
+        );
+
+        for offset in [-1, 1] {
+            let active = target == Some(DropTarget::Workspace(offset));
+            draw_rect(
+                fb,
+                &edge_zone_rect(offset, fb_shape),
+                zone_color(active),
+                true,
+            );
+        }
+    }
+
+    // The held thumbnail goes over the others
+    let is_dragged =
+        |window: &&OverviewWindow| drag.is_some_and(|drag| drag.app_name == window.app_name);
+    let (held, others): (Vec<&OverviewWindow>, Vec<&OverviewWindow>) =
+        windows.iter().partition(is_dragged);
+
+    for window in others.into_iter().chain(held) {
+        let dragged = drag.filter(|drag| drag.app_name == window.app_name);
+        let mut rect = lerp_rect(&window.content_rect, &window.thumbnail_rect, t);
+        if let Some(drag) = dragged {
+            rect.x0 += px - drag.start.x;
+            rect.y0 += py - drag.start.y;
+        }
+
+        // Crashed and starting apps have nothing to show
+        match thumbnails.get(window.app_name) {
+            Some(thumb_fb) if thumb_fb.shape() == rect.shape() => {
+                fb.copy_from_fb(thumb_fb, rect.origin(), false)
+            }
+            Some(thumb_fb) => fb.copy_from_fb_scaled(
+                thumb_fb,
+                &thumb_fb.shape_as_rect(),
+                &rect,
+                ScaleFilter::Nearest,
+                false,
+            ),
+            None => draw_rect(fb, &rect, stylesheet.colors.element, false),
+        }
+
+        if !shown || dragged.is_some() {
+            continue;
+        }
+
+        if hovered == Some(window.app_name) {
+            draw_rect_outline(
+                fb,
+                &rect.offset(HIGHLIGHT_THICKNESS as i64),
+                stylesheet.colors.accent,
+                false,
+                HIGHLIGHT_THICKNESS,
+            );
+        }
+
+        let title = ellipsize_text(window.app_name, font, window.title_rect.w);
+        draw_line_in_rect(
+            fb,
+            &title,
+            &window.title_rect,
+            font,
+            stylesheet.colors.text,
+            TextJustification::Center,
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use applib::drawing::primitives::draw_rect;
+use applib::drawing::text::{draw_char, Font, FONT_FAMILIES};
+use applib::{BorrowedMutPixels, Color, FbView, Framebuffer, Rect};
+use core::arch::asm;
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::ptr::{addr_of, null_mut};
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use uefi::table::runtime::{ResetType, RuntimeServices};
+use uefi::Status;
+use x86_64::instructions::{hlt, interrupts};
+
+use crate::logging;
+use crate::serial::SERIAL1;
+use crate::serial_println;
+use crate::virtio::gpu::VirtioGPU;
+
+// Deeper frames are not printed
+const MAX_FRAMES: usize = 32;
+// Frame pointers further up the stack than that are taken as garbage
+const MAX_STACK_SPAN: u64 = 1 << 20;
+
+// Bitmap fonts are drawn without allocating
+const FONT_FAMILY: &str = "NotoSansMono";
+const FONT_SIZE: u32 = 16;
+const MARGIN: u32 = 24;
+const BANNER_H: u32 = 56;
+const BACKGROUND_COLOR: Color = Color::rgb(0x1c, 0x1c, 0x24);
+const BANNER_COLOR: Color = Color::rgb(0xb0, 0x20, 0x20);
+const TEXT_COLOR: Color = Color::WHITE;
+
+// Set once the devices are up, before that panics only go to the serial port
+static GPU: AtomicPtr<VirtioGPU> = AtomicPtr::new(null_mut());
+static RUNTIME_SERVICES: AtomicPtr<RuntimeServices> = AtomicPtr::new(null_mut());
+
+// A panic while handling one halts right away
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    // Where the loader put the kernel image, addresses relative to it can be symbolized
+    // against the kernel binary
+    static __ImageBase: u8;
+}
+
+// Both must never move, main() keeps them until the end
+pub fn register(gpu: &mut VirtioGPU, runtime_services: &RuntimeServices) {
+    GPU.store(gpu, Ordering::Relaxed);
+    RUNTIME_SERVICES.store(
+        runtime_services as *const RuntimeServices as *mut RuntimeServices,
+        Ordering::Relaxed,
+    );
+}
+
+// Nothing here allocates, the allocator may be what panicked
+pub fn handle(info: &PanicInfo, reboot_delay: Option<f64>) -> ! {
+    interrupts::disable();
+
+    // Nothing else runs anymore, and the panic may have happened with the port held
+    unsafe { SERIAL1.force_unlock() };
+
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        serial_println!("PANIC while handling a panic: {}", info);
+        halt();
+    }
+
+    // The logger locks may be held too
+    log::set_max_level(log::LevelFilter::Off);
+
+    let registers = Registers::capture();
+    let (frames, nb_frames) = walk_frames(registers.rbp, registers.rsp);
+    let frames = &frames[..nb_frames];
+    let image_base = unsafe { addr_of!(__ImageBase) } as u64;
+
+    serial_println!("KERNEL PANIC: {}", info);
+    registers.print();
+    serial_println!("Backtrace (image base 0x{:x}):", image_base);
+    for (i, addr) in frames.iter().enumerate() {
+        serial_println!(
+            "  #{:<2} 0x{:016x} (image +0x{:x})",
+            i,
+            addr,
+            addr.wrapping_sub(image_base)
+        );
+    }
+
+    let runtime_services = unsafe { RUNTIME_SERVICES.load(Ordering::Relaxed).as_ref() };
+    let reboot_delay = reboot_delay.filter(|_| runtime_services.is_some());
+
+    if let Some(gpu) = unsafe { GPU.load(Ordering::Relaxed).as_mut() } {
+        draw_panic_screen(gpu, info, frames, image_base, reboot_delay);
+    }
+
+    match (runtime_services, reboot_delay) {
+        (Some(runtime_services), Some(delay)) => {
+            serial_println!("Rebooting in {}s", delay);
+            let deadline = logging::uptime_ms() + delay * 1000.0;
+            while logging::uptime_ms() < deadline {
+                core::hint::spin_loop();
+            }
+            runtime_services.reset(ResetType::COLD, Status::ABORTED, None);
+        }
+        _ => {
+            serial_println!("System halted");
+            halt();
+        }
+    }
+}
+
+fn halt() -> ! {
+    loop {
+        hlt();
+    }
+}
+
+// Callee-saved registers, as of when the panic handler was entered. Best effort, the
+// compiler may have already used some of them.
+struct Registers {
+    rsp: u64,
+    rbp: u64,
+    rbx: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+}
+
+macro_rules! read_register {
+    ($name:literal) => {{
+        let value: u64;
+        unsafe {
+            asm!(
+                concat!("mov {}, ", $name),
+                out(reg) value,
+                options(nomem, nostack, preserves_flags)
+            )
+        };
+        value
+    }};
+}
+
+impl Registers {
+    #[inline(always)]
+    fn capture() -> Self {
+        Registers {
+            rsp: read_register!("rsp"),
+            rbp: read_register!("rbp"),
+            rbx: read_register!("rbx"),
+            r12: read_register!("r12"),
+            r13: read_register!("r13"),
+            r14: read_register!("r14"),
+            r15: read_register!("r15"),
+        }
+    }
+
+    fn print(&self) {
+        serial_println!(
+            "rsp 0x{:016x} rbp 0x{:016x} rbx 0x{:016x}",
+            self.rsp,
+            self.rbp,
+            self.rbx
+        );
+        serial_println!(
+            "r12 0x{:016x} r13 0x{:016x} r14 0x{:016x} r15 0x{:016x}",
+            self.r12,
+            self.r13,
+            self.r14,
+            self.r15
+        );
+    }
+}
+
+// Return addresses found by following the saved frame pointers, innermost first
+fn walk_frames(mut rbp: u64, rsp: u64) -> ([u64; MAX_FRAMES], usize) {
+    let mut frames = [0; MAX_FRAMES];
+    let mut nb_frames = 0;
+
+    while nb_frames < MAX_FRAMES {
+        // Callers are further up the stack, the walk stops at anything else
+        let on_stack = rbp >= rsp && rbp - rsp < MAX_STACK_SPAN;
+        if rbp == 0 || rbp % 8 != 0 || !on_stack {
+            break;
+        }
+
+        let (next_rbp, return_addr) = unsafe { (*(rbp as *const u64), *((rbp + 8) as *const u64)) };
+        if return_addr == 0 {
+            break;
+        }
+
+        frames[nb_frames] = return_addr;
+        nb_frames += 1;
+
+        if next_rbp <= rbp {
+            break;
+        }
+        rbp = next_rbp;
+    }
+
+    (frames, nb_frames)
+}
+
+// Drawn right into the scanout framebuffer, whatever the compositor was doing
+fn draw_panic_screen(
+    gpu: &mut VirtioGPU,
+    info: &PanicInfo,
+    frames: &[u64],
+    image_base: u64,
+    reboot_delay: Option<f64>,
+) {
+    let Some(family) = FONT_FAMILIES.get(FONT_FAMILY) else {
+        return;
+    };
+    let font = family.get_size(FONT_SIZE);
+
+    let (w, h) = gpu.get_dims();
+    let (w, h) = (w as u32, h as u32);
+    let format = gpu.format;
+    let background_color = format.convert_color(BACKGROUND_COLOR);
+    let banner_color = format.convert_color(BANNER_COLOR);
+    let text_color = format.convert_color(TEXT_COLOR);
+    let mut fb = Framebuffer::<BorrowedMutPixels>::from_bytes(&mut gpu.framebuffer, w, h);
+
+    let screen_rect = fb.shape_as_rect();
+    let banner_rect = Rect {
+        h: BANNER_H,
+        ..screen_rect
+    };
+    draw_rect(&mut fb, &screen_rect, background_color, false);
+    draw_rect(&mut fb, &banner_rect, banner_color, false);
+
+    let text_rect = Rect {
+        x0: MARGIN as i64,
+        y0: BANNER_H as i64 + MARGIN as i64,
+        w: w.saturating_sub(2 * MARGIN),
+        h: h.saturating_sub(BANNER_H + 2 * MARGIN),
+    };
+    let banner_text_rect = Rect {
+        x0: MARGIN as i64,
+        y0: (BANNER_H as i64 - font.char_h as i64) / 2,
+        w: w.saturating_sub(2 * MARGIN),
+        h: BANNER_H,
+    };
+
+    let mut banner = ScreenWriter::new(&mut fb, font, text_color, &banner_text_rect);
+    let _ = match reboot_delay {
+        Some(delay) => write!(banner, "KERNEL PANIC - rebooting in {}s", delay),
+        None => write!(banner, "KERNEL PANIC - system halted"),
+    };
+
+    let mut text = ScreenWriter::new(&mut fb, font, text_color, &text_rect);
+    if let Some(location) = info.location() {
+        let _ = writeln!(text, "at {}\n", location);
+    }
+    let _ = writeln!(text, "{}\n", info.message());
+    let _ = writeln!(text, "Backtrace (image base 0x{:x}):", image_base);
+    for (i, addr) in frames.iter().enumerate() {
+        let _ = writeln!(text, "  #{:<2} 0x{:016x}", i, addr);
+    }
+    let _ = writeln!(text, "\nMore details were printed to the serial port");
+
+    gpu.flush();
+}
+
+// Draws text as it is formatted, wrapping it to a rect and dropping what does not fit
+struct ScreenWriter<'a, 'b> {
+    fb: &'a mut Framebuffer<BorrowedMutPixels<'b>>,
+    font: &'static Font,
+    color: Color,
+    rect: Rect,
+    x: i64,
+    y: i64,
+}
+
+impl<'a, 'b> ScreenWriter<'a, 'b> {
+    fn new(
+        fb: &'a mut Framebuffer<BorrowedMutPixels<'b>>,
+        font: &'static Font,
+        color: Color,
+        rect: &Rect,
+    ) -> Self {
+        ScreenWriter {
+            fb,
+            font,
+            color,
+            rect: rect.clone(),
+            x: rect.x0,
+            y: rect.y0,
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.x = self.rect.x0;
+        self.y += self.font.char_h as i64;
+    }
+}
+
+impl Write for ScreenWriter<'_, '_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let [_, _, x1, y1] = self.rect.as_xyxy();
+        let char_w = self.font.char_w as i64;
+
+        for c in s.chars() {
+            if c == '\n' {
+                self.new_line();
+                continue;
+            }
+            if self.x + char_w > x1 + 1 {
+                self.new_line();
+            }
+            if self.y + self.font.char_h as i64 > y1 + 1 {
+                break;
+            }
+            draw_char(self.fb, c, self.x, self.y, self.font, self.color, true);
+            self.x += char_w;
+        }
+
+        Ok(())
+    }
+}
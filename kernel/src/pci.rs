@@ -1,11 +1,24 @@
-use alloc::borrow::ToOwned;
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use bitvec::field::BitField;
 use bitvec::prelude::Lsb0;
 use bitvec::view::BitView;
-use core::mem;
+use core::ptr::write_volatile;
+use core::{fmt, mem};
 use x86_64::instructions::port::{Port, PortWriteOnly};
+use x86_64::PhysAddr;
+
+use crate::memory;
+
+const MSIX_CAP_ID: u8 = 0x11;
+
+// Bits of the command register
+const COMMAND_IO_SPACE: u32 = 0x1 << 0;
+const COMMAND_MEMORY_SPACE: u32 = 0x1 << 1;
+const COMMAND_BUS_MASTER: u32 = 0x1 << 2;
+
+// Bit of the header type set for devices with more than one function
+const HEADER_MULTI_FUNCTION: u8 = 0x80;
 
 #[derive(Debug)]
 pub struct PciDevice {
@@ -13,18 +26,28 @@ pub struct PciDevice {
     pub vendor_id: u16,
     pub device_id: u16,
     pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
 
     pub capabilities: Vec<PciCapability>,
     pub bars: BTreeMap<u32, PciBar>,
 }
 
-#[derive(Debug, Clone)]
+// Ordered as the devices are on the bus
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct PciAddress {
     bus: u8,
     device: u8,
     function: u8,
 }
 
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.device, self.function)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PciCapability {
     pub vendor: u8,
@@ -43,7 +66,7 @@ pub enum PciBar {
         addr_type: BarAddrType,
         prefetchable: bool,
         base_addr: u64,
-        size: u32,
+        size: u64,
     },
     IO {
         base_addr: u32,
@@ -51,6 +74,15 @@ pub enum PciBar {
     },
 }
 
+// Where the MSI-X table and pending bit array are, both in memory BARs
+#[derive(Debug, Clone, Copy)]
+pub struct MsixCapability {
+    offset: u8,
+    pub table_size: u16,
+    pub table_addr: u64,
+    pub pba_addr: u64,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum BarAddrType {
     Bar32,
@@ -84,84 +116,227 @@ impl PciDevice {
         bits[..8].load()
     }
 
+    // Lets the device answer at its memory BARs and access memory itself (virtqueues,
+    // MSI-X messages). To be called before a driver touches the device.
+    pub fn enable_memory_and_bus_master(&self) {
+        let mut pci_config_space = PciConfigSpace::new();
+
+        // The status register in the upper half is cleared by writing ones, it is left
+        // as is
+        let command = unsafe { pci_config_space.read(&self.addr, 0x04) } & 0xffff;
+        let enabled = command | COMMAND_MEMORY_SPACE | COMMAND_BUS_MASTER;
+        if enabled != command {
+            unsafe { pci_config_space.write(&self.addr, 0x04, enabled) };
+        }
+    }
+
     pub fn disable_msix(&self) {
+        if let Some(msix) = self.msix_capability() {
+            self.set_msix_enabled(&msix, false);
+        }
+    }
+
+    // None if the device has no MSI-X capability, or its table is not in a memory BAR
+    pub fn msix_capability(&self) -> Option<MsixCapability> {
         let mut pci_config_space = PciConfigSpace::new();
 
-        let cap = self.capabilities.iter().find(|cap| cap.vendor == 0x11);
+        let cap = self
+            .capabilities
+            .iter()
+            .find(|cap| cap.vendor == MSIX_CAP_ID)?;
+
+        let (word_0, word_4, word_8) = unsafe {
+            (
+                pci_config_space.read(&self.addr, cap.offset),
+                pci_config_space.read(&self.addr, cap.offset + 4),
+                pci_config_space.read(&self.addr, cap.offset + 8),
+            )
+        };
+
+        // The BAR index in the low bits, the offset in that BAR in the others
+        let addr_in_bar = |word: u32| -> Option<u64> {
+            let bits = word.view_bits::<Lsb0>();
+            let bar_index = bits[..3].load::<u32>();
+            let offset = (word & !0x7) as u64;
+            match self.bars.get(&bar_index)? {
+                PciBar::Memory { base_addr, .. } => Some(base_addr + offset),
+                PciBar::IO { .. } => None,
+            }
+        };
+
+        let bits_0 = word_0.view_bits::<Lsb0>();
+        let table_size = bits_0[16..27].load::<u16>() + 1;
+
+        Some(MsixCapability {
+            offset: cap.offset,
+            table_size,
+            table_addr: addr_in_bar(word_4)?,
+            pba_addr: addr_in_bar(word_8)?,
+        })
+    }
 
-        let cap = if let Some(cap) = cap { cap } else { return };
+    // Also unmasks the whole function, entries stay masked until they are set
+    pub fn set_msix_enabled(&self, msix: &MsixCapability, enabled: bool) {
+        let mut pci_config_space = PciConfigSpace::new();
 
-        let mut word = unsafe { pci_config_space.read(&self.addr, cap.offset) };
+        let mut word = unsafe { pci_config_space.read(&self.addr, msix.offset) };
 
         let bits = word.view_bits_mut::<Lsb0>();
-        bits.set(31, false);
+        bits.set(30, false);
+        bits.set(31, enabled);
+
+        unsafe { pci_config_space.write(&self.addr, msix.offset, bits.load()) };
+    }
+
+    // The device writes `data` to `addr` to raise entry `entry`, which gets unmasked
+    pub fn set_msix_entry(&self, msix: &MsixCapability, entry: u16, addr: u64, data: u32) {
+        assert!(entry < msix.table_size);
+
+        let entry_addr = PhysAddr::new(msix.table_addr + 16 * entry as u64);
+        let ptr = memory::get_mapper()
+            .phys_to_virt(entry_addr)
+            .as_mut_ptr::<u32>();
 
-        unsafe { pci_config_space.write(&self.addr, cap.offset, bits.load()) };
+        unsafe {
+            write_volatile(ptr, addr as u32);
+            write_volatile(ptr.add(1), (addr >> 32) as u32);
+            write_volatile(ptr.add(2), data);
+            write_volatile(ptr.add(3), 0); // Vector control, unmasked
+        }
     }
 }
 
 pub fn enumerate() -> Vec<PciDevice> {
     let mut pci_config_space = PciConfigSpace::new();
+    let mut pci_devices = Vec::new();
 
-    // Don't care about multi-function devices for now
-    let function = 0;
-
-    let bus_iter = 0..=255u8;
-    let device_iter = 0..32u8;
-
-    bus_iter
-        .flat_map(move |bus| device_iter.clone().map(move |device| (bus, device)))
-        .filter_map(move |(bus, device)| {
+    for bus in 0..=255u8 {
+        for device in 0..32u8 {
             let addr = PciAddress {
                 bus,
                 device,
-                function,
+                function: 0,
             };
 
-            let word_0 = unsafe { pci_config_space.read(&addr, 0x0) };
-
             // No device at this address
-            if word_0 == u32::MAX {
-                return None;
+            let Some(header_type) = read_header_type(&mut pci_config_space, &addr) else {
+                continue;
+            };
+
+            // The other functions are only looked at in multi-function devices, single
+            // function ones may answer for all of them
+            let nb_functions = match header_type & HEADER_MULTI_FUNCTION != 0 {
+                true => 8,
+                false => 1,
+            };
+
+            for function in 0..nb_functions {
+                let addr = PciAddress {
+                    bus,
+                    device,
+                    function,
+                };
+                if let Some(pci_device) = read_function(&mut pci_config_space, addr) {
+                    log_device(&pci_device);
+                    pci_devices.push(pci_device);
+                }
             }
+        }
+    }
 
-            // Header type
-            let word_0c = unsafe { pci_config_space.read(&addr, 0x0c) };
-            let bits_0c = word_0c.view_bits::<Lsb0>();
-            let mut header_bits = bits_0c[16..24].to_owned();
-            header_bits.set(7, false);
-            let header_type = header_bits.load::<u8>();
-            assert_eq!(header_type, 0x00); // We don't support PCI bridges for now
-
-            // Device/Vendor IDs
-            let bits_0 = word_0.view_bits::<Lsb0>();
-            let device_id = bits_0[16..32].load();
-            let vendor_id = bits_0[0..16].load();
-
-            // Device class
-            let word_8 = unsafe { pci_config_space.read(&addr, 0x8) };
-            let bits_8 = word_8.view_bits::<Lsb0>();
-            let class = bits_8[24..32].load();
-
-            let capabilities = get_capabilities(&mut pci_config_space, &addr);
-            let bars = get_bars(&mut pci_config_space, &addr);
-
-            log::info!(
-                "Found PCI device, vendor={:#x} device={:#x}",
-                vendor_id,
-                device_id
-            );
-
-            Some(PciDevice {
-                addr,
-                vendor_id,
-                device_id,
-                class,
-                capabilities,
-                bars,
-            })
-        })
-        .collect()
+    pci_devices
+}
+
+// None if there is no function at this address
+fn read_header_type(pci_config_space: &mut PciConfigSpace, addr: &PciAddress) -> Option<u8> {
+    let word_0 = unsafe { pci_config_space.read(addr, 0x0) };
+    if word_0 & 0xffff == 0xffff {
+        return None;
+    }
+
+    let word_0c = unsafe { pci_config_space.read(addr, 0x0c) };
+    let bits_0c = word_0c.view_bits::<Lsb0>();
+    Some(bits_0c[16..24].load::<u8>())
+}
+
+fn read_function(pci_config_space: &mut PciConfigSpace, addr: PciAddress) -> Option<PciDevice> {
+    let header_type = read_header_type(pci_config_space, &addr)? & !HEADER_MULTI_FUNCTION;
+
+    // Bridges have a different header, the devices behind them are found on their bus
+    if header_type != 0x00 {
+        log::debug!(
+            "Skipping PCI function {} of header type {:#x}",
+            addr,
+            header_type
+        );
+        return None;
+    }
+
+    // Device/Vendor IDs
+    let word_0 = unsafe { pci_config_space.read(&addr, 0x0) };
+    let bits_0 = word_0.view_bits::<Lsb0>();
+    let device_id = bits_0[16..32].load();
+    let vendor_id = bits_0[0..16].load();
+
+    // Device class
+    let word_8 = unsafe { pci_config_space.read(&addr, 0x8) };
+    let bits_8 = word_8.view_bits::<Lsb0>();
+    let revision = bits_8[0..8].load();
+    let prog_if = bits_8[8..16].load();
+    let subclass = bits_8[16..24].load();
+    let class = bits_8[24..32].load();
+
+    let capabilities = get_capabilities(pci_config_space, &addr);
+    let bars = get_bars(pci_config_space, &addr);
+
+    Some(PciDevice {
+        addr,
+        vendor_id,
+        device_id,
+        class,
+        subclass,
+        prog_if,
+        revision,
+        capabilities,
+        bars,
+    })
+}
+
+fn log_device(pci_device: &PciDevice) {
+    log::info!(
+        "Found PCI device {}, vendor={:#x} device={:#x} class={:02x}.{:02x}.{:02x} rev={}",
+        pci_device.addr,
+        pci_device.vendor_id,
+        pci_device.device_id,
+        pci_device.class,
+        pci_device.subclass,
+        pci_device.prog_if,
+        pci_device.revision
+    );
+
+    for (i, bar) in pci_device.bars.iter() {
+        match bar {
+            PciBar::Memory {
+                addr_type,
+                prefetchable,
+                base_addr,
+                size,
+            } => log::info!(
+                "  BAR{} memory {:#x} size {:#x}{}{}",
+                i,
+                base_addr,
+                size,
+                match addr_type {
+                    BarAddrType::Bar32 => "",
+                    BarAddrType::Bar64 => " 64-bit",
+                },
+                if *prefetchable { " prefetchable" } else { "" }
+            ),
+            PciBar::IO { base_addr, size } => {
+                log::info!("  BAR{} I/O {:#x} size {:#x}", i, base_addr, size)
+            }
+        }
+    }
 }
 
 fn get_capabilities(
@@ -196,41 +371,26 @@ fn get_capabilities(
 fn get_bars(pci_config_space: &mut PciConfigSpace, addr: &PciAddress) -> BTreeMap<u32, PciBar> {
     const MAX_BARS: u32 = 6;
 
+    // Decoding is turned off while the BARs hold all ones, so that the device does not
+    // answer at those addresses in the meantime
+    let command = unsafe { pci_config_space.read(addr, 0x04) } & 0xffff;
+    unsafe {
+        pci_config_space.write(
+            addr,
+            0x04,
+            command & !(COMMAND_IO_SPACE | COMMAND_MEMORY_SPACE),
+        )
+    };
+
     let mut bars = BTreeMap::new();
     let mut it = 0..MAX_BARS;
 
     while let Some(i) = it.next() {
         let offset = 0x10 + 0x4 * (i as u8);
-        let word_bars = unsafe { pci_config_space.read(&addr, offset) };
+        let (word_bars, word_size) = unsafe { probe_bar(pci_config_space, addr, offset) };
 
         let bits_bar = word_bars.view_bits::<Lsb0>();
-
         let io_mapped = bits_bar[0];
-        let n_flags = if io_mapped { 2 } else { 4 };
-
-        // BAR size
-        let size: u32 = {
-            let mut word_size = unsafe {
-                pci_config_space.write(&addr, offset, u32::MAX);
-                let word_size = pci_config_space.read(&addr, offset);
-                pci_config_space.write(&addr, offset, word_bars);
-                word_size
-            };
-
-            let bits_size = word_size.view_bits_mut::<Lsb0>();
-            bits_size[..n_flags].fill(false);
-            let val: u32 = bits_size.load();
-
-            if val == 0 {
-                0
-            } else {
-                !val + 1
-            }
-        };
-
-        if size == 0 {
-            continue;
-        }
 
         let bar = match io_mapped {
             // Memory-mapped BAR
@@ -242,60 +402,81 @@ fn get_bars(pci_config_space: &mut PciConfigSpace, addr: &PciAddress) -> BTreeMa
                     val => panic!("Unsupported BAR size type: {}", val),
                 };
 
-                // Address (lower bits)
-                let addr_low_bits = {
-                    let mut val: u64 = bits_bar.load();
-                    let addr_bits = val.view_bits_mut();
-                    addr_bits[..4].fill(false);
-                    addr_bits.to_owned()
-                };
-
-                let base_addr = match addr_type {
-                    BarAddrType::Bar32 => addr_low_bits.load::<u64>(),
-
+                // The high bits of the address and size are in the next BAR
+                let (high_bars, high_size, implemented) = match addr_type {
+                    BarAddrType::Bar32 => (0, 0, u32::MAX as u64),
                     BarAddrType::Bar64 => {
-                        // Grabbing high bits of the address from next BAR
                         let next_i = it.next().expect("64-bit BAR but already in last BAR");
                         let next_offset = 0x10 + 0x4 * (next_i as u8);
-                        let next_word_bars = unsafe { pci_config_space.read(&addr, next_offset) };
-                        let next_word_bars: u64 = next_word_bars.into();
-                        let addr_high_bits = next_word_bars.view_bits::<Lsb0>();
-
-                        let addr_low_bits = addr_low_bits.as_bitslice();
-
-                        let mut addr = 0u64;
-                        let addr_bits = addr.view_bits_mut::<Lsb0>();
-
-                        addr_bits[00..32].copy_from_bitslice(&addr_low_bits[..32]);
-                        addr_bits[32..64].copy_from_bitslice(&addr_high_bits[..32]);
-
-                        addr_bits.load::<u64>()
+                        let (high_bars, high_size) =
+                            unsafe { probe_bar(pci_config_space, addr, next_offset) };
+                        (high_bars, high_size, u64::MAX)
                     }
                 };
 
+                let base_addr = (high_bars as u64) << 32 | (word_bars & !0xf) as u64;
+                let probed = (high_size as u64) << 32 | (word_size & !0xf) as u64;
+
                 PciBar::Memory {
                     addr_type,
                     prefetchable: bits_bar[3],
                     base_addr,
-                    size,
+                    size: bar_size(probed, implemented),
                 }
             }
 
             // I/0-mapped BAR
             true => {
-                let mut addr_bits = bits_bar.to_owned();
-                addr_bits[..2].fill(false);
-                let base_addr = addr_bits.load::<u32>();
-                PciBar::IO { base_addr, size }
+                // The upper 16 bits may not be implemented and read as zeroes
+                let implemented = match word_size & 0xffff_0000 {
+                    0 => 0xffff,
+                    _ => u32::MAX as u64,
+                };
+                PciBar::IO {
+                    base_addr: word_bars & !0x3,
+                    size: bar_size((word_size & !0x3) as u64, implemented) as u32,
+                }
             }
         };
 
+        let size = match bar {
+            PciBar::Memory { size, .. } => size,
+            PciBar::IO { size, .. } => size as u64,
+        };
+        if size == 0 {
+            continue;
+        }
+
         bars.insert(i, bar);
     }
 
+    unsafe { pci_config_space.write(addr, 0x04, command) };
+
     bars
 }
 
+// Writes all ones to a BAR, returns what it held and what read back, then restores it
+unsafe fn probe_bar(
+    pci_config_space: &mut PciConfigSpace,
+    addr: &PciAddress,
+    offset: u8,
+) -> (u32, u32) {
+    let word = pci_config_space.read(addr, offset);
+    pci_config_space.write(addr, offset, u32::MAX);
+    let probed = pci_config_space.read(addr, offset);
+    pci_config_space.write(addr, offset, word);
+    (word, probed)
+}
+
+// From what a BAR reads back once written all ones, without its flag bits. The device
+// keeps the address bits below its size at zero. Zero for an unimplemented BAR.
+fn bar_size(probed: u64, implemented: u64) -> u64 {
+    match probed & implemented {
+        0 => 0,
+        probed => (!probed & implemented) + 1,
+    }
+}
+
 impl PciConfigSpace {
     pub fn new() -> Self {
         PciConfigSpace {
@@ -0,0 +1,175 @@
+use alloc::collections::VecDeque;
+use rand::rngs::SmallRng;
+use rand::{RngCore, SeedableRng};
+
+use crate::virtio::entropy::VirtioEntropy;
+
+// Topped up every frame, drawn from when the generator is reseeded
+const POOL_SIZE: usize = 256;
+const SEED_LEN: usize = 32;
+
+// The generator is reseeded after handing out that many bytes
+const RESEED_BYTES: usize = 4096;
+
+// Device output with a longer run of the same byte is taken as broken
+const MAX_RUN: usize = 8;
+// After that many rejected buffers in a row, the device is no longer used
+const MAX_REJECTED: usize = 4;
+
+// At boot, how long to wait for the device to fill the pool enough for the first seed
+const INITIAL_POLLS: usize = 100_000;
+
+// Without a device, bytes of TSC jitter mixed into the pool per refill
+const JITTER_BYTES_PER_REFILL: usize = 4;
+// Busy work timed for each jitter sample
+const JITTER_ROUNDS: u64 = 64;
+
+// The system random number generator. A fast generator, reseeded from an entropy pool
+// that the virtio-rng device keeps filled.
+pub struct SystemRng {
+    rng: SmallRng,
+    pool: VecDeque<u8>,
+    // None if absent or broken, TSC jitter is used instead
+    device: Option<VirtioEntropy>,
+    // Since the last reseed
+    drawn: usize,
+    // Device buffers in a row that failed the health check
+    rejected: usize,
+}
+
+impl SystemRng {
+    pub fn new(device: Option<VirtioEntropy>) -> Self {
+        if device.is_none() {
+            log::warn!("No virtio-rng device, falling back to TSC jitter for entropy");
+        }
+
+        let mut system_rng = SystemRng {
+            rng: SmallRng::from_seed([0; SEED_LEN]),
+            pool: VecDeque::with_capacity(POOL_SIZE),
+            device,
+            drawn: 0,
+            rejected: 0,
+        };
+
+        for _ in 0..INITIAL_POLLS {
+            if system_rng.pool.len() >= SEED_LEN {
+                break;
+            }
+            system_rng.refill();
+            core::hint::spin_loop();
+        }
+        system_rng.reseed();
+
+        system_rng
+    }
+
+    // Takes what the device produced since the last call, without waiting for more
+    pub fn refill(&mut self) {
+        let SystemRng {
+            pool,
+            device,
+            rejected,
+            ..
+        } = self;
+
+        match device {
+            Some(device) => device.poll(|bytes| {
+                if !is_healthy(bytes) {
+                    log::warn!("Discarding virtio-rng output that failed the health check");
+                    *rejected += 1;
+                    return;
+                }
+                *rejected = 0;
+                let room = POOL_SIZE - pool.len();
+                pool.extend(bytes.iter().take(room));
+            }),
+            None => {
+                for _ in 0..usize::min(JITTER_BYTES_PER_REFILL, POOL_SIZE - pool.len()) {
+                    pool.push_back(jitter_byte());
+                }
+            }
+        }
+
+        if *rejected >= MAX_REJECTED {
+            log::error!("virtio-rng keeps failing the health check, falling back to TSC jitter");
+            *device = None;
+            *rejected = 0;
+        }
+    }
+
+    // Mixes fresh entropy into the current state, TSC jitter makes up for an empty pool
+    fn reseed(&mut self) {
+        let mut seed = [0u8; SEED_LEN];
+        self.rng.fill_bytes(&mut seed);
+        for byte in seed.iter_mut() {
+            *byte ^= self.pool.pop_front().unwrap_or_else(jitter_byte);
+        }
+
+        self.rng = SmallRng::from_seed(seed);
+        self.drawn = 0;
+    }
+
+    fn draw(&mut self, nb_bytes: usize) {
+        if self.drawn >= RESEED_BYTES {
+            self.reseed();
+        }
+        self.drawn += nb_bytes;
+    }
+}
+
+impl RngCore for SystemRng {
+    fn next_u32(&mut self) -> u32 {
+        self.draw(4);
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.draw(8);
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(RESEED_BYTES) {
+            self.draw(chunk.len());
+            self.rng.fill_bytes(chunk);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// Rejects obviously broken output, like all zeroes or a stuck byte
+fn is_healthy(bytes: &[u8]) -> bool {
+    let mut run = 0;
+    let mut prev = None;
+    for byte in bytes {
+        match prev == Some(byte) {
+            true => run += 1,
+            false => run = 1,
+        }
+        if run >= MAX_RUN {
+            return false;
+        }
+        prev = Some(byte);
+    }
+    true
+}
+
+// How long some busy work takes varies a little from one run to the next. Weak, but
+// better than a fixed seed.
+fn jitter_byte() -> u8 {
+    let mut byte = 0u8;
+    for _ in 0..8 {
+        let t0 = unsafe { core::arch::x86_64::_rdtsc() };
+        let mut x = t0;
+        for i in 0..JITTER_ROUNDS {
+            x = core::hint::black_box(x.rotate_left(7) ^ i);
+        }
+        let dt = unsafe { core::arch::x86_64::_rdtsc() } - t0;
+        byte = byte.rotate_left(1) ^ (dt as u8) ^ (dt >> 8) as u8;
+    }
+    byte
+}
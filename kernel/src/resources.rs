@@ -1,87 +1,269 @@
 use crate::app::AppDescriptor;
 use applib::{Color, Framebuffer, OwnedPixels, Rect};
-use applib::{StyleSheet, StyleSheetColors, StyleSheetText, TextSizes};
+use applib::color_utils::Palette;
+use applib::drawing::gradient::GradientDirection;
+use applib::{ChromeImage, ChromeStyle, StyleSheet, StyleSheetColors, StyleSheetText, TextSizes, TitleBarStyle, WidgetStyle};
 use lazy_static::lazy_static;
 
+// A stylesheet and how the wallpaper looks with it
+pub struct Theme {
+    pub name: &'static str,
+    pub stylesheet: &'static StyleSheet,
+    // Around the wallpaper when it does not cover the screen, or instead of it
+    pub backdrop: Color,
+    // Blended over the whole wallpaper
+    pub wallpaper_tint: Option<Color>,
+}
+
+pub struct WallpaperDescriptor {
+    pub name: &'static str,
+    pub source: WallpaperSource,
+}
+
+pub enum WallpaperSource {
+    // QOI, decoded when the wallpaper is rendered. If it is broken, the backdrop is
+    // shown instead.
+    Image(&'static [u8]),
+    // From the accent color of the theme to its backdrop
+    Gradient,
+    Plain,
+}
+
+// Converted from the PNG of the same name by build.rs
+macro_rules! qoi_bytes {
+    ($name: expr) => {
+        include_bytes!(concat!(env!("OUT_DIR"), "/", $name, ".qoi"))
+    };
+}
+
+macro_rules! qoi_asset {
+    ($name: expr) => {
+        Framebuffer::from_qoi(qoi_bytes!($name))
+            .expect(concat!("Invalid embedded image ", $name))
+    };
+}
+
 lazy_static! {
 
     //
     // Wallpaper
 
-    pub static ref WALLPAPER: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../wallpaper.png"));
+    pub static ref WALLPAPERS: [WallpaperDescriptor; 3] = [
+        WallpaperDescriptor {
+            name: "Default",
+            source: WallpaperSource::Image(qoi_bytes!("wallpaper")),
+        },
+        WallpaperDescriptor {
+            name: "Gradient",
+            source: WallpaperSource::Gradient,
+        },
+        WallpaperDescriptor {
+            name: "Plain",
+            source: WallpaperSource::Plain,
+        },
+    ];
 
 
     //
     // App icons
 
     pub static ref CUBE_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/cube.png"));
+        qoi_asset!("cube");
     pub static ref CHRONO_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/chronometer.png"));
+        qoi_asset!("chronometer");
     pub static ref TERMINAL_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/terminal.png"));
+        qoi_asset!("terminal");
     pub static ref CLOSE_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/close.png"));
+        qoi_asset!("close");
     pub static ref RELOAD_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/reload.png"));
+        qoi_asset!("reload");
     pub static ref MOVE_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/move.png"));
+        qoi_asset!("move");
     pub static ref PLAY_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/play.png"));
+        qoi_asset!("play");
     pub static ref PAUSE_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/pause.png"));
+        qoi_asset!("pause");
     pub static ref INSPECT_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/inspect.png"));
+        qoi_asset!("inspect");
     pub static ref SPEEDOMETER_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/speedometer.png"));
+        qoi_asset!("speedometer");
     pub static ref CHIP_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/chip.png"));
+        qoi_asset!("chip");
     pub static ref NETWORK_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/network.png"));
+        qoi_asset!("network");
     pub static ref WEB_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/web.png"));
+        qoi_asset!("web");
     pub static ref PYTHON_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/python.png"));
+        qoi_asset!("python");
     pub static ref UI_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../../icons/png/ui.png"));
+        qoi_asset!("ui");
+    pub static ref HOME_ICON: Framebuffer<OwnedPixels> =
+        qoi_asset!("home");
+    pub static ref PAINT_ICON: Framebuffer<OwnedPixels> =
+        qoi_asset!("paint");
+    pub static ref CALCULATOR_ICON: Framebuffer<OwnedPixels> =
+        qoi_asset!("calculator");
     pub static ref BLANK_ICON: Framebuffer<OwnedPixels> = Framebuffer::new_owned(32, 32);
 
     //
     // Stylesheet
 
-    pub static ref STYLESHEET: StyleSheet = StyleSheet {
-        colors: StyleSheetColors {
-            background: Color::rgb(68, 68, 68),
-            blue: Color::rgb(0, 0, 150),
-            purple: Color::rgb(100, 10, 210),
-            element: Color::rgb(100, 100, 100),
-            frame: Color::rgb(50, 50, 50),
-            green: Color::rgb(0, 180, 0),
-            hover_overlay: Color::rgba(150, 150, 150, 100),
-            selected_overlay: Color::rgb(30, 30, 30),
-            red: Color::rgb(180, 0, 0),
-            yellow: Color::rgb(180, 180, 0),
-            text: Color::WHITE,
-            accent: Color::rgb(122, 0, 255),
-            editable: Color::BLACK,
-            outline: Color::rgb(25, 25, 25),
-        },
-        margin: 2,
-        text: StyleSheetText::new(
-            "NotoSansMono",
-            TextSizes {
-                small: 12,
-                medium: 16,
-                large: 22,
-            }
-        ),
+    pub static ref STYLESHEET: StyleSheet = {
+        let palette = Palette::from_accent(Color::rgb(122, 0, 255), Color::rgb(100, 100, 100));
+        StyleSheet {
+            colors: StyleSheetColors {
+                background: Color::rgb(68, 68, 68),
+                blue: Color::rgb(0, 0, 150),
+                purple: Color::rgb(100, 10, 210),
+                element: palette.normal.bg,
+                frame: Color::rgb(50, 50, 50),
+                frame_focused: Color::rgb(70, 40, 110),
+                green: Color::rgb(0, 180, 0),
+                hover_overlay: Color::rgba(150, 150, 150, 100),
+                selected_overlay: Color::rgb(30, 30, 30),
+                red: Color::rgb(180, 0, 0),
+                yellow: Color::rgb(180, 180, 0),
+                text: Color::WHITE,
+                accent: palette.accent,
+                editable: Color::BLACK,
+                outline: Color::rgb(25, 25, 25),
+                disabled: palette.disabled.text,
+                disabled_element: palette.disabled.bg,
+                tooltip: Color::rgb(30, 30, 30),
+                focus_ring: palette.focus_ring,
+            },
+            margin: 2,
+            text: StyleSheetText::new(
+                "NotoSansMono",
+                TextSizes {
+                    small: 12,
+                    medium: 16,
+                    large: 22,
+                }
+            ),
+            tooltip_delay: 600,
+            focus_ring_width: 1,
+            focus_ring_dash: 2,
+            widgets: WidgetStyle {
+                normal: palette.normal,
+                hover: palette.hover,
+                pressed: palette.pressed,
+                selected: palette.selected,
+                disabled: palette.disabled,
+                border_width: 0,
+                border_radius: 0,
+                padding: 2,
+            },
+            titlebar: TitleBarStyle {
+                gradient: false,
+                gradient_start: Color::rgb(50, 50, 50),
+                gradient_end: Color::rgb(50, 50, 50),
+                gradient_direction: GradientDirection::Horizontal,
+                frame_alpha: 255,
+            },
+            chrome: ChromeStyle {
+                button: ChromeImage::none(),
+                panel: ChromeImage::none(),
+            },
+        }
+    };
+
+    pub static ref DARK_STYLESHEET: StyleSheet = {
+        let palette = Palette::from_accent(Color::rgb(70, 140, 255), Color::rgb(40, 40, 46));
+        StyleSheet {
+            colors: StyleSheetColors {
+                background: Color::rgb(24, 24, 28),
+                blue: Color::rgb(40, 80, 200),
+                purple: Color::rgb(120, 60, 220),
+                element: palette.normal.bg,
+                frame: Color::rgb(16, 16, 20),
+                frame_focused: Color::rgb(30, 45, 75),
+                green: Color::rgb(40, 170, 90),
+                hover_overlay: Color::rgba(255, 255, 255, 40),
+                selected_overlay: Color::rgb(50, 50, 70),
+                red: Color::rgb(200, 50, 50),
+                yellow: Color::rgb(190, 160, 40),
+                text: Color::rgb(220, 220, 225),
+                accent: palette.accent,
+                editable: Color::rgb(12, 12, 14),
+                outline: Color::rgb(60, 60, 70),
+                disabled: palette.disabled.text,
+                disabled_element: palette.disabled.bg,
+                tooltip: Color::rgb(50, 50, 58),
+                focus_ring: palette.focus_ring,
+            },
+            margin: 3,
+            text: StyleSheetText::new(
+                "NotoSansMono",
+                TextSizes {
+                    small: 12,
+                    medium: 16,
+                    large: 22,
+                }
+            ),
+            tooltip_delay: 400,
+            focus_ring_width: 2,
+            focus_ring_dash: 0,
+            widgets: WidgetStyle {
+                normal: palette.normal,
+                hover: palette.hover,
+                pressed: palette.pressed,
+                selected: palette.selected,
+                disabled: palette.disabled,
+                border_width: 1,
+                border_radius: 4,
+                padding: 4,
+            },
+            titlebar: TitleBarStyle {
+                gradient: true,
+                gradient_start: Color::rgb(35, 55, 90),
+                gradient_end: Color::rgb(16, 16, 20),
+                gradient_direction: GradientDirection::Horizontal,
+                frame_alpha: 255,
+            },
+            chrome: ChromeStyle {
+                button: ChromeImage::none(),
+                panel: ChromeImage::none(),
+            },
+        }
+    };
+
+    // The dark one with translucent decorations
+    pub static ref GLASS_STYLESHEET: StyleSheet = {
+        let mut stylesheet = DARK_STYLESHEET.clone();
+        stylesheet.titlebar.gradient = false;
+        stylesheet.titlebar.frame_alpha = 170;
+        stylesheet
     };
 
+    //
+    // Themes, the first one is the default
+
+    pub static ref THEMES: [Theme; 3] = [
+        Theme {
+            name: "Light",
+            stylesheet: &STYLESHEET,
+            backdrop: Color::rgb(40, 30, 60),
+            wallpaper_tint: None,
+        },
+        Theme {
+            name: "Dark",
+            stylesheet: &DARK_STYLESHEET,
+            backdrop: Color::rgb(10, 12, 20),
+            wallpaper_tint: Some(Color::rgba(0, 0, 20, 150)),
+        },
+        Theme {
+            name: "Glass",
+            stylesheet: &GLASS_STYLESHEET,
+            backdrop: Color::rgb(10, 12, 20),
+            wallpaper_tint: Some(Color::rgba(0, 0, 20, 80)),
+        },
+    ];
+
     //
     // WASM apps
 
-    pub static ref APPLICATIONS: [AppDescriptor; 5] = [
+    pub static ref APPLICATIONS: [AppDescriptor; 10] = [
         AppDescriptor {
             data: include_bytes!("../wasm/cube_3d.wasm"),
             name: "3D Demo",
@@ -142,5 +324,65 @@ lazy_static! {
             min_size: (200, 400),
             icon: &UI_ICON,
         },
+        AppDescriptor {
+            data: include_bytes!("../wasm/system_monitor.wasm"),
+            name: "System Monitor",
+            init_win_rect: Rect {
+                x0: 300,
+                y0: 150,
+                w: 600,
+                h: 680
+            },
+            min_size: (400, 520),
+            icon: &SPEEDOMETER_ICON,
+        },
+        AppDescriptor {
+            data: include_bytes!("../wasm/file_manager.wasm"),
+            name: "File Manager",
+            init_win_rect: Rect {
+                x0: 250,
+                y0: 200,
+                w: 700,
+                h: 450
+            },
+            min_size: (500, 300),
+            icon: &HOME_ICON,
+        },
+        AppDescriptor {
+            data: include_bytes!("../wasm/paint.wasm"),
+            name: "Paint",
+            init_win_rect: Rect {
+                x0: 150,
+                y0: 50,
+                w: 820,
+                h: 710
+            },
+            min_size: (500, 300),
+            icon: &PAINT_ICON,
+        },
+        AppDescriptor {
+            data: include_bytes!("../wasm/irc.wasm"),
+            name: "IRC",
+            init_win_rect: Rect {
+                x0: 200,
+                y0: 120,
+                w: 800,
+                h: 550
+            },
+            min_size: (600, 300),
+            icon: &NETWORK_ICON,
+        },
+        AppDescriptor {
+            data: include_bytes!("../wasm/calculator.wasm"),
+            name: "Calculator",
+            init_win_rect: Rect {
+                x0: 300,
+                y0: 150,
+                w: 560,
+                h: 420
+            },
+            min_size: (400, 300),
+            icon: &CALCULATOR_ICON,
+        },
     ];
 }
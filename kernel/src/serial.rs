@@ -1,15 +1,40 @@
 use lazy_static::lazy_static;
 use spin::Mutex;
 use uart_16550::SerialPort;
+use x86_64::instructions::port::Port;
+
+const COM1_BASE: u16 = 0x3F8;
+// Offset of the line status register, and its bit set when a byte was received
+const LINE_STATUS: u16 = 5;
+const DATA_READY: u8 = 1;
 
 lazy_static! {
     pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut port = unsafe { SerialPort::new(0x3F8) };
+        let mut port = unsafe { SerialPort::new(COM1_BASE) };
         port.init();
         Mutex::new(port)
     };
 }
 
+// Never waits, None if nothing was received
+pub fn try_read_byte() -> Option<u8> {
+    use x86_64::instructions::interrupts::without_interrupts;
+
+    without_interrupts(|| {
+        // Not used, but the port must be initialized and nothing else may use it
+        let _port = SERIAL1.lock();
+
+        let mut line_status = Port::<u8>::new(COM1_BASE + LINE_STATUS);
+        let mut data = Port::<u8>::new(COM1_BASE);
+        unsafe {
+            match line_status.read() & DATA_READY {
+                0 => None,
+                _ => Some(data.read()),
+            }
+        }
+    })
+}
+
 #[doc(hidden)]
 pub fn _print(args: core::fmt::Arguments) {
     use core::fmt::Write;
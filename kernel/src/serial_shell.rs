@@ -0,0 +1,320 @@
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use applib::shell::{Command, HELP};
+
+use crate::app::{AppState, AppsManager};
+use crate::system::System;
+use crate::{interrupts, logging, memory, serial, serial_print, serial_println};
+
+const PROMPT: &str = "> ";
+// Typing more is ignored
+const MAX_LINE_LEN: usize = 256;
+
+// What the main loop has to do itself
+pub enum ShellAction {
+    Screenshot,
+    Reboot,
+}
+
+// Interactive shell on the serial port, for when nothing can be seen on screen
+pub struct SerialShell {
+    line: String,
+    // Typed ahead, run one per frame
+    pending: VecDeque<String>,
+    // "\r\n" only ends one line
+    last_was_cr: bool,
+}
+
+impl SerialShell {
+    pub fn new() -> Self {
+        serial_println!("Serial shell ready, type help for the commands");
+        serial_print!("{}", PROMPT);
+        SerialShell {
+            line: String::new(),
+            pending: VecDeque::new(),
+            last_was_cr: false,
+        }
+    }
+
+    // Drains what was typed without waiting for more, then runs the next complete line
+    pub fn poll(
+        &mut self,
+        system: &mut System,
+        apps_manager: &mut AppsManager,
+    ) -> Option<ShellAction> {
+        while let Some(byte) = serial::try_read_byte() {
+            self.input(byte);
+        }
+
+        let line = self.pending.pop_front()?;
+        let action = match Command::parse(&line) {
+            Some(command) => run(command, system, apps_manager),
+            None if line.trim().is_empty() => None,
+            None => {
+                serial_println!("Unknown command or wrong arguments, type help for the commands");
+                None
+            }
+        };
+        serial_print!("{}", PROMPT);
+
+        action
+    }
+
+    fn input(&mut self, byte: u8) {
+        let last_was_cr = core::mem::replace(&mut self.last_was_cr, byte == b'\r');
+        match byte {
+            b'\n' if last_was_cr => (),
+            b'\r' | b'\n' => {
+                serial_println!();
+                self.pending.push_back(core::mem::take(&mut self.line));
+            }
+            // Backspace or DEL, depending on the terminal
+            0x08 | 0x7f => {
+                if self.line.pop().is_some() {
+                    serial_print!("\x08 \x08");
+                }
+            }
+            0x20..=0x7e if self.line.len() < MAX_LINE_LEN => {
+                self.line.push(byte as char);
+                serial_print!("{}", byte as char);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn run(
+    command: Command,
+    system: &mut System,
+    apps_manager: &mut AppsManager,
+) -> Option<ShellAction> {
+    match command {
+        Command::Help => serial_println!("{}", HELP),
+        Command::Ps => print_apps(system, apps_manager),
+        Command::Mem => print_memory(),
+        Command::Net => {
+            for line in system.tcp_stack.dump() {
+                serial_println!("{}", line);
+            }
+        }
+        Command::Irq => print_interrupts(system),
+        Command::IrqReset => {
+            interrupts::reset_cursor_latency();
+            serial_println!("Latency measurement restarted");
+        }
+        Command::Files => print_files(system),
+        Command::Remove(name) => match system.storage.delete(&name) {
+            Ok(()) => serial_println!("Deleted {}", name),
+            Err(err) => serial_println!("Cannot delete {}: {:?}", name, err),
+        },
+        Command::Rename { name, new_name } => match system.storage.rename(&name, &new_name) {
+            Ok(()) => serial_println!("Renamed {} to {}", name, new_name),
+            Err(err) => serial_println!("Cannot rename {}: {:?}", name, err),
+        },
+        Command::Kill(app_name) => match apps_manager.close(&app_name, system) {
+            true => serial_println!("Closed {}", app_name),
+            false => serial_println!("No app named {}", app_name),
+        },
+        Command::LogLevels => print_log_levels(),
+        Command::LogLevel { target, level } => {
+            match (target.as_str(), level) {
+                ("default", Some(level)) => logging::set_default_level(level),
+                ("default", None) => serial_println!("The default level cannot be reset"),
+                (target, level) => logging::set_target_level(target, level),
+            }
+            print_log_levels();
+        }
+        Command::Capture => print_capture(system),
+        Command::CaptureStart => {
+            system.tcp_stack.capture_mut().start();
+            print_capture(system);
+        }
+        Command::CaptureStop => {
+            system.tcp_stack.capture_mut().stop();
+            print_capture(system);
+        }
+        Command::CaptureSnaplen(snaplen) => {
+            system.tcp_stack.capture_mut().set_snaplen(snaplen);
+            print_capture(system);
+        }
+        Command::Screenshot => return Some(ShellAction::Screenshot),
+        Command::Reboot => return Some(ShellAction::Reboot),
+    }
+
+    None
+}
+
+fn print_apps(system: &System, apps_manager: &AppsManager) {
+    let mut apps: Vec<_> = apps_manager.apps().collect();
+    apps.sort_by_key(|app| app.descriptor.name);
+
+    serial_println!(
+        "{:<20} {:<8} {:<10} {:>10} {:>10} {:>12}",
+        "NAME",
+        "STATE",
+        "WINDOW",
+        "MEMORY",
+        "FRAMETIME",
+        "NET RECV/SENT"
+    );
+
+    for app in apps {
+        let state = match &app.app_state {
+            AppState::Init => "-",
+            AppState::Active { paused: true, .. } => "paused",
+            AppState::Active { .. } => "running",
+            AppState::Crashed { .. } => "crashed",
+        };
+        let window = match (app.is_open, app.minimized) {
+            (false, _) => "closed".to_string(),
+            (true, true) => "minimized".to_string(),
+            (true, false) => format!("workspace {}", app.workspace + 1),
+        };
+
+        let point = system
+            .stats
+            .get_last_app_points()
+            .find(|(app_name, _)| *app_name == app.descriptor.name)
+            .map(|(_, point)| point);
+
+        match point.filter(|point| point.running) {
+            Some(point) => serial_println!(
+                "{:<20} {:<8} {:<10} {:>7} kB {:>7.2} ms {:>5}/{:<5} B",
+                app.descriptor.name,
+                state,
+                window,
+                point.mem_used / 1000,
+                point.frametime_used,
+                point.net_recv,
+                point.net_sent
+            ),
+            None => serial_println!("{:<20} {:<8} {:<10}", app.descriptor.name, state, window),
+        }
+    }
+}
+
+fn print_memory() {
+    let stats = memory::stats();
+    let heap = &stats.heap;
+
+    serial_println!(
+        "total       {:>10} kB after {} grows",
+        heap.total / 1000,
+        heap.nb_grows
+    );
+    serial_println!("reserve     {:>10} kB", heap.reserve / 1000);
+    serial_println!(
+        "allocated   {:>10} kB in {} blocks",
+        heap.allocated / 1000,
+        heap.nb_allocs
+    );
+    serial_println!("peak        {:>10} kB", heap.peak / 1000);
+    serial_println!("explored    {:>10} kB", heap.explored / 1000);
+    serial_println!("reclaimable {:>10} kB", heap.reclaimable / 1000);
+    serial_println!("lost        {:>10} kB", heap.lost / 1000);
+    serial_println!("fragmentation {:.1}%", heap.fragmentation() * 100.0);
+
+    serial_println!();
+    serial_println!("{:>10} {:>10} {:>10}", "BLOCK", "LIVE", "FREE");
+    let size_classes = heap.size_classes.iter().zip(heap.free_size_classes.iter());
+    for (i, (live, free)) in size_classes.enumerate() {
+        if *live > 0 || *free > 0 {
+            serial_println!("{:>8} B {:>10} {:>10}", 1usize << i, live, free);
+        }
+    }
+
+    // Since boot
+    serial_println!();
+    serial_println!(
+        "{:<20} {:>12} {:>12} {:>10}",
+        "TAG",
+        "ALLOCATED",
+        "FREED",
+        "COUNT"
+    );
+    for (tag, tag_stats) in stats.by_tag {
+        serial_println!(
+            "{:<20} {:>9} kB {:>9} kB {:>10}",
+            tag,
+            tag_stats.allocated / 1000,
+            tag_stats.freed / 1000,
+            tag_stats.nb_allocs
+        );
+    }
+}
+
+fn print_interrupts(system: &System) {
+    match interrupts::armed() {
+        true => serial_println!("drivers wait for interrupts"),
+        false => serial_println!("drivers poll every frame"),
+    }
+
+    serial_println!("{:>6} {:<24} {:>10}", "VECTOR", "DEVICE", "COUNT");
+    for (vector, name, count) in interrupts::vectors() {
+        serial_println!("{:>#6x} {:<24} {:>10}", vector, name, count);
+    }
+    serial_println!("timer ticks {}", interrupts::timer_ticks());
+    serial_println!("unexpected  {}", interrupts::unexpected_count());
+
+    // From the input interrupt to the GPU cursor being moved
+    serial_println!();
+    let ms_per_cycle = 1000.0 * system.clock.cycle_period();
+    match interrupts::cursor_latency() {
+        Some((samples, mean, max)) => serial_println!(
+            "input-to-cursor latency: mean {:.3} ms, max {:.3} ms over {} moves",
+            mean as f64 * ms_per_cycle,
+            max as f64 * ms_per_cycle,
+            samples
+        ),
+        None => serial_println!("input-to-cursor latency: no cursor move timed yet"),
+    }
+}
+
+fn print_files(system: &System) {
+    let storage = &system.storage;
+
+    serial_println!("{:<40} {:>10}", "NAME", "SIZE");
+    for name in storage.list() {
+        if let Ok((size, _)) = storage.stat(name) {
+            serial_println!("{:<40} {:>8} B", name, size);
+        }
+    }
+
+    serial_println!();
+    serial_println!(
+        "{} of {} kB used",
+        storage.used() / 1000,
+        storage.quota() / 1000
+    );
+    match storage.disk_space() {
+        Some((free, total)) => {
+            serial_println!("disk: {} of {} kB free", free / 1000, total / 1000)
+        }
+        None => serial_println!("no disk, files are lost at shutdown"),
+    }
+}
+
+fn print_capture(system: &System) {
+    let status = system.tcp_stack.capture().status();
+    let state = match status.enabled {
+        true => "running",
+        false => "stopped",
+    };
+    serial_println!("capture {}, snaplen {} B", state, status.snaplen);
+    serial_println!(
+        "{} records, {} dropped when the ring was full, {} kB buffered",
+        status.nb_records,
+        status.nb_dropped,
+        status.buffered / 1000
+    );
+}
+
+fn print_log_levels() {
+    let (default_level, target_levels) = logging::levels();
+    serial_println!("default: {}", default_level);
+    for (target, level) in target_levels {
+        serial_println!("{}: {}", target, level);
+    }
+}
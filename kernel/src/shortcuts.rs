@@ -0,0 +1,141 @@
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use applib::input::{InputEvent, InputState, Keycode};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShortcutAction {
+    CloseWindow,
+    // Minimizes all windows, and brings them back the second time
+    ShowDesktop,
+    // Focuses or opens the Nth app of the launcher menu, from 0
+    FocusApp(usize),
+    Screenshot,
+    Lock,
+    // Frame rate and timings, see PerfOverlay
+    ToggleOverlay,
+    // To the previous (-1) or next (1) workspace, the focused window along with it for
+    // MoveToWorkspace
+    SwitchWorkspace(isize),
+    MoveToWorkspace(isize),
+    // Windows of the active workspace side by side, see overview::layout()
+    Overview,
+}
+
+// The modifiers must be held exactly, e.g. Alt+F4 does not fire with Ctrl+Alt+F4
+#[derive(Debug, Clone, Copy)]
+pub struct KeyBinding {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub meta: bool,
+    pub key: Keycode,
+    pub action: ShortcutAction,
+}
+
+pub fn default_keybindings() -> Vec<KeyBinding> {
+    let binding = |alt: bool, meta: bool, key: Keycode, action: ShortcutAction| KeyBinding {
+        ctrl: false,
+        shift: false,
+        alt,
+        meta,
+        key,
+        action,
+    };
+
+    let mut bindings = Vec::from([
+        binding(true, false, Keycode::KEY_F4, ShortcutAction::CloseWindow),
+        binding(false, true, Keycode::KEY_D, ShortcutAction::ShowDesktop),
+        binding(false, false, Keycode::KEY_SYSRQ, ShortcutAction::Screenshot),
+        binding(false, true, Keycode::KEY_L, ShortcutAction::Lock),
+        binding(false, true, Keycode::KEY_F12, ShortcutAction::ToggleOverlay),
+        binding(false, true, Keycode::KEY_TAB, ShortcutAction::Overview),
+    ]);
+
+    for (key, offset) in [(Keycode::KEY_LEFT, -1), (Keycode::KEY_RIGHT, 1)] {
+        let action = ShortcutAction::SwitchWorkspace(offset);
+        bindings.push(binding(false, true, key, action));
+        bindings.push(KeyBinding {
+            shift: true,
+            ..binding(false, true, key, ShortcutAction::MoveToWorkspace(offset))
+        });
+    }
+
+    let digits = [
+        Keycode::KEY_1,
+        Keycode::KEY_2,
+        Keycode::KEY_3,
+        Keycode::KEY_4,
+        Keycode::KEY_5,
+        Keycode::KEY_6,
+        Keycode::KEY_7,
+        Keycode::KEY_8,
+        Keycode::KEY_9,
+    ];
+    for (i, key) in digits.into_iter().enumerate() {
+        bindings.push(binding(false, true, key, ShortcutAction::FocusApp(i)));
+    }
+
+    bindings
+}
+
+// Takes the kernel shortcuts out of the input, before apps get it
+pub struct ShortcutFilter {
+    // Not tracked by InputState, apps never get the Super key
+    meta: bool,
+    // Keys which fired a shortcut, their release is dropped too
+    swallowed: BTreeSet<Keycode>,
+}
+
+impl ShortcutFilter {
+    pub fn new() -> Self {
+        ShortcutFilter {
+            meta: false,
+            swallowed: BTreeSet::new(),
+        }
+    }
+
+    pub fn intercept(
+        &mut self,
+        input_state: &mut InputState,
+        bindings: &[KeyBinding],
+    ) -> Vec<ShortcutAction> {
+        let (ctrl, shift, alt) = (input_state.ctrl, input_state.shift, input_state.alt);
+        let is_meta = |keycode: Keycode| {
+            keycode == Keycode::KEY_LEFTMETA || keycode == Keycode::KEY_RIGHTMETA
+        };
+
+        let mut actions = Vec::new();
+
+        input_state.retain_events(|event| match *event {
+            InputEvent::KeyPress { keycode } if is_meta(keycode) => {
+                self.meta = true;
+                false
+            }
+            InputEvent::KeyRelease { keycode } if is_meta(keycode) => {
+                self.meta = false;
+                false
+            }
+            InputEvent::KeyPress { keycode } => {
+                let binding = bindings.iter().find(|binding| {
+                    binding.key == keycode
+                        && binding.ctrl == ctrl
+                        && binding.shift == shift
+                        && binding.alt == alt
+                        && binding.meta == self.meta
+                });
+                match binding {
+                    Some(binding) => {
+                        actions.push(binding.action);
+                        self.swallowed.insert(keycode);
+                        false
+                    }
+                    None => true,
+                }
+            }
+            InputEvent::KeyRelease { keycode } => !self.swallowed.remove(&keycode),
+            _ => true,
+        });
+
+        actions
+    }
+}
@@ -0,0 +1,253 @@
+use core::net::Ipv4Addr;
+use core::str::FromStr;
+use smoltcp::wire::Ipv4Address;
+
+use crate::network::{DnsHandle, TcpStack, UdpHandle};
+use crate::time::SystemClock;
+
+const NTP_PORT: u16 = 123;
+const NTP_PACKET_SIZE: usize = 48;
+// From 1900, the NTP epoch, to 1970
+const NTP_UNIX_OFFSET: f64 = 2_208_988_800.0;
+// In ms, for the name lookup and the answer together
+const ATTEMPT_TIMEOUT: f64 = 10_000.0;
+// In ms after the first failure, doubled after each one in a row up to the sync interval
+const RETRY_DELAY: f64 = 15_000.0;
+
+// Keeps the wall clock of SystemClock in sync with a time server: queried at boot then at
+// every interval, and again with a backoff after failures. Polled from the main loop, it
+// never waits.
+pub struct SntpClient {
+    // A name or an IPv4 address
+    server: &'static str,
+    // In ms
+    interval: f64,
+    state: SntpState,
+    // In ms of SystemClock::time(), once Idle
+    next_attempt: f64,
+    attempt_start: f64,
+    // Since the last sync
+    nb_failures: u32,
+}
+
+enum SntpState {
+    Idle,
+    Resolving(DnsHandle),
+    Querying(Query),
+}
+
+struct Query {
+    socket: UdpHandle,
+    addr: Ipv4Address,
+    // Our transmit timestamp, the server sends it back as its originate one
+    sent: u64,
+    // UNIX time it was sent at
+    t1: f64,
+}
+
+impl SntpClient {
+    pub fn new(server: &'static str, interval: f64) -> Self {
+        log::info!(
+            "Syncing the wall clock to {} every {:.0}h",
+            server,
+            interval / 3_600_000.0
+        );
+        SntpClient {
+            server,
+            interval,
+            state: SntpState::Idle,
+            next_attempt: 0.0,
+            attempt_start: 0.0,
+            nb_failures: 0,
+        }
+    }
+
+    pub fn poll(&mut self, clock: &mut SystemClock, tcp_stack: &mut TcpStack) {
+        let time = clock.time();
+
+        let result = match self.state {
+            SntpState::Idle if time < self.next_attempt => return,
+            SntpState::Idle => {
+                self.attempt_start = time;
+                self.step(clock, tcp_stack)
+            }
+            _ if time - self.attempt_start > ATTEMPT_TIMEOUT => {
+                Err(anyhow::format_err!("No answer"))
+            }
+            _ => self.step(clock, tcp_stack),
+        };
+
+        match result {
+            Ok(false) => (),
+            Ok(true) => {
+                self.nb_failures = 0;
+                self.next_attempt = time + self.interval;
+            }
+            Err(err) => {
+                self.abort(tcp_stack);
+                let backoff = (1u32 << self.nb_failures.min(16)) as f64;
+                let delay = f64::min(RETRY_DELAY * backoff, self.interval);
+                self.nb_failures += 1;
+                self.next_attempt = time + delay;
+                log::warn!(
+                    "Time sync with {} failed: {}, retrying in {:.0}s",
+                    self.server,
+                    err,
+                    delay / 1000.0
+                );
+            }
+        }
+    }
+
+    // Moves the attempt forward, true once the clock was adjusted
+    fn step(&mut self, clock: &mut SystemClock, tcp_stack: &mut TcpStack) -> anyhow::Result<bool> {
+        match core::mem::replace(&mut self.state, SntpState::Idle) {
+            SntpState::Idle => {
+                self.state = match Ipv4Addr::from_str(self.server) {
+                    Ok(addr) => self.send_request(clock, tcp_stack, Ipv4Address(addr.octets()))?,
+                    Err(_) => SntpState::Resolving(tcp_stack.dns_query(self.server)?),
+                };
+                Ok(false)
+            }
+            SntpState::Resolving(handle) => match tcp_stack.dns_result(handle) {
+                None => {
+                    self.state = SntpState::Resolving(handle);
+                    Ok(false)
+                }
+                Some(addr) => {
+                    self.state = self.send_request(clock, tcp_stack, addr?)?;
+                    Ok(false)
+                }
+            },
+            SntpState::Querying(query) => {
+                let mut buf = [0u8; NTP_PACKET_SIZE];
+                let Some(len) = tcp_stack.udp_recv(query.socket, &mut buf) else {
+                    self.state = SntpState::Querying(query);
+                    return Ok(false);
+                };
+                let t4 = clock.unix_time();
+
+                // Late answers to an earlier request, or not from the server
+                let packet = &buf[..len];
+                if len < NTP_PACKET_SIZE || read_timestamp(packet, 24) != query.sent {
+                    log::debug!("Unexpected NTP packet from {}", query.addr);
+                    self.state = SntpState::Querying(query);
+                    return Ok(false);
+                }
+                tcp_stack.udp_close(query.socket);
+
+                let offset = query.offset(packet, t4)?;
+                let sync = clock.adjust(offset);
+                let correction = match sync.stepped {
+                    true => "stepped",
+                    false => "slewed",
+                };
+                log::info!(
+                    "Wall clock synced to {} ({}), offset {:.6}s {}",
+                    self.server,
+                    query.addr,
+                    offset,
+                    correction
+                );
+                if let Some(drift_ppm) = sync.drift_ppm {
+                    log::info!("TSC drift estimated to {:.2} ppm", drift_ppm);
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn send_request(
+        &self,
+        clock: &SystemClock,
+        tcp_stack: &mut TcpStack,
+        addr: Ipv4Address,
+    ) -> anyhow::Result<SntpState> {
+        let socket = tcp_stack.udp_open(addr)?;
+
+        // Version 4, client mode, everything else left to 0
+        let mut packet = [0u8; NTP_PACKET_SIZE];
+        packet[0] = (4 << 3) | 3;
+        let t1 = clock.unix_time();
+        let sent = to_ntp_timestamp(t1);
+        packet[40..48].copy_from_slice(&sent.to_be_bytes());
+
+        if let Err(err) = tcp_stack.udp_send(socket, addr, NTP_PORT, &packet) {
+            tcp_stack.udp_close(socket);
+            return Err(err);
+        }
+
+        Ok(SntpState::Querying(Query {
+            socket,
+            addr,
+            sent,
+            t1,
+        }))
+    }
+
+    // Frees what the attempt had open
+    fn abort(&mut self, tcp_stack: &mut TcpStack) {
+        match core::mem::replace(&mut self.state, SntpState::Idle) {
+            SntpState::Idle => (),
+            SntpState::Resolving(handle) => tcp_stack.dns_cancel(handle),
+            SntpState::Querying(query) => tcp_stack.udp_close(query.socket),
+        }
+    }
+}
+
+impl Query {
+    // Of our clock against the server's, from the four timestamps: sent (t1), received by
+    // the server (t2), sent back (t3) and received (t4). Half of the round trip is assumed
+    // each way.
+    fn offset(&self, packet: &[u8], t4: f64) -> anyhow::Result<f64> {
+        let leap_indicator = packet[0] >> 6;
+        let mode = packet[0] & 0x7;
+        let stratum = packet[1];
+        if mode != 4 {
+            anyhow::bail!("Not a server answer (mode {})", mode);
+        }
+        // Kiss-o'-death, e.g. RATE when queried too often
+        if stratum == 0 {
+            let code = core::str::from_utf8(&packet[12..16]).unwrap_or("?");
+            anyhow::bail!("Server refused ({})", code);
+        }
+        if leap_indicator == 3 || stratum >= 16 {
+            anyhow::bail!("Server not synchronized");
+        }
+
+        if read_timestamp(packet, 40) == 0 {
+            anyhow::bail!("No transmit timestamp");
+        }
+        let t2 = from_ntp_timestamp(read_timestamp(packet, 32));
+        let t3 = from_ntp_timestamp(read_timestamp(packet, 40));
+
+        let delay = (t4 - self.t1) - (t3 - t2);
+        log::debug!("NTP round trip {:.3}ms", delay * 1000.0);
+
+        Ok(((t2 - self.t1) + (t3 - t4)) / 2.0)
+    }
+}
+
+fn read_timestamp(packet: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&packet[offset..offset + 8]);
+    u64::from_be_bytes(bytes)
+}
+
+// Seconds since 1900 in the upper 32 bits, the fraction in the lower ones
+fn to_ntp_timestamp(unix_time: f64) -> u64 {
+    let t = unix_time + NTP_UNIX_OFFSET;
+    let secs = t as u64;
+    let frac = ((t - secs as f64) * 4_294_967_296.0) as u64;
+    (secs << 32) | (frac & 0xffff_ffff)
+}
+
+fn from_ntp_timestamp(timestamp: u64) -> f64 {
+    let mut secs = (timestamp >> 32) as f64;
+    // The seconds wrap around in 2036, earlier values are from the next era
+    if timestamp >> 63 == 0 {
+        secs += 4_294_967_296.0;
+    }
+    let frac = (timestamp & 0xffff_ffff) as f64 / 4_294_967_296.0;
+    secs + frac - NTP_UNIX_OFFSET
+}
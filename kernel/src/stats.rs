@@ -1,5 +1,6 @@
 use crate::allocator::AllocStats;
 use alloc::collections::BTreeMap;
+use alloc::string::String;
 
 const HISTORY_SIZE: usize = 256; // In number of frames
 
@@ -9,6 +10,9 @@ pub struct SystemStats {
     by_app: BTreeMap<&'static str, [AppDataPoint; HISTORY_SIZE]>,
     system: [SystemDataPoint; HISTORY_SIZE],
 
+    // Fuel consumed per timing key during the last step of each app
+    timings_by_app: BTreeMap<&'static str, BTreeMap<String, u64>>,
+
     ring_index: usize,
 }
 
@@ -18,13 +22,32 @@ pub struct SystemDataPoint {
     pub net_sent: usize,
     pub alloc: AllocStats,
     pub frametime_used: f64,
+    // Part of frametime_used spent polling the network interface
+    pub netpoll_used: f64,
+    // Since the start of the previous frame, including the wait for the FPS limit
+    pub frame_interval: f64,
+    // Drawn over this frame
+    pub damaged_pixels: usize,
+    // Uploaded to the GPU
+    pub flushed_pixels: usize,
+    // False when nothing changed and the frame was skipped
+    pub composited: bool,
+    // Out of the shown windows, those outside of the damage or behind opaque ones
+    pub windows_drawn: usize,
+    pub windows_skipped: usize,
+    // Virtqueue notifications written to the devices, and those skipped as not needed
+    pub virtio_notified: usize,
+    pub virtio_suppressed: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct AppDataPoint {
+    pub running: bool,
     pub net_recv: usize,
     pub net_sent: usize,
     pub mem_used: usize,
+    // Kernel heap bytes allocated for the app during its step
+    pub kernel_alloc: usize,
     pub frametime_used: f64,
 }
 
@@ -34,12 +57,7 @@ impl SystemStats {
             .iter()
             .map(|app_name| {
                 let app_history: [AppDataPoint; HISTORY_SIZE] =
-                    core::array::from_fn(|_| AppDataPoint {
-                        net_recv: 0,
-                        net_sent: 0,
-                        mem_used: 0,
-                        frametime_used: 0.0,
-                    });
+                    core::array::from_fn(|_| AppDataPoint::default());
 
                 (*app_name, app_history)
             })
@@ -51,12 +69,23 @@ impl SystemStats {
                 net_sent: 0,
                 alloc: alloc_stats.clone(),
                 frametime_used: 0.0,
+                netpoll_used: 0.0,
+                frame_interval: 0.0,
+                damaged_pixels: 0,
+                flushed_pixels: 0,
+                composited: true,
+                windows_drawn: 0,
+                windows_skipped: 0,
+                virtio_notified: 0,
+                virtio_suppressed: 0,
             });
 
         SystemStats {
-            heap_total: alloc_stats.total,
+            // What the heap can grow to
+            heap_total: alloc_stats.total + alloc_stats.reserve,
             by_app,
             system: system_history,
+            timings_by_app: BTreeMap::new(),
             ring_index: 0,
         }
     }
@@ -74,6 +103,41 @@ impl SystemStats {
         app_history.get_mut(self.ring_index).unwrap()
     }
 
+    // For an app that was closed, it starts from scratch when opened again
+    pub fn clear_app(&mut self, app_name: &str) {
+        let app_history = self.by_app.get_mut(app_name).expect("Unknown app");
+        app_history.fill(AppDataPoint::default());
+        self.timings_by_app.remove(app_name);
+    }
+
+    pub fn has_app(&self, app_name: &str) -> bool {
+        self.by_app.contains_key(app_name)
+    }
+
+    // The current frame is still being filled, so these return the previous one
+
+    pub fn get_last_system_point(&self) -> &SystemDataPoint {
+        get_history_point(&self.system, self.ring_index, 1)
+    }
+
+    pub fn get_last_app_points(&self) -> impl Iterator<Item = (&'static str, &AppDataPoint)> {
+        self.by_app.iter().map(|(app_name, app_history)| {
+            (
+                *app_name,
+                get_history_point(app_history, self.ring_index, 1),
+            )
+        })
+    }
+
+    pub fn set_app_timings(&mut self, app_name: &str, timings: BTreeMap<String, u64>) {
+        let (app_name, _) = self.by_app.get_key_value(app_name).expect("Unknown app");
+        self.timings_by_app.insert(*app_name, timings);
+    }
+
+    pub fn get_app_timings(&self, app_name: &str) -> Option<&BTreeMap<String, u64>> {
+        self.timings_by_app.get(app_name)
+    }
+
     pub fn get_system_history<T, F>(&self, selector: F) -> [T; HISTORY_SIZE]
     where
         F: Fn(&SystemDataPoint) -> T,
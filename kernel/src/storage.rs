@@ -0,0 +1,250 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::block::BlockDevice;
+use crate::fat32::{self, Fat32, FsError};
+
+pub const STORAGE_QUOTA: usize = 1_000_000;
+// On the disk, files are kept in that directory
+const STORAGE_DIR: &str = "storage";
+// Files of the kernel itself (lock screen passphrase, autostart list) are named with that
+// prefix, apps can neither see nor touch them
+pub const KERNEL_PREFIX: &str = "kernel.";
+
+pub struct Storage {
+    files: BTreeMap<String, StoredFile>,
+    quota: usize,
+    // Files are loaded from it at boot and written through to it. None if there is
+    // no disk or it cannot be mounted, files are then lost at shutdown.
+    volume: Option<Fat32>,
+}
+
+struct StoredFile {
+    data: Vec<u8>,
+    modified: f64, // UNIX time in ms
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageError {
+    NotFound,
+    InvalidName,
+    QuotaExceeded,
+    Disk(FsError),
+}
+
+impl StorageError {
+    pub fn as_code(&self) -> i32 {
+        match self {
+            StorageError::NotFound => -1,
+            StorageError::InvalidName => -2,
+            StorageError::QuotaExceeded => -3,
+            StorageError::Disk(_) => -4,
+        }
+    }
+}
+
+impl From<FsError> for StorageError {
+    fn from(err: FsError) -> Self {
+        match err {
+            FsError::NotFound => StorageError::NotFound,
+            FsError::InvalidName => StorageError::InvalidName,
+            err => StorageError::Disk(err),
+        }
+    }
+}
+
+impl Storage {
+    pub fn new(quota: usize, device: Option<Box<dyn BlockDevice>>, time: f64) -> Self {
+        let volume = match device.map(Fat32::mount) {
+            Some(Ok(volume)) => Some(volume),
+            Some(Err(err)) => {
+                log::error!("Cannot mount the disk: {:?}", err);
+                None
+            }
+            None => None,
+        };
+
+        let mut storage = Storage {
+            files: BTreeMap::new(),
+            quota,
+            volume,
+        };
+
+        if let Err(err) = storage.load(time) {
+            log::error!("Cannot load files from the disk: {:?}", err);
+            storage.files.clear();
+            storage.volume = None;
+        }
+        if storage.volume.is_none() {
+            log::warn!("Files are kept in memory only and lost at shutdown");
+        }
+
+        storage
+    }
+
+    fn load(&mut self, time: f64) -> Result<(), FsError> {
+        let Some(volume) = self.volume.as_mut() else {
+            return Ok(());
+        };
+
+        let entries = match volume.list_dir(STORAGE_DIR) {
+            Ok(entries) => entries,
+            Err(FsError::NotFound) => {
+                volume.create_dir(STORAGE_DIR, time)?;
+                Vec::new()
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut used = 0;
+        for entry in entries.into_iter().filter(|entry| !entry.is_dir) {
+            // Left on the disk
+            if used + entry.size as usize > self.quota {
+                log::warn!(
+                    "{} does not fit in the storage quota, skipping it",
+                    entry.name
+                );
+                continue;
+            }
+            let data = volume.read_file(&storage_path(&entry.name))?;
+            used += data.len();
+            let file = StoredFile {
+                data,
+                modified: entry.modified,
+            };
+            self.files.insert(entry.name, file);
+        }
+
+        log::info!("Loaded {} files from the disk", self.files.len());
+
+        Ok(())
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(|name| name.as_str())
+    }
+
+    pub fn read(&self, name: &str) -> Result<&[u8], StorageError> {
+        self.files
+            .get(name)
+            .map(|file| file.data.as_slice())
+            .ok_or(StorageError::NotFound)
+    }
+
+    // Returns the size and modification time of a file
+    pub fn stat(&self, name: &str) -> Result<(usize, f64), StorageError> {
+        self.files
+            .get(name)
+            .map(|file| (file.data.len(), file.modified))
+            .ok_or(StorageError::NotFound)
+    }
+
+    pub fn write(&mut self, name: &str, data: &[u8], time: f64) -> Result<(), StorageError> {
+        // Whether there is a disk or not, for files to behave the same
+        if !fat32::is_valid_name(name) {
+            return Err(StorageError::InvalidName);
+        }
+
+        let prev_size = self
+            .files
+            .get(name)
+            .map(|file| file.data.len())
+            .unwrap_or(0);
+        let new_used = self.used() - prev_size + data.len();
+
+        if new_used > self.quota {
+            return Err(StorageError::QuotaExceeded);
+        }
+
+        if let Some(volume) = self.volume.as_mut() {
+            volume.write_file(&storage_path(name), data, time)?;
+            // Names differing only in case are the same file on the disk
+            self.files
+                .retain(|other, _| other == name || !other.eq_ignore_ascii_case(name));
+        }
+
+        let file = StoredFile {
+            data: data.to_vec(),
+            modified: time,
+        };
+        self.files.insert(name.into(), file);
+
+        Ok(())
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<(), StorageError> {
+        if !self.files.contains_key(name) {
+            return Err(StorageError::NotFound);
+        }
+        if let Some(volume) = self.volume.as_mut() {
+            volume.delete(&storage_path(name))?;
+        }
+
+        self.files
+            .remove(name)
+            .map(|_| ())
+            .ok_or(StorageError::NotFound)
+    }
+
+    // Replaces the file already named `new_name`, if any
+    pub fn rename(&mut self, name: &str, new_name: &str) -> Result<(), StorageError> {
+        if !fat32::is_valid_name(new_name) {
+            return Err(StorageError::InvalidName);
+        }
+        if !self.files.contains_key(name) {
+            return Err(StorageError::NotFound);
+        }
+        if new_name == name {
+            return Ok(());
+        }
+
+        if let Some(volume) = self.volume.as_mut() {
+            // Names differing only in case are the same file on the disk
+            let replaced: Vec<String> = self
+                .files
+                .keys()
+                .filter(|other| other.eq_ignore_ascii_case(new_name))
+                .filter(|other| !other.eq_ignore_ascii_case(name))
+                .cloned()
+                .collect();
+            for other in replaced {
+                volume.delete(&storage_path(&other))?;
+                self.files.remove(&other);
+            }
+            volume.rename(&storage_path(name), new_name)?;
+        }
+
+        let file = self.files.remove(name).unwrap();
+        self.files.insert(new_name.into(), file);
+
+        Ok(())
+    }
+
+    // Free and total bytes, None without a disk
+    pub fn disk_space(&self) -> Option<(u64, u64)> {
+        self.volume
+            .as_ref()
+            .map(|volume| (volume.free_space(), volume.total_space()))
+    }
+
+    pub fn used(&self) -> usize {
+        self.files.values().map(|file| file.data.len()).sum()
+    }
+
+    pub fn quota(&self) -> usize {
+        self.quota
+    }
+}
+
+// Without regard to case, like names on the disk
+pub fn is_kernel_file(name: &str) -> bool {
+    name.get(..KERNEL_PREFIX.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(KERNEL_PREFIX))
+}
+
+fn storage_path(name: &str) -> String {
+    format!("{}/{}", STORAGE_DIR, name)
+}
@@ -1,12 +1,30 @@
+use crate::random::SystemRng;
+use crate::resources::Theme;
+use crate::shortcuts::KeyBinding;
 use crate::stats::SystemStats;
+use crate::storage::Storage;
+use crate::wallpaper::Wallpaper;
 use crate::{network::TcpStack, time::SystemClock};
-use applib::StyleSheet;
-use rand::rngs::SmallRng;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 pub struct System {
     pub clock: SystemClock,
     pub tcp_stack: TcpStack,
-    pub rng: SmallRng,
-    pub stylesheet: &'static StyleSheet,
+    pub rng: SystemRng,
+    // Can be switched at runtime, apps see the new stylesheet on their next step
+    pub theme: &'static Theme,
+    pub wallpaper: Wallpaper,
+    // Windows without the focus have their content faded
+    pub shade_unfocused: bool,
+    // Offered by the GPU, its preferred one first
+    pub display_modes: Vec<(u32, u32)>,
+    // Switched to by the main loop before the next frame
+    pub requested_mode: Option<(u32, u32)>,
     pub stats: SystemStats,
+    pub storage: Storage,
+    // Shared by all apps
+    pub clipboard: String,
+    // Shortcuts handled by the kernel, whichever app has the focus
+    pub keybindings: Vec<KeyBinding>,
 }
@@ -0,0 +1,562 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use applib::drawing::primitives::{draw_rect, draw_rect_outline};
+use applib::drawing::text::{draw_line_in_rect, ellipsize_text, Font, TextJustification};
+use applib::{Color, FbView, FbViewMut, Framebuffer, OwnedPixels, Rect, StyleSheet};
+
+use crate::app::AppDescriptor;
+use crate::resources::{self, APPLICATIONS, THEMES, WALLPAPERS};
+use crate::tray::{Tray, TrayItem, TrayStatus};
+use crate::wallpaper::{ScalingMode, SCALING_MODES};
+use crate::{TASKBAR_H, TOPBAR_H};
+
+const LAUNCHER_W: u32 = 56;
+const WORKSPACE_BUTTON_W: u32 = 28;
+const APP_BUTTON_W: u32 = 180;
+const BUTTON_GAP: u32 = 4;
+// Tray indicators, the quick settings button is at its end
+const STATUS_AREA_W: u32 = 240;
+const QUICK_SETTINGS_W: u32 = 80;
+const LAUNCHER_MENU_W: u32 = 240;
+const QUICK_SETTINGS_MENU_W: u32 = 160;
+const MENU_ROW_H: u32 = 40;
+const QUICK_SETTINGS_ROW_H: u32 = 28;
+const ICON_MARGIN_W: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskbarItem {
+    Launcher,
+    Workspace(usize),
+    App(&'static str),
+    QuickSettings,
+    Tray(TrayItem),
+}
+
+// In the workspace indicator, next to the launcher
+pub struct WorkspaceEntry {
+    pub active: bool,
+    // Has open windows
+    pub occupied: bool,
+}
+
+// A running app of the active workspace
+pub struct TaskbarEntry {
+    pub name: &'static str,
+    pub minimized: bool,
+    pub focused: bool,
+}
+
+pub struct Taskbar {
+    pub rect: Rect,
+    launcher_rect: Rect,
+    quick_settings_rect: Rect,
+    pub tray: Tray,
+    workspaces: Vec<(WorkspaceEntry, Rect)>,
+    entries: Vec<(TaskbarEntry, Rect)>,
+}
+
+impl Taskbar {
+    pub fn new(
+        fb_shape: (u32, u32),
+        workspaces: Vec<WorkspaceEntry>,
+        entries: Vec<TaskbarEntry>,
+    ) -> Self {
+        let (fb_w, fb_h) = fb_shape;
+
+        let rect = Rect {
+            x0: 0,
+            y0: (fb_h - TASKBAR_H) as i64,
+            w: fb_w,
+            h: TASKBAR_H,
+        };
+
+        let launcher_rect = Rect {
+            w: LAUNCHER_W,
+            ..rect.clone()
+        };
+
+        let status_rect = Rect {
+            x0: fb_w.saturating_sub(STATUS_AREA_W) as i64,
+            w: u32::min(STATUS_AREA_W, fb_w),
+            ..rect.clone()
+        };
+
+        let quick_settings_rect = Rect {
+            x0: fb_w.saturating_sub(QUICK_SETTINGS_W) as i64,
+            w: u32::min(QUICK_SETTINGS_W, fb_w),
+            ..rect.clone()
+        };
+
+        let tray = Tray::new(&Rect::from_xyxy([
+            status_rect.x0,
+            rect.y0,
+            quick_settings_rect.x0 - 1,
+            rect.y0 + rect.h as i64 - 1,
+        ]));
+
+        let workspaces_x0 = launcher_rect.x0 + (LAUNCHER_W + BUTTON_GAP) as i64;
+        let nb_workspaces = workspaces.len() as u32;
+        let workspaces = workspaces
+            .into_iter()
+            .enumerate()
+            .map(|(i, workspace)| {
+                let button_rect = Rect {
+                    x0: workspaces_x0 + (i as u32 * WORKSPACE_BUTTON_W) as i64,
+                    y0: rect.y0 + BUTTON_GAP as i64,
+                    w: WORKSPACE_BUTTON_W - BUTTON_GAP,
+                    h: TASKBAR_H - 2 * BUTTON_GAP,
+                };
+                (workspace, button_rect)
+            })
+            .collect();
+
+        // Buttons get narrower when there are too many of them to fit
+        let x0 = workspaces_x0 + (nb_workspaces * WORKSPACE_BUTTON_W + BUTTON_GAP) as i64;
+        let available_w = i64::max(0, status_rect.x0 - x0) as u32;
+        let button_w = match entries.len() as u32 {
+            0 => APP_BUTTON_W,
+            n => u32::min(APP_BUTTON_W, available_w / n),
+        };
+
+        let entries = entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let button_rect = Rect {
+                    x0: x0 + (i as u32 * button_w) as i64,
+                    y0: rect.y0 + BUTTON_GAP as i64,
+                    w: button_w.saturating_sub(BUTTON_GAP),
+                    h: TASKBAR_H - 2 * BUTTON_GAP,
+                };
+                (entry, button_rect)
+            })
+            .collect();
+
+        Taskbar {
+            rect,
+            launcher_rect,
+            quick_settings_rect,
+            tray,
+            workspaces,
+            entries,
+        }
+    }
+
+    pub fn item_at(&self, x: i64, y: i64) -> Option<TaskbarItem> {
+        if self.launcher_rect.check_contains_point(x, y) {
+            return Some(TaskbarItem::Launcher);
+        }
+
+        if self.quick_settings_rect.check_contains_point(x, y) {
+            return Some(TaskbarItem::QuickSettings);
+        }
+
+        if let Some(item) = self.tray.item_at(x, y) {
+            return Some(TaskbarItem::Tray(item));
+        }
+
+        if let Some(i) = self
+            .workspaces
+            .iter()
+            .position(|(_, rect)| rect.check_contains_point(x, y))
+        {
+            return Some(TaskbarItem::Workspace(i));
+        }
+
+        self.entries
+            .iter()
+            .find(|(_, rect)| rect.check_contains_point(x, y))
+            .map(|(entry, _)| TaskbarItem::App(entry.name))
+    }
+
+    pub fn draw<F: FbViewMut>(
+        &self,
+        fb: &mut F,
+        stylesheet: &StyleSheet,
+        font: &Font,
+        hovered: Option<TaskbarItem>,
+        launcher_open: bool,
+        quick_settings_open: bool,
+        tray_status: &TrayStatus,
+        tray_open: Option<TrayItem>,
+    ) {
+        let widgets = &stylesheet.widgets;
+
+        draw_rect(fb, &self.rect, stylesheet.colors.background, false);
+
+        //
+        // Launcher button
+
+        let launcher_colors = match (launcher_open, hovered) {
+            (true, _) => &widgets.pressed,
+            (false, Some(TaskbarItem::Launcher)) => &widgets.hover,
+            _ => &widgets.normal,
+        };
+        draw_rect(
+            fb,
+            &self.launcher_rect.offset(-(BUTTON_GAP as i64)),
+            launcher_colors.bg,
+            false,
+        );
+        let icon: &Framebuffer<OwnedPixels> = &resources::HOME_ICON;
+        let (icon_w, icon_h) = icon.shape();
+        let (xc, yc) = self.launcher_rect.center();
+        let icon_rect = Rect::from_center(xc, yc, icon_w, icon_h);
+        fb.copy_from_fb(icon, icon_rect.origin(), true);
+
+        //
+        // Workspace indicator
+
+        for (i, (workspace, rect)) in self.workspaces.iter().enumerate() {
+            let colors = match workspace.active {
+                true => &widgets.selected,
+                false if hovered == Some(TaskbarItem::Workspace(i)) => &widgets.hover,
+                false => &widgets.normal,
+            };
+            draw_rect(fb, rect, colors.bg, false);
+
+            // Dimmed while it has no window
+            let text_color = match workspace.occupied || workspace.active {
+                true => colors.text,
+                false => widgets.disabled.text,
+            };
+            let text = format!("{}", i + 1);
+            draw_line_in_rect(fb, &text, rect, font, text_color, TextJustification::Center);
+        }
+
+        //
+        // Running apps
+
+        for (entry, rect) in self.entries.iter() {
+            let colors = match entry.focused {
+                true => &widgets.selected,
+                false if hovered == Some(TaskbarItem::App(entry.name)) => &widgets.hover,
+                false => &widgets.normal,
+            };
+            draw_rect(fb, rect, colors.bg, false);
+
+            // Dimmed while the window is hidden
+            let text_color = match entry.minimized {
+                true => widgets.disabled.text,
+                false => colors.text,
+            };
+            let text_w = rect.w.saturating_sub(2 * widgets.padding);
+            let text = ellipsize_text(entry.name, font, text_w);
+            draw_line_in_rect(fb, &text, rect, font, text_color, TextJustification::Center);
+        }
+
+        //
+        // Tray
+
+        let tray_hover = match hovered {
+            Some(TaskbarItem::Tray(item)) => Some(item),
+            _ => None,
+        };
+        self.tray
+            .draw(fb, stylesheet, font, tray_status, tray_hover, tray_open);
+
+        //
+        // Quick settings button
+
+        let quick_settings_colors = match (quick_settings_open, hovered) {
+            (true, _) => &widgets.pressed,
+            (false, Some(TaskbarItem::QuickSettings)) => &widgets.hover,
+            _ => &widgets.normal,
+        };
+        let quick_settings_rect = self.quick_settings_rect.offset(-(BUTTON_GAP as i64));
+        draw_rect(fb, &quick_settings_rect, quick_settings_colors.bg, false);
+        draw_line_in_rect(
+            fb,
+            "Settings",
+            &quick_settings_rect,
+            font,
+            quick_settings_colors.text,
+            TextJustification::Center,
+        );
+
+        //
+        // Border
+
+        draw_rect(
+            fb,
+            &Rect {
+                h: stylesheet.margin,
+                ..self.rect.clone()
+            },
+            Color::BLACK,
+            false,
+        );
+    }
+}
+
+// Lists all apps above the launcher button, sorted by name
+pub struct LauncherMenu {
+    pub rect: Rect,
+    rows: Vec<(&'static AppDescriptor, Rect)>,
+}
+
+impl LauncherMenu {
+    pub fn new(taskbar: &Taskbar) -> Self {
+        let mut descriptors: Vec<&'static AppDescriptor> = APPLICATIONS.iter().collect();
+        descriptors.sort_by_key(|desc| desc.name);
+
+        let h = descriptors.len() as u32 * MENU_ROW_H;
+        let rect = Rect {
+            x0: taskbar.launcher_rect.x0,
+            y0: taskbar.rect.y0 - h as i64,
+            w: LAUNCHER_MENU_W,
+            h,
+        };
+
+        let rows = descriptors
+            .into_iter()
+            .enumerate()
+            .map(|(i, desc)| {
+                let row_rect = Rect {
+                    y0: rect.y0 + (i as u32 * MENU_ROW_H) as i64,
+                    h: MENU_ROW_H,
+                    ..rect.clone()
+                };
+                (desc, row_rect)
+            })
+            .collect();
+
+        LauncherMenu { rect, rows }
+    }
+
+    pub fn app_at(&self, x: i64, y: i64) -> Option<&'static str> {
+        self.rows
+            .iter()
+            .find(|(_, rect)| rect.check_contains_point(x, y))
+            .map(|(desc, _)| desc.name)
+    }
+
+    pub fn draw<F: FbViewMut>(
+        &self,
+        fb: &mut F,
+        stylesheet: &StyleSheet,
+        font: &Font,
+        hovered: Option<&'static str>,
+    ) {
+        draw_rect(fb, &self.rect, stylesheet.colors.background, false);
+
+        for (desc, rect) in self.rows.iter() {
+            if hovered == Some(desc.name) {
+                draw_rect(fb, rect, stylesheet.widgets.hover.bg, false);
+            }
+
+            let (icon_w, icon_h) = desc.icon.shape();
+            let icon_rect = Rect {
+                x0: rect.x0 + ICON_MARGIN_W as i64,
+                y0: 0,
+                w: icon_w,
+                h: icon_h,
+            }
+            .align_to_rect_vert(rect);
+            fb.copy_from_fb(desc.icon, icon_rect.origin(), true);
+
+            let text_x0 = icon_rect.x0 + (icon_w + ICON_MARGIN_W) as i64;
+            let text_rect = Rect::from_xyxy([
+                text_x0,
+                rect.y0,
+                rect.x0 + rect.w as i64 - 1,
+                rect.y0 + rect.h as i64 - 1,
+            ]);
+            let text = ellipsize_text(desc.name, font, text_rect.w);
+            draw_line_in_rect(
+                fb,
+                &text,
+                &text_rect,
+                font,
+                stylesheet.colors.text,
+                TextJustification::Left,
+            );
+        }
+
+        draw_rect_outline(fb, &self.rect, Color::BLACK, false, stylesheet.margin);
+    }
+}
+
+// Applied as soon as they are picked in the quick settings menu
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuickSetting {
+    // Indices in THEMES and WALLPAPERS
+    Theme(usize),
+    Wallpaper(usize),
+    Scaling(ScalingMode),
+    ShadeUnfocused(bool),
+    // Display mode, applied by the main loop before the next frame
+    Resolution(u32, u32),
+}
+
+enum QuickSettingsRow {
+    Header(&'static str),
+    Setting(QuickSetting, String),
+}
+
+pub struct QuickSettingsMenu {
+    pub rect: Rect,
+    rows: Vec<(QuickSettingsRow, Rect)>,
+}
+
+impl QuickSettingsMenu {
+    // Sections are laid out in columns that fit between the topbar and the taskbar,
+    // the menu grows to the left of its button
+    pub fn new(taskbar: &Taskbar, display_modes: &[(u32, u32)]) -> Self {
+        let sections: Vec<(&'static str, Vec<(QuickSetting, String)>)> = Vec::from([
+            (
+                "Theme",
+                THEMES
+                    .iter()
+                    .enumerate()
+                    .map(|(i, theme)| (QuickSetting::Theme(i), theme.name.to_string()))
+                    .collect(),
+            ),
+            (
+                "Wallpaper",
+                WALLPAPERS
+                    .iter()
+                    .enumerate()
+                    .map(|(i, desc)| (QuickSetting::Wallpaper(i), desc.name.to_string()))
+                    .collect(),
+            ),
+            (
+                "Scaling",
+                SCALING_MODES
+                    .into_iter()
+                    .map(|mode| (QuickSetting::Scaling(mode), mode.name().to_string()))
+                    .collect(),
+            ),
+            (
+                "Unfocused",
+                Vec::from([
+                    (QuickSetting::ShadeUnfocused(false), "Opaque".to_string()),
+                    (QuickSetting::ShadeUnfocused(true), "Shaded".to_string()),
+                ]),
+            ),
+            (
+                "Resolution",
+                display_modes
+                    .iter()
+                    .map(|&(w, h)| (QuickSetting::Resolution(w, h), format!("{}x{}", w, h)))
+                    .collect(),
+            ),
+        ]);
+
+        let max_rows = u32::max(
+            2,
+            taskbar.rect.y0.saturating_sub(TOPBAR_H as i64) as u32 / QUICK_SETTINGS_ROW_H,
+        ) as usize;
+
+        // A section starts a new column if it does not fit in the current one, and
+        // only goes on in the next one if it does not fit in a column at all
+        let mut columns: Vec<Vec<QuickSettingsRow>> = Vec::from([Vec::new()]);
+        for (header, settings) in sections {
+            let column_rows = columns.last().unwrap().len();
+            if column_rows > 0 && column_rows + settings.len() + 1 > max_rows {
+                columns.push(Vec::new());
+            }
+
+            let rows = core::iter::once(QuickSettingsRow::Header(header)).chain(
+                settings
+                    .into_iter()
+                    .map(|(setting, text)| QuickSettingsRow::Setting(setting, text)),
+            );
+            for row in rows {
+                if columns.last().unwrap().len() == max_rows {
+                    columns.push(Vec::new());
+                }
+                columns.last_mut().unwrap().push(row);
+            }
+        }
+
+        let w = columns.len() as u32 * QUICK_SETTINGS_MENU_W;
+        let h =
+            columns.iter().map(|column| column.len()).max().unwrap() as u32 * QUICK_SETTINGS_ROW_H;
+        let button_rect = &taskbar.quick_settings_rect;
+        let rect = Rect {
+            x0: i64::max(0, button_rect.x0 + button_rect.w as i64 - w as i64),
+            y0: taskbar.rect.y0 - h as i64,
+            w,
+            h,
+        };
+
+        let rows = columns
+            .into_iter()
+            .enumerate()
+            .flat_map(|(col, column)| {
+                let x0 = rect.x0 + (col as u32 * QUICK_SETTINGS_MENU_W) as i64;
+                let y0 = rect.y0;
+                column.into_iter().enumerate().map(move |(i, item)| {
+                    let row_rect = Rect {
+                        x0,
+                        y0: y0 + (i as u32 * QUICK_SETTINGS_ROW_H) as i64,
+                        w: QUICK_SETTINGS_MENU_W,
+                        h: QUICK_SETTINGS_ROW_H,
+                    };
+                    (item, row_rect)
+                })
+            })
+            .collect();
+
+        QuickSettingsMenu { rect, rows }
+    }
+
+    pub fn setting_at(&self, x: i64, y: i64) -> Option<QuickSetting> {
+        self.rows
+            .iter()
+            .find(|(_, rect)| rect.check_contains_point(x, y))
+            .and_then(|(row, _)| match row {
+                QuickSettingsRow::Setting(setting, _) => Some(*setting),
+                QuickSettingsRow::Header(_) => None,
+            })
+    }
+
+    pub fn draw<F: FbViewMut>(
+        &self,
+        fb: &mut F,
+        stylesheet: &StyleSheet,
+        font: &Font,
+        current: &[QuickSetting],
+        hovered: Option<QuickSetting>,
+    ) {
+        let widgets = &stylesheet.widgets;
+
+        draw_rect(fb, &self.rect, stylesheet.colors.background, false);
+
+        for (row, rect) in self.rows.iter() {
+            let text_rect = Rect {
+                x0: rect.x0 + ICON_MARGIN_W as i64,
+                w: rect.w.saturating_sub(2 * ICON_MARGIN_W),
+                ..rect.clone()
+            };
+
+            let (text, text_color) = match row {
+                QuickSettingsRow::Header(text) => (*text, widgets.disabled.text),
+                QuickSettingsRow::Setting(setting, text) => {
+                    let colors = match (current.contains(setting), hovered == Some(*setting)) {
+                        (true, _) => Some(&widgets.selected),
+                        (false, true) => Some(&widgets.hover),
+                        (false, false) => None,
+                    };
+                    if let Some(colors) = colors {
+                        draw_rect(fb, rect, colors.bg, false);
+                    }
+                    (text.as_str(), colors.unwrap_or(&widgets.normal).text)
+                }
+            };
+
+            draw_line_in_rect(
+                fb,
+                text,
+                &text_rect,
+                font,
+                text_color,
+                TextJustification::Left,
+            );
+        }
+
+        draw_rect_outline(fb, &self.rect, Color::BLACK, false, stylesheet.margin);
+    }
+}
@@ -2,9 +2,41 @@ use uefi::prelude::RuntimeServices;
 
 use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 
+// Smaller corrections of the wall clock are spread over time at that rate, as NTP daemons do
+const SLEW_RATE: f64 = 500e-6;
+// In s, larger errors are corrected at once
+const STEP_THRESHOLD: f64 = 0.5;
+// Of the TSC frequency against the time server, larger estimates are not trusted
+const MAX_DRIFT: f64 = 0.01;
+// In s between two syncs, for the drift to be estimated
+const MIN_DRIFT_INTERVAL: f64 = 60.0;
+
 pub struct SystemClock {
     period_s: f64,
     epoch_offset: f64,
+    // Wall clock correction, in s, as of correction_time (TSC time, in s)
+    correction: f64,
+    correction_time: f64,
+    // Estimated error of the TSC frequency, added to the correction as time goes
+    drift: f64,
+    // Still to be added to the correction, at SLEW_RATE from correction_time
+    slew: f64,
+    last_sync: Option<TimeSync>,
+}
+
+// The wall clock was last corrected against a time server
+#[derive(Debug, Clone, Copy)]
+pub struct TimeSync {
+    // UNIX time, after the correction
+    pub time: f64,
+    // Of the wall clock, in s, positive when it was behind
+    pub offset: f64,
+    // Whether the offset was corrected at once rather than slewed
+    pub stepped: bool,
+    // Of the TSC frequency, in ppm, positive when it runs slow. None until two syncs.
+    pub drift_ppm: Option<f64>,
+    // TSC time, in s
+    tsc_time: f64,
 }
 
 impl SystemClock {
@@ -18,6 +50,11 @@ impl SystemClock {
         SystemClock {
             period_s,
             epoch_offset,
+            correction: 0.0,
+            correction_time: 0.0,
+            drift: 0.0,
+            slew: 0.0,
+            last_sync: None,
         }
     }
 
@@ -27,6 +64,84 @@ impl SystemClock {
         1000f64 * (n as f64) * self.period_s + self.epoch_offset
     }
 
+    // Seconds since the UNIX epoch, as precise as the TSC. Corrected against the time
+    // server once synced, without ever going back unless an error had to be stepped.
+    pub fn unix_time(&self) -> f64 {
+        let t = self.tsc_time();
+        t + self.epoch_offset + self.correction_at(t)
+    }
+
+    pub fn utc_now(&self) -> DateTime<Utc> {
+        let millis = (self.unix_time() * 1000.0) as i64;
+        DateTime::from_timestamp_millis(millis).unwrap_or(DateTime::UNIX_EPOCH)
+    }
+
+    // Corrects the wall clock by `offset` s, as measured against a time server. It is
+    // slewed unless larger than STEP_THRESHOLD.
+    pub fn adjust(&mut self, offset: f64) -> TimeSync {
+        let t = self.tsc_time();
+
+        let correction = self.correction_at(t);
+
+        // Of the error since the last sync, what was left to slew then is not part
+        let error = offset - (self.slew - self.slewed_at(t));
+        let mut drift_ppm = self.last_sync.and_then(|sync| sync.drift_ppm);
+        if let Some(last_sync) = self.last_sync {
+            let elapsed = t - last_sync.tsc_time;
+            if elapsed > MIN_DRIFT_INTERVAL {
+                let drift = (self.drift + error / elapsed).clamp(-MAX_DRIFT, MAX_DRIFT);
+                drift_ppm = Some(drift * 1e6);
+                self.drift = drift;
+            }
+        }
+
+        self.correction = correction;
+        self.correction_time = t;
+        let stepped = offset.abs() > STEP_THRESHOLD;
+        match stepped {
+            true => {
+                self.correction += offset;
+                self.slew = 0.0;
+            }
+            false => self.slew = offset,
+        }
+
+        let sync = TimeSync {
+            time: t + self.epoch_offset + self.correction,
+            offset,
+            stepped,
+            drift_ppm,
+            tsc_time: t,
+        };
+        self.last_sync = Some(sync);
+        sync
+    }
+
+    pub fn last_sync(&self) -> Option<TimeSync> {
+        self.last_sync
+    }
+
+    // Of the TSC, in s
+    fn tsc_time(&self) -> f64 {
+        let n = unsafe { core::arch::x86_64::_rdtsc() };
+        (n as f64) * self.period_s
+    }
+
+    fn correction_at(&self, t: f64) -> f64 {
+        self.correction + (t - self.correction_time) * self.drift + self.slewed_at(t)
+    }
+
+    // Part of the slew already added
+    fn slewed_at(&self, t: f64) -> f64 {
+        let max = (t - self.correction_time) * SLEW_RATE;
+        self.slew.clamp(-max, max)
+    }
+
+    // Of the TSC, in seconds
+    pub fn cycle_period(&self) -> f64 {
+        self.period_s
+    }
+
     pub fn spin_delay(&self, duration: f64) {
         let t0 = self.time();
         while self.time() - t0 < duration {}
@@ -1,4 +1,5 @@
 use alloc::format;
+use alloc::string::String;
 use applib::drawing::primitives::draw_rect;
 use applib::drawing::text::{compute_text_bbox, draw_line_in_rect, get_font, TextJustification};
 use applib::uitk::{BarValue, HorizBarConfig, UiContext};
@@ -14,11 +15,113 @@ use crate::resources;
 use crate::stats::SystemStats;
 use crate::TOPBAR_H;
 
-pub fn topbar<'a, F: FbViewMut>(
-    uitk_context: &mut uitk::UiContext<F>,
-    system_stats: &SystemStats,
-    datetime: DateTime<Utc>,
-) {
+// So that the readouts do not damage the topbar every frame, in ms
+const REFRESH_DELAY: f64 = 500.0;
+
+const FRAMETIME_WINDOW_LEN: usize = 50;
+
+// Shown until the next refresh
+pub struct Topbar {
+    readouts: Option<Readouts>,
+    refreshed_at: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Readouts {
+    clock: String,
+    frametime: f32,
+    allocated: f32,
+    heap_total: f32,
+    net_sent: f32,
+    net_recv: f32,
+    net_sent_rate: f32,
+    net_recv_rate: f32,
+}
+
+impl Topbar {
+    pub fn new() -> Self {
+        Topbar {
+            readouts: None,
+            refreshed_at: 0.0,
+        }
+    }
+
+    // Whether what the topbar shows changed
+    pub fn update(
+        &mut self,
+        system_stats: &SystemStats,
+        datetime: DateTime<Utc>,
+        time: f64,
+    ) -> bool {
+        if self.readouts.is_some() && time - self.refreshed_at < REFRESH_DELAY {
+            return false;
+        }
+
+        let readouts = Readouts::new(system_stats, datetime);
+        let changed = self.readouts.as_ref() != Some(&readouts);
+        self.readouts = Some(readouts);
+        self.refreshed_at = time;
+        changed
+    }
+
+    pub fn draw<F: FbViewMut>(&self, uitk_context: &mut uitk::UiContext<F>) {
+        if let Some(readouts) = self.readouts.as_ref() {
+            topbar(uitk_context, readouts);
+        }
+    }
+}
+
+impl Readouts {
+    fn new(system_stats: &SystemStats, datetime: DateTime<Utc>) -> Self {
+        let month_str = Month::try_from(datetime.month() as u8).unwrap().name();
+
+        let day_suffix = match datetime.day() % 10 {
+            1 => "st",
+            2 => "nd",
+            _ => "th",
+        };
+
+        let clock = format!(
+            "{}, {} {}{}, {:02}:{:02}",
+            datetime.weekday(),
+            month_str,
+            datetime.day(),
+            day_suffix,
+            datetime.hour(),
+            datetime.minute()
+        );
+
+        let frametime_data = system_stats.get_system_history(|dp| dp.frametime_used as f32);
+        let frametime = frametime_data
+            .iter()
+            .take(FRAMETIME_WINDOW_LEN)
+            .fold(0.0, |acc, v| acc + v / FRAMETIME_WINDOW_LEN as f32);
+
+        let heap_allocated_data = system_stats.get_system_history(|dp| dp.alloc.allocated as f32);
+        let allocated = heap_allocated_data
+            .iter()
+            .fold(0.0, |acc, v| acc + v / heap_allocated_data.len() as f32);
+
+        let net_sent_data = system_stats.get_system_history(|dp| dp.net_sent as f32);
+        let net_recv_data = system_stats.get_system_history(|dp| dp.net_recv as f32);
+
+        let target_frametime: f32 = 1000.0 / crate::FPS_TARGET as f32;
+        let history_duration_sec = target_frametime * net_recv_data.len() as f32 / 1000.0;
+
+        Readouts {
+            clock,
+            frametime,
+            allocated,
+            heap_total: system_stats.heap_total as f32,
+            net_sent: net_sent_data.iter().sum::<f32>(),
+            net_recv: net_recv_data.iter().sum::<f32>(),
+            net_sent_rate: net_sent_data.iter().sum::<f32>() / history_duration_sec,
+            net_recv_rate: net_recv_data.iter().sum::<f32>() / history_duration_sec,
+        }
+    }
+}
+
+fn topbar<F: FbViewMut>(uitk_context: &mut uitk::UiContext<F>, readouts: &Readouts) {
     let UiContext { fb, stylesheet, .. } = uitk_context;
 
     let font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.medium);
@@ -37,29 +140,11 @@ pub fn topbar<'a, F: FbViewMut>(
     //
     // Date and time
 
-    let month_str = Month::try_from(datetime.month() as u8).unwrap().name();
-
-    let day_suffix = match datetime.day() % 10 {
-        1 => "st",
-        2 => "nd",
-        _ => "th",
-    };
-
-    let clock_str = format!(
-        "{}, {} {}{}, {:02}:{:02}",
-        datetime.weekday(),
-        month_str,
-        datetime.day(),
-        day_suffix,
-        datetime.hour(),
-        datetime.minute()
-    );
-
-    let clock_bbox = compute_text_bbox(&clock_str, font);
+    let clock_bbox = compute_text_bbox(&readouts.clock, font);
 
     draw_line_in_rect(
         *fb,
-        &clock_str,
+        &readouts.clock,
         &topbar_rect,
         font,
         stylesheet.colors.text,
@@ -69,7 +154,6 @@ pub fn topbar<'a, F: FbViewMut>(
     //
     // Resources
 
-    const FRAMETIME_WINDOW_LEN: usize = 50;
     const RESOURCES_BAR_W: u32 = 100;
     const RESOURCES_BAR_H: u32 = 15;
     const SEP_MARGIN_W: u32 = 30;
@@ -158,11 +242,7 @@ pub fn topbar<'a, F: FbViewMut>(
         *x += SEP_MARGIN_W as i64;
     };
 
-    let frametime_data = system_stats.get_system_history(|dp| dp.frametime_used as f32);
-    let agg_frametime = frametime_data
-        .iter()
-        .take(FRAMETIME_WINDOW_LEN)
-        .fold(0.0, |acc, v| acc + v / FRAMETIME_WINDOW_LEN as f32);
+    let agg_frametime = readouts.frametime;
 
     draw_text_box(
         uitk_context,
@@ -197,11 +277,8 @@ pub fn topbar<'a, F: FbViewMut>(
         },
     );
 
-    let heap_allocated_data = system_stats.get_system_history(|dp| dp.alloc.allocated as f32);
-    let agg_allocated = heap_allocated_data
-        .iter()
-        .fold(0.0, |acc, v| acc + v / heap_allocated_data.len() as f32);
-    let heap_total = system_stats.heap_total as f32;
+    let agg_allocated = readouts.allocated;
+    let heap_total = readouts.heap_total;
 
     draw_monitor(
         uitk_context,
@@ -221,15 +298,10 @@ pub fn topbar<'a, F: FbViewMut>(
         },
     );
 
-    let net_sent_data = system_stats.get_system_history(|dp| dp.net_sent as f32);
-    let net_recv_data = system_stats.get_system_history(|dp| dp.net_recv as f32);
-    let agg_net_sent = net_sent_data.iter().sum::<f32>();
-    let agg_net_recv = net_recv_data.iter().sum::<f32>();
-
-    let target_frametime: f32 = 1000.0 / crate::FPS_TARGET as f32;
-    let history_duration_sec = target_frametime * net_recv_data.len() as f32 / 1000.0;
-    let net_recv_rate = net_recv_data.iter().sum::<f32>() / history_duration_sec;
-    let net_sent_rate = net_sent_data.iter().sum::<f32>() / history_duration_sec;
+    let agg_net_sent = readouts.net_sent;
+    let agg_net_recv = readouts.net_recv;
+    let net_recv_rate = readouts.net_recv_rate;
+    let net_sent_rate = readouts.net_sent_rate;
 
     draw_monitor(
         uitk_context,
@@ -277,7 +349,7 @@ pub fn topbar<'a, F: FbViewMut>(
         "Munal OS v1.0",
         &version_rect,
         font,
-        Color::WHITE,
+        uitk_context.stylesheet.colors.text,
         TextJustification::Center,
     );
 
@@ -0,0 +1,436 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use applib::drawing::primitives::{draw_rect, draw_rect_outline};
+use applib::drawing::text::{draw_line_in_rect, ellipsize_text, Font, TextJustification};
+use applib::{Color, FbView, FbViewMut, Framebuffer, OwnedPixels, Rect, StyleSheet};
+use chrono::{DateTime, Datelike, Month, Months, NaiveDate, Timelike, Utc};
+
+use crate::resources;
+
+const TRAY_ICON_W: u32 = 36;
+const CLOCK_W: u32 = 64;
+const BADGE_W: u32 = 28;
+const BADGE_H: u32 = 22;
+const BLIP_SIZE: u32 = 4;
+const PANEL_W: u32 = 320;
+const PANEL_ROW_H: u32 = 28;
+// Most recent first, older ones are only kept in the log
+const MAX_PANEL_ROWS: usize = 8;
+const TEXT_MARGIN_W: u32 = 8;
+const TIMESTAMP_W: u32 = 56;
+const CALENDAR_CELL_W: u32 = 36;
+const CALENDAR_CELL_H: u32 = 28;
+const CALENDAR_WEEKS: u32 = 6;
+const WEEKDAYS: [&str; 7] = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+
+// Indicators in the status area of the taskbar, from left to right. Volume is missing
+// until there is an audio driver, see todo.txt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrayItem {
+    Network,
+    Notifications,
+    Clock,
+}
+
+impl TrayItem {
+    pub fn has_popup(&self) -> bool {
+        match self {
+            TrayItem::Network => false,
+            TrayItem::Notifications | TrayItem::Clock => true,
+        }
+    }
+}
+
+// What the indicators show, the taskbar is drawn again when it changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrayStatus {
+    pub link_up: bool,
+    // Traffic in the last few frames
+    pub receiving: bool,
+    pub sending: bool,
+    pub nb_notifications: usize,
+    pub clock: String,
+    pub date: NaiveDate,
+}
+
+// Sent by an app, kept until cleared from the notifications panel
+pub struct Notification {
+    pub app_name: &'static str,
+    pub text: String,
+    pub datetime: DateTime<Utc>,
+}
+
+pub struct Tray {
+    items: Vec<(TrayItem, Rect)>,
+}
+
+impl Tray {
+    // Right-aligned in the given part of the taskbar
+    pub fn new(area: &Rect) -> Self {
+        let widths = [
+            (TrayItem::Network, TRAY_ICON_W),
+            (TrayItem::Notifications, TRAY_ICON_W),
+            (TrayItem::Clock, CLOCK_W),
+        ];
+
+        let total_w: u32 = widths.iter().map(|(_, w)| w).sum();
+        let mut x = area.x0 + area.w as i64 - total_w as i64;
+        let items = widths
+            .into_iter()
+            .map(|(item, w)| {
+                let rect = Rect {
+                    x0: x,
+                    w,
+                    ..area.clone()
+                };
+                x += w as i64;
+                (item, rect)
+            })
+            .collect();
+
+        Tray { items }
+    }
+
+    pub fn item_at(&self, x: i64, y: i64) -> Option<TrayItem> {
+        self.items
+            .iter()
+            .find(|(_, rect)| rect.check_contains_point(x, y))
+            .map(|(item, _)| *item)
+    }
+
+    fn item_rect(&self, item: TrayItem) -> &Rect {
+        self.items
+            .iter()
+            .find(|(other, _)| *other == item)
+            .map(|(_, rect)| rect)
+            .unwrap()
+    }
+
+    // Above the taskbar, with its right side on the one of the indicator
+    fn popup_rect(&self, item: TrayItem, w: u32, h: u32) -> Rect {
+        let item_rect = self.item_rect(item);
+        Rect {
+            x0: i64::max(0, item_rect.x0 + item_rect.w as i64 - w as i64),
+            y0: item_rect.y0 - h as i64,
+            w,
+            h,
+        }
+    }
+
+    pub fn draw<F: FbViewMut>(
+        &self,
+        fb: &mut F,
+        stylesheet: &StyleSheet,
+        font: &Font,
+        status: &TrayStatus,
+        hovered: Option<TrayItem>,
+        open: Option<TrayItem>,
+    ) {
+        let widgets = &stylesheet.widgets;
+
+        for (item, rect) in self.items.iter() {
+            let colors = match (open == Some(*item), hovered == Some(*item)) {
+                (true, _) => Some(&widgets.pressed),
+                (false, true) if item.has_popup() => Some(&widgets.hover),
+                _ => None,
+            };
+            if let Some(colors) = colors {
+                draw_rect(fb, rect, colors.bg, false);
+            }
+            let text_color = colors.unwrap_or(&widgets.normal).text;
+
+            match item {
+                TrayItem::Network => {
+                    let icon: &Framebuffer<OwnedPixels> = &resources::NETWORK_ICON;
+                    let (icon_w, icon_h) = icon.shape();
+                    let (xc, yc) = rect.center();
+                    let icon_rect = Rect::from_center(xc, yc, icon_w, icon_h);
+                    fb.copy_from_fb(icon, icon_rect.origin(), true);
+
+                    // Faded out, with a red mark in the corner
+                    if !status.link_up {
+                        let (r, g, b, _) = stylesheet.colors.background.as_rgba();
+                        draw_rect(fb, &icon_rect, Color::rgba(r, g, b, 160), true);
+                        let mark_rect = Rect {
+                            x0: icon_rect.x0 + (icon_w - 2 * BLIP_SIZE) as i64,
+                            y0: icon_rect.y0 + (icon_h - 2 * BLIP_SIZE) as i64,
+                            w: 2 * BLIP_SIZE,
+                            h: 2 * BLIP_SIZE,
+                        };
+                        draw_rect(fb, &mark_rect, Color::RED, false);
+                    }
+
+                    // Same colors as the topbar network monitor
+                    let blips = [
+                        (status.sending, icon_rect.x0, Color::YELLOW),
+                        (
+                            status.receiving,
+                            icon_rect.x0 + (icon_w - BLIP_SIZE) as i64,
+                            Color::BLUE,
+                        ),
+                    ];
+                    for (active, x0, color) in blips {
+                        if active {
+                            let blip_rect = Rect {
+                                x0,
+                                y0: icon_rect.y0,
+                                w: BLIP_SIZE,
+                                h: BLIP_SIZE,
+                            };
+                            draw_rect(fb, &blip_rect, color, false);
+                        }
+                    }
+                }
+
+                TrayItem::Notifications => {
+                    let (xc, yc) = rect.center();
+                    let badge_rect = Rect::from_center(xc, yc, BADGE_W, BADGE_H);
+                    let badge_colors = match status.nb_notifications {
+                        0 => &widgets.disabled,
+                        _ => &widgets.selected,
+                    };
+                    draw_rect(fb, &badge_rect, badge_colors.bg, false);
+                    let text = match status.nb_notifications {
+                        n if n > 99 => String::from("99+"),
+                        n => format!("{}", n),
+                    };
+                    draw_line_in_rect(
+                        fb,
+                        &text,
+                        &badge_rect,
+                        font,
+                        badge_colors.text,
+                        TextJustification::Center,
+                    );
+                }
+
+                TrayItem::Clock => {
+                    draw_line_in_rect(
+                        fb,
+                        &status.clock,
+                        rect,
+                        font,
+                        text_color,
+                        TextJustification::Center,
+                    );
+                }
+            }
+        }
+    }
+}
+
+// Lists the last notifications, most recent first, with a button to clear them all
+pub struct NotificationsPanel {
+    pub rect: Rect,
+    rows: Vec<Rect>,
+    clear_rect: Rect,
+}
+
+impl NotificationsPanel {
+    pub fn new(tray: &Tray, nb_notifications: usize) -> Self {
+        // Room for the header, and for a placeholder when there is nothing to show
+        let nb_rows = usize::clamp(nb_notifications, 1, MAX_PANEL_ROWS) as u32;
+        let h = (nb_rows + 2) * PANEL_ROW_H;
+        let rect = tray.popup_rect(TrayItem::Notifications, PANEL_W, h);
+
+        let row_rect = |i: u32| Rect {
+            y0: rect.y0 + (i * PANEL_ROW_H) as i64,
+            h: PANEL_ROW_H,
+            ..rect.clone()
+        };
+        let rows = (1..=nb_rows).map(row_rect).collect();
+        let clear_rect = row_rect(nb_rows + 1);
+
+        NotificationsPanel {
+            rect,
+            rows,
+            clear_rect,
+        }
+    }
+
+    pub fn clear_all_at(&self, x: i64, y: i64) -> bool {
+        self.clear_rect.check_contains_point(x, y)
+    }
+
+    pub fn draw<F: FbViewMut>(
+        &self,
+        fb: &mut F,
+        stylesheet: &StyleSheet,
+        font: &Font,
+        notifications: &[Notification],
+        clear_hovered: bool,
+    ) {
+        let widgets = &stylesheet.widgets;
+
+        draw_rect(fb, &self.rect, stylesheet.colors.background, false);
+
+        let text_rect = |rect: &Rect| Rect {
+            x0: rect.x0 + TEXT_MARGIN_W as i64,
+            w: rect.w.saturating_sub(2 * TEXT_MARGIN_W),
+            ..rect.clone()
+        };
+
+        let header_rect = Rect {
+            h: PANEL_ROW_H,
+            ..self.rect.clone()
+        };
+        draw_line_in_rect(
+            fb,
+            "Notifications",
+            &text_rect(&header_rect),
+            font,
+            widgets.disabled.text,
+            TextJustification::Left,
+        );
+
+        if notifications.is_empty() {
+            draw_line_in_rect(
+                fb,
+                "Nothing new",
+                &text_rect(&self.rows[0]),
+                font,
+                widgets.disabled.text,
+                TextJustification::Left,
+            );
+        }
+
+        for (notification, rect) in notifications.iter().rev().zip(self.rows.iter()) {
+            let rect = text_rect(rect);
+            let datetime = notification.datetime;
+            let timestamp = format!("{:02}:{:02}", datetime.hour(), datetime.minute());
+            draw_line_in_rect(
+                fb,
+                &timestamp,
+                &rect,
+                font,
+                widgets.disabled.text,
+                TextJustification::Left,
+            );
+
+            let message_rect = Rect {
+                x0: rect.x0 + TIMESTAMP_W as i64,
+                w: rect.w.saturating_sub(TIMESTAMP_W),
+                ..rect
+            };
+            let message = format!("{}: {}", notification.app_name, notification.text);
+            let message = ellipsize_text(&message, font, message_rect.w);
+            draw_line_in_rect(
+                fb,
+                &message,
+                &message_rect,
+                font,
+                stylesheet.colors.text,
+                TextJustification::Left,
+            );
+        }
+
+        let clear_colors = match (notifications.is_empty(), clear_hovered) {
+            (true, _) => &widgets.disabled,
+            (false, true) => &widgets.hover,
+            (false, false) => &widgets.normal,
+        };
+        draw_rect(fb, &self.clear_rect, clear_colors.bg, false);
+        draw_line_in_rect(
+            fb,
+            "Clear all",
+            &self.clear_rect,
+            font,
+            clear_colors.text,
+            TextJustification::Center,
+        );
+
+        draw_rect_outline(fb, &self.rect, Color::BLACK, false, stylesheet.margin);
+    }
+}
+
+// Month of the given date, weeks start on Monday
+pub struct CalendarPopup {
+    pub rect: Rect,
+}
+
+impl CalendarPopup {
+    pub fn new(tray: &Tray) -> Self {
+        // Title and weekdays, then enough weeks for any month
+        let w = WEEKDAYS.len() as u32 * CALENDAR_CELL_W;
+        let h = (CALENDAR_WEEKS + 2) * CALENDAR_CELL_H;
+        let rect = tray.popup_rect(TrayItem::Clock, w, h);
+
+        CalendarPopup { rect }
+    }
+
+    pub fn draw<F: FbViewMut>(
+        &self,
+        fb: &mut F,
+        stylesheet: &StyleSheet,
+        font: &Font,
+        date: NaiveDate,
+    ) {
+        let widgets = &stylesheet.widgets;
+
+        draw_rect(fb, &self.rect, stylesheet.colors.background, false);
+
+        let cell_rect = |col: u32, row: u32| Rect {
+            x0: self.rect.x0 + (col * CALENDAR_CELL_W) as i64,
+            y0: self.rect.y0 + (row * CALENDAR_CELL_H) as i64,
+            w: CALENDAR_CELL_W,
+            h: CALENDAR_CELL_H,
+        };
+
+        let month_str = Month::try_from(date.month() as u8).unwrap().name();
+        let title = format!("{} {}", month_str, date.year());
+        let title_rect = Rect {
+            h: CALENDAR_CELL_H,
+            ..self.rect.clone()
+        };
+        draw_line_in_rect(
+            fb,
+            &title,
+            &title_rect,
+            font,
+            stylesheet.colors.text,
+            TextJustification::Center,
+        );
+
+        for (col, weekday) in WEEKDAYS.iter().enumerate() {
+            draw_line_in_rect(
+                fb,
+                weekday,
+                &cell_rect(col as u32, 1),
+                font,
+                widgets.disabled.text,
+                TextJustification::Center,
+            );
+        }
+
+        let first = date.with_day(1).unwrap();
+        let nb_days = first
+            .checked_add_months(Months::new(1))
+            .and_then(|next| next.pred_opt())
+            .map_or(31, |last| last.day());
+        let offset = first.weekday().num_days_from_monday();
+
+        for day in 1..=nb_days {
+            let i = offset + day - 1;
+            let rect = cell_rect(i % 7, 2 + i / 7);
+
+            let text_color = match day == date.day() {
+                true => {
+                    draw_rect(fb, &rect, widgets.selected.bg, false);
+                    widgets.selected.text
+                }
+                false => stylesheet.colors.text,
+            };
+            draw_line_in_rect(
+                fb,
+                &format!("{}", day),
+                &rect,
+                font,
+                text_color,
+                TextJustification::Center,
+            );
+        }
+
+        draw_rect_outline(fb, &self.rect, Color::BLACK, false, stylesheet.margin);
+    }
+}
@@ -0,0 +1,351 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::ptr::{addr_of, read_volatile};
+
+use super::{QueueMessage, VirtioDevice, VirtioQueue, VirtqSerializable};
+use crate::block::{BlockDevice, BlockError, Sector, SECTOR_SIZE};
+use crate::pci::PciDevice;
+
+// QEMU's default, requests take 3 descriptors
+const Q_SIZE: usize = 256;
+const BUF_SIZE: usize = core::mem::size_of::<BlockBuffer>();
+
+// Modern and transitional device IDs
+const DEVICE_IDS: [u16; 2] = [0x1040 + 2, 0x1001];
+
+// Sectors kept in memory, the least recently used are dropped first
+const CACHE_SECTORS: usize = 256;
+
+const HEADER_LEN: usize = 16;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+
+const VIRTIO_BLK_S_OK: u8 = 0;
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+#[repr(u32)]
+#[allow(non_camel_case_types)]
+enum BlockFeatureBits {
+    VIRTIO_BLK_F_RO = 0x1 << 5,
+    VIRTIO_BLK_F_FLUSH = 0x1 << 9,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct VirtioBlkConfig {
+    // In 512-byte sectors, whatever the block size of the device
+    capacity: u64,
+}
+
+pub struct VirtioBlock {
+    pub virtio_dev: VirtioDevice,
+    requestq: VirtioQueue<Q_SIZE, BUF_SIZE>,
+    nb_sectors: u64,
+    read_only: bool,
+    cache: SectorCache,
+    // Writes submitted and not completed yet
+    in_flight: usize,
+    // Since the last sync
+    failed_writes: usize,
+}
+
+impl VirtioBlock {
+    // None without a virtio-blk device
+    pub fn new(pci_devices: &mut Vec<PciDevice>) -> Option<Self> {
+        let i = (0..pci_devices.len()).find(|&i| {
+            pci_devices[i].vendor_id == 0x1af4 && DEVICE_IDS.contains(&pci_devices[i].device_id)
+        })?;
+
+        let pci_dev = pci_devices.swap_remove(i);
+        let feature_bits =
+            BlockFeatureBits::VIRTIO_BLK_F_RO as u32 | BlockFeatureBits::VIRTIO_BLK_F_FLUSH as u32;
+        let mut virtio_dev = VirtioDevice::new(pci_dev, feature_bits);
+
+        let requestq = virtio_dev.initialize_queue(0); // queue 0 (requestq)
+        virtio_dev.write_status(0x04); // DRIVER_OK
+
+        let config = virtio_dev.device_specific_config_ptr::<VirtioBlkConfig>();
+        let nb_sectors = unsafe { read_volatile(addr_of!((*config).capacity)) };
+        let read_only = virtio_dev.has_feature(BlockFeatureBits::VIRTIO_BLK_F_RO as u32);
+
+        log::info!(
+            "Block device of {} sectors ({} MB){}",
+            nb_sectors,
+            nb_sectors * SECTOR_SIZE as u64 / 1_000_000,
+            if read_only { ", read-only" } else { "" }
+        );
+
+        Some(VirtioBlock {
+            virtio_dev,
+            requestq,
+            nb_sectors,
+            read_only,
+            cache: SectorCache::new(),
+            in_flight: 0,
+            failed_writes: 0,
+        })
+    }
+
+    // Reads sector 0, and with `write`, writes a pattern to the last sector and reads it
+    // back from the device. That sector is restored after.
+    pub fn self_test(&mut self, write: bool) -> Result<(), BlockError> {
+        let mut sector = [0; SECTOR_SIZE];
+        self.read_sector(0, &mut sector)?;
+        match sector[SECTOR_SIZE - 2..] == [0x55, 0xaa] {
+            true => log::info!("Read sector 0, it has a boot signature"),
+            false => log::info!("Read sector 0, it has no boot signature"),
+        }
+
+        if !write {
+            return Ok(());
+        }
+
+        let lba = self.nb_sectors - 1;
+        let mut original = [0; SECTOR_SIZE];
+        self.read_sector(lba, &mut original)?;
+
+        let pattern: Sector = core::array::from_fn(|i| i as u8 ^ 0xa5);
+        self.write_sector(lba, &pattern)?;
+        self.sync()?;
+
+        // So that it comes from the device
+        self.cache.clear();
+        let mut read_back = [0; SECTOR_SIZE];
+        self.read_sector(lba, &mut read_back)?;
+
+        self.write_sector(lba, &original)?;
+        self.sync()?;
+
+        if read_back != pattern {
+            log::error!("Scratch sector {} did not read back as written", lba);
+            return Err(BlockError::Io);
+        }
+        log::info!("Scratch sector {} written and read back", lba);
+
+        Ok(())
+    }
+
+    fn check_lba(&self, lba: u64) -> Result<(), BlockError> {
+        match lba < self.nb_sectors {
+            true => Ok(()),
+            false => Err(BlockError::OutOfRange),
+        }
+    }
+
+    // Waits for descriptors to be handed back when the queue is full, nothing is dropped
+    fn push<const N: usize>(&mut self, messages: &[QueueMessage<BlockBuffer>; N]) {
+        unsafe {
+            while self.requestq.try_push(messages).is_none() {
+                self.reap_writes();
+                core::hint::spin_loop();
+            }
+            self.requestq.notify_device();
+        }
+    }
+
+    // Only valid with no write in flight, completions are not told apart
+    fn wait<const N: usize>(&mut self) -> [BlockBuffer; N] {
+        loop {
+            if let Some(buffers) = unsafe { self.requestq.try_pop::<BlockBuffer, N>() } {
+                return buffers;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn reap_writes(&mut self) {
+        while let Some([header, _, status]) = unsafe { self.requestq.try_pop::<BlockBuffer, 3>() } {
+            self.in_flight -= 1;
+            if status.0[0] != VIRTIO_BLK_S_OK {
+                // The cached copy is not what the device has
+                let lba = header.lba();
+                log::error!("Write of sector {} failed", lba);
+                self.cache.remove(lba);
+                self.failed_writes += 1;
+            }
+        }
+    }
+
+    fn wait_writes(&mut self) {
+        while self.in_flight > 0 {
+            self.reap_writes();
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl BlockDevice for VirtioBlock {
+    fn nb_sectors(&self) -> u64 {
+        self.nb_sectors
+    }
+
+    fn read_sector(&mut self, lba: u64, buf: &mut Sector) -> Result<(), BlockError> {
+        self.check_lba(lba)?;
+
+        if let Some(sector) = self.cache.get(lba) {
+            *buf = *sector;
+            return Ok(());
+        }
+
+        // Requests in flight may complete in any order, so an earlier write of that
+        // sector has to land first
+        self.wait_writes();
+
+        self.push(&[
+            QueueMessage::DevReadOnly {
+                data: BlockBuffer::header(VIRTIO_BLK_T_IN, lba),
+                len: Some(HEADER_LEN),
+            },
+            QueueMessage::DevWriteOnly,
+            QueueMessage::DevWriteOnlyLen { len: 1 },
+        ]);
+        let [_, data, status] = self.wait::<3>();
+        check_status(status.0[0])?;
+
+        *buf = data.0;
+        self.cache.insert(lba, buf);
+
+        Ok(())
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &Sector) -> Result<(), BlockError> {
+        self.check_lba(lba)?;
+        if self.read_only {
+            return Err(BlockError::ReadOnly);
+        }
+
+        self.push(&[
+            QueueMessage::DevReadOnly {
+                data: BlockBuffer::header(VIRTIO_BLK_T_OUT, lba),
+                len: Some(HEADER_LEN),
+            },
+            QueueMessage::DevReadOnly {
+                data: BlockBuffer(*buf),
+                len: None,
+            },
+            QueueMessage::DevWriteOnlyLen { len: 1 },
+        ]);
+        self.in_flight += 1;
+        self.cache.insert(lba, buf);
+
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), BlockError> {
+        self.wait_writes();
+        let failed_writes = core::mem::take(&mut self.failed_writes);
+
+        // Without the feature, the device completes writes only once they are persisted
+        if self
+            .virtio_dev
+            .has_feature(BlockFeatureBits::VIRTIO_BLK_F_FLUSH as u32)
+        {
+            self.push(&[
+                QueueMessage::DevReadOnly {
+                    data: BlockBuffer::header(VIRTIO_BLK_T_FLUSH, 0),
+                    len: Some(HEADER_LEN),
+                },
+                QueueMessage::DevWriteOnlyLen { len: 1 },
+            ]);
+            let [_, status] = self.wait::<2>();
+            check_status(status.0[0])?;
+        }
+
+        match failed_writes {
+            0 => Ok(()),
+            _ => Err(BlockError::Io),
+        }
+    }
+}
+
+fn check_status(status: u8) -> Result<(), BlockError> {
+    match status {
+        VIRTIO_BLK_S_OK => Ok(()),
+        VIRTIO_BLK_S_UNSUPP => Err(BlockError::Unsupported),
+        _ => Err(BlockError::Io),
+    }
+}
+
+// Write-through, so dropping a sector never loses data
+struct SectorCache {
+    sectors: BTreeMap<u64, Box<Sector>>,
+    // Least recently used first
+    order: VecDeque<u64>,
+}
+
+impl SectorCache {
+    fn new() -> Self {
+        SectorCache {
+            sectors: BTreeMap::new(),
+            order: VecDeque::with_capacity(CACHE_SECTORS),
+        }
+    }
+
+    fn get(&mut self, lba: u64) -> Option<&Sector> {
+        if !self.sectors.contains_key(&lba) {
+            return None;
+        }
+        self.touch(lba);
+        self.sectors.get(&lba).map(|sector| sector.as_ref())
+    }
+
+    fn insert(&mut self, lba: u64, data: &Sector) {
+        match self.sectors.get_mut(&lba) {
+            Some(sector) => **sector = *data,
+            None => {
+                if self.order.len() >= CACHE_SECTORS {
+                    if let Some(oldest) = self.order.pop_front() {
+                        self.sectors.remove(&oldest);
+                    }
+                }
+                self.sectors.insert(lba, Box::new(*data));
+            }
+        }
+        self.touch(lba);
+    }
+
+    fn remove(&mut self, lba: u64) {
+        self.sectors.remove(&lba);
+        self.order.retain(|other| *other != lba);
+    }
+
+    fn clear(&mut self) {
+        self.sectors.clear();
+        self.order.clear();
+    }
+
+    fn touch(&mut self, lba: u64) {
+        self.order.retain(|other| *other != lba);
+        self.order.push_back(lba);
+    }
+}
+
+// Requests are a header, the sector for reads and writes, then a status byte. Each one
+// is a descriptor of its own, backed by a buffer this large.
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct BlockBuffer(Sector);
+
+impl VirtqSerializable for BlockBuffer {}
+
+impl Default for BlockBuffer {
+    fn default() -> Self {
+        Self([0; SECTOR_SIZE])
+    }
+}
+
+impl BlockBuffer {
+    fn header(request_type: u32, lba: u64) -> Self {
+        let mut buffer = BlockBuffer::default();
+        buffer.0[0..4].copy_from_slice(&request_type.to_le_bytes());
+        buffer.0[8..16].copy_from_slice(&lba.to_le_bytes());
+        buffer
+    }
+
+    fn lba(&self) -> u64 {
+        u64::from_le_bytes(self.0[8..16].try_into().unwrap())
+    }
+}
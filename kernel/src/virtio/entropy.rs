@@ -0,0 +1,76 @@
+use super::{QueueMessage, VirtioDevice, VirtioQueue, VirtqSerializable};
+use crate::pci::PciDevice;
+use alloc::vec::Vec;
+
+// QEMU's virtio-rng has a single queue of that size
+const Q_SIZE: usize = 8;
+const BUF_SIZE: usize = core::mem::size_of::<EntropyBuffer>();
+
+// Modern and transitional device IDs
+const DEVICE_IDS: [u16; 2] = [0x1040 + 4, 0x1005];
+
+pub struct VirtioEntropy {
+    // Only kept to own the device, the queue is all that is used after initialization
+    _virtio_dev: VirtioDevice,
+    requestq: VirtioQueue<Q_SIZE, BUF_SIZE>,
+}
+
+impl VirtioEntropy {
+    // None without a virtio-rng device
+    pub fn new(pci_devices: &mut Vec<PciDevice>) -> Option<Self> {
+        let i = (0..pci_devices.len()).find(|&i| {
+            pci_devices[i].vendor_id == 0x1af4 && DEVICE_IDS.contains(&pci_devices[i].device_id)
+        })?;
+
+        let pci_dev = pci_devices.swap_remove(i);
+        let mut virtio_dev = VirtioDevice::new(pci_dev, 0x0);
+
+        let mut requestq = virtio_dev.initialize_queue(0); // queue 0 (requestq)
+        virtio_dev.write_status(0x04); // DRIVER_OK
+
+        // The device fills them in the background, they are handed back as they come in
+        let msg = [QueueMessage::<EntropyBuffer>::DevWriteOnly];
+        unsafe {
+            while requestq.try_push(&msg).is_some() {}
+            requestq.notify_device();
+        }
+
+        Some(VirtioEntropy {
+            _virtio_dev: virtio_dev,
+            requestq,
+        })
+    }
+
+    // Calls `f` with the bytes of each buffer the device filled since the last call.
+    // Never waits for the device.
+    pub fn poll<F: FnMut(&[u8])>(&mut self, mut f: F) {
+        let mut refilled = false;
+
+        while let Some(([buffer], len)) = unsafe { self.requestq.try_pop_len::<_, 1>() } {
+            let EntropyBuffer(data) = buffer;
+            f(&data[..usize::min(len, BUF_SIZE)]);
+
+            unsafe {
+                self.requestq
+                    .try_push(&[QueueMessage::<EntropyBuffer>::DevWriteOnly]);
+            }
+            refilled = true;
+        }
+
+        if refilled {
+            unsafe { self.requestq.notify_device() };
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct EntropyBuffer([u8; 64]);
+
+impl VirtqSerializable for EntropyBuffer {}
+
+impl Default for EntropyBuffer {
+    fn default() -> Self {
+        Self([0; 64])
+    }
+}
@@ -1,21 +1,121 @@
 use alloc::{boxed::Box, vec, vec::Vec};
+use applib::{Color, Rect};
 
-use crate::memory;
 use crate::pci::PciDevice;
+use crate::{interrupts, memory};
 use core::mem::MaybeUninit;
+use core::ptr::{addr_of_mut, write_volatile};
 
 use super::{QueueMessage, VirtioDevice, VirtioQueue, VirtqSerializable};
 
-pub const W: usize = 1366;
-pub const H: usize = 768;
+// Mode the display comes up in, others are switched to with set_mode()
+pub const DEFAULT_MODE: (usize, usize) = (1366, 768);
+
+// Offered next to those the device reports, if they fit in its preferred mode
+const FALLBACK_MODES: [(u32, u32); 6] = [
+    (1920, 1080),
+    (1600, 900),
+    (1366, 768),
+    (1280, 720),
+    (1024, 768),
+    (800, 600),
+];
 
 const Q_SIZE: usize = 64;
 const BUF_SIZE: usize = core::mem::size_of::<GpuVirtioMsg>();
+const CURSOR_Q_SIZE: usize = 16;
+const CURSOR_BUF_SIZE: usize = core::mem::size_of::<VirtioGpuUpdateCursor>();
+
+// Side of the cursor image, the only size the device takes
+pub const CURSOR_SIZE: u32 = 64;
+const CURSOR_RESOURCE_ID: u32 = 0x2;
+
+// The framebuffer resource alternates between those two, so that the new one is set
+// up before the old one is released
+const FRAMEBUFFER_RESOURCE_IDS: [u32; 2] = [0x1, 0x3];
+
+#[repr(u32)]
+#[allow(non_camel_case_types)]
+enum GpuFeatureBits {
+    VIRTIO_GPU_F_EDID = 0x1 << 1,
+}
+
+const VIRTIO_GPU_EVENT_DISPLAY: u32 = 0x1;
+
+// In order of preference, the first one the device accepts is used
+const SCANOUT_FORMATS: [PixelFormat; 3] = [PixelFormat::Rgba, PixelFormat::Bgrx, PixelFormat::Bgra];
 
 pub struct VirtioGPU {
     pub virtio_dev: VirtioDevice,
+    // Pixels in the scanout format
     pub framebuffer: Box<[u8]>,
+    pub format: PixelFormat,
+    width: usize,
+    height: usize,
+    framebuffer_resource_id: u32,
     controlq: VirtioQueue<Q_SIZE, BUF_SIZE>,
+    // None without a usable cursor plane, the cursor is drawn in the framebuffer then
+    cursorq: Option<VirtioQueue<CURSOR_Q_SIZE, CURSOR_BUF_SIZE>>,
+    // Backing of the cursor resource, in BGRA
+    cursor_image: Box<[u8]>,
+    // Raised for display changes, None if the config has to be polled
+    config_vector: Option<u8>,
+}
+
+// What VirtioGPU::on_config_change() found
+#[derive(Debug, Default)]
+pub struct ConfigChange {
+    // The preferred mode, if the display changed (e.g. the QEMU window was resized)
+    pub mode: Option<(u32, u32)>,
+    // Whether the device was reset
+    pub reset: bool,
+}
+
+// Byte order of the pixels of the scanout. applib always draws in RGBA, the order of
+// the bytes of Color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgba,
+    Bgra,
+    // The last byte is ignored
+    Bgrx,
+}
+
+impl PixelFormat {
+    fn virtio_format(&self) -> u32 {
+        match self {
+            PixelFormat::Rgba => 67, // VIRTIO_GPU_FORMAT_R8G8B8A8_UNORM
+            PixelFormat::Bgra => 1,  // VIRTIO_GPU_FORMAT_B8G8R8A8_UNORM
+            PixelFormat::Bgrx => 2,  // VIRTIO_GPU_FORMAT_B8G8R8X8_UNORM
+        }
+    }
+
+    // From an applib color, for drawing right into the scanout framebuffer
+    pub fn convert_color(&self, color: Color) -> Color {
+        let [r, g, b, a] = color.0;
+        match self {
+            PixelFormat::Rgba => color,
+            PixelFormat::Bgra | PixelFormat::Bgrx => Color([b, g, r, a]),
+        }
+    }
+
+    // From applib colors. The format is matched once for the whole row.
+    fn convert_row(&self, dst: &mut [u8], src: &[Color]) {
+        let dst = dst.chunks_exact_mut(4);
+        match self {
+            PixelFormat::Rgba => {
+                for (dst, src) in dst.zip(src) {
+                    dst.copy_from_slice(&src.0);
+                }
+            }
+            PixelFormat::Bgra | PixelFormat::Bgrx => {
+                for (dst, src) in dst.zip(src) {
+                    let [r, g, b, a] = src.0;
+                    dst.copy_from_slice(&[b, g, r, a]);
+                }
+            }
+        }
+    }
 }
 
 #[repr(C)]
@@ -27,9 +127,21 @@ union GpuVirtioMsg {
     set_scanout: VirtioGpuSetScanout,
     transfer_to_host_2d: VirtioGpuTransferToHost2d,
     resource_flush: VirtioGpuResourceFlush,
+    resource_unref: VirtioGpuResourceUnref,
+    get_edid: VirtioGpuGetEdid,
+    resp_edid: VirtioGpuRespEdid,
     ctrl_hdr: VirtioGpuCtrlHdr,
 }
 
+impl VirtqSerializable for VirtioGpuUpdateCursor {}
+
+impl Default for VirtioGpuUpdateCursor {
+    fn default() -> Self {
+        let x = MaybeUninit::<Self>::zeroed();
+        unsafe { x.assume_init() }
+    }
+}
+
 // TODO: is there a cleaner way?
 impl Default for GpuVirtioMsg {
     fn default() -> Self {
@@ -49,20 +161,32 @@ impl VirtioGPU {
             .expect("Cannot find VirtIO GPU device");
 
         let pci_dev = pci_devices.swap_remove(i);
-        let mut virtio_dev = VirtioDevice::new(pci_dev, 0x0);
+        let feature_bits = GpuFeatureBits::VIRTIO_GPU_F_EDID as u32;
+        let mut virtio_dev = VirtioDevice::new(pci_dev, feature_bits);
 
         let controlq = virtio_dev.initialize_queue(0); // queue 0 (controlq)
+        let cursorq = virtio_dev.try_initialize_queue(1); // queue 1 (cursorq)
+        let config_vector = virtio_dev.set_config_vector("virtio-gpu config");
         virtio_dev.write_status(0x04); // DRIVER_OK
 
+        let (width, height) = DEFAULT_MODE;
+
         VirtioGPU {
             virtio_dev,
-            framebuffer: vec![0u8; W * H * 4].into_boxed_slice(),
+            framebuffer: vec![0u8; width * height * 4].into_boxed_slice(),
+            format: PixelFormat::Rgba,
+            width,
+            height,
+            framebuffer_resource_id: FRAMEBUFFER_RESOURCE_IDS[0],
             controlq,
+            cursorq,
+            cursor_image: vec![0u8; (CURSOR_SIZE * CURSOR_SIZE * 4) as usize].into_boxed_slice(),
+            config_vector,
         }
     }
 
     pub fn get_dims(&self) -> (usize, usize) {
-        (W, H)
+        (self.width, self.height)
     }
 
     /*
@@ -111,21 +235,16 @@ impl VirtioGPU {
 
     fn send_command(&mut self, input: GpuVirtioMsg) -> GpuVirtioMsg {
         unsafe {
-            self.controlq
-                .try_push(&[
-                    QueueMessage::DevReadOnly {
-                        data: input,
-                        len: None,
-                    },
-                    QueueMessage::DevWriteOnly,
-                ])
-                .unwrap();
+            self.controlq.try_push(&command_messages(input)).unwrap();
             self.controlq.notify_device();
         }
+        self.wait_reply()
+    }
 
+    // TODO: check response status code
+    fn wait_reply(&mut self) -> GpuVirtioMsg {
         loop {
             if let Some(resp_list) = unsafe { self.controlq.try_pop::<_, 2>() } {
-                // TODO: check response status code
                 break resp_list[1];
             }
         }
@@ -133,17 +252,37 @@ impl VirtioGPU {
 
     fn send_command_noreply(&mut self, input: GpuVirtioMsg) -> Option<()> {
         let resp = self.send_command(input);
-        let resp: VirtioGpuCtrlHdr = unsafe { resp.ctrl_hdr };
-        if resp._type == VirtioGpuCtrlType::VIRTIO_GPU_RESP_OK_NODATA as u32 {
-            Some(())
-        } else {
-            log::debug!("Resp type: 0x{:x}", resp._type);
-            None
+        check_nodata(&resp)
+    }
+
+    // Pushed one after the other without waiting for the replies in between, the device
+    // works through the first ones while the next are queued. None if any failed.
+    fn send_commands_noreply(
+        &mut self,
+        commands: impl Iterator<Item = GpuVirtioMsg>,
+    ) -> Option<()> {
+        let mut in_flight = 0;
+        let mut all_ok = true;
+
+        for input in commands {
+            let messages = command_messages(input);
+            while unsafe { self.controlq.try_push(&messages) }.is_none() {
+                // The queue is full, the oldest command has to complete first
+                all_ok &= check_nodata(&self.wait_reply()).is_some();
+                in_flight -= 1;
+            }
+            unsafe { self.controlq.notify_device() };
+            in_flight += 1;
+        }
+
+        for _ in 0..in_flight {
+            all_ok &= check_nodata(&self.wait_reply()).is_some();
         }
+
+        all_ok.then_some(())
     }
 
-    #[allow(dead_code)]
-    pub fn get_display_info(&mut self) -> VirtioGpuRespDisplayInfo {
+    pub fn get_display_info(&mut self) -> Option<VirtioGpuRespDisplayInfo> {
         let res = self.send_command(GpuVirtioMsg {
             ctrl_hdr: VirtioGpuCtrlHdr {
                 _type: VirtioGpuCtrlType::VIRTIO_GPU_CMD_GET_DISPLAY_INFO as u32,
@@ -151,12 +290,213 @@ impl VirtioGPU {
             },
         });
 
-        unsafe { res.resp_display_info }
+        let res = unsafe { res.resp_display_info };
+        match res.hdr._type == VirtioGpuCtrlType::VIRTIO_GPU_RESP_OK_DISPLAY_INFO as u32 {
+            true => Some(res),
+            false => None,
+        }
     }
 
+    // None if the device does not offer EDID
+    fn get_edid(&mut self) -> Option<VirtioGpuRespEdid> {
+        if !self
+            .virtio_dev
+            .has_feature(GpuFeatureBits::VIRTIO_GPU_F_EDID as u32)
+        {
+            return None;
+        }
+
+        let res = self.send_command(GpuVirtioMsg {
+            get_edid: VirtioGpuGetEdid {
+                hdr: VirtioGpuCtrlHdr {
+                    _type: VirtioGpuCtrlType::VIRTIO_GPU_CMD_GET_EDID as u32,
+                    ..VirtioGpuCtrlHdr::default()
+                },
+                scanout: 0,
+                padding: 0x0,
+            },
+        });
+
+        let res = unsafe { res.resp_edid };
+        match res.hdr._type == VirtioGpuCtrlType::VIRTIO_GPU_RESP_OK_EDID as u32 {
+            true => Some(res),
+            false => None,
+        }
+    }
+
+    // Modes of the first scanout, the one the device prefers (its window size under
+    // QEMU) first, then the others from largest to smallest
+    pub fn display_modes(&mut self) -> Vec<(u32, u32)> {
+        let preferred = self
+            .get_display_info()
+            .map(|info| info.pmodes[0])
+            .filter(|pmode| pmode.enabled != 0 && pmode.r.width > 0 && pmode.r.height > 0)
+            .map(|pmode| (pmode.r.width, pmode.r.height));
+
+        let mut modes = match self.get_edid() {
+            Some(edid) => {
+                let size = usize::min(edid.size as usize, edid.edid.len());
+                parse_edid_modes(&edid.edid[..size])
+            }
+            None => Vec::new(),
+        };
+
+        let (max_w, max_h) = preferred.unwrap_or((u32::MAX, u32::MAX));
+        modes.extend(
+            FALLBACK_MODES
+                .into_iter()
+                .filter(|&(w, h)| w <= max_w && h <= max_h),
+        );
+        modes.sort_by(|a, b| (b.0 * b.1).cmp(&(a.0 * a.1)).then(b.0.cmp(&a.0)));
+        modes.dedup();
+
+        if let Some(preferred) = preferred {
+            modes.retain(|mode| *mode != preferred);
+            modes.insert(0, preferred);
+        }
+
+        modes
+    }
+
+    // Called every frame. When drivers wait for interrupts, the config is only read
+    // after a config change one.
+    pub fn on_config_change(&mut self) -> ConfigChange {
+        if let Some(vector) = self.config_vector {
+            if interrupts::take(vector).is_none() && interrupts::armed() {
+                return ConfigChange::default();
+            }
+        }
+
+        let reset = self.virtio_dev.needs_reset();
+        if reset {
+            self.reset();
+        }
+
+        let config: VirtioGpuConfig = self.virtio_dev.read_config();
+        let display_changed = config.events_read & VIRTIO_GPU_EVENT_DISPLAY != 0;
+        if display_changed {
+            let config = self
+                .virtio_dev
+                .device_specific_config_ptr::<VirtioGpuConfig>();
+            unsafe {
+                write_volatile(
+                    addr_of_mut!((*config).events_clear),
+                    VIRTIO_GPU_EVENT_DISPLAY,
+                )
+            };
+        }
+
+        // The display may have been reconfigured along with the reset
+        let mode = match display_changed || reset {
+            true => self.display_modes().first().copied(),
+            false => None,
+        };
+
+        ConfigChange { mode, reset }
+    }
+
+    // The host forgets every resource, they are created again from what the guest still
+    // has. The cursor image has to be uploaded again.
+    fn reset(&mut self) {
+        let VirtioGPU {
+            virtio_dev,
+            controlq,
+            cursorq,
+            ..
+        } = self;
+        virtio_dev.reset_and_reinit(|dev| {
+            dev.restore_queue(controlq);
+            if let Some(cursorq) = cursorq.as_mut() {
+                dev.restore_queue(cursorq);
+            }
+        });
+
+        let resource_id = self.framebuffer_resource_id;
+        let (w, h) = (self.width as u32, self.height as u32);
+        let restored = self
+            .create_framebuffer_resource(resource_id, self.format, w, h)
+            .and_then(|()| self.attach_framebuffer(resource_id, w, h));
+        if restored.is_none() {
+            log::error!("Cannot restore the GPU framebuffer");
+            return;
+        }
+
+        if self.cursorq.is_some() {
+            self.init_cursor();
+        }
+
+        self.flush();
+    }
+
+    // The scanout format is the first of SCANOUT_FORMATS the device can create a
+    // resource with
     pub fn init_framebuffer(&mut self) {
-        let resource_id = 0x1;
+        let resource_id = self.framebuffer_resource_id;
+        let (w, h) = (self.width as u32, self.height as u32);
+
+        self.format = SCANOUT_FORMATS
+            .into_iter()
+            .find(|format| {
+                let created = self.create_framebuffer_resource(resource_id, *format, w, h);
+                if created.is_none() {
+                    log::warn!("GPU scanout format {:?} not supported", format);
+                }
+                created.is_some()
+            })
+            .expect("No supported GPU scanout format");
+
+        self.attach_framebuffer(resource_id, w, h).unwrap();
+    }
+
+    // Returns false if the device rejects the mode, the current one is kept then
+    pub fn set_mode(&mut self, w: usize, h: usize) -> bool {
+        let old_resource_id = self.framebuffer_resource_id;
+        let resource_id = match old_resource_id == FRAMEBUFFER_RESOURCE_IDS[0] {
+            true => FRAMEBUFFER_RESOURCE_IDS[1],
+            false => FRAMEBUFFER_RESOURCE_IDS[0],
+        };
+
+        if self
+            .create_framebuffer_resource(resource_id, self.format, w as u32, h as u32)
+            .is_none()
+        {
+            log::error!("GPU rejected a {}x{} framebuffer", w, h);
+            return false;
+        }
 
+        // Kept until the old resource no longer uses it
+        let old_framebuffer = core::mem::replace(
+            &mut self.framebuffer,
+            vec![0u8; w * h * 4].into_boxed_slice(),
+        );
+
+        if self
+            .attach_framebuffer(resource_id, w as u32, h as u32)
+            .is_none()
+        {
+            log::error!("GPU rejected a {}x{} scanout", w, h);
+            self.unref_resource(resource_id);
+            self.framebuffer = old_framebuffer;
+            return false;
+        }
+
+        self.unref_resource(old_resource_id);
+        drop(old_framebuffer);
+
+        self.framebuffer_resource_id = resource_id;
+        self.width = w;
+        self.height = h;
+
+        true
+    }
+
+    fn create_framebuffer_resource(
+        &mut self,
+        resource_id: u32,
+        format: PixelFormat,
+        w: u32,
+        h: u32,
+    ) -> Option<()> {
         self.send_command_noreply(GpuVirtioMsg {
             resource_create_2d: VirtioGpuResourceCreate2d {
                 hdr: VirtioGpuCtrlHdr {
@@ -164,13 +504,15 @@ impl VirtioGPU {
                     ..VirtioGpuCtrlHdr::default()
                 },
                 resource_id,
-                format: 67, // RGBA,
-                width: W as u32,
-                height: H as u32,
+                format: format.virtio_format(),
+                width: w,
+                height: h,
             },
         })
-        .unwrap();
+    }
 
+    // Backs the resource with self.framebuffer and shows it on the scanout
+    fn attach_framebuffer(&mut self, resource_id: u32, w: u32, h: u32) -> Option<()> {
         let fb_addr = memory::get_mapper()
             .ref_to_phys(self.framebuffer.as_ref())
             .as_u64();
@@ -193,8 +535,7 @@ impl VirtioGPU {
                     entries
                 },
             },
-        })
-        .unwrap();
+        })?;
 
         self.send_command_noreply(GpuVirtioMsg {
             set_scanout: VirtioGpuSetScanout {
@@ -205,20 +546,91 @@ impl VirtioGPU {
                 r: VirtioGpuRect {
                     x: 0,
                     y: 0,
-                    width: W as u32,
-                    height: H as u32,
+                    width: w,
+                    height: h,
                 },
                 scanout_id: 0,
                 resource_id,
             },
         })
-        .unwrap();
     }
 
-    pub fn flush(&mut self) {
-        let resource_id = 0x1;
+    // Also detaches its backing
+    fn unref_resource(&mut self, resource_id: u32) {
+        let unrefed = self.send_command_noreply(GpuVirtioMsg {
+            resource_unref: VirtioGpuResourceUnref {
+                hdr: VirtioGpuCtrlHdr {
+                    _type: VirtioGpuCtrlType::VIRTIO_GPU_CMD_RESOURCE_UNREF as u32,
+                    ..VirtioGpuCtrlHdr::default()
+                },
+                resource_id,
+                padding: 0x0,
+            },
+        });
+        if unrefed.is_none() {
+            log::error!("Cannot release GPU resource {}", resource_id);
+        }
+    }
 
-        self.send_command_noreply(GpuVirtioMsg {
+    // Returns false if the device has no cursor queue or rejects the cursor resource
+    pub fn init_cursor(&mut self) -> bool {
+        if self.cursorq.is_none() {
+            return false;
+        }
+
+        let created = self.send_command_noreply(GpuVirtioMsg {
+            resource_create_2d: VirtioGpuResourceCreate2d {
+                hdr: VirtioGpuCtrlHdr {
+                    _type: VirtioGpuCtrlType::VIRTIO_GPU_CMD_RESOURCE_CREATE_2D as u32,
+                    ..VirtioGpuCtrlHdr::default()
+                },
+                resource_id: CURSOR_RESOURCE_ID,
+                format: PixelFormat::Bgra.virtio_format(),
+                width: CURSOR_SIZE,
+                height: CURSOR_SIZE,
+            },
+        });
+
+        let image_addr = memory::get_mapper()
+            .ref_to_phys(self.cursor_image.as_ref())
+            .as_u64();
+
+        let attached = created.and_then(|()| {
+            self.send_command_noreply(GpuVirtioMsg {
+                resource_attach_backing: VirtioGpuResourceAttachBacking {
+                    hdr: VirtioGpuCtrlHdr {
+                        _type: VirtioGpuCtrlType::VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING as u32,
+                        ..VirtioGpuCtrlHdr::default()
+                    },
+                    resource_id: CURSOR_RESOURCE_ID,
+                    nr_entries: 1,
+                    entries: {
+                        let mut entries = [VirtioGpuMemEntry::default(); MAX_MEM_PAGES];
+                        entries[0] = VirtioGpuMemEntry {
+                            addr: image_addr,
+                            length: self.cursor_image.len() as u32,
+                            padding: 0x0,
+                        };
+                        entries
+                    },
+                },
+            })
+        });
+
+        if attached.is_none() {
+            log::warn!("GPU cursor resource rejected");
+            self.cursorq = None;
+        }
+
+        attached.is_some()
+    }
+
+    // The image is CURSOR_SIZE pixels square, its hot spot is put at the pointer position
+    pub fn update_cursor(&mut self, image: &[Color], hot_spot: (u32, u32), pos: (u32, u32)) {
+        assert_eq!(image.len(), (CURSOR_SIZE * CURSOR_SIZE) as usize);
+        PixelFormat::Bgra.convert_row(&mut self.cursor_image, image);
+
+        let transferred = self.send_command_noreply(GpuVirtioMsg {
             transfer_to_host_2d: VirtioGpuTransferToHost2d {
                 hdr: VirtioGpuCtrlHdr {
                     _type: VirtioGpuCtrlType::VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D as u32,
@@ -227,33 +639,176 @@ impl VirtioGPU {
                 r: VirtioGpuRect {
                     x: 0,
                     y: 0,
-                    width: W as u32,
-                    height: H as u32,
+                    width: CURSOR_SIZE,
+                    height: CURSOR_SIZE,
+                },
+                offset: 0,
+                resource_id: CURSOR_RESOURCE_ID,
+                padding: 0x0,
+            },
+        });
+        if transferred.is_none() {
+            log::error!("Cannot upload the cursor image");
+        }
+
+        self.send_cursor_command(
+            VirtioGpuCtrlType::VIRTIO_GPU_CMD_UPDATE_CURSOR,
+            pos,
+            hot_spot,
+        );
+    }
+
+    pub fn move_cursor(&mut self, pos: (u32, u32)) {
+        self.send_cursor_command(VirtioGpuCtrlType::VIRTIO_GPU_CMD_MOVE_CURSOR, pos, (0, 0));
+    }
+
+    // The device does not reply to cursor commands, their buffers are taken back before
+    // the next one is sent
+    fn send_cursor_command(
+        &mut self,
+        command: VirtioGpuCtrlType,
+        pos: (u32, u32),
+        hot_spot: (u32, u32),
+    ) {
+        let Some(cursorq) = self.cursorq.as_mut() else {
+            return;
+        };
+
+        let msg = VirtioGpuUpdateCursor {
+            hdr: VirtioGpuCtrlHdr {
+                _type: command as u32,
+                ..VirtioGpuCtrlHdr::default()
+            },
+            pos: VirtioGpuCursorPos {
+                scanout_id: 0,
+                x: pos.0,
+                y: pos.1,
+                padding: 0x0,
+            },
+            resource_id: CURSOR_RESOURCE_ID,
+            hot_x: hot_spot.0,
+            hot_y: hot_spot.1,
+            padding: 0x0,
+        };
+
+        unsafe {
+            while cursorq.try_pop::<VirtioGpuUpdateCursor, 1>().is_some() {}
+            match cursorq.try_push(&[QueueMessage::DevReadOnly {
+                data: msg,
+                len: None,
+            }]) {
+                Some(()) => cursorq.notify_device(),
+                // The device is behind, the next move makes up for this one
+                None => log::debug!("GPU cursor queue full"),
+            }
+        }
+    }
+
+    // Converts those parts of src, in applib colors, into the scanout format
+    pub fn blit_regions(&mut self, src: &[Color], regions: &[Rect]) {
+        assert_eq!(src.len(), self.width * self.height);
+        let screen_rect = Rect {
+            x0: 0,
+            y0: 0,
+            w: self.width as u32,
+            h: self.height as u32,
+        };
+
+        for rect in regions
+            .iter()
+            .filter_map(|rect| rect.intersection(&screen_rect))
+        {
+            let (x0, w) = (rect.x0 as usize, rect.w as usize);
+            for y in rect.y0 as usize..(rect.y0 as usize + rect.h as usize) {
+                let i = y * self.width + x0;
+                self.format
+                    .convert_row(&mut self.framebuffer[i * 4..(i + w) * 4], &src[i..i + w]);
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        self.flush_regions(&[Rect {
+            x0: 0,
+            y0: 0,
+            w: self.width as u32,
+            h: self.height as u32,
+        }]);
+    }
+
+    // Only uploads and displays those parts of the framebuffer
+    pub fn flush_regions(&mut self, regions: &[Rect]) {
+        let resource_id = self.framebuffer_resource_id;
+        let screen_rect = Rect {
+            x0: 0,
+            y0: 0,
+            w: self.width as u32,
+            h: self.height as u32,
+        };
+
+        // Clipped as they are sent, nothing is allocated so that the panic screen can
+        // flush too
+        let clipped_regions = || {
+            regions
+                .iter()
+                .filter_map(|rect| rect.intersection(&screen_rect))
+                .map(|rect| VirtioGpuRect {
+                    x: rect.x0 as u32,
+                    y: rect.y0 as u32,
+                    width: rect.w,
+                    height: rect.h,
+                })
+        };
+
+        let width = self.width;
+        let transfers = clipped_regions().map(|r| GpuVirtioMsg {
+            transfer_to_host_2d: VirtioGpuTransferToHost2d {
+                hdr: VirtioGpuCtrlHdr {
+                    _type: VirtioGpuCtrlType::VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D as u32,
+                    ..VirtioGpuCtrlHdr::default()
                 },
-                offset: 0x0,
+                r,
+                // Where the region starts in the backing memory
+                offset: ((r.y as usize * width + r.x as usize) * 4) as u64,
                 resource_id,
                 padding: 0x0,
             },
-        })
-        .unwrap();
+        });
+        self.send_commands_noreply(transfers).unwrap();
 
-        self.send_command_noreply(GpuVirtioMsg {
+        // Only once every transfer is done, so that no region is flushed half uploaded
+        let flushes = clipped_regions().map(|r| GpuVirtioMsg {
             resource_flush: VirtioGpuResourceFlush {
                 hdr: VirtioGpuCtrlHdr {
                     _type: VirtioGpuCtrlType::VIRTIO_GPU_CMD_RESOURCE_FLUSH as u32,
                     ..VirtioGpuCtrlHdr::default()
                 },
-                r: VirtioGpuRect {
-                    x: 0,
-                    y: 0,
-                    width: W as u32,
-                    height: H as u32,
-                },
+                r,
                 resource_id,
                 padding: 0x0,
             },
-        })
-        .unwrap();
+        });
+        self.send_commands_noreply(flushes).unwrap();
+    }
+}
+
+fn command_messages(input: GpuVirtioMsg) -> [QueueMessage<GpuVirtioMsg>; 2] {
+    [
+        QueueMessage::DevReadOnly {
+            data: input,
+            len: None,
+        },
+        QueueMessage::DevWriteOnly,
+    ]
+}
+
+fn check_nodata(resp: &GpuVirtioMsg) -> Option<()> {
+    let resp: VirtioGpuCtrlHdr = unsafe { resp.ctrl_hdr };
+    if resp._type == VirtioGpuCtrlType::VIRTIO_GPU_RESP_OK_NODATA as u32 {
+        Some(())
+    } else {
+        log::debug!("Resp type: 0x{:x}", resp._type);
+        None
     }
 }
 
@@ -285,12 +840,18 @@ struct VirtioGpuCtrlHdr {
 enum VirtioGpuCtrlType {
     VIRTIO_GPU_CMD_GET_DISPLAY_INFO = 0x0100,
     VIRTIO_GPU_CMD_RESOURCE_CREATE_2D = 0x0101,
+    VIRTIO_GPU_CMD_RESOURCE_UNREF = 0x0102,
     VIRTIO_GPU_CMD_SET_SCANOUT = 0x0103,
     VIRTIO_GPU_CMD_RESOURCE_FLUSH = 0x0104,
     VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D = 0x0105,
     VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING = 0x0106,
+    VIRTIO_GPU_CMD_GET_EDID = 0x010a,
+    VIRTIO_GPU_CMD_UPDATE_CURSOR = 0x0300,
+    VIRTIO_GPU_CMD_MOVE_CURSOR = 0x0301,
 
     VIRTIO_GPU_RESP_OK_NODATA = 0x1100,
+    VIRTIO_GPU_RESP_OK_DISPLAY_INFO = 0x1101,
+    VIRTIO_GPU_RESP_OK_EDID = 0x1104,
 }
 
 #[repr(C)]
@@ -302,6 +863,17 @@ pub struct VirtioGpuRect {
     pub height: u32,
 }
 
+// Device specific config
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+#[allow(dead_code)]
+struct VirtioGpuConfig {
+    events_read: u32,
+    events_clear: u32,
+    num_scanouts: u32,
+    num_capsets: u32,
+}
+
 //
 // VIRTIO_GPU_CMD_GET_DISPLAY_INFO
 
@@ -333,6 +905,17 @@ pub struct VirtioGpuResourceCreate2d {
     height: u32,
 }
 
+//
+// VIRTIO_GPU_CMD_RESOURCE_UNREF
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct VirtioGpuResourceUnref {
+    hdr: VirtioGpuCtrlHdr,
+    resource_id: u32,
+    padding: u32,
+}
+
 //
 // VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING
 
@@ -398,3 +981,88 @@ pub struct VirtioGpuResourceFlush {
     resource_id: u32,
     padding: u32,
 }
+
+//
+// VIRTIO_GPU_CMD_UPDATE_CURSOR and VIRTIO_GPU_CMD_MOVE_CURSOR, on the cursor queue
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct VirtioGpuCursorPos {
+    scanout_id: u32,
+    x: u32,
+    y: u32,
+    padding: u32,
+}
+
+// Only the position is read for a move
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct VirtioGpuUpdateCursor {
+    hdr: VirtioGpuCtrlHdr,
+    pos: VirtioGpuCursorPos,
+    resource_id: u32,
+    hot_x: u32,
+    hot_y: u32,
+    padding: u32,
+}
+
+//
+// VIRTIO_GPU_CMD_GET_EDID
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct VirtioGpuGetEdid {
+    hdr: VirtioGpuCtrlHdr,
+    scanout: u32,
+    padding: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct VirtioGpuRespEdid {
+    hdr: VirtioGpuCtrlHdr,
+    size: u32,
+    padding: u32,
+    edid: [u8; 1024],
+}
+
+// Active sizes of the detailed timing descriptors and the standard timings of an
+// EDID base block
+fn parse_edid_modes(edid: &[u8]) -> Vec<(u32, u32)> {
+    const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+    let mut modes = Vec::new();
+
+    if edid.len() < 128 || edid[..8] != HEADER {
+        log::warn!("Invalid EDID");
+        return modes;
+    }
+
+    // Descriptors with a zero pixel clock are not timings
+    for desc in edid[54..126].chunks_exact(18) {
+        if desc[0] == 0 && desc[1] == 0 {
+            continue;
+        }
+        let w = desc[2] as u32 | ((desc[4] as u32 & 0xf0) << 4);
+        let h = desc[5] as u32 | ((desc[7] as u32 & 0xf0) << 4);
+        modes.push((w, h));
+    }
+
+    // 0x01 0x01 marks an unused slot
+    for timing in edid[38..54].chunks_exact(2) {
+        if timing[0] <= 0x01 {
+            continue;
+        }
+        let w = (timing[0] as u32 + 31) * 8;
+        let h = match timing[1] >> 6 {
+            0 => w * 10 / 16,
+            1 => w * 3 / 4,
+            2 => w * 4 / 5,
+            _ => w * 9 / 16,
+        };
+        modes.push((w, h));
+    }
+
+    modes.retain(|&(w, h)| w > 0 && h > 0);
+    modes
+}
@@ -1,43 +1,211 @@
 use super::{QueueMessage, VirtioDevice, VirtioQueue, VirtqSerializable};
+use crate::interrupts;
 use crate::pci::PciDevice;
+use alloc::string::String;
 use alloc::vec::Vec;
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
 
 const Q_SIZE: usize = 64;
 const BUF_SIZE: usize = core::mem::size_of::<VirtioInputEvent>();
 
+const VIRTIO_INPUT_CFG_ID_NAME: u8 = 0x01;
+const VIRTIO_INPUT_CFG_EV_BITS: u8 = 0x11;
+const VIRTIO_INPUT_CFG_ABS_INFO: u8 = 0x12;
+
+const EV_KEY: u8 = 0x1;
+const EV_REL: u8 = 0x2;
+const EV_ABS: u8 = 0x3;
+
+const ABS_X: u16 = 0;
+const ABS_Y: u16 = 1;
+
+const REL_HWHEEL: u16 = 6;
+const REL_WHEEL: u16 = 8;
+const REL_WHEEL_HI_RES: u16 = 11;
+const REL_HWHEEL_HI_RES: u16 = 12;
+
+// High-resolution wheel units in a notch
+const HI_RES_PER_NOTCH: i64 = 120;
+
+// Told apart by the events they can send
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputKind {
+    Keyboard,
+    // Relative motion, a mouse
+    Pointer,
+    // Absolute positions, e.g. QEMU's virtio-tablet
+    Tablet,
+    Other,
+}
+
+// Values the absolute axes of a tablet take, from one edge of the screen to the other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AbsRanges {
+    pub x: (i32, i32),
+    pub y: (i32, i32),
+}
+
+impl AbsRanges {
+    // Where an absolute event puts the pointer, None for other events
+    pub fn position(
+        &self,
+        event: &VirtioInputEvent,
+        pos: (i64, i64),
+        dims: (u32, u32),
+    ) -> Option<(i64, i64)> {
+        let value = (event.value as i32) as i64;
+        let scale = |(min, max): (i32, i32), len: u32| {
+            let span = i64::max(1, max as i64 - min as i64);
+            (value - min as i64).clamp(0, span) * (len as i64 - 1) / span
+        };
+        match (event._type, event.code) {
+            (t, ABS_X) if t == EV_ABS as u16 => Some((scale(self.x, dims.0), pos.1)),
+            (t, ABS_Y) if t == EV_ABS as u16 => Some((pos.0, scale(self.y, dims.1))),
+            _ => None,
+        }
+    }
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct VirtioInputConfig {
+    select: u8,
+    subsel: u8,
+    size: u8,
+    reserved: [u8; 5],
+    data: [u8; 128],
+}
+
 pub struct VirtioInput {
     pub virtio_dev: VirtioDevice,
+    pub kind: InputKind,
     eventq: VirtioQueue<Q_SIZE, BUF_SIZE>,
+    // Raised for new events, None if the device has to be polled
+    eventq_vector: Option<u8>,
+    // Taken from the queue by fetch(), handed out by the next poll()
+    fetched: Vec<VirtioInputEvent>,
+    // TSC when the first of the fetched events was signaled
+    raised_at: Option<u64>,
+    // Whether the horizontal and vertical wheels also send high-resolution events
+    hi_res_wheels: (bool, bool),
+    // High-resolution units not making up a full notch yet
+    hi_res_remainders: (i64, i64),
+    // For tablets, read again when the device configuration changes
+    pub abs_ranges: Option<AbsRanges>,
+    config_vector: Option<u8>,
 }
 
 impl VirtioInput {
-    pub fn new(pci_devices: &mut Vec<PciDevice>) -> Self {
-        let i = (0..pci_devices.len())
-            .find(|&i| {
-                pci_devices[i].vendor_id == 0x1af4 && pci_devices[i].device_id == 0x1040 + 18
-            })
-            .expect("Cannot find VirtIO input device");
+    // The next virtio-input device, None once there is none left
+    pub fn new(pci_devices: &mut Vec<PciDevice>) -> Option<Self> {
+        let i = (0..pci_devices.len()).find(|&i| {
+            pci_devices[i].vendor_id == 0x1af4 && pci_devices[i].device_id == 0x1040 + 18
+        })?;
 
         let pci_dev = pci_devices.swap_remove(i);
         let mut virtio_dev = VirtioDevice::new(pci_dev, 0x0);
 
+        let name = query_config(&virtio_dev, VIRTIO_INPUT_CFG_ID_NAME, 0);
+        let sends =
+            |ev_type: u8| !query_config(&virtio_dev, VIRTIO_INPUT_CFG_EV_BITS, ev_type).is_empty();
+        let rel_bits = query_config(&virtio_dev, VIRTIO_INPUT_CFG_EV_BITS, EV_REL);
+        let has_rel = |code: u16| {
+            rel_bits
+                .get(code as usize / 8)
+                .is_some_and(|byte| byte & (1 << (code % 8)) != 0)
+        };
+        let hi_res_wheels = (has_rel(REL_HWHEEL_HI_RES), has_rel(REL_WHEEL_HI_RES));
+        // A tablet can also have a relative wheel
+        let kind = match (sends(EV_ABS), sends(EV_REL), sends(EV_KEY)) {
+            (true, ..) => InputKind::Tablet,
+            (false, true, _) => InputKind::Pointer,
+            (false, false, true) => InputKind::Keyboard,
+            (false, false, false) => InputKind::Other,
+        };
+        log::info!(
+            "Input device \"{}\" ({:?})",
+            String::from_utf8_lossy(&name),
+            kind
+        );
+
         let mut eventq = virtio_dev.initialize_queue(0); // queue 0 (eventq)
                                                          //log::debug!("out of initialize_queue(): {:?}", eventq.descriptor_area.as_ptr());
+        let eventq_vector = virtio_dev.set_queue_vector(0, "virtio-input eventq");
+        let (abs_ranges, config_vector) = match kind {
+            InputKind::Tablet => {
+                let abs_ranges = query_abs_ranges(&virtio_dev);
+                log::info!("Tablet ranges: {:?}", abs_ranges);
+                let config_vector = virtio_dev.set_config_vector("virtio-input config");
+                (abs_ranges, config_vector)
+            }
+            _ => (None, None),
+        };
         virtio_dev.write_status(0x04); // DRIVER_OK
 
         let msg = [QueueMessage::<VirtioInputEvent>::DevWriteOnly];
         unsafe { while eventq.try_push(&msg).is_some() {} };
 
-        VirtioInput { virtio_dev, eventq }
+        Some(VirtioInput {
+            virtio_dev,
+            kind,
+            eventq,
+            eventq_vector,
+            fetched: Vec::new(),
+            raised_at: None,
+            hi_res_wheels,
+            hi_res_remainders: (0, 0),
+            abs_ranges,
+            config_vector,
+        })
+    }
+
+    // Notches scrolled by a wheel event, horizontally then vertically, None for other
+    // events. A device with high-resolution wheels sends both kinds of events for the same
+    // motion, only the high-resolution ones are counted then.
+    pub fn scroll_notches(&mut self, event: &VirtioInputEvent) -> Option<(i64, i64)> {
+        if event._type != EV_REL as u16 {
+            return None;
+        }
+        let value = (event.value as i32) as i64;
+        let (hi_res_x, hi_res_y) = self.hi_res_wheels;
+        let (rem_x, rem_y) = &mut self.hi_res_remainders;
+        match event.code {
+            REL_HWHEEL => Some((if hi_res_x { 0 } else { value }, 0)),
+            REL_WHEEL => Some((0, if hi_res_y { 0 } else { value })),
+            REL_HWHEEL_HI_RES => Some((take_notches(rem_x, value), 0)),
+            REL_WHEEL_HI_RES => Some((0, take_notches(rem_y, value))),
+            _ => None,
+        }
     }
 
+    // Events since the last poll(), including those fetch() already took
     pub fn poll(&mut self) -> Vec<VirtioInputEvent> {
-        let mut out = Vec::new();
+        if self.kind == InputKind::Tablet {
+            self.on_config_change();
+        }
+        self.fetch();
+        core::mem::take(&mut self.fetched)
+    }
+
+    // Takes the new events from the queue, they are kept for the next poll(). When
+    // drivers wait for interrupts, the queue is only looked at after one.
+    pub fn fetch(&mut self) -> &[VirtioInputEvent] {
+        let start = self.fetched.len();
+
+        if let Some(vector) = self.eventq_vector {
+            match interrupts::take(vector) {
+                Some(raised_at) => {
+                    self.raised_at.get_or_insert(raised_at);
+                }
+                None if interrupts::armed() => return &[],
+                None => (),
+            }
+        }
 
         while let Some(resp_list) = unsafe { self.eventq.try_pop::<_, 1>() } {
             // TODO: check response status code
             let event = resp_list.into_iter().next().unwrap();
-            out.push(event);
+            self.fetched.push(event);
 
             // TODO: unwrap()
             unsafe {
@@ -46,7 +214,64 @@ impl VirtioInput {
             }
         }
 
-        out
+        &self.fetched[start..]
+    }
+
+    // The tablet ranges can change with the display, e.g. after a resize. Without an
+    // interrupt for configuration changes, they are read again every time.
+    fn on_config_change(&mut self) {
+        if let Some(vector) = self.config_vector {
+            if interrupts::take(vector).is_none() && interrupts::armed() {
+                return;
+            }
+        }
+        let abs_ranges = query_abs_ranges(&self.virtio_dev);
+        if abs_ranges != self.abs_ranges {
+            log::info!("Tablet ranges changed: {:?}", abs_ranges);
+            self.abs_ranges = abs_ranges;
+        }
+    }
+
+    // When the interrupt for the events fetched since the last call was raised, None if
+    // there was none
+    pub fn take_raised_at(&mut self) -> Option<u64> {
+        self.raised_at.take()
+    }
+}
+
+// Whole notches out of the accumulated high-resolution units, the rest is kept
+fn take_notches(remainder: &mut i64, value: i64) -> i64 {
+    *remainder += value;
+    let notches = *remainder / HI_RES_PER_NOTCH;
+    *remainder -= notches * HI_RES_PER_NOTCH;
+    notches
+}
+
+// None if the device does not report both axes
+fn query_abs_ranges(virtio_dev: &VirtioDevice) -> Option<AbsRanges> {
+    // Starts with the minimum and maximum, then fuzz, flat and resolution
+    let range = |axis: u16| {
+        let info = query_config(virtio_dev, VIRTIO_INPUT_CFG_ABS_INFO, axis as u8);
+        let min = i32::from_le_bytes(info.get(0..4)?.try_into().unwrap());
+        let max = i32::from_le_bytes(info.get(4..8)?.try_into().unwrap());
+        Some((min, max))
+    };
+    Some(AbsRanges {
+        x: range(ABS_X)?,
+        y: range(ABS_Y)?,
+    })
+}
+
+// What the device reports for a selector, empty if nothing
+fn query_config(virtio_dev: &VirtioDevice, select: u8, subsel: u8) -> Vec<u8> {
+    let config = virtio_dev.device_specific_config_ptr::<VirtioInputConfig>();
+    unsafe {
+        write_volatile(addr_of_mut!((*config).select), select);
+        write_volatile(addr_of_mut!((*config).subsel), subsel);
+        let size = read_volatile(addr_of!((*config).size)) as usize;
+        (0..usize::min(size, 128))
+            .map(|i| read_volatile(addr_of!((*config).data[i])))
+            .collect()
     }
 }
 
@@ -1,18 +1,33 @@
-use alloc::borrow::ToOwned;
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::convert::TryInto;
 use core::hash::Hasher;
 use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, AtomicU64, Ordering};
 
 use core::{mem, usize};
 use tinyvec::ArrayVec;
 use x86_64::{PhysAddr, VirtAddr};
 
-use crate::memory;
-use crate::pci::{PciBar, PciConfigSpace, PciDevice};
+use crate::pci::{MsixCapability, PciBar, PciConfigSpace, PciDevice};
+use crate::{interrupts, memory};
 
 const VIRTIO_PCI_VENDOR: u8 = 0x09;
 
+// Read back from a vector register when the device did not take it
+const VIRTIO_MSI_NO_VECTOR: u16 = 0xffff;
+
+// Set in the device status when the device hit an error it cannot recover from
+const VIRTIO_STATUS_NEEDS_RESET: u8 = 0x40;
+
+// Asked of every device, see VirtioQueue::notify_device() and try_pop_with()
+const VIRTIO_F_EVENT_IDX: u32 = 0x1 << 29;
+
+static NOTIFY_SENT: AtomicU64 = AtomicU64::new(0);
+static NOTIFY_SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+
+pub mod block;
+pub mod entropy;
 pub mod gpu;
 pub mod input;
 pub mod network;
@@ -38,6 +53,17 @@ pub struct VirtioDevice {
     notification_cap: VirtioCapability,
     device_specific_config_cap: Option<VirtioCapability>,
     pub common_config: &'static mut VirtioPciCommonCfg,
+    // Requested by the driver, negotiated again after a reset
+    feature_bits: u32,
+    // Those of the requested feature bits (first 32) the device offered
+    features: u32,
+    // None without MSI-X, the device is polled then
+    msix: Option<MsixCapability>,
+    // MSI-X table entries used so far
+    msix_entries: u16,
+    // MSI-X table entries the device was told to use, pointed to again after a reset
+    config_msix_entry: Option<u16>,
+    queue_msix_entries: Vec<(u16, u16)>,
 }
 
 #[repr(u8)]
@@ -74,7 +100,7 @@ impl<const Q_SIZE: usize> VirtQStorage<Q_SIZE> {
             flags: 0x0,
             idx: 0,
             ring: [0u16; Q_SIZE],
-            used_event: 0, // Unused
+            used_event: 0,
         };
 
         let used_ring = {
@@ -84,7 +110,7 @@ impl<const Q_SIZE: usize> VirtQStorage<Q_SIZE> {
                 flags: 0x0,
                 idx: 0,
                 ring: [zero_used_elem; Q_SIZE],
-                avail_event: 0, // Unused
+                avail_event: 0,
             }
         };
 
@@ -99,9 +125,14 @@ impl<const Q_SIZE: usize> VirtQStorage<Q_SIZE> {
 pub struct VirtioQueue<const Q_SIZE: usize, const BUF_SIZE: usize> {
     q_index: u16,
     storage: Box<VirtQStorage<Q_SIZE>>,
-    pop_index: usize,
+    // Wraps around like the indices of the rings
+    pop_index: u16,
     notify_ptr: VirtAddr,
     avail_desc: [bool; Q_SIZE],
+    // Whether VIRTIO_F_EVENT_IDX was negotiated
+    event_idx: bool,
+    // Index of the available ring as of the last notification
+    notified_idx: u16,
 }
 
 pub trait VirtqSerializable: Clone + Default {}
@@ -109,6 +140,8 @@ pub trait VirtqSerializable: Clone + Default {}
 #[derive(Clone)]
 pub enum QueueMessage<T: VirtqSerializable> {
     DevWriteOnly,
+    // Only the first `len` bytes of the buffer are handed to the device
+    DevWriteOnlyLen { len: usize },
     DevReadOnly { data: T, len: Option<usize> },
 }
 
@@ -158,6 +191,24 @@ impl<const Q_SIZE: usize, const BUF_SIZE: usize> VirtioQueue<Q_SIZE, BUF_SIZE> {
         self.avail_desc[desc_index] = true;
     }
 
+    // Back to how it was when created, for a device that was reset
+    fn clear(&mut self) {
+        unsafe {
+            let driver_area = &mut self.storage.driver_area;
+            write_volatile(&mut driver_area.flags, 0);
+            write_volatile(&mut driver_area.idx, 0);
+            write_volatile(&mut driver_area.used_event, 0);
+
+            let device_area = &mut self.storage.device_area;
+            write_volatile(&mut device_area.flags, 0);
+            write_volatile(&mut device_area.idx, 0);
+            write_volatile(&mut device_area.avail_event, 0);
+        }
+        self.pop_index = 0;
+        self.notified_idx = 0;
+        self.avail_desc = [true; Q_SIZE];
+    }
+
     pub unsafe fn try_push<T: VirtqSerializable, const N: usize>(
         &mut self,
         messages: &[QueueMessage<T>; N],
@@ -205,16 +256,14 @@ impl<const Q_SIZE: usize, const BUF_SIZE: usize> VirtioQueue<Q_SIZE, BUF_SIZE> {
                     descriptor.len = mem::size_of::<T>() as u32;
                     T::default()
                 }
+                QueueMessage::DevWriteOnlyLen { len } => {
+                    descriptor.flags = 0x2;
+                    descriptor.len = *len as u32;
+                    T::default()
+                }
             };
 
-            let mapper = memory::get_mapper();
-
-            unsafe {
-                let virt_addr = mapper.phys_to_virt(PhysAddr::new(descriptor.addr));
-                let mut desc_buffer: Box<T> = Box::from_raw(virt_addr.as_mut_ptr());
-                *desc_buffer = buffer;
-                Box::leak(desc_buffer);
-            };
+            buffer_ptr::<T>(&descriptor).write(buffer);
 
             if i < n - 1 {
                 descriptor.next = desc_indices[i + 1] as u16;
@@ -224,74 +273,158 @@ impl<const Q_SIZE: usize, const BUF_SIZE: usize> VirtioQueue<Q_SIZE, BUF_SIZE> {
             write_volatile(desc_ref, descriptor);
         }
 
-        let ring_index = read_volatile(&self.storage.driver_area.idx) as usize;
+        self.make_available(desc_indices[0]);
 
-        write_volatile(
-            self.storage
-                .driver_area
-                .ring
-                .get_mut(ring_index % Q_SIZE)
-                .unwrap(),
-            desc_indices[0] as u16,
-        );
+        Some(())
+    }
+
+    // Same as pushing a single DevReadOnly message, except that `fill` writes the data
+    // straight into the buffer of the descriptor, nothing is built and copied in. It
+    // returns how many bytes of the buffer the device gets.
+    pub unsafe fn try_push_with<T: VirtqSerializable, F: FnOnce(&mut T) -> usize>(
+        &mut self,
+        fill: F,
+    ) -> Option<()> {
+        let desc_index = self.take_descriptor()?;
+        let desc_ref = self.storage.descriptor_area.0.get_mut(desc_index).unwrap();
+        let mut descriptor = read_volatile(desc_ref);
+
+        let len = fill(&mut *buffer_ptr::<T>(&descriptor));
+        descriptor.flags = 0x0;
+        descriptor.len = usize::min(len, mem::size_of::<T>()) as u32;
+        write_volatile(desc_ref, descriptor);
 
-        let old_idx = read_volatile(&self.storage.driver_area.idx);
-        write_volatile(&mut self.storage.driver_area.idx, old_idx + 1);
+        self.make_available(desc_index);
 
         Some(())
     }
 
-    pub unsafe fn notify_device(&self) {
+    fn make_available(&mut self, head_index: usize) {
+        unsafe {
+            let ring_index = read_volatile(&self.storage.driver_area.idx) as usize;
+
+            write_volatile(
+                self.storage
+                    .driver_area
+                    .ring
+                    .get_mut(ring_index % Q_SIZE)
+                    .unwrap(),
+                head_index as u16,
+            );
+
+            let old_idx = read_volatile(&self.storage.driver_area.idx);
+            write_volatile(&mut self.storage.driver_area.idx, old_idx.wrapping_add(1));
+        }
+    }
+
+    // With VIRTIO_F_EVENT_IDX, skipped while the device is still working through the
+    // buffers made available before the last notification, it gets to the new ones too
+    pub unsafe fn notify_device(&mut self) {
+        let new_idx = read_volatile(&self.storage.driver_area.idx);
+        let old_idx = mem::replace(&mut self.notified_idx, new_idx);
+
+        if self.event_idx {
+            // The new index has to be visible before the device's event index is read
+            fence(Ordering::SeqCst);
+            let avail_event = read_volatile(&self.storage.device_area.avail_event);
+            if !need_event(avail_event, new_idx, old_idx) {
+                NOTIFY_SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+
+        NOTIFY_SENT.fetch_add(1, Ordering::Relaxed);
         let q_index: u8 = self.q_index.try_into().unwrap();
         let ptr = self.notify_ptr.as_mut_ptr();
         write_volatile(ptr, q_index as u16);
     }
 
     pub unsafe fn try_pop<T: VirtqSerializable, const N: usize>(&mut self) -> Option<[T; N]> {
-        let mapper = memory::get_mapper();
+        self.try_pop_len().map(|(out, _)| out)
+    }
+
+    // Also returns how many bytes the device wrote into the buffers
+    pub unsafe fn try_pop_len<T: VirtqSerializable, const N: usize>(
+        &mut self,
+    ) -> Option<([T; N], usize)> {
+        let mut out = ArrayVec::<[T; N]>::new();
+        let len = self.try_pop_with(|buffer: &T| out.push(buffer.clone()))?;
+        Some((out.into_inner(), len))
+    }
 
-        let new_index = read_volatile(&self.storage.device_area.idx) as usize;
+    // Hands the buffers of the next chain the device used to `read`, in order and where
+    // they are, without copying them out. Returns how many bytes the device wrote.
+    pub unsafe fn try_pop_with<T: VirtqSerializable, F: FnMut(&T)>(
+        &mut self,
+        mut read: F,
+    ) -> Option<usize> {
+        let mut used_idx = read_volatile(&self.storage.device_area.idx);
+
+        // The device raises the queue interrupt for the next buffer it uses, but not while
+        // some are left to pop. It may have used one before seeing the new event index,
+        // so the index is read again.
+        if used_idx == self.pop_index && self.event_idx {
+            write_volatile(&mut self.storage.driver_area.used_event, self.pop_index);
+            fence(Ordering::SeqCst);
+            used_idx = read_volatile(&self.storage.device_area.idx);
+        }
 
-        if new_index == self.pop_index {
+        if used_idx == self.pop_index {
             return None;
         }
 
-        let idx: usize = self.pop_index.try_into().unwrap();
-
-        let it: VirtqUsedElem =
-            read_volatile(self.storage.device_area.ring.get(idx % Q_SIZE).unwrap());
+        let it: VirtqUsedElem = read_volatile(
+            self.storage
+                .device_area
+                .ring
+                .get(self.pop_index as usize % Q_SIZE)
+                .unwrap(),
+        );
         //log::debug!("Received element: {:?}", it);
 
-        let mut out = ArrayVec::<[T; N]>::new();
         let mut desc_index: usize = it.id.try_into().unwrap();
 
         loop {
             let descriptor = read_volatile(self.storage.descriptor_area.0.get(desc_index).unwrap());
             //log::debug!("Received descriptor: {:?}", descriptor);
-            unsafe {
-                let virt_addr = mapper.phys_to_virt(PhysAddr::new(descriptor.addr));
-                let desc_buffer: Box<T> = Box::from_raw(virt_addr.as_mut_ptr());
-                out.push(*desc_buffer.to_owned());
-                Box::leak(desc_buffer);
-            };
-
-            let next_desc = descriptor.next.into();
+            read(&*buffer_ptr::<T>(&descriptor));
 
             self.return_descriptor(desc_index);
 
-            if next_desc != 0 {
-                desc_index = next_desc
-            } else {
-                break;
+            // The next field is left as is in the last descriptor of a chain
+            match descriptor.flags & 0x1 {
+                0 => break,
+                _ => desc_index = descriptor.next.into(),
             }
         }
 
-        self.pop_index += 1;
+        self.pop_index = self.pop_index.wrapping_add(1);
 
-        Some(out.into_inner())
+        Some(it.len as usize)
     }
 }
 
+// Where the buffer of a descriptor is mapped, they are allocated with the queue
+fn buffer_ptr<T>(descriptor: &VirtqDesc) -> *mut T {
+    let mapper = memory::get_mapper();
+    mapper
+        .phys_to_virt(PhysAddr::new(descriptor.addr))
+        .as_mut_ptr()
+}
+
+// Whether an index going from `old` to `new` went past `event`, as in the spec
+fn need_event(event: u16, new: u16, old: u16) -> bool {
+    new.wrapping_sub(event).wrapping_sub(1) < new.wrapping_sub(old)
+}
+
+// Notifications sent to the devices, and skipped thanks to VIRTIO_F_EVENT_IDX, since the
+// last call
+pub fn pop_notify_counters() -> (usize, usize) {
+    let sent = NOTIFY_SENT.swap(0, Ordering::Relaxed);
+    let suppressed = NOTIFY_SUPPRESSED.swap(0, Ordering::Relaxed);
+    (sent as usize, suppressed as usize)
+}
+
 #[derive(Debug)]
 pub struct VirtioCapability {
     config_space_offset: u8,
@@ -324,6 +457,8 @@ pub fn get_addr_in_bar(pci_device: &PciDevice, virtio_cap: &VirtioPciCap) -> Vir
 
 impl VirtioDevice {
     pub fn new(pci_device: PciDevice, feature_bits: u32) -> Self {
+        pci_device.enable_memory_and_bus_master();
+
         let mut pci_config_space = PciConfigSpace::new();
 
         let mut find_cap = |cfg_type: CfgType| -> Option<VirtioCapability> {
@@ -368,22 +503,27 @@ impl VirtioDevice {
             notification_cap,
             device_specific_config_cap,
             common_config,
+            feature_bits,
+            features: 0x0,
+            msix: None,
+            msix_entries: 0,
+            config_msix_entry: None,
+            queue_msix_entries: Vec::new(),
         };
 
-        dev.initialize(feature_bits);
+        dev.pci_device.disable_msix();
+        dev.initialize();
 
         dev
     }
 
-    fn initialize(&mut self, feature_bits: u32) {
+    fn initialize(&mut self) {
         self.write_status(0x0); // RESET
 
-        self.pci_device.disable_msix();
-
         self.write_status(0x01); // ACKNOWLEDGE
         self.write_status(0x02); // DRIVER
 
-        let bits_0 = feature_bits;
+        let bits_0 = (self.feature_bits | VIRTIO_F_EVENT_IDX) & self.read_feature_bits(0x0);
         let bits_1 = FeatureBits::VIRTIO_F_VERSION_1 as u32;
 
         self.write_feature_bits(0x0, bits_0);
@@ -394,13 +534,133 @@ impl VirtioDevice {
         // Making sure features have been accepted
         let status = self.read_status();
         assert_eq!(status, 0x08);
+
+        self.features = bits_0;
+    }
+
+    // Whether the device stopped working until it is reset, see reset_and_reinit()
+    pub fn needs_reset(&self) -> bool {
+        self.read_status() & VIRTIO_STATUS_NEEDS_RESET != 0
+    }
+
+    // Takes the device through initialization again, with the same features and MSI-X
+    // vectors. Its queues are lost in the reset, `restore_queues` hands them back with
+    // restore_queue() before the device is started.
+    pub fn reset_and_reinit<F: FnOnce(&mut Self)>(&mut self, restore_queues: F) {
+        log::warn!("Resetting VirtIO device {:#x}", self.pci_device.device_id);
+
+        self.initialize();
+
+        if let Some(entry) = self.config_msix_entry {
+            unsafe { write_volatile(&mut self.common_config.msix_config, entry) };
+        }
+
+        restore_queues(self);
+
+        self.write_status(0x04); // DRIVER_OK
+    }
+
+    pub fn has_feature(&self, bit: u32) -> bool {
+        self.features & bit != 0
+    }
+
+    // Configuration changes raise the returned vector. None if the device has no MSI-X,
+    // or no vector is left.
+    pub fn set_config_vector(&mut self, name: &'static str) -> Option<u8> {
+        let (entry, vector) = self.next_msix_vector(name)?;
+        let taken = unsafe {
+            write_volatile(&mut self.common_config.msix_config, entry);
+            read_volatile(&self.common_config.msix_config)
+        };
+        self.config_msix_entry = Some(entry);
+        self.check_vector_taken(name, taken, vector)
+    }
+
+    // Same, for buffers the device hands back on that queue. To be set before DRIVER_OK.
+    pub fn set_queue_vector(&mut self, q_index: u16, name: &'static str) -> Option<u8> {
+        let (entry, vector) = self.next_msix_vector(name)?;
+        let taken = unsafe {
+            write_volatile(&mut self.common_config.queue_select, q_index);
+            write_volatile(&mut self.common_config.queue_msix_vector, entry);
+            read_volatile(&self.common_config.queue_msix_vector)
+        };
+        self.queue_msix_entries.push((q_index, entry));
+        self.check_vector_taken(name, taken, vector)
+    }
+
+    // Allocates a vector and points the next MSI-X table entry to it
+    fn next_msix_vector(&mut self, name: &'static str) -> Option<(u16, u8)> {
+        let msix = match self.msix {
+            Some(msix) => msix,
+            None => self.pci_device.msix_capability()?,
+        };
+
+        if self.msix_entries >= msix.table_size {
+            log::warn!("No MSI-X table entry left for {}", name);
+            return None;
+        }
+        let vector = interrupts::alloc_vector(name)?;
+        let entry = self.msix_entries;
+        self.msix_entries += 1;
+
+        let (addr, data) = interrupts::msi_message(vector);
+        self.pci_device.set_msix_entry(&msix, entry, addr, data);
+
+        // Only once there is a vector to raise, the device keeps its legacy interrupt
+        // otherwise
+        if self.msix.is_none() {
+            log::debug!(
+                "MSI-X table of {} entries at {:#x}, pending bits at {:#x}",
+                msix.table_size,
+                msix.table_addr,
+                msix.pba_addr
+            );
+            self.pci_device.set_msix_enabled(&msix, true);
+            self.msix = Some(msix);
+        }
+
+        Some((entry, vector))
+    }
+
+    fn check_vector_taken(&self, name: &'static str, taken: u16, vector: u8) -> Option<u8> {
+        match taken {
+            VIRTIO_MSI_NO_VECTOR => {
+                log::warn!("{} did not take its MSI-X vector, it is polled", name);
+                None
+            }
+            _ => {
+                log::info!("{} raises vector {:#x}", name, vector);
+                Some(vector)
+            }
+        }
     }
 
     pub fn initialize_queue<const Q_SIZE: usize, const BUF_SIZE: usize>(
         &mut self,
         q_index: u16,
     ) -> VirtioQueue<Q_SIZE, BUF_SIZE> {
-        let mapper = memory::get_mapper();
+        self.try_initialize_queue(q_index)
+            .expect("Unexpected VirtIO queue size")
+    }
+
+    // None if the device does not have that queue, or not with Q_SIZE entries
+    pub fn try_initialize_queue<const Q_SIZE: usize, const BUF_SIZE: usize>(
+        &mut self,
+        q_index: u16,
+    ) -> Option<VirtioQueue<Q_SIZE, BUF_SIZE>> {
+        let q_size = unsafe {
+            write_volatile(&mut self.common_config.queue_select, q_index);
+            read_volatile(&self.common_config.queue_size) as usize
+        };
+        if q_size != Q_SIZE {
+            log::warn!(
+                "VirtIO queue {} has {} entries, expected {}",
+                q_index,
+                q_size,
+                Q_SIZE
+            );
+            return None;
+        }
 
         // TODO: prevent a queue from being initialized twice
 
@@ -416,6 +676,34 @@ impl VirtioDevice {
             }
         }
 
+        self.enable_queue(q_index, &storage);
+
+        let notify_ptr = self.get_queue_notify_ptr(q_index);
+
+        Some(VirtioQueue {
+            q_index,
+            storage,
+            pop_index: 0,
+            notify_ptr,
+            avail_desc: [true; Q_SIZE],
+            event_idx: self.has_feature(VIRTIO_F_EVENT_IDX),
+            notified_idx: 0,
+        })
+    }
+
+    // Hands a queue back to the device after reset_and_reinit(), empty. The buffers the
+    // device held are taken back, what was in flight is lost.
+    pub fn restore_queue<const Q_SIZE: usize, const BUF_SIZE: usize>(
+        &mut self,
+        queue: &mut VirtioQueue<Q_SIZE, BUF_SIZE>,
+    ) {
+        queue.clear();
+        self.enable_queue(queue.q_index, &queue.storage);
+    }
+
+    fn enable_queue<const Q_SIZE: usize>(&mut self, q_index: u16, storage: &VirtQStorage<Q_SIZE>) {
+        let mapper = memory::get_mapper();
+
         // Calculating addresses
 
         let descr_area_addr = mapper
@@ -428,6 +716,12 @@ impl VirtioDevice {
         // log::debug!("driver_area_addr={:x}", driver_area_addr);
         // log::debug!("dev_area_addr={:x}", dev_area_addr);
 
+        let msix_entry = self
+            .queue_msix_entries
+            .iter()
+            .find(|(index, _)| *index == q_index)
+            .map(|(_, entry)| *entry);
+
         unsafe {
             let c = &mut self.common_config;
 
@@ -435,32 +729,32 @@ impl VirtioDevice {
             write_volatile(&mut c.queue_desc, descr_area_addr);
             write_volatile(&mut c.queue_driver, driver_area_addr);
             write_volatile(&mut c.queue_device, dev_area_addr);
+            if let Some(entry) = msix_entry {
+                write_volatile(&mut c.queue_msix_vector, entry);
+            }
             write_volatile(&mut c.queue_enable, 1);
-
-            // Reading back queue size
-            let q_size = read_volatile(&c.queue_size) as usize;
-
-            assert_eq!(q_size, Q_SIZE);
         }
+    }
 
-        let notify_ptr = self.get_queue_notify_ptr(q_index);
-
-        VirtioQueue {
-            q_index,
-            storage,
-            pop_index: 0,
-            notify_ptr,
-            avail_desc: [true; Q_SIZE],
+    // The device may change its config while it is read, it is read again until the
+    // generation counter shows it did not
+    pub fn read_config<T: Copy>(&self) -> T {
+        let config = self.device_specific_config_ptr::<T>();
+        loop {
+            let generation = unsafe { read_volatile(&self.common_config.config_generation) };
+            let value = unsafe { read_volatile(config) };
+            if unsafe { read_volatile(&self.common_config.config_generation) } == generation {
+                break value;
+            }
         }
     }
 
-    unsafe fn read_device_specific_config<T>(&self) -> &'static T {
+    // For configs with fields the driver writes to
+    fn device_specific_config_ptr<T>(&self) -> *mut T {
         let cap = self.device_specific_config_cap.as_ref().unwrap();
 
         let addr = get_addr_in_bar(&self.pci_device, &cap.virtio_cap);
-        let ptr = addr.as_ptr() as *const T;
-
-        ptr.as_ref().unwrap()
+        addr.as_mut_ptr() as *mut T
     }
 
     fn get_queue_notify_ptr(&mut self, q_index: u16) -> VirtAddr {
@@ -499,7 +793,6 @@ impl VirtioDevice {
         }
     }
 
-    #[allow(dead_code)]
     fn read_feature_bits(&mut self, select: u32) -> u32 {
         unsafe {
             write_volatile(&mut self.common_config.device_feature_select, select);
@@ -1,6 +1,7 @@
 use core::mem::MaybeUninit;
 
 use super::{QueueMessage, VirtioDevice, VirtioQueue, VirtqSerializable};
+use crate::interrupts;
 use crate::pci::PciDevice;
 use alloc::vec::Vec;
 use tinyvec::ArrayVec;
@@ -11,10 +12,13 @@ pub const MAX_PACKET_SIZE: usize = 1514;
 
 const BUF_SIZE: usize = core::mem::size_of::<VirtioNetPacket>();
 
+const VIRTIO_NET_S_LINK_UP: u16 = 1;
+
 #[repr(u32)]
 #[allow(non_camel_case_types)]
 enum NetworkFeatureBits {
     VIRTIO_NET_F_MAC = 0x1 << 5,
+    VIRTIO_NET_F_STATUS = 0x1 << 16,
 }
 
 pub struct VirtioNetwork {
@@ -22,6 +26,14 @@ pub struct VirtioNetwork {
     pub mac_addr: [u8; 6],
     receiveq1: VirtioQueue<Q_SIZE, BUF_SIZE>,
     transmitq1: VirtioQueue<Q_SIZE, BUF_SIZE>,
+    // Raised for received packets, None if the device has to be polled
+    receiveq1_vector: Option<u8>,
+    // Raised for link changes, None if the config has to be polled
+    config_vector: Option<u8>,
+    // As of the last on_config_change()
+    link_up: bool,
+    // Since the last interrupt, until the queue is found empty
+    recv_pending: bool,
     recv_counter: usize,
     sent_counter: usize,
 }
@@ -36,90 +48,174 @@ struct VirtioNetConfig {
 }
 
 impl VirtioNetwork {
-    pub fn new(pci_devices: &mut Vec<PciDevice>) -> Self {
+    // The remaining virtio-net device with the lowest PCI address, None once there is
+    // none left. Interfaces are numbered in that order.
+    pub fn new(pci_devices: &mut Vec<PciDevice>) -> Option<Self> {
         let i = (0..pci_devices.len())
-            .find(|&i| pci_devices[i].vendor_id == 0x1af4 && pci_devices[i].device_id == 0x1000)
-            .expect("Cannot find VirtIO network device");
+            .filter(|&i| {
+                pci_devices[i].vendor_id == 0x1af4
+                    && matches!(pci_devices[i].device_id, 0x1000 | 0x1041)
+            })
+            .min_by_key(|&i| pci_devices[i].addr.clone())?;
 
         let pci_dev = pci_devices.swap_remove(i);
-        let feature_bits = NetworkFeatureBits::VIRTIO_NET_F_MAC as u32;
+        let feature_bits = NetworkFeatureBits::VIRTIO_NET_F_MAC as u32
+            | NetworkFeatureBits::VIRTIO_NET_F_STATUS as u32;
         let mut virtio_dev = VirtioDevice::new(pci_dev, feature_bits);
 
         let mut receiveq1 = virtio_dev.initialize_queue(0); // queue 0 (receiveq1)
         let transmitq1 = virtio_dev.initialize_queue(1); // queue 1 (transmitq1)
+        let receiveq1_vector = virtio_dev.set_queue_vector(0, "virtio-net receiveq1");
+        let config_vector = virtio_dev.set_config_vector("virtio-net config");
         virtio_dev.write_status(0x04); // DRIVER_OK
 
-        let device_config = unsafe { virtio_dev.read_device_specific_config::<VirtioNetConfig>() };
-
-        let msg = [QueueMessage::<VirtioNetPacket>::DevWriteOnly];
+        let device_config: VirtioNetConfig = virtio_dev.read_config();
 
-        unsafe { while receiveq1.try_push(&msg).is_some() {} }
+        fill_receive_queue(&mut receiveq1);
 
-        VirtioNetwork {
+        let mut network = VirtioNetwork {
             virtio_dev,
             mac_addr: device_config.mac,
             receiveq1,
             transmitq1,
+            receiveq1_vector,
+            config_vector,
+            link_up: false,
+            recv_pending: false,
             recv_counter: 0,
             sent_counter: 0,
+        };
+        network.link_up = network.read_link_up();
+
+        Some(network)
+    }
+
+    // Called before the interface is polled. When drivers wait for interrupts, the
+    // config is only read after a config change one.
+    pub fn on_config_change(&mut self) {
+        if let Some(vector) = self.config_vector {
+            if interrupts::take(vector).is_none() && interrupts::armed() {
+                return;
+            }
+        }
+
+        if self.virtio_dev.needs_reset() {
+            self.reset();
+        }
+
+        let link_up = self.read_link_up();
+        if link_up != self.link_up {
+            log::info!("Network link {}", if link_up { "up" } else { "down" });
+            self.link_up = link_up;
         }
     }
 
-    pub fn try_recv(&mut self) -> Option<[u8; MAX_PACKET_SIZE]> {
-        let resp_list = unsafe { self.receiveq1.try_pop::<_, 1>()? };
+    // The packets in flight are lost, TCP sends them again
+    fn reset(&mut self) {
+        let VirtioNetwork {
+            virtio_dev,
+            receiveq1,
+            transmitq1,
+            ..
+        } = self;
+        virtio_dev.reset_and_reinit(|dev| {
+            dev.restore_queue(receiveq1);
+            dev.restore_queue(transmitq1);
+        });
+
+        fill_receive_queue(&mut self.receiveq1);
+        unsafe { self.receiveq1.notify_device() };
+        self.recv_pending = true;
+    }
+
+    // When drivers wait for interrupts, the queue is only looked at after one
+    // The frame, and its length
+    pub fn try_recv(&mut self) -> Option<([u8; MAX_PACKET_SIZE], usize)> {
+        if let Some(vector) = self.receiveq1_vector {
+            if interrupts::take(vector).is_some() {
+                self.recv_pending = true;
+            }
+            if interrupts::armed() && !self.recv_pending {
+                return None;
+            }
+        }
 
-        let virtio_packet: VirtioNetPacket = resp_list[0];
+        let mut data = [0u8; MAX_PACKET_SIZE];
+        let written = unsafe {
+            self.receiveq1
+                .try_pop_with(|packet: &VirtioNetPacket| data = packet.data)
+        };
+        let Some(written) = written else {
+            self.recv_pending = false;
+            return None;
+        };
+        // The device wrote the header first
+        let len = usize::min(
+            written.saturating_sub(core::mem::size_of::<VirtioNetHdr>()),
+            MAX_PACKET_SIZE,
+        );
 
+        // Only notified if the device ran out of buffers and waits for this one
         unsafe {
             self.receiveq1
                 .try_push(&[QueueMessage::<VirtioNetPacket>::DevWriteOnly])
                 .unwrap();
+            self.receiveq1.notify_device();
         }
 
-        self.recv_counter += virtio_packet.data.len();
+        self.recv_counter += len;
 
-        Some(virtio_packet.data)
+        Some((data, len))
     }
 
-    pub fn send(&mut self, mut data: ArrayVec<[u8; MAX_PACKET_SIZE]>) {
+    // The packet is written straight into a buffer of the queue. Its completion is only
+    // collected by a later send, so the device can work through the queue meanwhile.
+    pub fn send(&mut self, data: ArrayVec<[u8; MAX_PACKET_SIZE]>) {
         let len = data.len();
-        data.resize(MAX_PACKET_SIZE, 0x00);
-
-        let msg = VirtioNetPacket {
-            hdr: VirtioNetHdr {
-                flags: 0x0,
-                gso_type: 0x0,
-                hdr_len: 0x0,
-                gso_size: 0x0,
-                csum_start: 0x0,
-                csum_offset: 0x0,
-                num_buffers: 0x0,
-            },
-            data: data.into_inner(),
-        };
-
-        let virtio_buf_len = len + core::mem::size_of::<VirtioNetHdr>();
-
-        unsafe {
-            self.transmitq1
-                .try_push(&[QueueMessage::DevReadOnly {
-                    data: msg,
-                    len: Some(virtio_buf_len),
-                }])
-                .unwrap();
-            self.transmitq1.notify_device();
-        }
 
         loop {
-            let resp_list_opt = unsafe { self.transmitq1.try_pop::<VirtioNetPacket, 1>() };
-            if let Some(_) = resp_list_opt {
+            self.reap_sent();
+            let pushed = unsafe {
+                self.transmitq1
+                    .try_push_with(|packet: &mut VirtioNetPacket| {
+                        packet.hdr = VirtioNetHdr::default();
+                        packet.data[..len].copy_from_slice(&data);
+                        len + core::mem::size_of::<VirtioNetHdr>()
+                    })
+            };
+            if pushed.is_some() {
                 break;
             }
+            // Every descriptor is waiting for the device
+            core::hint::spin_loop();
         }
 
+        unsafe { self.transmitq1.notify_device() };
+
         self.sent_counter += len;
     }
 
+    fn reap_sent(&mut self) {
+        while unsafe { self.transmitq1.try_pop_with(|_: &VirtioNetPacket| ()) }.is_some() {}
+    }
+
+    pub fn link_up(&self) -> bool {
+        self.link_up
+    }
+
+    // Assumed up if the device does not report it
+    fn read_link_up(&self) -> bool {
+        if !self
+            .virtio_dev
+            .has_feature(NetworkFeatureBits::VIRTIO_NET_F_STATUS as u32)
+        {
+            return true;
+        }
+
+        let config: VirtioNetConfig = self.virtio_dev.read_config();
+        config.status & VIRTIO_NET_S_LINK_UP != 0
+    }
+
     pub fn get_counters(&mut self) -> (usize, usize) {
         let recv_counter = self.recv_counter;
         let sent_counter = self.sent_counter;
@@ -131,6 +227,12 @@ impl VirtioNetwork {
     }
 }
 
+// With buffers for the device to write received packets to
+fn fill_receive_queue(receiveq: &mut VirtioQueue<Q_SIZE, BUF_SIZE>) {
+    let msg = [QueueMessage::<VirtioNetPacket>::DevWriteOnly];
+    unsafe { while receiveq.try_push(&msg).is_some() {} }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct VirtioNetPacket {
@@ -148,7 +250,7 @@ impl Default for VirtioNetPacket {
 impl VirtqSerializable for VirtioNetPacket {}
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct VirtioNetHdr {
     pub flags: u8,
     pub gso_type: u8,
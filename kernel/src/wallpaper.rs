@@ -0,0 +1,153 @@
+use applib::drawing::gradient::{fill_linear_gradient, GradientDirection};
+use applib::drawing::primitives::draw_rect;
+use applib::{FbView, FbViewMut, Framebuffer, OwnedPixels, Rect, ScaleFilter};
+use core::f32::consts::FRAC_PI_4;
+use num_traits::Float;
+
+use crate::resources::{Theme, WallpaperDescriptor, WallpaperSource, WALLPAPERS};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalingMode {
+    // Covers the whole screen, the sides that stick out are cropped
+    Fill,
+    // Fully visible, with the backdrop on the sides
+    Fit,
+    // At its original size
+    Center,
+    // Repeated from the top-left corner, at its original size
+    Tile,
+}
+
+pub const SCALING_MODES: [ScalingMode; 4] = [
+    ScalingMode::Fill,
+    ScalingMode::Fit,
+    ScalingMode::Center,
+    ScalingMode::Tile,
+];
+
+impl ScalingMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScalingMode::Fill => "Fill",
+            ScalingMode::Fit => "Fit",
+            ScalingMode::Center => "Center",
+            ScalingMode::Tile => "Tile",
+        }
+    }
+}
+
+// The selected wallpaper, rendered once at the size of the screen so that drawing it
+// every frame is a single copy
+pub struct Wallpaper {
+    // Index in WALLPAPERS
+    pub selected: usize,
+    pub mode: ScalingMode,
+    // Wallpaper, scaling mode, theme name and screen size
+    rendered_for: Option<(usize, ScalingMode, &'static str, (u32, u32))>,
+    cache: Framebuffer<OwnedPixels>,
+}
+
+impl Wallpaper {
+    pub fn new() -> Self {
+        Wallpaper {
+            selected: 0,
+            mode: ScalingMode::Fill,
+            rendered_for: None,
+            cache: Framebuffer::new_owned(0, 0),
+        }
+    }
+
+    // Renders it again if the selection, the theme or the screen size changed. Returns
+    // true then, the whole screen has to be flushed.
+    pub fn update(&mut self, fb_shape: (u32, u32), theme: &'static Theme) -> bool {
+        let key = (self.selected, self.mode, theme.name, fb_shape);
+        if self.rendered_for == Some(key) {
+            return false;
+        }
+
+        let desc = &WALLPAPERS[self.selected];
+        log::info!(
+            "Rendering the {} wallpaper at {}x{} ({})",
+            desc.name,
+            fb_shape.0,
+            fb_shape.1,
+            self.mode.name()
+        );
+        self.cache = render(desc, self.mode, theme, fb_shape);
+        self.rendered_for = Some(key);
+        true
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer<OwnedPixels> {
+        &self.cache
+    }
+}
+
+fn render(
+    desc: &WallpaperDescriptor,
+    mode: ScalingMode,
+    theme: &Theme,
+    fb_shape: (u32, u32),
+) -> Framebuffer<OwnedPixels> {
+    let (w, h) = fb_shape;
+    let mut fb = Framebuffer::new_owned_filled(w, h, theme.backdrop);
+    let screen_rect = fb.shape_as_rect();
+
+    match desc.source {
+        WallpaperSource::Image(qoi) => match Framebuffer::from_qoi(qoi) {
+            Some(image) => draw_scaled(&mut fb, &image, mode),
+            None => log::error!("Cannot decode the {} wallpaper", desc.name),
+        },
+        WallpaperSource::Gradient => fill_linear_gradient(
+            &mut fb,
+            &screen_rect,
+            theme.stylesheet.colors.accent,
+            theme.backdrop,
+            GradientDirection::Angle(FRAC_PI_4),
+        ),
+        WallpaperSource::Plain => (),
+    }
+
+    if let Some(tint) = theme.wallpaper_tint {
+        draw_rect(&mut fb, &screen_rect, tint, true);
+    }
+
+    fb
+}
+
+fn draw_scaled<F: FbViewMut>(fb: &mut F, image: &Framebuffer<OwnedPixels>, mode: ScalingMode) {
+    let (w, h) = fb.shape();
+    let (img_w, img_h) = image.shape();
+    if img_w == 0 || img_h == 0 {
+        return;
+    }
+
+    let (xc, yc) = ((w / 2) as i64, (h / 2) as i64);
+
+    match mode {
+        ScalingMode::Fill | ScalingMode::Fit => {
+            let scale_x = w as f32 / img_w as f32;
+            let scale_y = h as f32 / img_h as f32;
+            let scale = match mode {
+                ScalingMode::Fill => f32::max(scale_x, scale_y),
+                _ => f32::min(scale_x, scale_y),
+            };
+            let dst_w = (img_w as f32 * scale).round() as u32;
+            let dst_h = (img_h as f32 * scale).round() as u32;
+            let dst_rect = Rect::from_center(xc, yc, dst_w, dst_h);
+            let src_rect = image.shape_as_rect();
+            fb.copy_from_fb_scaled(image, &src_rect, &dst_rect, ScaleFilter::Bilinear, false);
+        }
+        ScalingMode::Center => {
+            let dst_rect = Rect::from_center(xc, yc, img_w, img_h);
+            fb.copy_from_fb(image, dst_rect.origin(), false);
+        }
+        ScalingMode::Tile => {
+            for y in (0..h).step_by(img_h as usize) {
+                for x in (0..w).step_by(img_w as usize) {
+                    fb.copy_from_fb(image, (x as i64, y as i64), false);
+                }
+            }
+        }
+    }
+}
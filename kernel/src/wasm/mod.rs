@@ -1,14 +1,18 @@
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::format;
 use alloc::string::ToString;
 use alloc::vec;
+use alloc::vec::Vec;
 use alloc::{borrow::ToOwned, string::String};
 use applib::content::TrackedContent;
 use applib::content::UuidProvider;
 use applib::geometry::Point2D;
+use applib::stats::{AppStatsEntry, NetworkInterfaceEntry, SystemStatsEntry, TimeSyncEntry};
+use applib::uitk::CursorHint;
 use applib::BorrowedPixels;
+use applib::{StyleSheet, STYLESHEET_ABI_VERSION};
 use core::fmt::Write;
 use core::mem::size_of;
-use smoltcp::iface::SocketHandle;
 
 use rand::RngCore;
 use smoltcp::wire::Ipv4Address;
@@ -17,14 +21,26 @@ use wasmi::{
     TypedFunc,
 };
 
-use applib::{input::InputState, FbViewMut, Framebuffer, Rect};
+use applib::input::{InputEvent, InputState, Keycode, INPUT_STATE_ABI_VERSION};
+use applib::{Framebuffer, Rect};
 
+use crate::network::{TcpHandle, TcpStack};
+use crate::{logging, memory};
 use crate::stats::AppDataPoint;
+use crate::storage::{self, StorageError};
 use crate::system::System;
 
 pub struct WasmEngine;
 
 const STEP_FUEL: u64 = u64::MAX;
+// Kept from the console output of an app that crashed
+const CRASH_CONSOLE_LINES: usize = 20;
+
+// Why an app stopped, and what it printed last
+pub struct AppCrash {
+    pub error: anyhow::Error,
+    pub console_tail: Vec<String>,
+}
 
 impl WasmEngine {
     pub fn new() -> Self {
@@ -39,10 +55,16 @@ impl WasmEngine {
         wasm_code: &[u8],
         app_name: &str,
         init_rect: &Rect,
-    ) -> WasmApp {
+    ) -> Result<WasmApp, AppCrash> {
         let engine = Engine::new(&Config::default().consume_fuel(true));
 
-        let module = Module::new(&engine, wasm_code).unwrap();
+        let no_console = |error: anyhow::Error| AppCrash {
+            error,
+            console_tail: Vec::new(),
+        };
+
+        let module = Module::new(&engine, wasm_code)
+            .map_err(|err| no_console(anyhow::format_err!("Invalid WASM module: {}", err)))?;
         let store_data = StoreData::new(uuid_provider, app_name);
         let mut store: Store<StoreData> = Store::new(&engine, store_data);
         let mut linker = <Linker<StoreData>>::new(&engine);
@@ -51,26 +73,38 @@ impl WasmEngine {
 
         let instance = linker
             .instantiate(&mut store, &module)
-            .unwrap()
-            .start(&mut store)
-            .unwrap();
-
-        let wasm_init = instance.get_typed_func::<(), ()>(&store, "init").unwrap();
-        let wasm_step = instance.get_typed_func::<(), ()>(&store, "step").unwrap();
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|err| no_console(anyhow::format_err!(err)))?;
 
-        let mut store_wrapper = StoreWrapper { store };
-
-        store_wrapper.with_context(system, uuid_provider, input_state, init_rect, |store| {
-            log::info!("Initializing {}", app_name);
-            wasm_init
-                .call(store, ())
-                .expect("Failed to initialize WASM app");
-        });
+        let get_func = |store: &Store<StoreData>, name: &str| {
+            instance
+                .get_typed_func::<(), ()>(store, name)
+                .map_err(|err| no_console(anyhow::format_err!("No {} function: {}", name, err)))
+        };
+        let wasm_init = get_func(&store, "init")?;
+        let wasm_step = get_func(&store, "step")?;
 
-        WasmApp {
-            store_wrapper,
+        let mut wasm_app = WasmApp {
+            store_wrapper: StoreWrapper { store },
             instance,
             wasm_step,
+            held_keys: BTreeSet::new(),
+        };
+
+        let init_ret = wasm_app.store_wrapper.with_context(
+            system,
+            uuid_provider,
+            input_state,
+            init_rect,
+            |store| {
+                log::info!("Initializing {}", app_name);
+                wasm_init.call(store, ())
+            },
+        );
+
+        match init_ret {
+            Ok(()) => Ok(wasm_app),
+            Err(err) => Err(wasm_app.crash(anyhow::format_err!(err), &mut system.tcp_stack)),
         }
     }
 }
@@ -127,7 +161,7 @@ struct WasmFramebufferDef {
 }
 
 struct SocketsStore {
-    sockets: BTreeMap<i32, SocketHandle>,
+    sockets: BTreeMap<i32, TcpHandle>,
     next_id: i32,
 }
 
@@ -139,16 +173,20 @@ impl SocketsStore {
         }
     }
 
-    fn add_handle(&mut self, handle: SocketHandle) -> i32 {
+    fn add_handle(&mut self, handle: TcpHandle) -> i32 {
         let new_id = self.next_id;
         self.next_id += 1;
         self.sockets.insert(new_id, handle);
         new_id
     }
 
-    fn get_handle(&self, handle_id: i32) -> Option<SocketHandle> {
+    fn get_handle(&self, handle_id: i32) -> Option<TcpHandle> {
         self.sockets.get(&handle_id).cloned()
     }
+
+    fn remove_handle(&mut self, handle_id: i32) -> Option<TcpHandle> {
+        self.sockets.remove(&handle_id)
+    }
 }
 
 struct StoreWrapper {
@@ -196,6 +234,7 @@ impl StoreWrapper {
         let WasmFramebufferDef { addr, w, h } = wasm_fb_def;
         let wasm_fb_bytes = &mem_data[addr..addr + (w * h * 4) as usize];
 
+        // Apps draw with applib too, so their framebuffers are in RGBA whatever the scanout is
         let wasm_fb = Framebuffer::<BorrowedPixels>::from_bytes(wasm_fb_bytes, w, h);
 
         Some(wasm_fb)
@@ -210,6 +249,20 @@ struct StoreData {
     net_recv: usize,
     net_sent: usize,
     console_output: TrackedContent<String>,
+    focus_requested: bool,
+    notification: Option<String>,
+    close_requests: Vec<String>,
+    // The app asks before its window is closed, e.g. to save changes
+    close_guarded: bool,
+    // Closing was asked for while guarded, the app has not seen it yet
+    close_requested: bool,
+    open_requests: Vec<(String, String)>,
+    received_opens: VecDeque<String>,
+    // Only valid for the current frame
+    cursor_hint: CursorHint,
+    // Reported changes in the framebuffer for the current frame, None if the app did not
+    // report any
+    damage: Option<Vec<Rect>>,
 }
 
 struct StepContext {
@@ -240,6 +293,15 @@ impl StoreData {
             net_recv: 0,
             net_sent: 0,
             console_output: TrackedContent::new(String::new(), uuid_provider),
+            focus_requested: false,
+            notification: None,
+            close_requests: Vec::new(),
+            close_guarded: false,
+            close_requested: false,
+            open_requests: Vec::new(),
+            received_opens: VecDeque::new(),
+            cursor_hint: CursorHint::Default,
+            damage: None,
         }
     }
 
@@ -275,6 +337,8 @@ pub struct WasmApp {
     store_wrapper: StoreWrapper,
     instance: Instance,
     wasm_step: TypedFunc<(), ()>,
+    // Pressed while the app had the focus, and not released yet as far as it knows
+    held_keys: BTreeSet<Keycode>,
 }
 
 impl WasmApp {
@@ -285,6 +349,7 @@ impl WasmApp {
         input_state: &InputState,
         win_rect: &Rect,
         is_foreground: bool,
+        is_hovered: bool,
         is_paused: bool,
     ) -> Result<(), anyhow::Error> {
         //
@@ -292,9 +357,11 @@ impl WasmApp {
 
         let relative_input_state = {
             let mut input_state = input_state.clone();
-            if !is_foreground {
-                input_state.clear_events();
+            match is_foreground {
+                true => self.track_held_keys(&mut input_state),
+                false => self.release_held_keys(&mut input_state),
             }
+            route_scroll(&mut input_state, is_hovered);
             let (ox, oy) = win_rect.origin();
             input_state.change_origin(Point2D { x: ox, y: oy });
             input_state
@@ -305,32 +372,57 @@ impl WasmApp {
 
         let t0 = system.clock.time();
 
-        let step_ret = self
-            .store_wrapper
-            .with_context(
-                system,
-                uuid_provider,
-                &relative_input_state,
-                win_rect,
-                |mut store| {
-                    store.data_mut().net_recv = 0;
-                    store.data_mut().net_sent = 0;
-
-                    match is_paused {
-                        false => self.wasm_step.call(&mut store, ()),
-                        true => Ok(()),
-                    }
-                },
-            )
-            .map_err(|wasm_err| anyhow::format_err!(wasm_err));
+        // Host calls allocate on behalf of the app too
+        let app_name = self.store_wrapper.store.data().app_name.clone();
+        let alloc_tag = crate::TRACK_APP_ALLOCATIONS.then_some(app_name.as_str());
+        let allocated_before = alloc_tag
+            .and_then(memory::tag_stats)
+            .map(|tag_stats| tag_stats.allocated)
+            .unwrap_or(0);
+        memory::set_alloc_tag(alloc_tag);
+
+        let (step_ret, timings) = self.store_wrapper.with_context(
+            system,
+            uuid_provider,
+            &relative_input_state,
+            win_rect,
+            |mut store| {
+                store.data_mut().net_recv = 0;
+                store.data_mut().net_sent = 0;
+                store.data_mut().cursor_hint = CursorHint::Default;
+                // Nothing is drawn by a paused app
+                store.data_mut().damage = match is_paused {
+                    false => None,
+                    true => Some(Vec::new()),
+                };
+
+                let step_ret = match is_paused {
+                    false => self.wasm_step.call(&mut store, ()),
+                    true => Ok(()),
+                };
+
+                let timings = store
+                    .data_mut()
+                    .with_step_context(|step_context| core::mem::take(step_context.timings));
+
+                (step_ret, timings)
+            },
+        );
+
+        memory::set_alloc_tag(None);
+        let kernel_alloc = alloc_tag
+            .and_then(memory::tag_stats)
+            .map(|tag_stats| tag_stats.allocated - allocated_before)
+            .unwrap_or(0);
+
+        let step_ret = step_ret.map_err(|wasm_err| anyhow::format_err!(wasm_err));
 
         let t1 = system.clock.time();
 
         //
         // Filling app stats
 
-        let app_name = self.store_wrapper.store.data().app_name.as_str();
-        let app_stats = system.stats.get_app_point_mut(app_name);
+        let app_stats = system.stats.get_app_point_mut(&app_name);
 
         let store = &self.store_wrapper.store;
         let mem = self.instance.get_memory(store, "memory").unwrap();
@@ -340,15 +432,44 @@ impl WasmApp {
         let net_sent = store.data().net_sent;
 
         *app_stats = AppDataPoint {
+            running: true,
             net_recv,
             net_sent,
             mem_used: mem_size as usize,
+            kernel_alloc,
             frametime_used: t1 - t0,
         };
 
+        if !is_paused {
+            system.stats.set_app_timings(&app_name, timings);
+        }
+
         step_ret
     }
 
+    // Releases of keys pressed before the app got the focus are dropped, e.g. Alt after
+    // Alt+Tab
+    fn track_held_keys(&mut self, input_state: &mut InputState) {
+        let held_keys = &mut self.held_keys;
+        input_state.retain_events(|event| match event {
+            InputEvent::KeyPress { keycode } => {
+                held_keys.insert(*keycode);
+                true
+            }
+            InputEvent::KeyRelease { keycode } => held_keys.remove(keycode),
+            _ => true,
+        });
+    }
+
+    // Without the focus, the app gets no events. The keys it saw pressed are released,
+    // so that none stays stuck, e.g. Alt held during Alt+Tab.
+    fn release_held_keys(&mut self, input_state: &mut InputState) {
+        input_state.clear_events();
+        for keycode in core::mem::take(&mut self.held_keys) {
+            input_state.add_event(InputEvent::KeyRelease { keycode });
+        }
+    }
+
     pub fn get_framebuffer(&self) -> Option<Framebuffer<BorrowedPixels>> {
         self.store_wrapper.get_framebuffer(&self.instance)
     }
@@ -356,6 +477,100 @@ impl WasmApp {
     pub fn get_console_output(&self) -> &TrackedContent<String> {
         &self.store_wrapper.store.data().console_output
     }
+
+    pub fn take_close_requests(&mut self) -> Vec<String> {
+        core::mem::take(&mut self.store_wrapper.store.data_mut().close_requests)
+    }
+
+    pub fn close_guarded(&self) -> bool {
+        self.store_wrapper.store.data().close_guarded
+    }
+
+    // Lets a guarded app decide, it closes itself if it agrees
+    pub fn request_close(&mut self) {
+        self.store_wrapper.store.data_mut().close_requested = true;
+    }
+
+    // Whether a close request was made that the app did not take yet
+    pub fn close_pending(&self) -> bool {
+        self.store_wrapper.store.data().close_requested
+    }
+
+    // (target app name, path) pairs
+    pub fn take_open_requests(&mut self) -> Vec<(String, String)> {
+        core::mem::take(&mut self.store_wrapper.store.data_mut().open_requests)
+    }
+
+    pub fn push_open_request(&mut self, path: String) {
+        self.store_wrapper
+            .store
+            .data_mut()
+            .received_opens
+            .push_back(path);
+    }
+
+    pub fn take_focus_request(&mut self) -> bool {
+        core::mem::replace(
+            &mut self.store_wrapper.store.data_mut().focus_requested,
+            false,
+        )
+    }
+
+    pub fn take_notification(&mut self) -> Option<String> {
+        self.store_wrapper.store.data_mut().notification.take()
+    }
+
+    pub fn cursor_hint(&self) -> CursorHint {
+        self.store_wrapper.store.data().cursor_hint
+    }
+
+    // In framebuffer coordinates
+    pub fn take_damage(&mut self) -> Option<Vec<Rect>> {
+        self.store_wrapper.store.data_mut().damage.take()
+    }
+
+    // Closes the sockets the app left open, the rest goes away with the store
+    pub fn teardown(mut self, tcp_stack: &mut TcpStack) {
+        let sockets_store = &mut self.store_wrapper.store.data_mut().sockets_store;
+        for socket_handle in core::mem::take(&mut sockets_store.sockets).into_values() {
+            tcp_stack.close(socket_handle);
+        }
+    }
+
+    // Same as teardown(), keeping what is needed to tell the user what happened
+    pub fn crash(self, error: anyhow::Error, tcp_stack: &mut TcpStack) -> AppCrash {
+        let store_data = self.store_wrapper.store.data();
+        log::error!("{} has crashed: {:?}", store_data.app_name, error);
+
+        let console_output = store_data.console_output.as_ref();
+        let nb_lines = console_output.lines().count();
+        let console_tail = console_output
+            .lines()
+            .skip(nb_lines.saturating_sub(CRASH_CONSOLE_LINES))
+            .map(|line| line.to_owned())
+            .collect();
+
+        self.teardown(tcp_stack);
+
+        AppCrash {
+            error,
+            console_tail,
+        }
+    }
+}
+
+// The wheel scrolls the window under the pointer, whether it has the focus or not
+fn route_scroll(input_state: &mut InputState, is_hovered: bool) {
+    input_state.retain_events(|event| !matches!(event, InputEvent::Scroll { .. }));
+    let pointer = &mut input_state.pointer;
+    if !is_hovered {
+        pointer.scroll_x = 0;
+        pointer.scroll_y = 0;
+    }
+    let (delta_x, delta_y) = (pointer.scroll_x, pointer.scroll_y);
+    if delta_x != 0 || delta_y != 0 {
+        input_state.add_event(InputEvent::Scroll { delta_x, delta_y });
+    }
 }
 
 // fn debug_stall(t0: f64, t1: f64, fu0: u64, fu1: u64, store_data: &StoreData) {
@@ -496,8 +711,13 @@ fn add_host_apis(mut store: &mut Store<StoreData>, linker: &mut Linker<StoreData
             precision
         );
 
+        // CLOCK_REALTIME is the wall clock, in ns
         let t = caller.data_mut().with_step_context(|step_context| {
-            (step_context.system.clock.time() * 1e9) as u64 // Not sure about the 1e9
+            let clock = &step_context.system.clock;
+            match clock_id {
+                0 => (clock.unix_time() * 1e9) as u64,
+                _ => (clock.time() * 1e9) as u64, // Not sure about the 1e9
+            }
         });
 
         let mem = get_linear_memory(&caller);
@@ -653,22 +873,40 @@ fn add_host_apis(mut store: &mut Store<StoreData>, linker: &mut Linker<StoreData
             .trim_end()
             .to_owned();
 
+        let target = format!("app::{}", caller.data().app_name);
         caller.data_mut().with_step_context(|mut step_context| {
-            log_message(&msg, level, &mut step_context);
+            log_message(&msg, &target, level, &mut step_context);
         });
     });
 
-    linker_impl!(
-        m,
-        "host_get_input_state",
-        |mut caller: Caller<StoreData>, addr: i32| {
-            let system_state = caller
-                .data_mut()
-                .with_step_context(|step_context| step_context.input_state.clone());
-
-            write_to_wasm_mem(&mut caller, addr, &system_state);
+    linker_impl!(m, "host_get_input_state", |mut caller: Caller<
+        StoreData,
+    >,
+                                             addr: i32,
+                                             len: i32,
+                                             version: i32|
+     -> i32 {
+        // Same as the stylesheet, apps built against another layout would read garbage
+        let size = size_of::<InputState>();
+        if len as usize != size || version as u32 != INPUT_STATE_ABI_VERSION {
+            log::error!(
+                "Input ABI mismatch: app has version {} ({} bytes), kernel has {} ({} bytes)",
+                version,
+                len,
+                INPUT_STATE_ABI_VERSION,
+                size
+            );
+            return -1;
         }
-    );
+
+        let system_state = caller
+            .data_mut()
+            .with_step_context(|step_context| step_context.input_state.clone());
+
+        write_to_wasm_mem(&mut caller, addr, &system_state);
+
+        0
+    });
 
     linker_impl!(
         m,
@@ -705,7 +943,7 @@ fn add_host_apis(mut store: &mut Store<StoreData>, linker: &mut Linker<StoreData
                 step_context
                     .system
                     .tcp_stack
-                    .connect(Ipv4Address(ip_bytes), port)
+                    .connect(Ipv4Address(ip_bytes), port, None)
             })?;
 
             let handle_id = caller.data_mut().sockets_store.add_handle(socket_handle);
@@ -831,10 +1069,11 @@ fn add_host_apis(mut store: &mut Store<StoreData>, linker: &mut Linker<StoreData
         m,
         "host_tcp_close",
         |mut caller: Caller<StoreData>, handle_id: i32| {
+            // Forgotten so that it is not closed again when the app is torn down
             let socket_handle = caller
                 .data_mut()
                 .sockets_store
-                .get_handle(handle_id)
+                .remove_handle(handle_id)
                 .expect("No TCP connection");
 
             caller.data_mut().with_step_context(|step_context| {
@@ -861,17 +1100,32 @@ fn add_host_apis(mut store: &mut Store<StoreData>, linker: &mut Linker<StoreData
         }
     );
 
-    linker_impl!(
-        m,
-        "host_get_stylesheet",
-        |mut caller: Caller<StoreData>, addr: i32| {
-            let stylesheet = caller
-                .data_mut()
-                .with_step_context(|step_context| step_context.system.stylesheet.clone());
-
-            write_to_wasm_mem(&mut caller, addr, &stylesheet);
+    linker_impl!(m, "host_get_stylesheet", |mut caller: Caller<StoreData>,
+                                            addr: i32,
+                                            len: i32,
+                                            version: i32|
+     -> i32 {
+        // Apps built against another layout would read garbage
+        let size = size_of::<StyleSheet>();
+        if len as usize != size || version as u32 != STYLESHEET_ABI_VERSION {
+            log::error!(
+                "Stylesheet ABI mismatch: app has version {} ({} bytes), kernel has {} ({} bytes)",
+                version,
+                len,
+                STYLESHEET_ABI_VERSION,
+                size
+            );
+            return -1;
         }
-    );
+
+        let stylesheet = caller
+            .data_mut()
+            .with_step_context(|step_context| step_context.system.theme.stylesheet.clone());
+
+        write_to_wasm_mem(&mut caller, addr, &stylesheet);
+
+        0
+    });
 
     linker_impl!(
         m,
@@ -903,6 +1157,454 @@ fn add_host_apis(mut store: &mut Store<StoreData>, linker: &mut Linker<StoreData
         }
     );
 
+    linker_impl!(m, "host_storage_list", |mut caller: Caller<StoreData>,
+                                          addr: i32,
+                                          len: i32|
+     -> i32 {
+        let listing = caller.data_mut().with_step_context(|step_context| {
+            let names: Vec<&str> = step_context
+                .system
+                .storage
+                .list()
+                .filter(|name| !storage::is_kernel_file(name))
+                .collect();
+            names.join("\n")
+        });
+
+        let listing = listing.as_bytes();
+        let copy_len = usize::min(len as usize, listing.len());
+        let mem_slice = get_wasm_mem_slice_mut(&mut caller, addr, copy_len as i32);
+        mem_slice.copy_from_slice(&listing[..copy_len]);
+
+        listing.len() as i32
+    });
+
+    linker_impl!(m, "host_storage_read", |mut caller: Caller<StoreData>,
+                                          name_addr: i32,
+                                          name_len: i32,
+                                          addr: i32,
+                                          len: i32|
+     -> i32 {
+        let name_buf = get_wasm_mem_slice(&caller, name_addr, name_len);
+        let name = core::str::from_utf8(name_buf)
+            .expect("Invalid file name")
+            .to_string();
+
+        let read_res = caller.data_mut().with_step_context(|step_context| {
+            if storage::is_kernel_file(&name) {
+                return Err(StorageError::NotFound);
+            }
+            step_context
+                .system
+                .storage
+                .read(&name)
+                .map(|data| data.to_vec())
+        });
+
+        match read_res {
+            Ok(data) => {
+                let copy_len = usize::min(len as usize, data.len());
+                let mem_slice = get_wasm_mem_slice_mut(&mut caller, addr, copy_len as i32);
+                mem_slice.copy_from_slice(&data[..copy_len]);
+                data.len() as i32
+            }
+            Err(err) => {
+                log::error!("Cannot read {}: {:?}", name, err);
+                err.as_code()
+            }
+        }
+    });
+
+    linker_impl!(m, "host_storage_write", |mut caller: Caller<StoreData>,
+                                           name_addr: i32,
+                                           name_len: i32,
+                                           addr: i32,
+                                           len: i32|
+     -> i32 {
+        let name_buf = get_wasm_mem_slice(&caller, name_addr, name_len);
+        let name = core::str::from_utf8(name_buf)
+            .expect("Invalid file name")
+            .to_string();
+        let data = get_wasm_mem_slice(&caller, addr, len).to_vec();
+
+        let write_res = caller.data_mut().with_step_context(|step_context| {
+            if storage::is_kernel_file(&name) {
+                return Err(StorageError::InvalidName);
+            }
+            let system = step_context.system;
+            system.storage.write(&name, &data, system.clock.time())
+        });
+
+        match write_res {
+            Ok(()) => 0,
+            Err(err) => {
+                log::error!("Cannot write {}: {:?}", name, err);
+                err.as_code()
+            }
+        }
+    });
+
+    linker_impl!(m, "host_storage_stat", |mut caller: Caller<StoreData>,
+                                          name_addr: i32,
+                                          name_len: i32,
+                                          addr: i32|
+     -> i32 {
+        let name_buf = get_wasm_mem_slice(&caller, name_addr, name_len);
+        let name = core::str::from_utf8(name_buf)
+            .expect("Invalid file name")
+            .to_string();
+
+        let stat_res = caller.data_mut().with_step_context(|step_context| {
+            if storage::is_kernel_file(&name) {
+                return Err(StorageError::NotFound);
+            }
+            step_context.system.storage.stat(&name)
+        });
+
+        match stat_res {
+            Ok((size, modified)) => {
+                let mem_slice = get_wasm_mem_slice_mut(&mut caller, addr, 16);
+                mem_slice[..8].copy_from_slice(&(size as u64).to_le_bytes());
+                mem_slice[8..].copy_from_slice(&modified.to_le_bytes());
+                0
+            }
+            Err(err) => err.as_code(),
+        }
+    });
+
+    linker_impl!(m, "host_storage_delete", |mut caller: Caller<StoreData>,
+                                            name_addr: i32,
+                                            name_len: i32|
+     -> i32 {
+        let name_buf = get_wasm_mem_slice(&caller, name_addr, name_len);
+        let name = core::str::from_utf8(name_buf)
+            .expect("Invalid file name")
+            .to_string();
+
+        let delete_res = caller.data_mut().with_step_context(|step_context| {
+            if storage::is_kernel_file(&name) {
+                return Err(StorageError::NotFound);
+            }
+            step_context.system.storage.delete(&name)
+        });
+
+        match delete_res {
+            Ok(()) => 0,
+            Err(err) => {
+                log::error!("Cannot delete {}: {:?}", name, err);
+                err.as_code()
+            }
+        }
+    });
+
+    linker_impl!(m, "host_kernel_log_read", |mut caller: Caller<
+        StoreData,
+    >,
+                                             position_addr: i32,
+                                             addr: i32,
+                                             len: i32|
+     -> i32 {
+        let position_buf: [u8; 8] = get_wasm_mem_slice(&caller, position_addr, 8)
+            .try_into()
+            .unwrap();
+        let position = u64::from_le_bytes(position_buf);
+
+        let (data, next) = logging::read_log(position, len as usize);
+
+        let mem_slice = get_wasm_mem_slice_mut(&mut caller, addr, data.len() as i32);
+        mem_slice.copy_from_slice(&data);
+        let mem_slice = get_wasm_mem_slice_mut(&mut caller, position_addr, 8);
+        mem_slice.copy_from_slice(&next.to_le_bytes());
+
+        data.len() as i32
+    });
+
+    linker_impl!(m, "host_request_focus", |mut caller: Caller<StoreData>| {
+        caller.data_mut().focus_requested = true;
+    });
+
+    linker_impl!(
+        m,
+        "host_notify",
+        |mut caller: Caller<StoreData>, addr: i32, len: i32| {
+            let buf = get_wasm_mem_slice(&caller, addr, len);
+            let text = core::str::from_utf8(buf).expect("Invalid notification text");
+            caller.data_mut().notification = Some(text.to_string());
+        }
+    );
+
+    linker_impl!(
+        m,
+        "host_clipboard_set",
+        |mut caller: Caller<StoreData>, addr: i32, len: i32| {
+            let buf = get_wasm_mem_slice(&caller, addr, len);
+            let text = core::str::from_utf8(buf)
+                .expect("Invalid clipboard text")
+                .to_string();
+            caller
+                .data_mut()
+                .with_step_context(|step_context| step_context.system.clipboard = text.clone());
+        }
+    );
+
+    linker_impl!(m, "host_clipboard_get", |mut caller: Caller<StoreData>,
+                                           addr: i32,
+                                           len: i32|
+     -> i32 {
+        let text = caller
+            .data_mut()
+            .with_step_context(|step_context| step_context.system.clipboard.clone());
+
+        let text = text.as_bytes();
+        let copy_len = usize::min(len as usize, text.len());
+        let mem_slice = get_wasm_mem_slice_mut(&mut caller, addr, copy_len as i32);
+        mem_slice.copy_from_slice(&text[..copy_len]);
+
+        text.len() as i32
+    });
+
+    linker_impl!(
+        m,
+        "host_set_cursor_hint",
+        |mut caller: Caller<StoreData>, hint: i32| {
+            caller.data_mut().cursor_hint = CursorHint::n(hint as u32).unwrap_or(CursorHint::Default);
+        }
+    );
+
+    linker_impl!(
+        m,
+        "host_set_damage",
+        |mut caller: Caller<StoreData>, addr: i32, nb_rects: i32| {
+            // Each rect is sent as x0, y0, w, h
+            let buf = get_wasm_mem_slice(&mut caller, addr, nb_rects * 16);
+            let rects = buf
+                .chunks_exact(16)
+                .map(|chunk| {
+                    let v = |i: usize| i32::from_le_bytes(chunk[i..i + 4].try_into().unwrap());
+                    Rect {
+                        x0: v(0) as i64,
+                        y0: v(4) as i64,
+                        w: v(8) as u32,
+                        h: v(12) as u32,
+                    }
+                })
+                .collect();
+            caller.data_mut().damage = Some(rects);
+        }
+    );
+
+    linker_impl!(m, "host_get_stats", |mut caller: Caller<StoreData>,
+                                       system_addr: i32,
+                                       apps_addr: i32,
+                                       max_apps: i32|
+     -> i32 {
+        let (system_entry, app_entries) = caller.data_mut().with_step_context(|step_context| {
+            let stats = &step_context.system.stats;
+            let system_point = stats.get_last_system_point();
+
+            let system_entry = SystemStatsEntry {
+                frametime_used: system_point.frametime_used,
+                net_recv: system_point.net_recv as u64,
+                net_sent: system_point.net_sent as u64,
+                heap_allocated: system_point.alloc.allocated as u64,
+                heap_total: stats.heap_total as u64,
+                heap_peak: system_point.alloc.peak as u64,
+                heap_explored: system_point.alloc.explored as u64,
+                flushed_pixels: system_point.flushed_pixels as u64,
+            };
+
+            let app_entries: Vec<AppStatsEntry> = stats
+                .get_last_app_points()
+                .map(|(app_name, app_point)| {
+                    let mut entry = AppStatsEntry::new(app_name, app_point.running);
+                    entry.mem_used = app_point.mem_used as u64;
+                    entry.kernel_alloc = app_point.kernel_alloc as u64;
+                    entry.net_recv = app_point.net_recv as u64;
+                    entry.net_sent = app_point.net_sent as u64;
+                    entry.frametime_used = app_point.frametime_used;
+                    entry
+                })
+                .collect();
+
+            (system_entry, app_entries)
+        });
+
+        write_to_wasm_mem(&mut caller, system_addr, &system_entry);
+
+        for (i, app_entry) in app_entries.iter().take(max_apps as usize).enumerate() {
+            let addr = apps_addr + (i * size_of::<AppStatsEntry>()) as i32;
+            write_to_wasm_mem(&mut caller, addr, app_entry);
+        }
+
+        app_entries.len() as i32
+    });
+
+    linker_impl!(m, "host_get_network_status", |mut caller: Caller<
+        StoreData,
+    >,
+                                                addr: i32,
+                                                max_entries: i32,
+                                                sync_addr: i32|
+     -> i32 {
+        let (entries, sync_entry) = caller.data_mut().with_step_context(|step_context| {
+            let system = &step_context.system;
+            let sync_entry = match system.clock.last_sync() {
+                Some(sync) => TimeSyncEntry {
+                    last_sync: sync.time,
+                    offset: sync.offset,
+                    drift_ppm: sync.drift_ppm.unwrap_or(0.0),
+                },
+                None => TimeSyncEntry::default(),
+            };
+            (system.tcp_stack.interface_entries(), sync_entry)
+        });
+
+        for (i, entry) in entries.iter().take(max_entries as usize).enumerate() {
+            let entry_addr = addr + (i * size_of::<NetworkInterfaceEntry>()) as i32;
+            write_to_wasm_mem(&mut caller, entry_addr, entry);
+        }
+        write_to_wasm_mem(&mut caller, sync_addr, &sync_entry);
+
+        entries.len() as i32
+    });
+
+    linker_impl!(m, "host_get_timings", |mut caller: Caller<StoreData>,
+                                         name_addr: i32,
+                                         name_len: i32,
+                                         addr: i32,
+                                         len: i32|
+     -> i32 {
+        let name_buf = get_wasm_mem_slice(&caller, name_addr, name_len);
+        let name = core::str::from_utf8(name_buf)
+            .expect("Invalid app name")
+            .to_string();
+
+        let listing = caller.data_mut().with_step_context(|step_context| {
+            let stats = &step_context.system.stats;
+            match stats.has_app(&name) {
+                false => None,
+                true => {
+                    let lines: Vec<String> = stats
+                        .get_app_timings(&name)
+                        .into_iter()
+                        .flatten()
+                        .map(|(key, consumed)| format!("{} {}", key, consumed))
+                        .collect();
+                    Some(lines.join("\n"))
+                }
+            }
+        });
+
+        let Some(listing) = listing else {
+            log::error!("Cannot get timings: unknown app {}", name);
+            return -1;
+        };
+
+        let listing = listing.as_bytes();
+        let copy_len = usize::min(len as usize, listing.len());
+        let mem_slice = get_wasm_mem_slice_mut(&mut caller, addr, copy_len as i32);
+        mem_slice.copy_from_slice(&listing[..copy_len]);
+
+        listing.len() as i32
+    });
+
+    linker_impl!(m, "host_close_app", |mut caller: Caller<StoreData>,
+                                       name_addr: i32,
+                                       name_len: i32|
+     -> i32 {
+        let name_buf = get_wasm_mem_slice(&caller, name_addr, name_len);
+        let name = core::str::from_utf8(name_buf)
+            .expect("Invalid app name")
+            .to_string();
+
+        let app_exists = caller
+            .data_mut()
+            .with_step_context(|step_context| step_context.system.stats.has_app(&name));
+
+        match app_exists {
+            true => {
+                caller.data_mut().close_requests.push(name);
+                0
+            }
+            false => {
+                log::error!("Cannot close app: unknown app {}", name);
+                -1
+            }
+        }
+    });
+
+    linker_impl!(m, "host_close", |mut caller: Caller<StoreData>| {
+        let name = caller.data().app_name.clone();
+        caller.data_mut().close_requests.push(name);
+    });
+
+    linker_impl!(
+        m,
+        "host_set_close_guard",
+        |mut caller: Caller<StoreData>, enabled: i32| {
+            caller.data_mut().close_guarded = enabled != 0;
+        }
+    );
+
+    linker_impl!(m, "host_take_close_request", |mut caller: Caller<
+        StoreData,
+    >|
+     -> i32 {
+        core::mem::replace(&mut caller.data_mut().close_requested, false) as i32
+    });
+
+    linker_impl!(m, "host_open_with", |mut caller: Caller<StoreData>,
+                                       name_addr: i32,
+                                       name_len: i32,
+                                       path_addr: i32,
+                                       path_len: i32|
+     -> i32 {
+        let name_buf = get_wasm_mem_slice(&caller, name_addr, name_len);
+        let name = core::str::from_utf8(name_buf)
+            .expect("Invalid app name")
+            .to_string();
+        let path_buf = get_wasm_mem_slice(&caller, path_addr, path_len);
+        let path = core::str::from_utf8(path_buf)
+            .expect("Invalid path")
+            .to_string();
+
+        let app_exists = caller
+            .data_mut()
+            .with_step_context(|step_context| step_context.system.stats.has_app(&name));
+
+        match app_exists {
+            true => {
+                caller.data_mut().open_requests.push((name, path));
+                0
+            }
+            false => {
+                log::error!("Cannot open {} with unknown app {}", path, name);
+                -1
+            }
+        }
+    });
+
+    linker_impl!(m, "host_take_open_request", |mut caller: Caller<
+        StoreData,
+    >,
+                                               addr: i32,
+                                               len: i32|
+     -> i32 {
+        let Some(path) = caller.data().received_opens.front().cloned() else {
+            return -1;
+        };
+
+        // The request is only consumed once the guest buffer is large enough
+        let path = path.as_bytes();
+        if path.len() <= len as usize {
+            let mem_slice = get_wasm_mem_slice_mut(&mut caller, addr, path.len() as i32);
+            mem_slice.copy_from_slice(path);
+            caller.data_mut().received_opens.pop_front();
+        }
+
+        path.len() as i32
+    });
+
     linker_impl!(
         m,
         "host_qemu_dump",
@@ -921,7 +1623,7 @@ fn add_host_apis(mut store: &mut Store<StoreData>, linker: &mut Linker<StoreData
     );
 }
 
-fn log_message(msg: &str, level: i32, step_context: &mut StepContextView) {
+fn log_message(msg: &str, target: &str, level: i32, step_context: &mut StepContextView) {
     let StepContextView {
         uuid_provider,
         console_output,
@@ -932,11 +1634,11 @@ fn log_message(msg: &str, level: i32, step_context: &mut StepContextView) {
     console_output.write_char('\n').unwrap();
 
     match level {
-        1 => log::error!("{}", msg),
-        2 => log::warn!("{}", msg),
-        3 => log::info!("{}", msg),
-        4 => log::debug!("{}", msg),
-        _ => log::trace!("{}", msg),
+        1 => log::error!(target: target, "{}", msg),
+        2 => log::warn!(target: target, "{}", msg),
+        3 => log::info!(target: target, "{}", msg),
+        4 => log::debug!(target: target, "{}", msg),
+        _ => log::trace!(target: target, "{}", msg),
     };
 }
 
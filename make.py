@@ -14,6 +14,11 @@ WASM_APPS = [
     "terminal",
     "web_browser",
     "text_editor",
+    "system_monitor",
+    "file_manager",
+    "paint",
+    "irc",
+    "calculator",
 ]
 
 CRATE_PATHS = [
@@ -25,6 +30,10 @@ CRATE_PATHS = [
 
 TOOLCHAIN_VERSION = "nightly-2025-06-01-x86_64-unknown-linux-gnu"
 
+# Attached as a virtio-blk device, created on the first run
+DISK_IMAGE_PATH = Path("disk.img")
+DISK_IMAGE_SIZE = "64M"
+
 
 def main():
 
@@ -84,6 +93,14 @@ def _build():
 
 def _run():
 
+    if not DISK_IMAGE_PATH.exists():
+        _shell_exec(f"qemu-img create -f raw {DISK_IMAGE_PATH} {DISK_IMAGE_SIZE}")
+        # Without a filesystem, the kernel keeps files in memory
+        if shutil.which("mkfs.fat"):
+            _shell_exec(f"mkfs.fat -F 32 {DISK_IMAGE_PATH}")
+        else:
+            print("mkfs.fat not found, the disk image is left unformatted")
+
     #
     # Running QEMU
 
@@ -101,8 +118,14 @@ def _run():
 
             # VirtIO peripherals
             "-device virtio-keyboard",
-            "-device virtio-mouse",
-            "-device virtio-net-pci,netdev=network0 -netdev user,id=network0",
+            "-device virtio-tablet",
+            # The kernel HTTP server is reachable at localhost:8080
+            "-device virtio-net-pci,netdev=network0 -netdev user,id=network0,hostfwd=tcp:127.0.0.1:8080-:80",
+            # Host-only, the kernel gets its address there over DHCP
+            "-device virtio-net-pci,netdev=network1 -netdev user,id=network1,restrict=on,net=10.0.3.0/24",
+            "-device virtio-rng-pci,disable-legacy=on",
+            f"-drive if=none,id=disk0,format=raw,file={DISK_IMAGE_PATH}",
+            "-device virtio-blk-pci,drive=disk0,disable-legacy=on",
             "-vga virtio",
 
             # Debugging
@@ -7,7 +7,8 @@ from math import ceil
 from PIL import Image, ImageDraw, ImageFont
 
 FONTS_FOLDER_PATH = Path("applib/fonts/")
-CHAR_RANGE = (32, 127)
+# Printable ASCII and the Latin-1 supplement, end excluded
+CHAR_RANGES = [(32, 127), (160, 256)]
 
 
 def main():
@@ -20,7 +21,7 @@ def main():
 
     sizes_list = [int(s) for s in args.sizes.split(",")]
 
-    chars = [chr(i) for i in range(*CHAR_RANGE)]
+    chars = [chr(i) for (start, end) in CHAR_RANGES for i in range(start, end)]
 
     family_path = FONTS_FOLDER_PATH / args.name
     shutil.rmtree(family_path, ignore_errors=True)
@@ -53,6 +54,7 @@ def main():
             "char_h": char_h,
             "char_w": char_w,
             "base_y": asc,
+            "char_ranges": CHAR_RANGES,
         }
 
         with open(output_path / "spec.json", "w") as f:
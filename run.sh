@@ -27,6 +27,26 @@ cd text_editor/
 cargo build --release
 cd ../
 
+cd system_monitor/
+cargo build --release
+cd ../
+
+cd file_manager/
+cargo build --release
+cd ../
+
+cd paint/
+cargo build --release
+cd ../
+
+cd irc/
+cargo build --release
+cd ../
+
+cd calculator/
+cargo build --release
+cd ../
+
 cd ../
 
 
@@ -39,6 +59,11 @@ cp wasm_apps/chronometer/target/wasm32-wasip1/release/chronometer.wasm embedded_
 cp wasm_apps/terminal/target/wasm32-wasip1/release/terminal.wasm embedded_data/terminal.wasm
 cp wasm_apps/web_browser/target/wasm32-wasip1/release/web_browser.wasm embedded_data/web_browser.wasm
 cp wasm_apps/text_editor/target/wasm32-wasip1/release/text_editor.wasm embedded_data/text_editor.wasm
+cp wasm_apps/system_monitor/target/wasm32-wasip1/release/system_monitor.wasm embedded_data/system_monitor.wasm
+cp wasm_apps/file_manager/target/wasm32-wasip1/release/file_manager.wasm embedded_data/file_manager.wasm
+cp wasm_apps/paint/target/wasm32-wasip1/release/paint.wasm embedded_data/paint.wasm
+cp wasm_apps/irc/target/wasm32-wasip1/release/irc.wasm embedded_data/irc.wasm
+cp wasm_apps/calculator/target/wasm32-wasip1/release/calculator.wasm embedded_data/calculator.wasm
 
 
 #
@@ -55,6 +80,12 @@ cd ../
 mkdir -p esp/efi/boot/
 cp kernel/target/x86_64-unknown-uefi/release/kernel.efi esp/efi/boot/bootx64.efi
 
+# Attached as a virtio-blk device, the kernel keeps files in memory if it is not formatted
+if [ ! -f disk.img ]; then
+    qemu-img create -f raw disk.img 64M
+    mkfs.fat -F 32 disk.img || echo "Cannot format disk.img, leaving it unformatted"
+fi
+
 qemu-system-x86_64 \
     -enable-kvm \
     -m 1G \
@@ -64,7 +95,11 @@ qemu-system-x86_64 \
     -drive if=pflash,format=raw,readonly=on,file=uefi_firmware/vars.fd \
     -drive format=raw,file=fat:rw:esp \
     -device virtio-keyboard \
-    -device virtio-mouse \
-    -device virtio-net-pci,netdev=network0 -netdev user,id=network0 \
+    -device virtio-tablet \
+    -device virtio-net-pci,netdev=network0 -netdev user,id=network0,hostfwd=tcp:127.0.0.1:8080-:80 \
+    -device virtio-net-pci,netdev=network1 -netdev user,id=network1,restrict=on,net=10.0.3.0/24 \
+    -device virtio-rng-pci,disable-legacy=on \
+    -drive if=none,id=disk0,format=raw,file=disk.img \
+    -device virtio-blk-pci,drive=disk0,disable-legacy=on \
     -vga virtio \
     -serial stdio
@@ -0,0 +1,442 @@
+// Expression evaluation: the input is tokenized, converted to reverse polish
+// notation with the shunting-yard algorithm, then evaluated on a stack.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Func {
+    Sqrt,
+    Sin,
+    Cos,
+    Pow,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sqrt" => Some(Func::Sqrt),
+            "sin" => Some(Func::Sin),
+            "cos" => Some(Func::Cos),
+            "pow" => Some(Func::Pow),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Func::Sqrt => "sqrt",
+            Func::Sin => "sin",
+            Func::Cos => "cos",
+            Func::Pow => "pow",
+        }
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Func::Pow => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Number(f64),
+    Op(char),
+    Func(Func),
+    LParen,
+    RParen,
+    Comma,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RpnItem {
+    Number(f64),
+    Binary(char),
+    Neg,
+    Call(Func),
+}
+
+#[derive(Clone, Copy)]
+enum StackItem {
+    Binary(char),
+    Neg,
+    Func(Func),
+    // Whether the parenthesis opens a function call, and the number of commas seen in it
+    LParen {
+        call: Option<Func>,
+        nb_commas: usize,
+    },
+}
+
+impl StackItem {
+    fn precedence(&self) -> u8 {
+        match self {
+            StackItem::Binary('+' | '-') => 1,
+            StackItem::Binary('^') => 4,
+            StackItem::Binary(_) => 2,
+            StackItem::Neg => 3,
+            _ => 0,
+        }
+    }
+}
+
+pub fn evaluate(input: &str) -> anyhow::Result<f64> {
+    let tokens = tokenize(input)?;
+    let rpn = to_rpn(&tokens)?;
+    let value = eval_rpn(&rpn)?;
+
+    match value {
+        v if v.is_nan() => Err(anyhow::Error::msg("Invalid operation")),
+        v if v.is_infinite() => Err(anyhow::Error::msg("Result out of range")),
+        v => Ok(v),
+    }
+}
+
+fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(i, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '0'..='9' | '.' => {
+                let mut end = i;
+                while let Some(&(j, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    end = j + c.len_utf8();
+                    chars.next();
+                }
+                let s = &input[i..end];
+                let value = s
+                    .parse::<f64>()
+                    .map_err(|_| anyhow::Error::msg(format!("Invalid number: {}", s)))?;
+                tokens.push(Token::Number(value));
+            }
+            'a'..='z' | 'A'..='Z' => {
+                let mut end = i;
+                while let Some(&(j, c)) = chars.peek() {
+                    if !c.is_ascii_alphabetic() {
+                        break;
+                    }
+                    end = j + 1;
+                    chars.next();
+                }
+                let name = &input[i..end];
+                let func = Func::from_name(&name.to_ascii_lowercase())
+                    .ok_or_else(|| anyhow::Error::msg(format!("Unknown function: {}", name)))?;
+                tokens.push(Token::Func(func));
+            }
+            '+' | '-' | '*' | '/' | '%' | '^' => {
+                tokens.push(Token::Op(c));
+                chars.next();
+            }
+            '(' => {
+                tokens.push(Token::LParen);
+                chars.next();
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                chars.next();
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                chars.next();
+            }
+            c => return Err(anyhow::Error::msg(format!("Unexpected character: {}", c))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn to_rpn(tokens: &[Token]) -> anyhow::Result<Vec<RpnItem>> {
+    let mut output = Vec::new();
+    let mut stack: Vec<StackItem> = Vec::new();
+
+    // Tracks whether the next token must start an operand, which is how unary
+    // minus is told apart from subtraction, and how malformed input is caught
+    let mut expect_operand = true;
+
+    let pop_to_output = |item: StackItem, output: &mut Vec<RpnItem>| match item {
+        StackItem::Binary(op) => output.push(RpnItem::Binary(op)),
+        StackItem::Neg => output.push(RpnItem::Neg),
+        StackItem::Func(func) => output.push(RpnItem::Call(func)),
+        StackItem::LParen { .. } => unreachable!(),
+    };
+
+    for (i, token) in tokens.iter().enumerate() {
+        match *token {
+            Token::Number(value) if expect_operand => {
+                output.push(RpnItem::Number(value));
+                expect_operand = false;
+            }
+
+            Token::Func(func) if expect_operand => {
+                if tokens.get(i + 1) != Some(&Token::LParen) {
+                    return Err(anyhow::Error::msg("Expected ( after function name"));
+                }
+                stack.push(StackItem::Func(func));
+            }
+
+            Token::Op('-') if expect_operand => stack.push(StackItem::Neg),
+            Token::Op('+') if expect_operand => (),
+
+            Token::Op(op) if !expect_operand => {
+                let item = StackItem::Binary(op);
+                // All binary operators are left-associative, except ^ so that 2^3^2 is 2^9
+                let right_assoc = op == '^';
+                while let Some(top) = stack.last() {
+                    if top.precedence() < item.precedence()
+                        || (right_assoc && top.precedence() == item.precedence())
+                    {
+                        break;
+                    }
+                    pop_to_output(stack.pop().unwrap(), &mut output);
+                }
+                stack.push(item);
+                expect_operand = true;
+            }
+
+            Token::LParen if expect_operand => {
+                let call = match stack.last() {
+                    Some(StackItem::Func(func)) => Some(*func),
+                    _ => None,
+                };
+                stack.push(StackItem::LParen { call, nb_commas: 0 });
+            }
+
+            Token::Comma if !expect_operand => {
+                loop {
+                    match stack.last_mut() {
+                        Some(StackItem::LParen {
+                            call: Some(_),
+                            nb_commas,
+                        }) => {
+                            *nb_commas += 1;
+                            break;
+                        }
+                        Some(StackItem::LParen { call: None, .. }) | None => {
+                            return Err(anyhow::Error::msg("Unexpected ,"));
+                        }
+                        Some(_) => pop_to_output(stack.pop().unwrap(), &mut output),
+                    }
+                }
+                expect_operand = true;
+            }
+
+            Token::RParen if !expect_operand => loop {
+                match stack.pop() {
+                    Some(StackItem::LParen { call, nb_commas }) => {
+                        if let Some(func) = call {
+                            if nb_commas + 1 != func.arity() {
+                                return Err(anyhow::Error::msg(format!(
+                                    "{} expects {} argument(s)",
+                                    func.name(),
+                                    func.arity()
+                                )));
+                            }
+                            pop_to_output(stack.pop().unwrap(), &mut output);
+                        }
+                        break;
+                    }
+                    Some(item) => pop_to_output(item, &mut output),
+                    None => return Err(anyhow::Error::msg("Unbalanced parentheses")),
+                }
+            },
+
+            _ if expect_operand => return Err(anyhow::Error::msg("Expected a number")),
+            _ => return Err(anyhow::Error::msg("Expected an operator")),
+        }
+    }
+
+    if expect_operand {
+        return Err(anyhow::Error::msg("Incomplete expression"));
+    }
+
+    while let Some(item) = stack.pop() {
+        match item {
+            StackItem::LParen { .. } => return Err(anyhow::Error::msg("Unbalanced parentheses")),
+            item => pop_to_output(item, &mut output),
+        }
+    }
+
+    Ok(output)
+}
+
+fn eval_rpn(rpn: &[RpnItem]) -> anyhow::Result<f64> {
+    let mut stack: Vec<f64> = Vec::new();
+
+    let pop = |stack: &mut Vec<f64>| {
+        stack
+            .pop()
+            .ok_or_else(|| anyhow::Error::msg("Invalid expression"))
+    };
+
+    for item in rpn {
+        let value = match *item {
+            RpnItem::Number(value) => value,
+            RpnItem::Neg => -pop(&mut stack)?,
+            RpnItem::Binary(op) => {
+                let b = pop(&mut stack)?;
+                let a = pop(&mut stack)?;
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' | '%' if b == 0.0 => return Err(anyhow::Error::msg("Division by zero")),
+                    '/' => a / b,
+                    '%' => a % b,
+                    '^' => a.powf(b),
+                    _ => unreachable!(),
+                }
+            }
+            RpnItem::Call(func) => match func {
+                Func::Sqrt => {
+                    let x = pop(&mut stack)?;
+                    if x < 0.0 {
+                        return Err(anyhow::Error::msg("Square root of a negative number"));
+                    }
+                    x.sqrt()
+                }
+                Func::Sin => pop(&mut stack)?.sin(),
+                Func::Cos => pop(&mut stack)?.cos(),
+                Func::Pow => {
+                    let exp = pop(&mut stack)?;
+                    let base = pop(&mut stack)?;
+                    base.powf(exp)
+                }
+            },
+        };
+        stack.push(value);
+    }
+
+    match stack.as_slice() {
+        [value] => Ok(*value),
+        _ => Err(anyhow::Error::msg("Invalid expression")),
+    }
+}
+
+// Rounded to 12 significant digits, so that float noise like 0.1 + 0.2 = 0.30000000000000004
+// does not show up
+pub fn format_number(value: f64) -> String {
+    if value == 0.0 {
+        return "0".to_string();
+    }
+
+    let magnitude = value.abs().log10().floor() as i32;
+    if !(-6..15).contains(&magnitude) {
+        return format!("{:.6e}", value);
+    }
+
+    let decimals = (11 - magnitude).max(0) as usize;
+    let s = format!("{:.*}", decimals, value);
+    let s = match s.contains('.') {
+        true => s.trim_end_matches('0').trim_end_matches('.'),
+        false => &s,
+    };
+
+    match s {
+        "-0" => "0".to_string(),
+        s => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(input: &str) -> f64 {
+        evaluate(input).unwrap_or_else(|err| panic!("{}: {}", input, err))
+    }
+
+    fn error(input: &str) -> String {
+        match evaluate(input) {
+            Ok(value) => panic!("{} evaluated to {}", input, value),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+        assert_eq!(eval("48 / 4 / 2"), 6.0);
+        assert_eq!(eval("7 + 10 % 4 * 2"), 11.0);
+        assert_eq!(eval("2 * 3 ^ 2"), 18.0);
+        assert_eq!(eval("((2))"), 2.0);
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(eval("-3"), -3.0);
+        assert_eq!(eval("--3"), 3.0);
+        assert_eq!(eval("+3"), 3.0);
+        assert_eq!(eval("2 - -3"), 5.0);
+        assert_eq!(eval("-2 * 3"), -6.0);
+        assert_eq!(eval("-(1 + 2)"), -3.0);
+        assert_eq!(eval("-2 ^ 2"), -4.0);
+        assert_eq!(eval("2 ^ -1"), 0.5);
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(eval("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(eval("(2 ^ 3) ^ 2"), 64.0);
+        assert_eq!(eval("2 ^ 3 * 2"), 16.0);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(eval("sqrt(16)"), 4.0);
+        assert_eq!(eval("pow(2, 10)"), 1024.0);
+        assert_eq!(eval("pow(1 + 1, 2 * 2)"), 16.0);
+        assert_eq!(eval("SQRT(pow(3, 2) + 16)"), 5.0);
+        assert_eq!(eval("sin(0) + cos(0)"), 1.0);
+        assert_eq!(eval("-sqrt(4)"), -2.0);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(error("1 / 0"), "Division by zero");
+        assert_eq!(error("1 % 0"), "Division by zero");
+        assert_eq!(error("1 / (2 - 2)"), "Division by zero");
+    }
+
+    #[test]
+    fn invalid_operations() {
+        assert_eq!(error("sqrt(-1)"), "Square root of a negative number");
+        assert_eq!(error("(-8) ^ 0.5"), "Invalid operation");
+        assert_eq!(error("10 ^ 400"), "Result out of range");
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert_eq!(error(""), "Incomplete expression");
+        assert_eq!(error("1 +"), "Incomplete expression");
+        assert_eq!(error("* 2"), "Expected a number");
+        assert_eq!(error("1 2"), "Expected an operator");
+        assert_eq!(error("(1 + 2"), "Unbalanced parentheses");
+        assert_eq!(error("1 + 2)"), "Unbalanced parentheses");
+        assert_eq!(error("()"), "Expected a number");
+        assert_eq!(error("1.2.3"), "Invalid number: 1.2.3");
+        assert_eq!(error("1 # 2"), "Unexpected character: #");
+        assert_eq!(error("tan(1)"), "Unknown function: tan");
+        assert_eq!(error("sqrt 4"), "Expected ( after function name");
+        assert_eq!(error("sqrt(1, 2)"), "sqrt expects 1 argument(s)");
+        assert_eq!(error("pow(2)"), "pow expects 2 argument(s)");
+        assert_eq!(error("(1, 2)"), "Unexpected ,");
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_number(0.1 + 0.2), "0.3");
+        assert_eq!(format_number(-0.0), "0");
+        assert_eq!(format_number(2.5), "2.5");
+        assert_eq!(format_number(1e20), "1.000000e20");
+        assert_eq!(format_number(-1e-9), "-1.000000e-9");
+    }
+}
@@ -0,0 +1,330 @@
+use applib::content::{ContentId, TrackedContent};
+use applib::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use applib::input::{InputEvent, InputState, Keycode};
+use applib::uitk::layout::{make_horizontal_layout, make_vertical_layout, LayoutItem};
+use applib::uitk::{self, ButtonConfig, TextBoxState, UuidProvider};
+use applib::FbViewMut;
+use core::cell::OnceCell;
+use guestlib::{PixelData, WasmLogger};
+
+mod expr;
+
+static LOGGER: WasmLogger = WasmLogger;
+const LOGGING_LEVEL: log::LevelFilter = log::LevelFilter::Debug;
+
+const ENTRY_H: u32 = 40;
+const MESSAGE_H: u32 = 20;
+const HISTORY_W: u32 = 200;
+const HISTORY_ROW_H: u32 = 30;
+const MAX_HISTORY: usize = 50;
+
+const KEYPAD: [&[&str]; 6] = [
+    &["sqrt", "pow", "sin", "cos", "C"],
+    &["7", "8", "9", "/", "Del"],
+    &["4", "5", "6", "*", "("],
+    &["1", "2", "3", "-", ")"],
+    &["0", ".", "%", "+", ","],
+    &["^", "="],
+];
+
+enum Message {
+    None,
+    Result(String),
+    // Tied to the input it was computed from, and hidden once the input is edited
+    Error(String, ContentId),
+}
+
+struct HistoryEntry {
+    expr: String,
+    result: String,
+}
+
+struct AppState {
+    pixel_data: PixelData,
+    ui_store: uitk::UiStore,
+    uuid_provider: UuidProvider,
+
+    entry_text: TrackedContent<String>,
+    entry_state: TextBoxState,
+    message: Message,
+    history: Vec<HistoryEntry>,
+}
+
+static mut APP_STATE: OnceCell<AppState> = OnceCell::new();
+
+fn main() {}
+
+#[no_mangle]
+pub fn init() -> () {
+    log::set_max_level(LOGGING_LEVEL);
+    log::set_logger(&LOGGER).unwrap();
+
+    let mut uuid_provider = UuidProvider::new();
+
+    let mut ui_store = uitk::UiStore::new();
+    ui_store.set_clipboard(guestlib::HostClipboard);
+
+    let state = AppState {
+        pixel_data: PixelData::new(),
+        ui_store,
+
+        entry_text: TrackedContent::new(String::new(), &mut uuid_provider),
+        entry_state: TextBoxState::new(),
+        message: Message::None,
+        history: Vec::new(),
+
+        uuid_provider,
+    };
+
+    unsafe {
+        APP_STATE
+            .set(state)
+            .unwrap_or_else(|_| panic!("App already initialized"));
+    }
+}
+
+#[no_mangle]
+pub fn step() {
+    let state = unsafe { APP_STATE.get_mut().expect("App not initialized") };
+
+    let time = guestlib::get_time();
+    let stylesheet = guestlib::get_stylesheet();
+    let input_state = guestlib::get_input_state();
+    let win_rect = guestlib::get_win_rect().zero_origin();
+
+    let m = stylesheet.margin;
+    let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+
+    let main_layout = make_vertical_layout(
+        &win_rect.offset(-(m as i64)),
+        m,
+        &[
+            LayoutItem::Fixed { size: ENTRY_H },
+            LayoutItem::Fixed { size: MESSAGE_H },
+            LayoutItem::Float,
+        ],
+    );
+
+    let bottom_layout = make_horizontal_layout(
+        &main_layout[2],
+        m,
+        &[LayoutItem::Float, LayoutItem::Fixed { size: HISTORY_W }],
+    );
+
+    let keypad_layout =
+        make_vertical_layout(&bottom_layout[0], m, &vec![LayoutItem::Float; KEYPAD.len()]);
+
+    let history_rect = &bottom_layout[1];
+    let nb_history_rows = (history_rect.h + m) / (HISTORY_ROW_H + m);
+    let history_layout = make_vertical_layout(
+        history_rect,
+        m,
+        &vec![
+            LayoutItem::Fixed {
+                size: HISTORY_ROW_H
+            };
+            nb_history_rows as usize
+        ],
+    );
+
+    let mut framebuffer = state.pixel_data.get_framebuffer();
+
+    let AppState {
+        ui_store,
+        uuid_provider,
+        ..
+    } = state;
+
+    framebuffer.fill(stylesheet.colors.background);
+
+    let mut uitk_context = ui_store.get_context(
+        &mut framebuffer,
+        &stylesheet,
+        &input_state,
+        uuid_provider,
+        time,
+    );
+
+    //
+    // Entry
+
+    // The entry gets the keyboard back whenever nothing else has it
+    if uitk_context.interaction.focused.is_none() {
+        state.entry_state.focus();
+    }
+
+    uitk_context.editable_text_box(
+        &main_layout[0],
+        &mut state.entry_text,
+        &mut state.entry_state,
+        false,
+        false,
+        None::<&TrackedContent<String>>,
+    );
+
+    // Enter on a focused button presses that button instead
+    let mut evaluate = state.entry_state.is_focused() && check_enter_pressed(&input_state);
+
+    //
+    // Keypad
+
+    for (keys, row_rect) in KEYPAD.iter().zip(keypad_layout.iter()) {
+        let row_layout = make_horizontal_layout(row_rect, m, &vec![LayoutItem::Float; keys.len()]);
+
+        for (key, rect) in keys.iter().zip(row_layout.iter()) {
+            let clicked = uitk_context.button(&ButtonConfig {
+                rect: rect.clone(),
+                text: key.to_string(),
+                ..Default::default()
+            });
+
+            if !clicked {
+                continue;
+            }
+
+            // The keypad types into the entry, so typing can go on from the keyboard
+            state.entry_state.focus();
+
+            let uuid_provider = &mut *uitk_context.uuid_provider;
+            match *key {
+                "=" => evaluate = true,
+                "C" => {
+                    *state.entry_text.mutate(uuid_provider) = String::new();
+                    state.entry_state.cursor = 0;
+                    state.message = Message::None;
+                }
+                "Del" => delete_before_cursor(
+                    &mut state.entry_text,
+                    &mut state.entry_state,
+                    uuid_provider,
+                ),
+                "sqrt" | "pow" | "sin" | "cos" => insert_at_cursor(
+                    &mut state.entry_text,
+                    &mut state.entry_state,
+                    uuid_provider,
+                    &format!("{}(", key),
+                ),
+                key => insert_at_cursor(
+                    &mut state.entry_text,
+                    &mut state.entry_state,
+                    uuid_provider,
+                    key,
+                ),
+            }
+        }
+    }
+
+    //
+    // History
+
+    let mut reused = None;
+    for (entry, rect) in state.history.iter().rev().zip(history_layout.iter()) {
+        let clicked = uitk_context.button(&ButtonConfig {
+            rect: rect.clone(),
+            text: format!("{} = {}", entry.expr, entry.result),
+            ..Default::default()
+        });
+        if clicked {
+            reused = Some(entry.expr.clone());
+            state.entry_state.focus();
+        }
+    }
+
+    if let Some(expr) = reused {
+        state.entry_state.cursor = expr.chars().count();
+        *state.entry_text.mutate(uitk_context.uuid_provider) = expr;
+        state.message = Message::None;
+    }
+
+    //
+    // Evaluation
+
+    let expr = state.entry_text.as_ref().trim().to_string();
+    if evaluate && !expr.is_empty() {
+        match expr::evaluate(&expr) {
+            Ok(value) => {
+                let result = expr::format_number(value);
+
+                // Pressing = again on a result is not worth a history entry
+                if expr != result {
+                    state.history.push(HistoryEntry {
+                        expr,
+                        result: result.clone(),
+                    });
+                    if state.history.len() > MAX_HISTORY {
+                        state.history.remove(0);
+                    }
+                }
+
+                state.message = Message::Result(result.clone());
+                state.entry_state.cursor = result.chars().count();
+                *state.entry_text.mutate(uitk_context.uuid_provider) = result;
+            }
+            // The input is left as it is, so that it can be fixed
+            Err(err) => {
+                state.message = Message::Error(err.to_string(), state.entry_text.get_id());
+            }
+        }
+    }
+
+    if let Message::Error(_, content_id) = &state.message {
+        if *content_id != state.entry_text.get_id() {
+            state.message = Message::None;
+        }
+    }
+
+    let (message, color) = match &state.message {
+        Message::None => ("".to_string(), stylesheet.colors.text),
+        Message::Result(result) => (format!("= {}", result), stylesheet.colors.green),
+        Message::Error(err, _) => (err.clone(), stylesheet.colors.red),
+    };
+
+    draw_line_in_rect(
+        uitk_context.fb,
+        &message,
+        &main_layout[1],
+        font,
+        color,
+        TextJustification::Left,
+    );
+}
+
+fn check_enter_pressed(input_state: &InputState) -> bool {
+    input_state.events.iter().any(|event| {
+        matches!(
+            event,
+            Some(InputEvent::KeyPress {
+                keycode: Keycode::KEY_ENTER
+            })
+        )
+    })
+}
+
+// The cursor is a char index
+fn byte_index(s: &str, cursor: usize) -> usize {
+    s.char_indices().nth(cursor).map_or(s.len(), |(i, _)| i)
+}
+
+fn insert_at_cursor(
+    text: &mut TrackedContent<String>,
+    textbox_state: &mut TextBoxState,
+    uuid_provider: &mut UuidProvider,
+    s: &str,
+) {
+    let i = byte_index(text.as_ref(), textbox_state.cursor);
+    text.mutate(uuid_provider).insert_str(i, s);
+    textbox_state.cursor += s.chars().count();
+}
+
+fn delete_before_cursor(
+    text: &mut TrackedContent<String>,
+    textbox_state: &mut TextBoxState,
+    uuid_provider: &mut UuidProvider,
+) {
+    if textbox_state.cursor == 0 {
+        return;
+    }
+    let i = byte_index(text.as_ref(), textbox_state.cursor - 1);
+    text.mutate(uuid_provider).remove(i);
+    textbox_state.cursor -= 1;
+}
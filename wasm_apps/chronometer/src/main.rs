@@ -1,10 +1,11 @@
 extern crate alloc;
 
 use alloc::format;
+use applib::content::TrackedContent;
 use applib::drawing::primitives::draw_rect;
 use applib::drawing::text::{draw_line_in_rect, get_font, TextJustification};
 use applib::uitk::layout::{make_horizontal_layout, make_vertical_layout, LayoutItem};
-use applib::uitk::{self, ButtonConfig, ContentId, UuidProvider};
+use applib::uitk::{self, ButtonConfig, ContentId, NumberInputConfig, TextBoxState, UuidProvider};
 use applib::{Color, FbView, FbViewMut};
 use applib::{Framebuffer, OwnedPixels};
 use core::cell::OnceCell;
@@ -17,6 +18,12 @@ use drawing::{draw_background, draw_chrono};
 static LOGGER: WasmLogger = WasmLogger;
 const LOGGING_LEVEL: log::LevelFilter = log::LevelFilter::Debug;
 
+const COUNTDOWN_FILE: &str = "chronometer_countdown";
+const DEFAULT_COUNTDOWN: f64 = 5.0 * 60_000.0; // in ms
+const MAX_COUNTDOWN: f64 = 60.0 * 60_000.0 - 1000.0; // in ms
+const FLASH_PERIOD: f64 = 500.0; // in ms
+const FLASH_COLOR: Color = Color::rgba(255, 0, 0, 120);
+
 lazy_static! {
     pub static ref PLAY_ICON: Framebuffer<OwnedPixels> =
         Framebuffer::from_png(include_bytes!("../icons/play.png"));
@@ -28,12 +35,25 @@ lazy_static! {
 
 struct AppState {
     pixel_data: PixelData,
+    mode: Mode,
     chrono_state: ChronoState,
+    countdown_state: CountdownState,
+    countdown_duration: f64,
+
+    laps: Vec<f64>,
+    laps_text: TrackedContent<String>,
+    laps_text_state: TextBoxState,
 
     uuid_provider: UuidProvider,
     ui_store: uitk::UiStore,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Chrono,
+    Countdown,
+}
+
 #[derive(Debug)]
 enum ChronoState {
     Stopped,
@@ -41,6 +61,16 @@ enum ChronoState {
     Running { t_resume: f64, t_offset: f64 },
 }
 
+// All times are derived from the host clock, so that the countdown stays correct
+// even if the app is not stepped for a while (paused, closed window)
+#[derive(Debug)]
+enum CountdownState {
+    Stopped,
+    Paused { t_remaining: f64 },
+    Running { t_end: f64 },
+    Ringing { t_fired: f64 },
+}
+
 static mut APP_STATE: OnceCell<AppState> = OnceCell::new();
 
 fn main() {}
@@ -50,12 +80,24 @@ pub fn init() -> () {
     log::set_max_level(LOGGING_LEVEL);
     log::set_logger(&LOGGER).unwrap();
 
+    let mut uuid_provider = UuidProvider::new();
+
+    let mut ui_store = uitk::UiStore::new();
+    ui_store.set_clipboard(guestlib::HostClipboard);
+
     let state = AppState {
         pixel_data: PixelData::new(),
+        mode: Mode::Chrono,
         chrono_state: ChronoState::Stopped,
+        countdown_state: CountdownState::Stopped,
+        countdown_duration: load_countdown_duration(),
 
-        ui_store: uitk::UiStore::new(),
-        uuid_provider: UuidProvider::new(),
+        laps: Vec::new(),
+        laps_text: TrackedContent::new(String::new(), &mut uuid_provider),
+        laps_text_state: TextBoxState::new(),
+
+        ui_store,
+        uuid_provider,
     };
     unsafe {
         APP_STATE
@@ -67,6 +109,10 @@ pub fn init() -> () {
 #[no_mangle]
 pub fn step() {
     const BUTTON_W: u32 = 32;
+    const LAP_BUTTON_W: u32 = 40;
+    const MODE_BUTTON_W: u32 = 70;
+    const MINUTES_INPUT_W: u32 = 130;
+    const LAPS_H: u32 = 70;
 
     let state = unsafe { APP_STATE.get_mut().expect("App not initialized") };
 
@@ -92,10 +138,23 @@ pub fn step() {
         t_now,
     );
 
+    let show_laps = state.mode == Mode::Chrono && !state.laps.is_empty();
+
     let layout_1 = make_vertical_layout(
         &win_rect.offset(-(stylesheet.margin as i64)),
         stylesheet.margin,
-        &[LayoutItem::Fixed { size: BUTTON_W }, LayoutItem::Float],
+        &[
+            vec![
+                LayoutItem::Fixed { size: BUTTON_W },
+                LayoutItem::Fixed { size: BUTTON_W },
+                LayoutItem::Float,
+            ],
+            match show_laps {
+                true => vec![LayoutItem::Fixed { size: LAPS_H }],
+                false => vec![],
+            },
+        ]
+        .concat(),
     );
 
     let layout_2 = make_horizontal_layout(
@@ -103,30 +162,48 @@ pub fn step() {
         stylesheet.margin,
         &[
             LayoutItem::Float,
+            LayoutItem::Fixed { size: LAP_BUTTON_W },
             LayoutItem::Fixed { size: BUTTON_W },
             LayoutItem::Fixed { size: BUTTON_W },
         ],
     );
 
-    let font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.medium);
-
-    let elapsed = match state.chrono_state {
-        ChronoState::Stopped => 0.0,
-        ChronoState::Paused { t_elapsed } => t_elapsed,
-        ChronoState::Running { t_resume, t_offset } => t_now - t_resume + t_offset,
-    };
+    let layout_3 = make_horizontal_layout(
+        &layout_1[1],
+        stylesheet.margin,
+        &[
+            LayoutItem::Fixed {
+                size: MODE_BUTTON_W,
+            },
+            LayoutItem::Float,
+            LayoutItem::Fixed {
+                size: MINUTES_INPUT_W,
+            },
+        ],
+    );
 
-    let time_str = {
-        let t_ms = f64::round(elapsed) as u32;
-        let disp_ms = t_ms % 1000;
+    let font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.medium);
 
-        let t_s = t_ms / 1000;
-        let disp_s = t_s % 60;
+    //
+    // Countdown expiry
 
-        let t_min = t_s / 60;
-        let disp_min = t_min % 60;
+    if let CountdownState::Running { t_end } = state.countdown_state {
+        if t_now >= t_end {
+            log::info!("Countdown finished");
+            state.countdown_state = CountdownState::Ringing { t_fired: t_end };
+            state.mode = Mode::Countdown;
+            guestlib::request_focus();
+        }
+    }
 
-        format!("{:02}:{:02}.{:03}", disp_min, disp_s, disp_ms)
+    let displayed_time = match state.mode {
+        Mode::Chrono => chrono_elapsed(&state.chrono_state, t_now),
+        Mode::Countdown => match state.countdown_state {
+            CountdownState::Stopped => state.countdown_duration,
+            CountdownState::Paused { t_remaining } => t_remaining,
+            CountdownState::Running { t_end } => t_end - t_now,
+            CountdownState::Ringing { .. } => 0.0,
+        },
     };
 
     draw_rect(
@@ -136,78 +213,145 @@ pub fn step() {
         false,
     );
 
+    draw_rect(
+        uitk_context.fb,
+        &layout_1[1],
+        stylesheet.colors.element,
+        false,
+    );
+
     draw_line_in_rect(
         uitk_context.fb,
-        &time_str,
+        &format_time(displayed_time),
         &layout_2[0],
         font,
         Color::YELLOW,
         TextJustification::Left,
     );
 
-    match state.chrono_state {
-        ChronoState::Stopped => {
-            let play_pressed = uitk_context.button(&ButtonConfig {
-                rect: layout_2[2].clone(),
-                icon: Some(("play_icon".to_owned(), &PLAY_ICON)),
-                ..Default::default()
-            });
+    let mode_pressed = uitk_context.button(&ButtonConfig {
+        rect: layout_3[0].clone(),
+        text: match state.mode {
+            Mode::Chrono => "Timer".to_owned(),
+            Mode::Countdown => "Chrono".to_owned(),
+        },
+        ..Default::default()
+    });
+
+    if mode_pressed {
+        state.mode = match state.mode {
+            Mode::Chrono => Mode::Countdown,
+            Mode::Countdown => Mode::Chrono,
+        };
+    }
 
-            if play_pressed {
-                state.chrono_state = ChronoState::Running {
-                    t_resume: t_now,
-                    t_offset: 0.0,
-                };
+    match state.mode {
+        Mode::Chrono => {
+            if let ChronoState::Running { .. } = state.chrono_state {
+                let lap_pressed = uitk_context.button(&ButtonConfig {
+                    rect: layout_2[1].clone(),
+                    text: "Lap".to_owned(),
+                    ..Default::default()
+                });
+
+                if lap_pressed {
+                    let t_lap = chrono_elapsed(&state.chrono_state, t_now);
+                    let t_prev = state.laps.last().copied().unwrap_or(0.0);
+                    state.laps.push(t_lap);
+                    state
+                        .laps_text
+                        .mutate(uitk_context.uuid_provider)
+                        .push_str(&format!(
+                            "Lap {:<3} {}  {}\n",
+                            state.laps.len(),
+                            format_time(t_lap - t_prev),
+                            format_time(t_lap)
+                        ));
+                }
             }
-        }
 
-        ChronoState::Paused { t_elapsed } => {
-            let stop_pressed = uitk_context.button(&ButtonConfig {
-                rect: layout_2[1].clone(),
-                icon: Some(("stop_icon".to_owned(), &STOP_ICON)),
-                ..Default::default()
-            });
-
-            let play_pressed = uitk_context.button(&ButtonConfig {
-                rect: layout_2[2].clone(),
-                icon: Some(("play_icon".to_owned(), &PLAY_ICON)),
-                ..Default::default()
-            });
-
-            if stop_pressed {
-                state.chrono_state = ChronoState::Stopped;
-            } else if play_pressed {
-                state.chrono_state = ChronoState::Running {
-                    t_resume: t_now,
-                    t_offset: t_elapsed,
-                };
+            match chrono_controls(&mut uitk_context, &layout_2, &state.chrono_state) {
+                Some(Control::Stop) => {
+                    state.chrono_state = ChronoState::Stopped;
+                    state.laps.clear();
+                    *state.laps_text.mutate(uitk_context.uuid_provider) = String::new();
+                }
+                Some(Control::Pause) => {
+                    state.chrono_state = ChronoState::Paused {
+                        t_elapsed: chrono_elapsed(&state.chrono_state, t_now),
+                    };
+                }
+                Some(Control::Play) => {
+                    state.chrono_state = ChronoState::Running {
+                        t_resume: t_now,
+                        t_offset: chrono_elapsed(&state.chrono_state, t_now),
+                    };
+                }
+                None => (),
             }
         }
 
-        ChronoState::Running { t_resume, t_offset } => {
-            let stop_pressed = uitk_context.button(&ButtonConfig {
-                rect: layout_2[1].clone(),
-                icon: Some(("stop_icon".to_owned(), &STOP_ICON)),
-                ..Default::default()
-            });
-
-            let pause_pressed = uitk_context.button(&ButtonConfig {
-                rect: layout_2[2].clone(),
-                icon: Some(("pause_icon".to_owned(), &PAUSE_ICON)),
-                ..Default::default()
-            });
+        Mode::Countdown => {
+            // Whole minutes of the remaining time, the seconds stay as they are
+            let old_minutes = (displayed_time / 60_000.0) as i64;
+            let mut minutes = old_minutes;
+            uitk_context.number_input(
+                &NumberInputConfig {
+                    id: ContentId::from_hash(&"countdown_minutes"),
+                    rect: layout_3[2].clone(),
+                    label: "min".to_owned(),
+                    min: 0.0,
+                    max: (MAX_COUNTDOWN / 60_000.0).floor(),
+                    drag_px_per_step: 8,
+                    ..Default::default()
+                },
+                &mut minutes,
+            );
+
+            if minutes != old_minutes {
+                adjust_countdown(
+                    &mut state.countdown_state,
+                    &mut state.countdown_duration,
+                    (minutes - old_minutes) as f64 * 60_000.0,
+                    t_now,
+                );
+            }
 
-            if stop_pressed {
-                state.chrono_state = ChronoState::Stopped;
-            } else if pause_pressed {
-                state.chrono_state = ChronoState::Paused {
-                    t_elapsed: t_now - t_resume + t_offset,
-                };
+            let control_state = match state.countdown_state {
+                CountdownState::Stopped => ChronoState::Stopped,
+                CountdownState::Running { .. } => ChronoState::Running {
+                    t_resume: t_now,
+                    t_offset: 0.0,
+                },
+                CountdownState::Paused { .. } | CountdownState::Ringing { .. } => {
+                    ChronoState::Paused { t_elapsed: 0.0 }
+                }
+            };
+
+            match chrono_controls(&mut uitk_context, &layout_2, &control_state) {
+                Some(Control::Stop) => state.countdown_state = CountdownState::Stopped,
+                Some(Control::Pause) => {
+                    if let CountdownState::Running { t_end } = state.countdown_state {
+                        state.countdown_state = CountdownState::Paused {
+                            t_remaining: t_end - t_now,
+                        };
+                    }
+                }
+                Some(Control::Play) => {
+                    let t_remaining = match state.countdown_state {
+                        CountdownState::Paused { t_remaining } => t_remaining,
+                        _ => state.countdown_duration,
+                    };
+                    state.countdown_state = CountdownState::Running {
+                        t_end: t_now + t_remaining,
+                    };
+                }
+                None => (),
             }
         }
-    };
+    }
 
-    let mut canvas_fb = uitk_context.fb.subregion_mut(&layout_1[1]);
+    let mut canvas_fb = uitk_context.fb.subregion_mut(&layout_1[2]);
     let (canvas_w, canvas_h) = canvas_fb.shape();
 
     let bg_content_id = ContentId::from_hash(&(canvas_w, canvas_h));
@@ -222,5 +366,148 @@ pub fn step() {
 
     canvas_fb.copy_from_fb(bg_fb, (0, 0), false);
 
-    draw_chrono(&mut canvas_fb, elapsed);
+    draw_chrono(&mut canvas_fb, displayed_time);
+
+    if show_laps {
+        uitk_context.text_box(
+            &layout_1[3],
+            &state.laps_text,
+            &mut state.laps_text_state,
+            true,
+        );
+    }
+
+    //
+    // Alarm
+
+    if let CountdownState::Ringing { t_fired } = state.countdown_state {
+        let flash_on = ((t_now - t_fired) / FLASH_PERIOD) as u64 % 2 == 0;
+        if flash_on {
+            draw_rect(uitk_context.fb, &win_rect, FLASH_COLOR, true);
+        }
+    }
+
+    uitk_context.draw_overlay();
+}
+
+enum Control {
+    Stop,
+    Pause,
+    Play,
+}
+
+fn chrono_controls<F: FbViewMut>(
+    uitk_context: &mut uitk::UiContext<F>,
+    layout: &[applib::Rect],
+    chrono_state: &ChronoState,
+) -> Option<Control> {
+    let stop_config = ButtonConfig {
+        rect: layout[2].clone(),
+        icon: Some(("stop_icon".to_owned(), &STOP_ICON)),
+        tooltip: Some("Reset".to_owned()),
+        ..Default::default()
+    };
+
+    let play_config = ButtonConfig {
+        rect: layout[3].clone(),
+        icon: Some(("play_icon".to_owned(), &PLAY_ICON)),
+        tooltip: Some("Start".to_owned()),
+        ..Default::default()
+    };
+
+    let pause_config = ButtonConfig {
+        rect: layout[3].clone(),
+        icon: Some(("pause_icon".to_owned(), &PAUSE_ICON)),
+        tooltip: Some("Pause".to_owned()),
+        ..Default::default()
+    };
+
+    match chrono_state {
+        ChronoState::Stopped => uitk_context.button(&play_config).then_some(Control::Play),
+
+        ChronoState::Paused { .. } => {
+            let stop_pressed = uitk_context.button(&stop_config);
+            let play_pressed = uitk_context.button(&play_config);
+
+            if stop_pressed {
+                Some(Control::Stop)
+            } else if play_pressed {
+                Some(Control::Play)
+            } else {
+                None
+            }
+        }
+
+        ChronoState::Running { .. } => {
+            let stop_pressed = uitk_context.button(&stop_config);
+            let pause_pressed = uitk_context.button(&pause_config);
+
+            if stop_pressed {
+                Some(Control::Stop)
+            } else if pause_pressed {
+                Some(Control::Pause)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+fn chrono_elapsed(chrono_state: &ChronoState, t_now: f64) -> f64 {
+    match *chrono_state {
+        ChronoState::Stopped => 0.0,
+        ChronoState::Paused { t_elapsed } => t_elapsed,
+        ChronoState::Running { t_resume, t_offset } => t_now - t_resume + t_offset,
+    }
+}
+
+fn adjust_countdown(
+    countdown_state: &mut CountdownState,
+    countdown_duration: &mut f64,
+    delta: f64,
+    t_now: f64,
+) {
+    let clamp = |t: f64| f64::min(f64::max(t, 0.0), MAX_COUNTDOWN);
+
+    match countdown_state {
+        CountdownState::Stopped | CountdownState::Ringing { .. } => {
+            *countdown_duration = clamp(*countdown_duration + delta);
+            *countdown_state = CountdownState::Stopped;
+            save_countdown_duration(*countdown_duration);
+        }
+        CountdownState::Paused { t_remaining } => *t_remaining = clamp(*t_remaining + delta),
+        CountdownState::Running { t_end } => *t_end = t_now + clamp(*t_end - t_now + delta),
+    }
+}
+
+fn load_countdown_duration() -> f64 {
+    let duration = guestlib::storage_read(COUNTDOWN_FILE)
+        .ok()
+        .and_then(|data| String::from_utf8(data).ok())
+        .and_then(|s| s.trim().parse::<f64>().ok());
+
+    match duration {
+        Some(duration) => f64::min(f64::max(duration, 0.0), MAX_COUNTDOWN),
+        None => DEFAULT_COUNTDOWN,
+    }
+}
+
+fn save_countdown_duration(duration: f64) {
+    let data = format!("{}", duration);
+    if let Err(err) = guestlib::storage_write(COUNTDOWN_FILE, data.as_bytes()) {
+        log::error!("Cannot save countdown duration: {}", err);
+    }
+}
+
+fn format_time(t: f64) -> String {
+    let t_ms = f64::round(f64::max(t, 0.0)) as u32;
+    let disp_ms = t_ms % 1000;
+
+    let t_s = t_ms / 1000;
+    let disp_s = t_s % 60;
+
+    let t_min = t_s / 60;
+    let disp_min = t_min % 60;
+
+    format!("{:02}:{:02}.{:03}", disp_min, disp_s, disp_ms)
 }
@@ -0,0 +1,173 @@
+use applib::content::TrackedContent;
+use applib::drawing::primitives::{draw_rect, draw_rect_outline};
+use applib::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use applib::input::Keycode;
+use applib::uitk::layout::{make_horizontal_layout, make_vertical_layout, LayoutItem};
+use applib::uitk::{ButtonConfig, TextBoxState, UiContext, UuidProvider};
+use applib::{FbViewMut, Rect};
+
+use crate::fs::{ItemKind, ListItem};
+
+const DIALOG_W: u32 = 400;
+const ROW_H: u32 = 30;
+const BUTTON_W: u32 = 100;
+
+pub enum Dialog {
+    Closed,
+    NewFile {
+        name: TrackedContent<String>,
+        name_state: TextBoxState,
+    },
+    Rename {
+        item: ListItem,
+        name: TrackedContent<String>,
+        name_state: TextBoxState,
+    },
+    ConfirmDelete {
+        items: Vec<ListItem>,
+    },
+}
+
+pub enum DialogAction {
+    Confirm,
+    Cancel,
+}
+
+impl Dialog {
+    pub fn new_file(uuid_provider: &mut UuidProvider) -> Self {
+        let mut name_state = TextBoxState::new();
+        name_state.focus();
+        Dialog::NewFile {
+            name: TrackedContent::new(String::new(), uuid_provider),
+            name_state,
+        }
+    }
+
+    pub fn rename(uuid_provider: &mut UuidProvider, item: ListItem) -> Self {
+        let mut name_state = TextBoxState::new();
+        name_state.cursor = item.name.chars().count();
+        name_state.focus();
+        Dialog::Rename {
+            name: TrackedContent::new(item.name.clone(), uuid_provider),
+            name_state,
+            item,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        !matches!(self, Dialog::Closed)
+    }
+}
+
+pub fn dialog<F: FbViewMut>(
+    uitk_context: &mut UiContext<F>,
+    rect: &Rect,
+    dialog: &mut Dialog,
+) -> Option<DialogAction> {
+    let stylesheet = uitk_context.stylesheet.clone();
+    let m = stylesheet.margin;
+    let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.medium);
+
+    let (title, confirm_text) = match dialog {
+        Dialog::Closed => return None,
+        Dialog::NewFile { .. } => ("New file name (use / for folders)".to_owned(), "Create"),
+        Dialog::Rename { item, .. } => (format!("Rename {}", item.name), "Rename"),
+        Dialog::ConfirmDelete { items } => {
+            let title = match items.as_slice() {
+                [item] if item.kind == ItemKind::Dir => {
+                    format!("Delete {} and all its content?", item.name)
+                }
+                [item] => format!("Delete {}?", item.name),
+                items => format!("Delete {} items?", items.len()),
+            };
+            (title, "Delete")
+        }
+    };
+
+    let dialog_h = 3 * ROW_H + 4 * m;
+    let (xc, yc) = rect.center();
+    let dialog_rect = Rect {
+        x0: xc - DIALOG_W as i64 / 2,
+        y0: yc - dialog_h as i64 / 2,
+        w: DIALOG_W,
+        h: dialog_h,
+    };
+
+    draw_rect(
+        uitk_context.fb,
+        &dialog_rect,
+        stylesheet.colors.element,
+        false,
+    );
+    draw_rect_outline(
+        uitk_context.fb,
+        &dialog_rect,
+        stylesheet.colors.outline,
+        false,
+        m,
+    );
+
+    let dialog_layout = make_vertical_layout(
+        &dialog_rect.offset(-(m as i64)),
+        m,
+        &[LayoutItem::Fixed { size: ROW_H }; 3],
+    );
+
+    let buttons_layout = make_horizontal_layout(
+        &dialog_layout[2],
+        m,
+        &[
+            LayoutItem::Float,
+            LayoutItem::Fixed { size: BUTTON_W },
+            LayoutItem::Fixed { size: BUTTON_W },
+        ],
+    );
+
+    draw_line_in_rect(
+        uitk_context.fb,
+        &title,
+        &dialog_layout[0],
+        font,
+        stylesheet.colors.text,
+        TextJustification::Left,
+    );
+
+    match dialog {
+        Dialog::NewFile { name, name_state }
+        | Dialog::Rename {
+            name, name_state, ..
+        } => {
+            uitk_context.editable_text_box(
+                &dialog_layout[1],
+                name,
+                name_state,
+                false,
+                false,
+                None::<&TrackedContent<String>>,
+            );
+        }
+        _ => (),
+    }
+
+    let confirm = uitk_context.button(&ButtonConfig {
+        rect: buttons_layout[1].clone(),
+        text: confirm_text.to_owned(),
+        ..Default::default()
+    });
+    let cancel = uitk_context.button(&ButtonConfig {
+        rect: buttons_layout[2].clone(),
+        text: "Cancel".to_owned(),
+        ..Default::default()
+    });
+
+    let input_state = uitk_context.input_state;
+
+    // Enter on the focused Cancel button cancels
+    if cancel || input_state.check_key_pressed(Keycode::KEY_ESC) {
+        Some(DialogAction::Cancel)
+    } else if confirm || input_state.check_key_pressed(Keycode::KEY_ENTER) {
+        Some(DialogAction::Confirm)
+    } else {
+        None
+    }
+}
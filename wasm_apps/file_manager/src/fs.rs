@@ -0,0 +1,212 @@
+use alloc::format;
+use guestlib::FileInfo;
+
+// The storage is a flat namespace: directories only exist as path prefixes
+// ("docs/notes.txt" lives in "docs/"). Directory paths always end with a slash,
+// and the root is the empty string.
+
+pub struct FileEntry {
+    pub path: String,
+    pub info: FileInfo,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum ItemKind {
+    Dir,
+    File,
+}
+
+// One row of the file list, for the current directory
+#[derive(Clone)]
+pub struct ListItem {
+    pub name: String,
+    pub path: String,
+    pub kind: ItemKind,
+    pub size: usize, // For directories: total size of all contained files
+    pub modified: f64,
+}
+
+pub fn list_storage() -> anyhow::Result<Vec<FileEntry>> {
+    let names = guestlib::storage_list()?;
+
+    let entries = names
+        .into_iter()
+        .filter_map(|path| {
+            // Can fail if the file was deleted in the meantime
+            let info = guestlib::storage_stat(&path).ok()?;
+            Some(FileEntry { path, info })
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+pub fn dir_exists(entries: &[FileEntry], dir: &str) -> bool {
+    dir.is_empty() || entries.iter().any(|entry| entry.path.starts_with(dir))
+}
+
+pub fn list_dir(entries: &[FileEntry], dir: &str) -> Vec<ListItem> {
+    let mut subdirs: Vec<ListItem> = Vec::new();
+    let mut files: Vec<ListItem> = Vec::new();
+
+    for entry in entries {
+        let Some(rel_path) = entry.path.strip_prefix(dir) else {
+            continue;
+        };
+
+        match rel_path.split_once('/') {
+            None => files.push(ListItem {
+                name: rel_path.to_owned(),
+                path: entry.path.clone(),
+                kind: ItemKind::File,
+                size: entry.info.size,
+                modified: entry.info.modified,
+            }),
+
+            Some((subdir_name, _)) => {
+                // Entries are sorted, so all files of a subdirectory are contiguous
+                match subdirs.last_mut() {
+                    Some(item) if item.name == subdir_name => {
+                        item.size += entry.info.size;
+                        item.modified = f64::max(item.modified, entry.info.modified);
+                    }
+                    _ => subdirs.push(ListItem {
+                        name: subdir_name.to_owned(),
+                        path: format!("{}{}/", dir, subdir_name),
+                        kind: ItemKind::Dir,
+                        size: entry.info.size,
+                        modified: entry.info.modified,
+                    }),
+                }
+            }
+        }
+    }
+
+    subdirs.append(&mut files);
+    subdirs
+}
+
+pub fn parent_dir(dir: &str) -> String {
+    let trimmed = dir.trim_end_matches('/');
+    match trimmed.rfind('/') {
+        Some(i) => trimmed[..i + 1].to_owned(),
+        None => String::new(),
+    }
+}
+
+pub fn dir_name(dir: &str) -> &str {
+    let trimmed = dir.trim_end_matches('/');
+    match trimmed.rfind('/') {
+        Some(i) => &trimmed[i + 1..],
+        None => trimmed,
+    }
+}
+
+// Files affected by an operation on an item (all contained files for a directory)
+fn item_files<'a>(entries: &'a [FileEntry], item: &ListItem) -> Vec<&'a str> {
+    entries
+        .iter()
+        .map(|entry| entry.path.as_str())
+        .filter(|path| match item.kind {
+            ItemKind::File => *path == item.path,
+            ItemKind::Dir => path.starts_with(&item.path),
+        })
+        .collect()
+}
+
+pub fn delete_item(entries: &[FileEntry], item: &ListItem) -> anyhow::Result<()> {
+    for path in item_files(entries, item) {
+        guestlib::storage_delete(path)?;
+    }
+    Ok(())
+}
+
+pub fn rename_item(entries: &[FileEntry], item: &ListItem, new_name: &str) -> anyhow::Result<()> {
+    if new_name.is_empty() || new_name.contains('/') {
+        return Err(anyhow::Error::msg("Invalid name"));
+    }
+
+    let new_file_path = format!("{}{}", parent_dir(&item.path), new_name);
+    let new_dir_path = format!("{}/", new_file_path);
+
+    // Either a file or a directory with that name
+    let conflict = entries
+        .iter()
+        .any(|entry| entry.path == new_file_path || entry.path.starts_with(&new_dir_path));
+
+    if conflict {
+        return Err(anyhow::Error::msg(format!("{} already exists", new_name)));
+    }
+
+    let new_prefix = match item.kind {
+        ItemKind::File => new_file_path,
+        ItemKind::Dir => new_dir_path,
+    };
+
+    let moves: Vec<(&str, String)> = item_files(entries, item)
+        .into_iter()
+        .map(|path| (path, format!("{}{}", new_prefix, &path[item.path.len()..])))
+        .collect();
+
+    // Copying everything first, so that a failure (e.g. quota) does not lose data
+    for (old_path, new_path) in moves.iter() {
+        let data = guestlib::storage_read(old_path)?;
+        guestlib::storage_write(new_path, &data)?;
+    }
+
+    for (old_path, _) in moves.iter() {
+        guestlib::storage_delete(old_path)?;
+    }
+
+    Ok(())
+}
+
+pub fn create_file(entries: &[FileEntry], dir: &str, name: &str) -> anyhow::Result<String> {
+    let path = format!("{}{}", dir, name.trim_matches('/'));
+
+    if name.trim_matches('/').is_empty() {
+        return Err(anyhow::Error::msg("Invalid name"));
+    }
+
+    if entries.iter().any(|entry| entry.path == path) {
+        return Err(anyhow::Error::msg(format!("{} already exists", name)));
+    }
+
+    guestlib::storage_write(&path, &[])?;
+
+    Ok(path)
+}
+
+pub fn format_size(size: usize) -> String {
+    match size {
+        s if s < 1000 => format!("{} B", s),
+        s if s < 1_000_000 => format!("{:.1} kB", s as f64 / 1000.0),
+        s => format!("{:.1} MB", s as f64 / 1_000_000.0),
+    }
+}
+
+// UTC date from a UNIX time in ms
+pub fn format_time(t: f64) -> String {
+    let secs = (t / 1000.0) as i64;
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // Days to civil date, from Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60
+    )
+}
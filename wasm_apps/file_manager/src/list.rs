@@ -0,0 +1,94 @@
+use applib::drawing::text::TextJustification;
+use applib::uitk::{ColumnDef, ContentId, TreeAdapter};
+
+use crate::fs::{dir_name, format_size, format_time, list_dir, FileEntry, ItemKind, ListItem};
+
+pub const ROW_H: u32 = 20;
+const NAME_COLUMN_MIN_W: u32 = 100;
+const SIZE_COLUMN_W: u32 = 80;
+const MODIFIED_COLUMN_W: u32 = 140;
+
+// The name column initially takes the room left by the others. The items keep the
+// order of list_dir(), so the columns are not sortable.
+pub fn column_defs(list_w: u32) -> [ColumnDef; 3] {
+    let name_w = list_w.saturating_sub(SIZE_COLUMN_W + MODIFIED_COLUMN_W);
+    [
+        ColumnDef {
+            title: "Name".to_owned(),
+            width: u32::max(NAME_COLUMN_MIN_W, name_w),
+            min_width: NAME_COLUMN_MIN_W,
+            sortable: false,
+            ..Default::default()
+        },
+        ColumnDef {
+            title: "Size".to_owned(),
+            width: SIZE_COLUMN_W,
+            justification: TextJustification::Right,
+            sortable: false,
+            ..Default::default()
+        },
+        ColumnDef {
+            title: "Modified".to_owned(),
+            width: MODIFIED_COLUMN_W,
+            justification: TextJustification::Right,
+            sortable: false,
+            ..Default::default()
+        },
+    ]
+}
+
+// Only called for the visible rows, so that huge directories stay cheap
+pub fn cell(item: &ListItem, column: usize) -> String {
+    match column {
+        0 => match item.kind {
+            ItemKind::Dir => format!("{}/", item.name),
+            ItemKind::File => item.name.clone(),
+        },
+        1 => format_size(item.size),
+        _ => format_time(item.modified),
+    }
+}
+
+// The directory tree only walks the directories the user expands
+pub struct DirTreeAdapter<'a> {
+    pub entries: &'a [FileEntry],
+}
+
+pub fn dir_id(dir: &str) -> ContentId {
+    ContentId::from_hash(&dir)
+}
+
+impl<'a> TreeAdapter for DirTreeAdapter<'a> {
+    type Node = String;
+
+    fn id(&self, dir: &String) -> ContentId {
+        dir_id(dir)
+    }
+
+    fn roots(&self) -> impl Iterator<Item = String> {
+        core::iter::once(String::new())
+    }
+
+    fn children(&self, dir: &String) -> impl Iterator<Item = String> {
+        list_dir(self.entries, dir)
+            .into_iter()
+            .filter(|item| item.kind == ItemKind::Dir)
+            .map(|item| item.path)
+    }
+
+    fn label(&self, dir: &String) -> String {
+        match dir.is_empty() {
+            true => "/".to_owned(),
+            false => format!("{}/", dir_name(dir)),
+        }
+    }
+
+    fn has_children(&self, dir: &String) -> bool {
+        self.entries.iter().any(|entry| {
+            entry
+                .path
+                .strip_prefix(dir.as_str())
+                .is_some_and(|rel_path| rel_path.contains('/'))
+        })
+    }
+}
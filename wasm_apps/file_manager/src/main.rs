@@ -0,0 +1,568 @@
+extern crate alloc;
+
+use alloc::collections::BTreeSet;
+use alloc::format;
+use applib::drawing::primitives::draw_rect;
+use applib::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use applib::input::{InputState, Keycode};
+use applib::uitk::layout::{make_horizontal_layout, make_vertical_layout, LayoutItem};
+use applib::uitk::{
+    self, ButtonConfig, ContentId, ContextMenuConfig, MenuItem, SplitConfig, TableConfig,
+    TableEvent, TableState, TreeViewConfig, TreeViewEvent, TreeViewState, UuidProvider,
+};
+use core::cell::OnceCell;
+use guestlib::{PixelData, WasmLogger};
+
+mod dialog;
+mod fs;
+mod list;
+
+use dialog::{dialog, Dialog, DialogAction};
+use fs::{FileEntry, ItemKind, ListItem};
+use list::{DirTreeAdapter, ROW_H};
+
+static LOGGER: WasmLogger = WasmLogger;
+const LOGGING_LEVEL: log::LevelFilter = log::LevelFilter::Debug;
+
+const TEXT_EDITOR_APP: &str = "Text Editor";
+const REFRESH_PERIOD: f64 = 2000.0; // in ms
+const BUTTON_H: u32 = 30;
+const TREE_RATIO: f32 = 0.3;
+const TREE_MIN_W: u32 = 100;
+const LIST_MIN_W: u32 = 200;
+
+struct AppState {
+    pixel_data: PixelData,
+    ui_store: uitk::UiStore,
+    uuid_provider: UuidProvider,
+
+    entries: Vec<FileEntry>,
+    items: Vec<ListItem>,
+    current_dir: String,
+    t_last_refresh: f64,
+
+    table_state: TableState,
+    tree_state: TreeViewState,
+    tree_ratio: f32,
+
+    dialog: Dialog,
+    error_msg: Option<String>,
+}
+
+static mut APP_STATE: OnceCell<AppState> = OnceCell::new();
+
+fn main() {}
+
+#[no_mangle]
+pub fn init() -> () {
+    log::set_max_level(LOGGING_LEVEL);
+    log::set_logger(&LOGGER).unwrap();
+
+    let mut ui_store = uitk::UiStore::new();
+    ui_store.set_clipboard(guestlib::HostClipboard);
+
+    let mut state = AppState {
+        pixel_data: PixelData::new(),
+        ui_store,
+        uuid_provider: UuidProvider::new(),
+
+        entries: Vec::new(),
+        items: Vec::new(),
+        current_dir: String::new(),
+        t_last_refresh: guestlib::get_time(),
+
+        table_state: TableState::new(),
+        tree_state: TreeViewState::new(),
+        tree_ratio: TREE_RATIO,
+
+        dialog: Dialog::Closed,
+        error_msg: None,
+    };
+
+    refresh(&mut state);
+    reveal_dir(&mut state.tree_state, "");
+
+    unsafe {
+        APP_STATE
+            .set(state)
+            .unwrap_or_else(|_| panic!("App already initialized"));
+    }
+}
+
+#[no_mangle]
+pub fn step() {
+    let state = unsafe { APP_STATE.get_mut().expect("App not initialized") };
+
+    let time = guestlib::get_time();
+    let stylesheet = guestlib::get_stylesheet();
+    let input_state = guestlib::get_input_state();
+    let win_rect = guestlib::get_win_rect().zero_origin();
+
+    let unfocused_input_state = {
+        let mut input_state = input_state.clone();
+        input_state.clear_events();
+        input_state
+    };
+
+    // Other apps can write to the storage too
+    if !state.dialog.is_open() && time - state.t_last_refresh > REFRESH_PERIOD {
+        refresh(state);
+    }
+
+    let m = stylesheet.margin;
+    let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+
+    let main_layout = make_vertical_layout(
+        &win_rect.offset(-(m as i64)),
+        m,
+        &[
+            LayoutItem::Fixed { size: BUTTON_H },
+            LayoutItem::Float,
+            LayoutItem::Fixed { size: ROW_H },
+        ],
+    );
+
+    let toolbar_layout = make_horizontal_layout(
+        &main_layout[0],
+        m,
+        &[
+            LayoutItem::Fixed { size: 50 },
+            LayoutItem::Fixed { size: 50 },
+            LayoutItem::Fixed { size: 70 },
+            LayoutItem::Fixed { size: 70 },
+            LayoutItem::Fixed { size: 120 },
+            LayoutItem::Float,
+        ],
+    );
+
+    //
+    // Keyboard shortcuts
+
+    // The arrow keys belong to the tree or the list while they have the focus
+    if !state.dialog.is_open() && !state.tree_state.is_focused() {
+        handle_keyboard(state, &input_state);
+    }
+
+    let mut framebuffer = state.pixel_data.get_framebuffer();
+
+    let mut uitk_context = state.ui_store.get_context(
+        &mut framebuffer,
+        &stylesheet,
+        &input_state,
+        &mut state.uuid_provider,
+        time,
+    );
+
+    // The dialog is modal
+    if state.dialog.is_open() {
+        uitk_context.input_state = &unfocused_input_state;
+    }
+
+    draw_rect(
+        uitk_context.fb,
+        &win_rect,
+        stylesheet.colors.background,
+        false,
+    );
+
+    //
+    // Toolbar
+
+    let toolbar_buttons = ["Up", "New", "Rename", "Delete", "Text editor"];
+    let pressed: Vec<bool> = toolbar_buttons
+        .iter()
+        .zip(toolbar_layout.iter())
+        .map(|(text, rect)| {
+            uitk_context.button(&ButtonConfig {
+                rect: rect.clone(),
+                text: text.to_string(),
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    draw_line_in_rect(
+        uitk_context.fb,
+        &format!("/{}", state.current_dir),
+        &toolbar_layout[5],
+        font,
+        stylesheet.colors.text,
+        TextJustification::Left,
+    );
+
+    // Applied once the UI is drawn
+    let mut action = match pressed.iter().position(|&p| p) {
+        Some(0) => Some(Action::GoUp),
+        Some(1) => Some(Action::NewFile),
+        Some(2) => Some(Action::Rename),
+        Some(3) => Some(Action::Delete),
+        Some(4) => Some(Action::Open),
+        _ => None,
+    };
+
+    let mut tree_event = TreeViewEvent::None;
+    let mut table_event = TableEvent::None;
+
+    uitk_context.split_pane(
+        &SplitConfig {
+            id: ContentId::from_hash(&"panes"),
+            rect: main_layout[1].clone(),
+            min_sizes: (TREE_MIN_W, LIST_MIN_W),
+            default_ratio: TREE_RATIO,
+            ..Default::default()
+        },
+        &mut state.tree_ratio,
+        //
+        // Directory tree
+        |uitk_context, tree_rect| {
+            tree_event = uitk_context.tree_view(
+                &TreeViewConfig {
+                    id: ContentId::from_hash(&"dir_tree"),
+                    rect: tree_rect.clone(),
+                    row_h: ROW_H,
+                },
+                &DirTreeAdapter {
+                    entries: &state.entries,
+                },
+                &mut state.tree_state,
+            );
+        },
+        //
+        // File list
+        |uitk_context, list_rect| {
+            table_event = uitk_context.table(
+                &TableConfig {
+                    id: ContentId::from_hash(&"file_list"),
+                    rect: list_rect.clone(),
+                    row_h: ROW_H,
+                },
+                &list::column_defs(list_rect.w),
+                state.items.len(),
+                |row, col| list::cell(&state.items[row], col),
+                &mut state.table_state,
+            );
+        },
+    );
+
+    match tree_event {
+        TreeViewEvent::Selected(dir) | TreeViewEvent::Activated(dir) => {
+            action = Some(Action::ChangeDir(dir))
+        }
+        TreeViewEvent::None => (),
+    }
+
+    if let TableEvent::Activated(_) = table_event {
+        action = Some(Action::Open);
+    }
+
+    let pointer = &uitk_context.input_state.pointer;
+    let list_rect = state.table_state.body_rect();
+
+    // Right-clicking an item outside the selection selects it first
+    let menu_open_at = match pointer.right_click_trigger {
+        true if !state.dialog.is_open() && list_rect.check_contains_point(pointer.x, pointer.y) => {
+            let clicked = state.table_state.row_at(pointer.x, pointer.y);
+            if !clicked.is_some_and(|i| state.table_state.selection.contains(&i)) {
+                set_cursor(&mut state.table_state, clicked);
+            }
+            Some((pointer.x, pointer.y))
+        }
+        _ => None,
+    };
+
+    let table_state = &state.table_state;
+    let has_item = table_state.cursor.is_some() || !table_state.selection.is_empty();
+    let menu_choice = uitk_context.context_menu(&ContextMenuConfig {
+        id: ContentId::from_hash(&"list_menu"),
+        items: vec![
+            MenuItem::Entry {
+                label: "Open".to_owned(),
+                shortcut: Some("Enter".to_owned()),
+                disabled: !has_item,
+            },
+            MenuItem::Entry {
+                label: "Rename".to_owned(),
+                shortcut: Some("F2".to_owned()),
+                disabled: !has_item,
+            },
+            MenuItem::Entry {
+                label: "Delete".to_owned(),
+                shortcut: Some("Del".to_owned()),
+                disabled: !has_item,
+            },
+            MenuItem::Separator,
+            MenuItem::entry("New file"),
+        ],
+        open_at: menu_open_at,
+    });
+
+    match menu_choice {
+        Some(0) => action = Some(Action::Open),
+        Some(1) => action = Some(Action::Rename),
+        Some(2) => action = Some(Action::Delete),
+        Some(4) => action = Some(Action::NewFile),
+        _ => (),
+    }
+
+    //
+    // Status bar
+
+    let status_rect = &main_layout[2];
+    let status = match state.error_msg.as_ref() {
+        Some(error_msg) => error_msg.clone(),
+        None => format!(
+            "{} items, {} selected",
+            state.items.len(),
+            state.table_state.selection.len()
+        ),
+    };
+
+    let status_color = match state.error_msg {
+        Some(_) => stylesheet.colors.red,
+        None => stylesheet.colors.element,
+    };
+
+    draw_rect(uitk_context.fb, status_rect, status_color, false);
+    draw_line_in_rect(
+        uitk_context.fb,
+        &status,
+        status_rect,
+        font,
+        stylesheet.colors.text,
+        TextJustification::Left,
+    );
+
+    //
+    // Dialog
+
+    uitk_context.input_state = &input_state;
+
+    let dialog_action = dialog(&mut uitk_context, &win_rect, &mut state.dialog);
+
+    uitk_context.draw_overlay();
+
+    guestlib::set_cursor_hint(state.ui_store.take_cursor_hint());
+
+    match dialog_action {
+        Some(DialogAction::Confirm) => apply_dialog(state),
+        Some(DialogAction::Cancel) => state.dialog = Dialog::Closed,
+        None => (),
+    }
+
+    if let Some(action) = action {
+        apply_action(state, action);
+    }
+}
+
+enum Action {
+    GoUp,
+    NewFile,
+    Rename,
+    Delete,
+    Open,
+    ChangeDir(String),
+}
+
+fn apply_action(state: &mut AppState, action: Action) {
+    let cursor_item = cursor_item(state);
+
+    match action {
+        Action::GoUp => change_dir(state, fs::parent_dir(&state.current_dir)),
+        Action::NewFile => state.dialog = Dialog::new_file(&mut state.uuid_provider),
+        Action::Rename => {
+            if let Some(item) = cursor_item {
+                state.dialog = Dialog::rename(&mut state.uuid_provider, item);
+            }
+        }
+        Action::Delete => request_delete(state),
+        Action::Open => {
+            if let Some(item) = cursor_item {
+                open_item(state, &item);
+            }
+        }
+        Action::ChangeDir(dir) => change_dir(state, dir),
+    }
+}
+
+fn handle_keyboard(state: &mut AppState, input_state: &InputState) {
+    let nb_items = state.items.len();
+
+    let move_cursor = |cursor: Option<usize>, delta: i64| -> Option<usize> {
+        match (nb_items, cursor) {
+            (0, _) => None,
+            (_, None) => Some(0),
+            (n, Some(i)) => Some(i64::clamp(i as i64 + delta, 0, n as i64 - 1) as usize),
+        }
+    };
+
+    let cursor_item = cursor_item(state);
+    let cursor = state.table_state.cursor;
+
+    // Otherwise the list moves its cursor and opens items itself
+    let list_focused = state.table_state.is_focused();
+
+    let pressed = |keycode| input_state.check_key_pressed(keycode);
+
+    if pressed(Keycode::KEY_UP) && !list_focused {
+        set_cursor(&mut state.table_state, move_cursor(cursor, -1));
+    } else if pressed(Keycode::KEY_DOWN) && !list_focused {
+        set_cursor(&mut state.table_state, move_cursor(cursor, 1));
+    } else if pressed(Keycode::KEY_LEFT) || pressed(Keycode::KEY_BACKSPACE) {
+        change_dir(state, fs::parent_dir(&state.current_dir));
+    } else if pressed(Keycode::KEY_DELETE) {
+        request_delete(state);
+    } else if let Some(item) = cursor_item {
+        if pressed(Keycode::KEY_ENTER) && !list_focused || pressed(Keycode::KEY_RIGHT) {
+            open_item(state, &item);
+        } else if pressed(Keycode::KEY_F2) {
+            state.dialog = Dialog::rename(&mut state.uuid_provider, item);
+        }
+    }
+}
+
+fn cursor_item(state: &AppState) -> Option<ListItem> {
+    state
+        .table_state
+        .cursor
+        .and_then(|i| state.items.get(i))
+        .cloned()
+}
+
+fn set_cursor(table_state: &mut TableState, cursor: Option<usize>) {
+    table_state.cursor = cursor;
+    table_state.selection = cursor.into_iter().collect();
+
+    if let Some(i) = cursor {
+        table_state.scroll_to_row(i);
+    }
+}
+
+fn change_dir(state: &mut AppState, dir: String) {
+    reveal_dir(&mut state.tree_state, &dir);
+    state.items = fs::list_dir(&state.entries, &dir);
+    state.current_dir = dir;
+    state.table_state.reset();
+    state.table_state.cursor = match state.items.is_empty() {
+        true => None,
+        false => Some(0),
+    };
+}
+
+// Selects a directory in the tree, expanding its parents so that it is visible
+fn reveal_dir(tree_state: &mut TreeViewState, dir: &str) {
+    tree_state.selected = Some(list::dir_id(dir));
+
+    let mut parent = dir.to_owned();
+    while !parent.is_empty() {
+        parent = fs::parent_dir(&parent);
+        tree_state.set_expanded(list::dir_id(&parent), true);
+    }
+}
+
+fn open_item(state: &mut AppState, item: &ListItem) {
+    match item.kind {
+        ItemKind::Dir => change_dir(state, item.path.clone()),
+        ItemKind::File => {
+            if let Err(err) = guestlib::open_with(TEXT_EDITOR_APP, &item.path) {
+                state.error_msg = Some(format!("Cannot open {}: {}", item.name, err));
+            }
+        }
+    }
+}
+
+fn request_delete(state: &mut AppState) {
+    let mut items: Vec<ListItem> = state
+        .table_state
+        .selection
+        .iter()
+        .filter_map(|&i| state.items.get(i).cloned())
+        .collect();
+
+    if items.is_empty() {
+        items.extend(cursor_item(state));
+    }
+
+    if !items.is_empty() {
+        state.dialog = Dialog::ConfirmDelete { items };
+    }
+}
+
+fn apply_dialog(state: &mut AppState) {
+    let dialog = core::mem::replace(&mut state.dialog, Dialog::Closed);
+
+    let res = match &dialog {
+        Dialog::Closed => Ok(()),
+        Dialog::NewFile { name, .. } => {
+            fs::create_file(&state.entries, &state.current_dir, name.as_ref().trim()).map(|_| ())
+        }
+        Dialog::Rename { item, name, .. } => {
+            fs::rename_item(&state.entries, item, name.as_ref().trim())
+        }
+        Dialog::ConfirmDelete { items } => items
+            .iter()
+            .try_for_each(|item| fs::delete_item(&state.entries, item)),
+    };
+
+    match res {
+        Ok(()) => state.error_msg = None,
+        Err(err) => {
+            log::error!("{}", err);
+            state.error_msg = Some(err.to_string());
+            // Letting the user fix the name
+            if !matches!(dialog, Dialog::ConfirmDelete { .. }) {
+                state.dialog = dialog;
+            }
+        }
+    }
+
+    refresh(state);
+}
+
+fn refresh(state: &mut AppState) {
+    state.t_last_refresh = guestlib::get_time();
+
+    state.entries = match fs::list_storage() {
+        Ok(entries) => entries,
+        Err(err) => {
+            state.error_msg = Some(format!("Cannot list files: {}", err));
+            return;
+        }
+    };
+
+    if !fs::dir_exists(&state.entries, &state.current_dir) {
+        state.current_dir = String::new();
+        reveal_dir(&mut state.tree_state, "");
+    }
+
+    // Keeping the selection and cursor on the same items if they still exist
+    let selected_paths: BTreeSet<String> = state
+        .table_state
+        .selection
+        .iter()
+        .filter_map(|&i| state.items.get(i))
+        .map(|item| item.path.clone())
+        .collect();
+    let cursor_path = cursor_item(state).map(|item| item.path);
+
+    state.items = fs::list_dir(&state.entries, &state.current_dir);
+
+    state.table_state.selection = state
+        .items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| selected_paths.contains(&item.path))
+        .map(|(i, _)| i)
+        .collect();
+
+    let cursor = state.table_state.cursor;
+    state.table_state.cursor = match state.items.is_empty() {
+        true => None,
+        false => {
+            let i = state
+                .items
+                .iter()
+                .position(|item| Some(&item.path) == cursor_path.as_ref());
+            Some(i.unwrap_or(usize::min(cursor.unwrap_or(0), state.items.len() - 1)))
+        }
+    };
+}
@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+use std::net::Ipv4Addr;
+
+use crate::dns;
+use crate::lines::LineBuffer;
+
+const DNS_SERVER: [u8; 4] = [1, 1, 1, 1];
+const DNS_PORT: u16 = 53;
+
+const CONNECT_TIMEOUT: f64 = 15_000.0;
+const MIN_BACKOFF: f64 = 2_000.0;
+const MAX_BACKOFF: f64 = 120_000.0;
+
+const READ_BUF_SIZE: usize = 4096;
+
+enum ConnState {
+    Idle,
+    Waiting {
+        t_retry: f64,
+    },
+    Resolving {
+        handle: i32,
+        query: Vec<u8>,
+        sent: usize,
+        resp: Vec<u8>,
+        t_start: f64,
+    },
+    Connecting {
+        handle: i32,
+        t_start: f64,
+    },
+    Connected {
+        handle: i32,
+    },
+}
+
+pub enum ClientEvent {
+    Connected,
+    Disconnected(String),
+    Line(String),
+}
+
+pub struct Client {
+    host: String,
+    port: u16,
+    state: ConnState,
+    backoff: f64,
+    out_queue: VecDeque<u8>,
+    line_buffer: LineBuffer,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Client {
+            host: String::new(),
+            port: 0,
+            state: ConnState::Idle,
+            backoff: MIN_BACKOFF,
+            out_queue: VecDeque::new(),
+            line_buffer: LineBuffer::new(),
+        }
+    }
+
+    pub fn connect(&mut self, host: &str, port: u16, time: f64) {
+        self.close_socket();
+        self.host = host.to_string();
+        self.port = port;
+        self.backoff = MIN_BACKOFF;
+        self.state = ConnState::Waiting { t_retry: time };
+    }
+
+    pub fn disconnect(&mut self) {
+        self.close_socket();
+        self.state = ConnState::Idle;
+    }
+
+    // Called once the server has accepted the registration. Being connected is
+    // not enough, since servers may close right away if we reconnect too fast.
+    pub fn reset_backoff(&mut self) {
+        self.backoff = MIN_BACKOFF;
+    }
+
+    pub fn is_active(&self) -> bool {
+        !matches!(self.state, ConnState::Idle)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state, ConnState::Connected { .. })
+    }
+
+    pub fn status(&self, time: f64) -> String {
+        match &self.state {
+            ConnState::Idle => "Disconnected".to_string(),
+            ConnState::Waiting { t_retry } => {
+                let secs = f64::max(0.0, (t_retry - time) / 1000.0).ceil();
+                format!("Reconnecting in {}s", secs)
+            }
+            ConnState::Resolving { .. } => format!("Resolving {}...", self.host),
+            ConnState::Connecting { .. } => {
+                format!("Connecting to {}:{}...", self.host, self.port)
+            }
+            ConnState::Connected { .. } => format!("Connected to {}:{}", self.host, self.port),
+        }
+    }
+
+    // Lines are only queued while connected, there is no point in sending
+    // stale commands after a reconnection
+    pub fn send_line(&mut self, line: &str) {
+        if self.is_connected() {
+            log::debug!(">> {}", line);
+            self.out_queue.extend(line.as_bytes());
+            self.out_queue.extend(b"\r\n");
+        }
+    }
+
+    pub fn step(&mut self, time: f64) -> Vec<ClientEvent> {
+        let mut events = Vec::new();
+
+        if let Err(err) = self.try_step(time, &mut events) {
+            log::error!("IRC connection error: {}", err);
+            self.close_socket();
+            events.push(ClientEvent::Disconnected(err.to_string()));
+            self.state = ConnState::Waiting {
+                t_retry: time + self.backoff,
+            };
+            self.backoff = f64::min(2.0 * self.backoff, MAX_BACKOFF);
+        }
+
+        events
+    }
+
+    fn try_step(&mut self, time: f64, events: &mut Vec<ClientEvent>) -> anyhow::Result<()> {
+        match &mut self.state {
+            ConnState::Idle => (),
+
+            ConnState::Waiting { t_retry } => {
+                if time >= *t_retry {
+                    self.state = match self.host.parse::<Ipv4Addr>() {
+                        Ok(ip_addr) => ConnState::Connecting {
+                            handle: guestlib::tcp_connect(ip_addr.octets(), self.port)?,
+                            t_start: time,
+                        },
+                        Err(_) => {
+                            let query = dns::make_tcp_dns_request(&self.host)?;
+                            ConnState::Resolving {
+                                handle: guestlib::tcp_connect(DNS_SERVER, DNS_PORT)?,
+                                query,
+                                sent: 0,
+                                resp: Vec::new(),
+                                t_start: time,
+                            }
+                        }
+                    };
+                }
+            }
+
+            ConnState::Resolving {
+                handle,
+                query,
+                sent,
+                resp,
+                t_start,
+            } => {
+                let handle = *handle;
+
+                if time - *t_start > CONNECT_TIMEOUT {
+                    return Err(anyhow::Error::msg("DNS request timed out"));
+                }
+
+                if !guestlib::tcp_may_send(handle) {
+                    return Ok(());
+                }
+
+                if *sent < query.len() {
+                    *sent += guestlib::tcp_write(&query[*sent..], handle)?;
+                    return Ok(());
+                }
+
+                let mut buf = [0u8; READ_BUF_SIZE];
+                let n = guestlib::tcp_read(&mut buf, handle)?;
+                resp.extend_from_slice(&buf[..n]);
+
+                if resp.len() < 2 {
+                    return Ok(());
+                }
+
+                let dns_len = u16::from_be_bytes([resp[0], resp[1]]) as usize;
+                if resp.len() < 2 + dns_len {
+                    return Ok(());
+                }
+
+                let ip_addr = dns::parse_tcp_dns_response(&resp[2..2 + dns_len])?;
+                guestlib::tcp_close(handle);
+
+                log::info!("Resolved {} to {:?}", self.host, ip_addr);
+
+                self.state = ConnState::Connecting {
+                    handle: guestlib::tcp_connect(ip_addr, self.port)?,
+                    t_start: time,
+                };
+            }
+
+            ConnState::Connecting { handle, t_start } => {
+                if guestlib::tcp_may_send(*handle) {
+                    self.state = ConnState::Connected { handle: *handle };
+                    self.out_queue.clear();
+                    self.line_buffer.clear();
+                    events.push(ClientEvent::Connected);
+                } else if time - *t_start > CONNECT_TIMEOUT {
+                    return Err(anyhow::Error::msg("Connection timed out"));
+                }
+            }
+
+            ConnState::Connected { handle } => {
+                let handle = *handle;
+
+                let mut buf = [0u8; READ_BUF_SIZE];
+                let read_res = loop {
+                    match guestlib::tcp_read(&mut buf, handle) {
+                        Ok(0) => break Ok(()),
+                        Ok(n) => self.line_buffer.push(&buf[..n]),
+                        Err(err) => break Err(err),
+                    }
+                };
+
+                // The server usually explains why it is closing the connection,
+                // so lines received until then must still go through
+                while let Some(line) = self.line_buffer.next_line() {
+                    log::debug!("<< {}", line);
+                    events.push(ClientEvent::Line(line));
+                }
+
+                // Reading fails once the server has closed the connection
+                read_res.map_err(|_| anyhow::Error::msg("Connection closed"))?;
+
+                if !self.out_queue.is_empty() && guestlib::tcp_may_send(handle) {
+                    let data = self.out_queue.make_contiguous();
+                    let n = guestlib::tcp_write(data, handle)?;
+                    self.out_queue.drain(..n);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn close_socket(&mut self) {
+        match self.state {
+            ConnState::Resolving { handle, .. }
+            | ConnState::Connecting { handle, .. }
+            | ConnState::Connected { handle } => guestlib::tcp_close(handle),
+            _ => (),
+        }
+        self.state = ConnState::Idle;
+    }
+}
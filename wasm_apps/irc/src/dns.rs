@@ -0,0 +1,90 @@
+use anyhow::Context;
+
+// Just enough DNS to resolve the server name: a single A query over TCP
+
+const DNS_HEADER_LEN: usize = 12;
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+
+pub fn make_tcp_dns_request(domain_name: &str) -> anyhow::Result<Vec<u8>> {
+    let mut msg = Vec::new();
+
+    msg.extend_from_slice(&0x0001u16.to_be_bytes()); // ID
+    msg.extend_from_slice(&0x0100u16.to_be_bytes()); // Flags: recursion desired
+    msg.extend_from_slice(&1u16.to_be_bytes()); // Questions
+    msg.extend_from_slice(&[0; 6]); // Answers, authorities, additionals
+
+    for label in domain_name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(anyhow::Error::msg(format!(
+                "Invalid domain name {}",
+                domain_name
+            )));
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+
+    msg.extend_from_slice(&TYPE_A.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    let msg_len = msg.len() as u16;
+    Ok([&msg_len.to_be_bytes(), msg.as_slice()].concat())
+}
+
+// Takes the response without its TCP length prefix
+pub fn parse_tcp_dns_response(buf: &[u8]) -> anyhow::Result<[u8; 4]> {
+    let invalid = || anyhow::Error::msg("Invalid DNS response");
+
+    let read_u16 = |pos: usize| -> anyhow::Result<u16> {
+        let bytes = buf.get(pos..pos + 2).ok_or_else(invalid)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    let skip_name = |mut pos: usize| -> anyhow::Result<usize> {
+        loop {
+            let len = *buf.get(pos).ok_or_else(invalid)? as usize;
+            match len {
+                0 => return Ok(pos + 1),
+                // Compressed name: pointer to a previous occurrence
+                len if len & 0xC0 == 0xC0 => return Ok(pos + 2),
+                len => pos += 1 + len,
+            }
+        }
+    };
+
+    if buf.len() < DNS_HEADER_LEN {
+        return Err(invalid());
+    }
+
+    let rcode = buf[3] & 0x0F;
+    if rcode != 0 {
+        return Err(anyhow::Error::msg(format!("DNS error (rcode {})", rcode)));
+    }
+
+    let nb_questions = read_u16(4)?;
+    let nb_answers = read_u16(6)?;
+
+    let mut pos = DNS_HEADER_LEN;
+    for _ in 0..nb_questions {
+        pos = skip_name(pos)? + 4;
+    }
+
+    for _ in 0..nb_answers {
+        pos = skip_name(pos)?;
+        let rr_type = read_u16(pos)?;
+        let rr_class = read_u16(pos + 2)?;
+        let data_len = read_u16(pos + 8)? as usize;
+        pos += 10;
+
+        if rr_type == TYPE_A && rr_class == CLASS_IN && data_len == 4 {
+            let data = buf.get(pos..pos + 4).ok_or_else(invalid)?;
+            return data.try_into().context("Invalid A record");
+        }
+
+        pos += data_len;
+    }
+
+    Err(anyhow::Error::msg("Invalid DNS response (no A record)"))
+}
@@ -0,0 +1,137 @@
+// TCP gives no guarantee about how the stream is split: a read can contain
+// part of a line, or several lines at once. This accumulates the received
+// bytes and hands out complete lines only.
+
+// RFC 2812 caps messages at 512 bytes, but some servers send longer ones
+const MAX_LINE_LEN: usize = 8192;
+
+pub struct LineBuffer {
+    buf: Vec<u8>,
+    // Within a line already found too long, dropped up to its end
+    skipping: bool,
+}
+
+impl LineBuffer {
+    pub fn new() -> Self {
+        LineBuffer {
+            buf: Vec::new(),
+            skipping: false,
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.skipping = false;
+    }
+
+    // Lines are terminated by CRLF, but a lone LF is accepted too
+    pub fn next_line(&mut self) -> Option<String> {
+        loop {
+            let Some(i) = self.buf.iter().position(|&b| b == b'\n') else {
+                // A peer that never sends a newline must not grow the buffer forever
+                if self.buf.len() > MAX_LINE_LEN {
+                    log::warn!("Dropping {} bytes without line ending", self.buf.len());
+                    self.buf.clear();
+                    self.skipping = true;
+                }
+                return None;
+            };
+
+            let mut line: Vec<u8> = self.buf.drain(..=i).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+
+            // Otherwise the end of the line would come out as a line of its own
+            if core::mem::replace(&mut self.skipping, false) || line.len() > MAX_LINE_LEN {
+                log::warn!("Dropping a line longer than {} bytes", MAX_LINE_LEN);
+                continue;
+            }
+
+            // Empty lines are allowed by the protocol but carry nothing
+            if !line.is_empty() {
+                return Some(String::from_utf8_lossy(&line).into_owned());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(buffer: &mut LineBuffer) -> Vec<String> {
+        core::iter::from_fn(|| buffer.next_line()).collect()
+    }
+
+    #[test]
+    fn line_split_across_reads() {
+        let mut buffer = LineBuffer::new();
+        buffer.push(b":server 001 ni");
+        assert_eq!(buffer.next_line(), None);
+        buffer.push(b"ck :Welcome\r");
+        assert_eq!(buffer.next_line(), None);
+        buffer.push(b"\n");
+        assert_eq!(lines(&mut buffer), [":server 001 nick :Welcome"]);
+        assert_eq!(buffer.next_line(), None);
+    }
+
+    #[test]
+    fn several_lines_in_one_read() {
+        let mut buffer = LineBuffer::new();
+        buffer.push(b"PING :a\r\nPING :b\n\r\n\nPING :c\r\nPING :");
+        assert_eq!(lines(&mut buffer), ["PING :a", "PING :b", "PING :c"]);
+        buffer.push(b"d\r\n");
+        assert_eq!(lines(&mut buffer), ["PING :d"]);
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let mut buffer = LineBuffer::new();
+        buffer.push(b"caf\xe9\r\n");
+        assert_eq!(lines(&mut buffer), ["caf\u{fffd}"]);
+    }
+
+    #[test]
+    fn over_long_line_across_reads() {
+        let mut buffer = LineBuffer::new();
+        buffer.push(&[b'a'; MAX_LINE_LEN]);
+        assert_eq!(buffer.next_line(), None);
+        buffer.push(b"aaaa");
+        assert_eq!(buffer.next_line(), None);
+        assert!(buffer.buf.is_empty());
+
+        // The rest of the line is dropped too, the next one comes through
+        buffer.push(b"aaaa\r\nPING :x\r\n");
+        assert_eq!(lines(&mut buffer), ["PING :x"]);
+    }
+
+    #[test]
+    fn over_long_line_in_one_read() {
+        let mut buffer = LineBuffer::new();
+        let mut data = vec![b'a'; MAX_LINE_LEN + 1];
+        data.extend_from_slice(b"\r\nPING :x\r\n");
+        buffer.push(&data);
+        assert_eq!(lines(&mut buffer), ["PING :x"]);
+
+        let mut data = vec![b'a'; MAX_LINE_LEN];
+        data.extend_from_slice(b"\r\n");
+        buffer.push(&data);
+        assert_eq!(lines(&mut buffer).len(), 1);
+    }
+
+    #[test]
+    fn clear_stops_skipping() {
+        let mut buffer = LineBuffer::new();
+        buffer.push(&[b'a'; MAX_LINE_LEN + 1]);
+        assert_eq!(buffer.next_line(), None);
+        buffer.clear();
+        buffer.push(b"PING :x\r\n");
+        assert_eq!(lines(&mut buffer), ["PING :x"]);
+    }
+}
@@ -0,0 +1,437 @@
+use applib::content::TrackedContent;
+use applib::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use applib::input::{InputEvent, InputState, Keycode};
+use applib::uitk::layout::{make_horizontal_layout, make_vertical_layout, LayoutItem};
+use applib::uitk::{
+    self, ButtonConfig, ContentId, TabBarConfig, TabItem, TextBoxState, TextInputConfig,
+    TextInputEvent, TextInputState, UuidProvider,
+};
+use applib::FbViewMut;
+use core::cell::OnceCell;
+use guestlib::{PixelData, WasmLogger};
+
+mod client;
+mod dns;
+mod lines;
+mod message;
+mod session;
+
+use client::{Client, ClientEvent};
+use session::{LineKind, Session};
+
+static LOGGER: WasmLogger = WasmLogger;
+const LOGGING_LEVEL: log::LevelFilter = log::LevelFilter::Debug;
+
+const CONFIG_FILE: &str = "irc_config";
+const DEFAULT_SERVER: &str = "irc.libera.chat:6667";
+const DEFAULT_NICK: &str = "munal_user";
+const DEFAULT_CHANNELS: &str = "#munal-os";
+const DEFAULT_PORT: u16 = 6667;
+
+const BUTTON_H: u32 = 30;
+const LABEL_W: u32 = 70;
+const SHORT_LABEL_W: u32 = 40;
+const NICK_W: u32 = 120;
+const CHANNELS_W: u32 = 160;
+const CONNECT_BUTTON_W: u32 = 100;
+const TAB_W: u32 = 110;
+const NICK_LIST_W: u32 = 140;
+const STATUS_H: u32 = 20;
+
+struct Config {
+    server: String,
+    nick: String,
+    channels: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            server: DEFAULT_SERVER.to_string(),
+            nick: DEFAULT_NICK.to_string(),
+            channels: DEFAULT_CHANNELS.to_string(),
+        }
+    }
+}
+
+struct AppState {
+    pixel_data: PixelData,
+    ui_store: uitk::UiStore,
+    uuid_provider: UuidProvider,
+
+    client: Client,
+    session: Session,
+
+    server_text: String,
+    server_state: TextInputState,
+    nick_text: String,
+    nick_state: TextInputState,
+    channels_text: String,
+    channels_state: TextInputState,
+    input_text: TrackedContent<String>,
+    input_state: TextBoxState,
+}
+
+static mut APP_STATE: OnceCell<AppState> = OnceCell::new();
+
+fn main() {}
+
+#[no_mangle]
+pub fn init() -> () {
+    log::set_max_level(LOGGING_LEVEL);
+    log::set_logger(&LOGGER).unwrap();
+
+    let mut uuid_provider = UuidProvider::new();
+    let config = load_config();
+
+    let mut input_state = TextBoxState::new();
+    input_state.focus();
+
+    let mut ui_store = uitk::UiStore::new();
+    ui_store.set_clipboard(guestlib::HostClipboard);
+
+    let state = AppState {
+        pixel_data: PixelData::new(),
+        ui_store,
+
+        client: Client::new(),
+        session: Session::new(&mut uuid_provider),
+
+        server_text: config.server,
+        server_state: TextInputState::new(),
+        nick_text: config.nick,
+        nick_state: TextInputState::new(),
+        channels_text: config.channels,
+        channels_state: TextInputState::new(),
+        input_text: TrackedContent::new(String::new(), &mut uuid_provider),
+        input_state,
+
+        uuid_provider,
+    };
+
+    unsafe {
+        APP_STATE
+            .set(state)
+            .unwrap_or_else(|_| panic!("App already initialized"));
+    }
+}
+
+#[no_mangle]
+pub fn step() {
+    let state = unsafe { APP_STATE.get_mut().expect("App not initialized") };
+
+    let time = guestlib::get_time();
+    let stylesheet = guestlib::get_stylesheet();
+    let input_state = guestlib::get_input_state();
+    let win_rect = guestlib::get_win_rect().zero_origin();
+
+    let m = stylesheet.margin;
+    let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+
+    //
+    // Network
+
+    for event in state.client.step(time) {
+        let AppState {
+            client,
+            session,
+            uuid_provider,
+            ..
+        } = &mut *state;
+        match event {
+            ClientEvent::Connected => session.on_connected(client),
+            ClientEvent::Disconnected(reason) => {
+                session.on_disconnected(&reason);
+                if session.quitting {
+                    client.disconnect();
+                }
+            }
+            ClientEvent::Line(line) => session.handle_line(client, &line, uuid_provider),
+        }
+    }
+
+    //
+    // Layout
+
+    let main_layout = make_vertical_layout(
+        &win_rect.offset(-(m as i64)),
+        m,
+        &[
+            LayoutItem::Fixed { size: BUTTON_H },
+            LayoutItem::Fixed { size: BUTTON_H },
+            LayoutItem::Float,
+            LayoutItem::Fixed { size: BUTTON_H },
+            LayoutItem::Fixed { size: STATUS_H },
+        ],
+    );
+
+    let config_layout = make_horizontal_layout(
+        &main_layout[0],
+        m,
+        &[
+            LayoutItem::Fixed {
+                size: SHORT_LABEL_W,
+            },
+            LayoutItem::Float,
+            LayoutItem::Fixed {
+                size: SHORT_LABEL_W,
+            },
+            LayoutItem::Fixed { size: NICK_W },
+            LayoutItem::Fixed { size: LABEL_W },
+            LayoutItem::Fixed { size: CHANNELS_W },
+            LayoutItem::Fixed {
+                size: CONNECT_BUTTON_W,
+            },
+        ],
+    );
+
+    let show_nick_list = state.session.buffers[state.session.current].is_channel();
+    let chat_layout = match show_nick_list {
+        true => make_horizontal_layout(
+            &main_layout[2],
+            m,
+            &[LayoutItem::Float, LayoutItem::Fixed { size: NICK_LIST_W }],
+        ),
+        false => vec![main_layout[2].clone()],
+    };
+
+    let input_rect = &main_layout[3];
+
+    let enter_pressed = check_enter_pressed(&input_state);
+
+    let mut framebuffer = state.pixel_data.get_framebuffer();
+
+    let AppState {
+        ui_store,
+        uuid_provider,
+        client,
+        session,
+        ..
+    } = state;
+
+    framebuffer.fill(stylesheet.colors.background);
+
+    let mut uitk_context = ui_store.get_context(
+        &mut framebuffer,
+        &stylesheet,
+        &input_state,
+        uuid_provider,
+        time,
+    );
+
+    //
+    // Connection settings
+
+    for (i, label) in [(0, "Server"), (2, "Nick"), (4, "Channels")] {
+        draw_line_in_rect(
+            uitk_context.fb,
+            label,
+            &config_layout[i],
+            font,
+            stylesheet.colors.text,
+            TextJustification::Left,
+        );
+    }
+
+    let config_inputs = [
+        (
+            &mut state.server_text,
+            &mut state.server_state,
+            "host[:port]",
+        ),
+        (&mut state.nick_text, &mut state.nick_state, "nickname"),
+        (
+            &mut state.channels_text,
+            &mut state.channels_state,
+            "#chan1,#chan2",
+        ),
+    ];
+
+    let mut config_submitted = false;
+    for (i, (text, input_state, placeholder)) in config_inputs.into_iter().enumerate() {
+        let event = uitk_context.text_input(
+            &TextInputConfig {
+                rect: config_layout[2 * i + 1].clone(),
+                placeholder: placeholder.to_string(),
+                filter: Some(|c| c != ' '),
+                ..Default::default()
+            },
+            text,
+            input_state,
+        );
+        config_submitted |= event == TextInputEvent::Submitted;
+    }
+
+    let connect_text = match client.is_active() {
+        true => "Disconnect",
+        false => "Connect",
+    };
+
+    let connect_clicked = uitk_context.button(&ButtonConfig {
+        rect: config_layout[6].clone(),
+        text: connect_text.to_string(),
+        ..Default::default()
+    });
+
+    if connect_clicked && client.is_active() {
+        client.disconnect();
+        session.on_disconnected("closed by user");
+    } else if connect_clicked || config_submitted {
+        let config = Config {
+            server: state.server_text.trim().to_string(),
+            nick: state.nick_text.trim().to_string(),
+            channels: state.channels_text.trim().to_string(),
+        };
+
+        match parse_server(&config.server) {
+            Some((host, port)) if !config.nick.is_empty() => {
+                save_config(&config);
+                session.start(&config.nick, &config.channels);
+                client.connect(host, port, time);
+                state.input_state.focus();
+            }
+            _ => session.push_status(
+                "Invalid server address or nickname".to_string(),
+                LineKind::Error,
+            ),
+        }
+    }
+
+    //
+    // Tabs
+
+    let tabs: Vec<TabItem> = session
+        .buffers
+        .iter()
+        .enumerate()
+        .map(|(i, buffer)| TabItem {
+            label: match buffer.highlighted {
+                true => format!("{} (!)", buffer.name),
+                false => buffer.name.clone(),
+            },
+            dirty: buffer.unread,
+            closable: i != 0,
+        })
+        .collect();
+
+    let mut current = session.current;
+    let response = uitk_context.tab_bar(
+        &TabBarConfig {
+            id: ContentId::from_hash(&"irc_tabs"),
+            rect: main_layout[1].clone(),
+            max_tab_w: TAB_W,
+            ..Default::default()
+        },
+        &tabs,
+        &mut current,
+    );
+
+    if let Some((from, to)) = response.moved {
+        session.move_buffer(from, to);
+    } else if let Some(i) = response.activated {
+        session.select(i);
+    }
+
+    if let Some(i) = response.closed {
+        session.close(i, client);
+    }
+
+    //
+    // Chat log and nick list
+
+    let buffer = &mut session.buffers[session.current];
+    buffer.update(uitk_context.uuid_provider, font, &stylesheet.colors);
+
+    uitk_context.text_box(&chat_layout[0], &buffer.log, &mut buffer.log_state, true);
+
+    if show_nick_list {
+        if let Some(rect) = chat_layout.get(1) {
+            uitk_context.text_box(rect, &buffer.nick_list, &mut buffer.nick_list_state, false);
+        }
+    }
+
+    //
+    // Input
+
+    uitk_context.editable_text_box(
+        input_rect,
+        &mut state.input_text,
+        &mut state.input_state,
+        false,
+        false,
+        None::<&TrackedContent<String>>,
+    );
+
+    if enter_pressed && state.input_state.is_focused() {
+        let input = state.input_text.as_ref().clone();
+        *state.input_text.mutate(uitk_context.uuid_provider) = String::new();
+        state.input_state = TextBoxState::new();
+        state.input_state.focus();
+        session.handle_input(client, &input, uitk_context.uuid_provider);
+    }
+
+    //
+    // Status bar
+
+    let status = match session.nick.is_empty() {
+        true => client.status(time),
+        false => format!("{} as {}", client.status(time), session.nick),
+    };
+
+    draw_line_in_rect(
+        uitk_context.fb,
+        &status,
+        &main_layout[4],
+        font,
+        stylesheet.colors.text,
+        TextJustification::Left,
+    );
+}
+
+fn check_enter_pressed(input_state: &InputState) -> bool {
+    input_state.events.iter().any(|event| {
+        matches!(
+            event,
+            Some(InputEvent::KeyPress {
+                keycode: Keycode::KEY_ENTER
+            })
+        )
+    })
+}
+
+fn parse_server(server: &str) -> Option<(&str, u16)> {
+    let (host, port) = match server.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (server, DEFAULT_PORT),
+    };
+
+    match host.is_empty() || host.contains(' ') {
+        true => None,
+        false => Some((host, port)),
+    }
+}
+
+// One setting per line: server, nick, channels
+fn load_config() -> Config {
+    let data = match guestlib::storage_read(CONFIG_FILE) {
+        Ok(data) => data,
+        Err(_) => return Config::default(),
+    };
+
+    let data = String::from_utf8_lossy(&data);
+    let mut lines = data.lines();
+    let default = Config::default();
+
+    Config {
+        server: lines.next().map_or(default.server, str::to_string),
+        nick: lines.next().map_or(default.nick, str::to_string),
+        channels: lines.next().map_or(default.channels, str::to_string),
+    }
+}
+
+fn save_config(config: &Config) {
+    let data = format!("{}\n{}\n{}\n", config.server, config.nick, config.channels);
+    if let Err(err) = guestlib::storage_write(CONFIG_FILE, data.as_bytes()) {
+        log::error!("Cannot save IRC config: {}", err);
+    }
+}
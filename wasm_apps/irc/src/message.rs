@@ -0,0 +1,80 @@
+// IRC message as described in RFC 2812 section 2.3.1:
+// [":" prefix SPACE] command [params] CRLF
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub prefix: Option<String>,
+    pub command: String,
+    pub params: Vec<String>,
+}
+
+impl Message {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+
+        // IRCv3 message tags are not supported, just skip them
+        if rest.starts_with('@') {
+            rest = rest.split_once(' ')?.1;
+        }
+
+        let prefix = match rest.strip_prefix(':') {
+            Some(s) => {
+                let (prefix, s) = s.split_once(' ')?;
+                rest = s;
+                Some(prefix.to_string())
+            }
+            None => None,
+        };
+
+        rest = rest.trim_start_matches(' ');
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            // The trailing parameter may contain spaces
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params.push(trailing.to_string());
+                break;
+            }
+            let (param, s) = rest.split_once(' ').unwrap_or((rest, ""));
+            params.push(param.to_string());
+            rest = s;
+        }
+
+        Some(Message {
+            prefix,
+            command: command.to_ascii_uppercase(),
+            params,
+        })
+    }
+
+    // For a "nick!user@host" prefix
+    pub fn source_nick(&self) -> Option<&str> {
+        let prefix = self.prefix.as_deref()?;
+        Some(prefix.split_once('!').map_or(prefix, |(nick, _)| nick))
+    }
+
+    pub fn param(&self, i: usize) -> &str {
+        self.params.get(i).map(|s| s.as_str()).unwrap_or("")
+    }
+
+    // Last parameter, which usually holds the human-readable text
+    pub fn trailing(&self) -> &str {
+        self.params.last().map(|s| s.as_str()).unwrap_or("")
+    }
+}
+
+pub fn is_channel(target: &str) -> bool {
+    target.starts_with(['#', '&', '+', '!'])
+}
+
+// Nicknames in NAMES replies are prefixed with their channel status
+pub fn strip_nick_status(nick: &str) -> &str {
+    nick.trim_start_matches(['@', '+', '%', '~', '&'])
+}
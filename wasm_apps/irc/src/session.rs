@@ -0,0 +1,538 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use applib::content::{TrackedContent, UuidProvider};
+use applib::drawing::text::{Font, RichText};
+use applib::uitk::TextBoxState;
+use applib::StyleSheetColors;
+
+use crate::client::Client;
+use crate::message::{is_channel, strip_nick_status, Message};
+
+pub const STATUS_BUFFER: &str = "Server";
+const MAX_LOG_LINES: usize = 500;
+
+#[derive(Clone, Copy)]
+pub enum LineKind {
+    Message,
+    Own,
+    Highlight,
+    Info,
+    Error,
+}
+
+pub struct Buffer {
+    pub name: String,
+    lines: VecDeque<(String, LineKind)>,
+    pub log: TrackedContent<RichText>,
+    pub log_state: TextBoxState,
+
+    pub nicks: BTreeSet<String>,
+    // NAMES replies can span several messages, they are committed on RPL_ENDOFNAMES
+    pending_names: Option<BTreeSet<String>>,
+    pub nick_list: TrackedContent<String>,
+    pub nick_list_state: TextBoxState,
+
+    pub unread: bool,
+    pub highlighted: bool,
+    dirty: bool,
+}
+
+impl Buffer {
+    fn new(name: &str, uuid_provider: &mut UuidProvider) -> Self {
+        Buffer {
+            name: name.to_string(),
+            lines: VecDeque::new(),
+            log: TrackedContent::new(RichText::new(), uuid_provider),
+            log_state: TextBoxState::new(),
+            nicks: BTreeSet::new(),
+            pending_names: None,
+            nick_list: TrackedContent::new(String::new(), uuid_provider),
+            nick_list_state: TextBoxState::new(),
+            unread: false,
+            highlighted: false,
+            dirty: false,
+        }
+    }
+
+    pub fn is_channel(&self) -> bool {
+        is_channel(&self.name)
+    }
+
+    fn push(&mut self, text: String, kind: LineKind) {
+        self.lines.push_back((text, kind));
+        if self.lines.len() > MAX_LOG_LINES {
+            self.lines.pop_front();
+        }
+        self.dirty = true;
+    }
+
+    fn set_nicks(&mut self, nicks: BTreeSet<String>) {
+        self.nicks = nicks;
+        self.dirty = true;
+    }
+
+    fn add_nick(&mut self, nick: &str) {
+        self.dirty |= self.nicks.insert(nick.to_string());
+    }
+
+    fn remove_nick(&mut self, nick: &str) -> bool {
+        let removed = self.nicks.remove(nick);
+        self.dirty |= removed;
+        removed
+    }
+
+    // The log and nick list are only re-formatted when displayed
+    pub fn update(
+        &mut self,
+        uuid_provider: &mut UuidProvider,
+        font: &'static Font,
+        colors: &StyleSheetColors,
+    ) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let log = self.log.mutate(uuid_provider);
+        log.clear();
+        for (i, (text, kind)) in self.lines.iter().enumerate() {
+            let color = match kind {
+                LineKind::Message => colors.text,
+                LineKind::Own => colors.blue,
+                LineKind::Highlight => colors.yellow,
+                LineKind::Info => colors.green,
+                LineKind::Error => colors.red,
+            };
+            if i > 0 {
+                log.add_part("\n", color, font, None);
+            }
+            log.add_part(text, color, font, None);
+        }
+
+        let nick_list = self.nick_list.mutate(uuid_provider);
+        *nick_list = self.nicks.iter().cloned().collect::<Vec<_>>().join("\n");
+    }
+}
+
+pub struct Session {
+    pub nick: String,
+    pub buffers: Vec<Buffer>,
+    pub current: usize,
+    registered: bool,
+    autojoin: Vec<String>,
+    // Set by /quit, so that the server closing the connection does not trigger a reconnection
+    pub quitting: bool,
+}
+
+impl Session {
+    pub fn new(uuid_provider: &mut UuidProvider) -> Self {
+        Session {
+            nick: String::new(),
+            buffers: vec![Buffer::new(STATUS_BUFFER, uuid_provider)],
+            current: 0,
+            registered: false,
+            autojoin: Vec::new(),
+            quitting: false,
+        }
+    }
+
+    pub fn start(&mut self, nick: &str, channels: &str) {
+        self.nick = nick.to_string();
+        self.autojoin = channels
+            .split([',', ' '])
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect();
+        self.quitting = false;
+    }
+
+    pub fn select(&mut self, i: usize) {
+        self.current = i;
+        let buffer = &mut self.buffers[i];
+        buffer.unread = false;
+        buffer.highlighted = false;
+    }
+
+    // Leaves the channel if it is one, the server buffer cannot be closed
+    pub fn close(&mut self, i: usize, client: &mut Client) {
+        if i == 0 || i >= self.buffers.len() {
+            return;
+        }
+
+        let buffer = self.buffers.remove(i);
+        if buffer.is_channel() && client.is_connected() {
+            client.send_line(&format!("PART {} :", buffer.name));
+        }
+
+        if self.current >= i {
+            self.select(self.current - 1);
+        }
+    }
+
+    // The server buffer stays first
+    pub fn move_buffer(&mut self, from: usize, to: usize) {
+        let n = self.buffers.len();
+        if from == 0 || to == 0 || from >= n || to >= n {
+            return;
+        }
+
+        let buffer = self.buffers.remove(from);
+        self.buffers.insert(to, buffer);
+
+        self.current = match self.current {
+            c if c == from => to,
+            c if from < c && c <= to => c - 1,
+            c if to <= c && c < from => c + 1,
+            c => c,
+        };
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.buffers
+            .iter()
+            .position(|b| b.name.eq_ignore_ascii_case(name))
+    }
+
+    fn find_or_create(&mut self, name: &str, uuid_provider: &mut UuidProvider) -> usize {
+        match self.find(name) {
+            Some(i) => i,
+            None => {
+                self.buffers.push(Buffer::new(name, uuid_provider));
+                self.buffers.len() - 1
+            }
+        }
+    }
+
+    fn push(&mut self, i: usize, text: String, kind: LineKind) {
+        if i != self.current {
+            self.buffers[i].unread = true;
+            if let LineKind::Highlight = kind {
+                self.buffers[i].highlighted = true;
+            }
+        }
+        self.buffers[i].push(text, kind);
+    }
+
+    pub fn push_status(&mut self, text: String, kind: LineKind) {
+        self.push(0, text, kind);
+    }
+
+    fn push_current(&mut self, text: String, kind: LineKind) {
+        self.push(self.current, text, kind);
+    }
+
+    fn is_me(&self, nick: &str) -> bool {
+        nick.eq_ignore_ascii_case(&self.nick)
+    }
+
+    pub fn on_connected(&mut self, client: &mut Client) {
+        self.registered = false;
+        self.push_status("Connected, registering...".to_string(), LineKind::Info);
+        client.send_line(&format!("NICK {}", self.nick));
+        client.send_line(&format!("USER {} 0 * :{}", self.nick, self.nick));
+    }
+
+    pub fn on_disconnected(&mut self, reason: &str) {
+        self.registered = false;
+        for i in 0..self.buffers.len() {
+            self.buffers[i].set_nicks(BTreeSet::new());
+            self.push(i, format!("Disconnected: {}", reason), LineKind::Error);
+        }
+    }
+
+    pub fn handle_line(
+        &mut self,
+        client: &mut Client,
+        line: &str,
+        uuid_provider: &mut UuidProvider,
+    ) {
+        let Some(msg) = Message::parse(line) else {
+            log::warn!("Invalid IRC message: {}", line);
+            return;
+        };
+
+        let from = msg.source_nick().unwrap_or("").to_string();
+
+        match msg.command.as_str() {
+            "PING" => client.send_line(&format!("PONG :{}", msg.trailing())),
+
+            // RPL_WELCOME
+            "001" => {
+                self.registered = true;
+                self.nick = msg.param(0).to_string();
+                client.reset_backoff();
+                self.push_status(msg.trailing().to_string(), LineKind::Info);
+
+                // Channels still open from a previous connection are joined again
+                let mut channels = self.autojoin.clone();
+                for buffer in self.buffers.iter().filter(|b| b.is_channel()) {
+                    if !channels
+                        .iter()
+                        .any(|c| c.eq_ignore_ascii_case(&buffer.name))
+                    {
+                        channels.push(buffer.name.clone());
+                    }
+                }
+                if !channels.is_empty() {
+                    client.send_line(&format!("JOIN {}", channels.join(",")));
+                }
+            }
+
+            // ERR_NICKNAMEINUSE
+            "433" if !self.registered => {
+                self.nick.push('_');
+                self.push_status(
+                    format!("Nickname in use, trying {}", self.nick),
+                    LineKind::Error,
+                );
+                client.send_line(&format!("NICK {}", self.nick));
+            }
+
+            "PRIVMSG" | "NOTICE" => {
+                let target = msg.param(0);
+                let text = msg.trailing();
+
+                // Server notices and notices sent before registration have no real sender
+                let is_server = !msg.prefix.as_deref().unwrap_or("").contains('!');
+
+                let i = if is_channel(target) {
+                    self.find_or_create(target, uuid_provider)
+                } else if msg.command == "NOTICE" || is_server {
+                    0
+                } else {
+                    self.find_or_create(&from, uuid_provider)
+                };
+
+                let (text, is_action) = match parse_ctcp(text) {
+                    Some(("ACTION", arg)) => (arg, true),
+                    Some(_) => return,
+                    None => (text, false),
+                };
+
+                let formatted = match (is_action, msg.command.as_str()) {
+                    (true, _) => format!("* {} {}", from, text),
+                    (false, "NOTICE") if is_server => text.to_string(),
+                    (false, "NOTICE") => format!("-{}- {}", from, text),
+                    (false, _) => format!("<{}> {}", from, text),
+                };
+
+                // Private messages always count as highlights
+                let highlight = !is_server
+                    && !self.is_me(&from)
+                    && (!is_channel(target) || contains_nick(text, &self.nick));
+
+                if highlight {
+                    guestlib::notify(&format!("{}: {}", self.buffers[i].name, formatted));
+                    self.push(i, formatted, LineKind::Highlight);
+                } else {
+                    self.push(i, formatted, LineKind::Message);
+                }
+            }
+
+            "JOIN" => {
+                let channel = msg.param(0);
+                let i = self.find_or_create(channel, uuid_provider);
+                if self.is_me(&from) {
+                    self.buffers[i].set_nicks(BTreeSet::new());
+                    self.select(i);
+                    self.push(i, format!("You have joined {}", channel), LineKind::Info);
+                } else {
+                    self.push(i, format!("{} has joined", from), LineKind::Info);
+                }
+                self.buffers[i].add_nick(&from);
+            }
+
+            "PART" => {
+                let Some(i) = self.find(msg.param(0)) else {
+                    return;
+                };
+                if self.is_me(&from) {
+                    self.buffers[i].set_nicks(BTreeSet::new());
+                    self.push(i, "You have left the channel".to_string(), LineKind::Info);
+                } else {
+                    self.buffers[i].remove_nick(&from);
+                    let reason = msg.params.get(1).map(|s| s.as_str()).unwrap_or("");
+                    self.push(i, format!("{} has left ({})", from, reason), LineKind::Info);
+                }
+            }
+
+            "KICK" => {
+                let Some(i) = self.find(msg.param(0)) else {
+                    return;
+                };
+                let victim = msg.param(1);
+                let text = format!("{} was kicked by {} ({})", victim, from, msg.param(2));
+                if self.is_me(victim) {
+                    self.buffers[i].set_nicks(BTreeSet::new());
+                    guestlib::notify(&text);
+                    self.push(i, text, LineKind::Highlight);
+                } else {
+                    self.buffers[i].remove_nick(victim);
+                    self.push(i, text, LineKind::Info);
+                }
+            }
+
+            "QUIT" => {
+                for i in 0..self.buffers.len() {
+                    if self.buffers[i].remove_nick(&from) {
+                        let text = format!("{} has quit ({})", from, msg.trailing());
+                        self.push(i, text, LineKind::Info);
+                    }
+                }
+            }
+
+            "NICK" => {
+                let new_nick = msg.trailing().to_string();
+                if self.is_me(&from) {
+                    self.nick = new_nick.clone();
+                    self.push_status(format!("You are now {}", new_nick), LineKind::Info);
+                }
+                for i in 0..self.buffers.len() {
+                    let buffer = &mut self.buffers[i];
+                    if buffer.name.eq_ignore_ascii_case(&from) {
+                        buffer.name = new_nick.clone();
+                    }
+                    if buffer.remove_nick(&from) {
+                        buffer.add_nick(&new_nick);
+                        let text = format!("{} is now known as {}", from, new_nick);
+                        self.push(i, text, LineKind::Info);
+                    }
+                }
+            }
+
+            "TOPIC" => {
+                let i = self.find_or_create(msg.param(0), uuid_provider);
+                let text = format!("{} changed the topic to: {}", from, msg.trailing());
+                self.push(i, text, LineKind::Info);
+            }
+
+            // RPL_TOPIC
+            "332" => {
+                let i = self.find_or_create(msg.param(1), uuid_provider);
+                self.push(i, format!("Topic: {}", msg.trailing()), LineKind::Info);
+            }
+
+            // RPL_NAMREPLY
+            "353" => {
+                let Some(i) = self.find(msg.param(2)) else {
+                    return;
+                };
+                let names = msg.trailing().split(' ').filter(|s| !s.is_empty());
+                self.buffers[i]
+                    .pending_names
+                    .get_or_insert_with(BTreeSet::new)
+                    .extend(names.map(|s| strip_nick_status(s).to_string()));
+            }
+
+            // RPL_ENDOFNAMES
+            "366" => {
+                let Some(i) = self.find(msg.param(1)) else {
+                    return;
+                };
+                if let Some(names) = self.buffers[i].pending_names.take() {
+                    self.buffers[i].set_nicks(names);
+                }
+            }
+
+            "ERROR" => self.push_status(msg.trailing().to_string(), LineKind::Error),
+
+            cmd if cmd.len() == 3 && cmd.bytes().all(|b| b.is_ascii_digit()) => {
+                // The first parameter is always our own nick
+                let text = msg.params.get(1..).unwrap_or(&[]).join(" ");
+                let kind = match cmd.starts_with(['4', '5']) {
+                    true => LineKind::Error,
+                    false => LineKind::Message,
+                };
+                self.push_status(text, kind);
+            }
+
+            _ => log::debug!("Unhandled IRC message: {}", line),
+        }
+    }
+
+    pub fn handle_input(
+        &mut self,
+        client: &mut Client,
+        input: &str,
+        uuid_provider: &mut UuidProvider,
+    ) {
+        let input = input.trim_end();
+        if input.is_empty() {
+            return;
+        }
+
+        if !client.is_connected() {
+            self.push_current("Not connected".to_string(), LineKind::Error);
+            return;
+        }
+
+        let current_name = self.buffers[self.current].name.clone();
+        let is_status = self.current == 0;
+
+        // "//text" sends "/text" as a plain message
+        let command = match input.strip_prefix('/') {
+            Some(s) if !s.starts_with('/') => Some(s.split_once(' ').unwrap_or((s, ""))),
+            _ => None,
+        };
+
+        match command {
+            None if is_status => {
+                self.push_current(
+                    "Cannot send messages to the server buffer".to_string(),
+                    LineKind::Error,
+                );
+            }
+            None => {
+                let text = input.strip_prefix('/').unwrap_or(input);
+                client.send_line(&format!("PRIVMSG {} :{}", current_name, text));
+                self.push_current(format!("<{}> {}", self.nick, text), LineKind::Own);
+            }
+            Some((cmd, args)) => match cmd.to_ascii_lowercase().as_str() {
+                "join" if !args.is_empty() => client.send_line(&format!("JOIN {}", args)),
+                "part" => {
+                    let (channel, reason) = match args.split_once(' ') {
+                        Some((c, r)) if is_channel(c) => (c, r),
+                        None if is_channel(args) => (args, ""),
+                        _ => (current_name.as_str(), args),
+                    };
+                    client.send_line(&format!("PART {} :{}", channel, reason));
+                }
+                "msg" => match args.split_once(' ') {
+                    Some((target, text)) if !text.is_empty() => {
+                        client.send_line(&format!("PRIVMSG {} :{}", target, text));
+                        let i = self.find_or_create(target, uuid_provider);
+                        self.push(i, format!("<{}> {}", self.nick, text), LineKind::Own);
+                    }
+                    _ => {
+                        self.push_current("Usage: /msg <nick> <text>".to_string(), LineKind::Error)
+                    }
+                },
+                "me" if !is_status => {
+                    client.send_line(&format!(
+                        "PRIVMSG {} :\x01ACTION {}\x01",
+                        current_name, args
+                    ));
+                    self.push_current(format!("* {} {}", self.nick, args), LineKind::Own);
+                }
+                "nick" if !args.is_empty() => client.send_line(&format!("NICK {}", args)),
+                "quit" => {
+                    self.quitting = true;
+                    client.send_line(&format!("QUIT :{}", args));
+                }
+                "quote" if !args.is_empty() => client.send_line(args),
+                _ => self.push_current(format!("Invalid command: {}", input), LineKind::Error),
+            },
+        }
+    }
+}
+
+// CTCP messages are delimited by 0x01 bytes
+fn parse_ctcp(text: &str) -> Option<(&str, &str)> {
+    let inner = text.strip_prefix('\x01')?;
+    let inner = inner.strip_suffix('\x01').unwrap_or(inner);
+    Some(inner.split_once(' ').unwrap_or((inner, "")))
+}
+
+fn contains_nick(text: &str, nick: &str) -> bool {
+    !nick.is_empty() && text.to_lowercase().contains(&nick.to_lowercase())
+}
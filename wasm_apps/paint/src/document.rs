@@ -0,0 +1,178 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use applib::uitk::{ContentId, TileRenderer};
+use applib::{Color, FbView, FbViewMut, Framebuffer, OwnedPixels, Rect};
+
+const TILE_SIZE: u32 = 64;
+const MAX_UNDO: usize = 20;
+
+// Content of the tiles touched by a stroke, as they were before it
+struct Snapshot {
+    tiles: BTreeMap<usize, Framebuffer<OwnedPixels>>,
+}
+
+pub struct Document {
+    fb: Framebuffer<OwnedPixels>,
+    tiles_x: u32,
+
+    // Bumped every time a tile is drawn to, so that only those get re-rendered
+    versions: Vec<u64>,
+    next_version: u64,
+
+    stroke: Option<Snapshot>,
+    undo_stack: VecDeque<Snapshot>,
+}
+
+impl Document {
+    pub fn new(w: u32, h: u32, color: Color) -> Self {
+        let tiles_x = w.div_ceil(TILE_SIZE);
+        let nb_tiles = (tiles_x * h.div_ceil(TILE_SIZE)) as usize;
+
+        Document {
+            fb: Framebuffer::new_owned_filled(w, h, color),
+            tiles_x,
+            versions: vec![0; nb_tiles],
+            next_version: 1,
+            stroke: None,
+            undo_stack: VecDeque::new(),
+        }
+    }
+
+    pub fn fb(&self) -> &Framebuffer<OwnedPixels> {
+        &self.fb
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.fb.shape_as_rect()
+    }
+
+    fn tile_rect(&self, i: usize) -> Rect {
+        let (tx, ty) = (i as u32 % self.tiles_x, i as u32 / self.tiles_x);
+        let tile_rect = Rect {
+            x0: (tx * TILE_SIZE) as i64,
+            y0: (ty * TILE_SIZE) as i64,
+            w: TILE_SIZE,
+            h: TILE_SIZE,
+        };
+
+        // Tiles on the right and bottom edges may be cut
+        tile_rect.intersection(&self.rect()).unwrap()
+    }
+
+    fn tile_index(&self, x: i64, y: i64) -> Option<usize> {
+        match self.rect().check_contains_point(x, y) {
+            true => {
+                let (tx, ty) = (x as u32 / TILE_SIZE, y as u32 / TILE_SIZE);
+                Some((ty * self.tiles_x + tx) as usize)
+            }
+            false => None,
+        }
+    }
+
+    fn tiles_in(&self, rect: &Rect) -> Vec<usize> {
+        let Some(rect) = rect.intersection(&self.rect()) else {
+            return Vec::new();
+        };
+
+        let [x0, y0, x1, y1] = rect.as_xyxy();
+        let (tx0, ty0) = (x0 as u32 / TILE_SIZE, y0 as u32 / TILE_SIZE);
+        let (tx1, ty1) = (x1 as u32 / TILE_SIZE, y1 as u32 / TILE_SIZE);
+
+        (ty0..=ty1)
+            .flat_map(|ty| (tx0..=tx1).map(move |tx| (ty * self.tiles_x + tx) as usize))
+            .collect()
+    }
+
+    pub fn begin_stroke(&mut self) {
+        self.end_stroke();
+        self.stroke = Some(Snapshot {
+            tiles: BTreeMap::new(),
+        });
+    }
+
+    pub fn end_stroke(&mut self) {
+        if let Some(snapshot) = self.stroke.take() {
+            if !snapshot.tiles.is_empty() {
+                self.undo_stack.push_back(snapshot);
+                if self.undo_stack.len() > MAX_UNDO {
+                    self.undo_stack.pop_front();
+                }
+            }
+        }
+    }
+
+    // All drawing to the document must go through there, so that the tiles
+    // overlapping `rect` are saved for undo and marked as dirty beforehand
+    pub fn paint<F>(&mut self, rect: &Rect, func: F)
+    where
+        F: FnOnce(&mut Framebuffer<OwnedPixels>),
+    {
+        for i in self.tiles_in(rect) {
+            let tile_rect = self.tile_rect(i);
+
+            if let Some(snapshot) = self.stroke.as_mut() {
+                snapshot.tiles.entry(i).or_insert_with(|| {
+                    let mut tile_fb = Framebuffer::new_owned(tile_rect.w, tile_rect.h);
+                    tile_fb.copy_from_fb(&self.fb.subregion(&tile_rect), (0, 0), false);
+                    tile_fb
+                });
+            }
+
+            self.versions[i] = self.next_version;
+            self.next_version += 1;
+        }
+
+        func(&mut self.fb);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn undo(&mut self) {
+        self.end_stroke();
+
+        let Some(snapshot) = self.undo_stack.pop_back() else {
+            return;
+        };
+
+        for (i, tile_fb) in snapshot.tiles {
+            let Rect { x0, y0, .. } = self.tile_rect(i);
+            self.fb.copy_from_fb(&tile_fb, (x0, y0), false);
+            self.versions[i] = self.next_version;
+            self.next_version += 1;
+        }
+    }
+}
+
+pub struct DocumentRenderer<'a> {
+    pub doc: &'a Document,
+    pub bg_color: Color,
+}
+
+impl<'a> TileRenderer for DocumentRenderer<'a> {
+    fn shape(&self) -> (u32, u32) {
+        self.doc.fb.shape()
+    }
+
+    fn tile_shape(&self) -> (u32, u32) {
+        (TILE_SIZE, TILE_SIZE)
+    }
+
+    fn content_id(&self, viewport_rect: &Rect) -> ContentId {
+        let version = self
+            .doc
+            .tile_index(viewport_rect.x0, viewport_rect.y0)
+            .map(|i| self.doc.versions[i]);
+
+        ContentId::from_hash(&(viewport_rect, version, self.bg_color))
+    }
+
+    fn render<F: FbViewMut>(&self, dst_fb: &mut F, viewport_rect: &Rect) {
+        dst_fb.fill(self.bg_color);
+
+        if let Some(rect) = viewport_rect.intersection(&self.doc.rect()) {
+            let dst = (rect.x0 - viewport_rect.x0, rect.y0 - viewport_rect.y0);
+            dst_fb.copy_from_fb(&self.doc.fb.subregion(&rect), dst, false);
+        }
+    }
+}
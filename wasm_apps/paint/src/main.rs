@@ -0,0 +1,544 @@
+extern crate alloc;
+
+use alloc::format;
+use applib::drawing::primitives::{draw_rect, draw_rect_outline};
+use applib::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use applib::input::{Keycode, PointerState};
+use applib::uitk::layout::{make_horizontal_layout, make_vertical_layout, LayoutItem};
+use applib::uitk::{
+    self, ButtonConfig, ButtonIndicatorMode, ColorPickerConfig, ContentId, SliderConfig,
+    UuidProvider,
+};
+use applib::{Color, FbViewMut, Rect};
+use core::cell::OnceCell;
+use guestlib::{PixelData, WasmLogger};
+
+mod document;
+mod tools;
+
+use document::{Document, DocumentRenderer};
+use tools::Tool;
+
+static LOGGER: WasmLogger = WasmLogger;
+const LOGGING_LEVEL: log::LevelFilter = log::LevelFilter::Debug;
+
+const DOC_W: u32 = 800;
+const DOC_H: u32 = 600;
+const DOC_COLOR: Color = Color::WHITE;
+const EXPORT_FILE: &str = "paint.png";
+
+const BUTTON_H: u32 = 30;
+const TOOL_BUTTON_W: u32 = 60;
+const ACTION_BUTTON_W: u32 = 70;
+const SWATCH_W: u32 = 24;
+const PICKER_BUTTON_W: u32 = 60;
+const PICKER_W: u32 = 220;
+const PICKER_H: u32 = 280;
+const SIZE_LABEL_W: u32 = 70;
+const SIZE_SLIDER_W: u32 = 160;
+const STATUS_H: u32 = 20;
+const MAX_BRUSH_SIZE: u32 = 40;
+const SCROLLBAR_W: u32 = 16; // Scrollbars of the canvas, where clicks must not draw
+
+const PALETTE: [Color; 12] = [
+    Color::BLACK,
+    Color::WHITE,
+    Color::GREY,
+    Color::rgb(128, 64, 0),
+    Color::RED,
+    Color::ORANGE,
+    Color::YELLOW,
+    Color::GREEN,
+    Color::rgb(0, 128, 0),
+    Color::AQUA,
+    Color::BLUE,
+    Color::FUCHSIA,
+];
+
+struct AppState {
+    pixel_data: PixelData,
+    ui_store: uitk::UiStore,
+    uuid_provider: UuidProvider,
+
+    doc: Document,
+    tool: Tool,
+    color: Color,
+    picker_open: bool,
+    brush_size: u32,
+    stroke: Stroke,
+    // In document coordinates
+    selection: Option<Rect>,
+
+    canvas_offsets: (i64, i64),
+    canvas_dragging: (bool, bool),
+
+    status_msg: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Stroke {
+    Idle,
+    Freehand { last: (i64, i64) },
+    Shape { start: (i64, i64) },
+    Select { start: (i64, i64) },
+}
+
+static mut APP_STATE: OnceCell<AppState> = OnceCell::new();
+
+fn main() {}
+
+#[no_mangle]
+pub fn init() -> () {
+    log::set_max_level(LOGGING_LEVEL);
+    log::set_logger(&LOGGER).unwrap();
+
+    let state = AppState {
+        pixel_data: PixelData::new(),
+        ui_store: uitk::UiStore::new(),
+        uuid_provider: UuidProvider::new(),
+
+        doc: Document::new(DOC_W, DOC_H, DOC_COLOR),
+        tool: Tool::Brush,
+        color: Color::BLACK,
+        picker_open: false,
+        brush_size: 4,
+        stroke: Stroke::Idle,
+        selection: None,
+
+        canvas_offsets: (0, 0),
+        canvas_dragging: (false, false),
+
+        status_msg: None,
+    };
+
+    unsafe {
+        APP_STATE
+            .set(state)
+            .unwrap_or_else(|_| panic!("App already initialized"));
+    }
+}
+
+#[no_mangle]
+pub fn step() {
+    let state = unsafe { APP_STATE.get_mut().expect("App not initialized") };
+
+    let time = guestlib::get_time();
+    let stylesheet = guestlib::get_stylesheet();
+    let input_state = guestlib::get_input_state();
+    let win_rect = guestlib::get_win_rect().zero_origin();
+
+    let m = stylesheet.margin;
+    let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+
+    let main_layout = make_vertical_layout(
+        &win_rect.offset(-(m as i64)),
+        m,
+        &[
+            LayoutItem::Fixed { size: BUTTON_H },
+            LayoutItem::Fixed { size: BUTTON_H },
+            LayoutItem::Float,
+            LayoutItem::Fixed { size: STATUS_H },
+        ],
+    );
+
+    let toolbar_layout = make_horizontal_layout(
+        &main_layout[0],
+        m,
+        &[
+            vec![
+                LayoutItem::Fixed {
+                    size: TOOL_BUTTON_W
+                };
+                Tool::ALL.len()
+            ],
+            vec![LayoutItem::Float],
+            vec![
+                LayoutItem::Fixed {
+                    size: ACTION_BUTTON_W
+                };
+                3
+            ],
+        ]
+        .concat(),
+    );
+
+    let palette_layout = make_horizontal_layout(
+        &main_layout[1],
+        m,
+        &[
+            vec![LayoutItem::Fixed { size: SWATCH_W }; PALETTE.len()],
+            vec![
+                LayoutItem::Fixed {
+                    size: PICKER_BUTTON_W,
+                },
+                LayoutItem::Float,
+                LayoutItem::Fixed { size: SIZE_LABEL_W },
+                LayoutItem::Fixed {
+                    size: SIZE_SLIDER_W,
+                },
+            ],
+        ]
+        .concat(),
+    );
+
+    // The color picker takes the right of the canvas while open
+    let canvas_layout = make_horizontal_layout(
+        &main_layout[2],
+        m,
+        &match state.picker_open {
+            true => vec![LayoutItem::Float, LayoutItem::Fixed { size: PICKER_W }],
+            false => vec![LayoutItem::Float],
+        },
+    );
+
+    // Handled before drawing the canvas, so that changes show up without a frame of delay
+    let canvas_rect = &canvas_layout[0];
+    let shape_preview = handle_canvas_input(state, canvas_rect, &input_state.pointer);
+
+    let mut framebuffer = state.pixel_data.get_framebuffer();
+
+    let AppState {
+        ui_store,
+        uuid_provider,
+        ..
+    } = state;
+
+    framebuffer.fill(stylesheet.colors.background);
+
+    let mut uitk_context = ui_store.get_context(
+        &mut framebuffer,
+        &stylesheet,
+        &input_state,
+        uuid_provider,
+        time,
+    );
+
+    //
+    // Toolbar
+
+    for (tool, rect) in Tool::ALL.iter().zip(toolbar_layout.iter()) {
+        let mut active = state.tool == *tool;
+        uitk_context.button_toggle(
+            &ButtonConfig {
+                rect: rect.clone(),
+                text: tool.name().to_owned(),
+                indicator_mode: ButtonIndicatorMode::Border,
+                ..Default::default()
+            },
+            &mut active,
+        );
+        if active {
+            state.tool = *tool;
+        }
+    }
+
+    let action_rects = &toolbar_layout[Tool::ALL.len() + 1..];
+
+    let undo_pressed = uitk_context.button(&ButtonConfig {
+        rect: action_rects[0].clone(),
+        text: "Undo".to_owned(),
+        ..Default::default()
+    });
+    let clear_pressed = uitk_context.button(&ButtonConfig {
+        rect: action_rects[1].clone(),
+        text: "Clear".to_owned(),
+        ..Default::default()
+    });
+    let export_pressed = uitk_context.button(&ButtonConfig {
+        rect: action_rects[2].clone(),
+        text: "Export".to_owned(),
+        ..Default::default()
+    });
+
+    //
+    // Palette and brush size
+
+    let pointer = &input_state.pointer;
+
+    for (color, rect) in PALETTE.iter().zip(palette_layout.iter()) {
+        draw_rect(uitk_context.fb, rect, *color, false);
+
+        if *color == state.color {
+            draw_rect_outline(uitk_context.fb, rect, stylesheet.colors.accent, false, 3);
+        }
+
+        if pointer.left_click_trigger && rect.check_contains_point(pointer.x, pointer.y) {
+            state.color = *color;
+        }
+    }
+
+    uitk_context.button_toggle(
+        &ButtonConfig {
+            rect: palette_layout[PALETTE.len()].clone(),
+            text: "More".to_owned(),
+            indicator_mode: ButtonIndicatorMode::Light,
+            tooltip: Some("Pick any color".to_owned()),
+            ..Default::default()
+        },
+        &mut state.picker_open,
+    );
+
+    if let Some(picker_column) = canvas_layout.get(1) {
+        uitk_context.color_picker(
+            &ColorPickerConfig {
+                id: ContentId::from_hash(&"color_picker"),
+                rect: Rect {
+                    h: u32::min(PICKER_H, picker_column.h),
+                    ..picker_column.clone()
+                },
+                ..Default::default()
+            },
+            &mut state.color,
+        );
+    }
+
+    let size_label_rect = &palette_layout[PALETTE.len() + 2];
+    draw_line_in_rect(
+        uitk_context.fb,
+        &format!("Size: {}", state.brush_size),
+        size_label_rect,
+        font,
+        stylesheet.colors.text,
+        TextJustification::Right,
+    );
+
+    let mut brush_size = state.brush_size as i64;
+    uitk_context.slider_int(
+        &SliderConfig {
+            id: ContentId::from_hash(&"brush_size"),
+            rect: palette_layout.last().unwrap().clone(),
+            min: 1.0,
+            max: MAX_BRUSH_SIZE as f32,
+            ..Default::default()
+        },
+        &mut brush_size,
+    );
+    state.brush_size = brush_size as u32;
+
+    //
+    // Canvas
+
+    uitk_context.dynamic_canvas(
+        canvas_rect,
+        &DocumentRenderer {
+            doc: &state.doc,
+            bg_color: stylesheet.colors.frame,
+        },
+        &mut state.canvas_offsets,
+        &mut state.canvas_dragging,
+    );
+
+    if let Some((start, end)) = shape_preview {
+        let (scroll_x, scroll_y) = state.canvas_offsets;
+        let to_canvas = |(x, y): (i64, i64)| (x - scroll_x, y - scroll_y);
+
+        let mut canvas_fb = uitk_context.fb.subregion_mut(canvas_rect);
+        tools::draw_shape(
+            &mut canvas_fb,
+            state.tool,
+            to_canvas(start),
+            to_canvas(end),
+            state.brush_size,
+            state.color,
+        );
+    }
+
+    if let Some(selection) = state.selection.as_ref() {
+        let (scroll_x, scroll_y) = state.canvas_offsets;
+        let rect = Rect {
+            x0: selection.x0 - scroll_x,
+            y0: selection.y0 - scroll_y,
+            ..selection.clone()
+        };
+
+        let mut canvas_fb = uitk_context.fb.subregion_mut(canvas_rect);
+        tools::draw_selection(&mut canvas_fb, &rect, time);
+    }
+
+    //
+    // Actions
+
+    let undo_requested = input_state.ctrl && input_state.check_key_pressed(Keycode::KEY_Z);
+
+    if (undo_pressed || undo_requested) && state.doc.can_undo() {
+        state.doc.undo();
+        state.stroke = Stroke::Idle;
+    }
+
+    // Only the selection is cleared if there is one
+    let erase_requested = input_state.check_key_pressed(Keycode::KEY_DELETE);
+
+    if clear_pressed || (erase_requested && state.selection.is_some()) {
+        let rect = state.selection.clone().unwrap_or_else(|| state.doc.rect());
+        state.doc.begin_stroke();
+        state
+            .doc
+            .paint(&rect, |fb| draw_rect(fb, &rect, DOC_COLOR, false));
+        state.doc.end_stroke();
+    }
+
+    if input_state.check_key_pressed(Keycode::KEY_ESC) {
+        state.selection = None;
+    }
+
+    if export_pressed {
+        state.status_msg = Some(export(&state.doc));
+    }
+
+    //
+    // Status bar
+
+    let status_rect = &main_layout[3];
+    let status = match state.status_msg.as_ref() {
+        Some(msg) => msg.clone(),
+        None => format!("{}x{}", DOC_W, DOC_H),
+    };
+
+    draw_rect(
+        uitk_context.fb,
+        status_rect,
+        stylesheet.colors.element,
+        false,
+    );
+    draw_line_in_rect(
+        uitk_context.fb,
+        &status,
+        status_rect,
+        font,
+        stylesheet.colors.text,
+        TextJustification::Left,
+    );
+}
+
+// Returns the endpoints of the line or rectangle being drawn, if any
+fn handle_canvas_input(
+    state: &mut AppState,
+    canvas_rect: &Rect,
+    pointer: &PointerState,
+) -> Option<((i64, i64), (i64, i64))> {
+    let (doc_w, doc_h) = state.doc.rect().shape();
+    let (scroll_x, scroll_y) = state.canvas_offsets;
+
+    let drawable_rect = Rect {
+        w: match doc_h > canvas_rect.h {
+            true => canvas_rect.w.saturating_sub(SCROLLBAR_W),
+            false => canvas_rect.w,
+        },
+        h: match doc_w > canvas_rect.w {
+            true => canvas_rect.h.saturating_sub(SCROLLBAR_W),
+            false => canvas_rect.h,
+        },
+        ..canvas_rect.clone()
+    };
+
+    let p = (
+        pointer.x - canvas_rect.x0 + scroll_x,
+        pointer.y - canvas_rect.y0 + scroll_y,
+    );
+
+    let size = state.brush_size;
+    let color = match state.tool {
+        Tool::Eraser => DOC_COLOR,
+        _ => state.color,
+    };
+
+    match state.stroke {
+        Stroke::Idle => {
+            let pressed = pointer.left_click_trigger
+                && drawable_rect.check_contains_point(pointer.x, pointer.y);
+
+            if !pressed {
+                return None;
+            }
+
+            match state.tool {
+                Tool::Brush | Tool::Eraser => {
+                    state.doc.begin_stroke();
+                    state.doc.paint(&tools::stroke_bbox(p, p, size), |fb| {
+                        tools::draw_stroke(fb, p, p, size, color)
+                    });
+                    state.stroke = Stroke::Freehand { last: p };
+                }
+                Tool::Line | Tool::Rectangle => {
+                    state.stroke = Stroke::Shape { start: p };
+                    return Some((p, p));
+                }
+                Tool::Select => {
+                    state.selection = None;
+                    state.stroke = Stroke::Select { start: p };
+                }
+                Tool::Fill => {
+                    let spans = tools::flood_fill_spans(state.doc.fb(), p.0, p.1, color);
+                    if let Some(bbox) = tools::spans_bbox(&spans) {
+                        state.doc.begin_stroke();
+                        state.doc.paint(&bbox, |fb| {
+                            for span in spans.iter() {
+                                fb.fill_line(span.x0, span.w, span.y, color, false);
+                            }
+                        });
+                        state.doc.end_stroke();
+                    }
+                }
+            }
+        }
+
+        Stroke::Freehand { last } => {
+            if last != p {
+                state.doc.paint(&tools::stroke_bbox(last, p, size), |fb| {
+                    tools::draw_stroke(fb, last, p, size, color)
+                });
+                state.stroke = Stroke::Freehand { last: p };
+            }
+
+            if !pointer.left_clicked {
+                state.doc.end_stroke();
+                state.stroke = Stroke::Idle;
+            }
+        }
+
+        Stroke::Shape { start } => {
+            if pointer.left_clicked {
+                return Some((start, p));
+            }
+
+            let tool = state.tool;
+            state.doc.begin_stroke();
+            state
+                .doc
+                .paint(&tools::shape_bbox(tool, start, p, size), |fb| {
+                    tools::draw_shape(fb, tool, start, p, size, color)
+                });
+            state.doc.end_stroke();
+            state.stroke = Stroke::Idle;
+        }
+
+        Stroke::Select { start } => {
+            state.selection = tools::points_rect(start, p).intersection(&state.doc.rect());
+
+            // A click without dragging only drops the previous selection
+            if !pointer.left_clicked {
+                if start == p {
+                    state.selection = None;
+                }
+                state.stroke = Stroke::Idle;
+            }
+        }
+    }
+
+    None
+}
+
+fn export(doc: &Document) -> String {
+    let png_bytes = doc.fb().to_png();
+
+    match guestlib::storage_write(EXPORT_FILE, &png_bytes) {
+        Ok(()) => {
+            log::info!("Exported {} ({} bytes)", EXPORT_FILE, png_bytes.len());
+            format!("Saved to {} ({} bytes)", EXPORT_FILE, png_bytes.len())
+        }
+        Err(err) => {
+            log::error!("Could not export {}: {}", EXPORT_FILE, err);
+            format!("Could not save {}: {}", EXPORT_FILE, err)
+        }
+    }
+}
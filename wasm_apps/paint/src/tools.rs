@@ -0,0 +1,204 @@
+use applib::drawing::primitives::{
+    draw_line, draw_rect, draw_rect_outline, draw_rect_outline_styled, StrokeStyle,
+};
+use applib::geometry::Point2D;
+use applib::{Color, FbView, FbViewMut, Rect};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tool {
+    Brush,
+    Eraser,
+    Line,
+    Rectangle,
+    Fill,
+    Select,
+}
+
+// Of the selection outline, in pixels and ms per pixel of march
+const ANTS_PATTERN: [u32; 2] = [4, 4];
+const ANTS_SPEED: f64 = 60.0;
+
+impl Tool {
+    pub const ALL: [Tool; 6] = [
+        Tool::Brush,
+        Tool::Eraser,
+        Tool::Line,
+        Tool::Rectangle,
+        Tool::Fill,
+        Tool::Select,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Brush => "Brush",
+            Tool::Eraser => "Eraser",
+            Tool::Line => "Line",
+            Tool::Rectangle => "Rect",
+            Tool::Fill => "Fill",
+            Tool::Select => "Select",
+        }
+    }
+}
+
+pub fn points_rect(p0: (i64, i64), p1: (i64, i64)) -> Rect {
+    let (x0, x1) = (i64::min(p0.0, p1.0), i64::max(p0.0, p1.0));
+    let (y0, y1) = (i64::min(p0.1, p1.1), i64::max(p0.1, p1.1));
+    Rect::from_xyxy([x0, y0, x1, y1])
+}
+
+// Area covered by a round brush of diameter `size` moved from p0 to p1
+pub fn stroke_bbox(p0: (i64, i64), p1: (i64, i64), size: u32) -> Rect {
+    let r = (size / 2) as i64;
+    let [x0, y0, x1, y1] = points_rect(p0, p1).as_xyxy();
+    Rect::from_xyxy([x0 - r, y0 - r, x1 + r, y1 + r])
+}
+
+// A round brush of diameter `size` moved from p0 to p1
+pub fn draw_stroke<F: FbViewMut>(
+    fb: &mut F,
+    p0: (i64, i64),
+    p1: (i64, i64),
+    size: u32,
+    color: Color,
+) {
+    let to_point = |(x, y): (i64, i64)| Point2D { x, y };
+    let style = StrokeStyle::solid(size);
+    draw_line(fb, to_point(p0), to_point(p1), color, &style, false);
+}
+
+fn draw_rectangle<F: FbViewMut>(
+    fb: &mut F,
+    p0: (i64, i64),
+    p1: (i64, i64),
+    size: u32,
+    color: Color,
+) {
+    let rect = points_rect(p0, p1);
+
+    if 2 * size >= u32::min(rect.w, rect.h) {
+        draw_rect(fb, &rect, color, false);
+    } else {
+        draw_rect_outline(fb, &rect, color, false, size);
+    }
+}
+
+// Line and rectangle are drawn from the point where the pointer was pressed
+pub fn shape_bbox(tool: Tool, p0: (i64, i64), p1: (i64, i64), size: u32) -> Rect {
+    match tool {
+        Tool::Rectangle => points_rect(p0, p1),
+        _ => stroke_bbox(p0, p1, size),
+    }
+}
+
+pub fn draw_shape<F: FbViewMut>(
+    fb: &mut F,
+    tool: Tool,
+    p0: (i64, i64),
+    p1: (i64, i64),
+    size: u32,
+    color: Color,
+) {
+    match tool {
+        Tool::Rectangle => draw_rectangle(fb, p0, p1, size, color),
+        _ => draw_stroke(fb, p0, p1, size, color),
+    }
+}
+
+// Marching ants: black dashes over a white outline, so that it shows on any color
+pub fn draw_selection<F: FbViewMut>(fb: &mut F, rect: &Rect, time: f64) {
+    draw_rect_outline(fb, rect, Color::WHITE, false, 1);
+
+    let period = ANTS_PATTERN.iter().sum::<u32>();
+    let style = StrokeStyle {
+        thickness: 1,
+        pattern: Some(&ANTS_PATTERN),
+        phase: (time / ANTS_SPEED) as u32 % period,
+    };
+    draw_rect_outline_styled(fb, rect, Color::BLACK, false, &style);
+}
+
+pub struct FillSpan {
+    pub x0: i64,
+    pub y: i64,
+    pub w: u32,
+}
+
+// Scanline flood fill of the area of same color around (x, y).
+// The spans are computed without touching the framebuffer, so that the affected
+// region is known before drawing.
+pub fn flood_fill_spans<F: FbView>(fb: &F, x: i64, y: i64, color: Color) -> Vec<FillSpan> {
+    let mut spans = Vec::new();
+
+    let Some(target) = fb.get_pixel(x, y) else {
+        return spans;
+    };
+
+    if target == color {
+        return spans;
+    }
+
+    let (w, h) = fb.shape();
+    let (w, h) = (w as i64, h as i64);
+    let mut visited = vec![false; (w * h) as usize];
+
+    let matches = |visited: &[bool], x: i64, y: i64| {
+        !visited[(y * w + x) as usize] && fb.get_pixel(x, y) == Some(target)
+    };
+
+    let mut seeds = vec![(x, y)];
+
+    while let Some((x, y)) = seeds.pop() {
+        if !matches(&visited, x, y) {
+            continue;
+        }
+
+        let mut x0 = x;
+        while x0 > 0 && matches(&visited, x0 - 1, y) {
+            x0 -= 1;
+        }
+        let mut x1 = x;
+        while x1 < w - 1 && matches(&visited, x1 + 1, y) {
+            x1 += 1;
+        }
+
+        for xi in x0..=x1 {
+            visited[(y * w + xi) as usize] = true;
+        }
+
+        for y_next in [y - 1, y + 1] {
+            if y_next < 0 || y_next >= h {
+                continue;
+            }
+
+            // One seed per run of matching pixels
+            let mut in_run = false;
+            for xi in x0..=x1 {
+                let m = matches(&visited, xi, y_next);
+                if m && !in_run {
+                    seeds.push((xi, y_next));
+                }
+                in_run = m;
+            }
+        }
+
+        spans.push(FillSpan {
+            x0,
+            y,
+            w: (x1 - x0 + 1) as u32,
+        });
+    }
+
+    spans
+}
+
+pub fn spans_bbox(spans: &[FillSpan]) -> Option<Rect> {
+    spans
+        .iter()
+        .map(|span| Rect {
+            x0: span.x0,
+            y0: span.y,
+            w: span.w,
+            h: 1,
+        })
+        .reduce(|a, b| a.bounding_box(&b))
+}
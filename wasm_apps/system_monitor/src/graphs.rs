@@ -0,0 +1,86 @@
+use alloc::collections::VecDeque;
+use applib::drawing::primitives::{draw_polyline, StrokeStyle};
+use applib::drawing::text::{draw_str, Font};
+use applib::geometry::Point2D;
+use applib::uitk::{ContentId, TileRenderer};
+use applib::{Color, FbViewMut, Rect};
+
+pub struct GraphSeries<'a> {
+    pub data: &'a VecDeque<f32>,
+    pub color: Color,
+}
+
+// Renders a whole graph as a single tile, which only changes when a new sample comes in
+pub struct GraphRenderer<'a> {
+    pub shape: (u32, u32),
+    pub title: &'a str,
+    pub series: &'a [GraphSeries<'a>],
+    pub nb_points: usize,
+    pub min_max_val: f32,
+    pub sample_index: u64,
+    pub font: &'static Font,
+    pub bg_color: Color,
+    pub text_color: Color,
+}
+
+impl<'a> TileRenderer for GraphRenderer<'a> {
+    fn shape(&self) -> (u32, u32) {
+        self.shape
+    }
+
+    fn tile_shape(&self) -> (u32, u32) {
+        self.shape
+    }
+
+    fn content_id(&self, _viewport_rect: &Rect) -> ContentId {
+        ContentId::from_hash(&(self.shape, self.title, self.sample_index))
+    }
+
+    fn render<F: FbViewMut>(&self, dst_fb: &mut F, _viewport_rect: &Rect) {
+        let (w, h) = self.shape;
+
+        dst_fb.fill(self.bg_color);
+
+        let max_val = self
+            .series
+            .iter()
+            .flat_map(|series| series.data.iter().copied())
+            .fold(self.min_max_val, f32::max);
+
+        let title_h = self.font.char_h as u32;
+        let title = format!("{} (max {:.1})", self.title, max_val);
+        draw_str(dst_fb, &title, 2, 0, self.font, self.text_color, None);
+
+        if h <= title_h + 2 || w < 2 {
+            return;
+        }
+
+        let plot_rect = Rect {
+            x0: 0,
+            y0: title_h as i64,
+            w,
+            h: h - title_h,
+        };
+
+        for series in self.series {
+            let points: Vec<Point2D<i64>> = series
+                .data
+                .iter()
+                .enumerate()
+                .map(|(i, &val)| {
+                    // Newest sample on the right edge
+                    let i = self.nb_points - series.data.len() + i;
+                    let x = (i as i64) * (plot_rect.w as i64 - 1) / (self.nb_points as i64 - 1);
+                    let frac = f32::min(1.0, val / max_val);
+                    let y = plot_rect.y0 + plot_rect.h as i64
+                        - 1
+                        - f32::round(frac * (plot_rect.h - 1) as f32) as i64;
+                    Point2D { x, y }
+                })
+                .collect();
+
+            let style = StrokeStyle::solid(1);
+            draw_polyline(dst_fb, &points, false, series.color, &style, false);
+        }
+    }
+}
@@ -0,0 +1,585 @@
+extern crate alloc;
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use applib::content::TrackedContent;
+use applib::drawing::text::get_font;
+use applib::stats::AppStatsEntry;
+use applib::uitk::layout::{make_horizontal_layout, make_vertical_layout, LayoutItem};
+use applib::uitk::{
+    self, ButtonConfig, ContentId, TableConfig, TableEvent, TableState, TextBoxState, UuidProvider,
+};
+use applib::{FbViewMut, Rect};
+use core::cell::OnceCell;
+use guestlib::{PixelData, WasmLogger};
+
+mod graphs;
+mod table;
+
+use graphs::{GraphRenderer, GraphSeries};
+use table::{column_defs, Column, ROW_H};
+
+static LOGGER: WasmLogger = WasmLogger;
+const LOGGING_LEVEL: log::LevelFilter = log::LevelFilter::Debug;
+
+const SAMPLE_PERIOD: f64 = 1000.0; // in ms
+const HISTORY_SIZE: usize = 60; // in samples
+const KILL_BUTTON_W: u32 = 40;
+const DETAILS_H: u32 = 110;
+const GRAPHS_H: u32 = 110;
+const KERNEL_LOG_H: u32 = 160;
+// Older lines are dropped from the kernel log pane
+const KERNEL_LOG_LINES: usize = 500;
+
+struct AppState {
+    pixel_data: PixelData,
+
+    accumulators: BTreeMap<String, StatsAccumulator>,
+    system_accumulator: StatsAccumulator,
+    t_last_sample: f64,
+
+    rows: Vec<AppRow>,
+    sample_index: u64,
+    frametime_history: VecDeque<f32>,
+    net_recv_history: VecDeque<f32>,
+    net_sent_history: VecDeque<f32>,
+    heap_allocated_history: VecDeque<f32>,
+    heap_explored_history: VecDeque<f32>,
+    heap_peak: u64,
+
+    selected: Option<String>,
+    table_state: TableState,
+    details_text: TrackedContent<String>,
+    details_state: TextBoxState,
+
+    // Tailed from the kernel, from where the last read stopped
+    kernel_log: TrackedContent<String>,
+    kernel_log_state: TextBoxState,
+    kernel_log_position: u64,
+
+    uuid_provider: UuidProvider,
+    ui_store: uitk::UiStore,
+}
+
+#[derive(Default)]
+struct StatsAccumulator {
+    nb_frames: u32,
+    frametime_used: f64,
+    net_recv: u64,
+    net_sent: u64,
+    mem_used: u64,
+    kernel_alloc: u64,
+    // Only for the system
+    heap_explored: u64,
+    heap_peak: u64,
+    running: bool,
+}
+
+impl StatsAccumulator {
+    fn add(&mut self, entry: &AppStatsEntry) {
+        self.nb_frames += 1;
+        self.running = entry.running();
+        if entry.running() {
+            self.frametime_used += entry.frametime_used;
+            self.net_recv += entry.net_recv;
+            self.net_sent += entry.net_sent;
+            self.mem_used = entry.mem_used;
+            self.kernel_alloc += entry.kernel_alloc;
+        }
+    }
+}
+
+// Values averaged over the last sample period
+struct AppRow {
+    name: String,
+    running: bool,
+    mem_used: u64,
+    kernel_alloc_rate: f64, // in bytes/s
+    net_recv_rate: f64,     // in bytes/s
+    net_sent_rate: f64,     // in bytes/s
+    frametime_used: f64,    // in ms
+}
+
+impl AppRow {
+    fn cells(&self) -> [String; table::NB_COLUMNS] {
+        let name = self.name.clone();
+        match self.running {
+            false => [
+                name,
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+            ],
+            true => [
+                name,
+                format!("{:.1} MB", self.mem_used as f64 / 1_000_000.0),
+                format!("{:.1} kB/s", self.kernel_alloc_rate / 1000.0),
+                format!("{:.1} kB/s", self.net_recv_rate / 1000.0),
+                format!("{:.1} kB/s", self.net_sent_rate / 1000.0),
+                format!("{:.2} ms", self.frametime_used),
+            ],
+        }
+    }
+}
+
+static mut APP_STATE: OnceCell<AppState> = OnceCell::new();
+
+fn main() {}
+
+#[no_mangle]
+pub fn init() -> () {
+    log::set_max_level(LOGGING_LEVEL);
+    log::set_logger(&LOGGER).unwrap();
+
+    let mut uuid_provider = UuidProvider::new();
+
+    let mut ui_store = uitk::UiStore::new();
+    ui_store.set_clipboard(guestlib::HostClipboard);
+
+    // Sorted by name at first
+    let mut table_state = TableState::new();
+    table_state.sort = Some((0, true));
+
+    let state = AppState {
+        pixel_data: PixelData::new(),
+
+        accumulators: BTreeMap::new(),
+        system_accumulator: StatsAccumulator::default(),
+        t_last_sample: guestlib::get_time(),
+
+        rows: Vec::new(),
+        sample_index: 0,
+        frametime_history: VecDeque::new(),
+        net_recv_history: VecDeque::new(),
+        net_sent_history: VecDeque::new(),
+        heap_allocated_history: VecDeque::new(),
+        heap_explored_history: VecDeque::new(),
+        heap_peak: 0,
+
+        selected: None,
+        table_state,
+        details_text: TrackedContent::new(
+            "Click on an app to see its timings".to_owned(),
+            &mut uuid_provider,
+        ),
+        details_state: TextBoxState::new(),
+
+        kernel_log: TrackedContent::new(String::new(), &mut uuid_provider),
+        kernel_log_state: TextBoxState::new(),
+        kernel_log_position: 0,
+
+        ui_store,
+        uuid_provider,
+    };
+    unsafe {
+        APP_STATE
+            .set(state)
+            .unwrap_or_else(|_| panic!("App already initialized"));
+    }
+}
+
+#[no_mangle]
+pub fn step() {
+    let state = unsafe { APP_STATE.get_mut().expect("App not initialized") };
+
+    let input_state = guestlib::get_input_state();
+    let win_rect = guestlib::get_win_rect().zero_origin();
+    let t_now = guestlib::get_time();
+    let stylesheet = guestlib::get_stylesheet();
+
+    //
+    // Stats sampling
+
+    let (system_entry, app_entries) = guestlib::get_system_stats();
+
+    for entry in app_entries.iter() {
+        state
+            .accumulators
+            .entry(entry.name().to_owned())
+            .or_default()
+            .add(entry);
+    }
+
+    let sys_acc = &mut state.system_accumulator;
+    sys_acc.nb_frames += 1;
+    sys_acc.frametime_used += system_entry.frametime_used;
+    sys_acc.net_recv += system_entry.net_recv;
+    sys_acc.net_sent += system_entry.net_sent;
+    sys_acc.mem_used = system_entry.heap_allocated;
+    sys_acc.heap_explored = system_entry.heap_explored;
+    sys_acc.heap_peak = system_entry.heap_peak;
+
+    if t_now - state.t_last_sample >= SAMPLE_PERIOD {
+        take_sample(state, t_now);
+    }
+
+    let new_lines = guestlib::kernel_log_read(&mut state.kernel_log_position);
+    if !new_lines.is_empty() {
+        let kernel_log = state.kernel_log.mutate(&mut state.uuid_provider);
+        kernel_log.push_str(&new_lines);
+        trim_lines(kernel_log, KERNEL_LOG_LINES);
+    }
+
+    let mut framebuffer = state.pixel_data.get_framebuffer();
+
+    let AppState {
+        ui_store,
+        uuid_provider,
+        ..
+    } = state;
+
+    framebuffer.fill(stylesheet.colors.background);
+
+    let mut uitk_context = ui_store.get_context(
+        &mut framebuffer,
+        &stylesheet,
+        &input_state,
+        uuid_provider,
+        t_now,
+    );
+
+    let font = get_font(&stylesheet.text.font_family(), stylesheet.text.sizes.small);
+
+    let layout_1 = make_vertical_layout(
+        &win_rect.offset(-(stylesheet.margin as i64)),
+        stylesheet.margin,
+        &[
+            LayoutItem::Float,
+            LayoutItem::Fixed { size: DETAILS_H },
+            LayoutItem::Fixed { size: GRAPHS_H },
+            LayoutItem::Fixed { size: KERNEL_LOG_H },
+        ],
+    );
+
+    let layout_table = make_horizontal_layout(
+        &layout_1[0],
+        stylesheet.margin,
+        &[
+            LayoutItem::Float,
+            LayoutItem::Fixed {
+                size: KILL_BUTTON_W,
+            },
+        ],
+    );
+
+    let layout_graphs = make_horizontal_layout(
+        &layout_1[2],
+        stylesheet.margin,
+        &[LayoutItem::Float, LayoutItem::Float, LayoutItem::Float],
+    );
+
+    //
+    // App table
+
+    sort_rows(&mut state.rows, state.table_state.sort);
+
+    // Keeping the selection on the same app when the rows move
+    let selected_index = state
+        .rows
+        .iter()
+        .position(|row| Some(&row.name) == state.selected.as_ref());
+    state.table_state.selection = selected_index.into_iter().collect();
+    state.table_state.cursor = selected_index;
+
+    let table_rect = &layout_table[0];
+    let cells: Vec<[String; table::NB_COLUMNS]> = state.rows.iter().map(AppRow::cells).collect();
+
+    let table_event = uitk_context.table(
+        &TableConfig {
+            id: ContentId::from_hash(&"apps_table"),
+            rect: table_rect.clone(),
+            row_h: ROW_H,
+        },
+        &column_defs(table_rect.w),
+        cells.len(),
+        |row, col| cells[row][col].clone(),
+        &mut state.table_state,
+    );
+
+    match table_event {
+        TableEvent::Sort { column, ascending } => {
+            state.table_state.sort = Some((column, ascending));
+        }
+        TableEvent::Selected(i) | TableEvent::Activated(i) => {
+            state.selected = Some(state.rows[i].name.clone());
+            update_details(
+                &mut state.details_text,
+                uitk_context.uuid_provider,
+                &state.selected,
+            );
+        }
+        TableEvent::None => (),
+    }
+
+    // Kill buttons, next to the rows they belong to
+    let body_rect = state.table_state.body_rect();
+    let kill_column = Rect {
+        y0: body_rect.y0,
+        h: body_rect.h,
+        ..layout_table[1].clone()
+    };
+    let (_, scroll_y) = state.table_state.scroll_offsets();
+    let row_rect = |i: usize| Rect {
+        x0: kill_column.x0,
+        y0: body_rect.y0 + (i as u32 * ROW_H) as i64 - scroll_y,
+        w: kill_column.w,
+        h: ROW_H,
+    };
+
+    for (i, row) in state.rows.iter().enumerate() {
+        let button_rect = row_rect(i).intersection(&kill_column);
+
+        match button_rect {
+            Some(rect) if row.running && rect.h == ROW_H => {
+                let kill_pressed = uitk_context.button(&ButtonConfig {
+                    rect: Rect {
+                        w: KILL_BUTTON_W,
+                        ..rect
+                    },
+                    text: "Kill".to_owned(),
+                    ..Default::default()
+                });
+
+                if kill_pressed {
+                    log::info!("Closing {}", row.name);
+                    if let Err(err) = guestlib::close_app(&row.name) {
+                        log::error!("Cannot close {}: {}", row.name, err);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    //
+    // Timings of the selected app
+
+    uitk_context.text_box(
+        &layout_1[1],
+        &state.details_text,
+        &mut state.details_state,
+        false,
+    );
+
+    //
+    // Graphs
+
+    // Explored but not allocated is what fragmentation costs
+    let heap_title = format!(
+        "Heap (MB), peak {:.1}",
+        state.heap_peak as f64 / 1_000_000.0
+    );
+
+    let graph_specs = [
+        (
+            "Frametime (ms)",
+            1000.0 / 60.0,
+            vec![GraphSeries {
+                data: &state.frametime_history,
+                color: stylesheet.colors.red,
+            }],
+        ),
+        (
+            "Network (kB/s)",
+            1.0,
+            vec![
+                GraphSeries {
+                    data: &state.net_recv_history,
+                    color: stylesheet.colors.blue,
+                },
+                GraphSeries {
+                    data: &state.net_sent_history,
+                    color: stylesheet.colors.yellow,
+                },
+            ],
+        ),
+        (
+            heap_title.as_str(),
+            1.0,
+            vec![
+                GraphSeries {
+                    data: &state.heap_explored_history,
+                    color: stylesheet.colors.purple,
+                },
+                GraphSeries {
+                    data: &state.heap_allocated_history,
+                    color: stylesheet.colors.green,
+                },
+            ],
+        ),
+    ];
+
+    for ((title, min_max_val, series), graph_rect) in graph_specs.iter().zip(layout_graphs.iter()) {
+        let renderer = GraphRenderer {
+            shape: graph_rect.shape(),
+            title,
+            series,
+            nb_points: HISTORY_SIZE,
+            min_max_val: *min_max_val,
+            sample_index: state.sample_index,
+            font,
+            bg_color: stylesheet.colors.element,
+            text_color: stylesheet.colors.text,
+        };
+
+        uitk_context.dynamic_canvas(graph_rect, &renderer, &mut (0, 0), &mut (false, false));
+    }
+
+    //
+    // Kernel log
+
+    uitk_context.text_box(
+        &layout_1[3],
+        &state.kernel_log,
+        &mut state.kernel_log_state,
+        true,
+    );
+}
+
+fn take_sample(state: &mut AppState, t_now: f64) {
+    let dt_s = (t_now - state.t_last_sample) / 1000.0;
+    state.t_last_sample = t_now;
+
+    state.rows = core::mem::take(&mut state.accumulators)
+        .into_iter()
+        .map(|(name, acc)| {
+            let nb_frames = u32::max(1, acc.nb_frames) as f64;
+            AppRow {
+                name,
+                running: acc.running,
+                mem_used: acc.mem_used,
+                kernel_alloc_rate: acc.kernel_alloc as f64 / dt_s,
+                net_recv_rate: acc.net_recv as f64 / dt_s,
+                net_sent_rate: acc.net_sent as f64 / dt_s,
+                frametime_used: acc.frametime_used / nb_frames,
+            }
+        })
+        .collect();
+
+    // Apps could (in theory) disappear from the stats, keep the selection consistent
+    if !state
+        .rows
+        .iter()
+        .any(|row| Some(&row.name) == state.selected.as_ref())
+    {
+        state.selected = None;
+    }
+
+    let sys_acc = core::mem::take(&mut state.system_accumulator);
+    let nb_frames = u32::max(1, sys_acc.nb_frames) as f64;
+    state.heap_peak = sys_acc.heap_peak;
+
+    let histories = [
+        (
+            &mut state.frametime_history,
+            sys_acc.frametime_used / nb_frames,
+        ),
+        (
+            &mut state.net_recv_history,
+            sys_acc.net_recv as f64 / dt_s / 1000.0,
+        ),
+        (
+            &mut state.net_sent_history,
+            sys_acc.net_sent as f64 / dt_s / 1000.0,
+        ),
+        (
+            &mut state.heap_allocated_history,
+            sys_acc.mem_used as f64 / 1_000_000.0,
+        ),
+        (
+            &mut state.heap_explored_history,
+            sys_acc.heap_explored as f64 / 1_000_000.0,
+        ),
+    ];
+
+    for (history, val) in histories {
+        history.push_back(val as f32);
+        if history.len() > HISTORY_SIZE {
+            history.pop_front();
+        }
+    }
+
+    state.sample_index += 1;
+
+    update_details(
+        &mut state.details_text,
+        &mut state.uuid_provider,
+        &state.selected,
+    );
+}
+
+// Keeps the last `max_lines` lines
+fn trim_lines(text: &mut String, max_lines: usize) {
+    let nb_lines = text.lines().count();
+    if nb_lines <= max_lines {
+        return;
+    }
+
+    let cut = text
+        .match_indices('\n')
+        .nth(nb_lines - max_lines - 1)
+        .map(|(i, _)| i + 1)
+        .unwrap_or(0);
+    text.drain(..cut);
+}
+
+// Column index and whether it is ascending, as picked in the table header
+fn sort_rows(rows: &mut [AppRow], sort: Option<(usize, bool)>) {
+    let Some((column, ascending)) = sort else {
+        return;
+    };
+
+    rows.sort_by(|a, b| {
+        let ordering = match Column::ALL[column] {
+            Column::Name => a.name.cmp(&b.name),
+            Column::Memory => a.mem_used.cmp(&b.mem_used),
+            Column::KernelAlloc => a.kernel_alloc_rate.total_cmp(&b.kernel_alloc_rate),
+            Column::NetRecv => a.net_recv_rate.total_cmp(&b.net_recv_rate),
+            Column::NetSent => a.net_sent_rate.total_cmp(&b.net_sent_rate),
+            Column::Frametime => a.frametime_used.total_cmp(&b.frametime_used),
+        };
+
+        let ordering = match ascending {
+            true => ordering,
+            false => ordering.reverse(),
+        };
+
+        // Stopped apps always at the bottom
+        b.running.cmp(&a.running).then(ordering)
+    });
+}
+
+fn update_details(
+    details_text: &mut TrackedContent<String>,
+    uuid_provider: &mut UuidProvider,
+    selected: &Option<String>,
+) {
+    let new_text = match selected {
+        None => "Click on an app to see its timings".to_owned(),
+        Some(app_name) => match guestlib::get_timings(app_name) {
+            Err(err) => format!("Cannot get timings of {}: {}", app_name, err),
+            Ok(timings) if timings.is_empty() => {
+                format!("{}: no timings reported during the last step", app_name)
+            }
+            Ok(mut timings) => {
+                timings.sort_by(|(_, a), (_, b)| b.cmp(a));
+                let lines: Vec<String> = timings
+                    .iter()
+                    .map(|(key, consumed)| format!("{:<40} {:>12}", key, consumed))
+                    .collect();
+                format!(
+                    "{}: fuel consumed during the last step\n{}",
+                    app_name,
+                    lines.join("\n")
+                )
+            }
+        },
+    };
+
+    // Avoids invalidating the text box cache when nothing changed
+    if *details_text.as_ref() != new_text {
+        *details_text.mutate(uuid_provider) = new_text;
+    }
+}
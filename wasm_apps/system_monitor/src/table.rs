@@ -0,0 +1,61 @@
+use applib::drawing::text::TextJustification;
+use applib::uitk::ColumnDef;
+
+pub const ROW_H: u32 = 20;
+pub const NB_COLUMNS: usize = 6;
+const NUM_COLUMN_W: u32 = 90;
+const NAME_COLUMN_MIN_W: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Name,
+    Memory,
+    KernelAlloc,
+    NetRecv,
+    NetSent,
+    Frametime,
+}
+
+impl Column {
+    pub const ALL: [Column; NB_COLUMNS] = [
+        Column::Name,
+        Column::Memory,
+        Column::KernelAlloc,
+        Column::NetRecv,
+        Column::NetSent,
+        Column::Frametime,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Column::Name => "App",
+            Column::Memory => "Memory",
+            Column::KernelAlloc => "Kernel",
+            Column::NetRecv => "Down",
+            Column::NetSent => "Up",
+            Column::Frametime => "Frame",
+        }
+    }
+}
+
+// The name column initially takes the room left by the others
+pub fn column_defs(table_w: u32) -> Vec<ColumnDef> {
+    let nums_w = (NB_COLUMNS as u32 - 1) * NUM_COLUMN_W;
+    Column::ALL
+        .iter()
+        .map(|col| match col {
+            Column::Name => ColumnDef {
+                title: col.title().to_owned(),
+                width: u32::max(NAME_COLUMN_MIN_W, table_w.saturating_sub(nums_w)),
+                min_width: NAME_COLUMN_MIN_W,
+                ..Default::default()
+            },
+            _ => ColumnDef {
+                title: col.title().to_owned(),
+                width: NUM_COLUMN_W,
+                justification: TextJustification::Right,
+                ..Default::default()
+            },
+        })
+        .collect()
+}
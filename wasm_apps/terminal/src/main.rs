@@ -49,13 +49,20 @@ pub fn init() -> () {
 
     let mut uuid_provider = uitk::UuidProvider::new();
 
+    // The console is the only widget, it always has the focus
+    let mut textbox_state = TextBoxState::new();
+    textbox_state.focus();
+
+    let mut ui_store = uitk::UiStore::new();
+    ui_store.set_clipboard(guestlib::HostClipboard);
+
     let state = AppState {
         pixel_data: PixelData::new(),
         input_buffer: TrackedContent::new(RichText::new(), &mut uuid_provider),
         history: TrackedContent::new(Vec::new(), &mut uuid_provider),
-        ui_store: uitk::UiStore::new(),
+        ui_store,
         uuid_provider,
-        textbox_state: TextBoxState::new(),
+        textbox_state,
         python: python::Python::new(),
     };
     unsafe {
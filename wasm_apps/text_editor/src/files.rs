@@ -0,0 +1,205 @@
+use applib::content::TrackedContent;
+use applib::drawing::primitives::draw_rect;
+use applib::drawing::text::{draw_line_in_rect, get_font, TextJustification};
+use applib::uitk::layout::{make_horizontal_layout, make_vertical_layout, LayoutItem};
+use applib::uitk::{ButtonConfig, ContentId, ModalConfig, ModalResult, TextBoxState, UiContext};
+use applib::{FbViewMut, Rect};
+
+const ROW_H: u32 = 30;
+const DIALOG_BUTTON_W: u32 = 100;
+
+pub enum FileDialog {
+    Closed,
+    Open {
+        files: Vec<String>,
+    },
+    SaveAs {
+        name: TrackedContent<String>,
+        name_state: Box<TextBoxState>,
+    },
+    ConfirmDiscard {
+        discarding: Discarding,
+    },
+    // Sent by another app, goes through the same checks as a regular open
+    Requested {
+        file_name: String,
+    },
+}
+
+// What loses the unsaved changes once confirmed
+pub enum Discarding {
+    Open(String),
+    Close,
+}
+
+pub enum FileAction {
+    Open(String),
+    ConfirmedOpen(String),
+    ConfirmedClose,
+    SaveAs(String),
+    Cancel,
+}
+
+pub fn file_dialog<F: FbViewMut>(
+    uitk_context: &mut UiContext<F>,
+    rect: &Rect,
+    dialog: &mut FileDialog,
+) -> Option<FileAction> {
+    let stylesheet = uitk_context.stylesheet.clone();
+    let m = stylesheet.margin;
+    let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.medium);
+
+    draw_rect(uitk_context.fb, rect, stylesheet.colors.element, false);
+
+    let max_rows = (rect.h.saturating_sub(2 * m) / (ROW_H + m)) as usize;
+    let n_rows = usize::max(1, max_rows.saturating_sub(2));
+
+    let dialog_layout = make_vertical_layout(
+        &rect.offset(-(m as i64)),
+        m,
+        &[
+            vec![LayoutItem::Fixed { size: ROW_H }; n_rows + 1],
+            vec![LayoutItem::Float, LayoutItem::Fixed { size: ROW_H }],
+        ]
+        .concat(),
+    );
+
+    let bottom_layout = make_horizontal_layout(
+        dialog_layout.last().unwrap(),
+        m,
+        &[
+            LayoutItem::Float,
+            LayoutItem::Fixed {
+                size: DIALOG_BUTTON_W,
+            },
+            LayoutItem::Fixed {
+                size: DIALOG_BUTTON_W,
+            },
+        ],
+    );
+
+    let draw_title = |uitk_context: &mut UiContext<F>, title: &str| {
+        draw_line_in_rect(
+            uitk_context.fb,
+            title,
+            &dialog_layout[0],
+            font,
+            stylesheet.colors.text,
+            TextJustification::Left,
+        );
+    };
+
+    let mut action = None;
+
+    match dialog {
+        FileDialog::Closed => (),
+
+        FileDialog::Requested { file_name } => {
+            action = Some(FileAction::Open(file_name.clone()));
+        }
+
+        FileDialog::Open { files } => {
+            let title = match files.is_empty() {
+                true => "No saved documents",
+                false => "Open document",
+            };
+            draw_title(uitk_context, title);
+
+            for (i, file_name) in files.iter().take(n_rows).enumerate() {
+                let clicked = uitk_context.button(&ButtonConfig {
+                    rect: dialog_layout[i + 1].clone(),
+                    text: file_name.clone(),
+                    ..Default::default()
+                });
+                if clicked {
+                    action = Some(FileAction::Open(file_name.clone()));
+                }
+            }
+
+            let cancel = uitk_context.button(&ButtonConfig {
+                rect: bottom_layout[2].clone(),
+                text: "Cancel".to_owned(),
+                ..Default::default()
+            });
+            if cancel {
+                action = Some(FileAction::Cancel);
+            }
+        }
+
+        FileDialog::SaveAs { name, name_state } => {
+            draw_title(uitk_context, "Save document as");
+
+            uitk_context.editable_text_box(
+                &dialog_layout[1],
+                name,
+                name_state,
+                false,
+                false,
+                None::<&TrackedContent<String>>,
+            );
+
+            let save = uitk_context.button(&ButtonConfig {
+                rect: bottom_layout[1].clone(),
+                text: "Save".to_owned(),
+                ..Default::default()
+            });
+            let cancel = uitk_context.button(&ButtonConfig {
+                rect: bottom_layout[2].clone(),
+                text: "Cancel".to_owned(),
+                ..Default::default()
+            });
+
+            let file_name = name.as_ref().trim();
+            if save && !file_name.is_empty() {
+                action = Some(FileAction::SaveAs(file_name.to_owned()));
+            } else if cancel {
+                action = Some(FileAction::Cancel);
+            }
+        }
+
+        // Shown as a modal, see confirm_discard()
+        FileDialog::ConfirmDiscard { .. } => (),
+    }
+
+    action
+}
+
+pub fn confirm_discard<F: FbViewMut>(
+    uitk_context: &mut UiContext<F>,
+    discarding: &Discarding,
+) -> Option<FileAction> {
+    let message = match discarding {
+        Discarding::Open(file_name) => format!("Opening {} loses the current changes", file_name),
+        Discarding::Close => "Closing loses the current changes".to_owned(),
+    };
+
+    let result = uitk_context.modal(
+        &ModalConfig {
+            id: ContentId::from_hash(&"confirm_discard"),
+            title: "Discard unsaved changes?".to_owned(),
+            ok_text: Some("Discard".to_owned()),
+            ..Default::default()
+        },
+        |uitk_context, rect| {
+            let stylesheet = &uitk_context.stylesheet;
+            let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
+            draw_line_in_rect(
+                uitk_context.fb,
+                &message,
+                rect,
+                font,
+                stylesheet.colors.text,
+                TextJustification::Left,
+            );
+        },
+    );
+
+    match result {
+        ModalResult::Confirmed => match discarding {
+            Discarding::Open(file_name) => Some(FileAction::ConfirmedOpen(file_name.clone())),
+            Discarding::Close => Some(FileAction::ConfirmedClose),
+        },
+        ModalResult::Cancelled => Some(FileAction::Cancel),
+        ModalResult::Open => None,
+    }
+}
@@ -0,0 +1,366 @@
+use applib::content::TrackedContent;
+use applib::drawing::text::{draw_line_in_rect, get_font, RichText, TextJustification};
+use applib::uitk::layout::{make_horizontal_layout, LayoutItem};
+use applib::uitk::{
+    ButtonConfig, CheckboxConfig, ContentId, TextBoxState, UiContext, UuidProvider,
+};
+use applib::{FbViewMut, Rect};
+
+const SMALL_BUTTON_W: u32 = 40;
+const LABEL_W: u32 = 70;
+const REPLACE_BUTTON_W: u32 = 100;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FindMode {
+    Find,
+    Replace,
+}
+
+pub struct FindState {
+    pub mode: FindMode,
+    pub query: TrackedContent<String>,
+    pub query_state: TextBoxState,
+    pub replacement: TrackedContent<String>,
+    pub replacement_state: TextBoxState,
+    pub case_sensitive: bool,
+    pub matches: Vec<(usize, usize)>,
+
+    anchor: usize,
+    search_key: Option<(ContentId, ContentId, bool)>,
+}
+
+impl FindState {
+    pub fn new(mode: FindMode, anchor: usize, uuid_provider: &mut UuidProvider) -> Self {
+        FindState {
+            mode,
+            query: TrackedContent::new(String::new(), uuid_provider),
+            query_state: TextBoxState::new(),
+            replacement: TrackedContent::new(String::new(), uuid_provider),
+            replacement_state: TextBoxState::new(),
+            case_sensitive: false,
+            matches: Vec::new(),
+            anchor,
+            search_key: None,
+        }
+    }
+
+    // Returns true if the query itself changed (caller should then jump to the first match)
+    pub fn update(&mut self, text: &TrackedContent<RichText>) -> bool {
+        let search_key = (text.get_id(), self.query.get_id(), self.case_sensitive);
+
+        let prev_key = self.search_key.replace(search_key);
+        if prev_key == Some(search_key) {
+            return false;
+        }
+
+        let haystack: Vec<char> = text.as_ref().as_string().chars().collect();
+        self.matches = find_matches(&haystack, self.query.as_ref(), self.case_sensitive);
+
+        prev_key.map(|(_, query_id, _)| query_id) != Some(self.query.get_id())
+    }
+
+    pub fn first_after_anchor(&self) -> Option<(usize, usize)> {
+        self.matches
+            .iter()
+            .find(|(start, _)| *start >= self.anchor)
+            .or(self.matches.first())
+            .cloned()
+    }
+
+    pub fn next(&self, cursor: usize) -> Option<(usize, usize)> {
+        self.matches
+            .iter()
+            .find(|(start, _)| *start >= cursor)
+            .or(self.matches.first())
+            .cloned()
+    }
+
+    pub fn prev(&self, cursor: usize) -> Option<(usize, usize)> {
+        self.matches
+            .iter()
+            .rev()
+            .find(|(_, end)| *end < cursor)
+            .or(self.matches.last())
+            .cloned()
+    }
+
+    // Cursor is placed at the end of the current match
+    pub fn current(&self, cursor: usize) -> Option<(usize, (usize, usize))> {
+        self.matches
+            .iter()
+            .enumerate()
+            .find(|(_, (_, end))| *end == cursor)
+            .map(|(i, m)| (i, *m))
+    }
+}
+
+#[derive(Default)]
+pub struct FindBarActions {
+    pub next: bool,
+    pub prev: bool,
+    pub replace: bool,
+    pub replace_all: bool,
+    pub close: bool,
+}
+
+pub fn find_bar<F: FbViewMut>(
+    uitk_context: &mut UiContext<F>,
+    rows: &[Rect],
+    find: &mut FindState,
+    cursor: usize,
+) -> FindBarActions {
+    let stylesheet = uitk_context.stylesheet.clone();
+    let m = stylesheet.margin;
+    let font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.medium);
+
+    let mut actions = FindBarActions::default();
+
+    let find_layout = make_horizontal_layout(
+        &rows[0],
+        m,
+        &[
+            LayoutItem::Float,
+            LayoutItem::Fixed {
+                size: SMALL_BUTTON_W,
+            },
+            LayoutItem::Fixed {
+                size: SMALL_BUTTON_W,
+            },
+            LayoutItem::Fixed {
+                size: SMALL_BUTTON_W,
+            },
+            LayoutItem::Fixed { size: LABEL_W },
+            LayoutItem::Fixed {
+                size: SMALL_BUTTON_W,
+            },
+        ],
+    );
+
+    uitk_context.editable_text_box(
+        &find_layout[0],
+        &mut find.query,
+        &mut find.query_state,
+        false,
+        false,
+        None::<&TrackedContent<String>>,
+    );
+
+    uitk_context.checkbox(
+        &CheckboxConfig {
+            id: ContentId::from_hash(&"find_case_sensitive"),
+            rect: find_layout[1].clone(),
+            label: "Aa".to_owned(),
+            ..Default::default()
+        },
+        &mut find.case_sensitive,
+    );
+
+    actions.prev = uitk_context.button(&ButtonConfig {
+        rect: find_layout[2].clone(),
+        icon_name: Some("arrow_left".to_owned()),
+        ..Default::default()
+    });
+
+    actions.next = uitk_context.button(&ButtonConfig {
+        rect: find_layout[3].clone(),
+        icon_name: Some("arrow_right".to_owned()),
+        ..Default::default()
+    });
+
+    let count_text = match find.current(cursor) {
+        Some((i, _)) => format!("{}/{}", i + 1, find.matches.len()),
+        None => format!("{}", find.matches.len()),
+    };
+    draw_line_in_rect(
+        uitk_context.fb,
+        &count_text,
+        &find_layout[4],
+        font,
+        stylesheet.colors.text,
+        TextJustification::Center,
+    );
+
+    actions.close = uitk_context.button(&ButtonConfig {
+        rect: find_layout[5].clone(),
+        icon_name: Some("close".to_owned()),
+        ..Default::default()
+    });
+
+    if find.mode == FindMode::Replace {
+        let replace_layout = make_horizontal_layout(
+            &rows[1],
+            m,
+            &[
+                LayoutItem::Float,
+                LayoutItem::Fixed {
+                    size: REPLACE_BUTTON_W,
+                },
+                LayoutItem::Fixed {
+                    size: REPLACE_BUTTON_W,
+                },
+            ],
+        );
+
+        uitk_context.editable_text_box(
+            &replace_layout[0],
+            &mut find.replacement,
+            &mut find.replacement_state,
+            false,
+            false,
+            None::<&TrackedContent<String>>,
+        );
+
+        actions.replace = uitk_context.button(&ButtonConfig {
+            rect: replace_layout[1].clone(),
+            text: "Replace".to_owned(),
+            ..Default::default()
+        });
+
+        actions.replace_all = uitk_context.button(&ButtonConfig {
+            rect: replace_layout[2].clone(),
+            text: "Replace all".to_owned(),
+            ..Default::default()
+        });
+    }
+
+    actions
+}
+
+#[derive(Clone, Copy)]
+enum PatternToken {
+    Char(char),
+    AnyChar,
+    AnySeq,
+}
+
+/// Finds non-overlapping matches of a pattern, where `?` matches any single character
+/// and `*` the shortest run of characters (not crossing line boundaries).
+pub fn find_matches(haystack: &[char], pattern: &str, case_sensitive: bool) -> Vec<(usize, usize)> {
+    let tokens: Vec<PatternToken> = pattern
+        .chars()
+        .map(|c| match c {
+            '?' => PatternToken::AnyChar,
+            '*' => PatternToken::AnySeq,
+            c => PatternToken::Char(c),
+        })
+        .collect();
+
+    let mut matches = Vec::new();
+
+    if tokens.is_empty() {
+        return matches;
+    }
+
+    let mut pos = 0;
+    while pos < haystack.len() {
+        match match_at(haystack, pos, &tokens, case_sensitive) {
+            Some(end) if end > pos => {
+                matches.push((pos, end));
+                pos = end;
+            }
+            _ => pos += 1,
+        }
+    }
+
+    matches
+}
+
+// Returns the end of the match starting at `pos`, if any. Each `*` takes the shortest
+// run that lets the rest match, and only the last one seen is ever extended: extending an
+// earlier one cannot help, as the tokens after it would then match further along anyway.
+fn match_at(
+    haystack: &[char],
+    pos: usize,
+    tokens: &[PatternToken],
+    case_sensitive: bool,
+) -> Option<usize> {
+    let (mut ti, mut hi) = (0, pos);
+
+    // Token after the last `*` and where its run currently ends
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while ti < tokens.len() {
+        let matched = match (tokens[ti], haystack.get(hi)) {
+            (PatternToken::AnySeq, _) => {
+                ti += 1;
+                backtrack = Some((ti, hi));
+                continue;
+            }
+            (PatternToken::AnyChar, Some(&c)) => c != '\n',
+            (PatternToken::Char(pc), Some(&c)) => chars_equal(c, pc, case_sensitive),
+            (_, None) => false,
+        };
+
+        if matched {
+            ti += 1;
+            hi += 1;
+            continue;
+        }
+
+        // One more character in the run of the last `*`
+        let (star_ti, star_end) = backtrack?;
+        match haystack.get(star_end) {
+            Some(&c) if c != '\n' => {
+                backtrack = Some((star_ti, star_end + 1));
+                ti = star_ti;
+                hi = star_end + 1;
+            }
+            _ => return None,
+        }
+    }
+
+    Some(hi)
+}
+
+fn chars_equal(a: char, b: char, case_sensitive: bool) -> bool {
+    match case_sensitive {
+        true => a == b,
+        false => a == b || a.to_lowercase().eq(b.to_lowercase()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(haystack: &str, pattern: &str) -> Vec<(usize, usize)> {
+        let haystack: Vec<char> = haystack.chars().collect();
+        find_matches(&haystack, pattern, true)
+    }
+
+    #[test]
+    fn star_at_start() {
+        assert_eq!(find("foo bar", "*bar"), vec![(0, 7)]);
+        assert_eq!(find("foo\nbar", "*bar"), vec![(4, 7)]);
+    }
+
+    #[test]
+    fn star_at_end() {
+        // The shortest run is empty
+        assert_eq!(find("foo bar foo", "foo*"), vec![(0, 3), (8, 11)]);
+    }
+
+    #[test]
+    fn consecutive_stars() {
+        assert_eq!(find("foo bar", "f**r"), vec![(0, 7)]);
+        assert_eq!(find("foo bar", "o***b"), vec![(1, 5)]);
+        assert_eq!(find("foo\nbar", "f**r"), vec![]);
+    }
+
+    #[test]
+    fn stars_and_any_char() {
+        assert_eq!(find("abc abd", "a?*d"), vec![(0, 7)]);
+        assert_eq!(find("ab\nd", "a*?d"), vec![]);
+    }
+
+    #[test]
+    fn pathological_stars() {
+        // Each `*` retrying every run of the ones before it took exponential time here
+        let haystack = "a".repeat(5000);
+        let pattern = format!("{}b", "a*".repeat(20));
+        assert_eq!(find(&haystack, &pattern), vec![]);
+
+        let haystack = format!("{}b", haystack);
+        assert_eq!(find(&haystack, &pattern), vec![(0, 5001)]);
+    }
+}
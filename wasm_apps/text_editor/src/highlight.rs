@@ -0,0 +1,520 @@
+use applib::content::{ContentId, TrackedContent};
+use applib::drawing::text::RichText;
+use applib::uitk::UuidProvider;
+use applib::Color;
+
+const KEYWORD_COLOR: Color = Color::rgb(0, 60, 200);
+const STRING_COLOR: Color = Color::rgb(0, 140, 0);
+const COMMENT_COLOR: Color = Color::GREY;
+const NUMBER_COLOR: Color = Color::rgb(200, 100, 0);
+const LIFETIME_COLOR: Color = Color::rgb(170, 0, 170);
+const KEY_COLOR: Color = Color::rgb(0, 120, 160);
+const TABLE_COLOR: Color = Color::rgb(170, 0, 170);
+
+const RUST_KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Language {
+    Plain,
+    Rust,
+    Toml,
+}
+
+impl Language {
+    pub fn from_file_name(file_name: &str) -> Self {
+        match file_name.rsplit_once('.') {
+            Some((_, "rs")) => Language::Rust,
+            Some((_, "toml")) => Language::Toml,
+            _ => Language::Plain,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::Plain => "Plain",
+            Language::Rust => "Rust",
+            Language::Toml => "TOML",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Language::Plain => Language::Rust,
+            Language::Rust => Language::Toml,
+            Language::Toml => Language::Plain,
+        }
+    }
+}
+
+// Lexer state at the start of a line, for constructs spanning multiple lines
+#[derive(Clone, Copy, PartialEq)]
+enum LexState {
+    Normal,
+    BlockComment(u32), // Nesting depth
+    Str,
+    RawStr(u32), // Number of '#'
+    MultiBasicStr,
+    MultiLiteralStr,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Token {
+    Text,
+    Keyword,
+    Str,
+    Comment,
+    Number,
+    Lifetime,
+    Key,
+    Table,
+}
+
+impl Token {
+    fn color(&self, default_color: Color) -> Color {
+        match self {
+            Token::Text => default_color,
+            Token::Keyword => KEYWORD_COLOR,
+            Token::Str => STRING_COLOR,
+            Token::Comment => COMMENT_COLOR,
+            Token::Number => NUMBER_COLOR,
+            Token::Lifetime => LIFETIME_COLOR,
+            Token::Key => KEY_COLOR,
+            Token::Table => TABLE_COLOR,
+        }
+    }
+}
+
+pub struct Highlighter {
+    language: Language,
+    default_color: Color,
+    reset_colors: bool,
+    text_id: Option<ContentId>,
+
+    // Text and per-line lexer start states as of the last update
+    chars: Vec<char>,
+    line_states: Vec<LexState>,
+}
+
+impl Highlighter {
+    pub fn new(language: Language) -> Self {
+        Highlighter {
+            language,
+            default_color: Color::BLACK,
+            reset_colors: false,
+            text_id: None,
+            chars: Vec::new(),
+            line_states: vec![LexState::Normal],
+        }
+    }
+
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    pub fn set_language(&mut self, language: Language) {
+        if language != self.language {
+            let reset_colors = self.language != Language::Plain;
+            *self = Highlighter::new(language);
+            self.reset_colors = reset_colors;
+        }
+    }
+
+    /// Re-colors the lines which changed since the last update. Lexing continues past the
+    /// edited region only for as long as line start states differ from the previous ones.
+    pub fn update(
+        &mut self,
+        text: &mut TrackedContent<RichText>,
+        uuid_provider: &mut UuidProvider,
+        default_color: Color,
+    ) {
+        if default_color != self.default_color {
+            self.text_id = None;
+            self.chars.clear();
+            self.line_states = vec![LexState::Normal];
+            self.default_color = default_color;
+        }
+
+        if self.text_id == Some(text.get_id()) {
+            return;
+        }
+
+        let mut recolors = Vec::new();
+
+        if self.language == Language::Plain {
+            // Leaving a highlighting mode resets token colors
+            if self.reset_colors {
+                recolors.extend((0..text.as_ref().len()).map(|pos| (pos, default_color)));
+                self.reset_colors = false;
+            }
+        } else {
+            let new_chars: Vec<char> = text.as_ref().chars().map(|rich_char| rich_char.c).collect();
+            self.relex(&new_chars, |pos, color| recolors.push((pos, color)));
+            self.chars = new_chars;
+        }
+
+        recolors.retain(|&(pos, color)| text.as_ref().get_char(pos).color != color);
+
+        if !recolors.is_empty() {
+            let rich_text = text.mutate(uuid_provider);
+            for (pos, color) in recolors {
+                rich_text.set_color(pos, color);
+            }
+        }
+
+        self.text_id = Some(text.get_id());
+    }
+
+    fn relex<C: FnMut(usize, Color)>(&mut self, new_chars: &[char], mut set_color: C) {
+        let old_chars = &self.chars;
+
+        let prefix = old_chars
+            .iter()
+            .zip(new_chars.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let max_suffix = usize::min(old_chars.len(), new_chars.len()) - prefix;
+        let suffix = old_chars
+            .iter()
+            .rev()
+            .zip(new_chars.iter().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let count_lines = |chars: &[char]| chars.iter().filter(|&&c| c == '\n').count();
+
+        let first_line = count_lines(&new_chars[..prefix]);
+        let removed_lines = count_lines(&old_chars[prefix..old_chars.len() - suffix]);
+        let added_lines = count_lines(&new_chars[prefix..new_chars.len() - suffix]);
+        let last_dirty_line = first_line + added_lines;
+
+        // States of lines inside the edited region are recomputed below
+        self.line_states.splice(
+            first_line + 1..first_line + 1 + removed_lines,
+            core::iter::repeat_n(LexState::Normal, added_lines),
+        );
+
+        let mut line = first_line;
+        let mut pos = new_chars[..prefix]
+            .iter()
+            .rposition(|&c| c == '\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let mut state = self.line_states[line];
+        let mut tokens = Vec::new();
+
+        loop {
+            let line_end = new_chars[pos..]
+                .iter()
+                .position(|&c| c == '\n')
+                .map(|i| pos + i)
+                .unwrap_or(new_chars.len());
+
+            tokens.clear();
+            let line_chars = &new_chars[pos..line_end];
+            let end_state = match self.language {
+                Language::Rust => lex_rust_line(line_chars, state, &mut tokens),
+                Language::Toml => lex_toml_line(line_chars, state, &mut tokens),
+                Language::Plain => LexState::Normal,
+            };
+
+            for (i, token) in tokens.iter().enumerate() {
+                set_color(pos + i, token.color(self.default_color));
+            }
+
+            if line_end == new_chars.len() {
+                break;
+            }
+
+            line += 1;
+            pos = line_end + 1;
+            let old_state = core::mem::replace(&mut self.line_states[line], end_state);
+            state = end_state;
+
+            if line > last_dirty_line && old_state == end_state {
+                break;
+            }
+        }
+    }
+}
+
+fn lex_rust_line(line: &[char], mut state: LexState, tokens: &mut Vec<Token>) -> LexState {
+    let at = |i: usize| line.get(i).copied().unwrap_or('\0');
+    let mut i = 0;
+
+    while i < line.len() {
+        let start = i;
+
+        let token = match state {
+            LexState::BlockComment(mut depth) => {
+                while i < line.len() && depth > 0 {
+                    if at(i) == '/' && at(i + 1) == '*' {
+                        depth += 1;
+                        i += 2;
+                    } else if at(i) == '*' && at(i + 1) == '/' {
+                        depth -= 1;
+                        i += 2;
+                    } else {
+                        i += 1;
+                    }
+                }
+                state = match depth {
+                    0 => LexState::Normal,
+                    depth => LexState::BlockComment(depth),
+                };
+                Token::Comment
+            }
+
+            LexState::Str => {
+                while i < line.len() {
+                    match at(i) {
+                        '\\' => i += 2,
+                        '"' => {
+                            i += 1;
+                            state = LexState::Normal;
+                            break;
+                        }
+                        _ => i += 1,
+                    }
+                }
+                i = usize::min(i, line.len());
+                Token::Str
+            }
+
+            LexState::RawStr(hashes) => {
+                while i < line.len() {
+                    let closing = at(i) == '"' && (1..=hashes as usize).all(|k| at(i + k) == '#');
+                    if closing {
+                        i += 1 + hashes as usize;
+                        state = LexState::Normal;
+                        break;
+                    }
+                    i += 1;
+                }
+                Token::Str
+            }
+
+            LexState::MultiBasicStr | LexState::MultiLiteralStr => {
+                unreachable!("TOML state in Rust lexer")
+            }
+
+            LexState::Normal => {
+                let c = at(i);
+                if c == '/' && at(i + 1) == '/' {
+                    i = line.len();
+                    Token::Comment
+                } else if c == '/' && at(i + 1) == '*' {
+                    i += 2;
+                    state = LexState::BlockComment(1);
+                    Token::Comment
+                } else if c == '"' || (c == 'b' && at(i + 1) == '"') {
+                    i += if c == 'b' { 2 } else { 1 };
+                    state = LexState::Str;
+                    Token::Str
+                } else if let Some((prefix_len, hashes)) = raw_string_start(line, i) {
+                    i += prefix_len;
+                    state = LexState::RawStr(hashes);
+                    Token::Str
+                } else if c == '\'' {
+                    lex_rust_quote(line, &mut i)
+                } else if c.is_ascii_digit() {
+                    while at(i).is_alphanumeric()
+                        || at(i) == '_'
+                        || (at(i) == '.' && at(i + 1).is_ascii_digit())
+                    {
+                        i += 1;
+                    }
+                    Token::Number
+                } else if is_ident_start(c) {
+                    while is_ident_char(at(i)) {
+                        i += 1;
+                    }
+                    let word: String = line[start..i].iter().collect();
+                    match RUST_KEYWORDS.contains(&word.as_str()) {
+                        true => Token::Keyword,
+                        false => Token::Text,
+                    }
+                } else {
+                    i += 1;
+                    Token::Text
+                }
+            }
+        };
+
+        tokens.extend(core::iter::repeat_n(token, i - start));
+    }
+
+    state
+}
+
+// Returns the length of the opening sequence and the number of '#' for r"..", r#".."#, br#".."#
+fn raw_string_start(line: &[char], i: usize) -> Option<(usize, u32)> {
+    let at = |i: usize| line.get(i).copied().unwrap_or('\0');
+
+    if i > 0 && is_ident_char(at(i - 1)) {
+        return None;
+    }
+
+    let mut j = i;
+    if at(j) == 'b' {
+        j += 1;
+    }
+    if at(j) != 'r' {
+        return None;
+    }
+    j += 1;
+
+    let mut hashes = 0;
+    while at(j) == '#' {
+        hashes += 1;
+        j += 1;
+    }
+
+    match at(j) {
+        '"' => Some((j + 1 - i, hashes)),
+        _ => None,
+    }
+}
+
+// Character literal or lifetime
+fn lex_rust_quote(line: &[char], i: &mut usize) -> Token {
+    let at = |i: usize| line.get(i).copied().unwrap_or('\0');
+
+    if at(*i + 1) == '\\' {
+        *i += 2;
+        while *i < line.len() && at(*i) != '\'' {
+            *i += 1;
+        }
+        *i = usize::min(*i + 1, line.len());
+        Token::Str
+    } else if at(*i + 2) == '\'' {
+        *i += 3;
+        Token::Str
+    } else if is_ident_start(at(*i + 1)) {
+        *i += 1;
+        while is_ident_char(at(*i)) {
+            *i += 1;
+        }
+        Token::Lifetime
+    } else {
+        *i += 1;
+        Token::Text
+    }
+}
+
+fn lex_toml_line(line: &[char], mut state: LexState, tokens: &mut Vec<Token>) -> LexState {
+    let at = |i: usize| line.get(i).copied().unwrap_or('\0');
+    let is_triple = |i: usize, q: char| at(i) == q && at(i + 1) == q && at(i + 2) == q;
+    let mut i = 0;
+
+    while i < line.len() {
+        let start = i;
+
+        let token = match state {
+            LexState::MultiBasicStr => {
+                while i < line.len() {
+                    if at(i) == '\\' {
+                        i += 2;
+                    } else if is_triple(i, '"') {
+                        i += 3;
+                        state = LexState::Normal;
+                        break;
+                    } else {
+                        i += 1;
+                    }
+                }
+                i = usize::min(i, line.len());
+                Token::Str
+            }
+
+            LexState::MultiLiteralStr => {
+                while i < line.len() {
+                    if is_triple(i, '\'') {
+                        i += 3;
+                        state = LexState::Normal;
+                        break;
+                    }
+                    i += 1;
+                }
+                Token::Str
+            }
+
+            LexState::BlockComment(_) | LexState::Str | LexState::RawStr(_) => {
+                unreachable!("Rust state in TOML lexer")
+            }
+
+            LexState::Normal => {
+                let c = at(i);
+                let line_start = line[..i].iter().all(|c| c.is_whitespace());
+
+                if c == '#' {
+                    i = line.len();
+                    Token::Comment
+                } else if c == '[' && line_start {
+                    while i < line.len() && at(i) != ']' {
+                        i += 1;
+                    }
+                    while at(i) == ']' {
+                        i += 1;
+                    }
+                    Token::Table
+                } else if is_triple(i, '"') {
+                    i += 3;
+                    state = LexState::MultiBasicStr;
+                    Token::Str
+                } else if is_triple(i, '\'') {
+                    i += 3;
+                    state = LexState::MultiLiteralStr;
+                    Token::Str
+                } else if c == '"' || c == '\'' {
+                    i += 1;
+                    while i < line.len() && at(i) != c {
+                        i += if c == '"' && at(i) == '\\' { 2 } else { 1 };
+                    }
+                    i = usize::min(i + 1, line.len());
+                    Token::Str
+                } else if c.is_ascii_digit()
+                    || ((c == '+' || c == '-') && at(i + 1).is_ascii_digit())
+                {
+                    i += 1;
+                    while at(i).is_alphanumeric() || matches!(at(i), '_' | '.' | ':' | '-' | '+') {
+                        i += 1;
+                    }
+                    Token::Number
+                } else if c.is_alphanumeric() || c == '_' || c == '-' {
+                    while at(i).is_alphanumeric() || at(i) == '_' || at(i) == '-' {
+                        i += 1;
+                    }
+                    let next = line[i..].iter().find(|c| !c.is_whitespace());
+                    let word: String = line[start..i].iter().collect();
+                    match next {
+                        Some('=') | Some('.') => Token::Key,
+                        _ if word == "true" || word == "false" => Token::Keyword,
+                        _ => Token::Text,
+                    }
+                } else {
+                    i += 1;
+                    Token::Text
+                }
+            }
+        };
+
+        tokens.extend(core::iter::repeat_n(token, i - start));
+    }
+
+    state
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
@@ -1,22 +1,30 @@
 extern crate alloc;
 
-use applib::uitk::layout::{
-    make_grid_layout, make_horizontal_layout, make_vertical_layout, LayoutItem,
-};
+mod files;
+mod find;
+mod highlight;
+
+use applib::uitk::layout::{make_horizontal_layout, make_vertical_layout, BoxItem, LayoutItem};
 use lazy_static::lazy_static;
 
 use applib::content::TrackedContent;
 use applib::drawing::primitives::draw_rect;
 use applib::drawing::text::{
-    draw_line_in_rect, get_font, RichText, TextJustification, FONT_FAMILIES,
+    draw_line_in_rect, get_font, Font, RichText, TextJustification, FONT_FAMILIES,
 };
+use applib::input::Keycode;
 use applib::uitk::{
-    self, ButtonConfig, ButtonIndicatorMode, EditableRichText, TextBoxState, UuidProvider,
+    self, ButtonConfig, ButtonIndicatorMode, CheckboxConfig, ContentId, DropdownConfig,
+    EditableRichText, EditableText, Menu, MenuAction, MenuBarConfig, MenuItem, ShortcutConfig,
+    SliderConfig, TextBoxState, ToastConfig, UiContext, UuidProvider,
 };
-use applib::{Color, StyleSheetText};
-use applib::{Framebuffer, OwnedPixels};
+use applib::Color;
+use applib::{FbViewMut, Framebuffer, OwnedPixels};
 use core::cell::OnceCell;
+use files::{confirm_discard, file_dialog, Discarding, FileAction, FileDialog};
+use find::{find_bar, FindBarActions, FindMode, FindState};
 use guestlib::{PixelData, WasmLogger};
+use highlight::{Highlighter, Language};
 use std::vec;
 
 const AVAILABLE_TEXT_COLORS: [Color; 10] = [
@@ -38,16 +46,29 @@ const INTRO_TITLE_SIZE: u32 = 22;
 const INTRO_BODY_TEXT: &'static str =
     "You can change text justification, font, size and colors on the right.
 Left/right arrow keys or left click to change the cursor position.
+Use the File menu at the top (Alt+F) to open and save documents.
+Rust and TOML files are highlighted, the top right button switches the mode.
+Ctrl+F to find text (F3 / Shift+F3 to cycle), Ctrl+H to replace.
+Ctrl+Z to undo, Ctrl+Y or Ctrl+Shift+Z to redo.
 ";
 const INTRO_BODY_SIZE: u32 = 16;
 
+// Menu bar entries, items are numbered separators included
+const MENU_OPEN: MenuAction = MenuAction { menu: 0, item: 0 };
+const MENU_SAVE: MenuAction = MenuAction { menu: 0, item: 1 };
+const MENU_SAVE_AS: MenuAction = MenuAction { menu: 0, item: 2 };
+const MENU_UNDO: MenuAction = MenuAction { menu: 1, item: 0 };
+const MENU_REDO: MenuAction = MenuAction { menu: 1, item: 1 };
+const MENU_CUT: MenuAction = MenuAction { menu: 1, item: 3 };
+const MENU_COPY: MenuAction = MenuAction { menu: 1, item: 4 };
+const MENU_PASTE: MenuAction = MenuAction { menu: 1, item: 5 };
+const MENU_SELECT_ALL: MenuAction = MenuAction { menu: 1, item: 6 };
+const MENU_FIND: MenuAction = MenuAction { menu: 1, item: 8 };
+const MENU_REPLACE: MenuAction = MenuAction { menu: 1, item: 9 };
+
+const COPIED_TOAST_DURATION: f64 = 1000.0; // in ms
+
 lazy_static! {
-    pub static ref JUSTIF_LEFT_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../icons/justif_left.png"));
-    pub static ref JUSTIF_CENTER_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../icons/justif_center.png"));
-    pub static ref JUSTIF_RIGHT_ICON: Framebuffer<OwnedPixels> =
-        Framebuffer::from_png(include_bytes!("../icons/justif_right.png"));
     pub static ref COLOR_ICONS: Vec<(Color, Framebuffer<OwnedPixels>)> = AVAILABLE_TEXT_COLORS
         .iter()
         .map(|&color| (color, Framebuffer::new_owned_filled(19, 16, color)))
@@ -61,12 +82,23 @@ struct AppState {
 
     justification: SingleSelection<TextJustification>,
     font_family: SingleSelection<String>,
-    font_size: SingleSelection<u32>,
+    font_size: i64,
     text_color: SingleSelection<Color>,
     bg_color: SingleSelection<Color>,
 
     textbox_text: TrackedContent<RichText>,
     textbox_state: TextBoxState,
+    // Off to lock the document and its formatting
+    editing: bool,
+
+    file_name: Option<String>,
+    saved_content_id: ContentId,
+    file_dialog: FileDialog,
+    error_msg: Option<String>,
+
+    find: Option<FindState>,
+
+    highlighter: Highlighter,
 }
 
 static mut APP_STATE: OnceCell<AppState> = OnceCell::new();
@@ -87,13 +119,14 @@ pub fn init() -> () {
 
     let justification = SingleSelection(TextJustification::Center);
     let font_family_name = SingleSelection(stylesheet.text.font_family().to_owned());
-    let font_size = SingleSelection(stylesheet.text.sizes.medium);
+    let font_size = stylesheet.text.sizes.medium as i64;
     let text_color = SingleSelection(Color::BLACK);
     let bg_color = SingleSelection(Color::WHITE);
 
     let textbox_state = {
         let mut tb_state = TextBoxState::new();
         tb_state.justif = *justification.selected();
+        tb_state.focus();
         tb_state
     };
 
@@ -128,10 +161,15 @@ pub fn init() -> () {
         TrackedContent::new(text, &mut uuid_provider)
     };
 
+    let saved_content_id = textbox_text.get_id();
+
+    let mut ui_store = uitk::UiStore::new();
+    ui_store.set_clipboard(guestlib::HostClipboard);
+
     let state = AppState {
         pixel_data: PixelData::new(),
-        ui_store: uitk::UiStore::new(),
-        uuid_provider: UuidProvider::new(),
+        ui_store,
+        uuid_provider,
 
         justification,
         font_family: font_family_name,
@@ -141,6 +179,16 @@ pub fn init() -> () {
 
         textbox_text,
         textbox_state,
+        editing: true,
+
+        file_name: None,
+        saved_content_id,
+        file_dialog: FileDialog::Closed,
+        error_msg: None,
+
+        find: None,
+
+        highlighter: Highlighter::new(Language::Plain),
     };
     unsafe {
         APP_STATE
@@ -168,10 +216,18 @@ impl<T: PartialEq> SingleSelection<T> {
 #[no_mangle]
 pub fn step() {
     const TOOL_PANEL_W: u32 = 143;
+    const FILE_BUTTON_W: u32 = 80;
+    const MENU_BAR_W: u32 = 120;
     const BUTTON_H: u32 = 30;
-    const SELECTION_GRID_H: u32 = 50;
+    // The color grids grow with the window, the spacers around them take the rest
+    const SELECTION_GRID_MIN_H: u32 = 50;
+    const COLOR_GRID_COLUMNS: usize = 5;
+    const COLOR_GRID_ROWS: usize = 2;
     const SECTION_TITLE_H: u32 = 18;
-    const AVAILABLE_FONT_SIZES: [u32; 6] = [12, 14, 16, 18, 20, 22];
+    // Fonts are bitmaps, only available in these sizes
+    const MIN_FONT_SIZE: i64 = 12;
+    const MAX_FONT_SIZE: i64 = 22;
+    const FONT_SIZE_STEP: i64 = 2;
 
     let state = unsafe { APP_STATE.get_mut().expect("App not initialized") };
 
@@ -180,6 +236,30 @@ pub fn step() {
     let input_state = guestlib::get_input_state();
     let win_rect = guestlib::get_win_rect().zero_origin();
 
+    update_highlighting(state);
+
+    // Documents handed over by other apps, e.g. the file manager
+    if let Some(file_name) = guestlib::take_open_request() {
+        state.file_dialog = FileDialog::Requested { file_name };
+    }
+
+    // The kernel only asks before closing the window while there are unsaved changes
+    let has_changes = state.textbox_text.get_id() != state.saved_content_id;
+    if guestlib::take_close_request() {
+        match has_changes {
+            true => {
+                state.file_dialog = FileDialog::ConfirmDiscard {
+                    discarding: Discarding::Close,
+                }
+            }
+            // Saved since the guard was last set
+            false => guestlib::close(),
+        }
+    }
+    guestlib::set_close_guard(has_changes);
+
+    let dialog_open = !matches!(state.file_dialog, FileDialog::Closed);
+
     let mut framebuffer = state.pixel_data.get_framebuffer();
 
     let mut uitk_context = state.ui_store.get_context(
@@ -190,8 +270,16 @@ pub fn step() {
         time,
     );
 
+    // Not the same as input_state while a popup is open
+    let ui_input_state = uitk_context.input_state;
+
+    // Applied at the end, like the menu actions
+    let find_shortcut = match dialog_open {
+        false => find_shortcut(&mut uitk_context),
+        true => None,
+    };
+
     let available_families: Vec<&str> = FONT_FAMILIES.keys().map(|s| *s).collect();
-    let n_families = available_families.len();
     let m = stylesheet.margin;
 
     let columns_layout = make_horizontal_layout(
@@ -200,37 +288,6 @@ pub fn step() {
         &[LayoutItem::Float, LayoutItem::Fixed { size: TOOL_PANEL_W }],
     );
 
-    let right_col_layout = make_vertical_layout(
-        &columns_layout.last().unwrap(),
-        stylesheet.margin,
-        &[
-            vec![LayoutItem::Fixed { size: BUTTON_H }, LayoutItem::Float],
-            vec![LayoutItem::Fixed { size: BUTTON_H }; n_families],
-            vec![
-                LayoutItem::Fixed {
-                    size: SELECTION_GRID_H,
-                },
-                LayoutItem::Float,
-            ],
-            vec![
-                LayoutItem::Fixed {
-                    size: SECTION_TITLE_H,
-                },
-                LayoutItem::Fixed {
-                    size: SELECTION_GRID_H,
-                },
-                LayoutItem::Float,
-                LayoutItem::Fixed {
-                    size: SECTION_TITLE_H,
-                },
-                LayoutItem::Fixed {
-                    size: SELECTION_GRID_H,
-                },
-            ],
-        ]
-        .concat(),
-    );
-
     let font_family = FONT_FAMILIES
         .get(state.font_family.selected().as_str())
         .expect("Unknown font family");
@@ -238,215 +295,732 @@ pub fn step() {
     let ui_font = get_font(stylesheet.text.font_family(), stylesheet.text.sizes.small);
     let ui_text_color = stylesheet.colors.text;
 
-    let mut layout_offset = 0;
+    //
+    // Tool panel
+
+    let panel_items = [
+        BoxItem::Fixed(BUTTON_H),
+        BoxItem::grow(1),
+        BoxItem::Fixed(BUTTON_H),
+        BoxItem::Fixed(BUTTON_H),
+        BoxItem::grow(1),
+        BoxItem::Fixed(SECTION_TITLE_H),
+        BoxItem::min(SELECTION_GRID_MIN_H),
+        BoxItem::grow(1),
+        BoxItem::Fixed(SECTION_TITLE_H),
+        BoxItem::min(SELECTION_GRID_MIN_H),
+        BoxItem::Fixed(BUTTON_H),
+    ];
+
+    uitk_context.vbox(
+        &columns_layout[1],
+        m,
+        &panel_items,
+        |uitk_context, cells| {
+            //
+            // Justification
+
+            let mut button_config = ButtonConfig {
+                indicator_mode: ButtonIndicatorMode::Light,
+                disabled: !state.editing,
+                ..Default::default()
+            };
+
+            let justif_buttons = [
+                (TextJustification::Left, "align_left", "Align left"),
+                (TextJustification::Center, "align_center", "Center"),
+                (TextJustification::Right, "align_right", "Align right"),
+            ];
+
+            let justif_items = [BoxItem::grow(1); 3];
+            uitk_context.hbox(
+                &cells[0].rect,
+                m,
+                &justif_items,
+                |uitk_context, justif_cells| {
+                    for ((justif, icon_name, tooltip), cell) in
+                        justif_buttons.into_iter().zip(justif_cells)
+                    {
+                        state.justification.scope(justif, |button_state| {
+                            button_config.rect = cell.rect.clone();
+                            button_config.icon_name = Some(icon_name.to_owned());
+                            button_config.tooltip = Some(tooltip.to_owned());
+                            uitk_context.button_toggle_once(&button_config, button_state);
+                        });
+                    }
+                },
+            );
+
+            draw_rect(
+                uitk_context.fb,
+                &cells[1].rect,
+                stylesheet.colors.element,
+                false,
+            );
+
+            //
+            // Font family
+
+            let mut family_index = available_families
+                .iter()
+                .position(|family| *family == state.font_family.selected())
+                .unwrap_or(0);
+
+            uitk_context.dropdown(
+                &DropdownConfig {
+                    id: cells[2].id,
+                    rect: cells[2].rect.clone(),
+                    options: available_families.iter().map(|s| s.to_string()).collect(),
+                    disabled: !state.editing,
+                    ..Default::default()
+                },
+                &mut family_index,
+            );
+
+            state.font_family = SingleSelection(available_families[family_index].to_owned());
+
+            //
+            // Font size
+
+            uitk_context.slider_int(
+                &SliderConfig {
+                    id: cells[3].id,
+                    rect: cells[3].rect.clone(),
+                    min: MIN_FONT_SIZE as f32,
+                    max: MAX_FONT_SIZE as f32,
+                    step: FONT_SIZE_STEP as f32,
+                    nb_ticks: ((MAX_FONT_SIZE - MIN_FONT_SIZE) / FONT_SIZE_STEP + 1) as u32,
+                    show_labels: true,
+                    disabled: !state.editing,
+                },
+                &mut state.font_size,
+            );
+
+            draw_rect(
+                uitk_context.fb,
+                &cells[4].rect,
+                stylesheet.colors.element,
+                false,
+            );
+
+            //
+            // Text and background colors
+
+            let palettes = [
+                ("Foreground", &cells[5], &cells[6], &mut state.text_color),
+                ("Background", &cells[8], &cells[9], &mut state.bg_color),
+            ];
+
+            for (title, title_cell, grid_cell, selection) in palettes {
+                draw_rect(
+                    uitk_context.fb,
+                    &title_cell.rect,
+                    stylesheet.colors.element,
+                    false,
+                );
+                draw_line_in_rect(
+                    uitk_context.fb,
+                    title,
+                    &title_cell.rect,
+                    ui_font,
+                    ui_text_color,
+                    TextJustification::Left,
+                );
+
+                let mut button_config = ButtonConfig {
+                    indicator_mode: ButtonIndicatorMode::Border,
+                    ..Default::default()
+                };
+
+                let columns = [BoxItem::grow(1); COLOR_GRID_COLUMNS];
+                let rows = [BoxItem::grow(1); COLOR_GRID_ROWS];
+                uitk_context.grid(
+                    &grid_cell.rect,
+                    m,
+                    &columns,
+                    &rows,
+                    |uitk_context, color_cells| {
+                        for ((color, icon), cell) in COLOR_ICONS.iter().zip(color_cells) {
+                            selection.scope(*color, |button_state| {
+                                button_config.rect = cell.rect.clone();
+                                button_config.icon = Some((format!("{:?}", color), icon));
+                                uitk_context.button_toggle_once(&button_config, button_state);
+                            });
+                        }
+                    },
+                );
+            }
+
+            draw_rect(
+                uitk_context.fb,
+                &cells[7].rect,
+                stylesheet.colors.element,
+                false,
+            );
+
+            //
+            // Editing toggle
+
+            uitk_context.checkbox(
+                &CheckboxConfig {
+                    id: cells[10].id,
+                    rect: cells[10].rect.clone(),
+                    label: "Editing".into(),
+                    tooltip: Some("Uncheck to lock the document".into()),
+                    ..Default::default()
+                },
+                &mut state.editing,
+            );
+        },
+    );
 
     //
-    // Justification
+    // File toolbar
+
+    let show_error_bar = state.error_msg.is_some();
+    let find_rows = match state.find.as_ref().map(|find| find.mode) {
+        _ if dialog_open => 0,
+        Some(FindMode::Find) => 1,
+        Some(FindMode::Replace) => 2,
+        None => 0,
+    };
 
-    let justif_layout = make_horizontal_layout(
-        &right_col_layout[layout_offset],
+    let left_col_layout = make_vertical_layout(
+        &columns_layout[0],
         stylesheet.margin,
-        &[LayoutItem::Float; 3],
+        &[
+            vec![LayoutItem::Fixed { size: BUTTON_H }],
+            match show_error_bar {
+                true => vec![LayoutItem::Fixed { size: BUTTON_H }],
+                false => vec![],
+            },
+            vec![LayoutItem::Fixed { size: BUTTON_H }; find_rows],
+            vec![LayoutItem::Float],
+        ]
+        .concat(),
     );
 
-    let mut button_config = ButtonConfig {
-        indicator_mode: ButtonIndicatorMode::Light,
-        ..Default::default()
-    };
+    let toolbar_layout = make_horizontal_layout(
+        &left_col_layout[0],
+        stylesheet.margin,
+        &[
+            LayoutItem::Fixed { size: MENU_BAR_W },
+            LayoutItem::Float,
+            LayoutItem::Fixed {
+                size: FILE_BUTTON_W,
+            },
+        ],
+    );
 
-    state
-        .justification
-        .scope(TextJustification::Left, |button_state| {
-            button_config.rect = justif_layout[0].clone();
-            button_config.icon = Some(("justif_left_icon".to_owned(), &JUSTIF_LEFT_ICON));
-            uitk_context.button_toggle_once(&button_config, button_state);
-        });
+    let menu_action = uitk_context.menu_bar(&MenuBarConfig {
+        id: ContentId::from_hash(&"menu_bar"),
+        rect: toolbar_layout[0].clone(),
+        menus: make_menus(&state.textbox_state, state.editing),
+    });
 
-    state
-        .justification
-        .scope(TextJustification::Center, |button_state| {
-            button_config.rect = justif_layout[1].clone();
-            button_config.icon = Some(("justif_center_icon".to_owned(), &JUSTIF_CENTER_ICON));
-            uitk_context.button_toggle_once(&button_config, button_state);
-        });
+    let open_clicked = menu_action == Some(MENU_OPEN);
+    let save_clicked = menu_action == Some(MENU_SAVE);
+    let save_as_clicked = menu_action == Some(MENU_SAVE_AS);
 
-    state
-        .justification
-        .scope(TextJustification::Right, |button_state| {
-            button_config.rect = justif_layout[2].clone();
-            button_config.icon = Some(("justif_right_icon".to_owned(), &JUSTIF_RIGHT_ICON));
-            uitk_context.button_toggle_once(&button_config, button_state);
-        });
+    let language_clicked = uitk_context.button(&ButtonConfig {
+        rect: toolbar_layout[2].clone(),
+        text: state.highlighter.language().name().to_owned(),
+        ..Default::default()
+    });
+
+    if language_clicked {
+        let language = state.highlighter.language().next();
+        state.highlighter.set_language(language);
+    }
 
-    layout_offset += 1;
+    let is_dirty = state.textbox_text.get_id() != state.saved_content_id;
 
-    draw_rect(
+    let title = format!(
+        "{}{}",
+        state.file_name.as_deref().unwrap_or("Untitled"),
+        if is_dirty { "*" } else { "" }
+    );
+    draw_line_in_rect(
         uitk_context.fb,
-        &right_col_layout[layout_offset],
-        stylesheet.colors.element,
-        false,
+        &title,
+        &toolbar_layout[1],
+        ui_font,
+        ui_text_color,
+        TextJustification::Left,
     );
 
-    layout_offset += 1;
+    if !dialog_open {
+        if open_clicked {
+            state.file_dialog = match guestlib::storage_list() {
+                Ok(files) => FileDialog::Open { files },
+                Err(err) => {
+                    state.error_msg = Some(format!("Cannot list documents: {}", err));
+                    FileDialog::Closed
+                }
+            };
+        } else if save_as_clicked || (save_clicked && state.file_name.is_none()) {
+            let name = state.file_name.clone().unwrap_or_default();
+            let mut name_state = TextBoxState::new();
+            name_state.cursor = name.chars().count();
+            name_state.focus();
+            state.file_dialog = FileDialog::SaveAs {
+                name: TrackedContent::new(name, uitk_context.uuid_provider),
+                name_state: Box::new(name_state),
+            };
+        } else if let (true, Some(file_name)) = (save_clicked, state.file_name.as_ref()) {
+            match write_document(file_name, state.textbox_text.as_ref()) {
+                Ok(()) => {
+                    state.saved_content_id = state.textbox_text.get_id();
+                    uitk_context.push_toast(saved_toast(file_name));
+                }
+                Err(err) => state.error_msg = Some(err),
+            }
+        }
+    }
 
-    // Font family
+    //
+    // Error bar
 
-    let mut button_config = ButtonConfig {
-        indicator_mode: ButtonIndicatorMode::Light,
-        ..Default::default()
-    };
+    if let (true, Some(error_msg)) = (show_error_bar, state.error_msg.as_ref()) {
+        let error_layout = make_horizontal_layout(
+            &left_col_layout[1],
+            stylesheet.margin,
+            &[
+                LayoutItem::Float,
+                LayoutItem::Fixed {
+                    size: FILE_BUTTON_W,
+                },
+            ],
+        );
 
-    for (i, &family) in available_families.iter().enumerate() {
-        state.font_family.scope(family.to_owned(), |button_state| {
-            button_config.rect = right_col_layout[layout_offset + i].clone();
-            button_config.text = family.to_owned();
-            uitk_context
-                .style(|ss| {
-                    let mut text_sizes = ss.text.sizes.clone();
-                    text_sizes.medium = text_sizes.small;
-                    ss.text = StyleSheetText::new(family, text_sizes);
-                })
-                .button_toggle_once(&button_config, button_state);
+        draw_rect(
+            uitk_context.fb,
+            &left_col_layout[1],
+            stylesheet.colors.red,
+            false,
+        );
+        draw_line_in_rect(
+            uitk_context.fb,
+            error_msg,
+            &error_layout[0].offset(-(m as i64)),
+            ui_font,
+            ui_text_color,
+            TextJustification::Left,
+        );
+
+        let dismiss_clicked = uitk_context.button(&ButtonConfig {
+            rect: error_layout[1].clone(),
+            text: "Dismiss".to_owned(),
+            ..Default::default()
         });
+
+        if dismiss_clicked {
+            state.error_msg = None;
+        }
     }
 
-    layout_offset += n_families;
+    //
+    // Find bar
+
+    let mut find_actions = FindBarActions::default();
+
+    if let (true, Some(find)) = (find_rows > 0, state.find.as_mut()) {
+        let find_offset = if show_error_bar { 2 } else { 1 };
+        find_actions = find_bar(
+            &mut uitk_context,
+            &left_col_layout[find_offset..find_offset + find_rows],
+            find,
+            state.textbox_state.cursor,
+        );
+    }
 
     //
-    // Font size
+    // Canvas
 
-    let mut button_config = ButtonConfig {
-        indicator_mode: ButtonIndicatorMode::Border,
-        ..Default::default()
-    };
+    let canvas_rect = left_col_layout.last().unwrap();
 
-    let sizes_layout = make_grid_layout(&right_col_layout[layout_offset], stylesheet.margin, 3, 2);
+    // The discard confirmation is a modal over the document, the other dialogs replace it
+    let canvas_dialog_open = !matches!(
+        state.file_dialog,
+        FileDialog::Closed | FileDialog::ConfirmDiscard { .. }
+    );
 
-    for (i, &size) in AVAILABLE_FONT_SIZES.iter().enumerate() {
-        state.font_size.scope(size, |button_state| {
-            button_config.rect = sizes_layout[i].clone();
-            button_config.text = format!("{}", size);
-            uitk_context
-                .style(|ss| ss.text.sizes.medium = ss.text.sizes.small)
-                .button_toggle_once(&button_config, button_state);
-        });
+    let mut action = None;
+
+    if canvas_dialog_open {
+        action = file_dialog(&mut uitk_context, canvas_rect, &mut state.file_dialog);
+    } else {
+        // Typing goes to the document unless another widget was given the keyboard
+        if uitk_context.interaction.focused.is_none() {
+            state.textbox_state.focus();
+        }
+
+        state.textbox_state.justif = *state.justification.selected();
+        state.textbox_state.disabled = !state.editing;
+        uitk_context
+            .style(|s| s.colors.editable = *state.bg_color.selected())
+            .editable_text_box(
+                &canvas_rect,
+                &mut EditableRichText {
+                    color: *state.text_color.selected(),
+                    font: font_family.get_size(state.font_size as u32),
+                    rich_text: &mut state.textbox_text,
+                },
+                &mut state.textbox_state,
+                false,
+                true,
+                None::<&EditableRichText>,
+            );
+    }
+
+    if let FileDialog::ConfirmDiscard { discarding } = &state.file_dialog {
+        action = confirm_discard(&mut uitk_context, discarding);
     }
 
-    layout_offset += 1;
+    // Drawn last, over the rest of the UI
+    uitk_context.draw_overlay();
 
-    draw_rect(
-        uitk_context.fb,
-        &right_col_layout[layout_offset],
-        stylesheet.colors.element,
-        false,
-    );
+    let font = font_family.get_size(state.font_size as u32);
+    let color = *state.text_color.selected();
+
+    match action {
+        Some(FileAction::Open(file_name)) if is_dirty => {
+            state.file_dialog = FileDialog::ConfirmDiscard {
+                discarding: Discarding::Open(file_name),
+            };
+        }
+        Some(FileAction::Open(file_name)) | Some(FileAction::ConfirmedOpen(file_name)) => {
+            match read_document(&file_name, font, color) {
+                Ok(rich_text) => {
+                    state.textbox_text = TrackedContent::new(rich_text, uitk_context.uuid_provider);
+                    state.textbox_state = TextBoxState::new();
+                    state.saved_content_id = state.textbox_text.get_id();
+                    state
+                        .highlighter
+                        .set_language(Language::from_file_name(&file_name));
+                    state.file_name = Some(file_name);
+                }
+                Err(err) => state.error_msg = Some(err),
+            }
+            state.file_dialog = FileDialog::Closed;
+        }
+        Some(FileAction::SaveAs(file_name)) => {
+            match write_document(&file_name, state.textbox_text.as_ref()) {
+                Ok(()) => {
+                    state.saved_content_id = state.textbox_text.get_id();
+                    uitk_context.push_toast(saved_toast(&file_name));
+                    state
+                        .highlighter
+                        .set_language(Language::from_file_name(&file_name));
+                    state.file_name = Some(file_name);
+                }
+                Err(err) => state.error_msg = Some(err),
+            }
+            state.file_dialog = FileDialog::Closed;
+        }
+        Some(FileAction::ConfirmedClose) => guestlib::close(),
+        Some(FileAction::Cancel) => state.file_dialog = FileDialog::Closed,
+        None => (),
+    }
+
+    //
+    // Find/replace actions
+
+    if !dialog_open {
+        let focus_on_query = state
+            .find
+            .as_ref()
+            .is_some_and(|find| find.query_state.is_focused());
+        find_actions.next |=
+            ui_input_state.check_key_pressed(Keycode::KEY_F3) && !ui_input_state.shift;
+        find_actions.next |= focus_on_query && ui_input_state.check_key_pressed(Keycode::KEY_ENTER);
+        find_actions.prev |=
+            ui_input_state.check_key_pressed(Keycode::KEY_F3) && ui_input_state.shift;
+        find_actions.close |= ui_input_state.check_key_pressed(Keycode::KEY_ESC);
+
+        let font = font_family.get_size(state.font_size as u32);
+        apply_find_actions(state, find_actions, font);
+    }
 
-    layout_offset += 1;
+    if let Some(mode) = find_shortcut {
+        open_find(state, mode);
+    }
 
     //
-    // Text color
+    // Edit menu
+
+    if let (false, Some(action)) = (dialog_open, menu_action) {
+        let font = font_family.get_size(state.font_size as u32);
+        apply_edit_action(state, action, font);
+    }
+}
+
+// Highlighting only changes colors, so it does not make a saved document dirty
+fn update_highlighting(state: &mut AppState) {
+    let was_saved = state.textbox_text.get_id() == state.saved_content_id;
+
+    state.highlighter.update(
+        &mut state.textbox_text,
+        &mut state.uuid_provider,
+        *state.text_color.selected(),
+    );
+
+    if was_saved {
+        state.saved_content_id = state.textbox_text.get_id();
+    }
+}
 
-    let mut button_config = ButtonConfig {
-        indicator_mode: ButtonIndicatorMode::Border,
+// Global, so that they work while typing in the document
+fn find_shortcut<F: FbViewMut>(uitk_context: &mut UiContext<F>) -> Option<FindMode> {
+    let find = uitk_context.shortcut(&ShortcutConfig {
+        id: ContentId::from_hash(&"find"),
+        keys: &[Keycode::KEY_LEFTCTRL, Keycode::KEY_F],
+        global: true,
         ..Default::default()
+    });
+    let replace = uitk_context.shortcut(&ShortcutConfig {
+        id: ContentId::from_hash(&"replace"),
+        keys: &[Keycode::KEY_LEFTCTRL, Keycode::KEY_H],
+        global: true,
+        ..Default::default()
+    });
+
+    match (find, replace) {
+        (true, _) => Some(FindMode::Find),
+        (_, true) => Some(FindMode::Replace),
+        _ => None,
+    }
+}
+
+fn open_find(state: &mut AppState, mode: FindMode) {
+    // Replacing would edit a locked document
+    let mode = match state.editing {
+        true => mode,
+        false => FindMode::Find,
     };
+    let cursor = state.textbox_state.cursor;
+    let find = state
+        .find
+        .get_or_insert_with(|| FindState::new(mode, cursor, &mut state.uuid_provider));
+    find.mode = mode;
+    find.query_state.focus();
+}
 
-    draw_rect(
-        uitk_context.fb,
-        &right_col_layout[layout_offset],
-        stylesheet.colors.element,
-        false,
-    );
-    draw_line_in_rect(
-        uitk_context.fb,
-        "Foreground",
-        &right_col_layout[layout_offset],
-        ui_font,
-        ui_text_color,
-        TextJustification::Left,
-    );
+fn make_menus(textbox_state: &TextBoxState, editing: bool) -> Vec<Menu> {
+    let has_selection = textbox_state.selection().is_some();
+    let entry = |label: &str, shortcut: &str, enabled: bool| MenuItem::Entry {
+        label: label.to_owned(),
+        shortcut: Some(shortcut.to_owned()),
+        disabled: !enabled,
+    };
 
-    layout_offset += 1;
+    vec![
+        Menu::new(
+            "File",
+            vec![
+                MenuItem::entry("Open"),
+                MenuItem::entry("Save"),
+                MenuItem::entry("Save as"),
+            ],
+        ),
+        Menu::new(
+            "Edit",
+            vec![
+                entry(
+                    "Undo",
+                    "Ctrl+Z",
+                    editing && textbox_state.history.can_undo(),
+                ),
+                entry(
+                    "Redo",
+                    "Ctrl+Y",
+                    editing && textbox_state.history.can_redo(),
+                ),
+                MenuItem::Separator,
+                entry("Cut", "Ctrl+X", editing && has_selection),
+                entry("Copy", "Ctrl+C", has_selection),
+                entry("Paste", "Ctrl+V", editing),
+                entry("Select all", "Ctrl+A", true),
+                MenuItem::Separator,
+                entry("Find", "Ctrl+F", true),
+                entry("Replace", "Ctrl+H", editing),
+            ],
+        ),
+    ]
+}
 
-    let colors_layout = make_grid_layout(&right_col_layout[layout_offset], stylesheet.margin, 5, 2);
+// The same edits as the keyboard shortcuts, which the text box handles itself
+fn apply_edit_action(state: &mut AppState, action: MenuAction, font: &'static Font) {
+    let tb_state = &mut state.textbox_state;
+    let mut editable_text = EditableRichText {
+        color: *state.text_color.selected(),
+        font,
+        rich_text: &mut state.textbox_text,
+    };
 
-    for (i, (color, icon)) in COLOR_ICONS.iter().enumerate() {
-        state.text_color.scope(*color, |button_state| {
-            let icon_key = format!("{:?}", color);
-            button_config.rect = colors_layout[i].clone();
-            button_config.icon = Some((icon_key, icon));
-            uitk_context.button_toggle_once(&button_config, button_state);
-        });
+    let selection = tb_state.selection();
+
+    match action {
+        MENU_UNDO | MENU_REDO => {
+            let cursor = match action == MENU_UNDO {
+                true => tb_state
+                    .history
+                    .undo(&mut editable_text, &mut state.uuid_provider),
+                false => tb_state
+                    .history
+                    .redo(&mut editable_text, &mut state.uuid_provider),
+            };
+            if let Some(cursor) = cursor {
+                tb_state.cursor = cursor;
+                tb_state.anchor = None;
+            }
+        }
+        MENU_CUT | MENU_COPY => {
+            if let Some((start, end)) = selection {
+                guestlib::clipboard_set(&editable_text.slice(start, end).to_plain());
+                if action == MENU_COPY {
+                    state.ui_store.push_toast(ToastConfig {
+                        text: "Copied".to_owned(),
+                        duration: COPIED_TOAST_DURATION,
+                        ..Default::default()
+                    });
+                }
+                if action == MENU_CUT {
+                    tb_state.replace_ranges(
+                        &mut editable_text,
+                        &mut state.uuid_provider,
+                        &[(start, end)],
+                        "",
+                        start,
+                    );
+                }
+            }
+        }
+        MENU_PASTE => {
+            let pasted: String = guestlib::clipboard_get()
+                .chars()
+                .filter(|c| *c != '\r')
+                .collect();
+            let (start, end) = selection.unwrap_or((tb_state.cursor, tb_state.cursor));
+            if !pasted.is_empty() {
+                tb_state.replace_ranges(
+                    &mut editable_text,
+                    &mut state.uuid_provider,
+                    &[(start, end)],
+                    &pasted,
+                    start + pasted.chars().count(),
+                );
+            }
+        }
+        MENU_SELECT_ALL => {
+            tb_state.anchor = Some(0);
+            tb_state.cursor = editable_text.len();
+        }
+        MENU_FIND => return open_find(state, FindMode::Find),
+        MENU_REPLACE => return open_find(state, FindMode::Replace),
+        _ => return,
     }
 
-    layout_offset += 1;
+    tb_state.scroll_to_cursor = true;
+    tb_state.focus();
+}
 
-    draw_rect(
-        uitk_context.fb,
-        &right_col_layout[layout_offset],
-        stylesheet.colors.element,
-        false,
-    );
+fn apply_find_actions(state: &mut AppState, mut actions: FindBarActions, font: &'static Font) {
+    let Some(find) = state.find.as_mut() else {
+        return;
+    };
+
+    if actions.close {
+        state.find = None;
+        state.textbox_state.highlights.clear();
+        state.textbox_state.focus();
+        return;
+    }
 
-    layout_offset += 1;
+    // The replace bar may still be open from before the document was locked
+    if !state.editing {
+        actions.replace = false;
+        actions.replace_all = false;
+    }
 
-    //
-    // Background color
+    let cursor = state.textbox_state.cursor;
+    let replacement = find.replacement.as_ref().clone();
 
-    let mut button_config = ButtonConfig {
-        indicator_mode: ButtonIndicatorMode::Border,
-        ..Default::default()
+    let mut editable_text = EditableRichText {
+        color: *state.text_color.selected(),
+        font,
+        rich_text: &mut state.textbox_text,
     };
 
-    draw_rect(
-        uitk_context.fb,
-        &right_col_layout[layout_offset],
-        stylesheet.colors.element,
-        false,
-    );
-    draw_line_in_rect(
-        uitk_context.fb,
-        "Background",
-        &right_col_layout[layout_offset],
-        ui_font,
-        ui_text_color,
-        TextJustification::Left,
-    );
+    if actions.replace {
+        if let Some((_, (start, end))) = find.current(cursor) {
+            state.textbox_state.replace_ranges(
+                &mut editable_text,
+                &mut state.uuid_provider,
+                &[(start, end)],
+                &replacement,
+                start + replacement.chars().count(),
+            );
+            find.update(editable_text.rich_text);
+        }
+        actions.next = true;
+    }
 
-    layout_offset += 1;
+    if actions.replace_all && !find.matches.is_empty() {
+        // Single edit, so that the whole operation is one content change and one undo step
+        let old_len = editable_text.rich_text.as_ref().len();
+        let ranges = find.matches.clone();
+        let new_len = old_len + ranges.len() * replacement.chars().count()
+            - ranges.iter().map(|(start, end)| end - start).sum::<usize>();
+        state.textbox_state.replace_ranges(
+            &mut editable_text,
+            &mut state.uuid_provider,
+            &ranges,
+            &replacement,
+            usize::min(cursor, new_len),
+        );
+    }
 
-    let bg_colors_layout =
-        make_grid_layout(&right_col_layout[layout_offset], stylesheet.margin, 5, 2);
+    let query_changed = find.update(&state.textbox_text);
+
+    let cursor = state.textbox_state.cursor;
+    let jump_to = if query_changed {
+        find.first_after_anchor()
+    } else if actions.next {
+        find.next(cursor)
+    } else if actions.prev {
+        find.prev(cursor)
+    } else {
+        None
+    };
 
-    for (i, (color, icon)) in COLOR_ICONS.iter().enumerate() {
-        state.bg_color.scope(*color, |button_state| {
-            let icon_key = format!("{:?}", color);
-            button_config.rect = bg_colors_layout[i].clone();
-            button_config.icon = Some((icon_key, icon));
-            uitk_context.button_toggle_once(&button_config, button_state);
-        });
+    if let Some((_, end)) = jump_to {
+        state.textbox_state.cursor = end;
+        state.textbox_state.scroll_to_cursor = true;
     }
 
-    //
-    // Canvas
+    state.textbox_state.highlights = find.matches.clone();
+}
 
-    let canvas_rect = &columns_layout[0];
-
-    state.textbox_state.justif = *state.justification.selected();
-    uitk_context
-        .style(|s| s.colors.editable = *state.bg_color.selected())
-        .editable_text_box(
-            &canvas_rect,
-            &mut EditableRichText {
-                color: *state.text_color.selected(),
-                font: font_family.get_size(*state.font_size.selected()),
-                rich_text: &mut state.textbox_text,
-            },
-            &mut state.textbox_state,
-            false,
-            true,
-            None::<&EditableRichText>,
-        );
+fn read_document(file_name: &str, font: &'static Font, color: Color) -> Result<RichText, String> {
+    let data = guestlib::storage_read(file_name)
+        .map_err(|err| format!("Cannot open {}: {}", file_name, err))?;
+    let text = String::from_utf8(data)
+        .map_err(|_| format!("Cannot open {}: not a UTF-8 text document", file_name))?;
+    Ok(RichText::from_str(&text, color, font, None))
+}
+
+fn saved_toast(file_name: &str) -> ToastConfig {
+    ToastConfig {
+        text: format!("Saved {}", file_name),
+        ..Default::default()
+    }
+}
+
+fn write_document(file_name: &str, rich_text: &RichText) -> Result<(), String> {
+    let text = rich_text.as_string();
+    guestlib::storage_write(file_name, text.as_bytes())
+        .map_err(|err| format!("Cannot save {}: {}", file_name, err))
 }
 
 const POEM_TEXT: &'static str = "Across old bark
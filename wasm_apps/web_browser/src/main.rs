@@ -144,30 +144,35 @@ impl Debug for RequestState {
     }
 }
 
-fn get_progress_repr(request_state: &RequestState) -> (u64, Cow<str>) {
+// No fraction while a fetch is in flight, since its length is not known in advance
+fn get_progress_repr(request_state: &RequestState) -> (Option<f32>, Cow<str>) {
     match request_state {
-        RequestState::Home => (0, Cow::Borrowed("Home")),
+        RequestState::Home => (Some(0.0), Cow::Borrowed("Home")),
         RequestState::Dns { dns_state, .. } => match dns_state {
-            DnsState::Connecting => (0, Cow::Borrowed("DNS: connecting")),
+            DnsState::Connecting => (None, Cow::Borrowed("DNS: connecting")),
             DnsState::Sending { out_count } => {
-                (1, Cow::Owned(format!("DNS: sent {} bytes", out_count)))
+                (None, Cow::Owned(format!("DNS: sent {} bytes", out_count)))
             }
-            DnsState::ReceivingLen { .. } => (2, Cow::Borrowed("DNS: receiving response length")),
-            DnsState::ReceivingResp { in_count } => {
-                (3, Cow::Owned(format!("DNS: received {} bytes", in_count)))
+            DnsState::ReceivingLen { .. } => {
+                (None, Cow::Borrowed("DNS: receiving response length"))
             }
+            DnsState::ReceivingResp { in_count } => (
+                None,
+                Cow::Owned(format!("DNS: received {} bytes", in_count)),
+            ),
         },
         RequestState::Https { https_state, .. } => match https_state {
-            HttpsState::Connecting => (4, Cow::Borrowed("HTTPS: connecting")),
+            HttpsState::Connecting => (None, Cow::Borrowed("HTTPS: connecting")),
             HttpsState::Sending { out_count } => {
-                (5, Cow::Owned(format!("HTTPS: sent {} bytes", out_count)))
-            }
-            HttpsState::Receiving { in_count } => {
-                (6, Cow::Owned(format!("HTTPS: received {} bytes", in_count)))
+                (None, Cow::Owned(format!("HTTPS: sent {} bytes", out_count)))
             }
+            HttpsState::Receiving { in_count } => (
+                None,
+                Cow::Owned(format!("HTTPS: received {} bytes", in_count)),
+            ),
         },
-        RequestState::Render { .. } => (7, Cow::Borrowed("Rendering")),
-        RequestState::Idle { .. } => (0, Cow::Borrowed("")),
+        RequestState::Render { .. } => (Some(1.0), Cow::Borrowed("Rendering")),
+        RequestState::Idle { .. } => (Some(0.0), Cow::Borrowed("")),
     }
 }
 
@@ -188,13 +193,16 @@ pub fn init() -> () {
 
     let mut uuid_provider = uitk::UuidProvider::new();
 
+    let mut ui_store = uitk::UiStore::new();
+    ui_store.set_clipboard(guestlib::HostClipboard);
+
     let state = AppState {
         pixel_data: PixelData::new(),
         url_text: TrackedContent::new(url_text, &mut uuid_provider),
         url_textbox_state: TextBoxState::new(),
 
         buffer: vec![0u8; BUFFER_SIZE],
-        ui_store: uitk::UiStore::new(),
+        ui_store,
         uuid_provider,
         webview_scroll_offsets: (0, 0),
         webview_scroll_dragging: (false, false),
@@ -253,6 +261,11 @@ pub fn step() {
         }),
     };
 
+    // The URL bar has the keyboard unless a button was given it
+    if uitk_context.interaction.focused.is_none() {
+        state.url_textbox_state.focus();
+    }
+
     uitk_context.editable_text_box(
         &ui_layout.url_bar_rect,
         &mut state.url_text,
@@ -262,19 +275,23 @@ pub fn step() {
         None::<&TrackedContent<String>>,
     );
 
-    let (progress_val, progress_str) = get_progress_repr(&state.request_state);
+    let (progress_fraction, progress_str) = get_progress_repr(&state.request_state);
 
     uitk_context.progress_bar(
         &uitk::ProgressBarConfig {
             rect: ui_layout.progress_bar_rect.clone(),
-            max_val: 8,
+            mode: match progress_fraction {
+                Some(_) => uitk::ProgressBarMode::Determinate,
+                None => uitk::ProgressBarMode::Indeterminate,
+            },
+            text: progress_str.into_owned(),
             ..Default::default()
         },
-        progress_val,
-        &progress_str,
+        progress_fraction.unwrap_or(0.0),
     );
 
-    let url_bar_go = match buttons_state.go || check_enter_pressed(&input_state) {
+    let enter_pressed = state.url_textbox_state.is_focused() && check_enter_pressed(&input_state);
+    let url_bar_go = match buttons_state.go || enter_pressed {
         false => None,
         true => Some(state.url_text.as_ref().to_owned()),
     };